@@ -1,14 +1,364 @@
+use std::fmt::Write;
+use std::path::PathBuf;
+
+use prost::Message;
+
 fn main() {
     println!("cargo:warning=🚀 Build script is running!");
-    
+
+    let out_dir = PathBuf::from(std::env::var("OUT_DIR").unwrap());
+    let descriptors = out_dir.join("zkp_auth.v1.bin");
     tonic_build::configure()
         .build_server(true)
         .out_dir("src/")
+        .file_descriptor_set_path(&descriptors)
         .compile(
-            &["proto/zkp_auth.proto"],
+            &["proto/zkp_auth/v1/zkp_auth.proto"],
             &["proto/"],
         )
         .unwrap_or_else(|e| panic!("Failed to compile protos: {}", e));
-    
+
+    let bytes = std::fs::read(&descriptors).expect("protoc wrote the descriptors");
+    let set = FileDescriptorSet::decode(bytes.as_slice()).expect("protoc wrote valid descriptors");
+    let file = set.file.iter().find(|file| file.package.as_deref() == Some("zkp_auth.v1")).expect("the v1 package");
+    std::fs::write(out_dir.join("zkp_auth.v1.validate.rs"), validation(file)).expect("could not write the validation code");
+    std::fs::write(out_dir.join("zkp_auth.v1.openapi.json"), openapi(file)).expect("could not write the OpenAPI document");
+
     println!("cargo:warning=✅ Proto compilation completed!");
-}
\ No newline at end of file
+}
+
+/// `Validate` for every message from its `(zkp_auth.validate.rules)` options,
+/// and `Validated<T>` for every service, checking each request before `T` sees it
+fn validation(file: &FileDescriptorProto) -> String {
+    let mut code = String::from("// @generated by build.rs from the (zkp_auth.validate.rules) options\n");
+    for message in &file.message_type {
+        let name = message.name();
+        writeln!(code, "impl crate::validate::Validate for {} {{", name).unwrap();
+        writeln!(code, "    fn validate(&self) -> Result<(), crate::validate::Violation> {{").unwrap();
+        for field in &message.field {
+            let rules = field.options.as_ref().and_then(|options| options.rules.clone()).unwrap_or_default();
+            let (field_name, repeated) = (field.name(), field.label == Some(LABEL_REPEATED));
+            let value = format!("self.{}", rust_ident(field_name));
+            let check = match field.r#type {
+                Some(TYPE_STRING) => "text",
+                Some(TYPE_BYTES) => "bytes",
+                Some(TYPE_MESSAGE) if repeated => {
+                    assert_eq!(rules, FieldRules::default(), "{}.{}: no rules for repeated fields", name, field_name);
+                    writeln!(code, "        crate::validate::each({:?}, &{})?;", field_name, value).unwrap();
+                    continue;
+                }
+                Some(TYPE_MESSAGE) => {
+                    writeln!(code, "        crate::validate::message({:?}, {}.as_ref(), {})?;", field_name, value, rules.required).unwrap();
+                    continue;
+                }
+                _ => {
+                    assert_eq!(rules, FieldRules::default(), "{}.{}: rules only apply to strings, bytes and messages", name, field_name);
+                    continue;
+                }
+            };
+            if rules == FieldRules::default() {
+                continue;
+            }
+            assert!(!repeated, "{}.{}: no rules for repeated fields", name, field_name);
+            writeln!(
+                code,
+                "        crate::validate::{}({:?}, &{}, {}, {}, {})?;",
+                check, field_name, value, rules.required, rules.min_len, rules.max_len
+            )
+            .unwrap();
+        }
+        writeln!(code, "        Ok(())\n    }}\n}}").unwrap();
+    }
+
+    for service in &file.service {
+        let (name, module) = (service.name(), format!("{}_server", snake_case(service.name())));
+        writeln!(code, "#[tonic::async_trait]").unwrap();
+        writeln!(code, "impl<T: {module}::{name}> {module}::{name} for crate::validate::Validated<T> {{").unwrap();
+        for method in &service.method {
+            let rust_name = snake_case(method.name());
+            let input = local_type(method.input_type());
+            let output = match method.server_streaming() {
+                true => {
+                    let stream = format!("{}Stream", method.name());
+                    writeln!(code, "    type {stream} = T::{stream};").unwrap();
+                    format!("Self::{}", stream)
+                }
+                false => local_type(method.output_type()),
+            };
+            // streamed requests are checked one by one by the handler, a bad one shouldn't end the stream
+            let (request, check) = match method.client_streaming() {
+                true => (format!("tonic::Streaming<{}>", input), ""),
+                false => (input, "        crate::validate::check(&request)?;\n"),
+            };
+            writeln!(
+                code,
+                "    async fn {rust_name}(&self, request: tonic::Request<{request}>) -> Result<tonic::Response<{output}>, tonic::Status> {{\n{check}        self.0.{rust_name}(request).await\n    }}"
+            )
+            .unwrap();
+        }
+        writeln!(code, "}}").unwrap();
+    }
+    code
+}
+
+/// The OpenAPI 3.1 document of the REST gateway: every unary RPC is
+/// `POST /v1/<service>/<method>` in kebab case, with the proto3 JSON of its
+/// messages; see src/openapi.rs
+fn openapi(file: &FileDescriptorProto) -> String {
+    let mut paths = Vec::new();
+    for service in &file.service {
+        for method in &service.method {
+            // a stream has no REST form
+            if method.client_streaming() || method.server_streaming() {
+                continue;
+            }
+            let mut operation = format!(
+                r#""operationId":{},"tags":[{}],"requestBody":{{"required":true,"content":{{"application/json":{{"schema":{}}}}}}},"responses":{{"200":{{"description":"OK","content":{{"application/json":{{"schema":{}}}}}}},"default":{{"description":"the gRPC status as HTTP, see http_status.rs","content":{{"application/problem+json":{{"schema":{}}}}}}}}}"#,
+                json(&format!("{}.{}", service.name(), method.name())),
+                json(service.name()),
+                schema_ref(&local_type(method.input_type())),
+                schema_ref(&local_type(method.output_type())),
+                schema_ref("Problem"),
+            );
+            // Admin is only served with --admin-token-file, and every call needs the token
+            if service.name() == "Admin" {
+                operation.push_str(r#","security":[{"adminToken":[]}]"#);
+            }
+            let path = format!("/v1/{}/{}", kebab_case(service.name()), kebab_case(method.name()));
+            paths.push(format!(r#"{}:{{"post":{{{}}}}}"#, json(&path), operation));
+        }
+    }
+
+    let mut schemas = Vec::new();
+    for message in &file.message_type {
+        let mut properties = Vec::new();
+        let mut required = Vec::new();
+        for field in &message.field {
+            let name = match &field.json_name {
+                Some(json_name) => json_name.clone(),
+                None => lower_camel_case(field.name()),
+            };
+            let rules = field.options.as_ref().and_then(|options| options.rules.clone()).unwrap_or_default();
+            if rules.required {
+                required.push(json(&name));
+            }
+            let mut schema = match field.r#type {
+                Some(TYPE_DOUBLE | TYPE_FLOAT) => r#"{"type":"number"}"#.to_string(),
+                Some(TYPE_INT64 | TYPE_SINT64 | TYPE_SFIXED64) => r#"{"type":"string","format":"int64"}"#.to_string(),
+                Some(TYPE_UINT64 | TYPE_FIXED64) => r#"{"type":"string","format":"uint64"}"#.to_string(),
+                Some(TYPE_INT32 | TYPE_SINT32 | TYPE_SFIXED32) => r#"{"type":"integer","format":"int32"}"#.to_string(),
+                Some(TYPE_UINT32 | TYPE_FIXED32) => r#"{"type":"integer","format":"uint32"}"#.to_string(),
+                Some(TYPE_BOOL) => r#"{"type":"boolean"}"#.to_string(),
+                Some(TYPE_STRING) => {
+                    let mut limits = String::new();
+                    if rules.min_len > 0 {
+                        limits.push_str(&format!(r#","minLength":{}"#, rules.min_len));
+                    }
+                    if rules.max_len > 0 {
+                        limits.push_str(&format!(r#","maxLength":{}"#, rules.max_len));
+                    }
+                    format!(r#"{{"type":"string"{}}}"#, limits)
+                }
+                Some(TYPE_BYTES) => r#"{"type":"string","contentEncoding":"base64"}"#.to_string(),
+                Some(TYPE_MESSAGE | TYPE_ENUM) => schema_ref(&local_type(field.type_name())),
+                other => panic!("{}.{}: no JSON schema for field type {:?}", message.name(), field.name(), other),
+            };
+            if field.label == Some(LABEL_REPEATED) {
+                schema = format!(r#"{{"type":"array","items":{}}}"#, schema);
+            }
+            properties.push(format!("{}:{}", json(&name), schema));
+        }
+        let required = match required.is_empty() {
+            true => String::new(),
+            false => format!(r#","required":[{}]"#, required.join(",")),
+        };
+        schemas.push(format!(r#"{}:{{"type":"object","properties":{{{}}}{}}}"#, json(message.name()), properties.join(","), required));
+    }
+    for enumeration in &file.enum_type {
+        let values: Vec<String> = enumeration.value.iter().map(|value| json(value.name())).collect();
+        schemas.push(format!(r#"{}:{{"type":"string","enum":[{}]}}"#, json(enumeration.name()), values.join(",")));
+    }
+    // RFC 9457, as http_status::Problem writes it
+    schemas.push(
+        r#""Problem":{"type":"object","properties":{"type":{"type":"string"},"title":{"type":"string"},"status":{"type":"integer"},"detail":{"type":"string"},"grpc_status":{"type":"string"}},"required":["type","title","status"]}"#
+            .to_string(),
+    );
+
+    format!(
+        r#"{{"openapi":"3.1.0","info":{{"title":"zkp_auth.v1","version":{}}},"paths":{{{}}},"components":{{"schemas":{{{}}},"securitySchemes":{{"adminToken":{{"type":"http","scheme":"bearer"}}}}}}}}"#,
+        json(&std::env::var("CARGO_PKG_VERSION").unwrap()),
+        paths.join(","),
+        schemas.join(","),
+    )
+}
+
+fn schema_ref(name: &str) -> String {
+    format!(r##"{{"$ref":"#/components/schemas/{}"}}"##, name)
+}
+
+/// a JSON string; proto names and our descriptions need no escapes beyond these
+fn json(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// `CreateAuthenticationChallenge` -> `create-authentication-challenge`
+fn kebab_case(name: &str) -> String {
+    snake_case(name).replace('_', "-")
+}
+
+/// `auth_id` -> `authId`, what protoc puts in json_name
+fn lower_camel_case(name: &str) -> String {
+    let mut camel = String::new();
+    let mut upper = false;
+    for c in name.chars() {
+        match c {
+            '_' => upper = true,
+            c if upper => {
+                camel.push(c.to_ascii_uppercase());
+                upper = false;
+            }
+            c => camel.push(c),
+        }
+    }
+    camel
+}
+
+/// `.zkp_auth.v1.RegisterRequest` -> `RegisterRequest`
+fn local_type(name: &str) -> String {
+    name.strip_prefix(".zkp_auth.v1.").unwrap_or_else(|| panic!("{} is not in zkp_auth.v1", name)).to_string()
+}
+
+/// `CreateAuthenticationChallenge` -> `create_authentication_challenge`
+fn snake_case(name: &str) -> String {
+    let mut snake = String::new();
+    for (i, c) in name.chars().enumerate() {
+        if c.is_ascii_uppercase() && i > 0 {
+            snake.push('_');
+        }
+        snake.push(c.to_ascii_lowercase());
+    }
+    snake
+}
+
+fn rust_ident(name: &str) -> String {
+    match name {
+        "type" | "match" | "ref" | "use" | "mod" | "fn" | "in" | "as" => format!("r#{}", name),
+        name => name.to_string(),
+    }
+}
+
+// just the parts of descriptor.proto (and our option) the code above reads
+
+const LABEL_REPEATED: i32 = 3;
+const TYPE_DOUBLE: i32 = 1;
+const TYPE_FLOAT: i32 = 2;
+const TYPE_INT64: i32 = 3;
+const TYPE_UINT64: i32 = 4;
+const TYPE_INT32: i32 = 5;
+const TYPE_FIXED64: i32 = 6;
+const TYPE_FIXED32: i32 = 7;
+const TYPE_BOOL: i32 = 8;
+const TYPE_STRING: i32 = 9;
+const TYPE_MESSAGE: i32 = 11;
+const TYPE_BYTES: i32 = 12;
+const TYPE_UINT32: i32 = 13;
+const TYPE_ENUM: i32 = 14;
+const TYPE_SFIXED32: i32 = 15;
+const TYPE_SFIXED64: i32 = 16;
+const TYPE_SINT32: i32 = 17;
+const TYPE_SINT64: i32 = 18;
+
+#[derive(Clone, PartialEq, Message)]
+struct FileDescriptorSet {
+    #[prost(message, repeated, tag = "1")]
+    file: Vec<FileDescriptorProto>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct FileDescriptorProto {
+    #[prost(string, optional, tag = "2")]
+    package: Option<String>,
+    #[prost(message, repeated, tag = "4")]
+    message_type: Vec<DescriptorProto>,
+    #[prost(message, repeated, tag = "5")]
+    enum_type: Vec<EnumDescriptorProto>,
+    #[prost(message, repeated, tag = "6")]
+    service: Vec<ServiceDescriptorProto>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct DescriptorProto {
+    #[prost(string, optional, tag = "1")]
+    name: Option<String>,
+    #[prost(message, repeated, tag = "2")]
+    field: Vec<FieldDescriptorProto>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct FieldDescriptorProto {
+    #[prost(string, optional, tag = "1")]
+    name: Option<String>,
+    #[prost(int32, optional, tag = "4")]
+    label: Option<i32>,
+    #[prost(int32, optional, tag = "5")]
+    r#type: Option<i32>,
+    #[prost(string, optional, tag = "6")]
+    type_name: Option<String>,
+    #[prost(message, optional, tag = "8")]
+    options: Option<FieldOptions>,
+    #[prost(string, optional, tag = "10")]
+    json_name: Option<String>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct EnumDescriptorProto {
+    #[prost(string, optional, tag = "1")]
+    name: Option<String>,
+    #[prost(message, repeated, tag = "2")]
+    value: Vec<EnumValueDescriptorProto>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct EnumValueDescriptorProto {
+    #[prost(string, optional, tag = "1")]
+    name: Option<String>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct FieldOptions {
+    /// `(zkp_auth.validate.rules)`, see proto/zkp_auth/validate.proto
+    #[prost(message, optional, tag = "50731")]
+    rules: Option<FieldRules>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct FieldRules {
+    #[prost(bool, tag = "1")]
+    required: bool,
+    #[prost(uint32, tag = "2")]
+    max_len: u32,
+    #[prost(uint32, tag = "3")]
+    min_len: u32,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct ServiceDescriptorProto {
+    #[prost(string, optional, tag = "1")]
+    name: Option<String>,
+    #[prost(message, repeated, tag = "2")]
+    method: Vec<MethodDescriptorProto>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct MethodDescriptorProto {
+    #[prost(string, optional, tag = "1")]
+    name: Option<String>,
+    #[prost(string, optional, tag = "2")]
+    input_type: Option<String>,
+    #[prost(string, optional, tag = "3")]
+    output_type: Option<String>,
+    #[prost(bool, optional, tag = "5")]
+    client_streaming: Option<bool>,
+    #[prost(bool, optional, tag = "6")]
+    server_streaming: Option<bool>,
+}
@@ -0,0 +1,27 @@
+#!/usr/bin/env bash
+# Generates the TypeScript and Go clients from ../proto and logs in with each
+# against a fresh Rust server. Needs cargo, protoc (for the server), buf,
+# node and go. CI runs it in the `clients` job.
+set -euo pipefail
+cd "$(dirname "$0")"
+
+export ZKP_SERVER="127.0.0.1:${ZKP_PORT:-50151}"
+
+buf generate
+
+cargo build --bin server
+../target/debug/server --listen "$ZKP_SERVER" > server.log 2>&1 &
+server=$!
+trap 'kill $server' EXIT
+for _ in $(seq 1 50); do
+    (echo > "/dev/tcp/${ZKP_SERVER%:*}/${ZKP_SERVER#*:}") 2>/dev/null && break
+    sleep 0.2
+done
+
+echo "🟦 TypeScript client"
+(cd typescript && npm install --no-audit --no-fund && npx tsc && npx tsx src/login.ts)
+
+echo "🐹 Go client"
+(cd go && go mod tidy && go vet ./... && go run ./login)
+
+echo "✅ Both clients logged in to the Rust server"
@@ -0,0 +1,81 @@
+// Registers a fresh user and logs in with Chaum-Pedersen, the same steps as
+// the Rust `client` binary, then checks a wrong password is refused.
+// ../interop.sh runs it against a live server (ZKP_SERVER=host:port).
+import { randomBytes } from "node:crypto";
+
+import { Code, ConnectError, createClient } from "@connectrpc/connect";
+import { createGrpcTransport } from "@connectrpc/connect-node";
+
+import { Auth } from "./gen/zkp_auth/v1/zkp_auth_pb";
+
+const server = process.env.ZKP_SERVER ?? "127.0.0.1:50051";
+const client = createClient(Auth, createGrpcTransport({ baseUrl: `http://${server}` }));
+
+// big-endian bytes <-> bigint, as BigUint::from_bytes_be / to_bytes_be
+function toBigInt(bytes: Uint8Array): bigint {
+  return bytes.length === 0 ? 0n : BigInt("0x" + Buffer.from(bytes).toString("hex"));
+}
+
+function toBytes(n: bigint): Uint8Array {
+  const hex = n.toString(16);
+  return Uint8Array.from(Buffer.from(hex.length % 2 ? "0" + hex : hex, "hex"));
+}
+
+function modPow(base: bigint, exp: bigint, m: bigint): bigint {
+  let result = 1n;
+  base %= m;
+  for (; exp > 0n; exp >>= 1n) {
+    if (exp & 1n) result = (result * base) % m;
+    base = (base * base) % m;
+  }
+  return result;
+}
+
+// 64 extra bits so reducing mod q is practically unbiased
+function randomBelow(q: bigint): bigint {
+  return toBigInt(randomBytes(toBytes(q).length + 8)) % q;
+}
+
+// the legacy derivation: x is the password bytes as a number
+const derive = (password: string) => toBigInt(new TextEncoder().encode(password));
+
+const params = await client.getParameters({});
+const [p, q, alpha, beta] = [params.p, params.q, params.alpha, params.beta].map(toBigInt);
+
+const user = `ts-${randomBytes(6).toString("hex")}`;
+const x = derive("correct horse battery staple");
+await client.register({ user, y1: toBytes(modPow(alpha, x, p)), y2: toBytes(modPow(beta, x, p)) });
+console.log(`✅ Registered ${user}`);
+
+async function login(password: string): Promise<string> {
+  const k = randomBelow(q);
+  const challenge = await client.createAuthenticationChallenge({
+    user,
+    r1: toBytes(modPow(alpha, k, p)),
+    r2: toBytes(modPow(beta, k, p)),
+  });
+  if (challenge.powDifficulty > 0) {
+    throw new Error(`the server asked for a ${challenge.powDifficulty}-bit proof of work, which this example doesn't do`);
+  }
+  // s = k - c * x mod q
+  const c = toBigInt(challenge.c);
+  const s = (((k - c * derive(password)) % q) + q) % q;
+  const answer = await client.verifyAuthentication({
+    authId: challenge.authId,
+    s: toBytes(s),
+    challengeToken: challenge.challengeToken, // only from --stateless-challenges servers
+  });
+  return answer.sessionId;
+}
+
+const sessionId = await login("correct horse battery staple");
+if (!sessionId) throw new Error("logged in without a session id");
+console.log(`✅ Logged in, session ${sessionId}`);
+
+try {
+  await login("Tr0ub4dor&3");
+  throw new Error("a wrong password logged in");
+} catch (e) {
+  if (ConnectError.from(e).code !== Code.PermissionDenied) throw e;
+  console.log("✅ A wrong password is refused");
+}
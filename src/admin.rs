@@ -0,0 +1,87 @@
+//! Authorization for the Admin service.
+//!
+//! The admin RPCs act on other people's accounts, so they can't use the
+//! session of a login. The server is given a shared secret with
+//! `--admin-token-file` and every admin call carries it as
+//! `authorization: Bearer <token>`; without the flag the service isn't served
+//! at all.
+
+use std::io;
+use std::path::Path;
+
+use tonic::metadata::MetadataValue;
+use tonic::service::Interceptor;
+use tonic::{Request, Status};
+
+use crate::mac::constant_time_eq;
+
+/// Metadata key the token travels in
+pub const AUTHORIZATION: &str = "authorization";
+
+/// Shortest token we accept, so a placeholder can't end up guarding the service
+pub const MIN_TOKEN_LEN: usize = 16;
+
+/// The shared secret admin calls are checked against
+#[derive(Clone)]
+pub struct AdminToken(String);
+
+// never print the secret
+impl std::fmt::Debug for AdminToken {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("AdminToken(..)")
+    }
+}
+
+impl AdminToken {
+    pub fn new(token: &str) -> io::Result<Self> {
+        let token = token.trim();
+        if token.len() < MIN_TOKEN_LEN {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("the admin token must be at least {} characters", MIN_TOKEN_LEN),
+            ));
+        }
+        // it goes into a header as is
+        if !token.bytes().all(|b| b.is_ascii_graphic()) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "the admin token must be printable ASCII without spaces",
+            ));
+        }
+        Ok(AdminToken(token.to_string()))
+    }
+
+    /// read the token from a file (surrounding whitespace is ignored)
+    pub fn load(path: &Path) -> io::Result<Self> {
+        Self::new(&std::fs::read_to_string(path)?)
+    }
+
+    /// whether a request carries this token
+    #[allow(clippy::result_large_err)] // the signature tonic interceptors have
+    pub fn check<T>(&self, request: Request<T>) -> Result<Request<T>, Status> {
+        let presented = request
+            .metadata()
+            .get(AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "));
+        match presented {
+            Some(presented) if constant_time_eq(presented.as_bytes(), self.0.as_bytes()) => Ok(request),
+            Some(_) => Err(Status::unauthenticated("wrong admin token")),
+            None => Err(Status::unauthenticated("admin calls need `authorization: Bearer <token>`")),
+        }
+    }
+
+    /// add the token to an outgoing request
+    pub fn authorize<T>(&self, mut request: Request<T>) -> Request<T> {
+        let value = MetadataValue::try_from(format!("Bearer {}", self.0)).expect("checked to be printable ASCII");
+        request.metadata_mut().insert(AUTHORIZATION, value);
+        request
+    }
+}
+
+/// Server interceptor for the Admin service: the trace as for every call, then the token
+impl Interceptor for AdminToken {
+    fn call(&mut self, request: Request<()>) -> Result<Request<()>, Status> {
+        self.check(crate::trace::server_interceptor(request)?)
+    }
+}
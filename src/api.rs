@@ -0,0 +1,47 @@
+//! The gRPC API, one module per protobuf package version.
+//!
+//! `v1` is generated from proto/zkp_auth/v1/zkp_auth.proto. Clients built
+//! before the API was versioned still call the unversioned `zkp_auth.Auth`
+//! service; the messages are identical on the wire, so the server only has to
+//! route those calls to v1 (see [`upgrade_legacy_path`]). Every message
+//! implements [`crate::validate::Validate`], generated from its field rules.
+
+use tonic::codegen::http::{uri::PathAndQuery, Request, Uri};
+
+pub mod v1 {
+    include!("./zkp_auth.v1.rs");
+    include!(concat!(env!("OUT_DIR"), "/zkp_auth.v1.validate.rs"));
+}
+
+/// proto/zkp_auth/v1/zkp_auth.proto as this crate was built from, for
+/// generating clients in other languages (see clients/ in the repository)
+pub const PROTO_V1: &str = include_str!("../proto/zkp_auth/v1/zkp_auth.proto");
+
+/// proto/zkp_auth/validate.proto, imported by [`PROTO_V1`]
+pub const PROTO_VALIDATE: &str = include_str!("../proto/zkp_auth/validate.proto");
+
+/// service name used before the API was versioned
+pub const LEGACY_SERVICE: &str = "zkp_auth.Auth";
+
+/// the service legacy calls are routed to
+pub const CURRENT_SERVICE: &str = "zkp_auth.v1.Auth";
+
+/// rewrite `/zkp_auth.Auth/<method>` to `/zkp_auth.v1.Auth/<method>`, leave
+/// everything else alone; meant for `tower::util::MapRequestLayer` in front of the server
+pub fn upgrade_legacy_path<B>(mut request: Request<B>) -> Request<B> {
+    let Some(method) = request
+        .uri()
+        .path()
+        .strip_prefix('/')
+        .and_then(|path| path.strip_prefix(LEGACY_SERVICE))
+        .and_then(|rest| rest.strip_prefix('/'))
+    else {
+        return request;
+    };
+
+    let path = format!("/{}/{}", CURRENT_SERVICE, method);
+    let mut parts = request.uri().clone().into_parts();
+    parts.path_and_query = Some(PathAndQuery::try_from(path).expect("a service path is a valid uri path"));
+    *request.uri_mut() = Uri::from_parts(parts).expect("only the path changed");
+    request
+}
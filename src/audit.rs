@@ -0,0 +1,455 @@
+//! Audit trail of security-relevant events.
+//!
+//! The server's console output is for operators watching it run; the audit
+//! trail is the record of who registered, logged in, failed to, elevated or
+//! revoked what, and when. Each [`AuditEvent`] goes to every configured sink,
+//! independently of the console: a JSON lines file that rotates by size
+//! ([`JsonlSink`]), syslog in RFC 5424 format ([`SyslogSink`]), stdout
+//! ([`StdoutSink`]) or a webhook, through an outbox ([`crate::outbox`]). A
+//! sink that fails is logged and doesn't fail the request.
+//!
+//! For data-subject requests the JSON lines file can be searched for a user's
+//! events and have them replaced by tombstones: the time, action and outcome
+//! stay, who, from where and the detail go, and an `erased` field names the
+//! erasure. The same rewrite drops events past their retention (see
+//! [`crate::retention`]). Syslog and stdout are written once and can't be
+//! taken back.
+
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::net::UdpSocket;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::clock::{civil_from_days, days_from_civil, unix_millis};
+use crate::lease;
+use crate::retention::RetentionPolicy;
+
+/// How an audited action ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+    Success,
+    /// a wrong answer or otherwise invalid request
+    Failure,
+    /// a valid request refused by policy
+    Denied,
+    /// a sign of an intrusion, such as any use of a honeypot account
+    Alert,
+}
+
+/// One entry in the audit trail
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuditEvent {
+    pub time: SystemTime,
+    pub action: &'static str, // register, login, elevate_session, revoke_session, revoke_proof
+    pub outcome: Outcome,
+    pub user: String,
+    pub source: String, // peer address, empty when unknown
+    pub trace_id: String,
+    pub detail: String,
+}
+
+impl AuditEvent {
+    /// one line of JSON, no trailing newline
+    pub fn to_json(&self) -> String {
+        self.to_value().to_string()
+    }
+
+    fn to_value(&self) -> Value {
+        serde_json::json!({
+            "time": rfc3339(self.time),
+            "action": self.action,
+            "outcome": self.outcome,
+            "user": self.user,
+            "source": self.source,
+            "trace_id": self.trace_id,
+            "detail": self.detail,
+        })
+    }
+
+    /// a CloudEvents 1.0 event in the structured JSON format, with the JSON
+    /// above as its data; the type is `zkp-auth.<action>.<outcome>` and the
+    /// subject the user, when there is one
+    pub fn to_cloud_event(&self, id: &str, source: &str) -> String {
+        let mut event = serde_json::json!({
+            "specversion": "1.0",
+            "id": id,
+            "source": source,
+            "type": format!("zkp-auth.{}.{}", self.action, self.outcome_name()),
+            "time": rfc3339(self.time),
+            "datacontenttype": "application/json",
+            "data": self.to_value(),
+        });
+        if !self.user.is_empty() {
+            event["subject"] = Value::from(self.user.as_str());
+        }
+        event.to_string()
+    }
+
+    /// an RFC 5424 syslog message (facility authpriv)
+    pub fn to_syslog(&self, hostname: &str, app_name: &str) -> String {
+        let severity = match self.outcome {
+            Outcome::Success => 6, // informational
+            Outcome::Denied => 5,  // notice
+            Outcome::Failure => 4, // warning
+            Outcome::Alert => 1,   // alert
+        };
+        format!(
+            "<{}>1 {} {} {} {} {} [zkp@32473 user=\"{}\" outcome=\"{}\" source=\"{}\" trace_id=\"{}\"] {}",
+            AUTHPRIV * 8 + severity,
+            rfc3339(self.time),
+            or_nil(hostname),
+            or_nil(app_name),
+            std::process::id(),
+            self.action,
+            escape_param(&self.user),
+            self.outcome_name(),
+            escape_param(&self.source),
+            escape_param(&self.trace_id),
+            match self.detail.is_empty() {
+                true => format!("{} {}", self.action, self.outcome_name()),
+                false => self.detail.clone(),
+            },
+        )
+    }
+
+    fn outcome_name(&self) -> &'static str {
+        match self.outcome {
+            Outcome::Success => "success",
+            Outcome::Failure => "failure",
+            Outcome::Denied => "denied",
+            Outcome::Alert => "alert",
+        }
+    }
+}
+
+/// Somewhere audit events are written
+pub trait AuditSink: fmt::Debug + Send + Sync {
+    fn record(&self, event: &AuditEvent) -> io::Result<()>;
+
+    /// the events about `user` this sink can read back, oldest first
+    fn export(&self, _user: &str) -> io::Result<Vec<Value>> {
+        Ok(Vec::new())
+    }
+
+    /// replace the events about `user` with tombstones naming `tombstone`; how many
+    fn erase(&self, _user: &str, _tombstone: &str) -> io::Result<usize> {
+        Ok(0)
+    }
+
+    /// drop the events `policy` no longer keeps at `now`; how many
+    fn purge(&self, _policy: &RetentionPolicy, _now: SystemTime) -> io::Result<usize> {
+        Ok(0)
+    }
+}
+
+/// Every configured sink (none by default)
+#[derive(Debug, Default)]
+pub struct Audit {
+    sinks: Vec<Box<dyn AuditSink>>,
+}
+
+impl Audit {
+    pub fn add(&mut self, sink: impl AuditSink + 'static) {
+        self.sinks.push(Box::new(sink));
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.sinks.is_empty()
+    }
+
+    pub fn record(&self, event: &AuditEvent) {
+        for sink in &self.sinks {
+            if let Err(e) = sink.record(event) {
+                println!("⚠️  Could not write to the audit sink {:?}: {}", sink, e);
+            }
+        }
+    }
+
+    /// the events about `user` in every sink that can be read back
+    pub fn export(&self, user: &str) -> io::Result<Vec<Value>> {
+        let mut events = Vec::new();
+        for sink in &self.sinks {
+            events.extend(sink.export(user)?);
+        }
+        Ok(events)
+    }
+
+    /// tombstone the events about `user` in every sink that can be rewritten
+    pub fn erase(&self, user: &str, tombstone: &str) -> io::Result<usize> {
+        let mut erased = 0;
+        for sink in &self.sinks {
+            erased += sink.erase(user, tombstone)?;
+        }
+        Ok(erased)
+    }
+
+    /// apply `policy` to every sink that can be rewritten
+    pub fn purge(&self, policy: &RetentionPolicy, now: SystemTime) -> io::Result<usize> {
+        let mut purged = 0;
+        for sink in &self.sinks {
+            purged += sink.purge(policy, now)?;
+        }
+        Ok(purged)
+    }
+}
+
+/// JSON lines on stdout
+#[derive(Debug, Default)]
+pub struct StdoutSink;
+
+impl AuditSink for StdoutSink {
+    fn record(&self, event: &AuditEvent) -> io::Result<()> {
+        writeln!(io::stdout().lock(), "{}", event.to_json())
+    }
+}
+
+/// JSON lines appended to a file; when it would grow past `max_bytes` it is
+/// renamed to `<path>.1` (and `.1` to `.2`, ...), keeping `keep` old files
+#[derive(Debug)]
+pub struct JsonlSink {
+    path: PathBuf,
+    max_bytes: u64,
+    keep: usize,
+    file: Mutex<(File, u64)>, // the open file and its size
+}
+
+impl JsonlSink {
+    pub fn open(path: &Path, max_bytes: u64, keep: usize) -> io::Result<Self> {
+        let file = append(path)?;
+        let size = file.metadata()?.len();
+        Ok(JsonlSink { path: path.to_path_buf(), max_bytes, keep, file: Mutex::new((file, size)) })
+    }
+
+    fn rotated(&self, n: usize) -> PathBuf {
+        PathBuf::from(format!("{}.{}", self.path.display(), n))
+    }
+
+    /// the rotated files that exist, oldest first, then the current one
+    fn files(&self) -> Vec<PathBuf> {
+        let mut files: Vec<PathBuf> = (1..=self.keep).rev().map(|n| self.rotated(n)).filter(|path| path.exists()).collect();
+        files.push(self.path.clone());
+        files
+    }
+
+    fn rotate(&self) -> io::Result<File> {
+        let rotated = |n: usize| self.rotated(n);
+        if self.keep == 0 {
+            fs::remove_file(&self.path)?;
+        } else {
+            for n in (1..self.keep).rev() {
+                if rotated(n).exists() {
+                    fs::rename(rotated(n), rotated(n + 1))?;
+                }
+            }
+            fs::rename(&self.path, rotated(1))?;
+        }
+        append(&self.path)
+    }
+
+    /// pass every event in every file through `edit`, rewriting the files it
+    /// changed; how many events it replaced or dropped
+    fn rewrite(&self, mut edit: impl FnMut(Value) -> Edit) -> io::Result<usize> {
+        // held throughout, so nothing is appended to a file while it is rewritten
+        let mut file = self.file.lock().unwrap();
+        let mut edited = 0;
+        for path in self.files() {
+            let text = fs::read_to_string(&path)?;
+            let mut found = 0;
+            let mut lines = String::new();
+            for line in text.lines() {
+                // lines that aren't JSON are left alone
+                match serde_json::from_str::<Value>(line).map_or(Edit::Keep, &mut edit) {
+                    Edit::Keep => lines += &format!("{}\n", line),
+                    Edit::Replace(event) => {
+                        lines += &format!("{}\n", event);
+                        found += 1;
+                    }
+                    Edit::Drop => found += 1,
+                }
+            }
+            if found == 0 {
+                continue;
+            }
+            let rewrite = PathBuf::from(format!("{}.rewrite", path.display()));
+            let mut out = File::create(&rewrite)?;
+            out.write_all(lines.as_bytes())?;
+            out.sync_all()?;
+            fs::rename(&rewrite, &path)?;
+            edited += found;
+        }
+        // the current file may have been replaced, append to the new one
+        let current = append(&self.path)?;
+        let size = current.metadata()?.len();
+        *file = (current, size);
+        Ok(edited)
+    }
+}
+
+/// What a rewrite does with one event
+enum Edit {
+    Keep,
+    Replace(Value),
+    Drop,
+}
+
+impl AuditSink for JsonlSink {
+    fn record(&self, event: &AuditEvent) -> io::Result<()> {
+        let line = format!("{}\n", event.to_json());
+        let mut file = self.file.lock().unwrap();
+        if !lease::is_leader() {
+            // only the leader rotates or purges a shared log (see lease.rs),
+            // and may have since: append to the file that is there now
+            let current = append(&self.path)?;
+            let size = current.metadata()?.len();
+            *file = (current, size);
+        } else if file.1 > 0 && file.1 + line.len() as u64 > self.max_bytes {
+            *file = (self.rotate()?, 0);
+        }
+        file.0.write_all(line.as_bytes())?;
+        file.1 += line.len() as u64;
+        Ok(())
+    }
+
+    fn export(&self, user: &str) -> io::Result<Vec<Value>> {
+        let _file = self.file.lock().unwrap();
+        let mut events = Vec::new();
+        for path in self.files() {
+            let text = fs::read_to_string(&path)?;
+            events.extend(text.lines().filter_map(|line| serde_json::from_str::<Value>(line).ok()).filter(|event| event["user"] == user));
+        }
+        Ok(events)
+    }
+
+    fn erase(&self, user: &str, tombstone: &str) -> io::Result<usize> {
+        self.rewrite(|mut event| {
+            if event["user"] != user {
+                return Edit::Keep;
+            }
+            for field in ["user", "source", "detail"] {
+                event[field] = Value::from("");
+            }
+            event["erased"] = Value::from(tombstone);
+            Edit::Replace(event)
+        })
+    }
+
+    fn purge(&self, policy: &RetentionPolicy, now: SystemTime) -> io::Result<usize> {
+        self.rewrite(|event| {
+            let action = event["action"].as_str().unwrap_or_default();
+            let outcome = event["outcome"].as_str().unwrap_or_default();
+            match event["time"].as_str().and_then(parse_rfc3339) {
+                Some(time) if policy.audit_expired(action, outcome, time, now) => Edit::Drop,
+                _ => Edit::Keep,
+            }
+        })
+    }
+}
+
+/// RFC 5424 messages to a syslog daemon, over UDP (`host:port`) or a local
+/// datagram socket (a path such as `/dev/log`)
+#[derive(Debug)]
+pub struct SyslogSink {
+    socket: SyslogSocket,
+    hostname: String,
+}
+
+#[derive(Debug)]
+enum SyslogSocket {
+    Udp(UdpSocket),
+    #[cfg(unix)]
+    Unix(std::os::unix::net::UnixDatagram),
+}
+
+impl SyslogSink {
+    pub fn connect(address: &str) -> io::Result<Self> {
+        let socket = match address.starts_with('/') {
+            #[cfg(unix)]
+            true => {
+                let socket = std::os::unix::net::UnixDatagram::unbound()?;
+                socket.connect(address)?;
+                SyslogSocket::Unix(socket)
+            }
+            _ => {
+                let socket = UdpSocket::bind(("0.0.0.0", 0))?;
+                socket.connect(address)?;
+                SyslogSocket::Udp(socket)
+            }
+        };
+        Ok(SyslogSink { socket, hostname: hostname() })
+    }
+}
+
+impl AuditSink for SyslogSink {
+    fn record(&self, event: &AuditEvent) -> io::Result<()> {
+        let message = event.to_syslog(&self.hostname, "zkp-auth");
+        match &self.socket {
+            SyslogSocket::Udp(socket) => socket.send(message.as_bytes()),
+            #[cfg(unix)]
+            SyslogSocket::Unix(socket) => socket.send(message.as_bytes()),
+        }
+        .map(|_| ())
+    }
+}
+
+const AUTHPRIV: u32 = 10;
+
+fn append(path: &Path) -> io::Result<File> {
+    OpenOptions::new().create(true).append(true).open(path)
+}
+
+/// the machine's name as syslog wants it, "-" when unknown
+pub(crate) fn hostname() -> String {
+    fs::read_to_string("/proc/sys/kernel/hostname")
+        .or_else(|_| std::env::var("HOSTNAME"))
+        .map(|name| name.trim().to_string())
+        .unwrap_or_default()
+}
+
+fn or_nil(value: &str) -> &str {
+    match value.is_empty() {
+        true => "-",
+        false => value,
+    }
+}
+
+/// PARAM-VALUE escaping from RFC 5424 section 6.3.3
+fn escape_param(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace(']', "\\]")
+}
+
+/// `2026-10-15T10:41:55.123Z`
+fn rfc3339(time: SystemTime) -> String {
+    let millis = unix_millis(time);
+    let secs = millis / 1000;
+    let (year, month, day) = civil_from_days((secs / 86400) as i64);
+    let rest = secs % 86400;
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        rest / 3600,
+        rest / 60 % 60,
+        rest % 60,
+        millis % 1000
+    )
+}
+
+/// the time in an event, as rfc3339 writes it; None for anything else
+pub fn parse_rfc3339(text: &str) -> Option<SystemTime> {
+    let number = |range: std::ops::Range<usize>| text.get(range)?.parse::<u64>().ok();
+    let bytes = text.as_bytes();
+    if bytes.len() != 24 || [4, 7, 10, 13, 16, 19, 23].iter().zip(b"--T::.Z").any(|(&i, &c)| bytes[i] != c) {
+        return None;
+    }
+    let days = days_from_civil(number(0..4)? as i64, number(5..7)? as i64, number(8..10)? as i64);
+    let secs = u64::try_from(days).ok()? * 86400 + number(11..13)? * 3600 + number(14..16)? * 60 + number(17..19)?;
+    Some(UNIX_EPOCH + Duration::from_millis(secs * 1000 + number(20..23)?))
+}
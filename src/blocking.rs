@@ -0,0 +1,188 @@
+//! A dedicated thread pool for big-integer work.
+//!
+//! A modpow on 1024-bit numbers takes long enough to stall every other
+//! request sharing a tokio worker, so handlers hand it to a [`CryptoPool`]:
+//! a fixed number of OS threads with a bounded queue. A full queue rejects new
+//! work with `ResourceExhausted` instead of letting it pile up, so a burst of
+//! logins can't starve the threads serving I/O.
+//!
+//! When the client goes away tonic drops the handler's future; a job that
+//! hasn't started by then is skipped instead of tying up a pool thread (a
+//! running modpow can't be interrupted). A job that panics answers `Internal`
+//! and the thread goes on to the next one.
+
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use tokio::sync::oneshot;
+use tonic::{Code, Status};
+
+use crate::catch_panic;
+
+/// Jobs that may wait for a thread before new ones are rejected
+pub const DEFAULT_QUEUE: usize = 64;
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/// Fixed-size pool of threads for crypto work
+#[derive(Debug)]
+pub struct CryptoPool {
+    sender: SyncSender<Job>,
+    threads: usize,
+    queue: usize,
+    stats: Arc<Counters>,
+}
+
+/// What the pool has been doing, for logs and metrics
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PoolStats {
+    pub queued: u64,    // waiting for a thread right now
+    pub running: u64,   // on a thread right now
+    pub completed: u64, // ran to the end
+    pub rejected: u64,  // turned away because the queue was full
+    pub cancelled: u64, // skipped because the caller was gone
+    pub panicked: u64,  // panicked, and answered Internal
+    pub total_wait: Duration, // time completed jobs spent queued, summed
+}
+
+#[derive(Debug, Default)]
+struct Counters {
+    queued: AtomicU64,
+    running: AtomicU64,
+    completed: AtomicU64,
+    rejected: AtomicU64,
+    cancelled: AtomicU64,
+    panicked: AtomicU64,
+    wait_micros: AtomicU64,
+}
+
+/// Marks the job cancelled when the awaiting future is dropped
+struct CancelOnDrop(Arc<AtomicBool>);
+
+impl Drop for CancelOnDrop {
+    fn drop(&mut self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+}
+
+impl CryptoPool {
+    /// `threads` workers (at least one) and room for `queue` waiting jobs
+    pub fn new(threads: usize, queue: usize) -> Self {
+        let threads = threads.max(1);
+        let (sender, receiver) = mpsc::sync_channel::<Job>(queue);
+        let receiver = Arc::new(Mutex::new(receiver));
+        for i in 0..threads {
+            let receiver = receiver.clone();
+            thread::Builder::new()
+                .name(format!("crypto-{}", i))
+                .spawn(move || worker(&receiver))
+                .expect("could not start a crypto thread");
+        }
+        CryptoPool {
+            sender,
+            threads,
+            queue,
+            stats: Arc::new(Counters::default()),
+        }
+    }
+
+    pub fn threads(&self) -> usize {
+        self.threads
+    }
+
+    pub fn queue(&self) -> usize {
+        self.queue
+    }
+
+    pub fn stats(&self) -> PoolStats {
+        let stats = &self.stats;
+        PoolStats {
+            queued: stats.queued.load(Ordering::Relaxed),
+            running: stats.running.load(Ordering::Relaxed),
+            completed: stats.completed.load(Ordering::Relaxed),
+            rejected: stats.rejected.load(Ordering::Relaxed),
+            cancelled: stats.cancelled.load(Ordering::Relaxed),
+            panicked: stats.panicked.load(Ordering::Relaxed),
+            total_wait: Duration::from_micros(stats.wait_micros.load(Ordering::Relaxed)),
+        }
+    }
+
+    /// run `job` on the pool; ResourceExhausted when the queue is full,
+    /// Cancelled if the caller gave up before it got a thread
+    pub async fn run<T, F>(&self, job: F) -> Result<T, Status>
+    where
+        T: Send + 'static,
+        F: FnOnce() -> T + Send + 'static,
+    {
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let _guard = CancelOnDrop(cancelled.clone());
+        let (done, result) = oneshot::channel();
+        let stats = self.stats.clone();
+        let queued_at = Instant::now();
+
+        let task: Job = Box::new(move || {
+            stats.queued.fetch_sub(1, Ordering::Relaxed);
+            if cancelled.load(Ordering::Relaxed) {
+                stats.cancelled.fetch_add(1, Ordering::Relaxed);
+                return;
+            }
+            stats.wait_micros.fetch_add(queued_at.elapsed().as_micros() as u64, Ordering::Relaxed);
+            stats.running.fetch_add(1, Ordering::Relaxed);
+            let result = catch_panic::contain(job);
+            // count before answering, so the caller already sees it in stats()
+            stats.running.fetch_sub(1, Ordering::Relaxed);
+            match result {
+                Ok(_) => stats.completed.fetch_add(1, Ordering::Relaxed),
+                Err(_) => stats.panicked.fetch_add(1, Ordering::Relaxed),
+            };
+            let _ = done.send(result);
+        });
+
+        self.stats.queued.fetch_add(1, Ordering::Relaxed);
+        if let Err(e) = self.sender.try_send(task) {
+            self.stats.queued.fetch_sub(1, Ordering::Relaxed);
+            return Err(match e {
+                TrySendError::Full(_) => {
+                    self.stats.rejected.fetch_add(1, Ordering::Relaxed);
+                    Status::new(Code::ResourceExhausted, "server is busy, try again later")
+                }
+                TrySendError::Disconnected(_) => Status::new(Code::Internal, "crypto pool is gone"),
+            });
+        }
+
+        match result.await {
+            Ok(Ok(result)) => Ok(result),
+            Ok(Err(panic)) => {
+                println!("💥 crypto job {}\n{}", panic, panic.backtrace);
+                Err(Status::new(Code::Internal, "crypto task failed"))
+            }
+            Err(_) => Err(Status::new(Code::Internal, "crypto task failed")),
+        }
+    }
+}
+
+/// one thread per CPU, with the default queue
+impl Default for CryptoPool {
+    fn default() -> Self {
+        CryptoPool::new(default_threads(), DEFAULT_QUEUE)
+    }
+}
+
+/// one thread per CPU
+pub fn default_threads() -> usize {
+    thread::available_parallelism().map(usize::from).unwrap_or(1)
+}
+
+/// take jobs until the pool is dropped
+fn worker(receiver: &Mutex<Receiver<Job>>) {
+    loop {
+        let job = match receiver.lock().unwrap().recv() {
+            Ok(job) => job,
+            Err(_) => return,
+        };
+        job();
+    }
+}
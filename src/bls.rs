@@ -0,0 +1,172 @@
+//! Chaum-Pedersen in G1 of BLS12-381 (the `bls12-381` feature).
+//!
+//! The same non-interactive proofs as [`crate::nizk`], in the group that BLS
+//! signatures and most pairing-based tooling already use, so a deployment
+//! can keep one curve library and one key format. G1 points are 48 bytes
+//! compressed and scalars 32, so (r1, r2, s) is 128 bytes. Proofs come back
+//! as the usual [`NonInteractiveProof`]; [`to_compact`] and [`from_compact`]
+//! convert the numbers to and from a fixed 128-byte array.
+//!
+//! alpha is the standard G1 generator; beta is hashed to G1 with the RFC 9380
+//! hash-to-curve (SHA-256, SSWU), so nobody knows its discrete log. Only G1
+//! is used: no pairings are needed to check a proof, and keys here are not
+//! BLS signing keys (reusing one x for both is up to the caller and not
+//! covered by any proof of security).
+
+use bls12_381::hash_to_curve::{ExpandMsgXmd, HashToCurve};
+use bls12_381::{G1Affine, G1Projective, Scalar};
+use num_bigint::BigUint;
+use sha2::{Digest, Sha512};
+
+use crate::clock::Clock;
+use crate::nizk::{absorb, new_proof_id, NonInteractiveProof, ValidityWindow};
+use crate::rng;
+
+/// Domain separation for the Fiat-Shamir challenge
+const DOMAIN: &[u8] = b"zkp-auth/non-interactive/bls12-381-g1/v1";
+/// Hash-to-curve domain separation tag for beta (RFC 9380 style)
+const BETA_DST: &[u8] = b"ZKP-AUTH-V01-CS01-with-BLS12381G1_XMD:SHA-256_SSWU_RO_";
+
+/// Bytes of a compressed G1 point
+const POINT_LEN: usize = 48;
+/// Bytes of (r1, r2, s)
+pub const PROOF_LEN: usize = 2 * POINT_LEN + 32;
+/// Bytes of (y1, y2)
+pub const PUBLIC_KEY_LEN: usize = 2 * POINT_LEN;
+
+/// The group: two generators of G1
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Bls12381G1 {
+    pub alpha: G1Projective,
+    pub beta: G1Projective,
+}
+
+/// (y1, y2) = (x * alpha, x * beta)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlsPublicKey {
+    pub y1: G1Projective,
+    pub y2: G1Projective,
+}
+
+impl Default for Bls12381G1 {
+    fn default() -> Self {
+        Bls12381G1 {
+            alpha: G1Projective::generator(),
+            beta: <G1Projective as HashToCurve<ExpandMsgXmd<sha2_09::Sha256>>>::hash_to_curve(b"beta", BETA_DST),
+        }
+    }
+}
+
+impl Bls12381G1 {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// a uniformly random scalar, from the same generator as [`crate::ZKP`]'s numbers
+    pub fn random_scalar() -> Scalar {
+        let mut wide = [0u8; 64];
+        rng::with_rng(|rng| rng.fill_bytes(&mut wide));
+        Scalar::from_bytes_wide(&wide)
+    }
+
+    pub fn public_key(&self, x: &Scalar) -> BlsPublicKey {
+        BlsPublicKey { y1: self.alpha * x, y2: self.beta * x }
+    }
+
+    /// prove knowledge of x for `window`, bound to `context` (e.g. who the proof is for)
+    pub fn prove_non_interactive(&self, x: &Scalar, window: ValidityWindow, context: &[u8]) -> NonInteractiveProof {
+        let key = self.public_key(x);
+        let k = Self::random_scalar();
+        let (r1, r2) = (G1Affine::from(self.alpha * k), G1Affine::from(self.beta * k));
+        let id = new_proof_id();
+        let c = self.fiat_shamir(&key, &r1, &r2, &id, &window, context);
+        let s = k - c * x;
+        NonInteractiveProof {
+            id,
+            r1: r1.to_compressed().to_vec(),
+            r2: r2.to_compressed().to_vec(),
+            s: s.to_bytes().to_vec(),
+            window,
+        }
+    }
+
+    /// check `proof` for `key` and `context`, and that `clock` is inside its window
+    pub fn verify_non_interactive(&self, key: &BlsPublicKey, proof: &NonInteractiveProof, context: &[u8], clock: impl Into<Clock>) -> bool {
+        if !proof.window.contains(clock) {
+            return false;
+        }
+        let Some(compact) = to_compact(proof) else {
+            return false;
+        };
+        // from_compressed checks the points are on the curve and in G1
+        let r1 = G1Affine::from_compressed(&compact[..POINT_LEN].try_into().unwrap());
+        let r2 = G1Affine::from_compressed(&compact[POINT_LEN..2 * POINT_LEN].try_into().unwrap());
+        let s = Scalar::from_bytes(&compact[2 * POINT_LEN..].try_into().unwrap());
+        let (Some(r1), Some(r2), Some(s)) = (Option::<G1Affine>::from(r1), Option::<G1Affine>::from(r2), Option::<Scalar>::from(s)) else {
+            return false;
+        };
+        let c = self.fiat_shamir(key, &r1, &r2, &proof.id, &proof.window, context);
+        G1Projective::from(r1) == self.alpha * s + key.y1 * c && G1Projective::from(r2) == self.beta * s + key.y2 * c
+    }
+
+    /// H(domain | generators | y1 | y2 | r1 | r2 | id | window | context) as a scalar,
+    /// laid out like [`crate::nizk`]'s hash
+    fn fiat_shamir(&self, key: &BlsPublicKey, r1: &G1Affine, r2: &G1Affine, id: &str, window: &ValidityWindow, context: &[u8]) -> Scalar {
+        let points = [self.alpha.into(), self.beta.into(), key.y1.into(), key.y2.into(), *r1, *r2].map(|point: G1Affine| point.to_compressed());
+        let mut hasher = Sha512::new();
+        absorb(&mut hasher, DOMAIN, &points, id, window, context);
+        Scalar::from_bytes_wide(&hasher.finalize().into())
+    }
+}
+
+impl BlsPublicKey {
+    /// y1 | y2, compressed
+    pub fn to_bytes(&self) -> [u8; PUBLIC_KEY_LEN] {
+        let mut bytes = [0u8; PUBLIC_KEY_LEN];
+        bytes[..POINT_LEN].copy_from_slice(&G1Affine::from(self.y1).to_compressed());
+        bytes[POINT_LEN..].copy_from_slice(&G1Affine::from(self.y2).to_compressed());
+        bytes
+    }
+
+    /// None unless both halves are valid G1 points
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() != PUBLIC_KEY_LEN {
+            return None;
+        }
+        let y1 = Option::<G1Affine>::from(G1Affine::from_compressed(&bytes[..POINT_LEN].try_into().unwrap()))?;
+        let y2 = Option::<G1Affine>::from(G1Affine::from_compressed(&bytes[POINT_LEN..].try_into().unwrap()))?;
+        Some(BlsPublicKey { y1: y1.into(), y2: y2.into() })
+    }
+}
+
+/// r1 | r2 | s of a G1 proof, None if a field has the wrong length
+pub fn to_compact(proof: &NonInteractiveProof) -> Option<[u8; PROOF_LEN]> {
+    if proof.r1.len() != POINT_LEN || proof.r2.len() != POINT_LEN || proof.s.len() != 32 {
+        return None;
+    }
+    let mut bytes = [0u8; PROOF_LEN];
+    bytes[..POINT_LEN].copy_from_slice(&proof.r1);
+    bytes[POINT_LEN..2 * POINT_LEN].copy_from_slice(&proof.r2);
+    bytes[2 * POINT_LEN..].copy_from_slice(&proof.s);
+    Some(bytes)
+}
+
+/// rebuild a proof from its 128 bytes and the id and window sent alongside
+pub fn from_compact(bytes: &[u8; PROOF_LEN], id: String, window: ValidityWindow) -> NonInteractiveProof {
+    NonInteractiveProof {
+        id,
+        r1: bytes[..POINT_LEN].to_vec(),
+        r2: bytes[POINT_LEN..2 * POINT_LEN].to_vec(),
+        s: bytes[2 * POINT_LEN..].to_vec(),
+        window,
+    }
+}
+
+/// x reduced into the scalar field, e.g. a secret derived with [`crate::kdf`]
+pub fn scalar_from_biguint(x: &BigUint) -> Scalar {
+    let mut wide = [0u8; 64];
+    let order = BigUint::from_bytes_le(&(-Scalar::one()).to_bytes()) + 1u32;
+    let reduced = (x % order).to_bytes_le();
+    wide[..reduced.len()].copy_from_slice(&reduced);
+    Scalar::from_bytes_wide(&wide)
+}
@@ -0,0 +1,93 @@
+//! Known-breached passwords, looked up in Have I Been Pwned's Pwned Passwords.
+//!
+//! The lookup uses k-anonymity: only the first 5 hex digits of the password's
+//! SHA-1 go out, the range API answers with the suffixes of every breached
+//! hash that starts with them (several hundred), and the match is made here.
+//! Neither the password nor its full hash leaves the client, and padding
+//! (`Add-Padding: true`) keeps the size of the answer from telling the prefix
+//! apart. Like every password check it runs where the password is: the server
+//! only ever sees (y1, y2).
+
+use std::sync::Arc;
+
+use hyper::{Body, Request, Uri};
+use ring::digest::{digest, SHA1_FOR_LEGACY_USE_ONLY};
+use tokio_rustls::rustls::ClientConfig;
+
+use crate::http;
+
+/// The Pwned Passwords range API, the prefix goes at the end
+pub const RANGE_API: &str = "https://api.pwnedpasswords.com/range/";
+
+/// How many hex digits of the hash are sent
+pub const PREFIX_LENGTH: usize = 5;
+
+/// the upper-case hex SHA-1 of `password`, split into the prefix that is sent
+/// and the suffix that is looked for in the answer
+pub fn hash_parts(password: &str) -> (String, String) {
+    let hash = hex::encode_upper(digest(&SHA1_FOR_LEGACY_USE_ONLY, password.as_bytes()));
+    let (prefix, suffix) = hash.split_at(PREFIX_LENGTH);
+    (prefix.to_string(), suffix.to_string())
+}
+
+/// how often `suffix` was seen, in a range API answer (`SUFFIX:COUNT` lines);
+/// padding lines have a count of 0
+pub fn count_in_range(range: &str, suffix: &str) -> u64 {
+    range
+        .lines()
+        .filter_map(|line| line.trim().split_once(':'))
+        .find(|(candidate, _)| candidate.eq_ignore_ascii_case(suffix))
+        .and_then(|(_, count)| count.trim().parse().ok())
+        .unwrap_or(0)
+}
+
+/// Looks passwords up in a range API
+pub struct BreachCheck {
+    api: String, // ends in /, the prefix is appended
+    tls: Option<Arc<ClientConfig>>, // None for an http:// API
+}
+
+impl std::fmt::Debug for BreachCheck {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BreachCheck").field("api", &self.api).finish()
+    }
+}
+
+impl Default for BreachCheck {
+    fn default() -> Self {
+        BreachCheck::new(RANGE_API).expect("the Pwned Passwords API is a valid https URL")
+    }
+}
+
+impl BreachCheck {
+    /// a range API at `api` (e.g. [`RANGE_API`], or a mirror); https uses the system's roots
+    pub fn new(api: &str) -> Result<Self, String> {
+        let api = match api.ends_with('/') {
+            true => api.to_string(),
+            false => format!("{}/", api),
+        };
+        let uri = format!("{}00000", api).parse::<Uri>().map_err(|e| format!("bad range API {}: {}", api, e))?;
+        let tls = match uri.scheme_str() {
+            Some("https") => Some(http::tls_config(None).map_err(|e| format!("no TLS roots: {}", e))?),
+            Some("http") => None,
+            _ => return Err(format!("the range API {} must be http:// or https://", api)),
+        };
+        Ok(BreachCheck { api, tls })
+    }
+
+    /// how many times `password` appears in known breaches, 0 when it doesn't
+    pub async fn times_seen(&self, password: &str) -> Result<u64, String> {
+        let (prefix, suffix) = hash_parts(password);
+        let get = Request::get(format!("{}{}", self.api, prefix))
+            .header(hyper::header::USER_AGENT, concat!("zkp-auth/", env!("CARGO_PKG_VERSION")))
+            .header("Add-Padding", "true")
+            .body(Body::empty())
+            .map_err(|e| e.to_string())?;
+        let (status, body) = http::exchange(get, self.tls.as_ref()).await?;
+        if !status.is_success() {
+            return Err(format!("the range API answered {}", status));
+        }
+        let range = std::str::from_utf8(&body).map_err(|_| "the range API's answer isn't text".to_string())?;
+        Ok(count_in_range(range, &suffix))
+    }
+}
@@ -0,0 +1,134 @@
+//! Validated construction of [`ZKP`].
+//!
+//! A struct literal accepts any four numbers, including a group where the
+//! generators don't have order q and proofs leak the secret. The builder runs
+//! the group checks once, when the instance is made, so code holding a `ZKP`
+//! from `build()` doesn't have to re-check it. p and q go through
+//! Miller-Rabin: g^q = 1 only says the order of g divides q, which is the
+//! order itself only when q is prime.
+
+use num_bigint::{BigUint, RandBigInt};
+
+use crate::params::ParametersError;
+use crate::ZKP;
+
+/// Collects (p, q, alpha, beta) for [`ZKP::builder`]
+#[derive(Debug, Clone, Default)]
+pub struct ZKPBuilder {
+    p: Option<BigUint>,
+    q: Option<BigUint>,
+    alpha: Option<BigUint>,
+    beta: Option<BigUint>,
+}
+
+impl ZKPBuilder {
+    /// the prime modulus
+    pub fn p(mut self, p: BigUint) -> Self {
+        self.p = Some(p);
+        self
+    }
+
+    /// the prime order of the subgroup, must divide p - 1
+    pub fn q(mut self, q: BigUint) -> Self {
+        self.q = Some(q);
+        self
+    }
+
+    /// the first generator
+    pub fn alpha(mut self, alpha: BigUint) -> Self {
+        self.alpha = Some(alpha);
+        self
+    }
+
+    /// the second generator
+    pub fn beta(mut self, beta: BigUint) -> Self {
+        self.beta = Some(beta);
+        self
+    }
+
+    /// all four values from the RFC 5114 group (see [`ZKP::get_constants`])
+    pub fn rfc5114(self) -> Self {
+        let (alpha, beta, p, q) = ZKP::get_constants();
+        self.p(p).q(q).alpha(alpha).beta(beta)
+    }
+
+    /// check every value is set and the numbers form a usable group
+    pub fn build(self) -> Result<ZKP, ParametersError> {
+        let zkp = ZKP {
+            p: self.p.ok_or(ParametersError::InvalidGroup("p is not set"))?,
+            q: self.q.ok_or(ParametersError::InvalidGroup("q is not set"))?,
+            alpha: self.alpha.ok_or(ParametersError::InvalidGroup("alpha is not set"))?,
+            beta: self.beta.ok_or(ParametersError::InvalidGroup("beta is not set"))?,
+        };
+        check_group(&zkp)?;
+        Ok(zkp)
+    }
+}
+
+impl ZKP {
+    /// start building a validated instance
+    pub fn builder() -> ZKPBuilder {
+        ZKPBuilder::default()
+    }
+}
+
+/// Miller-Rabin rounds; a composite passes them all with probability at most 4^-32
+const PRIME_ROUNDS: usize = 32;
+
+/// sanity checks: p and q are prime, q divides p - 1 and both generators have order q
+fn check_group(zkp: &ZKP) -> Result<(), ParametersError> {
+    let one = BigUint::from(1u32);
+    if zkp.p <= one || zkp.q <= one {
+        return Err(ParametersError::InvalidGroup("p and q must be greater than 1"));
+    }
+    if (&zkp.p - &one) % &zkp.q != BigUint::from(0u32) {
+        return Err(ParametersError::InvalidGroup("q does not divide p - 1"));
+    }
+    if !is_probable_prime(&zkp.q) {
+        return Err(ParametersError::InvalidGroup("q is not prime"));
+    }
+    if !is_probable_prime(&zkp.p) {
+        return Err(ParametersError::InvalidGroup("p is not prime"));
+    }
+    for generator in [&zkp.alpha, &zkp.beta] {
+        if *generator <= one || *generator >= zkp.p {
+            return Err(ParametersError::InvalidGroup("generators must be between 1 and p"));
+        }
+        if generator.modpow(&zkp.q, &zkp.p) != one {
+            return Err(ParametersError::InvalidGroup("generators must have order q"));
+        }
+    }
+    Ok(())
+}
+
+/// Miller-Rabin with random bases, after trial division by the small primes
+fn is_probable_prime(n: &BigUint) -> bool {
+    let (one, two) = (BigUint::from(1u32), BigUint::from(2u32));
+    if *n < two {
+        return false;
+    }
+    for small in [2u32, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37] {
+        if n % small == BigUint::from(0u32) {
+            return *n == BigUint::from(small);
+        }
+    }
+    // n - 1 = d * 2^s with d odd
+    let n_minus_one = n - &one;
+    let s = n_minus_one.trailing_zeros().expect("n is odd and above 37");
+    let d = &n_minus_one >> s;
+    // the verifier's own randomness, so never the seeded generator
+    let mut rng = rand::thread_rng();
+    (0..PRIME_ROUNDS).all(|_| {
+        let mut x = rng.gen_biguint_range(&two, &n_minus_one).modpow(&d, n);
+        if x == one || x == n_minus_one {
+            return true;
+        }
+        for _ in 1..s {
+            x = &x * &x % n;
+            if x == n_minus_one {
+                return true;
+            }
+        }
+        false
+    })
+}
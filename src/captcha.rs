@@ -0,0 +1,95 @@
+//! CAPTCHA tokens checked with the provider before a challenge is issued.
+//!
+//! Cloudflare Turnstile, hCaptcha and reCAPTCHA verify the same way: the
+//! server POSTs its secret, the token from the client's widget and the
+//! client's IP address to the provider's siteverify endpoint, and gets back
+//! `{"success": true|false, "error-codes": [...], "hostname": ...}`. A token
+//! is good for one verification only, so a bot has to solve a CAPTCHA for
+//! every password it tries. When hostnames are configured, the token must
+//! also have been solved on one of those sites.
+
+use std::sync::Arc;
+
+use hyper::{Body, Request, Uri};
+use serde_json::Value;
+use tokio_rustls::rustls::ClientConfig;
+
+use crate::gate::{ChallengeContext, GateError, GateFuture, PreChallengeGate, TOKEN_HEADER};
+use crate::http;
+
+/// Cloudflare Turnstile's siteverify endpoint
+pub const TURNSTILE: &str = "https://challenges.cloudflare.com/turnstile/v0/siteverify";
+
+/// Checks the token of every challenge request with a siteverify endpoint
+pub struct Captcha {
+    uri: Uri,
+    secret: String,
+    hostnames: Vec<String>, // sites tokens may come from, empty for any
+    tls: Option<Arc<ClientConfig>>, // None for an http:// endpoint
+}
+
+impl std::fmt::Debug for Captcha {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Captcha").field("uri", &self.uri).field("hostnames", &self.hostnames).finish()
+    }
+}
+
+impl Captcha {
+    pub fn new(uri: Uri, secret: &str, hostnames: Vec<String>, tls: Option<Arc<ClientConfig>>) -> Self {
+        Captcha { uri, secret: secret.trim().to_string(), hostnames, tls }
+    }
+
+    async fn verify(&self, request: &ChallengeContext) -> Result<(), GateError> {
+        if request.token.is_empty() {
+            return Err(GateError::Refused(format!("solve the CAPTCHA and send its token in {}", TOKEN_HEADER)));
+        }
+        let mut form = vec![("secret", self.secret.as_str()), ("response", request.token.as_str())];
+        // the peer's IP, without the port
+        let ip = request.source.rsplit_once(':').map_or(request.source.as_str(), |(ip, _)| ip).trim_matches(['[', ']']);
+        if !ip.is_empty() {
+            form.push(("remoteip", ip));
+        }
+        let body = form.iter().map(|(name, value)| format!("{}={}", name, form_encode(value))).collect::<Vec<_>>().join("&");
+        let post = Request::post(self.uri.clone())
+            .header(hyper::header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+            .body(Body::from(body))
+            .expect("a valid request");
+
+        let (status, body) = http::exchange(post, self.tls.as_ref()).await.map_err(GateError::Unavailable)?;
+        if !status.is_success() {
+            return Err(GateError::Unavailable(format!("the CAPTCHA provider answered {}", status)));
+        }
+        let outcome: Value = serde_json::from_slice(&body)
+            .map_err(|e| GateError::Unavailable(format!("the CAPTCHA provider's answer isn't JSON: {}", e)))?;
+        if outcome["success"] != true {
+            let codes: Vec<&str> = outcome["error-codes"].as_array().into_iter().flatten().filter_map(Value::as_str).collect();
+            return Err(GateError::Refused(match codes.is_empty() {
+                true => "the CAPTCHA token wasn't accepted".to_string(),
+                false => format!("the CAPTCHA token wasn't accepted ({})", codes.join(", ")),
+            }));
+        }
+        let hostname = outcome["hostname"].as_str().unwrap_or_default();
+        if !self.hostnames.is_empty() && !self.hostnames.iter().any(|allowed| allowed == hostname) {
+            return Err(GateError::Refused(format!("the CAPTCHA was solved on {:?}, not on this site", hostname)));
+        }
+        Ok(())
+    }
+}
+
+impl PreChallengeGate for Captcha {
+    fn check<'a>(&'a self, request: &'a ChallengeContext) -> GateFuture<'a> {
+        Box::pin(self.verify(request))
+    }
+}
+
+/// application/x-www-form-urlencoded: unreserved characters as they are, the rest as %XX
+pub fn form_encode(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => encoded.push(byte as char),
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
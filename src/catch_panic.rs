@@ -0,0 +1,162 @@
+//! Keeping a panic in one request from taking down the server.
+//!
+//! [`CatchPanicLayer`] goes in front of every gRPC service: a handler that
+//! panics (in crypto, storage, anywhere) answers `Internal` and the panic is
+//! logged with its location and a backtrace, instead of dropping the
+//! connection. The crypto pool and the scheduler contain panics the same way,
+//! so a bad job costs one request or one run, not a thread or the job itself.
+//!
+//! Panics outside [`contain`] and [`contained`] still go to the default hook,
+//! so startup errors look as they always did.
+
+use std::backtrace::Backtrace;
+use std::cell::{Cell, RefCell};
+use std::fmt;
+use std::future::Future;
+use std::panic::{self, AssertUnwindSafe};
+use std::pin::Pin;
+use std::sync::{Mutex, MutexGuard, Once, PoisonError};
+use std::task::{Context, Poll};
+
+use tonic::body::BoxBody;
+use tonic::codegen::http;
+use tonic::Status;
+use tower::{Layer, Service};
+
+thread_local! {
+    static CONTAINED: Cell<usize> = const { Cell::new(0) }; // contain() calls on this thread's stack
+    static LAST: RefCell<Option<(String, Backtrace)>> = const { RefCell::new(None) }; // where the last contained panic happened
+}
+
+/// A panic that was caught
+#[derive(Debug)]
+pub struct Panic {
+    pub message: String,
+    pub location: String, // file:line:column, empty if unknown
+    pub backtrace: String,
+}
+
+impl fmt::Display for Panic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.location.is_empty() {
+            true => write!(f, "panicked: {}", self.message),
+            false => write!(f, "panicked at {}: {}", self.location, self.message),
+        }
+    }
+}
+
+impl std::error::Error for Panic {}
+
+/// record contained panics for [`Panic`] instead of printing them
+fn install_hook() {
+    static HOOK: Once = Once::new();
+    HOOK.call_once(|| {
+        let default = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            if CONTAINED.get() == 0 {
+                return default(info);
+            }
+            let location = info.location().map(|l| l.to_string()).unwrap_or_default();
+            LAST.set(Some((location, Backtrace::force_capture())));
+        }));
+    });
+}
+
+/// run `f`, turning a panic into [`Panic`]
+pub fn contain<T>(f: impl FnOnce() -> T) -> Result<T, Panic> {
+    install_hook();
+    CONTAINED.set(CONTAINED.get() + 1);
+    let result = panic::catch_unwind(AssertUnwindSafe(f));
+    CONTAINED.set(CONTAINED.get() - 1);
+    result.map_err(|payload| {
+        let message = match (payload.downcast_ref::<&str>(), payload.downcast_ref::<String>()) {
+            (Some(message), _) => message.to_string(),
+            (_, Some(message)) => message.clone(),
+            _ => "a panic without a message".to_string(),
+        };
+        let (location, backtrace) = LAST.take().map(|(l, b)| (l, b.to_string())).unwrap_or_default();
+        Panic { message, location, backtrace }
+    })
+}
+
+/// `future`, with a panic while polling it turned into [`Panic`]
+pub fn contained<F: Future>(future: F) -> Contained<F> {
+    Contained(Box::pin(future))
+}
+
+/// See [`contained`]
+pub struct Contained<F>(Pin<Box<F>>);
+
+impl<F: Future> Future for Contained<F> {
+    type Output = Result<F::Output, Panic>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match contain(|| self.0.as_mut().poll(cx)) {
+            Ok(Poll::Pending) => Poll::Pending,
+            Ok(Poll::Ready(output)) => Poll::Ready(Ok(output)),
+            Err(panic) => Poll::Ready(Err(panic)),
+        }
+    }
+}
+
+/// a mutex even if a panicking handler held it: what that handler left half
+/// done is better than failing every later request
+pub trait LockExt<T> {
+    fn locked(&self) -> MutexGuard<'_, T>;
+}
+
+impl<T> LockExt<T> for Mutex<T> {
+    fn locked(&self) -> MutexGuard<'_, T> {
+        self.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Answers `Internal` for requests whose handler panicked
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CatchPanicLayer;
+
+impl<S> Layer<S> for CatchPanicLayer {
+    type Service = CatchPanic<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        CatchPanic(inner)
+    }
+}
+
+/// See [`CatchPanicLayer`]
+#[derive(Debug, Clone)]
+pub struct CatchPanic<S>(S);
+
+impl<S, B> Service<http::Request<B>> for CatchPanic<S>
+where
+    S: Service<http::Request<B>, Response = http::Response<BoxBody>>,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<S::Response, S::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.0.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: http::Request<B>) -> Self::Future {
+        let path = request.uri().path().to_string();
+        let future = match contain(|| self.0.call(request)) {
+            Ok(future) => future,
+            Err(panic) => return Box::pin(async move { Ok(internal(&path, panic)) }),
+        };
+        Box::pin(async move {
+            match contained(future).await {
+                Ok(result) => result,
+                Err(panic) => Ok(internal(&path, panic)),
+            }
+        })
+    }
+}
+
+/// log the panic, answer without giving any of it away
+fn internal(path: &str, panic: Panic) -> http::Response<BoxBody> {
+    println!("💥 {} {}\n{}", path, panic, panic.backtrace);
+    Status::internal("internal error").to_http()
+}
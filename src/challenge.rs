@@ -0,0 +1,63 @@
+//! Committed challenges.
+//!
+//! Plain Chaum-Pedersen is only honest-verifier zero knowledge: a verifier
+//! that picks c as a function of (r1, r2) could learn something. In the
+//! committed flow the server first sends H(c | nonce), the client then sends
+//! (r1, r2), and only then does the server open the commitment. The client
+//! checks the opening, so c can't depend on the commitments any more.
+
+use num_bigint::{BigUint, RandBigInt};
+use sha2::{Digest, Sha256};
+
+use crate::rng;
+
+const DOMAIN: &[u8] = b"zkp-auth/challenge-commitment/v1";
+
+/// Length of the random nonce that hides c inside the commitment
+pub const NONCE_LEN: usize = 32;
+
+/// a fresh nonce for one commitment
+pub fn new_nonce() -> Vec<u8> {
+    let mut nonce = vec![0u8; NONCE_LEN];
+    rng::with_rng(|rng| rng.fill_bytes(&mut nonce));
+    nonce
+}
+
+/// H(domain | len(c) | c | nonce)
+pub fn commit(c: &BigUint, nonce: &[u8]) -> Vec<u8> {
+    let c = c.to_bytes_be();
+    let mut hasher = Sha256::new();
+    hasher.update(DOMAIN);
+    hasher.update((c.len() as u32).to_be_bytes());
+    hasher.update(&c);
+    hasher.update(nonce);
+    hasher.finalize().to_vec()
+}
+
+/// does (c, nonce) open `commitment`?
+pub fn verify_opening(commitment: &[u8], c: &BigUint, nonce: &[u8]) -> bool {
+    nonce.len() == NONCE_LEN && commit(c, nonce) == commitment
+}
+
+/// Smallest challenge size a server may be configured with: a prover who
+/// doesn't know x gets through with probability 2^-bits
+pub const MIN_CHALLENGE_BITS: u64 = 80;
+
+/// check a configured challenge size against the minimum and against q
+pub fn validate_bits(bits: u64, q: &BigUint) -> Result<(), &'static str> {
+    if bits < MIN_CHALLENGE_BITS {
+        return Err("challenges need at least 80 bits for sound proofs");
+    }
+    if bits > q.bits() {
+        return Err("challenges can't have more bits than q");
+    }
+    Ok(())
+}
+
+/// a uniformly random challenge of `bits` bits, or below q when q is shorter
+pub fn random(bits: u64, q: &BigUint) -> BigUint {
+    if bits >= q.bits() {
+        return rng::with_rng(|rng| rng.gen_biguint_below(q));
+    }
+    rng::with_rng(|rng| rng.gen_biguint(bits))
+}
@@ -0,0 +1,132 @@
+//! Signed challenge tokens, for servers that keep no pending challenges.
+//!
+//! Normally the server remembers each challenge it hands out (auth_id, c,
+//! r1, r2, ...) until it is answered, so every replica that might get the
+//! answer has to share that state. With `--stateless-challenges` it
+//! remembers nothing: the challenge response carries a [`ChallengeToken`]
+//! with everything the answer is checked against, HMAC-SHA256 signed with a
+//! key all replicas share (`--challenge-token-key-file`). The client sends it
+//! back with its answer and any replica can check it.
+//!
+//! A token can be answered until it expires, as a pending auth_id can until
+//! its TTL. Each replica refuses the same proof for another challenge, and a
+//! token it already logged someone in with (duplicates.rs); a replica that
+//! never saw that login can't know, so the answer could be replayed there
+//! once within the token's lifetime.
+
+use std::fmt;
+use std::io;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+
+use crate::clock::unix_millis;
+use crate::mac::{constant_time_eq, hmac_sha256};
+use crate::params::hex_bytes;
+use crate::ZKP;
+
+/// How long a token can be answered when challenges have no TTL
+pub const DEFAULT_LIFETIME: Duration = Duration::from_secs(5 * 60);
+
+/// Shortest key we accept
+pub const MIN_KEY_LEN: usize = 32;
+
+/// A challenge as the server handed it out
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChallengeToken {
+    pub auth_id: String, // a ULID as usual, for logs and the duplicate filter
+    pub user: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub device_id: String, // the enrolled device whose key answers, empty for the password's
+    #[serde(with = "hex_bytes")]
+    pub c: Vec<u8>,
+    #[serde(with = "hex_bytes")]
+    pub r1: Vec<u8>,
+    #[serde(with = "hex_bytes")]
+    pub r2: Vec<u8>,
+    #[serde(default, with = "hex_bytes", skip_serializing_if = "Vec::is_empty")]
+    pub client: Vec<u8>, // the ClientMetadata sent with the challenge, protobuf-encoded
+    #[serde(default)]
+    pub pow_difficulty: u32,
+    pub expires_unix_millis: u64,
+}
+
+/// Why a token was refused
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TokenError {
+    /// not a token this module issues
+    Malformed(String),
+    /// not signed with our key
+    BadSignature,
+    Expired,
+}
+
+impl fmt::Display for TokenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TokenError::Malformed(reason) => write!(f, "malformed challenge token: {}", reason),
+            TokenError::BadSignature => write!(f, "challenge token signature is invalid"),
+            TokenError::Expired => write!(f, "challenge token has expired"),
+        }
+    }
+}
+
+impl std::error::Error for TokenError {}
+
+/// The key tokens are signed with, the same on every replica
+#[derive(Clone)]
+pub struct ChallengeTokenKey(Vec<u8>);
+
+// never print the secret
+impl fmt::Debug for ChallengeTokenKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ChallengeTokenKey(..)")
+    }
+}
+
+impl ChallengeTokenKey {
+    pub fn new(key: &[u8]) -> io::Result<Self> {
+        if key.len() < MIN_KEY_LEN {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("the challenge token key must be at least {} bytes", MIN_KEY_LEN),
+            ));
+        }
+        Ok(ChallengeTokenKey(key.to_vec()))
+    }
+
+    /// read the key from a file (surrounding whitespace is ignored)
+    pub fn load(path: &Path) -> io::Result<Self> {
+        Self::new(std::fs::read_to_string(path)?.trim().as_bytes())
+    }
+
+    /// a fresh key, good for a single replica only
+    pub fn random() -> Self {
+        ChallengeTokenKey(ZKP::generate_random_string(MIN_KEY_LEN).into_bytes())
+    }
+
+    /// `<payload>.<mac>`, both base64url
+    pub fn sign(&self, token: &ChallengeToken) -> String {
+        let payload = URL_SAFE_NO_PAD.encode(serde_json::to_vec(token).expect("a token serializes"));
+        let mac = URL_SAFE_NO_PAD.encode(hmac_sha256(&self.0, payload.as_bytes()));
+        format!("{}.{}", payload, mac)
+    }
+
+    /// the token in `signed` if we signed it and it hasn't expired at `now`
+    pub fn open(&self, signed: &str, now: SystemTime) -> Result<ChallengeToken, TokenError> {
+        let (payload, mac) = signed.split_once('.').ok_or(TokenError::Malformed("no signature".to_string()))?;
+        let mac = URL_SAFE_NO_PAD.decode(mac).map_err(|e| TokenError::Malformed(e.to_string()))?;
+        if !constant_time_eq(&mac, &hmac_sha256(&self.0, payload.as_bytes())) {
+            return Err(TokenError::BadSignature);
+        }
+        let json = URL_SAFE_NO_PAD.decode(payload).map_err(|e| TokenError::Malformed(e.to_string()))?;
+        let token: ChallengeToken = serde_json::from_slice(&json).map_err(|e| TokenError::Malformed(e.to_string()))?;
+        if unix_millis(now) > token.expires_unix_millis {
+            return Err(TokenError::Expired);
+        }
+        Ok(token)
+    }
+}
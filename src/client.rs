@@ -1,13 +1,8 @@
 use num_bigint::BigUint;
 use std::io::stdin;
 
-// Import our generated gRPC code
-pub mod zkp_auth {
-    include!("./zkp_auth.rs");
-}
-
-// Import the specific types we need from the generated code
-use zkp_auth::{
+// Import the specific types we need from the generated gRPC code
+use rust_zkp_chaum_pedersen::api::v1::{
     auth_client::AuthClient,           // The client to connect to our server
     AuthenticationAnswerRequest,       // Request to send our solution
     AuthenticationChallengeRequest,    // Request to ask for a challenge
@@ -15,23 +10,18 @@ use zkp_auth::{
 };
 
 // Import our ZKP library
-use rust_zkp_chaum_pedersen::ZKP;
+use rust_zkp_chaum_pedersen::{pow, types::Challenge, ZKP};
 
 #[tokio::main]  // This makes our main function async
 async fn main() {
     // Buffer to store user input
     let mut buf = String::new();
     
-    // Get the mathematical constants for our ZKP protocol
-    let (alpha, beta, p, q) = ZKP::get_constants();
-    
-    // Create a ZKP instance with these constants
-    let zkp = ZKP {
-        alpha: alpha.clone(),
-        beta: beta.clone(),
-        p: p.clone(),
-        q: q.clone(),
-    };
+    // Create a ZKP instance with the RFC 5114 constants (checked once, here)
+    let zkp = ZKP::builder()
+        .rfc5114()
+        .build()
+        .expect("❌ RFC 5114 parameters are valid");
 
     // Step 1: Connect to the server
     println!("🔌 Connecting to ZKP Authentication Server...");
@@ -61,7 +51,7 @@ async fn main() {
 
     // Step 4: Generate registration values (y1, y2)
     println!("🔐 Generating registration proof...");
-    let (y1, y2) = zkp.compute_pair(&password);
+    let public_key = zkp.public_key(&password);
     
     // What's happening here:
     // y1 = alpha^password mod p
@@ -72,8 +62,14 @@ async fn main() {
     // Step 5: Send registration request to server
     let register_request = RegisterRequest {
         user: username.clone(),
-        y1: y1.to_bytes_be(),  // Convert BigUint to bytes for network transmission
-        y2: y2.to_bytes_be(),
+        y1: public_key.y1.to_bytes_be(),  // Convert BigUint to bytes for network transmission
+        y2: public_key.y2.to_bytes_be(),
+        idempotency_key: String::new(), // no retries here, so no key needed
+        password_policy_version: 0,     // the password isn't checked here
+        kdf: None,                      // x is simply the password bytes (legacy derivation)
+        beta: Vec::new(),               // the global beta
+        group: String::new(),           // "modp"
+        hash: String::new(),            // "sha256"
     };
 
     let _response = client
@@ -94,10 +90,10 @@ async fn main() {
 
     // Step 7: Generate random number 'k' for this authentication session
     println!("🎲 Generating random challenge values...");
-    let k = ZKP::generate_random_number_below(&q);
+    let k = ZKP::generate_random_number_below(&zkp.q);
     
     // Step 8: Compute commitment values for this session
-    let (r1, r2) = zkp.compute_pair(&k);
+    let commitment = zkp.commitment(&k);
     
     // What's happening:
     // r1 = alpha^k mod p
@@ -107,8 +103,11 @@ async fn main() {
     // Step 9: Send authentication challenge request
     let challenge_request = AuthenticationChallengeRequest {
         user: username.clone(),
-        r1: r1.to_bytes_be(),
-        r2: r2.to_bytes_be(),
+        r1: commitment.r1.to_bytes_be(),
+        r2: commitment.r2.to_bytes_be(),
+        auth_id: String::new(), // a fresh (direct) challenge
+        client: None,           // nothing to report about this device
+        device_id: String::new(), // the password's key
     };
 
     println!("📤 Sending authentication challenge request...");
@@ -120,23 +119,34 @@ async fn main() {
 
     // Step 10: Extract challenge from server response
     let auth_id = challenge_response.auth_id;
-    let c = BigUint::from_bytes_be(&challenge_response.c);
+    let c = Challenge::from_bytes_be(&challenge_response.c);
     
     println!("📥 Received challenge from server (auth_id: {})", auth_id);
 
     // Step 11: Solve the challenge
     println!("🧮 Solving the authentication challenge...");
-    let s = zkp.solve(&k, &c, &login_password);
+    let s = zkp.respond(&k, &c, &login_password);
     
     // What's happening:
     // s = k - c * password mod q
     // This is our "proof" that we know the password without revealing it!
     // The server can verify this using our public commitments (y1, y2) and (r1, r2)
 
+    // A server under load may also ask for a proof of work, to make floods expensive
+    if challenge_response.pow_difficulty > 0 {
+        println!("⛏️  Working out a {}-bit proof of work...", challenge_response.pow_difficulty);
+    }
+    let pow_nonce = pow::solve(&auth_id, &challenge_response.c, challenge_response.pow_difficulty)
+        .expect("❌ The server asked for more proof of work than a client does");
+
     // Step 12: Send our solution back to the server
     let answer_request = AuthenticationAnswerRequest {
         auth_id,
         s: s.to_bytes_be(),
+        id_token: None,
+        ssh_public_key: String::new(),
+        pow_nonce,
+        challenge_token: challenge_response.challenge_token, // only from --stateless-challenges servers
     };
 
     println!("📤 Sending authentication solution...");
@@ -0,0 +1,163 @@
+//! Clocks for time-bound checks, and how far off ours is.
+//!
+//! Validity windows are written with the prover's clock and checked with the
+//! verifier's, so a [`Clock`] carries the allowed skew along with the time.
+//! `GetServerTime` lets a client measure its offset from the server and find
+//! out why time-bound operations fail.
+//!
+//! Code that expires things on its own reads the time from a [`Time`]
+//! handle rather than `SystemTime::now()`, so a test can hand it a
+//! [`TestClock`] and move time forward instead of sleeping.
+
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Skew tolerated when none is configured
+pub const DEFAULT_SKEW: Duration = Duration::from_secs(60);
+
+/// The time to check against and how far clocks may disagree
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Clock {
+    pub now: SystemTime,
+    pub skew: Duration,
+}
+
+impl Clock {
+    /// the system clock, tolerating `skew`
+    pub fn system(skew: Duration) -> Self {
+        Clock {
+            now: SystemTime::now(),
+            skew,
+        }
+    }
+
+    /// is `now` inside [not_before, not_after] (unix seconds) give or take the skew?
+    pub fn within(&self, not_before: u64, not_after: u64) -> bool {
+        let now = unix_seconds(self.now);
+        let skew = self.skew.as_secs();
+        not_before.saturating_sub(skew) <= now && now <= not_after.saturating_add(skew)
+    }
+}
+
+/// an exact point in time, no skew allowed
+impl From<SystemTime> for Clock {
+    fn from(now: SystemTime) -> Self {
+        Clock {
+            now,
+            skew: Duration::ZERO,
+        }
+    }
+}
+
+/// Where the current time comes from
+pub trait TimeSource: fmt::Debug + Send + Sync {
+    fn now(&self) -> SystemTime;
+}
+
+/// The system clock
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl TimeSource for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+/// A clock that only moves when told to; clones share the time
+#[derive(Debug, Clone)]
+pub struct TestClock(Arc<Mutex<SystemTime>>);
+
+impl TestClock {
+    pub fn new(start: SystemTime) -> Self {
+        TestClock(Arc::new(Mutex::new(start)))
+    }
+
+    pub fn advance(&self, by: Duration) {
+        *self.0.lock().unwrap() += by;
+    }
+
+    pub fn set(&self, now: SystemTime) {
+        *self.0.lock().unwrap() = now;
+    }
+}
+
+impl TimeSource for TestClock {
+    fn now(&self) -> SystemTime {
+        *self.0.lock().unwrap()
+    }
+}
+
+/// A shared [`TimeSource`], the system clock unless a test says otherwise
+#[derive(Debug, Clone)]
+pub struct Time(Arc<dyn TimeSource>);
+
+impl Default for Time {
+    fn default() -> Self {
+        Time(Arc::new(SystemClock))
+    }
+}
+
+impl Time {
+    pub fn new(source: impl TimeSource + 'static) -> Self {
+        Time(Arc::new(source))
+    }
+
+    pub fn now(&self) -> SystemTime {
+        self.0.now()
+    }
+
+    /// how long ago `earlier` was, zero if it is in the future
+    pub fn since(&self, earlier: SystemTime) -> Duration {
+        self.now().duration_since(earlier).unwrap_or_default()
+    }
+
+    /// the current time, tolerating `skew`
+    pub fn clock(&self, skew: Duration) -> Clock {
+        Clock { now: self.now(), skew }
+    }
+}
+
+/// server clock minus ours in milliseconds, assuming the server read its clock
+/// halfway between `sent` and `received` (local times of the request)
+pub fn estimate_offset_millis(sent: SystemTime, received: SystemTime, server_unix_millis: u64) -> i64 {
+    let sent = unix_millis(sent);
+    let received = unix_millis(received).max(sent);
+    let midpoint = sent + (received - sent) / 2;
+    server_unix_millis as i64 - midpoint as i64
+}
+
+pub fn unix_seconds(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+pub fn unix_millis(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or(0)
+}
+
+/// days since 1970-01-01 to (year, month, day), from Howard Hinnant's
+/// date algorithms
+pub(crate) fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+/// (year, month, day) to days since 1970-01-01, the inverse of civil_from_days
+pub(crate) fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
@@ -0,0 +1,45 @@
+//! gRPC message compression.
+//!
+//! With the 2048-bit group every (r1, r2) or (y1, y2) is half a kilobyte, so
+//! bandwidth-constrained deployments can compress messages. Both sides always
+//! accept gzip and zstd; the setting only picks what a side sends, so a
+//! compressing client still works with a server that answers uncompressed.
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+use tonic::codec::CompressionEncoding;
+
+/// What to compress outgoing messages with
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Compression {
+    #[default]
+    None,
+    Gzip,
+    Zstd,
+}
+
+/// Every encoding we can decompress
+pub const ACCEPTED: [CompressionEncoding; 2] = [CompressionEncoding::Gzip, CompressionEncoding::Zstd];
+
+impl Compression {
+    /// the tonic encoding to send with, None for uncompressed
+    pub fn encoding(self) -> Option<CompressionEncoding> {
+        match self {
+            Compression::None => None,
+            Compression::Gzip => Some(CompressionEncoding::Gzip),
+            Compression::Zstd => Some(CompressionEncoding::Zstd),
+        }
+    }
+}
+
+impl fmt::Display for Compression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Compression::None => "none",
+            Compression::Gzip => "gzip",
+            Compression::Zstd => "zstd",
+        })
+    }
+}
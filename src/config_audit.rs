@@ -0,0 +1,183 @@
+//! Checks a deployment's settings against a security baseline.
+//!
+//! Settings that each look reasonable can still add up to something weaker
+//! than its strongest part: 160-bit challenges don't help when a session ID
+//! can be guessed in 2^71 tries, and no group is large enough to make up for
+//! a plaintext connection. `zkp-auth audit-config` collects what the client is
+//! configured with and what the server reports in GetServerInfo into a
+//! [`ConfigSnapshot`]. [`audit`] then compares it with a [`Baseline`] and
+//! returns a [`Finding`] for each setting that falls short.
+
+use std::fmt;
+use std::net::IpAddr;
+use std::time::Duration;
+
+use crate::api::v1::SecuritySettings;
+use crate::challenge::MIN_CHALLENGE_BITS;
+
+/// How bad a finding is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, clap::ValueEnum)]
+pub enum Severity {
+    /// worth knowing, nothing to fix
+    Info,
+    /// weaker than the baseline, but only matters together with something else
+    Low,
+    /// weaker than the baseline on its own
+    Medium,
+    /// an attacker can get in or read along without much effort
+    High,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Severity::Info => "info",
+            Severity::Low => "low",
+            Severity::Medium => "medium",
+            Severity::High => "high",
+        })
+    }
+}
+
+/// One setting that falls short of the baseline
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Finding {
+    pub severity: Severity,
+    pub check: &'static str, // which setting, e.g. "session_id"
+    pub message: String,
+}
+
+/// What the server reports about itself (`SecuritySettings`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ServerSettings {
+    pub challenge_bits: u64,
+    pub session_id_bits: u64,
+    pub challenge_ttl: Option<Duration>,     // None keeps challenges until answered or restart
+    pub session_retention: Option<Duration>, // None lets sessions last until revoked
+}
+
+impl From<&SecuritySettings> for ServerSettings {
+    fn from(settings: &SecuritySettings) -> Self {
+        let secs = |secs: u64| (secs > 0).then(|| Duration::from_secs(secs));
+        ServerSettings {
+            challenge_bits: settings.challenge_bits,
+            session_id_bits: settings.session_id_bits,
+            challenge_ttl: secs(settings.challenge_ttl_secs),
+            session_retention: secs(settings.session_retention_secs),
+        }
+    }
+}
+
+/// Everything the audit looks at
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConfigSnapshot {
+    pub group_bits: u64, // bits of p
+    pub q_bits: u64,
+    pub tls: bool,
+    pub loopback: bool,   // the endpoint is this machine
+    pub pinned_key: bool, // the group parameters are checked against --server-key
+    pub server: Option<ServerSettings>, // None from servers that don't report their settings
+}
+
+/// The built-in baseline
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Baseline {
+    pub min_group_bits: u64,
+    pub min_challenge_bits: u64,
+    pub min_session_id_bits: u64,
+    pub max_challenge_ttl: Duration,
+    pub max_session_retention: Duration,
+}
+
+impl Default for Baseline {
+    fn default() -> Self {
+        Baseline {
+            min_group_bits: 2048,
+            min_challenge_bits: 128,
+            min_session_id_bits: 128,
+            max_challenge_ttl: Duration::from_secs(10 * 60),
+            max_session_retention: Duration::from_secs(30 * 24 * 3600),
+        }
+    }
+}
+
+/// Entropy of a random alphanumeric string of `len` characters, as
+/// `ZKP::generate_random_string` makes them (62 symbols, about 5.95 bits each)
+pub fn alphanumeric_bits(len: usize) -> u64 {
+    (len as f64 * 62f64.log2()).floor() as u64
+}
+
+/// whether `endpoint` (a URL such as http://127.0.0.1:50051) is this machine
+pub fn is_loopback(endpoint: &str) -> bool {
+    let host = endpoint.split_once("://").map_or(endpoint, |(_, rest)| rest);
+    let host = host.split(['/', '?']).next().unwrap_or_default();
+    let host = match host.strip_prefix('[') {
+        Some(bracketed) => bracketed.split(']').next().unwrap_or_default(),
+        None => host.rsplit_once(':').map_or(host, |(host, _)| host),
+    };
+    host.eq_ignore_ascii_case("localhost") || host.parse::<IpAddr>().is_ok_and(|ip| ip.is_loopback())
+}
+
+/// the findings for `config`, the most severe first
+pub fn audit(config: &ConfigSnapshot, baseline: &Baseline) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    let mut add = |severity, check, message: String| findings.push(Finding { severity, check, message });
+
+    if config.group_bits < 1024 {
+        add(Severity::High, "group", format!("p has {} bits, discrete logs in a group this small are within reach", config.group_bits));
+    } else if config.group_bits < baseline.min_group_bits {
+        add(Severity::Medium, "group", format!("p has {} bits, the baseline is {}", config.group_bits, baseline.min_group_bits));
+    }
+
+    let challenge_bits = config.server.map_or(config.q_bits, |server| server.challenge_bits);
+    if challenge_bits < MIN_CHALLENGE_BITS {
+        add(Severity::High, "challenge", format!("{}-bit challenges, a prover without x gets through once in 2^{} tries", challenge_bits, challenge_bits));
+    } else if challenge_bits < baseline.min_challenge_bits {
+        add(Severity::Low, "challenge", format!("{}-bit challenges, the baseline is {} (--challenge-bits)", challenge_bits, baseline.min_challenge_bits));
+    }
+
+    match config.server {
+        None => add(Severity::Info, "server", "the server doesn't report its settings, session and TTL checks were skipped".to_string()),
+        Some(server) => {
+            if server.session_id_bits < baseline.min_session_id_bits {
+                add(Severity::Medium, "session_id", format!("session IDs have {} bits of entropy, the baseline is {}", server.session_id_bits, baseline.min_session_id_bits));
+            }
+            if server.session_id_bits < server.challenge_bits {
+                add(
+                    Severity::Low,
+                    "session_vs_challenge",
+                    format!(
+                        "guessing a session ID (2^{}) is easier than answering a challenge without x (2^{}), so larger challenges buy nothing",
+                        server.session_id_bits, server.challenge_bits
+                    ),
+                );
+            }
+            match server.challenge_ttl {
+                None => add(Severity::Medium, "challenge_ttl", "challenges are kept until answered or the server restarts (--challenge-ttl)".to_string()),
+                Some(ttl) if ttl > baseline.max_challenge_ttl => {
+                    add(Severity::Low, "challenge_ttl", format!("challenges can be answered for {}s, the baseline is {}s", ttl.as_secs(), baseline.max_challenge_ttl.as_secs()))
+                }
+                Some(_) => {}
+            }
+            match server.session_retention {
+                None => add(Severity::Low, "session_retention", "sessions last until revoked or crowded out (--session-retention)".to_string()),
+                Some(age) if age > baseline.max_session_retention => add(
+                    Severity::Low,
+                    "session_retention",
+                    format!("sessions last {} days, the baseline is {}", age.as_secs() / 86400, baseline.max_session_retention.as_secs() / 86400),
+                ),
+                Some(_) => {}
+            }
+        }
+    }
+
+    match (config.tls, config.loopback) {
+        (false, false) => add(Severity::High, "tls", "plaintext connection to another machine, session IDs and tokens can be read on the way".to_string()),
+        (false, true) => add(Severity::Info, "tls", "plaintext connection, fine for this machine only".to_string()),
+        (true, _) if !config.pinned_key => add(Severity::Low, "server_key", "the group parameters aren't checked against a pinned key (--server-key)".to_string()),
+        (true, _) => {}
+    }
+
+    findings.sort_by_key(|f| std::cmp::Reverse(f.severity));
+    findings
+}
@@ -0,0 +1,104 @@
+//! Per-call deadlines for clients.
+//!
+//! A client waiting on a slow server has nothing to tell its user, so each
+//! step of registering and logging in gets its own deadline. The server is
+//! told through `grpc-timeout` and can give up too, and the client stops
+//! waiting once it passes. A call that ran out of time comes back as
+//! [`CallError::DeadlineExceeded`] rather than as one more status, so a
+//! caller can say "the server is slow" instead of "the login failed".
+
+use std::fmt;
+use std::future::Future;
+use std::time::Duration;
+
+use tonic::{Code, Request, Response, Status};
+
+/// How long Register may take by default (the server checks the password policy and may write a store)
+pub const REGISTER_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long CreateAuthenticationChallenge (and CommitChallenge) may take by default
+pub const CHALLENGE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long VerifyAuthentication may take by default (the server runs the modpows)
+pub const VERIFY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A deadline per step
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CallTimeouts {
+    pub register: Duration,
+    pub challenge: Duration,
+    pub verify: Duration,
+}
+
+impl Default for CallTimeouts {
+    fn default() -> Self {
+        CallTimeouts {
+            register: REGISTER_TIMEOUT,
+            challenge: CHALLENGE_TIMEOUT,
+            verify: VERIFY_TIMEOUT,
+        }
+    }
+}
+
+/// Why a call with a deadline failed
+#[derive(Debug)]
+pub enum CallError {
+    /// no answer in time; the request may still have reached the server
+    DeadlineExceeded { call: String, timeout: Duration },
+    /// the server answered with an error
+    Failed(Status),
+}
+
+impl CallError {
+    /// sort the status of `call`, made with `timeout`, into a slow server or a failure
+    pub fn new(call: &str, timeout: Duration, status: Status) -> Self {
+        match status.code() {
+            Code::DeadlineExceeded => CallError::DeadlineExceeded { call: call.to_string(), timeout },
+            _ => CallError::Failed(status),
+        }
+    }
+}
+
+impl fmt::Display for CallError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CallError::DeadlineExceeded { call, timeout } => {
+                write!(f, "{} got no answer within {:?}, the server is slow or unreachable", call, timeout)
+            }
+            CallError::Failed(status) => write!(f, "{} ({:?})", status.message(), status.code()),
+        }
+    }
+}
+
+impl std::error::Error for CallError {}
+
+/// `request` with a deadline the server is told about
+pub fn with_deadline<T>(mut request: Request<T>, timeout: Duration) -> Request<T> {
+    request.set_timeout(timeout);
+    request
+}
+
+/// `call`, given up with DEADLINE_EXCEEDED once `timeout` has passed
+pub async fn within<T>(
+    timeout: Duration,
+    call: impl Future<Output = Result<Response<T>, Status>>,
+) -> Result<Response<T>, Status> {
+    match tokio::time::timeout(timeout, call).await {
+        // the channel enforces grpc-timeout itself, and reports it as CANCELLED
+        Ok(Err(status)) if timed_out(&status) => Err(Status::deadline_exceeded(format!("no answer within {:?}", timeout))),
+        Ok(result) => result,
+        Err(_) => Err(Status::deadline_exceeded(format!("no answer within {:?}", timeout))),
+    }
+}
+
+/// whether `status` is the channel giving up at the request's grpc-timeout
+fn timed_out(status: &Status) -> bool {
+    let mut source = std::error::Error::source(status);
+    while let Some(error) = source {
+        if error.is::<tonic::transport::TimeoutExpired>() {
+            return true;
+        }
+        source = error.source();
+    }
+    false
+}
@@ -0,0 +1,151 @@
+//! Delegations: short-lived capabilities a user hands to a service.
+//!
+//! A user who wants a service (a backup job, a CI runner) to act in their name
+//! for a while mints a [`Delegation`]: a non-interactive proof of knowledge of
+//! x ([`crate::nizk`]) whose Fiat-Shamir context binds the user, the service it
+//! is for and the scopes it grants, and whose validity window is its expiry.
+//! Only someone with x can mint one, and none of it can be changed without
+//! redoing the proof. The service presents the blob to the server
+//! (`VerifyDelegation`) with its own name and the scope it is about to use;
+//! the server checks the proof against the user's key, the window, the scope
+//! and the revocation list. A delegation is revoked like any exported proof,
+//! by the proof id in it ([`crate::revocation`]).
+//!
+//! Whoever holds the blob holds the capability until it expires, so keep the
+//! lifetime short and the scopes narrow.
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::Duration;
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use num_bigint::BigUint;
+use serde::{Deserialize, Serialize};
+
+use crate::clock::Clock;
+use crate::nizk::{NonInteractiveProof, ValidityWindow};
+use crate::types::PublicKey;
+use crate::ZKP;
+
+/// Domain separation for the context the proof is bound to
+const DOMAIN: &[u8] = b"zkp-auth/delegation/v1";
+
+/// Longest lifetime a server accepts by default (--delegation-max-ttl)
+pub const MAX_LIFETIME: Duration = Duration::from_secs(24 * 60 * 60);
+/// Scopes one delegation can grant
+pub const MAX_SCOPES: usize = 16;
+/// Length of a scope, e.g. `backups:read`
+pub const MAX_SCOPE_LEN: usize = 64;
+/// Length of the name of the service a delegation is for
+pub const MAX_DELEGATE_LEN: usize = 256;
+
+/// A user's proof that `delegate` may use `scopes` in their name until the
+/// proof's window ends
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Delegation {
+    pub user: String,
+    pub delegate: String, // the service it is for
+    pub scopes: Vec<String>,
+    pub proof: NonInteractiveProof,
+}
+
+/// A delegation that can't be minted or read
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DelegationError {
+    NoDelegate,
+    NoScopes,
+    BadScope,
+    Malformed,
+}
+
+impl fmt::Display for DelegationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DelegationError::NoDelegate => write!(f, "a delegation needs the name of the service it is for (at most {} bytes)", MAX_DELEGATE_LEN),
+            DelegationError::NoScopes => write!(f, "a delegation grants 1 to {} scopes", MAX_SCOPES),
+            DelegationError::BadScope => write!(f, "scopes are 1 to {} printable characters without spaces", MAX_SCOPE_LEN),
+            DelegationError::Malformed => f.write_str("not a delegation"),
+        }
+    }
+}
+
+impl std::error::Error for DelegationError {}
+
+impl Delegation {
+    /// prove with `x` that `user` lets `delegate` use `scopes` during `window`
+    pub fn mint(
+        zkp: &ZKP,
+        x: &BigUint,
+        user: &str,
+        delegate: &str,
+        scopes: &[String],
+        window: ValidityWindow,
+    ) -> Result<Self, DelegationError> {
+        check(delegate, scopes)?;
+        let proof = zkp.prove_non_interactive(x, window, &context(user, delegate, scopes));
+        Ok(Delegation { user: user.to_string(), delegate: delegate.to_string(), scopes: scopes.to_vec(), proof })
+    }
+
+    /// is the proof right for `key`, with `clock` inside its window? Who it is
+    /// for and what it grants are up to the caller
+    pub fn verify(&self, zkp: &ZKP, key: &PublicKey, clock: impl Into<Clock>) -> bool {
+        check(&self.delegate, &self.scopes).is_ok()
+            && zkp.verify_non_interactive(&key.y1, &key.y2, &self.proof, &context(&self.user, &self.delegate, &self.scopes), clock)
+    }
+
+    /// does it grant `scope`?
+    pub fn allows(&self, scope: &str) -> bool {
+        self.scopes.iter().any(|granted| granted == scope)
+    }
+
+    /// the id to revoke it by
+    pub fn id(&self) -> &str {
+        &self.proof.id
+    }
+
+    /// the text a service is given: base64url of the JSON
+    pub fn to_blob(&self) -> String {
+        URL_SAFE_NO_PAD.encode(serde_json::to_vec(self).expect("delegations always serialize"))
+    }
+
+    pub fn from_blob(blob: &str) -> Result<Self, DelegationError> {
+        let json = URL_SAFE_NO_PAD.decode(blob.trim()).map_err(|_| DelegationError::Malformed)?;
+        serde_json::from_slice(&json).map_err(|_| DelegationError::Malformed)
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        Self::from_blob(&fs::read_to_string(path)?).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// write the blob to a new file only the owner can read; an existing one is never replaced
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        crate::params::write_private(path, format!("{}\n", self.to_blob()).as_bytes())
+    }
+}
+
+fn check(delegate: &str, scopes: &[String]) -> Result<(), DelegationError> {
+    if delegate.is_empty() || delegate.len() > MAX_DELEGATE_LEN {
+        return Err(DelegationError::NoDelegate);
+    }
+    if scopes.is_empty() || scopes.len() > MAX_SCOPES {
+        return Err(DelegationError::NoScopes);
+    }
+    let valid = |scope: &String| (1..=MAX_SCOPE_LEN).contains(&scope.len()) && scope.chars().all(|c| c.is_ascii_graphic());
+    match scopes.iter().all(valid) {
+        true => Ok(()),
+        false => Err(DelegationError::BadScope),
+    }
+}
+
+/// domain | user | delegate | scopes, every field length-prefixed
+fn context(user: &str, delegate: &str, scopes: &[String]) -> Vec<u8> {
+    let mut context = DOMAIN.to_vec();
+    for field in [user, delegate].into_iter().chain(scopes.iter().map(String::as_str)) {
+        context.extend_from_slice(&(field.len() as u32).to_be_bytes());
+        context.extend_from_slice(field.as_bytes());
+    }
+    context
+}
@@ -0,0 +1,362 @@
+//! `zkp-auth demo`: a terminal UI that walks through one registration and
+//! one login, showing every number that goes over the wire and the two
+//! equations the server checks. Everything runs locally, no server needed.
+//!
+//! `demo --all-in-one` does the same against a real server instead: it
+//! starts the `server` binary that ships next to zkp-auth on a free port,
+//! registers and logs in over gRPC, prints each step as plain text and shuts
+//! the server down again. It needs no terminal, so it also serves as a smoke
+//! test.
+
+use std::io;
+use std::net::TcpListener;
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+use std::time::Duration;
+
+use num_bigint::BigUint;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Modifier, Style, Stylize};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, List, ListState, Paragraph, Wrap};
+use ratatui::{DefaultTerminal, Frame};
+
+use tonic::transport::Channel;
+use tonic::Code;
+
+use rust_zkp_chaum_pedersen::api::v1::{
+    auth_client::AuthClient, AuthenticationAnswerRequest, AuthenticationChallengeRequest, RegisterRequest,
+};
+use rust_zkp_chaum_pedersen::ZKP;
+
+/// One screen of the walkthrough
+struct Step {
+    title: &'static str,
+    lines: Vec<Line<'static>>,
+}
+
+/// the small group from the Readme, handy because the numbers fit on screen
+fn toy_zkp() -> ZKP {
+    ZKP::builder()
+        .p(BigUint::from(23u32))
+        .q(BigUint::from(11u32))
+        .alpha(BigUint::from(4u32))
+        .beta(BigUint::from(9u32))
+        .build()
+        .expect("the toy group is valid")
+}
+
+pub fn run(toy: bool, password: Option<String>) -> io::Result<()> {
+    let zkp = if toy {
+        toy_zkp()
+    } else {
+        ZKP::builder().rfc5114().build().expect("RFC 5114 parameters are valid")
+    };
+
+    // the secret is reduced mod q so the toy group shows sensible numbers
+    let x = match password {
+        Some(password) => BigUint::from_bytes_be(password.as_bytes()) % &zkp.q,
+        None => ZKP::generate_random_number_below(&zkp.q),
+    };
+    let k = ZKP::generate_random_number_below(&zkp.q);
+    let c = ZKP::generate_random_number_below(&zkp.q);
+
+    let steps = build_steps(&zkp, &x, &k, &c);
+
+    let mut terminal = ratatui::init();
+    let result = run_app(&mut terminal, &steps);
+    ratatui::restore();
+    result
+}
+
+fn build_steps(zkp: &ZKP, x: &BigUint, k: &BigUint, c: &BigUint) -> Vec<Step> {
+    let (y1, y2) = zkp.compute_pair(x);
+    let (r1, r2) = zkp.compute_pair(k);
+    let s = zkp.solve(k, c, x);
+
+    let check1 = (zkp.alpha.modpow(&s, &zkp.p) * y1.modpow(c, &zkp.p)) % &zkp.p;
+    let check2 = (zkp.beta.modpow(&s, &zkp.p) * y2.modpow(c, &zkp.p)) % &zkp.p;
+    let valid = zkp.verify(&r1, &r2, &y1, &y2, c, &s);
+
+    vec![
+        Step {
+            title: "Public parameters",
+            lines: vec![
+                "Everyone (client and server) agrees on these values.".into(),
+                "".into(),
+                value("p (prime modulus)", &zkp.p),
+                value("q (order of the subgroup)", &zkp.q),
+                value("α (alpha, first generator)", &zkp.alpha),
+                value("β (beta, second generator)", &zkp.beta),
+            ],
+        },
+        Step {
+            title: "Registration",
+            lines: vec![
+                "The client turns the password into a secret x. x never leaves the client.".into(),
+                "".into(),
+                value("x (secret)", x),
+                "".into(),
+                "It sends the two public commitments to the server:".into(),
+                value("y1 = α^x mod p", &y1),
+                value("y2 = β^x mod p", &y2),
+            ],
+        },
+        Step {
+            title: "Login: commitment",
+            lines: vec![
+                "The client picks a fresh random k for this login only.".into(),
+                "".into(),
+                value("k (random nonce)", k),
+                "".into(),
+                "It sends the session commitments to the server:".into(),
+                value("r1 = α^k mod p", &r1),
+                value("r2 = β^k mod p", &r2),
+            ],
+        },
+        Step {
+            title: "Login: challenge",
+            lines: vec![
+                "The server answers with a random challenge c below q.".into(),
+                "".into(),
+                value("c (challenge)", c),
+            ],
+        },
+        Step {
+            title: "Login: response",
+            lines: vec![
+                "The client combines k, c and x into a single number.".into(),
+                "On its own s reveals nothing about x because k is random.".into(),
+                "".into(),
+                value("s = k - c·x mod q", &s),
+            ],
+        },
+        Step {
+            title: "Verification",
+            lines: vec![
+                "The server only uses public values (y1, y2, r1, r2, c, s):".into(),
+                "".into(),
+                value("α^s · y1^c mod p", &check1),
+                value("r1", &r1),
+                equation("α^s · y1^c mod p == r1", check1 == r1),
+                "".into(),
+                value("β^s · y2^c mod p", &check2),
+                value("r2", &r2),
+                equation("β^s · y2^c mod p == r2", check2 == r2),
+                "".into(),
+                if valid {
+                    Line::from("✅ Both equations hold: the client knows x").green().bold()
+                } else {
+                    Line::from("❌ Verification failed").red().bold()
+                },
+            ],
+        },
+    ]
+}
+
+fn value(label: &str, number: &BigUint) -> Line<'static> {
+    Line::from(labelled(label, number))
+}
+
+fn labelled(label: &str, number: &BigUint) -> String {
+    format!("{:<28} {}", label, number)
+}
+
+fn equation(label: &str, holds: bool) -> Line<'static> {
+    if holds {
+        Line::from(format!("  {} ✓", label)).green()
+    } else {
+        Line::from(format!("  {} ✗", label)).red()
+    }
+}
+
+fn run_app(terminal: &mut DefaultTerminal, steps: &[Step]) -> io::Result<()> {
+    let mut state = ListState::default().with_selected(Some(0));
+    loop {
+        terminal.draw(|frame| draw(frame, steps, &mut state))?;
+
+        if let Event::Key(key) = event::read()? {
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            match key.code {
+                KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+                KeyCode::Right | KeyCode::Down | KeyCode::Char('n') | KeyCode::Enter => {
+                    let next = state.selected().map_or(0, |i| (i + 1).min(steps.len() - 1));
+                    state.select(Some(next));
+                }
+                KeyCode::Left | KeyCode::Up | KeyCode::Char('p') => {
+                    let previous = state.selected().map_or(0, |i| i.saturating_sub(1));
+                    state.select(Some(previous));
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+fn draw(frame: &mut Frame, steps: &[Step], state: &mut ListState) {
+    let [main, footer] = Layout::vertical([Constraint::Min(0), Constraint::Length(1)])
+        .areas(frame.area());
+    let [sidebar, content] = Layout::horizontal([Constraint::Length(26), Constraint::Min(0)])
+        .areas(main);
+
+    let titles = steps
+        .iter()
+        .enumerate()
+        .map(|(i, step)| format!("{}. {}", i + 1, step.title));
+    let list = List::new(titles)
+        .block(Block::bordered().title(" Chaum-Pedersen "))
+        .highlight_style(Style::new().add_modifier(Modifier::REVERSED));
+    frame.render_stateful_widget(list, sidebar, state);
+
+    let step = &steps[state.selected().unwrap_or(0)];
+    let details = Paragraph::new(step.lines.clone())
+        .block(Block::bordered().title(format!(" {} ", step.title)))
+        .wrap(Wrap { trim: false });
+    frame.render_widget(details, content);
+
+    frame.render_widget(
+        Line::from(" ←/→ previous/next step   q quit").dim(),
+        footer,
+    );
+}
+
+/// The demo's server process, stopped when dropped so a failed step doesn't leave it running
+struct DemoServer(Child);
+
+impl Drop for DemoServer {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+        let _ = self.0.wait();
+    }
+}
+
+/// the `server` binary installed next to this one
+fn server_binary() -> io::Result<PathBuf> {
+    let path = std::env::current_exe()?.with_file_name(format!("server{}", std::env::consts::EXE_SUFFIX));
+    match path.exists() {
+        true => Ok(path),
+        false => Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("the all-in-one demo runs the server binary next to zkp-auth, there is none at {}", path.display()),
+        )),
+    }
+}
+
+/// a port nobody is listening on right now
+fn free_port() -> io::Result<u16> {
+    Ok(TcpListener::bind("127.0.0.1:0")?.local_addr()?.port())
+}
+
+fn other(e: impl std::fmt::Display) -> io::Error {
+    io::Error::other(e.to_string())
+}
+
+pub async fn run_all_in_one(password: Option<String>, seed: Option<u64>) -> io::Result<()> {
+    let port = free_port()?;
+    let mut command = Command::new(server_binary()?);
+    command.args(["--listen", &format!("127.0.0.1:{}", port)]).stdout(Stdio::null());
+    if let Some(seed) = seed {
+        command.args(["--seed", &seed.to_string()]);
+    }
+    let server = DemoServer(command.spawn()?);
+    println!("🚀 Started a server on 127.0.0.1:{} (pid {})", port, server.0.id());
+
+    let endpoint = format!("http://127.0.0.1:{}", port);
+    let mut channel = None;
+    for _ in 0..100 {
+        if let Ok(connected) = Channel::from_shared(endpoint.clone()).map_err(other)?.connect().await {
+            channel = Some(connected);
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    let mut client = AuthClient::new(channel.ok_or_else(|| other("the demo server didn't come up within 10s"))?);
+
+    let zkp = ZKP::builder().rfc5114().build().expect("RFC 5114 parameters are valid");
+    println!();
+    println!("1. Public parameters, the same on client and server");
+    println!("   {}", labelled("p (prime modulus)", &zkp.p));
+    println!("   {}", labelled("q (order of the subgroup)", &zkp.q));
+    println!("   {}", labelled("α (alpha, first generator)", &zkp.alpha));
+    println!("   {}", labelled("β (beta, second generator)", &zkp.beta));
+
+    let x = match password {
+        Some(password) => BigUint::from_bytes_be(password.as_bytes()) % &zkp.q,
+        None => ZKP::generate_random_number_below(&zkp.q),
+    };
+    let (y1, y2) = zkp.compute_pair(&x);
+    let user = format!("demo-{}", ZKP::generate_random_string(8).to_lowercase());
+    println!();
+    println!("2. Registration of {:?}: x stays here, y1 and y2 go to the server", user);
+    println!("   {}", labelled("x (secret)", &x));
+    println!("   {}", labelled("y1 = α^x mod p", &y1));
+    println!("   {}", labelled("y2 = β^x mod p", &y2));
+    let request = RegisterRequest { user: user.clone(), y1: y1.to_bytes_be(), y2: y2.to_bytes_be(), ..Default::default() };
+    client.register(request).await.map_err(other)?;
+    println!("   ✅ Register");
+
+    let k = ZKP::generate_random_number_below(&zkp.q);
+    let (r1, r2) = zkp.compute_pair(&k);
+    println!();
+    println!("3. Login: commitment with a fresh k");
+    println!("   {}", labelled("k (random nonce)", &k));
+    println!("   {}", labelled("r1 = α^k mod p", &r1));
+    println!("   {}", labelled("r2 = β^k mod p", &r2));
+    let request = AuthenticationChallengeRequest { user: user.clone(), r1: r1.to_bytes_be(), r2: r2.to_bytes_be(), ..Default::default() };
+    let challenge = client.create_authentication_challenge(request).await.map_err(other)?.into_inner();
+    let c = BigUint::from_bytes_be(&challenge.c);
+    println!();
+    println!("4. Login: challenge from the server");
+    println!("   {}", labelled("c (challenge)", &c));
+    println!("   auth_id {}", challenge.auth_id);
+
+    let s = zkp.solve(&k, &c, &x);
+    println!();
+    println!("5. Login: response");
+    println!("   {}", labelled("s = k - c·x mod q", &s));
+    let answer = AuthenticationAnswerRequest {
+        auth_id: challenge.auth_id.clone(),
+        s: s.to_bytes_be(),
+        challenge_token: challenge.challenge_token.clone(),
+        ..Default::default()
+    };
+    let session_id = client.verify_authentication(answer.clone()).await.map_err(other)?.into_inner().session_id;
+
+    println!();
+    println!("6. Verification on the server, with public values only");
+    let check1 = (zkp.alpha.modpow(&s, &zkp.p) * y1.modpow(&c, &zkp.p)) % &zkp.p;
+    let check2 = (zkp.beta.modpow(&s, &zkp.p) * y2.modpow(&c, &zkp.p)) % &zkp.p;
+    println!("   {}", labelled("α^s · y1^c mod p", &check1));
+    println!("   α^s · y1^c mod p == r1 {}", if check1 == r1 { "✓" } else { "✗" });
+    println!("   {}", labelled("β^s · y2^c mod p", &check2));
+    println!("   β^s · y2^c mod p == r2 {}", if check2 == r2 { "✓" } else { "✗" });
+    println!("   ✅ Logged in, session {}", session_id);
+
+    // one more login with the wrong secret, which the server has to refuse
+    let k = ZKP::generate_random_number_below(&zkp.q);
+    let (r1, r2) = zkp.compute_pair(&k);
+    let request = AuthenticationChallengeRequest { user: user.clone(), r1: r1.to_bytes_be(), r2: r2.to_bytes_be(), ..Default::default() };
+    let challenge = client.create_authentication_challenge(request).await.map_err(other)?.into_inner();
+    let wrong = zkp.solve(&k, &BigUint::from_bytes_be(&challenge.c), &(&x + 1u32));
+    let answer = AuthenticationAnswerRequest {
+        auth_id: challenge.auth_id,
+        s: wrong.to_bytes_be(),
+        challenge_token: challenge.challenge_token,
+        ..Default::default()
+    };
+    println!();
+    println!("7. A login with the wrong secret");
+    match client.verify_authentication(answer).await {
+        Err(status) if status.code() == Code::PermissionDenied => println!("   ✅ Refused: {}", status.message()),
+        Err(status) => return Err(other(format!("the wrong secret failed for another reason: {}", status))),
+        Ok(_) => return Err(other("the server accepted the wrong secret")),
+    }
+
+    drop(server);
+    println!();
+    println!("🛑 Stopped the server, the demo is complete");
+    Ok(())
+}
@@ -0,0 +1,146 @@
+//! Designated-verifier proofs.
+//!
+//! A non-interactive Chaum-Pedersen proof (Fiat-Shamir) convinces anyone who
+//! gets a copy. A designated-verifier proof instead shows "I know x with
+//! y1 = alpha^x and y2 = beta^x, OR I know the verifier's secret w with
+//! y_v = alpha^w" (the standard OR composition, [`crate::sigma::Or`]). The
+//! verifier knows it didn't make the proof itself, so it is convinced; but it
+//! can [`forge`] such a proof for any (y1, y2) with its own key, so a proof it
+//! shows to a third party is no evidence of anything. Like [`crate::nizk`]
+//! proofs they carry a validity window, so a leaked proof also expires.
+
+use num_bigint::BigUint;
+use serde::{Deserialize, Serialize};
+
+use crate::clock::Clock;
+use crate::nizk::{fiat_shamir, new_proof_id, ValidityWindow};
+use crate::params::hex_bytes;
+use crate::sigma::{self, ChaumPedersen, Or, OrResponse, OrWitness, Schnorr};
+use crate::types::{Challenge, Commitment, PublicKey, Response};
+use crate::ZKP;
+
+/// Domain separation for the Fiat-Shamir challenge
+const DOMAIN: &[u8] = b"zkp-auth/designated-verifier/v1";
+
+/// The verifier's trapdoor key: w and y_v = alpha^w
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VerifierKey {
+    pub secret: BigUint,
+    pub public: BigUint,
+}
+
+impl VerifierKey {
+    pub fn generate(zkp: &ZKP) -> Self {
+        let secret = ZKP::generate_random_number_below(&zkp.q);
+        let public = zkp.alpha.modpow(&secret, &zkp.p);
+        VerifierKey { secret, public }
+    }
+}
+
+/// A non-interactive proof only the holder of one [`VerifierKey`] can believe
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DesignatedProof {
+    pub id: String, // random, for revocation
+    #[serde(with = "hex_bytes")]
+    pub r1: Vec<u8>, // commitments of the prover's branch
+    #[serde(with = "hex_bytes")]
+    pub r2: Vec<u8>,
+    #[serde(with = "hex_bytes")]
+    pub r3: Vec<u8>, // commitment of the verifier's branch
+    #[serde(with = "hex_bytes")]
+    pub c1: Vec<u8>, // the two challenges add up to the Fiat-Shamir hash
+    #[serde(with = "hex_bytes")]
+    pub c2: Vec<u8>,
+    #[serde(with = "hex_bytes")]
+    pub s1: Vec<u8>,
+    #[serde(with = "hex_bytes")]
+    pub s2: Vec<u8>,
+    pub window: ValidityWindow,
+}
+
+/// prove knowledge of x for (y1, y2) to the holder of `verifier`, bound to `context`
+/// (e.g. the verifier's name and a nonce, so the proof can't be replayed elsewhere)
+pub fn prove(zkp: &ZKP, x: &BigUint, verifier: &BigUint, window: ValidityWindow, context: &[u8]) -> DesignatedProof {
+    // the verifier's branch is simulated, the prover's answered for real
+    let statement = (zkp.public_key(x), verifier.clone());
+    prove_either(zkp, &statement, &OrWitness::Left(x.clone()), window, context)
+}
+
+/// what the verifier can do with its secret: a valid-looking proof for any (y1, y2)
+/// without knowing x. This is why a designated proof convinces nobody else
+pub fn forge(
+    zkp: &ZKP,
+    y1: &BigUint,
+    y2: &BigUint,
+    verifier: &VerifierKey,
+    window: ValidityWindow,
+    context: &[u8],
+) -> DesignatedProof {
+    // the prover's branch is simulated, the verifier's answered for real with w
+    let statement = (PublicKey { y1: y1.clone(), y2: y2.clone() }, verifier.public.clone());
+    prove_either(zkp, &statement, &OrWitness::Right(verifier.secret.clone()), window, context)
+}
+
+/// check a proof against (y1, y2), the verifier's public key, the context and the clock.
+/// Only meaningful to the verifier: anyone holding its secret could have made it
+pub fn verify(
+    zkp: &ZKP,
+    y1: &BigUint,
+    y2: &BigUint,
+    verifier: &BigUint,
+    context: &[u8],
+    proof: &DesignatedProof,
+    clock: impl Into<Clock>,
+) -> bool {
+    if !proof.window.contains(clock) {
+        return false;
+    }
+    let [r1, r2, r3, c1, c2, s1, s2] = proof.numbers();
+    if [&c1, &c2, &s1, &s2].iter().any(|n| **n >= zkp.q) {
+        return false;
+    }
+    let statement = (PublicKey { y1: y1.clone(), y2: y2.clone() }, verifier.clone());
+    let commitment = (Commitment { r1, r2 }, r3);
+    let response = OrResponse { c_left: Challenge(c1), left: Response(s1), c_right: Challenge(c2), right: Response(s2) };
+    sigma::verify_with(&Or(ChaumPedersen(zkp), Schnorr(zkp)), &statement, &commitment, &response, |numbers| {
+        Challenge(fiat_shamir(DOMAIN, zkp, numbers, &proof.id, &proof.window, context))
+    })
+}
+
+/// Fiat-Shamir over "x for (y1, y2) OR w for y_v", with a witness for either side
+fn prove_either(
+    zkp: &ZKP,
+    statement: &(PublicKey, BigUint),
+    witness: &OrWitness<BigUint, BigUint>,
+    window: ValidityWindow,
+    context: &[u8],
+) -> DesignatedProof {
+    let mut id = String::new();
+    let ((commitment, r3), response) = sigma::prove_with(&Or(ChaumPedersen(zkp), Schnorr(zkp)), statement, witness, |numbers| {
+        id = new_proof_id();
+        Challenge(fiat_shamir(DOMAIN, zkp, numbers, &id, &window, context))
+    });
+    let OrResponse { c_left: c1, left: s1, c_right: c2, right: s2 } = response;
+    DesignatedProof::new(id, [&commitment.r1, &commitment.r2, &r3, &c1.0, &c2.0, &s1.0, &s2.0], window)
+}
+
+impl DesignatedProof {
+    fn new(id: String, numbers: [&BigUint; 7], window: ValidityWindow) -> Self {
+        let [r1, r2, r3, c1, c2, s1, s2] = numbers.map(|n| n.to_bytes_be());
+        DesignatedProof {
+            id,
+            r1,
+            r2,
+            r3,
+            c1,
+            c2,
+            s1,
+            s2,
+            window,
+        }
+    }
+
+    fn numbers(&self) -> [BigUint; 7] {
+        [&self.r1, &self.r2, &self.r3, &self.c1, &self.c2, &self.s1, &self.s2].map(|n| BigUint::from_bytes_be(n))
+    }
+}
@@ -0,0 +1,75 @@
+//! Keys of their own for a user's devices.
+//!
+//! Besides the key derived from the password, an account can enroll a key per
+//! device: a random secret x_i made on the device and kept there, whose
+//! (y1_i, y2_i) logs in as the account (AuthenticationChallengeRequest.device_id).
+//! A lost device is revoked on its own (RevokeDevice), which also ends the
+//! sessions it logged in; the password and the other devices stay as they are.
+//! A device is known by the fingerprint of its key, so the same key can't be
+//! enrolled twice.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::SystemTime;
+
+use num_bigint::BigUint;
+use serde::{Deserialize, Serialize};
+
+use crate::group::GroupId;
+use crate::memory::HeapSize;
+use crate::types::PublicKey;
+
+/// Devices one account can have enrolled at once
+pub const MAX_DEVICES_PER_USER: usize = 16;
+
+/// An enrolled device, as the server keeps it
+#[derive(Debug, Clone)]
+pub struct Device {
+    pub name: String, // e.g. "work laptop", picked by the user
+    pub public_key: PublicKey,
+    pub enrolled_at: SystemTime,
+}
+
+impl HeapSize for Device {
+    fn heap_size(&self) -> usize {
+        self.name.heap_size() + self.public_key.heap_size()
+    }
+}
+
+/// The id of a device: the full hex fingerprint of its key in the account's group
+pub fn device_id(group: GroupId, key: &PublicKey) -> String {
+    key.fingerprint_in(group).to_hex()
+}
+
+/// What an enrolled device keeps: whose key it is, its id and the secret x_i
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeviceKey {
+    pub user: String,
+    pub device_id: String,
+    pub x: String, // hex
+}
+
+impl DeviceKey {
+    pub fn new(user: &str, device_id: &str, x: &BigUint) -> Self {
+        DeviceKey { user: user.to_string(), device_id: device_id.to_string(), x: hex::encode(x.to_bytes_be()) }
+    }
+
+    pub fn secret(&self) -> BigUint {
+        BigUint::from_bytes_be(&hex::decode(&self.x).unwrap_or_default())
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let key: DeviceKey = serde_json::from_str(&fs::read_to_string(path)?)?;
+        if hex::decode(&key.x).is_err() {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "the device secret must be hex"));
+        }
+        Ok(key)
+    }
+
+    /// write a new file only the owner can read; an existing one is never replaced
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let json = serde_json::to_string_pretty(self).expect("device keys always serialize");
+        crate::params::write_private(path, format!("{}\n", json).as_bytes())
+    }
+}
@@ -0,0 +1,310 @@
+//! Logging in a device by scanning its QR code with a phone.
+//!
+//! A device without the password (a TV, a shared kiosk) calls
+//! StartDeviceLogin and shows the login id and a commitment to its challenge c
+//! as a QR code. The phone, where the user is logged in, scans it and sends
+//! (r1, r2) with ApproveDeviceLogin; only then does the server open the
+//! commitment. c must not be in the QR code itself: whoever knows c before
+//! committing can make (r1, r2, s) fit it without knowing x. The phone checks
+//! the opening, shows which device asked and answers with
+//! FinishDeviceLogin, and the session the proof earns goes to the device,
+//! which collects it with the poll token only it was given.
+//!
+//! Each login goes Waiting -> Scanned -> Verifying -> Approved or Denied, and
+//! is gone once the device has polled the outcome or after the TTL.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+use num_bigint::BigUint;
+use tonic::Status;
+
+use crate::api::v1::ClientMetadata;
+use crate::types::Commitment;
+
+/// How long a QR code can be scanned and answered
+pub const LOGIN_TTL: Duration = Duration::from_secs(5 * 60);
+/// Logins in flight at once; past it the oldest are dropped
+pub const MAX_PENDING: usize = 10_000;
+
+const QR_PREFIX: &str = "zkp-auth://device-login?";
+
+/// Where a device login is
+#[derive(Debug, Clone)]
+pub enum State {
+    /// shown as a QR code, not scanned yet
+    Waiting,
+    /// `user`'s phone sent (r1, r2) and got c, its answer is due
+    Scanned { user: String, phone_session: String, commitment: Commitment },
+    /// the phone's answer is being checked
+    Verifying { user: String },
+    /// the answer was right: `session_id` is the device's, until it polls
+    Approved { user: String, session_id: String },
+    /// the phone declined or answered wrong
+    Denied,
+}
+
+/// One login in flight
+#[derive(Debug, Clone)]
+pub struct DeviceLogin {
+    poll_token: String,
+    pub client: Option<ClientMetadata>, // as the device reported it
+    pub source: String,                 // where the device asked from
+    c: BigUint,
+    nonce: Vec<u8>,
+    started_at: SystemTime,
+    pub state: State,
+}
+
+impl DeviceLogin {
+    fn scan(&self) -> Scan {
+        Scan { c: self.c.clone(), nonce: self.nonce.clone(), client: self.client.clone(), source: self.source.clone() }
+    }
+}
+
+/// What a phone learns by scanning: the opening of the commitment and the
+/// device it is about to log in
+#[derive(Debug, Clone)]
+pub struct Scan {
+    pub c: BigUint,
+    pub nonce: Vec<u8>,
+    pub client: Option<ClientMetadata>,
+    pub source: String,
+}
+
+/// What the device learns by polling
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Poll {
+    Waiting,
+    Scanned,
+    Approved { user: String, session_id: String },
+    Denied,
+}
+
+/// A request that doesn't fit the state of the login
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeviceLoginError {
+    NotFound,
+    Expired,
+    AlreadyScanned,
+    NotScanned,
+    OtherPhone,
+    WrongToken,
+}
+
+impl fmt::Display for DeviceLoginError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            DeviceLoginError::NotFound => "no such device login, or its outcome was collected already",
+            DeviceLoginError::Expired => "the device login has expired, start a new one",
+            DeviceLoginError::AlreadyScanned => "the device login was scanned already",
+            DeviceLoginError::NotScanned => "the device login has not received (r1, r2) yet",
+            DeviceLoginError::OtherPhone => "the device login was scanned by another session",
+            DeviceLoginError::WrongToken => "wrong poll token for the device login",
+        })
+    }
+}
+
+impl std::error::Error for DeviceLoginError {}
+
+impl From<DeviceLoginError> for Status {
+    fn from(e: DeviceLoginError) -> Self {
+        match e {
+            DeviceLoginError::NotFound | DeviceLoginError::Expired => Status::not_found(e.to_string()),
+            DeviceLoginError::AlreadyScanned | DeviceLoginError::NotScanned => Status::failed_precondition(e.to_string()),
+            DeviceLoginError::OtherPhone | DeviceLoginError::WrongToken => Status::permission_denied(e.to_string()),
+        }
+    }
+}
+
+/// Device logins by login id
+#[derive(Debug)]
+pub struct DeviceLogins {
+    ttl: Duration,
+    // login ids are ULIDs, so the first entry is the oldest
+    logins: Mutex<BTreeMap<String, DeviceLogin>>,
+}
+
+impl Default for DeviceLogins {
+    fn default() -> Self {
+        Self::new(LOGIN_TTL)
+    }
+}
+
+impl DeviceLogins {
+    pub fn new(ttl: Duration) -> Self {
+        DeviceLogins { ttl, logins: Mutex::new(BTreeMap::new()) }
+    }
+
+    pub fn ttl(&self) -> Duration {
+        self.ttl
+    }
+
+    /// a new login waiting for a phone; (c, nonce) stay here until one scans it
+    pub fn start(
+        &self,
+        login_id: String,
+        poll_token: String,
+        client: Option<ClientMetadata>,
+        source: String,
+        (c, nonce): (BigUint, Vec<u8>),
+        now: SystemTime,
+    ) {
+        let mut logins = self.logins.lock().unwrap();
+        while logins.len() >= MAX_PENDING {
+            logins.pop_first();
+        }
+        let login = DeviceLogin { poll_token, client, source, c, nonce, started_at: now, state: State::Waiting };
+        logins.insert(login_id, login);
+    }
+
+    /// a phone of `user` scanned the code and committed to (r1, r2); only the
+    /// first scan counts
+    pub fn scan(
+        &self,
+        login_id: &str,
+        user: &str,
+        phone_session: &str,
+        commitment: Commitment,
+        now: SystemTime,
+    ) -> Result<Scan, DeviceLoginError> {
+        let mut logins = self.logins.lock().unwrap();
+        let login = self.live(&mut logins, login_id, now)?;
+        if !matches!(login.state, State::Waiting) {
+            return Err(DeviceLoginError::AlreadyScanned);
+        }
+        login.state = State::Scanned { user: user.to_string(), phone_session: phone_session.to_string(), commitment };
+        Ok(login.scan())
+    }
+
+    /// the phone that scanned the code answers: the commitment and c to check
+    /// the answer against, and the device it is for; the login waits for `finish`
+    pub fn answer(
+        &self,
+        login_id: &str,
+        user: &str,
+        phone_session: &str,
+        now: SystemTime,
+    ) -> Result<(Commitment, Scan), DeviceLoginError> {
+        let mut logins = self.logins.lock().unwrap();
+        let login = self.live(&mut logins, login_id, now)?;
+        let commitment = scanned_by(&login.state, user, phone_session)?.clone();
+        login.state = State::Verifying { user: user.to_string() };
+        Ok((commitment, login.scan()))
+    }
+
+    /// the phone that scanned the code declines it
+    pub fn decline(&self, login_id: &str, user: &str, phone_session: &str, now: SystemTime) -> Result<(), DeviceLoginError> {
+        let mut logins = self.logins.lock().unwrap();
+        let login = self.live(&mut logins, login_id, now)?;
+        scanned_by(&login.state, user, phone_session)?;
+        login.state = State::Denied;
+        Ok(())
+    }
+
+    /// the outcome of checking the answer: the device's new session, or None
+    pub fn finish(&self, login_id: &str, session_id: Option<String>) {
+        if let Some(login) = self.logins.lock().unwrap().get_mut(login_id) {
+            if let State::Verifying { user } = &login.state {
+                login.state = match session_id {
+                    Some(session_id) => State::Approved { user: user.clone(), session_id },
+                    None => State::Denied,
+                };
+            }
+        }
+    }
+
+    /// the device asks how its login is doing; an outcome is told once, then
+    /// the login is gone
+    pub fn poll(&self, login_id: &str, poll_token: &str, now: SystemTime) -> Result<Poll, DeviceLoginError> {
+        let mut logins = self.logins.lock().unwrap();
+        let login = self.live(&mut logins, login_id, now)?;
+        if !same_token(&login.poll_token, poll_token) {
+            return Err(DeviceLoginError::WrongToken);
+        }
+        let poll = match &login.state {
+            State::Waiting => return Ok(Poll::Waiting),
+            State::Scanned { .. } | State::Verifying { .. } => return Ok(Poll::Scanned),
+            State::Approved { user, session_id } => Poll::Approved { user: user.clone(), session_id: session_id.clone() },
+            State::Denied => Poll::Denied,
+        };
+        logins.remove(login_id);
+        Ok(poll)
+    }
+
+    /// drop the logins started longer than the TTL before `now`; how many
+    pub fn expire(&self, now: SystemTime) -> usize {
+        let mut logins = self.logins.lock().unwrap();
+        let before = logins.len();
+        logins.retain(|_, login| !self.is_expired(login, now));
+        before - logins.len()
+    }
+
+    pub fn len(&self) -> usize {
+        self.logins.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// the login, unless it is unknown or past the TTL (and dropped then)
+    fn live<'a>(
+        &self,
+        logins: &'a mut BTreeMap<String, DeviceLogin>,
+        login_id: &str,
+        now: SystemTime,
+    ) -> Result<&'a mut DeviceLogin, DeviceLoginError> {
+        match logins.get(login_id) {
+            None => return Err(DeviceLoginError::NotFound),
+            Some(login) if self.is_expired(login, now) => {
+                logins.remove(login_id);
+                return Err(DeviceLoginError::Expired);
+            }
+            Some(_) => {}
+        }
+        Ok(logins.get_mut(login_id).expect("checked above"))
+    }
+
+    fn is_expired(&self, login: &DeviceLogin, now: SystemTime) -> bool {
+        now.duration_since(login.started_at).is_ok_and(|age| age >= self.ttl)
+    }
+}
+
+/// the commitment of a login scanned by this very session
+fn scanned_by<'a>(state: &'a State, user: &str, phone_session: &str) -> Result<&'a Commitment, DeviceLoginError> {
+    match state {
+        State::Scanned { user: scanner, phone_session: session, commitment } if scanner == user && session == phone_session => {
+            Ok(commitment)
+        }
+        State::Scanned { .. } | State::Verifying { .. } => Err(DeviceLoginError::OtherPhone),
+        State::Waiting => Err(DeviceLoginError::NotScanned),
+        State::Approved { .. } | State::Denied => Err(DeviceLoginError::AlreadyScanned),
+    }
+}
+
+/// compare without stopping at the first difference
+fn same_token(a: &str, b: &str) -> bool {
+    a.len() == b.len() && a.bytes().zip(b.bytes()).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+/// What the device puts in its QR code: the login id and the commitment to c
+pub fn qr_payload(login_id: &str, commitment: &[u8]) -> String {
+    format!("{}id={}&commitment={}", QR_PREFIX, login_id, hex::encode(commitment))
+}
+
+/// (login id, commitment) back from a scanned QR code, None unless it is one
+/// of ours
+pub fn parse_qr_payload(payload: &str) -> Option<(String, Vec<u8>)> {
+    let (mut login_id, mut commitment) = (None, None);
+    for pair in payload.trim().strip_prefix(QR_PREFIX)?.split('&') {
+        match pair.split_once('=')? {
+            ("id", id) if !id.is_empty() => login_id = Some(id.to_string()),
+            ("commitment", hex) => commitment = Some(hex::decode(hex).ok()?),
+            _ => return None,
+        }
+    }
+    Some((login_id?, commitment?))
+}
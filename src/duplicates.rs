@@ -0,0 +1,151 @@
+//! Spotting the same proof submitted under different auth_ids.
+//!
+//! An honest client picks a fresh k for every login, so an identical
+//! (r1, r2, s) triple turning up for a second auth_id means a broken RNG or
+//! someone pasting a captured answer. Remembering every triple would grow
+//! without bound, so the server keeps two bloom filters that rotate every
+//! `window`: a triple is remembered for between one and two windows, and a
+//! false positive (about 1 in 10^5 at the default size) costs one retry.
+//!
+//! The same filters remember which challenge tokens (challenge_token.rs)
+//! were answered, since nothing else on a stateless server can use one up.
+
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, SystemTime};
+
+use sha2::{Digest, Sha256};
+
+use crate::clock::Time;
+
+/// How long submissions are remembered by default
+pub const DEFAULT_WINDOW: Duration = Duration::from_secs(5 * 60);
+
+/// Bits per filter (128 KiB) and probes per entry
+const BITS: usize = 1 << 20;
+const PROBES: u64 = 7;
+
+/// What a filter says about a submission
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Seen {
+    /// never seen (or forgotten)
+    New,
+    /// seen before under this auth_id: a plain retry
+    SameAuth,
+    /// seen before under another auth_id
+    Duplicate,
+}
+
+/// Recently submitted (r1, r2, s) triples
+#[derive(Debug)]
+pub struct DuplicateFilter {
+    window: Duration,
+    time: Time,
+    filters: Mutex<Filters>,
+}
+
+#[derive(Debug)]
+struct Filters {
+    rotated_at: SystemTime,
+    current: Vec<u64>,
+    previous: Vec<u64>,
+}
+
+impl Default for DuplicateFilter {
+    fn default() -> Self {
+        Self::new(DEFAULT_WINDOW)
+    }
+}
+
+impl DuplicateFilter {
+    pub fn new(window: Duration) -> Self {
+        let time = Time::default();
+        Self {
+            window,
+            filters: Mutex::new(Filters {
+                rotated_at: time.now(),
+                current: vec![0; BITS / 64],
+                previous: vec![0; BITS / 64],
+            }),
+            time,
+        }
+    }
+
+    /// read the time from `time` instead of the system clock
+    pub fn with_time(mut self, time: Time) -> Self {
+        self.filters.get_mut().unwrap().rotated_at = time.now();
+        Self { time, ..self }
+    }
+
+    /// record the answer for `auth_id` and say whether it was seen before
+    pub fn check(&self, auth_id: &str, r1: &[u8], r2: &[u8], s: &[u8]) -> Seen {
+        let proof = digest(&[r1, r2, s]);
+        let answer = digest(&[auth_id.as_bytes(), r1, r2, s]);
+
+        let mut filters = self.rotated();
+        let seen = match (filters.contains(&proof), filters.contains(&answer)) {
+            (false, _) => Seen::New,
+            (true, true) => Seen::SameAuth,
+            (true, false) => Seen::Duplicate,
+        };
+        filters.insert(&proof);
+        filters.insert(&answer);
+        seen
+    }
+
+    /// mark the challenge `auth_id` as answered; false if it already was
+    pub fn use_up(&self, auth_id: &str) -> bool {
+        let used = digest(&[b"used", auth_id.as_bytes()]);
+        let mut filters = self.rotated();
+        let fresh = !filters.contains(&used);
+        filters.insert(&used);
+        fresh
+    }
+
+    /// the filters, the current one started afresh if a window has passed
+    fn rotated(&self) -> MutexGuard<'_, Filters> {
+        let mut filters = self.filters.lock().unwrap();
+        if self.time.since(filters.rotated_at) >= self.window {
+            filters.previous = std::mem::replace(&mut filters.current, vec![0; BITS / 64]);
+            filters.rotated_at = self.time.now();
+        }
+        filters
+    }
+
+    /// the two filters are allocated up front, so this never changes
+    pub fn heap_size(&self) -> usize {
+        2 * BITS / 8
+    }
+}
+
+impl Filters {
+    fn contains(&self, hash: &[u8; 32]) -> bool {
+        probes(hash).all(|bit| get(&self.current, bit)) || probes(hash).all(|bit| get(&self.previous, bit))
+    }
+
+    fn insert(&mut self, hash: &[u8; 32]) {
+        for bit in probes(hash) {
+            self.current[bit / 64] |= 1 << (bit % 64);
+        }
+    }
+}
+
+fn get(bits: &[u64], bit: usize) -> bool {
+    bits[bit / 64] & (1 << (bit % 64)) != 0
+}
+
+/// double hashing: bit i is h1 + i * h2
+fn probes(hash: &[u8; 32]) -> impl Iterator<Item = usize> {
+    let h1 = u64::from_be_bytes(hash[..8].try_into().unwrap());
+    let h2 = u64::from_be_bytes(hash[8..16].try_into().unwrap()) | 1;
+    (0..PROBES).map(move |i| (h1.wrapping_add(i.wrapping_mul(h2)) % BITS as u64) as usize)
+}
+
+/// length-prefixed so (ab, c) and (a, bc) differ
+fn digest(parts: &[&[u8]]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    for part in parts {
+        hasher.update((part.len() as u32).to_be_bytes());
+        hasher.update(part);
+    }
+    hasher.finalize().into()
+}
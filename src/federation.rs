@@ -0,0 +1,239 @@
+//! Federation: checking what peer servers issued without asking them.
+//!
+//! A server with a parameter key (--params-key) and an identity
+//! (--id-token-issuer) publishes a [`FederationInfoResponse`] through
+//! GetFederationInfo: its issuer, its signed group parameters and its ID token
+//! key, signed together with the parameter key. A server started with
+//! `--federation-peer <endpoint>#<parameter key>` fetches that from each peer,
+//! checks it against the pinned key and keeps it with the peer's revocation
+//! list, refreshing it every --federation-refresh seconds. A peer that can't be
+//! reached keeps its last good copy, so a region goes on verifying locally
+//! while another is down.
+//!
+//! With a peer's copy, a server checks the ID tokens the peer minted
+//! (VerifyFederatedToken) and non-interactive proofs made for the peer in its
+//! group (VerifyFederatedProof); a proof is for a peer and one of its users
+//! when its context is bound to both with [`proof_context`].
+
+use std::fmt;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use tonic::transport::{ClientTlsConfig, Endpoint};
+
+use crate::api::v1::{auth_client::AuthClient, FederationInfoRequest, FederationInfoResponse, ParametersResponse, RevocationListRequest};
+use crate::catch_panic::LockExt;
+use crate::params::{parse_public_key, ParametersError, SignedParameters};
+use crate::revocation::RevocationList;
+use crate::ZKP;
+
+/// How often peers are fetched again by default
+pub const REFRESH: Duration = Duration::from_secs(10 * 60);
+/// How long fetching one peer may take
+const FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Domain separation for the signature over a server's federation info
+const DOMAIN: &[u8] = b"zkp-auth/federation/v1";
+/// Domain separation for the context of proofs made for a peer
+const PROOF_DOMAIN: &[u8] = b"zkp-auth/federation-proof/v1";
+
+/// A peer as configured: where to fetch it and the parameter key it must be signed with
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PeerSpec {
+    pub endpoint: String,
+    pub params_key: VerifyingKey,
+}
+
+/// `<endpoint>#<parameter key in hex>`, e.g. `https://eu.auth.example#8f2c...`
+impl FromStr for PeerSpec {
+    type Err = String;
+
+    fn from_str(spec: &str) -> Result<Self, Self::Err> {
+        let (endpoint, key) = spec
+            .rsplit_once('#')
+            .ok_or_else(|| format!("{:?} should be <endpoint>#<the peer's parameter key>", spec))?;
+        if !endpoint.starts_with("http://") && !endpoint.starts_with("https://") {
+            return Err(format!("{:?} is not an http(s) endpoint", endpoint));
+        }
+        let params_key = parse_public_key(key).map_err(|e| format!("{:?}: {}", spec, e))?;
+        Ok(PeerSpec { endpoint: endpoint.to_string(), params_key })
+    }
+}
+
+/// What a peer published, checked
+#[derive(Debug, Clone)]
+pub struct Peer {
+    pub issuer: String,
+    pub zkp: ZKP, // its group, from the signed parameters
+    pub id_token_key: Option<VerifyingKey>,
+    pub revocations: RevocationList,
+    pub fetched_at: SystemTime,
+}
+
+/// Why a peer's info wasn't taken
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FederationError {
+    /// the peer didn't answer, or not with its info
+    Unreachable(String),
+    /// the parameters aren't signed by the pinned key, or aren't a usable group
+    Parameters(ParametersError),
+    /// the info isn't signed by the parameter key
+    BadSignature,
+    /// no issuer, or an ID token key that isn't one
+    Malformed(&'static str),
+}
+
+impl fmt::Display for FederationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FederationError::Unreachable(reason) => write!(f, "unreachable: {}", reason),
+            FederationError::Parameters(e) => write!(f, "{}", e),
+            FederationError::BadSignature => f.write_str("the federation info isn't signed by the parameter key"),
+            FederationError::Malformed(reason) => write!(f, "malformed federation info: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for FederationError {}
+
+/// sign what this server publishes to its peers with its parameter key
+pub fn sign_info(
+    key: &SigningKey,
+    issuer: &str,
+    parameters: &SignedParameters,
+    id_token_key: Option<&VerifyingKey>,
+) -> FederationInfoResponse {
+    let id_token_public_key = id_token_key.map(|key| key.to_bytes().to_vec()).unwrap_or_default();
+    let signature = key.sign(&signed_message(issuer, &parameters.signature, &id_token_public_key));
+    FederationInfoResponse {
+        issuer: issuer.to_string(),
+        parameters: Some(ParametersResponse {
+            p: parameters.p.clone(),
+            q: parameters.q.clone(),
+            alpha: parameters.alpha.clone(),
+            beta: parameters.beta.clone(),
+            public_key: parameters.public_key.clone(),
+            signature: parameters.signature.clone(),
+        }),
+        id_token_public_key,
+        signature: signature.to_bytes().to_vec(),
+    }
+}
+
+/// check a peer's info against its pinned parameter key: (issuer, group, ID token key)
+pub fn check_info(
+    info: &FederationInfoResponse,
+    pinned: &VerifyingKey,
+) -> Result<(String, ZKP, Option<VerifyingKey>), FederationError> {
+    let parameters = info.parameters.clone().ok_or(FederationError::Malformed("no parameters"))?;
+    let parameters = SignedParameters {
+        p: parameters.p,
+        q: parameters.q,
+        alpha: parameters.alpha,
+        beta: parameters.beta,
+        public_key: parameters.public_key,
+        signature: parameters.signature,
+    };
+    let zkp = parameters.verify(pinned).map_err(FederationError::Parameters)?;
+    let signature = Signature::from_slice(&info.signature).map_err(|_| FederationError::BadSignature)?;
+    pinned
+        .verify(&signed_message(&info.issuer, &parameters.signature, &info.id_token_public_key), &signature)
+        .map_err(|_| FederationError::BadSignature)?;
+    if info.issuer.is_empty() {
+        return Err(FederationError::Malformed("no issuer"));
+    }
+    let id_token_key = match info.id_token_public_key.as_slice() {
+        [] => None,
+        bytes => {
+            let bytes = <[u8; 32]>::try_from(bytes).map_err(|_| FederationError::Malformed("the ID token key must be 32 bytes"))?;
+            Some(VerifyingKey::from_bytes(&bytes).map_err(|_| FederationError::Malformed("the ID token key is not an Ed25519 key"))?)
+        }
+    };
+    Ok((info.issuer.clone(), zkp, id_token_key))
+}
+
+/// domain | issuer | the parameters' signature | ID token key, every field length-prefixed
+fn signed_message(issuer: &str, parameters_signature: &[u8], id_token_key: &[u8]) -> Vec<u8> {
+    let mut message = DOMAIN.to_vec();
+    for field in [issuer.as_bytes(), parameters_signature, id_token_key] {
+        message.extend_from_slice(&(field.len() as u32).to_be_bytes());
+        message.extend_from_slice(field);
+    }
+    message
+}
+
+/// The context a non-interactive proof by `user` for the peer `issuer` is
+/// made with, so it can't be passed off as one for another server, or as
+/// another user's to dodge the peer's revocation list
+pub fn proof_context(issuer: &str, user: &str, context: &[u8]) -> Vec<u8> {
+    let mut bound = PROOF_DOMAIN.to_vec();
+    for field in [issuer.as_bytes(), user.as_bytes(), context] {
+        bound.extend_from_slice(&(field.len() as u32).to_be_bytes());
+        bound.extend_from_slice(field);
+    }
+    bound
+}
+
+/// The configured peers and the last good copy of each
+#[derive(Debug)]
+pub struct Federation {
+    peers: Vec<(PeerSpec, Mutex<Option<Arc<Peer>>>)>,
+}
+
+impl Federation {
+    pub fn new(specs: Vec<PeerSpec>) -> Self {
+        Federation { peers: specs.into_iter().map(|spec| (spec, Mutex::new(None))).collect() }
+    }
+
+    pub fn len(&self) -> usize {
+        self.peers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.peers.is_empty()
+    }
+
+    /// fetch every peer again; one that fails keeps its last good copy.
+    /// (endpoint, the issuer or why it failed) for each
+    pub async fn refresh(&self, now: SystemTime) -> Vec<(String, Result<String, FederationError>)> {
+        let mut outcomes = Vec::new();
+        for (spec, copy) in &self.peers {
+            let outcome = match fetch(spec, now).await {
+                Ok(peer) => {
+                    let issuer = peer.issuer.clone();
+                    *copy.locked() = Some(Arc::new(peer));
+                    Ok(issuer)
+                }
+                Err(e) => Err(e),
+            };
+            outcomes.push((spec.endpoint.clone(), outcome));
+        }
+        outcomes
+    }
+
+    /// the peer that published `issuer`, if one has been fetched; the first
+    /// configured one wins should two claim the same issuer
+    pub fn peer(&self, issuer: &str) -> Option<Arc<Peer>> {
+        self.peers.iter().find_map(|(_, copy)| copy.locked().clone().filter(|peer| peer.issuer == issuer))
+    }
+}
+
+async fn fetch(spec: &PeerSpec, now: SystemTime) -> Result<Peer, FederationError> {
+    let unreachable = |e: &dyn fmt::Display| FederationError::Unreachable(e.to_string());
+    let mut endpoint = Endpoint::from_shared(spec.endpoint.clone()).map_err(|e| unreachable(&e))?.timeout(FETCH_TIMEOUT);
+    if spec.endpoint.starts_with("https://") {
+        endpoint = endpoint.tls_config(ClientTlsConfig::new()).map_err(|e| unreachable(&e))?;
+    }
+    let channel = tokio::time::timeout(FETCH_TIMEOUT, endpoint.connect())
+        .await
+        .map_err(|e| unreachable(&e))?
+        .map_err(|e| unreachable(&e))?;
+    let mut client = AuthClient::new(channel);
+
+    let info = client.get_federation_info(FederationInfoRequest {}).await.map_err(|e| unreachable(&e.message()))?.into_inner();
+    let (issuer, zkp, id_token_key) = check_info(&info, &spec.params_key)?;
+    let revocations = client.get_revocation_list(RevocationListRequest {}).await.map_err(|e| unreachable(&e.message()))?.into_inner();
+    Ok(Peer { issuer, zkp, id_token_key, revocations: revocations.into(), fetched_at: now })
+}
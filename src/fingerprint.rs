@@ -0,0 +1,108 @@
+//! Short, readable names for public keys.
+//!
+//! (y1, y2) are two numbers of up to 2048 bits each, too long for a log line
+//! and impossible to compare by eye. [`PublicKey::fingerprint`] hashes them
+//! into a [`Fingerprint`], which prints as a few hex pairs and can also be
+//! shown as emoji or words, so a user reading their screen and an admin
+//! reading the logs can tell whether they mean the same key.
+//!
+//! ```
+//! use rust_zkp_chaum_pedersen::{types::PublicKey, ZKP};
+//!
+//! let zkp = ZKP::builder().rfc5114().build().unwrap();
+//! let key: PublicKey = zkp.public_key(&42u32.into());
+//! let fingerprint = key.fingerprint();
+//! println!("{} {} ({})", fingerprint, fingerprint.to_emoji(), fingerprint.to_words());
+//! ```
+
+use std::fmt;
+
+use sha2::{Digest, Sha256};
+
+use crate::group::GroupId;
+use crate::types::PublicKey;
+
+/// SHA-256 of a public key and the group it is in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Fingerprint(pub [u8; 32]);
+
+/// Bytes shown by the short forms: 64 bits in hex, 42 in emoji and words
+const SHORT_BYTES: usize = 8;
+const SYMBOLS: usize = 7;
+
+/// The 64 emoji of Matrix's SAS verification, picked to be told apart at a
+/// glance, each with one word for where emoji don't render
+const EMOJI: [(&str, &str); 64] = [
+    ("🐶", "dog"), ("🐱", "cat"), ("🦁", "lion"), ("🐎", "horse"),
+    ("🦄", "unicorn"), ("🐷", "pig"), ("🐘", "elephant"), ("🐰", "rabbit"),
+    ("🐼", "panda"), ("🐓", "rooster"), ("🐧", "penguin"), ("🐢", "turtle"),
+    ("🐟", "fish"), ("🐙", "octopus"), ("🦋", "butterfly"), ("🌷", "flower"),
+    ("🌳", "tree"), ("🌵", "cactus"), ("🍄", "mushroom"), ("🌏", "globe"),
+    ("🌙", "moon"), ("☁️", "cloud"), ("🔥", "fire"), ("🍌", "banana"),
+    ("🍎", "apple"), ("🍓", "strawberry"), ("🌽", "corn"), ("🍕", "pizza"),
+    ("🎂", "cake"), ("❤️", "heart"), ("😀", "smiley"), ("🤖", "robot"),
+    ("🎩", "hat"), ("👓", "glasses"), ("🔧", "spanner"), ("🎅", "santa"),
+    ("👍", "thumbs"), ("☂️", "umbrella"), ("⌛", "hourglass"), ("⏰", "clock"),
+    ("🎁", "gift"), ("💡", "bulb"), ("📕", "book"), ("✏️", "pencil"),
+    ("📎", "paperclip"), ("✂️", "scissors"), ("🔒", "lock"), ("🔑", "key"),
+    ("🔨", "hammer"), ("☎️", "telephone"), ("🏁", "flag"), ("🚂", "train"),
+    ("🚲", "bicycle"), ("✈️", "aeroplane"), ("🚀", "rocket"), ("🏆", "trophy"),
+    ("⚽", "ball"), ("🎸", "guitar"), ("🎺", "trumpet"), ("🔔", "bell"),
+    ("⚓", "anchor"), ("🎧", "headphones"), ("📁", "folder"), ("📌", "pin"),
+];
+
+impl Fingerprint {
+    /// hash `key` as a key in `group`; the numbers are hashed, not their
+    /// encoding, so leading zero bytes on the wire don't change it
+    pub fn new(group: GroupId, key: &PublicKey) -> Self {
+        let (y1, y2) = key.to_bytes_be();
+        let mut hasher = Sha256::new();
+        hasher.update(b"zkp-auth key fingerprint v1");
+        for part in [group.name().as_bytes(), &y1, &y2] {
+            hasher.update((part.len() as u32).to_be_bytes());
+            hasher.update(part);
+        }
+        Fingerprint(hasher.finalize().into())
+    }
+
+    /// all 64 hex digits, e.g. for an id
+    pub fn to_hex(&self) -> String {
+        hex::encode(self.0)
+    }
+
+    /// seven emoji for the first 42 bits, to compare by eye
+    pub fn to_emoji(&self) -> String {
+        self.symbols().map(|i| EMOJI[i].0).collect::<Vec<_>>().join(" ")
+    }
+
+    /// the same seven symbols as words, to read out
+    pub fn to_words(&self) -> String {
+        self.symbols().map(|i| EMOJI[i].1).collect::<Vec<_>>().join(" ")
+    }
+
+    /// 6-bit indices into EMOJI, from the first bits on
+    fn symbols(&self) -> impl Iterator<Item = usize> {
+        let bits = u64::from_be_bytes(self.0[..SHORT_BYTES].try_into().unwrap());
+        (0..SYMBOLS).map(move |i| (bits >> (58 - 6 * i)) as usize & 63)
+    }
+}
+
+/// the first 64 bits as hex pairs, e.g. `3f:a2:91:c0:77:de:0b:1e`
+impl fmt::Display for Fingerprint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let pairs: Vec<String> = self.0[..SHORT_BYTES].iter().map(|byte| format!("{:02x}", byte)).collect();
+        f.write_str(&pairs.join(":"))
+    }
+}
+
+impl PublicKey {
+    /// a short name for the key, to show instead of (y1, y2)
+    pub fn fingerprint(&self) -> Fingerprint {
+        self.fingerprint_in(GroupId::ModP)
+    }
+
+    /// the same for a key registered in another group
+    pub fn fingerprint_in(&self, group: GroupId) -> Fingerprint {
+        Fingerprint::new(group, self)
+    }
+}
@@ -0,0 +1,180 @@
+//! Parameter sets with sizes known at compile time.
+//!
+//! `BigUint::to_bytes_be` drops leading zeros, so a value that happens to be
+//! small encodes shorter than its siblings and every reader has to cope with
+//! that. [`FixedZKP`] carries the byte lengths of p and q as const generics:
+//! group elements always encode to `[u8; P]` and scalars to `[u8; Q]`, and
+//! decoding refuses anything of the wrong length or out of range.
+
+use std::fmt;
+use std::ops::Deref;
+use std::sync::LazyLock;
+
+use num_bigint::BigUint;
+
+use crate::params::ParametersError;
+use crate::types::{Challenge, Commitment, PublicKey, Response};
+use crate::ZKP;
+
+/// A validated group whose p is `P` bytes and q is `Q` bytes long
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FixedZKP<const P: usize, const Q: usize> {
+    zkp: ZKP,
+}
+
+/// RFC 5114 section 2.1: 1024-bit p, 160-bit q
+pub type ZKP1024 = FixedZKP<128, 20>;
+
+/// RFC 5114 section 2.3: 2048-bit p, 256-bit q
+pub type ZKP2048 = FixedZKP<256, 32>;
+
+/// Why a fixed-size value was rejected
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+    /// not exactly the encoded length of this parameter set
+    Length { expected: usize, found: usize },
+    /// not below p (elements) or q (scalars)
+    OutOfRange,
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::Length { expected, found } => {
+                write!(f, "expected {} bytes, found {}", expected, found)
+            }
+            DecodeError::OutOfRange => write!(f, "value is out of range for the group"),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+impl<const P: usize, const Q: usize> FixedZKP<P, Q> {
+    /// validate `zkp` and check p and q have exactly `P` and `Q` bytes
+    pub fn new(zkp: ZKP) -> Result<Self, ParametersError> {
+        if zkp.p.bits().div_ceil(8) as usize != P || zkp.q.bits().div_ceil(8) as usize != Q {
+            return Err(ParametersError::InvalidGroup("p and q don't match the parameter set size"));
+        }
+        let zkp = ZKP::builder().p(zkp.p).q(zkp.q).alpha(zkp.alpha).beta(zkp.beta).build()?;
+        Ok(FixedZKP { zkp })
+    }
+
+    /// the underlying (unsized) instance
+    pub fn into_inner(self) -> ZKP {
+        self.zkp
+    }
+
+    /// a value mod p as exactly `P` big-endian bytes
+    pub fn encode_element(&self, value: &BigUint) -> [u8; P] {
+        assert!(value < &self.zkp.p, "group element must be below p");
+        left_pad(value)
+    }
+
+    /// a value mod q as exactly `Q` big-endian bytes
+    pub fn encode_scalar(&self, value: &BigUint) -> [u8; Q] {
+        assert!(value < &self.zkp.q, "scalar must be below q");
+        left_pad(value)
+    }
+
+    pub fn decode_element(&self, bytes: &[u8]) -> Result<BigUint, DecodeError> {
+        decode(bytes, P, &self.zkp.p)
+    }
+
+    pub fn decode_scalar(&self, bytes: &[u8]) -> Result<BigUint, DecodeError> {
+        decode(bytes, Q, &self.zkp.q)
+    }
+
+    pub fn encode_public_key(&self, key: &PublicKey) -> ([u8; P], [u8; P]) {
+        (self.encode_element(&key.y1), self.encode_element(&key.y2))
+    }
+
+    pub fn decode_public_key(&self, y1: &[u8], y2: &[u8]) -> Result<PublicKey, DecodeError> {
+        Ok(PublicKey {
+            y1: self.decode_element(y1)?,
+            y2: self.decode_element(y2)?,
+        })
+    }
+
+    pub fn encode_commitment(&self, commitment: &Commitment) -> ([u8; P], [u8; P]) {
+        (self.encode_element(&commitment.r1), self.encode_element(&commitment.r2))
+    }
+
+    pub fn decode_commitment(&self, r1: &[u8], r2: &[u8]) -> Result<Commitment, DecodeError> {
+        Ok(Commitment {
+            r1: self.decode_element(r1)?,
+            r2: self.decode_element(r2)?,
+        })
+    }
+
+    pub fn encode_challenge(&self, c: &Challenge) -> [u8; Q] {
+        self.encode_scalar(&c.0)
+    }
+
+    pub fn decode_challenge(&self, bytes: &[u8]) -> Result<Challenge, DecodeError> {
+        self.decode_scalar(bytes).map(Challenge)
+    }
+
+    pub fn encode_response(&self, s: &Response) -> [u8; Q] {
+        self.encode_scalar(&s.0)
+    }
+
+    pub fn decode_response(&self, bytes: &[u8]) -> Result<Response, DecodeError> {
+        self.decode_scalar(bytes).map(Response)
+    }
+}
+
+impl ZKP1024 {
+    /// the group behind [`ZKP::get_constants`], validated once
+    pub fn rfc5114() -> Self {
+        RFC5114_1024.clone()
+    }
+}
+
+static RFC5114_1024: LazyLock<ZKP1024> = LazyLock::new(|| {
+    ZKP1024::new(ZKP::builder().rfc5114().build().expect("RFC 5114 parameters are valid"))
+        .expect("RFC 5114 section 2.1 is 1024/160 bits")
+});
+
+impl ZKP2048 {
+    /// the 2048-bit group with a 256-bit subgroup; beta is hashed to the group
+    /// so nobody knows log_alpha(beta). Derived once, cloned after that
+    pub fn rfc5114() -> Self {
+        RFC5114_2048.clone()
+    }
+}
+
+static RFC5114_2048: LazyLock<ZKP2048> = LazyLock::new(|| {
+    let p = BigUint::from_bytes_be(&hex::decode("87A8E61DB4B6663CFFBBD19C651959998CEEF608660DD0F25D2CEED4435E3B00E00DF8F1D61957D4FAF7DF4561B2AA3016C3D91134096FAA3BF4296D830E9A7C209E0C6497517ABD5A8A9D306BCF67ED91F9E6725B4758C022E0B1EF4275BF7B6C5BFC11D45F9088B941F54EB1E59BB8BC39A0BF12307F5C4FDB70C581B23F76B63ACAE1CAA6B7902D52526735488A0EF13C6D9A51BFA4AB3AD8347796524D8EF6A167B5A41825D967E144E5140564251CCACB83E6B486F6B3CA3F7971506026C0B857F689962856DED4010ABD0BE621C3A3960A54E710C375F26375D7014103A4B54330C198AF126116D2276E11715F693877FAD7EF09CADB094AE91E1A1597").unwrap());
+    let q = BigUint::from_bytes_be(&hex::decode("8CF83642A709A097B447997640129DA299B1A47D1EB3750BA308B0FE64F5FBD3").unwrap());
+    let alpha = BigUint::from_bytes_be(&hex::decode("3FB32C9B73134D0B2E77506660EDBD484CA7B18F21EF205407F4793A1A0BA12510DBC15077BE463FFF4FED4AAC0BB555BE3A6C1B0C6B47B1BC3773BF7E8C6F62901228F8C28CBB18A55AE31341000A650196F931C77A57F2DDF463E5E9EC144B777DE62AAAB8A8628AC376D282D6ED3864E67982428EBC831D14348F6F2F9193B5045AF2767164E1DFC967C1FB3F2E55A4BD1BFFE83B9C80D052B985D182EA0ADB2A3B7313D3FE14C8484B1E052588B9B7D2BBD2DF016199ECD06E1557CD0915B3353BBB64E0EC377FD028370DF92B52C7891428CDC67EB6184B523D1DB246C32F63078490F00EF8D647D148D47954515E2327CFEF98C582664B4C0F6CC41659").unwrap());
+    let group = ZKP { beta: alpha.clone(), p, q, alpha };
+    let beta = group.hash_to_group(b"zkp-auth/beta/2048");
+    ZKP2048::new(ZKP { beta, ..group }).expect("RFC 5114 section 2.3 is 2048/256 bits")
+});
+
+impl<const P: usize, const Q: usize> Deref for FixedZKP<P, Q> {
+    type Target = ZKP;
+
+    fn deref(&self) -> &ZKP {
+        &self.zkp
+    }
+}
+
+fn left_pad<const N: usize>(value: &BigUint) -> [u8; N] {
+    let bytes = value.to_bytes_be();
+    let mut out = [0u8; N];
+    out[N - bytes.len()..].copy_from_slice(&bytes);
+    out
+}
+
+fn decode(bytes: &[u8], len: usize, bound: &BigUint) -> Result<BigUint, DecodeError> {
+    if bytes.len() != len {
+        return Err(DecodeError::Length { expected: len, found: bytes.len() });
+    }
+    let value = BigUint::from_bytes_be(bytes);
+    if &value >= bound {
+        return Err(DecodeError::OutOfRange);
+    }
+    Ok(value)
+}
@@ -0,0 +1,83 @@
+//! Gates in front of challenge issuance.
+//!
+//! Every password a credential-stuffing bot tries starts with a challenge. A
+//! [`PreChallengeGate`] sees each request for a new one (a fresh
+//! `CreateAuthenticationChallenge` or a `CommitChallenge`) before the server
+//! looks up the user, and may turn it away. [`OpenGate`] is the default. With
+//! the `captcha` feature the server can check a CAPTCHA token (Cloudflare
+//! Turnstile, hCaptcha or reCAPTCHA) that the client sends in the
+//! [`TOKEN_HEADER`] metadata.
+
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+
+/// Request metadata a client puts its CAPTCHA token in
+pub const TOKEN_HEADER: &str = "x-captcha-token";
+
+/// What the server knows about a request for a challenge when the gate runs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChallengeContext {
+    pub user: String, // canonical, not known to exist yet
+    pub source: String, // peer address
+    pub token: String, // from TOKEN_HEADER, empty when the client sent none
+}
+
+/// Why a gate didn't let a request through
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GateError {
+    /// the request is turned away, the reason is sent to the client
+    Refused(String),
+    /// the gate couldn't decide, e.g. the CAPTCHA provider is down
+    Unavailable(String),
+}
+
+impl fmt::Display for GateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GateError::Refused(reason) => write!(f, "refused: {}", reason),
+            GateError::Unavailable(reason) => write!(f, "unavailable: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for GateError {}
+
+/// What [`PreChallengeGate::check`] returns
+pub type GateFuture<'a> = Pin<Box<dyn Future<Output = Result<(), GateError>> + Send + 'a>>;
+
+/// Decides whether a request gets a challenge; may call out to other services
+pub trait PreChallengeGate: fmt::Debug + Send + Sync {
+    fn check<'a>(&'a self, request: &'a ChallengeContext) -> GateFuture<'a>;
+}
+
+/// Lets every request through (the default)
+#[derive(Debug, Clone, Copy, Default)]
+pub struct OpenGate;
+
+impl PreChallengeGate for OpenGate {
+    fn check<'a>(&'a self, _request: &'a ChallengeContext) -> GateFuture<'a> {
+        Box::pin(async { Ok(()) })
+    }
+}
+
+/// A shareable gate, [`OpenGate`] by default
+#[derive(Debug, Clone)]
+pub struct Gate(Arc<dyn PreChallengeGate>);
+
+impl Gate {
+    pub fn new(gate: impl PreChallengeGate + 'static) -> Self {
+        Gate(Arc::new(gate))
+    }
+
+    pub async fn check(&self, request: &ChallengeContext) -> Result<(), GateError> {
+        self.0.check(request).await
+    }
+}
+
+impl Default for Gate {
+    fn default() -> Self {
+        Gate::new(OpenGate)
+    }
+}
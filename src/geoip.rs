@@ -0,0 +1,222 @@
+//! Country lookups in a MaxMind database (GeoLite2-Country, GeoIP2-City, ...).
+//!
+//! An .mmdb file is a binary search tree over the bits of an address whose
+//! leaves point into a data section of maps, strings and numbers; metadata at
+//! the end of the file says how many nodes the tree has and how wide its
+//! records are. Only as much of the format as a country lookup needs is read
+//! here, and the whole file is kept in memory.
+
+use std::io;
+use std::net::IpAddr;
+use std::path::Path;
+
+use serde_json::{Map, Number, Value};
+
+/// where the metadata starts, counted from the end of the file
+const METADATA_MARKER: &[u8] = b"\xAB\xCD\xEFMaxMind.com";
+
+/// how deep maps, arrays and pointers may nest before a file is taken as broken
+const MAX_DEPTH: usize = 32;
+
+/// A MaxMind database, read into memory
+#[derive(Debug)]
+pub struct GeoIp {
+    data: Vec<u8>,
+    node_count: usize,
+    record_size: usize, // bits per record, 24, 28 or 32
+    ip_version: u64, // 6 for a tree over IPv6 (with IPv4 at ::/96), 4 for IPv4 only
+    ipv4_start: usize, // the node IPv4 lookups start at
+    pub database_type: String, // e.g. GeoLite2-Country
+}
+
+impl GeoIp {
+    /// read a database from disk
+    pub fn open(path: &Path) -> io::Result<Self> {
+        GeoIp::from_bytes(std::fs::read(path)?).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    pub fn from_bytes(data: Vec<u8>) -> Result<Self, String> {
+        let marker = data
+            .windows(METADATA_MARKER.len())
+            .rposition(|window| window == METADATA_MARKER)
+            .ok_or("not a MaxMind database (no metadata)")?;
+        let (metadata, _) = decode(&data[marker + METADATA_MARKER.len()..], 0, 0)?;
+        let number = |key: &str| metadata[key].as_u64().ok_or(format!("the metadata has no {}", key));
+        let node_count = number("node_count")? as usize;
+        let record_size = number("record_size")? as usize;
+        let ip_version = number("ip_version")?;
+        if ![24, 28, 32].contains(&record_size) {
+            return Err(format!("records of {} bits aren't supported", record_size));
+        }
+        if ![4, 6].contains(&ip_version) {
+            return Err(format!("IP version {} isn't supported", ip_version));
+        }
+        if node_count.saturating_mul(record_size) / 4 + 16 > marker {
+            return Err(format!("{} nodes don't fit in the file", node_count));
+        }
+        let mut geoip = GeoIp {
+            data,
+            node_count,
+            record_size,
+            ip_version,
+            ipv4_start: 0,
+            database_type: metadata["database_type"].as_str().unwrap_or_default().to_string(),
+        };
+        if ip_version == 6 {
+            // ::a.b.c.d is 96 zero bits in
+            for _ in 0..96 {
+                if geoip.ipv4_start >= node_count {
+                    break;
+                }
+                geoip.ipv4_start = geoip.record(geoip.ipv4_start, 0)?;
+            }
+        }
+        Ok(geoip)
+    }
+
+    /// bytes the search tree takes, the data section starts 16 bytes after it
+    fn tree_size(&self) -> usize {
+        self.node_count * self.record_size / 4
+    }
+
+    /// the left (0) or right (1) record of a node
+    fn record(&self, node: usize, bit: u8) -> Result<usize, String> {
+        let width = self.record_size / 4;
+        let bytes = self.data.get(node * width..(node + 1) * width).ok_or("the search tree is truncated")?;
+        let value = match (self.record_size, bit) {
+            (24, 0) => be(&bytes[0..3]),
+            (24, _) => be(&bytes[3..6]),
+            (28, 0) => ((bytes[3] as usize & 0xf0) << 20) | be(&bytes[0..3]),
+            (28, _) => ((bytes[3] as usize & 0x0f) << 24) | be(&bytes[4..7]),
+            (_, 0) => be(&bytes[0..4]),
+            (_, _) => be(&bytes[4..8]),
+        };
+        Ok(value)
+    }
+
+    /// what the database says about an address, None when it has nothing
+    pub fn lookup(&self, ip: IpAddr) -> Result<Option<Value>, String> {
+        let (bytes, mut node) = match (ip, ip.to_canonical()) {
+            (_, IpAddr::V4(v4)) => (v4.octets().to_vec(), self.ipv4_start),
+            (IpAddr::V6(v6), _) if self.ip_version == 6 => (v6.octets().to_vec(), 0),
+            _ => return Ok(None), // IPv6 in an IPv4 database
+        };
+        for i in 0..bytes.len() * 8 {
+            if node >= self.node_count {
+                break;
+            }
+            node = self.record(node, (bytes[i / 8] >> (7 - i % 8)) & 1)?;
+        }
+        match node.cmp(&self.node_count) {
+            std::cmp::Ordering::Equal => Ok(None),
+            std::cmp::Ordering::Less => Err("the search tree is deeper than an address".to_string()),
+            std::cmp::Ordering::Greater => {
+                let section = self.data.get(self.tree_size() + 16..).ok_or("no data section")?;
+                Ok(Some(decode(section, node - self.node_count - 16, 0)?.0))
+            }
+        }
+    }
+
+    /// the ISO 3166 code of the country an address is in, or failing that the
+    /// one its network is registered in
+    pub fn country(&self, ip: IpAddr) -> Option<String> {
+        let record = self.lookup(ip).ok()??;
+        let code = record["country"]["iso_code"].as_str().or(record["registered_country"]["iso_code"].as_str())?;
+        Some(code.to_string())
+    }
+}
+
+/// a big-endian unsigned number
+fn be(bytes: &[u8]) -> usize {
+    bytes.iter().fold(0, |n, &byte| (n << 8) | byte as usize)
+}
+
+/// the value at `at` in a data section, and where the next one starts
+fn decode(section: &[u8], mut at: usize, depth: usize) -> Result<(Value, usize), String> {
+    if depth > MAX_DEPTH {
+        return Err("the data is nested too deep".to_string());
+    }
+    let take = |at: usize, n: usize| section.get(at..at + n).ok_or_else(|| "the data section is truncated".to_string());
+    let control = take(at, 1)?[0];
+    at += 1;
+    let mut kind = control >> 5;
+    if kind == 1 {
+        // a pointer, relative to the start of the section
+        let extra = ((control >> 3) & 0x3) as usize;
+        let low = (control & 0x7) as usize;
+        let bytes = take(at, extra + 1)?;
+        let target = match extra {
+            0 => (low << 8) | be(bytes),
+            1 => ((low << 16) | be(bytes)) + 2048,
+            2 => ((low << 24) | be(bytes)) + 526336,
+            _ => be(bytes),
+        };
+        let (value, _) = decode(section, target, depth + 1)?;
+        return Ok((value, at + extra + 1));
+    }
+    if kind == 0 {
+        kind = 7 + take(at, 1)?[0];
+        at += 1;
+    }
+    let size = match control & 0x1f {
+        29 => 29 + be(take(at, 1)?),
+        30 => 285 + be(take(at, 2)?),
+        31 => 65821 + be(take(at, 3)?),
+        size => size as usize,
+    };
+    at += match control & 0x1f {
+        29..=31 => (control & 0x1f) as usize - 28,
+        _ => 0,
+    };
+    let value = match kind {
+        2 => Value::String(String::from_utf8_lossy(take(at, size)?).into_owned()),
+        3 => {
+            let bytes: [u8; 8] = take(at, size)?.try_into().map_err(|_| "a double isn't 8 bytes")?;
+            Number::from_f64(f64::from_be_bytes(bytes)).map_or(Value::Null, Value::Number)
+        }
+        4 => Value::Array(take(at, size)?.iter().map(|&byte| Value::from(byte)).collect()),
+        5 | 6 | 9 | 10 => {
+            let bytes = take(at, size)?;
+            if size > 16 {
+                return Err(format!("an unsigned number of {} bytes", size));
+            }
+            let n = bytes.iter().fold(0u128, |n, &byte| (n << 8) | byte as u128);
+            u64::try_from(n).map_or_else(|_| Value::String(n.to_string()), Value::from)
+        }
+        8 => {
+            if size > 4 {
+                return Err(format!("an int32 of {} bytes", size));
+            }
+            Value::from(be(take(at, size)?) as u32 as i32)
+        }
+        7 | 11 => {
+            let (mut map, mut array) = (Map::new(), Vec::new());
+            for _ in 0..size {
+                if kind == 7 {
+                    let (key, next) = decode(section, at, depth + 1)?;
+                    let key = key.as_str().ok_or("a map key isn't a string")?.to_string();
+                    let (value, next) = decode(section, next, depth + 1)?;
+                    map.insert(key, value);
+                    at = next;
+                } else {
+                    let (value, next) = decode(section, at, depth + 1)?;
+                    array.push(value);
+                    at = next;
+                }
+            }
+            let value = match kind {
+                7 => Value::Object(map),
+                _ => Value::Array(array),
+            };
+            return Ok((value, at));
+        }
+        13 => Value::Null, // end marker
+        14 => return Ok((Value::Bool(size != 0), at)),
+        15 => {
+            let bytes: [u8; 4] = take(at, size)?.try_into().map_err(|_| "a float isn't 4 bytes")?;
+            Number::from_f64(f32::from_be_bytes(bytes) as f64).map_or(Value::Null, Value::Number)
+        }
+        _ => return Err(format!("unknown data type {}", kind)),
+    };
+    Ok((value, at + size))
+}
@@ -0,0 +1,151 @@
+//! The groups and hashes a user can register with, as named on the wire.
+//!
+//! Every server speaks "modp", the Z_p group of `GetParameters`. Builds with
+//! the `p256` feature also take "p256" ([`crate::nist`]). `GetServerInfo`
+//! lists what a server supports, the client picks one with [`negotiate`] and
+//! names it in `RegisterRequest.group`, and `GetUserKdfParams` tells later
+//! logins which group the account is in. Challenges are the same numbers in
+//! every group; only how (y1, y2), (r1, r2) and s are encoded and checked
+//! differs.
+//!
+//! [`HashId`] works the same way for the hash behind a per-user generator
+//! H2G(user), so accounts made with different hashes can live side by side
+//! while a user base moves from one to the other.
+
+use std::fmt;
+
+use num_bigint::BigUint;
+
+use crate::types::{Challenge, Commitment, PublicKey, Response};
+use crate::ZKP;
+
+/// A group registrations and logins can use
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum GroupId {
+    /// Z_p with the server's (p, q, alpha, beta), what every server speaks
+    #[default]
+    ModP,
+    /// NIST P-256, with the `p256` feature
+    P256,
+}
+
+impl GroupId {
+    pub const ALL: [GroupId; 2] = [GroupId::ModP, GroupId::P256];
+
+    /// the name on the wire
+    pub fn name(&self) -> &'static str {
+        match self {
+            GroupId::ModP => "modp",
+            GroupId::P256 => "p256",
+        }
+    }
+
+    /// parse a wire name; empty means "modp", as sent by clients that predate groups
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "" => Some(GroupId::ModP),
+            name => Self::ALL.into_iter().find(|group| group.name() == name),
+        }
+    }
+
+    /// was this build compiled with the group's backend?
+    pub fn is_supported(&self) -> bool {
+        match self {
+            GroupId::ModP => true,
+            GroupId::P256 => cfg!(feature = "p256"),
+        }
+    }
+
+    /// every group this build supports, "modp" first
+    pub fn supported() -> Vec<GroupId> {
+        Self::ALL.into_iter().filter(GroupId::is_supported).collect()
+    }
+
+    /// are (y1, y2) valid for this group? Z_p keys must be in the order-q
+    /// subgroup and not 1, so 0 or a number past p can't be registered
+    pub fn accepts_public_key(&self, y1: &[u8], y2: &[u8]) -> bool {
+        match self {
+            GroupId::ModP => {
+                let zkp = crate::fixed::ZKP1024::rfc5114();
+                [y1, y2].into_iter().all(|y| zkp.is_element(&BigUint::from_bytes_be(y)))
+            }
+            #[cfg(feature = "p256")]
+            GroupId::P256 => crate::nist::P256PublicKey::from_bytes(&[y1, y2].concat()).is_some(),
+            #[cfg(not(feature = "p256"))]
+            GroupId::P256 => {
+                let _ = (y1, y2);
+                false
+            }
+        }
+    }
+
+    /// check an answer as sent over gRPC. `zkp` is the user's Z_p group (with
+    /// its own beta, if any) and only used for "modp"
+    pub fn verify_answer(&self, zkp: &ZKP, key: &PublicKey, commitment: &Commitment, c: &Challenge, s: &Response) -> bool {
+        match self {
+            GroupId::ModP => zkp.verify_response(key, commitment, c, s),
+            #[cfg(feature = "p256")]
+            GroupId::P256 => crate::nist::P256::new().verify_wire(
+                &key.y1.to_bytes_be(),
+                &key.y2.to_bytes_be(),
+                &commitment.r1.to_bytes_be(),
+                &commitment.r2.to_bytes_be(),
+                &c.to_bytes_be(),
+                &s.to_bytes_be(),
+            ),
+            #[cfg(not(feature = "p256"))]
+            GroupId::P256 => false,
+        }
+    }
+}
+
+impl fmt::Display for GroupId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// A hash H2G(user) can be built on
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, clap::ValueEnum)]
+pub enum HashId {
+    /// the default, and what accounts from before hashes were named use
+    #[default]
+    Sha256,
+    Sha512,
+}
+
+impl HashId {
+    pub const ALL: [HashId; 2] = [HashId::Sha256, HashId::Sha512];
+
+    /// the name on the wire
+    pub fn name(&self) -> &'static str {
+        match self {
+            HashId::Sha256 => "sha256",
+            HashId::Sha512 => "sha512",
+        }
+    }
+
+    /// parse a wire name; empty means "sha256", as sent by clients that predate hashes
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "" => Some(HashId::Sha256),
+            name => Self::ALL.into_iter().find(|hash| hash.name() == name),
+        }
+    }
+}
+
+impl fmt::Display for HashId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// the first group in `preferred` that the server also `offers` (its
+/// `ServerInfoResponse.groups`; empty from a server that predates groups,
+/// which only speaks "modp")
+pub fn negotiate(preferred: &[GroupId], offers: &[String]) -> Option<GroupId> {
+    preferred.iter().copied().find(|group| match offers.is_empty() {
+        true => *group == GroupId::ModP,
+        false => offers.iter().any(|offer| offer == group.name()),
+    })
+}
@@ -0,0 +1,91 @@
+//! The few outgoing HTTP calls the server makes (Vault, cloud KMS, event webhooks, CAPTCHA checks),
+//! and the client's breach check.
+//!
+//! Each call gets a connection of its own: most happen rarely enough that
+//! pooling isn't worth keeping sockets open (a CAPTCHA check per login waits
+//! on the provider far longer than on a handshake), and it keeps the client
+//! down to hyper's connection API plus the rustls the server already links.
+
+use std::io::{self, BufReader};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use hyper::body::Bytes;
+use hyper::{Body, Request, StatusCode, Uri};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpStream;
+use tokio_rustls::rustls::pki_types::ServerName;
+use tokio_rustls::rustls::{ClientConfig, RootCertStore};
+use tokio_rustls::TlsConnector;
+
+/// How long one call may take
+pub const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// roots for https calls: only `ca_cert` (a PEM file) when given, the system's otherwise
+pub fn tls_config(ca_cert: Option<&Path>) -> io::Result<Arc<ClientConfig>> {
+    let mut roots = RootCertStore::empty();
+    match ca_cert {
+        Some(path) => {
+            for cert in rustls_pemfile::certs(&mut BufReader::new(std::fs::File::open(path)?)) {
+                roots.add(cert?).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            }
+        }
+        None => {
+            for cert in rustls_native_certs::load_native_certs()? {
+                let _ = roots.add(cert);
+            }
+        }
+    }
+    Ok(Arc::new(ClientConfig::builder().with_root_certificates(roots).with_no_client_auth()))
+}
+
+/// send `request` (with an absolute `http://` or `https://` URI) and read the
+/// whole answer; errors are for no answer at all, an error status is an answer
+pub async fn exchange(request: Request<Body>, tls: Option<&Arc<ClientConfig>>) -> Result<(StatusCode, Bytes), String> {
+    let uri = request.uri().clone();
+    let host = uri.host().ok_or_else(|| format!("{} has no host", uri))?.trim_matches(['[', ']']).to_string();
+    let https = match uri.scheme_str() {
+        Some("https") => true,
+        Some("http") => false,
+        _ => return Err(format!("{} must be http:// or https://", uri)),
+    };
+    let port = uri.port_u16().unwrap_or(if https { 443 } else { 80 });
+
+    // origin-form on the wire, with the authority in Host
+    let (mut parts, body) = request.into_parts();
+    parts.uri = uri.path_and_query().map_or("/", |path| path.as_str()).parse::<Uri>().map_err(|e| e.to_string())?;
+    let authority = uri.authority().map_or(host.clone(), |authority| authority.to_string());
+    parts.headers.insert(hyper::header::HOST, authority.parse().map_err(|_| format!("bad host {}", authority))?);
+    let request = Request::from_parts(parts, body);
+
+    let call = async {
+        let tcp = TcpStream::connect((host.as_str(), port)).await.map_err(|e| e.to_string())?;
+        match (https, tls) {
+            (true, Some(config)) => {
+                let name = ServerName::try_from(host.clone()).map_err(|e| e.to_string())?;
+                let tls = TlsConnector::from(config.clone()).connect(name, tcp).await.map_err(|e| e.to_string())?;
+                send(tls, request).await
+            }
+            (true, None) => Err(format!("{} needs a TLS configuration", uri)),
+            (false, _) => send(tcp, request).await,
+        }
+    };
+    tokio::time::timeout(TIMEOUT, call)
+        .await
+        .map_err(|_| format!("no answer within {:?}", TIMEOUT))?
+}
+
+async fn send<S>(io: S, request: Request<Body>) -> Result<(StatusCode, Bytes), String>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let (mut sender, connection) = hyper::client::conn::handshake(io).await.map_err(|e| e.to_string())?;
+    tokio::spawn(async move {
+        let _ = connection.await;
+    });
+    let response = sender.send_request(request).await.map_err(|e| e.to_string())?;
+    let status = response.status();
+    let body = hyper::body::to_bytes(response.into_body()).await.map_err(|e| e.to_string())?;
+    Ok((status, body))
+}
@@ -0,0 +1,200 @@
+//! gRPC statuses as HTTP answers, for anything that serves this API over HTTP.
+//!
+//! HTTP clients expect HTTP status codes, not a `grpc-status` trailer.
+//! [`StatusMapping`] picks the code with the usual gateway table (NotFound is
+//! 404, PermissionDenied 403, ResourceExhausted 429, ...), which a deployment
+//! can override code by code: a failed proof is PermissionDenied, and some
+//! clients want that as 401. The body is an RFC 9457 problem
+//! (`application/problem+json`) with the status message as its detail.
+//! 429 and 503 answers carry `Retry-After`, from the status's `retry-after`
+//! metadata when the server set it, and 401 answers `WWW-Authenticate: Bearer`.
+//!
+//! The axum middleware in integrations.rs answers this way, and so will a
+//! REST gateway.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+use tonic::codegen::http::{header, HeaderValue, Response, StatusCode};
+use tonic::{Code, Status};
+
+/// Content type of problem details
+pub const PROBLEM_JSON: &str = "application/problem+json";
+
+/// metadata key a status can say when to retry in, in seconds
+pub const RETRY_AFTER_METADATA: &str = "retry-after";
+
+/// the HTTP status a gateway answers `code` with unless told otherwise
+pub fn default_http_status(code: Code) -> StatusCode {
+    match code {
+        Code::Ok => StatusCode::OK,
+        Code::Cancelled => StatusCode::from_u16(499).expect("499 is a valid status"), // client closed the request
+        Code::InvalidArgument | Code::FailedPrecondition | Code::OutOfRange => StatusCode::BAD_REQUEST,
+        Code::DeadlineExceeded => StatusCode::GATEWAY_TIMEOUT,
+        Code::NotFound => StatusCode::NOT_FOUND,
+        Code::AlreadyExists | Code::Aborted => StatusCode::CONFLICT,
+        Code::PermissionDenied => StatusCode::FORBIDDEN,
+        Code::Unauthenticated => StatusCode::UNAUTHORIZED,
+        Code::ResourceExhausted => StatusCode::TOO_MANY_REQUESTS,
+        Code::Unimplemented => StatusCode::NOT_IMPLEMENTED,
+        Code::Unavailable => StatusCode::SERVICE_UNAVAILABLE,
+        Code::Unknown | Code::Internal | Code::DataLoss => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+/// the name of a code as written in configuration, e.g. NotFound
+pub fn code_name(code: Code) -> String {
+    format!("{:?}", code)
+}
+
+/// A gRPC code answered with another HTTP status, written `PermissionDenied=401`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CodeOverride {
+    pub code: Code,
+    pub status: StatusCode,
+}
+
+impl FromStr for CodeOverride {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, status) = s.split_once('=').ok_or(format!("{:?} isn't CODE=HTTP_STATUS", s))?;
+        // NotFound, NOT_FOUND and not_found all name the same code
+        let wanted = name.trim().replace('_', "").to_ascii_lowercase();
+        let code = (0..=16)
+            .map(Code::from_i32)
+            .find(|code| code_name(*code).to_ascii_lowercase() == wanted)
+            .ok_or(format!("{:?} isn't a gRPC status code", name.trim()))?;
+        if code == Code::Ok {
+            return Err("OK isn't an error, its status can't change".to_string());
+        }
+        let status = status
+            .trim()
+            .parse::<u16>()
+            .ok()
+            .and_then(|status| StatusCode::from_u16(status).ok())
+            .filter(|status| status.is_client_error() || status.is_server_error())
+            .ok_or(format!("{:?} isn't a 4xx or 5xx HTTP status", status.trim()))?;
+        Ok(CodeOverride { code, status })
+    }
+}
+
+impl fmt::Display for CodeOverride {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}={}", code_name(self.code), self.status.as_u16())
+    }
+}
+
+/// An RFC 9457 problem: what went wrong, for HTTP clients
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Problem {
+    pub type_uri: String, // about:blank unless the mapping has a base for problem types
+    pub title: String, // the HTTP reason phrase, e.g. Not Found
+    pub status: u16,
+    pub detail: String, // the gRPC status message
+    pub grpc_status: String, // extension member, e.g. NotFound
+}
+
+impl Problem {
+    pub fn to_json(&self) -> String {
+        serde_json::json!({
+            "type": self.type_uri,
+            "title": self.title,
+            "status": self.status,
+            "detail": self.detail,
+            "grpc_status": self.grpc_status,
+        })
+        .to_string()
+    }
+}
+
+/// Which HTTP status each gRPC code is answered with, and how
+#[derive(Debug, Clone)]
+pub struct StatusMapping {
+    overrides: HashMap<Code, StatusCode>,
+    retry_after: Duration, // for 429 and 503 when the status doesn't say
+    problem_types: Option<String>, // base URI of problem types, e.g. https://example.com/problems/
+}
+
+impl Default for StatusMapping {
+    fn default() -> Self {
+        StatusMapping { overrides: HashMap::new(), retry_after: Duration::from_secs(1), problem_types: None }
+    }
+}
+
+impl StatusMapping {
+    /// answer `code` with `status` instead of the default
+    pub fn with_override(mut self, code_override: CodeOverride) -> Self {
+        self.overrides.insert(code_override.code, code_override.status);
+        self
+    }
+
+    /// Retry-After for 429 and 503 answers whose status has no `retry-after` metadata
+    pub fn retry_after(mut self, retry_after: Duration) -> Self {
+        self.retry_after = retry_after;
+        self
+    }
+
+    /// name problem types `<base><code>`, e.g. https://example.com/problems/not-found,
+    /// instead of about:blank
+    pub fn problem_types(mut self, base: &str) -> Self {
+        self.problem_types = Some(base.to_string());
+        self
+    }
+
+    pub fn http_status(&self, code: Code) -> StatusCode {
+        self.overrides.get(&code).copied().unwrap_or_else(|| default_http_status(code))
+    }
+
+    pub fn problem(&self, status: &Status) -> Problem {
+        let http_status = self.http_status(status.code());
+        let name = code_name(status.code());
+        let type_uri = match &self.problem_types {
+            Some(base) => format!("{}{}", base, kebab_case(&name)),
+            None => "about:blank".to_string(),
+        };
+        Problem {
+            type_uri,
+            title: http_status.canonical_reason().unwrap_or("Error").to_string(),
+            status: http_status.as_u16(),
+            detail: status.message().to_string(),
+            grpc_status: name,
+        }
+    }
+
+    /// the whole HTTP answer to `status`: code, headers and problem+json body
+    pub fn response(&self, status: &Status) -> Response<String> {
+        let problem = self.problem(status);
+        let http_status = self.http_status(status.code());
+        let mut response = Response::new(problem.to_json());
+        *response.status_mut() = http_status;
+        let headers = response.headers_mut();
+        headers.insert(header::CONTENT_TYPE, HeaderValue::from_static(PROBLEM_JSON));
+        if http_status == StatusCode::TOO_MANY_REQUESTS || http_status == StatusCode::SERVICE_UNAVAILABLE {
+            let seconds = status
+                .metadata()
+                .get(RETRY_AFTER_METADATA)
+                .and_then(|value| value.to_str().ok()?.trim().parse::<u64>().ok())
+                .unwrap_or_else(|| self.retry_after.as_secs_f64().ceil() as u64);
+            headers.insert(header::RETRY_AFTER, HeaderValue::from(seconds));
+        }
+        if http_status == StatusCode::UNAUTHORIZED {
+            headers.insert(header::WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"));
+        }
+        response
+    }
+}
+
+/// NotFound -> not-found
+fn kebab_case(name: &str) -> String {
+    let mut kebab = String::with_capacity(name.len() + 4);
+    for (i, c) in name.chars().enumerate() {
+        if c.is_ascii_uppercase() && i > 0 {
+            kebab.push('-');
+        }
+        kebab.push(c.to_ascii_lowercase());
+    }
+    kebab
+}
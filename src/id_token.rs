@@ -0,0 +1,240 @@
+//! OpenID Connect-style ID tokens.
+//!
+//! A ZKP login proves who the user is to this server only. So that existing
+//! OIDC relying parties can use it, the server can mint an ID token after a
+//! login: a JWT signed with its long-term Ed25519 key (`alg: EdDSA`) carrying
+//! `iss`, `sub` (the user name), `aud`, `iat`, `exp` and the relying party's
+//! `nonce`. Relying parties get the public key as a JWK from [`jwk`] and check
+//! tokens with any JWT library, or with [`verify`].
+
+use std::fmt;
+use std::time::{Duration, SystemTime};
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::clock::unix_seconds;
+
+/// How long a token is valid by default
+pub const DEFAULT_LIFETIME: Duration = Duration::from_secs(5 * 60);
+
+/// The claims of an ID token
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IdTokenClaims {
+    pub iss: String,
+    pub sub: String, // the user name
+    pub aud: String,
+    pub iat: u64, // seconds since the epoch
+    pub exp: u64,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub nonce: String, // echoed from the relying party's request
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub sid: String, // the session the login created
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Header {
+    alg: String,
+    typ: String,
+    kid: String,
+}
+
+/// Mints ID tokens for a fixed issuer and set of audiences
+#[derive(Debug)]
+pub struct IdTokenIssuer {
+    key: SigningKey,
+    issuer: String,
+    audiences: Vec<String>, // relying parties (OIDC client ids) tokens may be minted for
+    lifetime: Duration,
+}
+
+/// Why a token was refused
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IdTokenError {
+    /// not a JWT this module issues
+    Malformed(String),
+    /// not signed by the expected key
+    BadSignature,
+    WrongIssuer,
+    WrongAudience,
+    Expired,
+}
+
+impl fmt::Display for IdTokenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IdTokenError::Malformed(reason) => write!(f, "malformed ID token: {}", reason),
+            IdTokenError::BadSignature => write!(f, "ID token signature is invalid"),
+            IdTokenError::WrongIssuer => write!(f, "ID token is from another issuer"),
+            IdTokenError::WrongAudience => write!(f, "ID token is for another audience"),
+            IdTokenError::Expired => write!(f, "ID token has expired"),
+        }
+    }
+}
+
+impl std::error::Error for IdTokenError {}
+
+impl IdTokenIssuer {
+    pub fn new(key: SigningKey, issuer: impl Into<String>, audiences: Vec<String>, lifetime: Duration) -> Self {
+        IdTokenIssuer { key, issuer: issuer.into(), audiences, lifetime }
+    }
+
+    pub fn issuer(&self) -> &str {
+        &self.issuer
+    }
+
+    /// whether tokens may be minted for `audience`
+    pub fn allows_audience(&self, audience: &str) -> bool {
+        self.audiences.iter().any(|allowed| allowed == audience)
+    }
+
+    /// the key tokens are signed with
+    pub fn public_key(&self) -> VerifyingKey {
+        self.key.verifying_key()
+    }
+
+    /// the public key relying parties verify with
+    pub fn jwk(&self) -> serde_json::Value {
+        jwk(&self.key.verifying_key())
+    }
+
+    /// a signed token saying `user` logged in at `now`
+    pub fn mint(&self, user: &str, audience: &str, nonce: &str, session_id: &str, now: SystemTime) -> String {
+        let claims = IdTokenClaims::new(&self.issuer, user, audience, nonce, session_id, now, self.lifetime);
+        let key = self.key.verifying_key();
+        let unsigned = UnsignedIdToken::new(&claims, &key);
+        let signature = self.key.sign(unsigned.signing_input());
+        unsigned.sign_with(&signature, &key).expect("signed with the matching key")
+    }
+}
+
+impl IdTokenClaims {
+    /// the claims of a token saying `user` logged in at `now`, valid for `lifetime`
+    pub fn new(
+        issuer: &str,
+        user: &str,
+        audience: &str,
+        nonce: &str,
+        session_id: &str,
+        now: SystemTime,
+        lifetime: Duration,
+    ) -> Self {
+        let iat = unix_seconds(now);
+        IdTokenClaims {
+            iss: issuer.to_string(),
+            sub: user.to_string(),
+            aud: audience.to_string(),
+            iat,
+            exp: iat + lifetime.as_secs(),
+            nonce: nonce.to_string(),
+            sid: session_id.to_string(),
+        }
+    }
+}
+
+/// A token waiting for its signature, for keys that are only reachable
+/// through a signing API (a cloud KMS) and never held in memory
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnsignedIdToken {
+    signing_input: String, // base64url header "." base64url claims
+}
+
+impl UnsignedIdToken {
+    /// the token for `claims`, to be signed with the private half of `key`
+    pub fn new(claims: &IdTokenClaims, key: &VerifyingKey) -> Self {
+        let header = Header {
+            alg: "EdDSA".to_string(),
+            typ: "JWT".to_string(),
+            kid: key_id(key),
+        };
+        UnsignedIdToken { signing_input: format!("{}.{}", encode_json(&header), encode_json(claims)) }
+    }
+
+    /// the bytes to sign
+    pub fn signing_input(&self) -> &[u8] {
+        self.signing_input.as_bytes()
+    }
+
+    /// the finished token; `signature` has to check out under `key`, so a
+    /// signer using the wrong key is caught here rather than by relying parties
+    pub fn sign_with(self, signature: &Signature, key: &VerifyingKey) -> Result<String, IdTokenError> {
+        key.verify(self.signing_input.as_bytes(), signature).map_err(|_| IdTokenError::BadSignature)?;
+        Ok(format!("{}.{}", self.signing_input, URL_SAFE_NO_PAD.encode(signature.to_bytes())))
+    }
+}
+
+/// check the signature, issuer, audience and expiry of `token`
+pub fn verify(
+    token: &str,
+    key: &VerifyingKey,
+    issuer: &str,
+    audience: &str,
+    now: SystemTime,
+) -> Result<IdTokenClaims, IdTokenError> {
+    let malformed = |reason: &str| IdTokenError::Malformed(reason.to_string());
+    let (signing_input, signature) = token.rsplit_once('.').ok_or_else(|| malformed("expected three parts"))?;
+    let (header, claims) = signing_input.split_once('.').ok_or_else(|| malformed("expected three parts"))?;
+
+    let header: Header = decode_json(header)?;
+    if header.alg != "EdDSA" {
+        return Err(malformed("alg must be EdDSA"));
+    }
+    let signature: [u8; 64] = URL_SAFE_NO_PAD
+        .decode(signature)
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| malformed("signature must be 64 base64url bytes"))?;
+    key.verify(signing_input.as_bytes(), &Signature::from_bytes(&signature))
+        .map_err(|_| IdTokenError::BadSignature)?;
+
+    let claims: IdTokenClaims = decode_json(claims)?;
+    if claims.iss != issuer {
+        return Err(IdTokenError::WrongIssuer);
+    }
+    if claims.aud != audience {
+        return Err(IdTokenError::WrongAudience);
+    }
+    if unix_seconds(now) >= claims.exp {
+        return Err(IdTokenError::Expired);
+    }
+    Ok(claims)
+}
+
+/// the `iss` of `token`, unchecked: only to pick the key to [`verify`] it with
+pub fn issuer_of(token: &str) -> Option<String> {
+    let claims = token.split('.').nth(1)?;
+    decode_json::<IdTokenClaims>(claims).ok().map(|claims| claims.iss)
+}
+
+/// `key` as an OKP JSON Web Key (RFC 8037), with its thumbprint as `kid`
+pub fn jwk(key: &VerifyingKey) -> serde_json::Value {
+    serde_json::json!({
+        "kty": "OKP",
+        "crv": "Ed25519",
+        "x": URL_SAFE_NO_PAD.encode(key.as_bytes()),
+        "use": "sig",
+        "alg": "EdDSA",
+        "kid": key_id(key),
+    })
+}
+
+/// the RFC 7638 thumbprint of `key`, used as the `kid` of its tokens
+pub fn key_id(key: &VerifyingKey) -> String {
+    // members in lexicographic order, no whitespace
+    let canonical = format!(r#"{{"crv":"Ed25519","kty":"OKP","x":"{}"}}"#, URL_SAFE_NO_PAD.encode(key.as_bytes()));
+    URL_SAFE_NO_PAD.encode(Sha256::digest(canonical.as_bytes()))
+}
+
+fn encode_json(value: &impl Serialize) -> String {
+    URL_SAFE_NO_PAD.encode(serde_json::to_vec(value).expect("claims serialize"))
+}
+
+fn decode_json<T: for<'de> Deserialize<'de>>(part: &str) -> Result<T, IdTokenError> {
+    let bytes = URL_SAFE_NO_PAD
+        .decode(part)
+        .map_err(|e| IdTokenError::Malformed(e.to_string()))?;
+    serde_json::from_slice(&bytes).map_err(|e| IdTokenError::Malformed(e.to_string()))
+}
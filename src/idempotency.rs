@@ -0,0 +1,121 @@
+//! Idempotency keys for retried requests.
+//!
+//! A client that times out waiting for `Register` can't know whether the
+//! server got the request, so it sends the retry with the same key. The server
+//! remembers which request each key completed for a while and answers a replay
+//! of it with success instead of `AlreadyExists`.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+use crate::clock::Time;
+use crate::memory::{HeapSize, StoreUsage, MAP_ENTRY_OVERHEAD};
+
+/// How long a key is remembered by default
+pub const DEFAULT_TTL: Duration = Duration::from_secs(10 * 60);
+
+/// What a key says about an incoming request
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Replay {
+    /// key not seen (or expired): process the request normally
+    New,
+    /// the same request already completed under this key
+    Completed,
+    /// the key was used for a different request: a client bug
+    Mismatch,
+}
+
+/// Completed requests by idempotency key, forgotten after `ttl`
+#[derive(Debug)]
+pub struct IdempotencyCache<R> {
+    ttl: Duration,
+    time: Time,
+    completed: Mutex<HashMap<String, (SystemTime, R)>>,
+}
+
+impl<R> Default for IdempotencyCache<R> {
+    fn default() -> Self {
+        Self::new(DEFAULT_TTL)
+    }
+}
+
+impl<R> IdempotencyCache<R> {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            time: Time::default(),
+            completed: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// read the time from `time` instead of the system clock
+    pub fn with_time(self, time: Time) -> Self {
+        Self { time, ..self }
+    }
+
+    pub fn ttl(&self) -> Duration {
+        self.ttl
+    }
+
+    /// drop the keys past their ttl; how many
+    pub fn expire(&self) -> usize {
+        let mut completed = self.completed.lock().unwrap();
+        let before = completed.len();
+        completed.retain(|_, (at, _)| self.time.since(*at) < self.ttl);
+        before - completed.len()
+    }
+}
+
+impl<R: PartialEq> IdempotencyCache<R> {
+    /// look up a key before processing `request`
+    pub fn check(&self, key: &str, request: &R) -> Replay {
+        self.check_with(key, |done| done == request)
+    }
+
+    /// look up a key, with `same` deciding whether the request it completed
+    /// is the one being retried, e.g. when the server filled in part of it
+    pub fn check_with(&self, key: &str, same: impl FnOnce(&R) -> bool) -> Replay {
+        let completed = self.completed.lock().unwrap();
+        match completed.get(key) {
+            Some((at, _)) if self.time.since(*at) >= self.ttl => Replay::New,
+            Some((_, done)) if same(done) => Replay::Completed,
+            Some(_) => Replay::Mismatch,
+            None => Replay::New,
+        }
+    }
+
+    /// remember that `request` completed under `key`, dropping expired keys
+    pub fn record(&self, key: &str, request: R) {
+        self.expire();
+        self.completed.lock().unwrap().insert(key.to_string(), (self.time.now(), request));
+    }
+
+    /// forget the keys whose request matches, e.g. everything about an erased user
+    pub fn forget(&self, matches: impl Fn(&R) -> bool) {
+        self.completed.lock().unwrap().retain(|_, (_, request)| !matches(request));
+    }
+
+    /// number of keys currently remembered (expired ones included until the next record)
+    pub fn len(&self) -> usize {
+        self.completed.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<R: HeapSize> IdempotencyCache<R> {
+    /// keys remembered and roughly the bytes they take
+    pub fn usage(&self) -> StoreUsage {
+        let completed = self.completed.lock().unwrap();
+        StoreUsage {
+            entries: completed.len(),
+            bytes: completed
+                .iter()
+                .map(|(key, (_, request))| key.total_size() + request.total_size() + MAP_ENTRY_OVERHEAD)
+                .sum(),
+        }
+    }
+}
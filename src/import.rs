@@ -0,0 +1,126 @@
+//! Users to provision in bulk, read from CSV or JSON.
+//!
+//! Moving an existing user base onto the server means registering (y1, y2)
+//! pairs that were computed elsewhere, by a migration tool or by the users'
+//! clients. `zkp-auth admin import-users` reads them with [`parse`] and
+//! streams them to the Admin service's `BulkRegister`. Numbers are hex in
+//! both formats:
+//!
+//! - CSV: `user,y1,y2`, optionally followed by the Argon2id
+//!   `salt,memory_kib,iterations,parallelism` the passwords were derived with;
+//!   a `user,...` header, blank lines and `#` comments are skipped
+//! - JSON: an array of registrations, or one per line, in the user store's
+//!   format: `{"user": "alice", "y1": "..", "y2": "..", "kdf": {..}, "beta": ".."}`
+
+use std::fmt;
+use std::path::Path;
+
+use crate::api::v1::{KdfParams, RegisterRequest};
+use crate::store::StoredUser;
+
+/// A line of the file that couldn't be read
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImportError {
+    pub line: usize, // 0 for an entry of a JSON array, the message says which
+    pub message: String,
+}
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.line {
+            0 => f.write_str(&self.message),
+            line => write!(f, "line {}: {}", line, self.message),
+        }
+    }
+}
+
+impl std::error::Error for ImportError {}
+
+/// read `text` as CSV when `path` ends in .csv, as JSON otherwise
+pub fn parse(path: &Path, text: &str) -> Result<Vec<RegisterRequest>, ImportError> {
+    match path.extension().and_then(|extension| extension.to_str()) {
+        Some(extension) if extension.eq_ignore_ascii_case("csv") => parse_csv(text),
+        _ => parse_json(text),
+    }
+}
+
+pub fn parse_csv(text: &str) -> Result<Vec<RegisterRequest>, ImportError> {
+    let mut users = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || (index == 0 && line.starts_with("user,")) {
+            continue;
+        }
+        let user = parse_csv_line(line).map_err(|message| ImportError { line: index + 1, message })?;
+        users.push(user);
+    }
+    Ok(users)
+}
+
+fn parse_csv_line(line: &str) -> Result<RegisterRequest, String> {
+    let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+    let (user, y1, y2, kdf) = match fields.as_slice() {
+        [user, y1, y2] => (user, y1, y2, None),
+        [user, y1, y2, salt, memory_kib, iterations, parallelism] => {
+            let number = |name: &str, field: &str| field.parse::<u32>().map_err(|_| format!("{} is not a number", name));
+            let kdf = KdfParams {
+                salt: hex_field("salt", salt)?,
+                memory_kib: number("memory_kib", memory_kib)?,
+                iterations: number("iterations", iterations)?,
+                parallelism: number("parallelism", parallelism)?,
+            };
+            (user, y1, y2, Some(kdf))
+        }
+        _ => return Err(format!("expected 3 or 7 fields, found {}", fields.len())),
+    };
+    if user.is_empty() {
+        return Err("the user is empty".to_string());
+    }
+    Ok(RegisterRequest {
+        user: user.to_string(),
+        y1: hex_field("y1", y1)?,
+        y2: hex_field("y2", y2)?,
+        kdf,
+        ..Default::default()
+    })
+}
+
+fn hex_field(name: &str, field: &str) -> Result<Vec<u8>, String> {
+    hex::decode(field).map_err(|e| format!("{} is not hex: {}", name, e))
+}
+
+pub fn parse_json(text: &str) -> Result<Vec<RegisterRequest>, ImportError> {
+    // (line, entry) of each registration, for the errors
+    let stored: Vec<((usize, usize), StoredUser)> = match text.trim_start().starts_with('[') {
+        true => serde_json::from_str::<Vec<StoredUser>>(text)
+            .map_err(|e| ImportError { line: e.line(), message: e.to_string() })?
+            .into_iter()
+            .enumerate()
+            .map(|(index, user)| ((0, index + 1), user))
+            .collect(),
+        false => text
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(index, line)| {
+                serde_json::from_str(line)
+                    .map(|user| ((index + 1, 0), user))
+                    .map_err(|e| ImportError { line: index + 1, message: e.to_string() })
+            })
+            .collect::<Result<_, _>>()?,
+    };
+
+    let mut users = Vec::with_capacity(stored.len());
+    for ((line, entry), user) in stored {
+        if !user.is_valid() || user.user.is_empty() {
+            let mut message = format!("registration of {:?} has an empty user or a field that isn't hex", user.user);
+            if entry > 0 {
+                message = format!("entry {}: {}", entry, message);
+            }
+            return Err(ImportError { line, message });
+        }
+        // a journal's idempotency keys and timestamps mean nothing to another server
+        users.push(RegisterRequest { idempotency_key: String::new(), ..RegisterRequest::from(&user) });
+    }
+    Ok(users)
+}
@@ -0,0 +1,130 @@
+//! Protecting web routes with ZKP sessions (the `axum` feature).
+//!
+//! A web app behind this server doesn't need to speak the protocol: the
+//! browser or app logs in with `zkp-auth` (or the gRPC API) and sends the
+//! session id as `Authorization: Bearer <session_id>`. [`require_session`] is
+//! an axum middleware that checks the token with `IntrospectSession` and puts
+//! a [`ZkpUser`] into the request extensions, or answers 401 on its own, with
+//! a problem+json body (see http_status.rs):
+//!
+//! ```ignore
+//! let validator = SessionValidator::new(Channel::from_static("http://127.0.0.1:50051").connect_lazy())
+//!     .admin_token(AdminToken::load(Path::new("/etc/zkp/admin-token"))?);
+//! let app = Router::new()
+//!     .route("/me", get(|Extension(user): Extension<ZkpUser>| async move { user.username }))
+//!     .route_layer(middleware::from_fn_with_state(validator, require_session));
+//! ```
+//!
+//! The server only answers introspection calls that carry its admin token
+//! (`--admin-token-file`), so the validator needs it too. Every request costs
+//! one introspection call; routes that need a recent
+//! proof use [`SessionValidator::require_elevated`] and answer 403 for
+//! standard sessions. When the auth server can't be asked the answer is 503,
+//! with Retry-After; [`SessionValidator::status_mapping`] changes the codes.
+
+use axum::extract::State;
+use axum::http::{header, HeaderMap, Request, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use tonic::transport::Channel;
+use tonic::{Request, Status};
+
+use crate::admin::AdminToken;
+use crate::api::v1::{auth_client::AuthClient, IntrospectSessionRequest, SessionTier};
+use crate::http_status::StatusMapping;
+
+/// Who made a request, as found by [`require_session`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ZkpUser {
+    pub username: String,
+    pub session_id: String,
+    pub elevated: bool, // proved the password recently, see ElevateSession
+}
+
+/// Checks session tokens against the auth server
+#[derive(Debug, Clone)]
+pub struct SessionValidator {
+    client: AuthClient<Channel>,
+    require_elevated: bool,
+    errors: StatusMapping, // how refusals are answered
+    admin_token: Option<AdminToken>, // introspection calls carry it
+}
+
+impl SessionValidator {
+    pub fn new(channel: Channel) -> Self {
+        SessionValidator { client: AuthClient::new(channel), require_elevated: false, errors: StatusMapping::default(), admin_token: None }
+    }
+
+    /// the server's admin token, which it wants on every introspection call
+    pub fn admin_token(mut self, token: AdminToken) -> Self {
+        self.admin_token = Some(token);
+        self
+    }
+
+    /// only let elevated sessions through, standard ones get 403
+    pub fn require_elevated(mut self) -> Self {
+        self.require_elevated = true;
+        self
+    }
+
+    /// answer refusals with these HTTP statuses
+    pub fn status_mapping(mut self, errors: StatusMapping) -> Self {
+        self.errors = errors;
+        self
+    }
+
+    /// the user behind `token`, or the status to answer with
+    pub async fn validate(&self, token: &str) -> Result<ZkpUser, StatusCode> {
+        self.check(token).await.map_err(|status| self.errors.http_status(status.code()))
+    }
+
+    /// the user behind `token`, or why not as a gRPC status
+    pub async fn check(&self, token: &str) -> Result<ZkpUser, Status> {
+        if token.is_empty() {
+            return Err(Status::unauthenticated("send a session id as Authorization: Bearer <session_id>"));
+        }
+        let mut request = Request::new(IntrospectSessionRequest { token: token.to_string() });
+        if let Some(admin_token) = &self.admin_token {
+            request = admin_token.authorize(request);
+        }
+        let introspection = self
+            .client
+            .clone()
+            .introspect_session(request)
+            .await
+            // whatever went wrong, it's the auth server and not the client
+            .map_err(|status| Status::unavailable(format!("the auth server couldn't check the session: {}", status.message())))?
+            .into_inner();
+        if !introspection.active {
+            return Err(Status::unauthenticated("the session isn't active, log in again"));
+        }
+        let elevated = introspection.tier() == SessionTier::Elevated;
+        if self.require_elevated && !elevated {
+            return Err(Status::permission_denied("this needs an elevated session, prove the password again"));
+        }
+        Ok(ZkpUser { username: introspection.username, session_id: token.to_string(), elevated })
+    }
+}
+
+/// axum middleware: validate the bearer token and hand the handler a [`ZkpUser`]
+pub async fn require_session<B>(
+    State(validator): State<SessionValidator>,
+    mut request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let token = bearer_token(request.headers()).unwrap_or_default().to_string();
+    match validator.check(&token).await {
+        Ok(user) => {
+            request.extensions_mut().insert(user);
+            next.run(request).await
+        }
+        Err(status) => validator.errors.response(&status).into_response(),
+    }
+}
+
+/// the token of an `Authorization: Bearer <token>` header
+pub fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    let value = headers.get(header::AUTHORIZATION)?.to_str().ok()?;
+    let (scheme, token) = value.split_once(' ')?;
+    scheme.eq_ignore_ascii_case("bearer").then(|| token.trim())
+}
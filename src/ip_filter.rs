@@ -0,0 +1,287 @@
+//! Who may call which RPC, by network and by country.
+//!
+//! A deployment restricted to a corporate network or to some regions lists
+//! where requests may come from, and where they may not. A rule is
+//! `[RPC=]SOURCE[,SOURCE...]`: the sources are networks in CIDR notation
+//! (`10.0.0.0/8`, `2001:db8::/32`, or a single address) or countries
+//! (`country:DE`, looked up in a MaxMind database, see geoip.rs), and the RPC
+//! is `Service/Method`, `Service/*` or just `Method`, e.g. `Admin/*`
+//! (without one the rule is for every RPC). A request is refused when a deny
+//! rule for its RPC matches, or when there are allow rules for its RPC and
+//! none of them does.
+//!
+//! [`IpFilterLayer`] goes in front of every gRPC service and answers
+//! `PermissionDenied` before a handler runs.
+
+use std::fmt;
+use std::future::Future;
+use std::net::{IpAddr, SocketAddr};
+use std::pin::Pin;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use tonic::body::BoxBody;
+use tonic::codegen::http;
+use tonic::transport::server::TcpConnectInfo;
+use tonic::Status;
+use tower::{Layer, Service};
+
+use crate::geoip::GeoIp;
+
+/// A network: an address and how many of its leading bits count
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cidr {
+    pub network: IpAddr,
+    pub prefix: u8,
+}
+
+impl Cidr {
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        // an IPv4 peer on a dual-stack socket shows up as ::ffff:a.b.c.d
+        let (network, ip, bits) = match (self.network, ip, ip.to_canonical()) {
+            (IpAddr::V4(network), _, IpAddr::V4(ip)) => (u32::from(network) as u128, u32::from(ip) as u128, 32),
+            (IpAddr::V6(network), IpAddr::V6(ip), _) => (u128::from(network), u128::from(ip), 128),
+            _ => return false,
+        };
+        let mask = match self.prefix {
+            0 => 0,
+            prefix => u128::MAX << (bits - prefix as u32),
+        };
+        (network ^ ip) & mask == 0
+    }
+}
+
+impl FromStr for Cidr {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (address, prefix) = s.split_once('/').map_or((s, None), |(address, prefix)| (address, Some(prefix)));
+        let network = address.parse::<IpAddr>().map_err(|_| format!("{:?} isn't an IP address", address))?;
+        let bits = match network {
+            IpAddr::V4(_) => 32,
+            IpAddr::V6(_) => 128,
+        };
+        let prefix = match prefix {
+            None => bits,
+            Some(prefix) => prefix.parse::<u8>().ok().filter(|&prefix| prefix <= bits).ok_or(format!("bad prefix length in {:?}", s))?,
+        };
+        // 10.1.2.3/8 is likely a typo for 10.0.0.0/8 or 10.1.2.3/32
+        if host_bits_set(network, prefix) {
+            return Err(format!("{} has bits set past the prefix", s));
+        }
+        Ok(Cidr { network, prefix })
+    }
+}
+
+impl fmt::Display for Cidr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.network, self.prefix)
+    }
+}
+
+fn host_bits_set(network: IpAddr, prefix: u8) -> bool {
+    let (value, bits) = match network {
+        IpAddr::V4(v4) => (u32::from(v4) as u128, 32),
+        IpAddr::V6(v6) => (u128::from(v6), 128),
+    };
+    match bits - prefix as u32 {
+        0 => false,
+        128 => value != 0,
+        host => value & ((1u128 << host) - 1) != 0,
+    }
+}
+
+/// Where a request may come from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Source {
+    Network(Cidr),
+    Country(String), // ISO 3166 code, upper case
+}
+
+impl FromStr for Source {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.strip_prefix("country:") {
+            Some(code) if code.len() == 2 && code.chars().all(|c| c.is_ascii_alphabetic()) => {
+                Ok(Source::Country(code.to_ascii_uppercase()))
+            }
+            Some(code) => Err(format!("{:?} isn't a two-letter country code", code)),
+            None => s.parse().map(Source::Network),
+        }
+    }
+}
+
+/// Sources, for every RPC or for some of them
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rule {
+    pub rpc: Option<String>, // Service/Method, Service/* or Method; None for every RPC
+    pub sources: Vec<Source>,
+    text: String, // as configured, for messages
+}
+
+impl Rule {
+    /// whether the rule is about an RPC (`Service/Method`)
+    pub fn applies_to(&self, rpc: &str) -> bool {
+        let Some(pattern) = &self.rpc else {
+            return true;
+        };
+        let (service, method) = rpc.split_once('/').unwrap_or(("", rpc));
+        match pattern.split_once('/') {
+            Some((pattern_service, "*")) => pattern_service == service,
+            Some(_) => pattern == rpc,
+            None => pattern == method,
+        }
+    }
+
+    fn matches(&self, ip: IpAddr, country: Option<&str>) -> bool {
+        self.sources.iter().any(|source| match source {
+            Source::Network(cidr) => cidr.contains(ip),
+            Source::Country(code) => country == Some(code.as_str()),
+        })
+    }
+
+    fn has_countries(&self) -> bool {
+        self.sources.iter().any(|source| matches!(source, Source::Country(_)))
+    }
+}
+
+impl FromStr for Rule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (rpc, sources) = match s.split_once('=') {
+            Some((rpc, sources)) if !rpc.trim().is_empty() => (Some(rpc.trim().to_string()), sources),
+            Some(_) => return Err(format!("{:?} has no RPC before the =", s)),
+            None => (None, s),
+        };
+        let sources = sources.split(',').map(|source| source.trim().parse()).collect::<Result<Vec<Source>, _>>()?;
+        Ok(Rule { rpc, sources, text: s.to_string() })
+    }
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+/// A request that was refused
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Blocked {
+    pub rpc: String, // Service/Method
+    pub source: Option<SocketAddr>, // None when the peer's address isn't known
+    pub country: Option<String>,
+    pub reason: String,
+}
+
+/// Allow and deny rules, and the database countries are looked up in
+#[derive(Debug, Default)]
+pub struct IpFilter {
+    pub allow: Vec<Rule>,
+    pub deny: Vec<Rule>,
+    pub geoip: Option<GeoIp>,
+}
+
+impl IpFilter {
+    /// whether there are any rules at all
+    pub fn is_empty(&self) -> bool {
+        self.allow.is_empty() && self.deny.is_empty()
+    }
+
+    /// whether some rule names a country, which needs a database
+    pub fn needs_geoip(&self) -> bool {
+        self.allow.iter().chain(&self.deny).any(Rule::has_countries)
+    }
+
+    /// whether a request from `source` may call `rpc` (`Service/Method`)
+    pub fn check(&self, rpc: &str, source: Option<SocketAddr>) -> Result<(), Blocked> {
+        let deny: Vec<&Rule> = self.deny.iter().filter(|rule| rule.applies_to(rpc)).collect();
+        let allow: Vec<&Rule> = self.allow.iter().filter(|rule| rule.applies_to(rpc)).collect();
+        if deny.is_empty() && allow.is_empty() {
+            return Ok(());
+        }
+        let blocked = |country: Option<String>, reason: String| Blocked { rpc: rpc.to_string(), source, country, reason };
+        let Some(ip) = source.map(|addr| addr.ip()) else {
+            return Err(blocked(None, "the peer's address isn't known".to_string()));
+        };
+        let country = match (deny.iter().chain(&allow).any(|rule| rule.has_countries()), &self.geoip) {
+            (true, Some(geoip)) => geoip.country(ip),
+            _ => None,
+        };
+        if let Some(rule) = deny.iter().find(|rule| rule.matches(ip, country.as_deref())) {
+            return Err(blocked(country, format!("denied by {}", rule)));
+        }
+        if !allow.is_empty() && !allow.iter().any(|rule| rule.matches(ip, country.as_deref())) {
+            return Err(blocked(country, "not on the allow list".to_string()));
+        }
+        Ok(())
+    }
+}
+
+/// `Service/Method` of a gRPC path, e.g. Auth/Register for /zkp_auth.v1.Auth/Register
+pub fn rpc_of(path: &str) -> String {
+    let (service, method) = path.trim_start_matches('/').split_once('/').unwrap_or(("", path));
+    format!("{}/{}", service.rsplit('.').next().unwrap_or_default(), method)
+}
+
+/// Called with every refused request, and its headers
+pub type OnBlocked = Arc<dyn Fn(&Blocked, &http::HeaderMap) + Send + Sync>;
+
+/// Answers `PermissionDenied` to requests an [`IpFilter`] refuses
+#[derive(Clone)]
+pub struct IpFilterLayer {
+    filter: Arc<IpFilter>,
+    on_blocked: OnBlocked,
+}
+
+impl IpFilterLayer {
+    pub fn new(filter: IpFilter, on_blocked: OnBlocked) -> Self {
+        IpFilterLayer { filter: Arc::new(filter), on_blocked }
+    }
+}
+
+impl<S> Layer<S> for IpFilterLayer {
+    type Service = IpFiltered<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        IpFiltered { inner, layer: self.clone() }
+    }
+}
+
+/// See [`IpFilterLayer`]
+#[derive(Clone)]
+pub struct IpFiltered<S> {
+    inner: S,
+    layer: IpFilterLayer,
+}
+
+impl<S, B> Service<http::Request<B>> for IpFiltered<S>
+where
+    S: Service<http::Request<B>, Response = http::Response<BoxBody>>,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<S::Response, S::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: http::Request<B>) -> Self::Future {
+        if self.layer.filter.is_empty() {
+            return Box::pin(self.inner.call(request));
+        }
+        let rpc = rpc_of(request.uri().path());
+        let source = request.extensions().get::<TcpConnectInfo>().and_then(|info| info.remote_addr());
+        match self.layer.filter.check(&rpc, source) {
+            Ok(()) => Box::pin(self.inner.call(request)),
+            Err(blocked) => {
+                (self.layer.on_blocked)(&blocked, request.headers());
+                Box::pin(async { Ok(Status::permission_denied("requests from your network aren't allowed").to_http()) })
+            }
+        }
+    }
+}
@@ -0,0 +1,364 @@
+//! A Kafka producer for audit events ([`crate::publish`]).
+//!
+//! Just enough of the Kafka protocol to produce: a Metadata request (v4) to
+//! the bootstrap brokers for the topic's partitions and their leaders, then
+//! one Produce request (v3, acks=all) per leader carrying a record batch
+//! (magic 2) per partition. Events are keyed by user, and a user's events all
+//! go to the partition picked by CRC-32C of the key, so they stay in order.
+//! Each record has a `content-type: application/cloudevents+json` header.
+//! Plain TCP only, without SASL: put a TLS or auth sidecar in between where
+//! the cluster requires them.
+
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::{Duration, SystemTime};
+
+use crate::clock::unix_millis;
+use crate::publish::{Message, Transport};
+
+/// How long to wait for a broker, and how long it may take to replicate a batch
+const TIMEOUT: Duration = Duration::from_secs(10);
+/// Largest response taken from a broker
+const MAX_RESPONSE: usize = 16 << 20;
+
+const PRODUCE: i16 = 0;
+const METADATA: i16 = 3;
+
+/// Produces to one topic
+#[derive(Debug)]
+pub struct Kafka {
+    bootstrap: Vec<String>, // host:port
+    topic: String,
+    leaders: Vec<i32>,                     // by partition, from the last metadata
+    brokers: HashMap<i32, String>,         // node id to host:port
+    connections: HashMap<i32, TcpStream>,  // by node id
+    correlation_id: i32,
+}
+
+impl Kafka {
+    pub fn new(bootstrap: Vec<String>, topic: &str) -> Self {
+        Kafka {
+            bootstrap,
+            topic: topic.to_string(),
+            leaders: Vec::new(),
+            brokers: HashMap::new(),
+            connections: HashMap::new(),
+            correlation_id: 0,
+        }
+    }
+
+    /// the partition `key` goes to, of `partitions`
+    pub fn partition_of(key: &str, partitions: usize) -> usize {
+        crc32c(key.as_bytes()) as usize % partitions.max(1)
+    }
+
+    /// ask a bootstrap broker for the topic's partitions and their leaders
+    fn refresh_metadata(&mut self) -> io::Result<()> {
+        let mut body = Vec::new();
+        put_i32(&mut body, 1);
+        put_string(&mut body, &self.topic);
+        body.push(1); // allow_auto_topic_creation
+        let mut last_error = io::Error::other("no bootstrap broker");
+        for address in self.bootstrap.clone() {
+            let response = connect(&address).and_then(|mut stream| self.call(&mut stream, METADATA, 4, &body));
+            match response.and_then(|response| parse_metadata(&response, &self.topic)) {
+                Ok((brokers, leaders)) => {
+                    self.brokers = brokers;
+                    self.leaders = leaders;
+                    return Ok(());
+                }
+                Err(e) => last_error = io::Error::new(e.kind(), format!("{}: {}", address, e)),
+            }
+        }
+        Err(last_error)
+    }
+
+    fn produce(&mut self, batch: &[Message]) -> io::Result<()> {
+        if self.leaders.is_empty() {
+            self.refresh_metadata()?;
+        }
+        let now = unix_millis(SystemTime::now()) as i64;
+        let mut by_partition: BTreeMap<usize, Vec<&Message>> = BTreeMap::new();
+        for message in batch {
+            by_partition.entry(Kafka::partition_of(&message.key, self.leaders.len())).or_default().push(message);
+        }
+        let mut by_leader: BTreeMap<i32, Vec<(usize, Vec<u8>)>> = BTreeMap::new();
+        for (partition, messages) in by_partition {
+            by_leader.entry(self.leaders[partition]).or_default().push((partition, record_batch(&messages, now)));
+        }
+
+        for (leader, partitions) in by_leader {
+            let mut body = Vec::new();
+            put_i16(&mut body, -1); // no transactional id
+            put_i16(&mut body, -1); // acks=all
+            put_i32(&mut body, TIMEOUT.as_millis() as i32);
+            put_i32(&mut body, 1);
+            put_string(&mut body, &self.topic);
+            put_i32(&mut body, partitions.len() as i32);
+            for (partition, records) in &partitions {
+                put_i32(&mut body, *partition as i32);
+                put_i32(&mut body, records.len() as i32);
+                body.extend_from_slice(records);
+            }
+            let mut stream = match self.connections.remove(&leader) {
+                Some(stream) => stream,
+                None => {
+                    let address = self.brokers.get(&leader).ok_or_else(|| io::Error::other(format!("no address for broker {}", leader)))?;
+                    connect(address)?
+                }
+            };
+            let response = self.call(&mut stream, PRODUCE, 3, &body)?;
+            self.connections.insert(leader, stream);
+            check_produce(&response)?;
+        }
+        Ok(())
+    }
+
+    /// one request on `stream` and its response, without the correlation id
+    fn call(&mut self, stream: &mut TcpStream, api_key: i16, api_version: i16, body: &[u8]) -> io::Result<Vec<u8>> {
+        self.correlation_id = self.correlation_id.wrapping_add(1);
+        let mut request = Vec::new();
+        put_i16(&mut request, api_key);
+        put_i16(&mut request, api_version);
+        put_i32(&mut request, self.correlation_id);
+        put_string(&mut request, "zkp-auth");
+        request.extend_from_slice(body);
+        let mut framed = (request.len() as i32).to_be_bytes().to_vec();
+        framed.extend_from_slice(&request);
+        stream.write_all(&framed)?;
+
+        let mut size = [0; 4];
+        stream.read_exact(&mut size)?;
+        let size = i32::from_be_bytes(size);
+        if !(4..=MAX_RESPONSE as i32).contains(&size) {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("a response of {} bytes", size)));
+        }
+        let mut response = vec![0; size as usize];
+        stream.read_exact(&mut response)?;
+        if response[..4] != self.correlation_id.to_be_bytes() {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "the response is for another request"));
+        }
+        Ok(response.split_off(4))
+    }
+}
+
+impl Transport for Kafka {
+    fn publish(&mut self, batch: &[Message]) -> io::Result<()> {
+        // a connection the broker dropped while idle shows only now: one more go on a new one
+        let reused = !self.connections.is_empty();
+        let mut result = self.produce(batch);
+        if result.is_err() && reused {
+            self.connections.clear();
+            result = self.produce(batch);
+        }
+        if result.is_err() {
+            // leaders move: ask again next time
+            self.connections.clear();
+            self.leaders.clear();
+        }
+        result
+    }
+}
+
+fn connect(address: &str) -> io::Result<TcpStream> {
+    let socket = address.to_socket_addrs()?.next().ok_or_else(|| io::Error::other(format!("{} has no address", address)))?;
+    let stream = TcpStream::connect_timeout(&socket, TIMEOUT)?;
+    // the broker may take up to TIMEOUT to replicate before answering
+    stream.set_read_timeout(Some(TIMEOUT * 2))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+    Ok(stream)
+}
+
+/// a record batch (magic 2) of `messages`, all stamped `now`
+pub fn record_batch(messages: &[&Message], now: i64) -> Vec<u8> {
+    let mut records = Vec::new();
+    for (offset, message) in messages.iter().enumerate() {
+        let mut record = vec![0]; // attributes
+        put_varint(&mut record, 0); // timestamp delta
+        put_varint(&mut record, offset as i64);
+        match message.key.is_empty() {
+            true => put_varint(&mut record, -1),
+            false => {
+                put_varint(&mut record, message.key.len() as i64);
+                record.extend_from_slice(message.key.as_bytes());
+            }
+        }
+        put_varint(&mut record, message.payload.len() as i64);
+        record.extend_from_slice(message.payload.as_bytes());
+        put_varint(&mut record, 1); // headers
+        for field in ["content-type", "application/cloudevents+json"] {
+            put_varint(&mut record, field.len() as i64);
+            record.extend_from_slice(field.as_bytes());
+        }
+        put_varint(&mut records, record.len() as i64);
+        records.extend_from_slice(&record);
+    }
+
+    // what the CRC covers: attributes to the end
+    let mut checked = Vec::new();
+    put_i16(&mut checked, 0); // attributes: no compression, create time
+    put_i32(&mut checked, messages.len() as i32 - 1); // last offset delta
+    put_i64(&mut checked, now); // base timestamp
+    put_i64(&mut checked, now); // max timestamp
+    put_i64(&mut checked, -1); // producer id
+    put_i16(&mut checked, -1); // producer epoch
+    put_i32(&mut checked, -1); // base sequence
+    put_i32(&mut checked, messages.len() as i32);
+    checked.extend_from_slice(&records);
+
+    let mut batch = Vec::new();
+    put_i64(&mut batch, 0); // base offset, set by the broker
+    put_i32(&mut batch, (4 + 1 + 4 + checked.len()) as i32); // from the leader epoch on
+    put_i32(&mut batch, -1); // partition leader epoch
+    batch.push(2); // magic
+    batch.extend_from_slice(&crc32c(&checked).to_be_bytes());
+    batch.extend_from_slice(&checked);
+    batch
+}
+
+/// CRC-32C (Castagnoli), as record batches are checked with
+pub fn crc32c(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0x82F6_3B78 & (crc & 1).wrapping_neg());
+        }
+    }
+    !crc
+}
+
+/// (node id to host:port, leader by partition) for `topic` from a Metadata v4 response
+fn parse_metadata(response: &[u8], topic: &str) -> io::Result<(HashMap<i32, String>, Vec<i32>)> {
+    let mut reader = Reader(response);
+    reader.i32()?; // throttle time
+    let mut brokers = HashMap::new();
+    for _ in 0..reader.i32()? {
+        let node_id = reader.i32()?;
+        let host = reader.string()?.unwrap_or_default();
+        let port = reader.i32()?;
+        reader.string()?; // rack
+        brokers.insert(node_id, format!("{}:{}", host, port));
+    }
+    reader.string()?; // cluster id
+    reader.i32()?; // controller id
+    for _ in 0..reader.i32()? {
+        let error_code = reader.i16()?;
+        let name = reader.string()?.unwrap_or_default();
+        reader.bytes(1)?; // is internal
+        let mut leaders = BTreeMap::new();
+        for _ in 0..reader.i32()? {
+            let partition_error = reader.i16()?;
+            let index = reader.i32()?;
+            let leader = reader.i32()?;
+            for _ in 0..2 {
+                // replicas, in-sync replicas
+                let nodes = reader.i32()?;
+                reader.bytes(4 * nodes.max(0) as usize)?;
+            }
+            if partition_error == 0 && leader >= 0 {
+                leaders.insert(index, leader);
+            }
+        }
+        if name != topic {
+            continue;
+        }
+        if error_code != 0 {
+            return Err(io::Error::other(format!("topic {}: error {}{}", topic, error_code, error_name(error_code))));
+        }
+        let partitions = leaders.keys().next_back().map_or(0, |last| last + 1) as usize;
+        if partitions == 0 || leaders.len() != partitions {
+            return Err(io::Error::other(format!("topic {}: not every partition has a leader", topic)));
+        }
+        return Ok((brokers, leaders.into_values().collect()));
+    }
+    Err(io::Error::other(format!("topic {} isn't in the metadata", topic)))
+}
+
+/// fail on the first partition that didn't take its batch
+fn check_produce(response: &[u8]) -> io::Result<()> {
+    let mut reader = Reader(response);
+    for _ in 0..reader.i32()? {
+        let topic = reader.string()?.unwrap_or_default();
+        for _ in 0..reader.i32()? {
+            let partition = reader.i32()?;
+            let error_code = reader.i16()?;
+            reader.bytes(16)?; // base offset, log append time
+            if error_code != 0 {
+                return Err(io::Error::other(format!("{}-{}: error {}{}", topic, partition, error_code, error_name(error_code))));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn error_name(code: i16) -> &'static str {
+    match code {
+        3 => " (unknown topic or partition)",
+        5 => " (leader not available)",
+        6 => " (not the leader)",
+        7 => " (request timed out)",
+        10 => " (message too large)",
+        19 | 20 => " (not enough replicas)",
+        29 => " (topic authorization failed)",
+        _ => "",
+    }
+}
+
+fn put_i16(out: &mut Vec<u8>, value: i16) {
+    out.extend_from_slice(&value.to_be_bytes());
+}
+
+fn put_i32(out: &mut Vec<u8>, value: i32) {
+    out.extend_from_slice(&value.to_be_bytes());
+}
+
+fn put_i64(out: &mut Vec<u8>, value: i64) {
+    out.extend_from_slice(&value.to_be_bytes());
+}
+
+fn put_string(out: &mut Vec<u8>, value: &str) {
+    put_i16(out, value.len() as i16);
+    out.extend_from_slice(value.as_bytes());
+}
+
+/// zigzag, then 7 bits at a time, low first
+fn put_varint(out: &mut Vec<u8>, value: i64) {
+    let mut zigzag = ((value << 1) ^ (value >> 63)) as u64;
+    while zigzag >= 0x80 {
+        out.push(zigzag as u8 | 0x80);
+        zigzag >>= 7;
+    }
+    out.push(zigzag as u8);
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, n: usize) -> io::Result<&'a [u8]> {
+        if self.0.len() < n {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "the response is cut short"));
+        }
+        let (bytes, rest) = self.0.split_at(n);
+        self.0 = rest;
+        Ok(bytes)
+    }
+
+    fn i16(&mut self) -> io::Result<i16> {
+        Ok(i16::from_be_bytes(self.bytes(2)?.try_into().expect("2 bytes")))
+    }
+
+    fn i32(&mut self) -> io::Result<i32> {
+        Ok(i32::from_be_bytes(self.bytes(4)?.try_into().expect("4 bytes")))
+    }
+
+    /// a nullable string
+    fn string(&mut self) -> io::Result<Option<String>> {
+        let len = self.i16()?;
+        if len < 0 {
+            return Ok(None);
+        }
+        Ok(Some(String::from_utf8_lossy(self.bytes(len as usize)?).into_owned()))
+    }
+}
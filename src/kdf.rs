@@ -0,0 +1,126 @@
+//! Deriving the secret x from a password with Argon2id.
+//!
+//! The salt and cost parameters are chosen at registration and stored by the
+//! server next to (y1, y2). Any device can ask for them (`GetUserKdfParams`)
+//! and rederive exactly the same x from the password, without the server ever
+//! learning it. Users registered before this existed have no parameters and
+//! keep the old derivation (the password bytes as a number).
+
+use std::fmt;
+
+use argon2::{Algorithm, Argon2, Params, Version};
+use num_bigint::BigUint;
+
+use crate::api::v1;
+use crate::rng;
+
+/// Bytes of Argon2 output reduced mod q (well above the size of q, so the bias is negligible)
+const OUTPUT_LEN: usize = 64;
+
+/// Limits on what we accept from a server, so a malicious one can't make the client
+/// burn gigabytes of memory or hours of CPU
+pub const MAX_MEMORY_KIB: u32 = 1024 * 1024;
+pub const MAX_ITERATIONS: u32 = 64;
+pub const MAX_PARALLELISM: u32 = 16;
+pub const MIN_SALT_LEN: usize = 16;
+
+/// Argon2id salt and cost parameters for one user
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KdfParams {
+    pub salt: Vec<u8>,
+    pub memory_kib: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+}
+
+/// Why a set of parameters can't be used
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KdfError(String);
+
+impl fmt::Display for KdfError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid KDF parameters: {}", self.0)
+    }
+}
+
+impl std::error::Error for KdfError {}
+
+impl KdfParams {
+    /// fresh random salt with the OWASP recommended cost (19 MiB, 2 passes)
+    pub fn generate() -> Self {
+        Self::with_cost(19 * 1024, 2, 1)
+    }
+
+    /// fresh random salt with explicit cost, e.g. cheaper for tests
+    pub fn with_cost(memory_kib: u32, iterations: u32, parallelism: u32) -> Self {
+        let mut salt = vec![0u8; MIN_SALT_LEN];
+        rng::with_rng(|rng| rng.fill_bytes(&mut salt));
+        KdfParams {
+            salt,
+            memory_kib,
+            iterations,
+            parallelism,
+        }
+    }
+
+    /// reject parameters that are too weak to mean anything or too expensive to run
+    pub fn validate(&self) -> Result<(), KdfError> {
+        if self.salt.len() < MIN_SALT_LEN {
+            return Err(KdfError(format!("salt must be at least {} bytes", MIN_SALT_LEN)));
+        }
+        if self.memory_kib > MAX_MEMORY_KIB {
+            return Err(KdfError(format!("memory cost above {} KiB", MAX_MEMORY_KIB)));
+        }
+        if self.iterations == 0 || self.iterations > MAX_ITERATIONS {
+            return Err(KdfError(format!("iterations must be between 1 and {}", MAX_ITERATIONS)));
+        }
+        if self.parallelism == 0 || self.parallelism > MAX_PARALLELISM {
+            return Err(KdfError(format!("parallelism must be between 1 and {}", MAX_PARALLELISM)));
+        }
+        self.argon2().map(|_| ())
+    }
+
+    /// the secret x in [0, q) for this password
+    #[cfg_attr(feature = "profiling", inline(never), tracing::instrument(level = "trace", skip_all))]
+    pub fn derive(&self, password: &str, q: &BigUint) -> Result<BigUint, KdfError> {
+        self.validate()?;
+        let mut output = [0u8; OUTPUT_LEN];
+        self.argon2()?
+            .hash_password_into(password.as_bytes(), &self.salt, &mut output)
+            .map_err(|e| KdfError(e.to_string()))?;
+        Ok(BigUint::from_bytes_be(&output) % q)
+    }
+
+    fn argon2(&self) -> Result<Argon2<'static>, KdfError> {
+        let params = Params::new(self.memory_kib, self.iterations, self.parallelism, Some(OUTPUT_LEN))
+            .map_err(|e| KdfError(e.to_string()))?;
+        Ok(Argon2::new(Algorithm::Argon2id, Version::V0x13, params))
+    }
+}
+
+/// what users registered without parameters use: the password bytes as a number
+pub fn legacy_derive(password: &str) -> BigUint {
+    BigUint::from_bytes_be(password.as_bytes())
+}
+
+impl From<&KdfParams> for v1::KdfParams {
+    fn from(params: &KdfParams) -> Self {
+        v1::KdfParams {
+            salt: params.salt.clone(),
+            memory_kib: params.memory_kib,
+            iterations: params.iterations,
+            parallelism: params.parallelism,
+        }
+    }
+}
+
+impl From<v1::KdfParams> for KdfParams {
+    fn from(params: v1::KdfParams) -> Self {
+        KdfParams {
+            salt: params.salt,
+            memory_kib: params.memory_kib,
+            iterations: params.iterations,
+            parallelism: params.parallelism,
+        }
+    }
+}
@@ -0,0 +1,378 @@
+//! Signing ID tokens with a key that never leaves a cloud KMS.
+//!
+//! With the `kms` feature the server needn't hold the ID token key at all:
+//! it builds each token ([`crate::id_token::UnsignedIdToken`]) and has AWS KMS
+//! or Google Cloud KMS sign it with an Ed25519 key only they hold, through
+//! their sign-only APIs. The public key is fetched once at startup, and every
+//! signature is checked against it before a token goes out. Credentials come
+//! from where the cloud SDKs look: `AWS_ACCESS_KEY_ID`, `AWS_SECRET_ACCESS_KEY`
+//! and `AWS_SESSION_TOKEN` for AWS (requests are signed with SigV4), and a
+//! token file or the GCE metadata server for Google.
+
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use ed25519_dalek::{Signature, VerifyingKey};
+use hyper::{Body, Method, Request};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use tokio_rustls::rustls::ClientConfig;
+
+use crate::clock::{civil_from_days, unix_seconds};
+use crate::http;
+use crate::mac::hmac_sha256;
+
+/// DER SubjectPublicKeyInfo of an Ed25519 key, up to the 32 key bytes (RFC 8410)
+const ED25519_SPKI_PREFIX: [u8; 12] = [0x30, 0x2a, 0x30, 0x05, 0x06, 0x03, 0x2b, 0x65, 0x70, 0x03, 0x21, 0x00];
+
+/// A key in a cloud KMS
+#[derive(Debug, Clone)]
+pub enum KmsKey {
+    /// an AWS KMS key of spec ECC_NIST_EDWARDS25519, by id, ARN or alias
+    Aws {
+        key_id: String,
+        region: String,
+        endpoint: Option<String>, // default https://kms.<region>.amazonaws.com
+        credentials: AwsCredentials,
+    },
+    /// a Google Cloud KMS key version with algorithm EC_SIGN_ED25519:
+    /// projects/../locations/../keyRings/../cryptoKeys/../cryptoKeyVersions/..
+    Gcp {
+        key_version: String,
+        endpoint: Option<String>, // default https://cloudkms.googleapis.com
+        token: GcpToken,
+    },
+}
+
+/// Static AWS credentials, as in AWS_ACCESS_KEY_ID and friends
+#[derive(Clone)]
+pub struct AwsCredentials {
+    pub access_key_id: String,
+    pub secret_access_key: String,
+    pub session_token: Option<String>, // for temporary credentials
+}
+
+// the secret key is a credential, keep it out of logs
+impl fmt::Debug for AwsCredentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AwsCredentials").field("access_key_id", &self.access_key_id).finish()
+    }
+}
+
+impl AwsCredentials {
+    /// AWS_ACCESS_KEY_ID, AWS_SECRET_ACCESS_KEY and AWS_SESSION_TOKEN
+    pub fn from_env() -> Result<Self, KmsError> {
+        let missing = |name: &str| KmsError::Malformed(format!("{} isn't set", name));
+        Ok(AwsCredentials {
+            access_key_id: env("AWS_ACCESS_KEY_ID").ok_or_else(|| missing("AWS_ACCESS_KEY_ID"))?,
+            secret_access_key: env("AWS_SECRET_ACCESS_KEY").ok_or_else(|| missing("AWS_SECRET_ACCESS_KEY"))?,
+            session_token: env("AWS_SESSION_TOKEN"),
+        })
+    }
+}
+
+/// Where Google access tokens come from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GcpToken {
+    /// a file holding the token, re-read for every call (e.g. kept fresh by a sidecar)
+    File(PathBuf),
+    /// the GCE / GKE metadata server (GCE_METADATA_HOST, or metadata.google.internal)
+    Metadata,
+}
+
+/// Why a signature couldn't be had
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KmsError {
+    /// no answer: connection, TLS or timeout
+    Unreachable(String),
+    /// the KMS answered with an error status
+    Refused { status: u16, message: String },
+    /// not an Ed25519 key, or a signature that doesn't verify under it
+    WrongKey(String),
+    Malformed(String),
+}
+
+impl fmt::Display for KmsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KmsError::Unreachable(reason) => write!(f, "the KMS is unreachable: {}", reason),
+            KmsError::Refused { status, message } => write!(f, "the KMS answered {}: {}", status, message),
+            KmsError::WrongKey(reason) => write!(f, "wrong KMS key: {}", reason),
+            KmsError::Malformed(reason) => write!(f, "{}", reason),
+        }
+    }
+}
+
+impl std::error::Error for KmsError {}
+
+/// Signs with a KMS key whose public half was fetched at startup
+pub struct KmsSigner {
+    client: KmsClient,
+    public_key: VerifyingKey,
+}
+
+impl fmt::Debug for KmsSigner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("KmsSigner").field("key", &self.describe()).finish()
+    }
+}
+
+impl KmsSigner {
+    /// fetch `key`'s public half, checking that it is an Ed25519 key we may use
+    pub async fn connect(key: KmsKey) -> Result<Self, KmsError> {
+        let tls = match endpoint(&key).starts_with("https://") {
+            true => Some(http::tls_config(None).map_err(|e| KmsError::Malformed(format!("system roots: {}", e)))?),
+            false => None,
+        };
+        let client = KmsClient { key, tls, access_token: Mutex::new(None) };
+        let public_key = client.public_key().await?;
+        Ok(KmsSigner { client, public_key })
+    }
+
+    pub fn public_key(&self) -> &VerifyingKey {
+        &self.public_key
+    }
+
+    /// which key this is, for logs
+    pub fn describe(&self) -> String {
+        match &self.client.key {
+            KmsKey::Aws { key_id, region, .. } => format!("AWS KMS key {} in {}", key_id, region),
+            KmsKey::Gcp { key_version, .. } => format!("Cloud KMS key {}", key_version),
+        }
+    }
+
+    /// an Ed25519 signature over `message`, checked against the public key
+    pub async fn sign(&self, message: &[u8]) -> Result<Signature, KmsError> {
+        let signature = self.client.sign(message).await?;
+        self.public_key
+            .verify_strict(message, &signature)
+            .map_err(|_| KmsError::WrongKey("the signature doesn't verify under the key's public half".to_string()))?;
+        Ok(signature)
+    }
+}
+
+/// The calls to one KMS key
+struct KmsClient {
+    key: KmsKey,
+    tls: Option<Arc<ClientConfig>>, // for https endpoints
+    access_token: Mutex<Option<(String, Instant)>>, // from the metadata server, until it expires
+}
+
+impl KmsClient {
+    async fn sign(&self, message: &[u8]) -> Result<Signature, KmsError> {
+        let encoded = match &self.key {
+            KmsKey::Aws { key_id, .. } => {
+                let request = json!({
+                    "KeyId": key_id,
+                    "Message": STANDARD.encode(message),
+                    "MessageType": "RAW",
+                    "SigningAlgorithm": "ED25519_SHA_512",
+                });
+                self.aws("TrentService.Sign", request).await?["Signature"].as_str().map(str::to_string)
+            }
+            KmsKey::Gcp { key_version, .. } => {
+                let request = json!({ "data": STANDARD.encode(message) });
+                let path = format!("v1/{}:asymmetricSign", key_version);
+                self.gcp(Method::POST, &path, Some(request)).await?["signature"].as_str().map(str::to_string)
+            }
+        };
+        let bytes = encoded
+            .and_then(|encoded| STANDARD.decode(encoded).ok())
+            .ok_or_else(|| KmsError::Malformed("no signature in the KMS's answer".to_string()))?;
+        <[u8; 64]>::try_from(bytes.as_slice())
+            .map(|bytes| Signature::from_bytes(&bytes))
+            .map_err(|_| KmsError::WrongKey(format!("a {}-byte signature isn't Ed25519", bytes.len())))
+    }
+
+    async fn public_key(&self) -> Result<VerifyingKey, KmsError> {
+        let der = match &self.key {
+            KmsKey::Aws { key_id, .. } => {
+                let response = self.aws("TrentService.GetPublicKey", json!({ "KeyId": key_id })).await?;
+                if response["KeySpec"] != "ECC_NIST_EDWARDS25519" {
+                    return Err(KmsError::WrongKey(format!("{} is a {} key, not ECC_NIST_EDWARDS25519", key_id, response["KeySpec"])));
+                }
+                response["PublicKey"].as_str().and_then(|encoded| STANDARD.decode(encoded).ok())
+            }
+            KmsKey::Gcp { key_version, .. } => {
+                let response = self.gcp(Method::GET, &format!("v1/{}/publicKey", key_version), None).await?;
+                if response["algorithm"] != "EC_SIGN_ED25519" {
+                    return Err(KmsError::WrongKey(format!("{} is {}, not EC_SIGN_ED25519", key_version, response["algorithm"])));
+                }
+                response["pem"].as_str().and_then(pem_body)
+            }
+        };
+        der.as_deref()
+            .and_then(|der| der.strip_prefix(ED25519_SPKI_PREFIX.as_slice()))
+            .and_then(|key| <[u8; 32]>::try_from(key).ok())
+            .and_then(|key| VerifyingKey::from_bytes(&key).ok())
+            .ok_or_else(|| KmsError::WrongKey("the public key isn't an Ed25519 SubjectPublicKeyInfo".to_string()))
+    }
+
+    /// a KMS JSON API call (X-Amz-Target `target`) signed with SigV4
+    async fn aws(&self, target: &str, body: Value) -> Result<Value, KmsError> {
+        let KmsKey::Aws { region, credentials, .. } = &self.key else {
+            unreachable!("only called for AWS keys")
+        };
+        let url = endpoint(&self.key);
+        let uri: hyper::Uri = url.parse().map_err(|e| KmsError::Malformed(format!("{}: {}", url, e)))?;
+        let host = uri.authority().map(|authority| authority.to_string()).unwrap_or_default();
+        let body = body.to_string();
+        let amz_date = amz_date(SystemTime::now());
+
+        let mut headers = vec![
+            ("content-type".to_string(), "application/x-amz-json-1.1".to_string()),
+            ("host".to_string(), host),
+            ("x-amz-date".to_string(), amz_date.clone()),
+            ("x-amz-target".to_string(), target.to_string()),
+        ];
+        if let Some(token) = &credentials.session_token {
+            headers.push(("x-amz-security-token".to_string(), token.clone()));
+        }
+        let authorization = sigv4_authorization("POST", uri.path(), "", &headers, body.as_bytes(), credentials, region, "kms", &amz_date);
+
+        let mut request = Request::builder().method(Method::POST).uri(&url).header("Authorization", authorization);
+        for (name, value) in headers.iter().filter(|(name, _)| name != "host") {
+            request = request.header(name.as_str(), value.as_str());
+        }
+        let request = request.body(Body::from(body)).map_err(|e| KmsError::Malformed(e.to_string()))?;
+        let (status, response) = http::exchange(request, self.tls.as_ref()).await.map_err(KmsError::Unreachable)?;
+        let response: Value = serde_json::from_slice(&response).unwrap_or(Value::Null);
+        if !status.is_success() {
+            let kind = response["__type"].as_str().unwrap_or_default();
+            let message = response["message"].as_str().or(response["Message"].as_str()).unwrap_or_default();
+            let message = format!("{} {}", kind, message).trim().to_string();
+            return Err(KmsError::Refused { status: status.as_u16(), message });
+        }
+        Ok(response)
+    }
+
+    /// a Cloud KMS REST call with a bearer token
+    async fn gcp(&self, method: Method, path: &str, body: Option<Value>) -> Result<Value, KmsError> {
+        let token = self.gcp_access_token().await?;
+        let url = format!("{}/{}", endpoint(&self.key).trim_end_matches('/'), path);
+        let request = Request::builder()
+            .method(method)
+            .uri(&url)
+            .header("Authorization", format!("Bearer {}", token))
+            .header("Content-Type", "application/json")
+            .body(body.map_or_else(Body::empty, |body| Body::from(body.to_string())))
+            .map_err(|e| KmsError::Malformed(e.to_string()))?;
+        let (status, response) = http::exchange(request, self.tls.as_ref()).await.map_err(KmsError::Unreachable)?;
+        let response: Value = serde_json::from_slice(&response).unwrap_or(Value::Null);
+        if !status.is_success() {
+            let message = response["error"]["message"].as_str().unwrap_or_default().to_string();
+            return Err(KmsError::Refused { status: status.as_u16(), message });
+        }
+        Ok(response)
+    }
+
+    async fn gcp_access_token(&self) -> Result<String, KmsError> {
+        let KmsKey::Gcp { token, .. } = &self.key else {
+            unreachable!("only called for Google keys")
+        };
+        match token {
+            GcpToken::File(path) => std::fs::read_to_string(path)
+                .map(|token| token.trim().to_string())
+                .map_err(|e| KmsError::Malformed(format!("could not read the access token {}: {}", path.display(), e))),
+            GcpToken::Metadata => {
+                if let Some((token, until)) = &*self.access_token.lock().unwrap() {
+                    if Instant::now() < *until {
+                        return Ok(token.clone());
+                    }
+                }
+                let host = env("GCE_METADATA_HOST").unwrap_or_else(|| "metadata.google.internal".to_string());
+                let request = Request::builder()
+                    .uri(format!("http://{}/computeMetadata/v1/instance/service-accounts/default/token", host))
+                    .header("Metadata-Flavor", "Google")
+                    .body(Body::empty())
+                    .map_err(|e| KmsError::Malformed(e.to_string()))?;
+                let (status, response) = http::exchange(request, None).await.map_err(KmsError::Unreachable)?;
+                let response: Value = serde_json::from_slice(&response).unwrap_or(Value::Null);
+                let Some(token) = response["access_token"].as_str().filter(|_| status.is_success()) else {
+                    return Err(KmsError::Refused { status: status.as_u16(), message: "no access token from the metadata server".to_string() });
+                };
+                // a minute early, so a token doesn't expire on the way to the KMS
+                let lifetime = Duration::from_secs(response["expires_in"].as_u64().unwrap_or(0).saturating_sub(60));
+                *self.access_token.lock().unwrap() = Some((token.to_string(), Instant::now() + lifetime));
+                Ok(token.to_string())
+            }
+        }
+    }
+}
+
+/// the `Authorization` header of an AWS Signature Version 4 request.
+/// `headers` are (lowercase name, value) and all get signed; `amz_date` is
+/// their x-amz-date (`20150830T123600Z`)
+#[allow(clippy::too_many_arguments)]
+pub fn sigv4_authorization(
+    method: &str,
+    path: &str,
+    query: &str,
+    headers: &[(String, String)],
+    body: &[u8],
+    credentials: &AwsCredentials,
+    region: &str,
+    service: &str,
+    amz_date: &str,
+) -> String {
+    let mut headers: Vec<_> = headers.iter().map(|(name, value)| (name.to_ascii_lowercase(), value.trim())).collect();
+    headers.sort();
+    let canonical_headers: String = headers.iter().map(|(name, value)| format!("{}:{}\n", name, value)).collect();
+    let signed_headers = headers.iter().map(|(name, _)| name.as_str()).collect::<Vec<_>>().join(";");
+    let canonical_request = format!(
+        "{}\n{}\n{}\n{}\n{}\n{}",
+        method,
+        if path.is_empty() { "/" } else { path },
+        query,
+        canonical_headers,
+        signed_headers,
+        hex::encode(Sha256::digest(body))
+    );
+
+    let date = &amz_date[..8];
+    let scope = format!("{}/{}/{}/aws4_request", date, region, service);
+    let string_to_sign = format!(
+        "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+        amz_date,
+        scope,
+        hex::encode(Sha256::digest(canonical_request.as_bytes()))
+    );
+    let key = [date, region, service, "aws4_request"]
+        .iter()
+        .fold(format!("AWS4{}", credentials.secret_access_key).into_bytes(), |key, part| hmac_sha256(&key, part.as_bytes()).to_vec());
+    let signature = hex::encode(hmac_sha256(&key, string_to_sign.as_bytes()));
+    format!(
+        "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+        credentials.access_key_id, scope, signed_headers, signature
+    )
+}
+
+/// `20150830T123600Z`
+pub fn amz_date(time: SystemTime) -> String {
+    let secs = unix_seconds(time);
+    let (year, month, day) = civil_from_days((secs / 86400) as i64);
+    let rest = secs % 86400;
+    format!("{:04}{:02}{:02}T{:02}{:02}{:02}Z", year, month, day, rest / 3600, rest / 60 % 60, rest % 60)
+}
+
+fn endpoint(key: &KmsKey) -> String {
+    match key {
+        KmsKey::Aws { endpoint: Some(endpoint), .. } | KmsKey::Gcp { endpoint: Some(endpoint), .. } => endpoint.clone(),
+        KmsKey::Aws { region, .. } => format!("https://kms.{}.amazonaws.com/", region),
+        KmsKey::Gcp { .. } => "https://cloudkms.googleapis.com".to_string(),
+    }
+}
+
+/// the DER inside a PEM block
+fn pem_body(pem: &str) -> Option<Vec<u8>> {
+    let body: String = pem.lines().filter(|line| !line.starts_with("-----")).collect();
+    STANDARD.decode(body.trim()).ok()
+}
+
+fn env(name: &str) -> Option<String> {
+    std::env::var(name).ok().filter(|value| !value.is_empty())
+}
@@ -0,0 +1,185 @@
+//! One leader among servers that share storage (`--leader-lease`).
+//!
+//! Servers sharing a user store journal, an audit log or a volume otherwise
+//! each run the jobs that rewrite shared files: purging old audit events and
+//! rotating the audit log. Done twice at once they race, and one rewrite can
+//! lose the other's events. With `--leader-lease <PATH>` on that shared
+//! volume the servers elect a leader through a lease file, and only the
+//! leader runs those jobs. Everything a server keeps in its own memory
+//! (pending challenges, sessions, idempotency keys) is still swept by every
+//! server, since nobody else can.
+//!
+//! The lease file holds `{"holder":..,"expires":<unix ms>}`. It is taken by
+//! linking a complete file into place, which fails if one is there already,
+//! so two servers can't both take a free lease. A lease that ran out is
+//! moved aside before it is taken over; whoever moved aside something else
+//! than the expired lease they read puts it back and stays a follower. The
+//! holder renews it every third of its lifetime (`--leader-lease-ttl`). A
+//! leader that fails to renew stops leading at once, and one that can't
+//! renew at all in time has stopped before anyone else can take over. This
+//! relies on the servers' clocks agreeing to well within the lifetime.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, SystemTime};
+
+use serde::{Deserialize, Serialize};
+
+use crate::clock::unix_millis;
+
+/// Default lifetime of a lease
+pub const DEFAULT_TTL: Duration = Duration::from_secs(30);
+
+/// What the lease file says
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Holder {
+    pub holder: String,
+    pub expires: u64, // unix milliseconds
+}
+
+/// A lease file and whether this process holds it
+#[derive(Debug)]
+pub struct Lease {
+    path: PathBuf,
+    id: String,
+    ttl: Duration,
+    held_until: Mutex<Option<u64>>, // unix ms, while we hold it
+}
+
+impl Lease {
+    /// a lease in `path` that this process would hold as `id`, for `ttl` at a time
+    pub fn new(path: &Path, id: &str, ttl: Duration) -> Self {
+        Lease { path: path.to_path_buf(), id: id.to_string(), ttl, held_until: Mutex::new(None) }
+    }
+
+    /// an id for this process: host name, process id and a random suffix
+    pub fn process_id() -> String {
+        let host = crate::audit::hostname();
+        format!("{}-{}-{:08x}", if host.is_empty() { "localhost" } else { &host }, std::process::id(), rand::random::<u32>())
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn ttl(&self) -> Duration {
+        self.ttl
+    }
+
+    /// whether the last `renew` won the lease and it hasn't run out since
+    pub fn is_leader(&self) -> bool {
+        self.held_until.lock().unwrap().is_some_and(|until| unix_millis(SystemTime::now()) < until)
+    }
+
+    /// take the lease if it is free or ran out, extend it if we hold it;
+    /// whether we lead now. An error leaves us a follower
+    pub fn renew(&self, now: SystemTime) -> io::Result<bool> {
+        let result = self.try_renew(now);
+        *self.held_until.lock().unwrap() = match result {
+            Ok(true) => Some(unix_millis(now) + self.ttl.as_millis() as u64),
+            _ => None,
+        };
+        result
+    }
+
+    fn try_renew(&self, now: SystemTime) -> io::Result<bool> {
+        let mine = Holder { holder: self.id.clone(), expires: unix_millis(now) + self.ttl.as_millis() as u64 };
+        let candidate = self.sibling("candidate");
+        fs::write(&candidate, serde_json::to_vec(&mine).expect("a lease always serializes"))?;
+        let taken = self.take(&candidate, now);
+        let _ = fs::remove_file(&candidate);
+        taken
+    }
+
+    /// put `candidate` in place if the lease is ours, free or ran out
+    fn take(&self, candidate: &Path, now: SystemTime) -> io::Result<bool> {
+        match fs::hard_link(candidate, &self.path) {
+            Ok(()) => return Ok(true),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {}
+            Err(e) => return Err(e),
+        }
+        let Some(current) = self.holder()? else {
+            // gone in between, try again next time
+            return Ok(false);
+        };
+        match (current.holder == self.id, current.expires > unix_millis(now)) {
+            (true, true) => {
+                fs::rename(candidate, &self.path)?;
+                Ok(true)
+            }
+            (false, true) => Ok(false),
+            // ours or not, once it ran out someone else may be taking it over
+            (_, false) => self.take_over(candidate, &current),
+        }
+    }
+
+    /// move the expired lease `expired` aside and take its place
+    fn take_over(&self, candidate: &Path, expired: &Holder) -> io::Result<bool> {
+        let aside = self.sibling("expired");
+        match fs::rename(&self.path, &aside) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(false),
+            Err(e) => return Err(e),
+        }
+        let moved = read_holder(&aside)?;
+        if moved.as_ref() != Some(expired) {
+            // someone took it over first: give theirs back
+            let _ = fs::hard_link(&aside, &self.path);
+            let _ = fs::remove_file(&aside);
+            return Ok(false);
+        }
+        let _ = fs::remove_file(&aside);
+        match fs::hard_link(candidate, &self.path) {
+            Ok(()) => Ok(true),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// who holds the lease according to its file, None without one
+    pub fn holder(&self) -> io::Result<Option<Holder>> {
+        read_holder(&self.path)
+    }
+
+    /// give the lease up, if we hold it, so another server can take over at once
+    pub fn release(&self) -> io::Result<()> {
+        *self.held_until.lock().unwrap() = None;
+        match self.holder()? {
+            Some(current) if current.holder == self.id => fs::remove_file(&self.path),
+            _ => Ok(()),
+        }
+    }
+
+    /// `<path>.<id>.<what>`, a file of our own next to the lease
+    fn sibling(&self, what: &str) -> PathBuf {
+        PathBuf::from(format!("{}.{}.{}", self.path.display(), self.id, what))
+    }
+}
+
+fn read_holder(path: &Path) -> io::Result<Option<Holder>> {
+    match fs::read(path) {
+        Ok(bytes) => serde_json::from_slice(&bytes).map(Some).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+static LEASE: OnceLock<Lease> = OnceLock::new();
+
+/// take part in the election for this process, once at startup; false if it already does
+pub fn set(lease: Lease) -> bool {
+    LEASE.set(lease).is_ok()
+}
+
+/// the lease this process takes part in, None without --leader-lease
+pub fn get() -> Option<&'static Lease> {
+    LEASE.get()
+}
+
+/// whether this process should run the jobs only one server may run: true
+/// without --leader-lease, where every server is on its own
+pub fn is_leader() -> bool {
+    LEASE.get().is_none_or(Lease::is_leader)
+}
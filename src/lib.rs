@@ -1,6 +1,177 @@
+//! Chaum-Pedersen zero-knowledge password authentication.
+//!
+//! The stable API is [`ZKP`] and the modules shown in these docs; most users
+//! only need `use rust_zkp_chaum_pedersen::prelude::*`. Modules marked
+//! hidden (`api`, `tls`, `trace`, ...) exist for the bundled `server` and
+//! `zkp-auth` binaries and may change in any release. The gRPC wire format
+//! has its own compatibility rules, see `proto/zkp_auth/v1/zkp_auth.proto`.
+//! `tests/public-api.txt` records the stable surface; a change there is a
+//! semver change.
+
+use std::sync::LazyLock;
+
 use num_bigint::{BigUint,RandBigInt};
 use rand::Rng;
 
+#[cfg(feature = "bls12-381")]
+pub mod bls;
+#[cfg(feature = "hibp")]
+pub mod breach;
+pub mod builder;
+pub mod challenge;
+pub mod clock;
+pub mod delegation;
+pub mod designated;
+pub mod fingerprint;
+pub mod fixed;
+pub mod gate;
+pub mod group;
+pub mod http_status;
+pub mod id_token;
+#[cfg(feature = "axum")]
+pub mod integrations;
+pub mod kdf;
+#[cfg(feature = "p256")]
+pub mod nist;
+pub mod nizk;
+pub mod params;
+pub mod password_policy;
+pub mod policy;
+pub mod pow;
+pub mod revocation;
+#[cfg(feature = "ristretto")]
+pub mod ristretto;
+pub mod sigma;
+#[cfg(feature = "zxcvbn")]
+pub mod strength;
+pub mod types;
+mod mac;
+mod rng;
+mod sync;
+
+// support for the bundled binaries, not part of the stable API
+#[doc(hidden)]
+pub mod admin;
+#[doc(hidden)]
+pub mod api;
+#[doc(hidden)]
+pub mod audit;
+#[doc(hidden)]
+pub mod blocking;
+#[cfg(feature = "captcha")]
+#[doc(hidden)]
+pub mod captcha;
+#[doc(hidden)]
+pub mod catch_panic;
+#[doc(hidden)]
+pub mod challenge_token;
+#[doc(hidden)]
+pub mod compression;
+#[doc(hidden)]
+pub mod config_audit;
+#[doc(hidden)]
+pub mod deadline;
+#[doc(hidden)]
+pub mod device_key;
+#[doc(hidden)]
+pub mod device_login;
+#[doc(hidden)]
+pub mod duplicates;
+#[doc(hidden)]
+pub mod federation;
+#[doc(hidden)]
+pub mod geoip;
+#[cfg(any(feature = "vault", feature = "kms", feature = "webhooks", feature = "captcha", feature = "hibp"))]
+#[doc(hidden)]
+pub mod http;
+#[doc(hidden)]
+pub mod idempotency;
+#[doc(hidden)]
+pub mod import;
+#[doc(hidden)]
+pub mod ip_filter;
+#[cfg(feature = "kafka")]
+#[doc(hidden)]
+pub mod kafka;
+#[cfg(feature = "kms")]
+#[doc(hidden)]
+pub mod kms;
+#[doc(hidden)]
+pub mod lease;
+#[doc(hidden)]
+pub mod memory;
+#[doc(hidden)]
+pub mod metrics;
+#[cfg(feature = "nats")]
+#[doc(hidden)]
+pub mod nats;
+#[doc(hidden)]
+pub mod openapi;
+#[doc(hidden)]
+pub mod outbox;
+#[doc(hidden)]
+pub mod pending;
+#[doc(hidden)]
+pub mod profile;
+#[cfg(feature = "profiling")]
+#[doc(hidden)]
+pub mod profiling;
+#[cfg(any(feature = "kafka", feature = "nats"))]
+#[doc(hidden)]
+pub mod publish;
+#[doc(hidden)]
+pub mod retention;
+#[doc(hidden)]
+pub mod scheduler;
+#[doc(hidden)]
+pub mod selftest;
+#[doc(hidden)]
+pub mod shamir;
+#[doc(hidden)]
+pub mod shard;
+#[cfg(feature = "sled")]
+#[doc(hidden)]
+pub mod sled_store;
+#[doc(hidden)]
+pub mod ssh_cert;
+#[doc(hidden)]
+pub mod store;
+#[doc(hidden)]
+pub mod telemetry;
+#[doc(hidden)]
+pub mod tls;
+#[doc(hidden)]
+pub mod tofu;
+#[doc(hidden)]
+pub mod trace;
+#[doc(hidden)]
+pub mod ulid;
+#[doc(hidden)]
+pub mod user_id;
+#[doc(hidden)]
+pub mod validate;
+#[cfg(feature = "vault")]
+#[doc(hidden)]
+pub mod vault;
+
+/// Everything needed to register, prove and verify
+pub mod prelude {
+    pub use crate::builder::ZKPBuilder;
+    pub use crate::clock::Clock;
+    pub use crate::delegation::Delegation;
+    pub use crate::designated::{DesignatedProof, VerifierKey};
+    pub use crate::fixed::{ZKP1024, ZKP2048};
+    pub use crate::kdf::{KdfError, KdfParams};
+    pub use crate::nizk::{NonInteractiveProof, ValidityWindow};
+    pub use crate::params::{ParametersError, SignedParameters};
+    pub use crate::password_policy::{DefaultPolicy, PasswordPolicy, Weakness};
+    pub use crate::policy::{Decision, LoginContext, PolicyHook};
+    pub use crate::revocation::{RevocationList, RevokedProof};
+    pub use crate::types::{Challenge, Commitment, PublicKey, Response};
+    pub use crate::ZKP;
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct ZKP {
     pub p:BigUint, // Large prime numbers (like 1024 bits)
     pub q:BigUint, // smaller prime number (like 160) bits
@@ -11,19 +182,23 @@ pub struct ZKP {
 impl ZKP {
   /// computing the pair (alpha^exp mod p, beta^exp mod p)  
   /// /// this is used both for registration and during the proof process
+  #[cfg_attr(feature = "profiling", inline(never), tracing::instrument(level = "trace", skip_all))]
   pub fn compute_pair(&self, exp:&BigUint) -> (BigUint, BigUint) {
-    // alpha ^exp mod p
-    let p1 = self.alpha.modpow(exp,&self.p);
-    // Beta^exp mod p
-    let p2 = self.beta.modpow(exp,&self.p);
+    metrics::timed(metrics::Operation::ComputePair, &self.p, || {
+        // alpha ^exp mod p
+        let p1 = self.alpha.modpow(exp,&self.p);
+        // Beta^exp mod p
+        let p2 = self.beta.modpow(exp,&self.p);
 
-    (p1,p2)
+        (p1,p2)
+    })
   }
   /// solves the challenege: s = k -x * x mod q
   /// This is the core of the proof generation
   /// k = random number we chose
   /// c = challenge from the verifier
   /// x = our secret
+  #[cfg_attr(feature = "profiling", inline(never), tracing::instrument(level = "trace", skip_all))]
   pub fn solve(&self,k: &BigUint,c:&BigUint,x:&BigUint) -> BigUint {
     // we need to handle the case where k<c*x
     if *k >= c*x {
@@ -36,51 +211,139 @@ impl ZKP {
   /// verifies a proof by checking two conditions
   /// 1. r1 = alpha ^ s * y1^c mod p
   /// 2. r2 = bets ^ s * y2^c mod p
+  ///
   /// If both are true, the proof is valid!
+  #[cfg_attr(feature = "profiling", inline(never), tracing::instrument(level = "trace", skip_all))]
   pub fn verify(
     &self,
     r1: &BigUint, // first commitment from prover
-    r2: &BigUint,// second commitment from prover
-    y1: &BigUint, // First public key from registration
-    y2:&BigUint, // Second public key from registration
+    r2: &BigUint, // second commitment from prover
+    y1: &BigUint, // first public key from registration
+    y2: &BigUint, // second public key from registration
     c: &BigUint, //challene we sent
     s: &BigUint, // solution from prover
   ) -> bool {
-    // check condition 2: r2 ?= beta ^ s * y2 * c mod p
-    let cond1 = *r1
-        == (&self.alpha.modpow(s,&self.p) * y1.modpow(c,&self.p))
-        .modpow(&BigUint::from(1u32), &self.p);
-
-    // check consition 2: r2?= beta^s * y2^c mod p
-    let cond2 = *r2
-        == (&self.beta.modpow(s,&self.p) * y2.modpow(c,&self.p))
-        .modpow(&BigUint::from(1u32),&self.p);
+    metrics::timed(metrics::Operation::Verify, &self.p, || {
+        // check condition 1: r1 ?= alpha^s * y1^c mod p
+        let cond1 = *r1
+            == (&self.alpha.modpow(s,&self.p) * y1.modpow(c,&self.p))
+            .modpow(&BigUint::from(1u32), &self.p);
 
-    // both condition must be true
-    cond1 && cond2
+        // check condition 2: r2 ?= beta^s * y2^c mod p
+        let cond2 = *r2
+            == (&self.beta.modpow(s,&self.p) * y2.modpow(c,&self.p))
+            .modpow(&BigUint::from(1u32),&self.p);
 
+        // both condition must be true
+        cond1 && cond2
+    })
   }
 
   /// generate a random number below the given bound
   /// this i sused for generating secretc and challenges
   pub fn generate_random_number_below(bound: &BigUint) -> BigUint {
-    let mut rng = rand::thread_rng();
-    rng.gen_biguint_below(bound)
+    rng::with_rng(|rng| rng.gen_biguint_below(bound))
   }
 
  
 
   /// generate a random string for session IDs and auth IDs
    pub fn generate_random_string(size: usize) -> String {
-    rand::thread_rng()
-    .sample_iter(rand::distributions::Alphanumeric)
-    .take(size).map(char::from)
-    .collect()
+    rng::with_rng(|rng| {
+        rng.sample_iter(rand::distributions::Alphanumeric)
+        .take(size).map(char::from)
+        .collect()
+    })
+   }
+
+   /// INSECURE: make every random number from now on come from a generator
+   /// seeded with `seed`, so demo runs and tests are reproducible.
+   /// Never use this for real logins, the seed gives away every nonce k
+   pub fn with_rng(seed: u64) {
+    rng::set_seed(Some(seed));
+   }
+
+   /// go back to the OS CSPRNG (the default)
+   pub fn with_os_rng() {
+    rng::set_seed(None);
+   }
+
+   /// true while the insecure seeded generator is in use
+   pub fn is_rng_seeded() -> bool {
+    rng::is_seeded()
+   }
+
+   /// hash `label` to an element of the subgroup of order q whose discrete log
+   /// nobody knows: expand H(label) mod p and raise it to (p - 1) / q
+   #[cfg_attr(feature = "profiling", inline(never), tracing::instrument(level = "trace", skip_all))]
+   pub fn hash_to_group(&self, label: &[u8]) -> BigUint {
+    self.hash_to_group_with(group::HashId::Sha256, label)
+   }
+
+   /// [`ZKP::hash_to_group`] with H = `hash`
+   pub fn hash_to_group_with(&self, hash: group::HashId, label: &[u8]) -> BigUint {
+    let cofactor = (&self.p - 1u32) / &self.q;
+    let one = BigUint::from(1u32);
+    // 128 extra bits so reducing mod p is practically unbiased
+    let len = (self.p.bits() as usize + 128).div_ceil(8);
+    let mut counter = 0u32;
+    loop {
+        let expanded = match hash {
+            group::HashId::Sha256 => expand_hash::<sha2::Sha256>(label, counter, len),
+            group::HashId::Sha512 => expand_hash::<sha2::Sha512>(label, counter, len),
+        };
+        let h = BigUint::from_bytes_be(&expanded) % &self.p;
+        let g = h.modpow(&cofactor, &self.p);
+        if g > one {
+            return g;
+        }
+        counter += 1;
+    }
+   }
+
+   /// this group with beta replaced by the user's own generator beta_i = H2G(user),
+   /// so an account doesn't depend on nobody knowing log_alpha(beta) of the global beta
+   pub fn for_user(&self, user: &str) -> ZKP {
+    self.for_user_with(group::HashId::Sha256, user)
+   }
+
+   /// [`ZKP::for_user`] with H2G built on `hash`
+   pub fn for_user_with(&self, hash: group::HashId, user: &str) -> ZKP {
+    let mut label = b"user-generator/".to_vec();
+    label.extend_from_slice(user.as_bytes());
+    ZKP {
+        beta: self.hash_to_group_with(hash, &label),
+        ..self.clone()
+    }
+   }
+
+   /// is `n` in the order-q subgroup and not 1? A proof about 0, 1 or a number
+   /// outside it can pass the verification equations without any witness
+   pub(crate) fn is_element(&self, n: &BigUint) -> bool {
+    let one = BigUint::from(1u32);
+    *n > one && *n < self.p && n.modpow(&self.q, &self.p) == one
    }
 
    /// get the standard cryptographic constants
    /// these are from RFC 5114 - real-world tested parameters
+   /// (decoded on the first call, cloned after that)
    pub fn get_constants() -> (BigUint,BigUint,BigUint,BigUint) {
+    RFC5114.clone()
+   }
+
+   /// decode the RFC 5114 groups (and derive their betas) now rather than on
+   /// first use, so the first login doesn't pay for it; how long that took
+   pub fn warm_up() -> std::time::Duration {
+    let started = std::time::Instant::now();
+    LazyLock::force(&RFC5114);
+    fixed::ZKP1024::rfc5114();
+    fixed::ZKP2048::rfc5114();
+    started.elapsed()
+   }
+}
+
+/// The RFC 5114 section 2.1 group as (alpha, beta, p, q), see [`ZKP::get_constants`]
+static RFC5114: LazyLock<(BigUint,BigUint,BigUint,BigUint)> = LazyLock::new(|| {
     // This is a 1024-bit prime from RFC 5114
     let p = BigUint::from_bytes_be(&hex::decode("B10B8F96A080E01DDE92DE5EAE5D54EC52C99FBCFB06A3C69A6A9DCA52D23B616073E28675A23D189838EF1E2EE652C013ECB4AEA906112324975C3CD49B83BFACCBDD7D90C4BD7098488E9C219A73724EFFD6FAE5644738FAA31A4FF55BCCC0A151AF5F0DC8B4BD45BF37DF365C1A65E68CFDA76D4DA708DF1FB2BC2E4A4371").unwrap());
 
@@ -93,13 +356,26 @@ impl ZKP {
     let alpha = BigUint::from_bytes_be(
             &hex::decode("A4D1CBD5C3FD34126765A442EFB99905F8104DD258AC507FD6406CFF14266D31266FEA1E5C41564B777E690F5504F213160217B4B01B886A5E91547F9E2749F4D7FBD7D3B9A92EE1909D0D2263F80A76A6A24C087A091F531DBF0A0169B6A28AD662A4D18E73AFA32D779D5918D08BC8858F4DCEF97C2A24855E6EEB22B3B2E5").unwrap(),
         );
- // Create another generator by raising alpha to a random power
-        let exp = BigUint::from_bytes_be(&hex::decode("266FEA1E5C41564B777E69").unwrap());
-        let beta = alpha.modpow(&exp, &p);
+    // Create another generator by raising alpha to a fixed power
+    let exp = BigUint::from_bytes_be(&hex::decode("266FEA1E5C41564B777E69").unwrap());
+    let beta = alpha.modpow(&exp, &p);
 
-          (alpha, beta, p, q)
+    (alpha, beta, p, q)
+});
 
-   }
+/// H in counter mode: H(domain | counter | block | label) for block = 0, 1, ...
+fn expand_hash<H: sha2::Digest>(label: &[u8], counter: u32, len: usize) -> Vec<u8> {
+    let mut out = Vec::with_capacity(len + 64);
+    let mut block = 0u32;
+    while out.len() < len {
+        let mut hasher = H::new();
+        hasher.update(b"zkp-auth/hash-to-group/v1");
+        hasher.update(counter.to_be_bytes());
+        hasher.update(block.to_be_bytes());
+        hasher.update(label);
+        out.extend_from_slice(&hasher.finalize());
+        block += 1;
+    }
+    out.truncate(len);
+    out
 }
-
-
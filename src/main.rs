@@ -1,4 +1,1747 @@
-use rust_zkp_chaum_pedersen;
-pub mod lib;
+use std::io::stdin;
+use std::path::{Path, PathBuf};
 
- fn main(){}
\ No newline at end of file
+use clap::{Args, CommandFactory, Parser, Subcommand};
+use clap_complete::Shell;
+use ed25519_dalek::SigningKey;
+use num_bigint::BigUint;
+use qrcode::{render::unicode, QrCode};
+use tonic::transport::{Certificate, Channel, ClientTlsConfig, Endpoint};
+
+mod demo;
+
+use rust_zkp_chaum_pedersen::admin::AdminToken;
+use rust_zkp_chaum_pedersen::api::v1::{
+    admin_client::AdminClient, auth_client::AuthClient, AuthenticationAnswerRequest, AuthenticationAnswerResponse,
+    AuthenticationChallengeRequest, ChallengeCommitmentRequest, ChallengeMode, ClientMetadata, EraseUserRequest,
+    ExportUserDataRequest, LoginRecord, ParametersRequest, ParametersResponse, RegisterRequest, RevocationListRequest,
+    ServerInfoRequest, ServerTimeRequest, SetUserEnabledRequest, UnsealIdTokenKeyRequest, UserKdfParamsRequest,
+    VerifyEmailRequest, ApproveDeviceLoginRequest, DeviceLoginState, FinishDeviceLoginRequest, PollDeviceLoginRequest,
+    StartDeviceLoginRequest, ElevateSessionRequest, EnrollDeviceRequest, ListDevicesRequest, RevokeDeviceRequest,
+    RevokeProofRequest, VerifyDelegationRequest,
+};
+
+use rust_zkp_chaum_pedersen::challenge;
+use rust_zkp_chaum_pedersen::clock;
+use rust_zkp_chaum_pedersen::compression::{self, Compression};
+use rust_zkp_chaum_pedersen::config_audit::{self, Baseline, ConfigSnapshot, ServerSettings, Severity};
+use rust_zkp_chaum_pedersen::deadline::{self, within, with_deadline, CallError, CallTimeouts};
+use rust_zkp_chaum_pedersen::delegation::Delegation;
+use rust_zkp_chaum_pedersen::device_key::DeviceKey;
+use rust_zkp_chaum_pedersen::device_login;
+use rust_zkp_chaum_pedersen::group::{GroupId, HashId};
+use rust_zkp_chaum_pedersen::import;
+use rust_zkp_chaum_pedersen::kdf::{self, KdfParams};
+use rust_zkp_chaum_pedersen::nizk::ValidityWindow;
+use rust_zkp_chaum_pedersen::params::{parse_public_key, SignedParameters};
+use rust_zkp_chaum_pedersen::password_policy::{DefaultPolicy, PasswordPolicy};
+use rust_zkp_chaum_pedersen::pow;
+use rust_zkp_chaum_pedersen::profile::{self, Profile, ProfileStore};
+use rust_zkp_chaum_pedersen::revocation::RevocationList;
+use rust_zkp_chaum_pedersen::shamir;
+#[cfg(feature = "zxcvbn")]
+use rust_zkp_chaum_pedersen::strength::{self, StrengthPolicy};
+#[cfg(feature = "hibp")]
+use rust_zkp_chaum_pedersen::{breach::{self, BreachCheck}, password_policy::Weakness};
+use rust_zkp_chaum_pedersen::store::StoredUser;
+use rust_zkp_chaum_pedersen::tofu::{self, KnownServers, TofuError, Trust};
+use rust_zkp_chaum_pedersen::trace::TraceContext;
+use rust_zkp_chaum_pedersen::types::{Challenge, PublicKey};
+use rust_zkp_chaum_pedersen::ZKP;
+
+/// Unified command line for the ZKP authentication system
+#[derive(Parser)]
+#[command(name = "zkp-auth", version, about)]
+struct Cli {
+    /// INSECURE: seed the random number generator so runs are reproducible (demos/debugging only)
+    #[arg(long, global = true)]
+    seed: Option<u64>,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Manage named server profiles
+    #[command(subcommand)]
+    Profile(ProfileCommand),
+    /// Register a new user on the server
+    Register {
+        #[command(flatten)]
+        target: Target,
+        #[command(flatten)]
+        options: RegisterOptions,
+    },
+    /// Confirm the email address of a new account with the code sent to it
+    VerifyEmail {
+        #[command(flatten)]
+        target: Target,
+        /// The code from the email; leave out to have a new one sent
+        #[arg(long)]
+        code: Option<String>,
+    },
+    /// Log in by proving knowledge of the password
+    Login {
+        #[command(flatten)]
+        target: Target,
+        /// SSH public key (e.g. ~/.ssh/id_ed25519.pub) to get a certificate for; saved as <name>-cert.pub
+        #[arg(long)]
+        ssh_key: Option<PathBuf>,
+        /// Prove this device's key from `device enroll` instead of the password
+        #[arg(long)]
+        device_key: Option<PathBuf>,
+    },
+    /// Give devices keys of their own, so a lost one can be revoked alone
+    #[command(subcommand)]
+    Device(DeviceCommand),
+    /// Let a service act in your name for a while, with only the scopes you give it
+    #[command(subcommand)]
+    Delegation(DelegationCommand),
+    /// Log this device in from a phone: show a QR code and wait for the phone to approve it
+    DeviceLogin(Target),
+    /// Approve a device's QR code login from here, where you are logged in
+    ApproveDevice {
+        #[command(flatten)]
+        target: Target,
+        /// Session ID of the login on this device
+        #[arg(long)]
+        session: String,
+        /// What the QR code says (zkp-auth://device-login?...)
+        qr: String,
+        /// Approve without asking once the device is shown
+        #[arg(long)]
+        yes: bool,
+    },
+    /// Derive (y1, y2) offline for `admin import-users`, without talking to a server
+    Keygen {
+        /// Username the key is for
+        #[arg(long)]
+        user: String,
+        /// File to write the registration to, as one JSON line (files can be concatenated for an import)
+        #[arg(long)]
+        out: PathBuf,
+        /// Argon2id salt (hex) to derive with instead of a fresh random one
+        #[arg(long)]
+        salt: Option<String>,
+        /// Argon2id memory cost in KiB
+        #[arg(long, default_value_t = 19 * 1024)]
+        memory_kib: u32,
+        /// Argon2id passes
+        #[arg(long, default_value_t = 2)]
+        iterations: u32,
+        /// Argon2id lanes
+        #[arg(long, default_value_t = 1)]
+        parallelism: u32,
+        #[command(flatten)]
+        options: RegisterOptions,
+    },
+    /// Fetch the server's signed group parameters and print them as a bundle file
+    Params(Target),
+    /// Compare the local clock with the server's
+    Time(Target),
+    /// Print the server's list of revoked proofs (CRL-style JSON) for offline verifiers
+    Revocations(Target),
+    /// Check the server's and this client's settings against a built-in security baseline
+    AuditConfig {
+        #[command(flatten)]
+        target: Target,
+        /// Exit with status 1 when a finding is at least this severe
+        #[arg(long, value_enum, default_value_t = Severity::High)]
+        fail_on: Severity,
+    },
+    /// Step through registration and login in a terminal UI, showing every number
+    Demo {
+        /// Use the tiny group from the Readme (p = 23) so the numbers fit on screen
+        #[arg(long)]
+        toy: bool,
+        /// Derive the secret from this password instead of picking a random one
+        #[arg(long)]
+        password: Option<String>,
+        /// Start a server on a free port, register and log in against it, print each step and stop it again
+        #[arg(long, conflicts_with = "toy")]
+        all_in_one: bool,
+    },
+    /// Inspect or forget servers remembered by --tofu
+    #[command(subcommand)]
+    KnownServers(KnownServersCommand),
+    /// Administer accounts (needs the server's admin token)
+    #[command(subcommand)]
+    Admin(AdminCommand),
+    /// Print a shell completion script for bash, zsh, fish, ...
+    Completions { shell: Shell },
+    /// Print the man page in roff format
+    Man,
+}
+
+#[derive(Subcommand)]
+enum ProfileCommand {
+    /// Add (or replace) a profile
+    Add {
+        name: String,
+        /// Server endpoint, e.g. http://127.0.0.1:50051
+        #[arg(long)]
+        endpoint: String,
+        /// Default username for this server
+        #[arg(long)]
+        user: Option<String>,
+        /// PEM CA certificate to trust for an https endpoint
+        #[arg(long)]
+        ca_cert: Option<PathBuf>,
+        /// Ed25519 key (hex) the server signs its group parameters with
+        #[arg(long)]
+        server_key: Option<String>,
+        /// Trust the server on first use and refuse if its certificate or key changes
+        #[arg(long)]
+        tofu: bool,
+        /// Compress requests to this server
+        #[arg(long, value_enum)]
+        compression: Option<Compression>,
+    },
+    /// List all profiles, marking the active one
+    List,
+    /// Make a profile the active one
+    Use { name: String },
+    /// Remove a profile
+    Remove { name: String },
+}
+
+#[derive(Subcommand)]
+enum DeviceCommand {
+    /// Enroll this device with a new random key (asks for the password to elevate the session)
+    Enroll {
+        #[command(flatten)]
+        target: Target,
+        /// Session ID of a login on this device
+        #[arg(long)]
+        session: String,
+        /// Name to list the device under, e.g. "work laptop"
+        #[arg(long)]
+        name: String,
+        /// File to keep the device's secret in, for `login --device-key`; must not exist yet
+        #[arg(long)]
+        out: PathBuf,
+    },
+    /// List the enrolled devices
+    List {
+        #[command(flatten)]
+        target: Target,
+        /// Session ID of a login
+        #[arg(long)]
+        session: String,
+    },
+    /// Revoke a device, e.g. a lost one, and end the sessions it logged in
+    Revoke {
+        #[command(flatten)]
+        target: Target,
+        /// Session ID of a login
+        #[arg(long)]
+        session: String,
+        /// Device ID from `device list`
+        device_id: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum DelegationCommand {
+    /// Mint a delegation for a service (asks for the password); anyone holding it can use it until it expires
+    Mint {
+        #[command(flatten)]
+        target: Target,
+        /// Name of the service it is for, as the service presents it
+        #[arg(long)]
+        to: String,
+        /// Scope it grants, e.g. backups:read (repeatable)
+        #[arg(long = "scope", required = true)]
+        scopes: Vec<String>,
+        /// Seconds it is valid for
+        #[arg(long, default_value_t = 3600)]
+        ttl: u64,
+        /// File to write it to instead of printing it; must not exist yet
+        #[arg(long)]
+        out: Option<PathBuf>,
+    },
+    /// Check a delegation as the service it was given to
+    Verify {
+        #[command(flatten)]
+        target: Target,
+        /// Name of the service presenting it
+        #[arg(long = "as")]
+        delegate: String,
+        /// Scope about to be used; leave out to check the delegation only
+        #[arg(long)]
+        scope: Option<String>,
+        /// The delegation, or @file to read it from one
+        delegation: String,
+    },
+    /// Revoke a delegation before it expires
+    Revoke {
+        #[command(flatten)]
+        target: Target,
+        /// Session ID of a login
+        #[arg(long)]
+        session: String,
+        /// Delegation ID printed by `delegation mint`
+        delegation_id: String,
+        /// Why, for the revocation list
+        #[arg(long, default_value = "")]
+        reason: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum KnownServersCommand {
+    /// List remembered servers with their certificate fingerprint and parameter key
+    List,
+    /// Forget a server after it was legitimately re-keyed
+    Forget { endpoint: String },
+}
+
+#[derive(Subcommand)]
+enum AdminCommand {
+    /// Disable an account: no more logins and its sessions end, the account and its history stay
+    Disable {
+        /// Username of the account
+        account: String,
+        /// Why, for the audit trail
+        #[arg(long, default_value = "")]
+        reason: String,
+        #[command(flatten)]
+        admin: AdminTarget,
+    },
+    /// Enable a disabled account again
+    Enable {
+        /// Username of the account
+        account: String,
+        /// Why, for the audit trail
+        #[arg(long, default_value = "")]
+        reason: String,
+        #[command(flatten)]
+        admin: AdminTarget,
+    },
+    /// Print everything the server holds about an account as JSON (data-subject access request)
+    Export {
+        /// Username of the account
+        account: String,
+        #[command(flatten)]
+        admin: AdminTarget,
+    },
+    /// Erase an account and tombstone its audit events (data-subject erasure request); can't be undone
+    Erase {
+        /// Username of the account
+        account: String,
+        /// Why, for the audit trail (leave the name out, it is what's being erased)
+        #[arg(long, default_value = "")]
+        reason: String,
+        /// Really erase it
+        #[arg(long)]
+        yes: bool,
+        #[command(flatten)]
+        admin: AdminTarget,
+    },
+    /// Register users with precomputed (y1, y2) from a .csv or .json file, e.g. to migrate a user base
+    ImportUsers {
+        /// CSV (user,y1,y2 in hex) or JSON (the user store's format), see import.rs
+        file: PathBuf,
+        #[command(flatten)]
+        admin: AdminTarget,
+    },
+    /// Split an ID token signing key into shares for the server's --id-token-key-share (offline)
+    SplitKey {
+        /// Directory to write share-<n>.txt to
+        #[arg(long)]
+        out_dir: PathBuf,
+        /// Shares needed to put the key back together
+        #[arg(long)]
+        threshold: u8,
+        /// Shares to make
+        #[arg(long)]
+        shares: u8,
+        /// Existing key to split (hex, like --params-key) instead of a new one that is never written whole
+        #[arg(long)]
+        key: Option<PathBuf>,
+    },
+    /// Send one share of the ID token signing key to a server waiting for it
+    Unseal {
+        /// File written by split-key
+        share_file: PathBuf,
+        #[command(flatten)]
+        admin: AdminTarget,
+    },
+}
+
+/// Which server an admin command talks to, and the token it proves itself with
+#[derive(Args)]
+struct AdminTarget {
+    /// File with the server's admin token (its --admin-token-file)
+    #[arg(long)]
+    token_file: PathBuf,
+    #[command(flatten)]
+    target: Target,
+}
+
+#[derive(Args)]
+struct RegisterOptions {
+    /// Register even if the password fails the password policy
+    #[arg(long)]
+    allow_weak_password: bool,
+    /// Refuse passwords whose strength estimate (zxcvbn) is below this, 0-4; checked here, never sent
+    #[cfg(feature = "zxcvbn")]
+    #[arg(long, default_value_t = 0, value_parser = clap::value_parser!(u8).range(0..=strength::MAX_SCORE as i64))]
+    min_strength: u8,
+    /// Refuse passwords found in known data breaches (Have I Been Pwned); only 5 hex digits of the password's SHA-1 are sent
+    #[cfg(feature = "hibp")]
+    #[arg(long)]
+    check_breaches: bool,
+    /// Pwned Passwords range API to ask, e.g. a mirror
+    #[cfg(feature = "hibp")]
+    #[arg(long, default_value = breach::RANGE_API, requires = "check_breaches")]
+    breach_api: String,
+    /// Use a generator derived from the username instead of the global beta
+    #[arg(long)]
+    per_user_generator: bool,
+    /// Hash the per-user generator is derived with
+    #[arg(long, value_enum, default_value_t, requires = "per_user_generator")]
+    hash: HashId,
+}
+
+/// Which server and user a register/login talks to
+#[derive(Args)]
+struct Target {
+    /// Profile to use instead of the active one
+    #[arg(long)]
+    profile: Option<String>,
+    /// Endpoint to use, overriding the profile
+    #[arg(long)]
+    endpoint: Option<String>,
+    /// Username to use, overriding the profile
+    #[arg(long)]
+    user: Option<String>,
+    /// PEM CA certificate to trust for an https endpoint, overriding the profile
+    #[arg(long)]
+    ca_cert: Option<PathBuf>,
+    /// Only use group parameters signed by this Ed25519 key (hex), overriding the profile
+    #[arg(long)]
+    server_key: Option<String>,
+    /// Trust the server on first use and refuse if its certificate or key changes
+    #[arg(long)]
+    tofu: bool,
+    /// Compress requests (gzip or zstd), overriding the profile
+    #[arg(long, value_enum)]
+    compression: Option<Compression>,
+    /// Seconds to wait for the server to answer a registration
+    #[arg(long, default_value_t = deadline::REGISTER_TIMEOUT.as_secs())]
+    register_timeout: u64,
+    /// Seconds to wait for the server to hand out a challenge
+    #[arg(long, default_value_t = deadline::CHALLENGE_TIMEOUT.as_secs())]
+    challenge_timeout: u64,
+    /// Seconds to wait for the server to check an answer
+    #[arg(long, default_value_t = deadline::VERIFY_TIMEOUT.as_secs())]
+    verify_timeout: u64,
+}
+
+/// Where to connect, resolved from flags and the profile
+struct Connection {
+    endpoint: String,
+    ca_cert: Option<PathBuf>,
+    server_key: Option<String>,
+    tofu: bool,
+    compression: Compression,
+    timeouts: CallTimeouts,
+}
+
+const DEFAULT_ENDPOINT: &str = "http://127.0.0.1:50051";
+
+#[tokio::main]
+async fn main() {
+    let cli = Cli::parse();
+    if let Some(seed) = cli.seed {
+        eprintln!("⚠️  INSECURE: random numbers are seeded with {}, never use this for real accounts", seed);
+        ZKP::with_rng(seed);
+    }
+    let path = ProfileStore::default_path();
+
+    match cli.command {
+        Command::Profile(command) => {
+            let mut store = load_profiles(&path);
+            run_profile_command(&mut store, command);
+            store.save(&path).expect("❌ Could not write the profiles file");
+        }
+        Command::Register { target, options } => {
+            let (connection, user) = resolve_target(&load_profiles(&path), target);
+            register(&connection, &user, options).await;
+        }
+        Command::VerifyEmail { target, code } => {
+            let (connection, user) = resolve_target(&load_profiles(&path), target);
+            verify_email(&connection, &user, code.unwrap_or_default()).await;
+        }
+        Command::Login { target, ssh_key, device_key } => {
+            let (connection, user) = resolve_target(&load_profiles(&path), target);
+            login(&connection, &user, ssh_key.as_deref(), device_key.as_deref()).await;
+        }
+        Command::Device(DeviceCommand::Enroll { target, session, name, out }) => {
+            let (connection, user) = resolve_target(&load_profiles(&path), target);
+            enroll_device(&connection, &user, &session, &name, &out).await;
+        }
+        Command::Device(DeviceCommand::List { target, session }) => {
+            let (connection, user) = resolve_target(&load_profiles(&path), target);
+            list_devices(&connection, &user, &session).await;
+        }
+        Command::Device(DeviceCommand::Revoke { target, session, device_id }) => {
+            let (connection, user) = resolve_target(&load_profiles(&path), target);
+            revoke_device(&connection, &user, &session, &device_id).await;
+        }
+        Command::Delegation(DelegationCommand::Mint { target, to, scopes, ttl, out }) => {
+            let (connection, user) = resolve_target(&load_profiles(&path), target);
+            mint_delegation(&connection, &user, &to, &scopes, std::time::Duration::from_secs(ttl), out.as_deref()).await;
+        }
+        Command::Delegation(DelegationCommand::Verify { target, delegate, scope, delegation }) => {
+            let connection = resolve_connection(&load_profiles(&path), &target);
+            verify_delegation(&connection, &delegate, &scope.unwrap_or_default(), &delegation).await;
+        }
+        Command::Delegation(DelegationCommand::Revoke { target, session, delegation_id, reason }) => {
+            let (connection, user) = resolve_target(&load_profiles(&path), target);
+            revoke_delegation(&connection, &user, &session, &delegation_id, &reason).await;
+        }
+        Command::DeviceLogin(target) => {
+            let connection = resolve_connection(&load_profiles(&path), &target);
+            device_login(&connection).await;
+        }
+        Command::ApproveDevice { target, session, qr, yes } => {
+            let (connection, user) = resolve_target(&load_profiles(&path), target);
+            approve_device(&connection, &user, &session, &qr, yes).await;
+        }
+        Command::Keygen { user, out, salt, memory_kib, iterations, parallelism, options } => {
+            let kdf_params = match salt {
+                Some(salt) => KdfParams {
+                    salt: hex::decode(&salt).unwrap_or_else(|e| panic!("❌ --salt is not hex: {}", e)),
+                    memory_kib,
+                    iterations,
+                    parallelism,
+                },
+                None => KdfParams::with_cost(memory_kib, iterations, parallelism),
+            };
+            keygen(&user, &out, kdf_params, options).await;
+        }
+        Command::Params(target) => {
+            let connection = resolve_connection(&load_profiles(&path), &target);
+            show_parameters(&connection).await;
+        }
+        Command::Time(target) => {
+            let connection = resolve_connection(&load_profiles(&path), &target);
+            show_time_offset(&connection).await;
+        }
+        Command::Revocations(target) => {
+            let connection = resolve_connection(&load_profiles(&path), &target);
+            show_revocations(&connection).await;
+        }
+        Command::AuditConfig { target, fail_on } => {
+            let connection = resolve_connection(&load_profiles(&path), &target);
+            audit_config(&connection, fail_on).await;
+        }
+        Command::Demo { toy, password, all_in_one: false } => {
+            demo::run(toy, password).expect("❌ Could not run the demo");
+        }
+        Command::Demo { password, all_in_one: true, .. } => {
+            demo::run_all_in_one(password, cli.seed).await.unwrap_or_else(|e| panic!("❌ The demo failed: {}", e));
+        }
+        Command::KnownServers(command) => {
+            let path = KnownServers::default_path();
+            let mut known = KnownServers::load(&path).expect("❌ Could not read the known servers file");
+            run_known_servers_command(&mut known, command);
+            known.save(&path).expect("❌ Could not write the known servers file");
+        }
+        Command::Admin(AdminCommand::Disable { account, reason, admin }) => {
+            set_user_enabled(&load_profiles(&path), admin, &account, false, reason).await;
+        }
+        Command::Admin(AdminCommand::Enable { account, reason, admin }) => {
+            set_user_enabled(&load_profiles(&path), admin, &account, true, reason).await;
+        }
+        Command::Admin(AdminCommand::Export { account, admin }) => {
+            export_user_data(&load_profiles(&path), admin, &account).await;
+        }
+        Command::Admin(AdminCommand::Erase { account, reason, yes, admin }) => {
+            if !yes {
+                panic!("❌ Erasing {} can't be undone, pass --yes to go ahead", account);
+            }
+            erase_user(&load_profiles(&path), admin, &account, reason).await;
+        }
+        Command::Admin(AdminCommand::ImportUsers { file, admin }) => {
+            import_users(&load_profiles(&path), admin, &file).await;
+        }
+        Command::Admin(AdminCommand::SplitKey { out_dir, threshold, shares, key }) => {
+            split_key(&out_dir, threshold, shares, key.as_deref());
+        }
+        Command::Admin(AdminCommand::Unseal { share_file, admin }) => {
+            unseal(&load_profiles(&path), admin, &share_file).await;
+        }
+        Command::Completions { shell } => {
+            clap_complete::generate(shell, &mut Cli::command(), "zkp-auth", &mut std::io::stdout());
+        }
+        Command::Man => {
+            clap_mangen::Man::new(Cli::command())
+                .render(&mut std::io::stdout())
+                .expect("❌ Could not write the man page");
+        }
+    }
+}
+
+fn load_profiles(path: &Path) -> ProfileStore {
+    ProfileStore::load(path).expect("❌ Could not read the profiles file")
+}
+
+fn run_profile_command(store: &mut ProfileStore, command: ProfileCommand) {
+    match command {
+        ProfileCommand::Add { name, endpoint, user, ca_cert, server_key, tofu, compression } => {
+            store.add(&name, Profile { endpoint, user, ca_cert, server_key, tofu, compression });
+            println!("✅ Saved profile {}", name);
+        }
+        ProfileCommand::List => {
+            if store.profiles.is_empty() {
+                println!("No profiles yet, add one with `zkp-auth profile add`");
+            }
+            for (name, profile) in &store.profiles {
+                let marker = if store.active.as_deref() == Some(name.as_str()) { "*" } else { " " };
+                let user = profile.user.as_deref().unwrap_or("-");
+                println!("{} {:<16} {:<32} {}", marker, name, profile.endpoint, user);
+            }
+        }
+        ProfileCommand::Use { name } => {
+            store.set_active(&name).unwrap_or_else(|e| panic!("❌ {}", e));
+            println!("✅ Now using profile {}", name);
+        }
+        ProfileCommand::Remove { name } => match store.remove(&name) {
+            Some(_) => println!("✅ Removed profile {}", name),
+            None => println!("⚠️  No profile named {}", name),
+        },
+    }
+}
+
+fn run_known_servers_command(known: &mut KnownServers, command: KnownServersCommand) {
+    match command {
+        KnownServersCommand::List => {
+            if known.servers.is_empty() {
+                println!("No known servers yet, connect with --tofu to remember one");
+            }
+            for (endpoint, server) in &known.servers {
+                println!("{}", endpoint);
+                println!("    certificate    {}", server.tls_fingerprint.as_deref().unwrap_or("-"));
+                println!("    parameter key  {}", server.params_key.as_deref().unwrap_or("-"));
+            }
+        }
+        KnownServersCommand::Forget { endpoint } => match known.forget(&endpoint) {
+            true => println!("✅ Forgot {}", endpoint),
+            false => println!("⚠️  {} is not a known server", endpoint),
+        },
+    }
+}
+
+/// flags win over the selected profile, which wins over the defaults
+fn resolve_target(store: &ProfileStore, target: Target) -> (Connection, String) {
+    let connection = resolve_connection(store, &target);
+    let profile = selected_profile(store, &target);
+    let user = match target.user.or_else(|| profile.and_then(|p| p.user.clone())) {
+        Some(user) => user,
+        None => prompt("Please provide your username:"),
+    };
+    (connection, user)
+}
+
+fn selected_profile<'a>(store: &'a ProfileStore, target: &Target) -> Option<&'a Profile> {
+    match target.profile.as_deref() {
+        Some(name) => Some(
+            store
+                .get(Some(name))
+                .unwrap_or_else(|| panic!("❌ profile {} does not exist", name))
+                .1,
+        ),
+        None => store.get(None).map(|(_, profile)| profile),
+    }
+}
+
+fn resolve_connection(store: &ProfileStore, target: &Target) -> Connection {
+    let profile = selected_profile(store, target);
+    Connection {
+        endpoint: target
+            .endpoint
+            .clone()
+            .or_else(|| profile.map(|p| p.endpoint.clone()))
+            .unwrap_or_else(|| DEFAULT_ENDPOINT.to_string()),
+        ca_cert: target.ca_cert.clone().or_else(|| profile.and_then(|p| p.ca_cert.clone())),
+        server_key: target
+            .server_key
+            .clone()
+            .or_else(|| profile.and_then(|p| p.server_key.clone())),
+        tofu: target.tofu || profile.is_some_and(|p| p.tofu),
+        compression: target
+            .compression
+            .or_else(|| profile.and_then(|p| p.compression))
+            .unwrap_or_default(),
+        timeouts: CallTimeouts {
+            register: std::time::Duration::from_secs(target.register_timeout),
+            challenge: std::time::Duration::from_secs(target.challenge_timeout),
+            verify: std::time::Duration::from_secs(target.verify_timeout),
+        },
+    }
+}
+
+fn prompt(message: &str) -> String {
+    let mut buf = String::new();
+    println!("{}", message);
+    stdin()
+        .read_line(&mut buf)
+        .expect("❌ Could not read from input");
+    buf.trim().to_string()
+}
+
+/// the group to work in: the server's signed parameters when we pin its key
+/// (or remember it from first use), the built-in RFC 5114 constants otherwise
+async fn get_zkp(client: &mut AuthClient<Channel>, connection: &Connection, trace: &TraceContext) -> ZKP {
+    if connection.tofu && connection.server_key.is_none() {
+        if let Some(zkp) = get_zkp_on_first_use(client, connection, trace).await {
+            return zkp;
+        }
+    }
+    match &connection.server_key {
+        Some(server_key) => {
+            let trusted = parse_public_key(server_key).unwrap_or_else(|e| panic!("❌ {}", e));
+            let zkp = fetch_parameters(client, trace)
+                .await
+                .verify(&trusted)
+                .unwrap_or_else(|e| panic!("❌ Refusing to use the server's parameters: {}", e));
+            println!("🔏 Verified the server's signed group parameters");
+            zkp
+        }
+        None => ZKP::builder().rfc5114().build().expect("❌ RFC 5114 parameters are valid"),
+    }
+}
+
+/// verify the parameters against the key we saw the first time; None when the
+/// server doesn't publish signed parameters at all
+async fn get_zkp_on_first_use(
+    client: &mut AuthClient<Channel>,
+    connection: &Connection,
+    trace: &TraceContext,
+) -> Option<ZKP> {
+    let parameters = match client.get_parameters(trace.request(ParametersRequest {})).await {
+        Ok(response) => signed_parameters(response.into_inner()),
+        Err(status) if status.code() == tonic::Code::Unimplemented => return None,
+        Err(status) => panic!("❌ Could not fetch the server's parameters: {}{}", status, trace_note(trace)),
+    };
+    let presented = hex::encode(&parameters.public_key);
+    remember(&connection.endpoint, "parameter key", &presented, KnownServers::check_params_key);
+
+    let trusted = parse_public_key(&presented).unwrap_or_else(|e| panic!("❌ {}", e));
+    let zkp = parameters
+        .verify(&trusted)
+        .unwrap_or_else(|e| panic!("❌ Refusing to use the server's parameters: {}", e));
+    println!("🔏 Verified the server's signed group parameters");
+    Some(zkp)
+}
+
+/// compare what the server presented with the known servers file, SSH style:
+/// remember it on first use, abort loudly when it changed
+fn remember(endpoint: &str, what: &str, presented: &str, check: fn(&mut KnownServers, &str, &str) -> Trust) {
+    let path = KnownServers::default_path();
+    let mut known = KnownServers::load(&path).expect("❌ Could not read the known servers file");
+    match check(&mut known, endpoint, presented) {
+        Trust::Known => {}
+        Trust::FirstUse => {
+            eprintln!("⚠️  First connection to {}, trusting its {} {}", endpoint, what, presented);
+            known.save(&path).expect("❌ Could not write the known servers file");
+        }
+        Trust::Changed { known } => panic!("{}", changed_warning(endpoint, what, &known, presented)),
+    }
+}
+
+fn changed_warning(endpoint: &str, what: &str, known: &str, presented: &str) -> String {
+    format!(
+        "❌ The {} of {} has CHANGED!\n\
+         Someone could be intercepting the connection, or the server was re-keyed.\n\
+         known:     {}\n\
+         presented: {}\n\
+         If the change is expected, run `zkp-auth known-servers forget {}`",
+        what, endpoint, known, presented, endpoint
+    )
+}
+
+async fn fetch_parameters(client: &mut AuthClient<Channel>, trace: &TraceContext) -> SignedParameters {
+    let response = client
+        .get_parameters(trace.request(ParametersRequest {}))
+        .await
+        .unwrap_or_else(|e| panic!("❌ Could not fetch the server's parameters: {}{}", e, trace_note(trace)))
+        .into_inner();
+    signed_parameters(response)
+}
+
+fn signed_parameters(response: ParametersResponse) -> SignedParameters {
+    SignedParameters {
+        p: response.p,
+        q: response.q,
+        alpha: response.alpha,
+        beta: response.beta,
+        public_key: response.public_key,
+        signature: response.signature,
+    }
+}
+
+async fn show_parameters(connection: &Connection) {
+    let mut client = connect(connection).await;
+    let parameters = fetch_parameters(&mut client, &TraceContext::new_root()).await;
+    match &connection.server_key {
+        Some(server_key) => {
+            let trusted = parse_public_key(server_key).unwrap_or_else(|e| panic!("❌ {}", e));
+            parameters.verify(&trusted).unwrap_or_else(|e| panic!("❌ {}", e));
+            eprintln!("🔏 Signature verified");
+        }
+        None => eprintln!(
+            "⚠️  Not verified, pin the key with --server-key {}",
+            hex::encode(&parameters.public_key)
+        ),
+    }
+    println!("{}", parameters.to_json());
+}
+
+async fn show_time_offset(connection: &Connection) {
+    let mut client = connect(connection).await;
+    let trace = TraceContext::new_root();
+    let sent = std::time::SystemTime::now();
+    let server = client
+        .get_server_time(trace.request(ServerTimeRequest {}))
+        .await
+        .unwrap_or_else(|e| panic!("❌ Could not fetch the server time: {}{}", e.message(), trace_note(&trace)))
+        .into_inner();
+    let received = std::time::SystemTime::now();
+
+    let offset = clock::estimate_offset_millis(sent, received, server.unix_millis);
+    let round_trip = received.duration_since(sent).unwrap_or_default();
+    println!(
+        "🕒 Server clock is {:+.3}s from ours (round trip {} ms)",
+        offset as f64 / 1000.0,
+        round_trip.as_millis()
+    );
+    if offset.unsigned_abs() / 1000 > server.max_skew_secs {
+        println!(
+            "⚠️  That is more than the {}s the server tolerates, fix this clock or proofs will look expired",
+            server.max_skew_secs
+        );
+    }
+}
+
+async fn audit_config(connection: &Connection, fail_on: Severity) {
+    let mut client = connect(connection).await;
+    let trace = TraceContext::new_root();
+
+    // servers without a --params-key serve the default group
+    let (p, q, pinned_key) = match client.get_parameters(trace.request(ParametersRequest {})).await {
+        Ok(response) => {
+            let parameters = signed_parameters(response.into_inner());
+            if let Some(server_key) = &connection.server_key {
+                let trusted = parse_public_key(server_key).unwrap_or_else(|e| panic!("❌ {}", e));
+                parameters.verify(&trusted).unwrap_or_else(|e| panic!("❌ {}", e));
+            }
+            (BigUint::from_bytes_be(&parameters.p), BigUint::from_bytes_be(&parameters.q), connection.server_key.is_some())
+        }
+        Err(status) if status.code() == tonic::Code::Unimplemented => {
+            let (p, _, _, q) = ZKP::get_constants();
+            (p, q, false)
+        }
+        Err(status) => panic!("❌ Could not fetch the server's parameters: {}{}", status, trace_note(&trace)),
+    };
+    let server = match client.get_server_info(trace.request(ServerInfoRequest {})).await {
+        Ok(info) => info.into_inner().security.as_ref().map(ServerSettings::from),
+        Err(status) if status.code() == tonic::Code::Unimplemented => None,
+        Err(status) => panic!("❌ Could not get the server info: {}{}", status, trace_note(&trace)),
+    };
+
+    let snapshot = ConfigSnapshot {
+        group_bits: p.bits(),
+        q_bits: q.bits(),
+        tls: connection.endpoint.starts_with("https://"),
+        loopback: config_audit::is_loopback(&connection.endpoint),
+        pinned_key,
+        server,
+    };
+    let findings = config_audit::audit(&snapshot, &Baseline::default());
+    println!("🔎 {} against the built-in baseline", connection.endpoint);
+    for finding in &findings {
+        let mark = match finding.severity {
+            Severity::High => "🔴",
+            Severity::Medium => "🟠",
+            Severity::Low => "🟡",
+            Severity::Info => "🔵",
+        };
+        println!("{} {:<6} {:<20} {}", mark, finding.severity, finding.check, finding.message);
+    }
+    match findings.iter().filter(|finding| finding.severity >= fail_on).count() {
+        0 if findings.is_empty() => println!("✅ Nothing to report"),
+        0 => println!("✅ Nothing at or above {}", fail_on),
+        failed => {
+            println!("❌ {} finding(s) at or above {}", failed, fail_on);
+            std::process::exit(1);
+        }
+    }
+}
+
+async fn show_revocations(connection: &Connection) {
+    let mut client = connect(connection).await;
+    let trace = TraceContext::new_root();
+    let list = client
+        .get_revocation_list(trace.request(RevocationListRequest {}))
+        .await
+        .unwrap_or_else(|e| panic!("❌ Could not fetch the revocation list: {}{}", e.message(), trace_note(&trace)))
+        .into_inner();
+    println!("{}", RevocationList::from(list).to_json());
+}
+
+async fn set_user_enabled(store: &ProfileStore, admin: AdminTarget, account: &str, enabled: bool, reason: String) {
+    let token = AdminToken::load(&admin.token_file).unwrap_or_else(|e| panic!("❌ Could not read the admin token: {}", e));
+    let connection = resolve_connection(store, &admin.target);
+    let mut client = AdminClient::new(open_channel(&connection).await);
+    let trace = TraceContext::new_root();
+    let request = SetUserEnabledRequest { user: account.to_string(), enabled, reason };
+    let response = client
+        .set_user_enabled(token.authorize(trace.request(request)))
+        .await
+        .unwrap_or_else(|e| panic!("❌ Could not change {}: {}{}", account, e, trace_note(&trace)))
+        .into_inner();
+    match enabled {
+        true => println!("✅ Enabled {}", account),
+        false => println!("✅ Disabled {}, {} session(s) ended", account, response.revoked_sessions),
+    }
+}
+
+async fn export_user_data(store: &ProfileStore, admin: AdminTarget, account: &str) {
+    let token = AdminToken::load(&admin.token_file).unwrap_or_else(|e| panic!("❌ Could not read the admin token: {}", e));
+    let connection = resolve_connection(store, &admin.target);
+    let mut client = AdminClient::new(open_channel(&connection).await);
+    let trace = TraceContext::new_root();
+    let request = ExportUserDataRequest { user: account.to_string() };
+    let response = client
+        .export_user_data(token.authorize(trace.request(request)))
+        .await
+        .unwrap_or_else(|e| panic!("❌ Could not export {}: {}{}", account, e, trace_note(&trace)))
+        .into_inner();
+    println!("{}", response.json);
+}
+
+async fn erase_user(store: &ProfileStore, admin: AdminTarget, account: &str, reason: String) {
+    let token = AdminToken::load(&admin.token_file).unwrap_or_else(|e| panic!("❌ Could not read the admin token: {}", e));
+    let connection = resolve_connection(store, &admin.target);
+    let mut client = AdminClient::new(open_channel(&connection).await);
+    let trace = TraceContext::new_root();
+    let request = EraseUserRequest { user: account.to_string(), reason };
+    let response = client
+        .erase_user(token.authorize(trace.request(request)))
+        .await
+        .unwrap_or_else(|e| panic!("❌ Could not erase {}: {}{}", account, e, trace_note(&trace)))
+        .into_inner();
+    println!(
+        "✅ Erased {}, {} session(s) ended, {} audit event(s) tombstoned as {}",
+        account, response.sessions_ended, response.audit_events_erased, response.tombstone
+    );
+}
+
+/// split a new (or existing) Ed25519 key into shares; only the shares and the public key come out
+fn split_key(out_dir: &Path, threshold: u8, shares: u8, key: Option<&Path>) {
+    let key = match key {
+        Some(path) => {
+            let hex_key = std::fs::read_to_string(path).unwrap_or_else(|e| panic!("❌ Could not read {}: {}", path.display(), e));
+            let bytes: [u8; 32] = hex::decode(hex_key.trim())
+                .ok()
+                .and_then(|bytes| bytes.try_into().ok())
+                .unwrap_or_else(|| panic!("❌ {} must hold 32 hex-encoded bytes", path.display()));
+            SigningKey::from_bytes(&bytes)
+        }
+        None => SigningKey::generate(&mut rand::rngs::OsRng),
+    };
+    let split = shamir::split(&key.to_bytes(), threshold, shares).unwrap_or_else(|e| panic!("❌ {}", e));
+    let paths = shamir::write_shares(out_dir, &split).unwrap_or_else(|e| panic!("❌ Could not write the shares: {}", e));
+    for path in &paths {
+        println!("🧩 {}", path.display());
+    }
+    println!("✅ {} shares, any {} of which give the key back", shares, threshold);
+    println!("   start the server with --id-token-public-key {}", hex::encode(key.verifying_key().as_bytes()));
+}
+
+async fn unseal(store: &ProfileStore, admin: AdminTarget, share_file: &Path) {
+    let token = AdminToken::load(&admin.token_file).unwrap_or_else(|e| panic!("❌ Could not read the admin token: {}", e));
+    let share = std::fs::read_to_string(share_file).unwrap_or_else(|e| panic!("❌ Could not read {}: {}", share_file.display(), e));
+    let connection = resolve_connection(store, &admin.target);
+    let mut client = AdminClient::new(open_channel(&connection).await);
+    let trace = TraceContext::new_root();
+    let request = UnsealIdTokenKeyRequest { share: share.trim().to_string() };
+    let response = client
+        .unseal_id_token_key(token.authorize(trace.request(request)))
+        .await
+        .unwrap_or_else(|e| panic!("❌ Could not unseal: {}{}", e, trace_note(&trace)))
+        .into_inner();
+    match response.unsealed {
+        true => println!("✅ The ID token key is unsealed"),
+        false => println!("🔒 {} of {} shares, still sealed", response.shares, response.threshold),
+    }
+}
+
+async fn import_users(store: &ProfileStore, admin: AdminTarget, file: &Path) {
+    let token = AdminToken::load(&admin.token_file).unwrap_or_else(|e| panic!("❌ Could not read the admin token: {}", e));
+    let text = std::fs::read_to_string(file).unwrap_or_else(|e| panic!("❌ Could not read {}: {}", file.display(), e));
+    // the whole file is checked before anything is sent
+    let users = import::parse(file, &text).unwrap_or_else(|e| panic!("❌ {}: {}", file.display(), e));
+    eprintln!("📥 Importing {} users from {}", users.len(), file.display());
+
+    let connection = resolve_connection(store, &admin.target);
+    let mut client = AdminClient::new(open_channel(&connection).await);
+    let trace = TraceContext::new_root();
+    let response = client
+        .bulk_register(token.authorize(trace.request(tokio_stream::iter(users))))
+        .await
+        .unwrap_or_else(|e| panic!("❌ Could not import the users: {}{}", e, trace_note(&trace)))
+        .into_inner();
+
+    for failure in &response.failures {
+        eprintln!("⚠️  {}: {} ({:?})", failure.user, failure.message, tonic::Code::from(failure.code));
+    }
+    if response.failed as usize > response.failures.len() {
+        eprintln!("⚠️  ... and {} more failures", response.failed as usize - response.failures.len());
+    }
+    // failures fail the command, so a migration script notices
+    if response.failed > 0 {
+        panic!("❌ {} registered, {} already there, {} failed", response.registered, response.unchanged, response.failed);
+    }
+    println!("✅ {} registered, {} already there", response.registered, response.unchanged);
+}
+
+async fn connect(connection: &Connection) -> AuthClient<Channel> {
+    let mut client = AuthClient::new(open_channel(connection).await);
+    for encoding in compression::ACCEPTED {
+        client = client.accept_compressed(encoding);
+    }
+    match connection.compression.encoding() {
+        Some(encoding) => client.send_compressed(encoding),
+        None => client,
+    }
+}
+
+async fn open_channel(connection: &Connection) -> Channel {
+    eprintln!("🔌 Connecting to {}...", connection.endpoint);
+    let mut endpoint = Endpoint::from_shared(connection.endpoint.clone())
+        .expect("❌ Invalid endpoint");
+
+    if connection.tofu && connection.ca_cert.is_none() && connection.endpoint.starts_with("https://") {
+        return connect_on_first_use(&connection.endpoint).await;
+    }
+
+    if connection.endpoint.starts_with("https://") {
+        let mut tls = ClientTlsConfig::new();
+        if let Some(ca_cert) = &connection.ca_cert {
+            let pem = std::fs::read(ca_cert).expect("❌ Could not read the CA certificate");
+            tls = tls.ca_certificate(Certificate::from_pem(pem));
+        }
+        endpoint = endpoint.tls_config(tls).expect("❌ Invalid TLS configuration");
+    }
+
+    endpoint
+        .connect()
+        .await
+        .expect("❌ Could not connect to the server")
+}
+
+/// TLS pinned to the certificate fingerprint we saw the first time
+async fn connect_on_first_use(endpoint: &str) -> Channel {
+    let path = KnownServers::default_path();
+    let pinned = KnownServers::load(&path)
+        .expect("❌ Could not read the known servers file")
+        .get(endpoint)
+        .and_then(|server| server.tls_fingerprint.clone());
+
+    match tofu::connect_pinned(endpoint, pinned).await {
+        Ok((channel, fingerprint)) => {
+            remember(endpoint, "certificate", &fingerprint, KnownServers::check_tls);
+            channel
+        }
+        Err(TofuError::Changed { known, presented }) => {
+            panic!("{}", changed_warning(endpoint, "certificate", &known, &presented))
+        }
+        Err(e) => panic!("❌ {}", e),
+    }
+}
+
+/// what we tell the server about this device; it shows up next to our sessions
+fn client_metadata() -> ClientMetadata {
+    ClientMetadata {
+        // an unwritable home just means an anonymous device
+        device_id: profile::load_or_create_device_id(&profile::default_device_id_path()).unwrap_or_default(),
+        app_version: env!("CARGO_PKG_VERSION").to_string(),
+        platform: std::env::consts::OS.to_string(),
+    }
+}
+
+/// Tries per call, and per login before giving up on new challenges
+const ATTEMPTS: u32 = 3;
+
+/// Pause before trying a call again
+const RETRY_PAUSE: std::time::Duration = std::time::Duration::from_secs(1);
+
+/// How often `device-login` asks whether the phone has approved
+const DEVICE_LOGIN_POLL: std::time::Duration = std::time::Duration::from_secs(2);
+
+/// the request may or may not have reached the server
+fn is_retryable(status: &tonic::Status) -> bool {
+    match status.code() {
+        tonic::Code::Unavailable | tonic::Code::DeadlineExceeded => true,
+        // the connection broke during the call, the server didn't answer with an error
+        tonic::Code::Unknown => {
+            std::error::Error::source(status).is_some_and(|source| source.is::<tonic::transport::Error>())
+        }
+        _ => false,
+    }
+}
+
+/// make a call, again after a pause while it fails in a way a retry can fix;
+/// `call` builds a fresh request each time
+async fn retrying<T, F, Fut>(what: &str, mut call: F) -> Result<T, tonic::Status>
+where
+    F: FnMut() -> Fut,
+    Fut: std::future::Future<Output = Result<tonic::Response<T>, tonic::Status>>,
+{
+    let mut attempt = 1;
+    loop {
+        match call().await {
+            Ok(response) => return Ok(response.into_inner()),
+            Err(status) if attempt < ATTEMPTS && is_retryable(&status) => {
+                eprintln!("⚠️  {} attempt {} failed ({}), retrying...", what, attempt, status.message());
+                attempt += 1;
+                tokio::time::sleep(RETRY_PAUSE).await;
+            }
+            Err(status) => return Err(status),
+        }
+    }
+}
+
+/// the server no longer has our challenge (it restarted, or the challenge
+/// expired or was used by an answer whose reply we lost): start over
+fn lost_challenge(status: tonic::Status) -> tonic::Status {
+    match status.code() {
+        tonic::Code::NotFound => tonic::Status::aborted(status.message()),
+        _ => status,
+    }
+}
+
+/// appended to errors so the failure can be found in the server logs
+fn trace_note(trace: &TraceContext) -> String {
+    format!(" (trace id {})", trace.trace_id_hex())
+}
+
+/// check the password against the policy and derive (y1, y2) from it; `zkp`
+/// already has the per-user generator when one was asked for
+async fn registration(zkp: &ZKP, user: &str, password: &str, kdf_params: &KdfParams, options: &RegisterOptions) -> RegisterRequest {
+    // the server never sees the password, so this is the only place to judge it
+    #[cfg(not(feature = "zxcvbn"))]
+    let policy = DefaultPolicy::default();
+    #[cfg(feature = "zxcvbn")]
+    let policy = {
+        let estimate = strength::estimate(user, password);
+        eprintln!("🔐 Password strength {} of {}, about {} to guess offline", estimate.score, strength::MAX_SCORE, estimate.crack_time);
+        for hint in estimate.warning.iter().chain(&estimate.suggestions) {
+            eprintln!("💡 {}", hint);
+        }
+        StrengthPolicy { rules: DefaultPolicy::default(), min_score: options.min_strength }
+    };
+    let weaknesses = policy.check(user, password);
+    #[cfg(feature = "hibp")]
+    let weaknesses = [weaknesses, breaches(password, options).await].concat();
+    for weakness in &weaknesses {
+        eprintln!("⚠️  Weak password: {}", weakness);
+    }
+    if !weaknesses.is_empty() && !options.allow_weak_password {
+        panic!("❌ Password rejected by the password policy, pass --allow-weak-password to use it anyway");
+    }
+    let password_policy_version = if weaknesses.is_empty() { policy.version() } else { 0 };
+
+    // the server keeps the salt and cost so any device can rederive x later
+    let x = kdf_params
+        .derive(password, &zkp.q)
+        .unwrap_or_else(|e| panic!("❌ {}", e));
+    let (y1, y2) = zkp.public_key(&x).to_bytes_be();
+
+    RegisterRequest {
+        user: user.to_string(),
+        y1,
+        y2,
+        idempotency_key: String::new(),
+        password_policy_version,
+        kdf: Some(kdf_params.into()),
+        beta: match options.per_user_generator {
+            true => zkp.beta.to_bytes_be(),
+            false => Vec::new(),
+        },
+        group: String::new(), // "modp"
+        hash: match options.hash {
+            HashId::Sha256 => String::new(),
+            hash => hash.name().to_string(),
+        },
+    }
+}
+
+/// look the password up in known breaches when asked to; no answer from the
+/// range API is an error, as the password would go unchecked
+#[cfg(feature = "hibp")]
+async fn breaches(password: &str, options: &RegisterOptions) -> Vec<Weakness> {
+    if !options.check_breaches {
+        return Vec::new();
+    }
+    let check = BreachCheck::new(&options.breach_api).unwrap_or_else(|e| panic!("❌ {}", e));
+    match check.times_seen(password).await {
+        Ok(0) => Vec::new(),
+        Ok(count) => vec![Weakness::Breached { count }],
+        Err(e) => panic!("❌ Could not check the password against known breaches: {}", e),
+    }
+}
+
+/// derive a registration offline, for enrollment pipelines that feed `admin import-users`;
+/// the file only holds public values, the password never leaves this process
+async fn keygen(user: &str, out: &Path, kdf_params: KdfParams, options: RegisterOptions) {
+    // the server's built-in group, there is nobody to ask for signed parameters
+    let mut zkp = ZKP::builder().rfc5114().build().expect("❌ RFC 5114 parameters are valid");
+    if options.per_user_generator {
+        zkp = zkp.for_user_with(options.hash, user);
+    }
+    let password = prompt("Please provide the password:");
+    let request = registration(&zkp, user, &password, &kdf_params, &options).await;
+
+    let line = serde_json::to_string(&StoredUser::from(&request)).expect("❌ registrations always serialize");
+    std::fs::write(out, format!("{}\n", line)).unwrap_or_else(|e| panic!("❌ Could not write {}: {}", out.display(), e));
+    println!("✅ Key for {} written to {}", user, out.display());
+    show_fingerprint(&request);
+}
+
+/// the key's fingerprint, for comparing with what the server logs and admins see
+fn show_fingerprint(request: &RegisterRequest) {
+    let fingerprint = PublicKey::from_bytes_be(&request.y1, &request.y2).fingerprint();
+    println!("🔑 Key fingerprint {}  {}  ({})", fingerprint, fingerprint.to_emoji(), fingerprint.to_words());
+}
+
+async fn register(connection: &Connection, user: &str, options: RegisterOptions) {
+    let trace = TraceContext::new_root();
+    let mut client = connect(connection).await;
+    let mut zkp = get_zkp(&mut client, connection, &trace).await;
+    if options.per_user_generator {
+        zkp = zkp.for_user_with(options.hash, user);
+    }
+
+    let password = prompt("Please provide your password:");
+
+    // every attempt carries the same key, so a retry after a registration that
+    // did reach the server isn't rejected as AlreadyExists
+    let request = RegisterRequest {
+        idempotency_key: ZKP::generate_random_string(24),
+        ..registration(&zkp, user, &password, &KdfParams::generate(), &options).await
+    };
+    let timeout = connection.timeouts.register;
+    let response = retrying("Registration", || {
+        let (mut client, request) = (client.clone(), with_deadline(trace.request(request.clone()), timeout));
+        async move { within(timeout, client.register(request)).await }
+    })
+    .await
+    .map_err(|status| CallError::new("Registration", timeout, status))
+    .unwrap_or_else(|e| panic!("❌ Could not register with server: {}{}", e, trace_note(&trace)));
+
+    // servers from before RegisterResponse.subject keep the name as is
+    match response.subject.as_str() {
+        "" => println!("✅ Registered {}", user),
+        subject if subject == user => println!("✅ Registered {}", user),
+        subject => println!("✅ Registered {}, log in as {}", user, subject),
+    }
+    show_fingerprint(&request);
+    if response.verify_email {
+        println!("📧 A code was sent to {}, confirm it with `zkp-auth verify-email --code <code>`", response.subject);
+    }
+}
+
+/// confirm an account's email address, or have a new code sent with an empty one
+async fn verify_email(connection: &Connection, user: &str, code: String) {
+    let trace = TraceContext::new_root();
+    let mut client = connect(connection).await;
+    let request = VerifyEmailRequest { user: user.to_string(), code };
+    let response = client
+        .verify_email(trace.request(request))
+        .await
+        .unwrap_or_else(|e| panic!("❌ Could not verify the email address: {}{}", e.message(), trace_note(&trace)))
+        .into_inner();
+    match response.verified {
+        true => println!("✅ {} is verified", user),
+        false => println!("📧 A new code was sent to {}", user),
+    }
+}
+
+/// ask the server which challenge modes it offers; servers from before
+/// GetServerInfo only do direct challenges
+async fn supports_committed_challenge(client: &AuthClient<Channel>, trace: &TraceContext) -> bool {
+    let info = retrying("Server info", || {
+        let (mut client, request) = (client.clone(), trace.request(ServerInfoRequest {}));
+        async move { client.get_server_info(request).await }
+    });
+    match info.await {
+        Ok(info) => info
+            .challenge_modes()
+            .any(|mode| mode == ChallengeMode::Committed),
+        Err(status) if status.code() == tonic::Code::Unimplemented => false,
+        Err(status) => panic!("❌ Could not get the server info: {}{}", status, trace_note(trace)),
+    }
+}
+
+/// the user's group and their secret x, derived from the password the same
+/// way as at registration
+async fn derive_secret(client: &mut AuthClient<Channel>, connection: &Connection, user: &str, trace: &TraceContext) -> (ZKP, BigUint) {
+    let (zkp, kdf_params) = user_group(client, connection, user, trace).await;
+    let password = prompt("Please provide your password:");
+    let x = match kdf_params {
+        Some(kdf_params) => kdf_params
+            .derive(&password, &zkp.q)
+            .unwrap_or_else(|e| panic!("❌ Refusing the server's {}", e)),
+        None => kdf::legacy_derive(&password),
+    };
+    (zkp, x)
+}
+
+/// the group the user registered in, with their own generator if they have
+/// one, and how their x is derived from the password
+async fn user_group(client: &mut AuthClient<Channel>, connection: &Connection, user: &str, trace: &TraceContext) -> (ZKP, Option<KdfParams>) {
+    let mut zkp = get_zkp(client, connection, trace).await;
+
+    // the salt and cost chosen at registration, so this device derives the same x
+    let user_params = retrying("KDF parameters", || {
+        let (mut client, request) = (client.clone(), trace.request(UserKdfParamsRequest { user: user.to_string() }));
+        async move { client.get_user_kdf_params(request).await }
+    })
+    .await
+    .unwrap_or_else(|e| panic!("❌ Could not fetch the KDF parameters: {}{}", e, trace_note(trace)));
+    let kdf_params = user_params.kdf.map(KdfParams::from);
+
+    // only modp is spoken here; the hash says how to recompute a per-user generator
+    if GroupId::from_name(&user_params.group) != Some(GroupId::ModP) {
+        panic!("❌ {} is registered in the {} group, which zkp-auth doesn't speak", user, user_params.group);
+    }
+    let Some(hash) = HashId::from_name(&user_params.hash) else {
+        panic!("❌ {} was registered with the hash {:?}, which zkp-auth doesn't know", user, user_params.hash);
+    };
+
+    // a per-user generator is recomputed here, never taken from the server as is
+    if !user_params.beta.is_empty() {
+        zkp = zkp.for_user_with(hash, user);
+        if zkp.beta.to_bytes_be() != user_params.beta {
+            panic!("❌ The server's generator for {} is not H2G({}), refusing to log in", user, user);
+        }
+    }
+    (zkp, kdf_params)
+}
+
+async fn login(connection: &Connection, user: &str, ssh_key: Option<&Path>, device_key: Option<&Path>) {
+    let trace = TraceContext::new_root();
+    let mut client = connect(connection).await;
+    let (zkp, x, device_id) = match device_key {
+        Some(path) => {
+            let key = DeviceKey::load(path).unwrap_or_else(|e| panic!("❌ Could not read the device key {}: {}", path.display(), e));
+            if key.user != user {
+                panic!("❌ {} is a device key of {}, not {}", path.display(), key.user, user);
+            }
+            let (zkp, _) = user_group(&mut client, connection, user, &trace).await;
+            (zkp, key.secret(), key.device_id)
+        }
+        None => {
+            let (zkp, x) = derive_secret(&mut client, connection, user, &trace).await;
+            (zkp, x, String::new())
+        }
+    };
+
+    let committed = supports_committed_challenge(&client, &trace).await;
+    let ssh_public_key = ssh_key
+        .map(|path| std::fs::read_to_string(path).expect("❌ Could not read the SSH public key"))
+        .unwrap_or_default();
+
+    let mut attempt = 1;
+    let answer = loop {
+        match prove(&client, &zkp, user, &x, &device_id, committed, ssh_public_key.trim(), connection.timeouts, &trace).await {
+            Ok(answer) => break answer,
+            Err(CallError::Failed(status)) if attempt < ATTEMPTS && status.code() == tonic::Code::Aborted => {
+                eprintln!("⚠️  The server lost our challenge ({}), starting over with a new one", status.message());
+                attempt += 1;
+            }
+            Err(slow @ CallError::DeadlineExceeded { .. }) => panic!(
+                "⏱️  {}; try again later or wait longer (--challenge-timeout, --verify-timeout){}",
+                slow,
+                trace_note(&trace)
+            ),
+            Err(e) => panic!("❌ {}{}", e, trace_note(&trace)),
+        }
+    };
+
+    // the canonical id, e.g. the address lowercased; servers from before it send none
+    let subject = if answer.subject.is_empty() { user } else { answer.subject.as_str() };
+    println!("✅ Logged in as {}! Session ID: {}", subject, answer.session_id);
+    if let Some(ssh_key) = ssh_key {
+        // where ssh looks for the certificate of id_ed25519: id_ed25519-cert.pub
+        let name = ssh_key.file_name().unwrap_or_default().to_string_lossy();
+        let cert_path = ssh_key.with_file_name(format!("{}-cert.pub", name.trim_end_matches(".pub")));
+        std::fs::write(&cert_path, format!("{}\n", answer.ssh_certificate))
+            .expect("❌ Could not save the SSH certificate");
+        println!("🔑 SSH certificate for {} saved to {}", user, cert_path.display());
+    }
+    match &answer.last_login {
+        Some(last) => println!("🕒 Last login {}", describe_login(last)),
+        None => println!("🕒 This is your first login"),
+    }
+    if let Some(failed) = answer.last_failed_login.as_ref().filter(|_| answer.failed_logins > 0) {
+        println!(
+            "⚠️  {} failed login(s) since then, the latest {}. If that wasn't you, change your password",
+            answer.failed_logins,
+            describe_login(failed)
+        );
+    }
+}
+
+/// elevate the session with the password, then enroll a new random key for this device
+async fn enroll_device(connection: &Connection, user: &str, session_id: &str, name: &str, out: &Path) {
+    if out.exists() {
+        panic!("❌ {} exists already, pick another file for the device key", out.display());
+    }
+    let trace = TraceContext::new_root();
+    let mut client = connect(connection).await;
+    let (zkp, x) = derive_secret(&mut client, connection, user, &trace).await;
+
+    let k = ZKP::generate_random_number_below(&zkp.q);
+    let (r1, r2) = zkp.commitment(&k).to_bytes_be();
+    let request = AuthenticationChallengeRequest { user: user.to_string(), r1, r2, ..Default::default() };
+    let challenge = client
+        .create_authentication_challenge(trace.request(request))
+        .await
+        .unwrap_or_else(|e| panic!("❌ Could not request challenge from server: {}{}", e.message(), trace_note(&trace)))
+        .into_inner();
+    let s = zkp.respond(&k, &Challenge::from_bytes_be(&challenge.c), &x).to_bytes_be();
+    let request = ElevateSessionRequest {
+        user: user.to_string(),
+        session_id: session_id.to_string(),
+        auth_id: challenge.auth_id,
+        s,
+        challenge_token: challenge.challenge_token,
+    };
+    client
+        .elevate_session(trace.request(request))
+        .await
+        .unwrap_or_else(|e| panic!("❌ Could not elevate the session: {}{}", e.message(), trace_note(&trace)));
+
+    // x_i is random, not derived: nothing about it is shared with the password or another device
+    let device_x = ZKP::generate_random_number_below(&zkp.q);
+    let (y1, y2) = zkp.public_key(&device_x).to_bytes_be();
+    let request = EnrollDeviceRequest { user: user.to_string(), session_id: session_id.to_string(), name: name.to_string(), y1, y2 };
+    let device_id = client
+        .enroll_device(trace.request(request))
+        .await
+        .unwrap_or_else(|e| panic!("❌ Could not enroll the device: {}{}", e.message(), trace_note(&trace)))
+        .into_inner()
+        .device_id;
+    DeviceKey::new(user, &device_id, &device_x)
+        .save(out)
+        .unwrap_or_else(|e| panic!("❌ Could not save the device key to {}: {}", out.display(), e));
+    println!("✅ Enrolled {:?} as device {}", name, device_id);
+    println!("🔑 Log in with `zkp-auth login --device-key {}`; keep the file private", out.display());
+}
+
+async fn list_devices(connection: &Connection, user: &str, session_id: &str) {
+    let trace = TraceContext::new_root();
+    let mut client = connect(connection).await;
+    let request = ListDevicesRequest { user: user.to_string(), session_id: session_id.to_string() };
+    let devices = client
+        .list_devices(trace.request(request))
+        .await
+        .unwrap_or_else(|e| panic!("❌ Could not list the devices: {}{}", e.message(), trace_note(&trace)))
+        .into_inner()
+        .devices;
+    if devices.is_empty() {
+        println!("No devices enrolled, only the password logs in");
+    }
+    for device in devices {
+        let current = if device.current { "  (this session)" } else { "" };
+        println!("📲 {}  {:?}{}", device.device_id, device.name, current);
+    }
+}
+
+async fn revoke_device(connection: &Connection, user: &str, session_id: &str, device_id: &str) {
+    let trace = TraceContext::new_root();
+    let mut client = connect(connection).await;
+    let request = RevokeDeviceRequest { user: user.to_string(), session_id: session_id.to_string(), device_id: device_id.to_string() };
+    let response = client
+        .revoke_device(trace.request(request))
+        .await
+        .unwrap_or_else(|e| panic!("❌ Could not revoke the device: {}{}", e.message(), trace_note(&trace)))
+        .into_inner();
+    println!("📵 Revoked {} ({} sessions ended)", device_id, response.sessions_ended);
+}
+
+async fn mint_delegation(connection: &Connection, user: &str, delegate: &str, scopes: &[String], ttl: std::time::Duration, out: Option<&Path>) {
+    if let Some(out) = out.filter(|out| out.exists()) {
+        panic!("❌ {} exists already, pick another file for the delegation", out.display());
+    }
+    let trace = TraceContext::new_root();
+    let mut client = connect(connection).await;
+    let (zkp, x) = derive_secret(&mut client, connection, user, &trace).await;
+    let window = ValidityWindow::starting_now(ttl);
+    let delegation = Delegation::mint(&zkp, &x, user, delegate, scopes, window).unwrap_or_else(|e| panic!("❌ {}", e));
+
+    println!("🤝 Delegation {} lets {:?} use {} for {} seconds", delegation.id(), delegate, scopes.join(", "), ttl.as_secs());
+    println!("🚫 Revoke it early with `zkp-auth delegation revoke --session <id> {}`", delegation.id());
+    match out {
+        Some(out) => {
+            delegation
+                .save(out)
+                .unwrap_or_else(|e| panic!("❌ Could not save the delegation to {}: {}", out.display(), e));
+            println!("✅ Saved to {}; whoever holds it can use it, so hand it over privately", out.display());
+        }
+        None => println!("{}", delegation.to_blob()),
+    }
+}
+
+async fn verify_delegation(connection: &Connection, delegate: &str, scope: &str, delegation: &str) {
+    let delegation = match delegation.strip_prefix('@') {
+        Some(file) => std::fs::read_to_string(file).unwrap_or_else(|e| panic!("❌ Could not read {}: {}", file, e)),
+        None => delegation.to_string(),
+    };
+    let trace = TraceContext::new_root();
+    let mut client = connect(connection).await;
+    let request = VerifyDelegationRequest { delegation: delegation.trim().to_string(), delegate: delegate.to_string(), scope: scope.to_string() };
+    let response = client
+        .verify_delegation(trace.request(request))
+        .await
+        .unwrap_or_else(|e| panic!("❌ Could not check the delegation: {}{}", e.message(), trace_note(&trace)))
+        .into_inner();
+    if !response.valid {
+        panic!("❌ Not a valid delegation: {}", response.reason);
+    }
+    let left = response.expires_unix.saturating_sub(clock::unix_seconds(std::time::SystemTime::now()));
+    println!("✅ {} may act for {} with {} for another {} seconds", delegate, response.user, response.scopes.join(", "), left);
+}
+
+async fn revoke_delegation(connection: &Connection, user: &str, session_id: &str, delegation_id: &str, reason: &str) {
+    let trace = TraceContext::new_root();
+    let mut client = connect(connection).await;
+    let request = RevokeProofRequest {
+        user: user.to_string(),
+        session_id: session_id.to_string(),
+        proof_id: delegation_id.to_string(),
+        reason: reason.to_string(),
+    };
+    client
+        .revoke_proof(trace.request(request))
+        .await
+        .unwrap_or_else(|e| panic!("❌ Could not revoke the delegation: {}{}", e.message(), trace_note(&trace)));
+    println!("🚫 Revoked delegation {}", delegation_id);
+}
+
+/// show a QR code for a logged-in phone to scan and wait for the session it approves
+async fn device_login(connection: &Connection) {
+    let trace = TraceContext::new_root();
+    let mut client = connect(connection).await;
+    let started = client
+        .start_device_login(trace.request(StartDeviceLoginRequest { client: Some(client_metadata()) }))
+        .await
+        .unwrap_or_else(|e| panic!("❌ Could not start a device login: {}{}", e.message(), trace_note(&trace)))
+        .into_inner();
+
+    let payload = device_login::qr_payload(&started.login_id, &started.commitment);
+    let qr = QrCode::new(&payload).expect("❌ The login doesn't fit into a QR code");
+    let image = qr
+        .render::<unicode::Dense1x2>()
+        .dark_color(unicode::Dense1x2::Light)
+        .light_color(unicode::Dense1x2::Dark)
+        .build();
+    println!("{}", image);
+    println!("📱 Scan this with a phone where you are logged in, or run there:");
+    println!("   zkp-auth approve-device --session <its session ID> '{}'", payload);
+
+    let mut scanned = false;
+    loop {
+        tokio::time::sleep(DEVICE_LOGIN_POLL).await;
+        let request = PollDeviceLoginRequest { login_id: started.login_id.clone(), poll_token: started.poll_token.clone() };
+        let poll = client
+            .poll_device_login(trace.request(request))
+            .await
+            .unwrap_or_else(|e| panic!("❌ {}{}", e.message(), trace_note(&trace)))
+            .into_inner();
+        match poll.state() {
+            DeviceLoginState::Waiting => {}
+            DeviceLoginState::Scanned if !scanned => {
+                println!("👀 Scanned, waiting for the phone to approve");
+                scanned = true;
+            }
+            DeviceLoginState::Scanned => {}
+            DeviceLoginState::Approved => {
+                println!("✅ Logged in as {}! Session ID: {}", poll.subject, poll.session_id);
+                return;
+            }
+            DeviceLoginState::Denied => panic!("❌ The login was declined on the phone"),
+        }
+    }
+}
+
+/// answer a device's QR code with a fresh proof, so the device gets a session
+async fn approve_device(connection: &Connection, user: &str, session_id: &str, qr: &str, yes: bool) {
+    let Some((login_id, commitment)) = device_login::parse_qr_payload(qr) else {
+        panic!("❌ {:?} is not a device login QR code", qr);
+    };
+    let trace = TraceContext::new_root();
+    let mut client = connect(connection).await;
+    let (zkp, x) = derive_secret(&mut client, connection, user, &trace).await;
+
+    let k = ZKP::generate_random_number_below(&zkp.q);
+    let (r1, r2) = zkp.commitment(&k).to_bytes_be();
+    let request = ApproveDeviceLoginRequest {
+        user: user.to_string(),
+        session_id: session_id.to_string(),
+        login_id: login_id.clone(),
+        r1,
+        r2,
+    };
+    let scanned = client
+        .approve_device_login(trace.request(request))
+        .await
+        .unwrap_or_else(|e| panic!("❌ Could not approve the device: {}{}", e.message(), trace_note(&trace)))
+        .into_inner();
+
+    // the QR code fixed c before k was picked
+    let c = BigUint::from_bytes_be(&scanned.c);
+    if !challenge::verify_opening(&commitment, &c, &scanned.nonce) {
+        panic!("❌ The server's challenge doesn't match the QR code, refusing to answer");
+    }
+
+    let device = match &scanned.client {
+        Some(client) => format!("{} on {} ({})", client.device_id, client.platform, client.app_version),
+        None => "A device that didn't say what it is".to_string(),
+    };
+    println!("📱 {} at {} wants to log in as {}", device, scanned.source, user);
+    let approved = yes || prompt("Approve it? [y/N]").eq_ignore_ascii_case("y");
+    let s = match approved {
+        true => zkp.respond(&k, &Challenge(c), &x).to_bytes_be(),
+        false => Vec::new(),
+    };
+    let request = FinishDeviceLoginRequest { user: user.to_string(), session_id: session_id.to_string(), login_id, s };
+    let finished = client
+        .finish_device_login(trace.request(request))
+        .await
+        .unwrap_or_else(|e| panic!("❌ Could not approve the device: {}{}", e.message(), trace_note(&trace)))
+        .into_inner();
+    match finished.approved {
+        true => println!("✅ The device is logged in as {}", user),
+        false => println!("🚫 Declined, the device stays logged out"),
+    }
+}
+
+/// one run of the protocol with a fresh k: challenge, answer, session; Aborted
+/// when the server lost the challenge and a new one should be asked for
+#[allow(clippy::too_many_arguments)]
+async fn prove(
+    client: &AuthClient<Channel>,
+    zkp: &ZKP,
+    user: &str,
+    x: &BigUint,
+    device_id: &str,
+    committed: bool,
+    ssh_public_key: &str,
+    timeouts: CallTimeouts,
+    trace: &TraceContext,
+) -> Result<AuthenticationAnswerResponse, CallError> {
+    // a slow server is told apart from one that answered with an error
+    let context = |what: &str, call: &'static str, timeout: std::time::Duration| {
+        let what = what.to_string();
+        move |status: tonic::Status| match CallError::new(call, timeout, status) {
+            CallError::Failed(status) => {
+                CallError::Failed(tonic::Status::new(status.code(), format!("{}: {}", what, status.message())))
+            }
+            slow => slow,
+        }
+    };
+
+    // with a committed challenge the server can't pick c after seeing (r1, r2)
+    let commitment = match committed {
+        true => Some(
+            retrying("Challenge commitment", || {
+                let request = trace.request(ChallengeCommitmentRequest { user: user.to_string() });
+                let (mut client, request) = (client.clone(), with_deadline(request, timeouts.challenge));
+                async move { within(timeouts.challenge, client.commit_challenge(request)).await }
+            })
+            .await
+            .map_err(context("Could not get a challenge commitment", "The challenge commitment", timeouts.challenge))?,
+        ),
+        false => None,
+    };
+
+    let k = ZKP::generate_random_number_below(&zkp.q);
+    let (r1, r2) = zkp.commitment(&k).to_bytes_be();
+
+    let challenge = retrying("Challenge", || {
+        let mut client = client.clone();
+        let request = trace.request(AuthenticationChallengeRequest {
+            user: user.to_string(),
+            r1: r1.clone(),
+            r2: r2.clone(),
+            auth_id: commitment.as_ref().map(|c| c.auth_id.clone()).unwrap_or_default(),
+            client: Some(client_metadata()),
+            device_id: device_id.to_string(),
+        });
+        let request = with_deadline(request, timeouts.challenge);
+        async move { within(timeouts.challenge, client.create_authentication_challenge(request)).await }
+    })
+    .await
+    // only an opening can go missing; without one NotFound is about the user
+    .map_err(|status| if committed { lost_challenge(status) } else { status })
+    .map_err(context("Could not request challenge from server", "The challenge", timeouts.challenge))?;
+
+    if let Some(commitment) = &commitment {
+        let opened = challenge::verify_opening(
+            &commitment.commitment,
+            &BigUint::from_bytes_be(&challenge.c),
+            &challenge.nonce,
+        );
+        if !opened || challenge.auth_id != commitment.auth_id {
+            panic!("❌ The server's challenge doesn't match its commitment, refusing to answer");
+        }
+    }
+
+    let c = Challenge::from_bytes_be(&challenge.c);
+    let s = zkp.respond(&k, &c, x).to_bytes_be();
+    // a server under load asks for some work first
+    let pow_nonce = pow::solve(&challenge.auth_id, &challenge.c, challenge.pow_difficulty).ok_or_else(|| {
+        CallError::Failed(tonic::Status::unavailable(format!("refusing the server's {}-bit proof of work", challenge.pow_difficulty)))
+    })?;
+    retrying("Answer", || {
+        let mut client = client.clone();
+        let request = trace.request(AuthenticationAnswerRequest {
+            auth_id: challenge.auth_id.clone(),
+            s: s.clone(),
+            id_token: None,
+            ssh_public_key: ssh_public_key.to_string(),
+            pow_nonce,
+            challenge_token: challenge.challenge_token.clone(),
+        });
+        let request = with_deadline(request, timeouts.verify);
+        async move { within(timeouts.verify, client.verify_authentication(request)).await }
+    })
+    .await
+    .map_err(lost_challenge)
+    .map_err(context("Could not verify authentication with server", "The answer", timeouts.verify))
+}
+
+/// "5 minutes ago from 10.0.0.7:51234 (laptop, linux)"
+fn describe_login(login: &LoginRecord) -> String {
+    let now = clock::unix_millis(std::time::SystemTime::now());
+    let secs = now.saturating_sub(login.unix_millis) / 1000;
+    let ago = match secs {
+        0..=59 => format!("{} seconds ago", secs),
+        60..=3599 => format!("{} minutes ago", secs / 60),
+        3600..=86399 => format!("{} hours ago", secs / 3600),
+        _ => format!("{} days ago", secs / 86400),
+    };
+    let source = if login.source.is_empty() { "an unknown address" } else { login.source.as_str() };
+    match &login.client {
+        Some(client) => format!("{} from {} ({}, {})", ago, source, client.device_id, client.platform),
+        None => format!("{} from {}", ago, source),
+    }
+}
@@ -0,0 +1,187 @@
+//! Approximate memory accounting for the server's in-memory stores.
+//!
+//! Counting entries says little about memory: a user record holds a handful
+//! of numbers as large as p, so the same number of users costs twice as much
+//! with the 2048-bit group as with the 1024-bit one, and client metadata is
+//! free text. [`HeapSize`] estimates what a value owns on the heap (capacity,
+//! not length, plus the container overhead we can see); [`ByteBudget`] keeps a
+//! running total for a store so caps can be enforced in bytes without walking
+//! it on every insert.
+
+use std::collections::HashMap;
+use std::mem::size_of;
+
+use num_bigint::BigUint;
+
+use crate::api::v1::{ClientMetadata, KdfParams as ApiKdfParams, LoginRecord, RegisterRequest};
+use crate::kdf::KdfParams;
+use crate::sync::{AtomicUsize, Ordering};
+use crate::types::{Challenge, Commitment, PublicKey, Response};
+
+/// Bookkeeping per map entry on top of key and value (hash or tree node, pointers)
+pub const MAP_ENTRY_OVERHEAD: usize = 32;
+
+/// Bytes a value owns on the heap, roughly
+pub trait HeapSize {
+    fn heap_size(&self) -> usize;
+
+    /// the value itself plus what it owns
+    fn total_size(&self) -> usize
+    where
+        Self: Sized,
+    {
+        size_of::<Self>() + self.heap_size()
+    }
+}
+
+impl HeapSize for BigUint {
+    fn heap_size(&self) -> usize {
+        // 64-bit digits
+        self.bits().div_ceil(64) as usize * 8
+    }
+}
+
+impl HeapSize for String {
+    fn heap_size(&self) -> usize {
+        self.capacity()
+    }
+}
+
+impl HeapSize for Vec<u8> {
+    fn heap_size(&self) -> usize {
+        self.capacity()
+    }
+}
+
+impl<T: HeapSize> HeapSize for Option<T> {
+    fn heap_size(&self) -> usize {
+        self.as_ref().map_or(0, HeapSize::heap_size)
+    }
+}
+
+impl<K: HeapSize, V: HeapSize> HeapSize for HashMap<K, V> {
+    fn heap_size(&self) -> usize {
+        self.capacity() * (size_of::<K>() + size_of::<V>() + 8)
+            + self.iter().map(|(k, v)| k.heap_size() + v.heap_size()).sum::<usize>()
+    }
+}
+
+impl HeapSize for PublicKey {
+    fn heap_size(&self) -> usize {
+        self.y1.heap_size() + self.y2.heap_size()
+    }
+}
+
+impl HeapSize for Commitment {
+    fn heap_size(&self) -> usize {
+        self.r1.heap_size() + self.r2.heap_size()
+    }
+}
+
+impl HeapSize for Challenge {
+    fn heap_size(&self) -> usize {
+        self.0.heap_size()
+    }
+}
+
+impl HeapSize for Response {
+    fn heap_size(&self) -> usize {
+        self.0.heap_size()
+    }
+}
+
+impl HeapSize for KdfParams {
+    fn heap_size(&self) -> usize {
+        self.salt.heap_size()
+    }
+}
+
+impl HeapSize for ApiKdfParams {
+    fn heap_size(&self) -> usize {
+        self.salt.heap_size()
+    }
+}
+
+impl HeapSize for ClientMetadata {
+    fn heap_size(&self) -> usize {
+        self.device_id.heap_size() + self.app_version.heap_size() + self.platform.heap_size()
+    }
+}
+
+impl HeapSize for LoginRecord {
+    fn heap_size(&self) -> usize {
+        self.source.heap_size() + self.client.heap_size()
+    }
+}
+
+impl HeapSize for RegisterRequest {
+    fn heap_size(&self) -> usize {
+        self.user.heap_size()
+            + self.y1.heap_size()
+            + self.y2.heap_size()
+            + self.idempotency_key.heap_size()
+            + self.kdf.as_ref().map_or(0, |kdf| size_of::<ApiKdfParams>() + kdf.heap_size())
+            + self.beta.heap_size()
+            + self.group.heap_size()
+            + self.hash.heap_size()
+    }
+}
+
+/// Running byte total of a store, with an optional cap
+#[derive(Debug, Default)]
+pub struct ByteBudget {
+    limit: Option<usize>,
+    used: AtomicUsize,
+}
+
+impl ByteBudget {
+    pub fn new(limit: Option<usize>) -> Self {
+        ByteBudget { limit, used: AtomicUsize::new(0) }
+    }
+
+    pub fn limit(&self) -> Option<usize> {
+        self.limit
+    }
+
+    pub fn used(&self) -> usize {
+        self.used.load(Ordering::Relaxed)
+    }
+
+    pub fn add(&self, bytes: usize) {
+        self.used.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    pub fn sub(&self, bytes: usize) {
+        // saturating, so an estimate that changed in between can't wrap
+        let _ = self
+            .used
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |used| Some(used.saturating_sub(bytes)));
+    }
+
+    /// whether `bytes` more fit under the cap
+    pub fn has_room_for(&self, bytes: usize) -> bool {
+        self.limit.is_none_or(|limit| self.used() + bytes <= limit)
+    }
+}
+
+/// What one store holds
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StoreUsage {
+    pub entries: usize,
+    pub bytes: usize,
+}
+
+/// `1.5 MiB` and the like, for the logs
+pub fn format_bytes(bytes: usize) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    match unit {
+        0 => format!("{} B", bytes),
+        _ => format!("{:.1} {}", value, UNITS[unit]),
+    }
+}
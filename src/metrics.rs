@@ -0,0 +1,534 @@
+//! Latency of the big-integer operations, for capacity planning.
+//!
+//! Every [`ZKP::compute_pair`](crate::ZKP::compute_pair) and
+//! [`ZKP::verify`](crate::ZKP::verify) is timed into a histogram per operation
+//! and group size (bits of p). The server serves them with
+//! `--metrics-listen 127.0.0.1:9100` on `GET /metrics` in the Prometheus text
+//! format, as summaries with p50/p95/p99:
+//!
+//! ```text
+//! zkp_crypto_op_seconds{op="verify",group_bits="2048",quantile="0.99"} 0.0123
+//! ```
+//!
+//! When p99 of `verify` climbs towards the request deadline the server needs
+//! more crypto threads or a faster math backend. Buckets are a quarter of a
+//! power of two wide, so quantiles are upper bounds at most ~19% high.
+//!
+//! The server also publishes what its in-memory stores hold
+//! (`zkp_store_bytes`, `zkp_store_entries`), see [`stores`], and what its
+//! retention task has purged (`zkp_retention_purged_total`), see [`retention`],
+//! how its background jobs ran (`zkp_job_runs_total`), see [`jobs`], which
+//! parameters its users are on (`zkp_users`), see [`users`], which of its
+//! user store shards can be written (`zkp_user_store_shard_up`), see [`shards`],
+//! how long syncing user store writes to disk takes
+//! (`zkp_user_store_flush_seconds`), see [`flushes`], and how long each kind
+//! of user store operation takes (`zkp_user_store_op_seconds`), see
+//! [`store_ops`].
+
+use std::collections::BTreeMap;
+use std::convert::Infallible;
+use std::fmt::{self, Write};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant, SystemTime};
+
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, StatusCode};
+use num_bigint::BigUint;
+
+use crate::memory::StoreUsage;
+
+/// Quantiles reported for each histogram
+pub const QUANTILES: [f64; 3] = [0.5, 0.95, 0.99];
+
+/// 4 buckets per power of two of microseconds, up to 2^64
+const BUCKETS: usize = 64 * 4;
+
+/// The timed operations
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Operation {
+    ComputePair,
+    Verify,
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Operation::ComputePair => write!(f, "compute_pair"),
+            Operation::Verify => write!(f, "verify"),
+        }
+    }
+}
+
+/// Log-scale histogram of durations, safe to record into from any thread
+#[derive(Debug)]
+pub struct LatencyHistogram {
+    buckets: Box<[AtomicU64]>,
+    count: AtomicU64,
+    sum_micros: AtomicU64,
+}
+
+impl Default for LatencyHistogram {
+    fn default() -> Self {
+        LatencyHistogram {
+            buckets: (0..BUCKETS).map(|_| AtomicU64::new(0)).collect(),
+            count: AtomicU64::new(0),
+            sum_micros: AtomicU64::new(0),
+        }
+    }
+}
+
+impl LatencyHistogram {
+    pub fn record(&self, elapsed: Duration) {
+        let micros = elapsed.as_micros().min(u64::MAX as u128) as u64;
+        self.buckets[bucket(micros)].fetch_add(1, Ordering::Relaxed);
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.sum_micros.fetch_add(micros, Ordering::Relaxed);
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count.load(Ordering::Relaxed)
+    }
+
+    pub fn sum(&self) -> Duration {
+        Duration::from_micros(self.sum_micros.load(Ordering::Relaxed))
+    }
+
+    /// upper bound of the bucket holding the `quantile` (0.0-1.0) sample, zero when empty
+    pub fn quantile(&self, quantile: f64) -> Duration {
+        let count = self.count();
+        if count == 0 {
+            return Duration::ZERO;
+        }
+        let rank = ((quantile.clamp(0.0, 1.0) * count as f64).ceil() as u64).max(1);
+        let mut seen = 0;
+        for (i, bucket) in self.buckets.iter().enumerate() {
+            seen += bucket.load(Ordering::Relaxed);
+            if seen >= rank {
+                return Duration::from_micros(upper_bound(i));
+            }
+        }
+        Duration::from_micros(upper_bound(BUCKETS - 1))
+    }
+}
+
+/// bucket index: the power of two and which quarter of it
+fn bucket(micros: u64) -> usize {
+    if micros < 4 {
+        return micros as usize;
+    }
+    let msb = 63 - micros.leading_zeros() as usize;
+    let quarter = ((micros >> (msb - 2)) & 3) as usize;
+    msb * 4 + quarter
+}
+
+/// largest value (in microseconds) that lands in bucket `i`
+fn upper_bound(i: usize) -> u64 {
+    if i < 4 {
+        return i as u64;
+    }
+    let (msb, quarter) = (i / 4, (i % 4) as u128);
+    (((4 + quarter + 1) << (msb - 2)) - 1).min(u64::MAX as u128) as u64
+}
+
+/// Histograms by operation and group size
+#[derive(Debug, Default)]
+pub struct CryptoMetrics {
+    histograms: Mutex<BTreeMap<(Operation, u64), Arc<LatencyHistogram>>>,
+}
+
+impl CryptoMetrics {
+    pub fn record(&self, operation: Operation, group_bits: u64, elapsed: Duration) {
+        self.histogram(operation, group_bits).record(elapsed);
+    }
+
+    pub fn histogram(&self, operation: Operation, group_bits: u64) -> Arc<LatencyHistogram> {
+        self.histograms
+            .lock()
+            .unwrap()
+            .entry((operation, group_bits))
+            .or_default()
+            .clone()
+    }
+
+    /// everything recorded so far in the Prometheus text format
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        out.push_str("# HELP zkp_crypto_op_seconds Latency of big-integer operations by group size.\n");
+        out.push_str("# TYPE zkp_crypto_op_seconds summary\n");
+        for ((operation, group_bits), histogram) in self.histograms.lock().unwrap().iter() {
+            let labels = format!("op=\"{}\",group_bits=\"{}\"", operation, group_bits);
+            for quantile in QUANTILES {
+                let _ = writeln!(
+                    out,
+                    "zkp_crypto_op_seconds{{{},quantile=\"{}\"}} {}",
+                    labels,
+                    quantile,
+                    histogram.quantile(quantile).as_secs_f64()
+                );
+            }
+            let _ = writeln!(out, "zkp_crypto_op_seconds_sum{{{}}} {}", labels, histogram.sum().as_secs_f64());
+            let _ = writeln!(out, "zkp_crypto_op_seconds_count{{{}}} {}", labels, histogram.count());
+        }
+        out
+    }
+}
+
+/// the process-wide metrics the library records into
+pub fn global() -> &'static CryptoMetrics {
+    static METRICS: OnceLock<CryptoMetrics> = OnceLock::new();
+    METRICS.get_or_init(CryptoMetrics::default)
+}
+
+/// Size of the server's stores, as last reported
+#[derive(Debug, Default)]
+pub struct StoreMetrics {
+    stores: Mutex<BTreeMap<&'static str, StoreUsage>>,
+}
+
+impl StoreMetrics {
+    pub fn set(&self, store: &'static str, usage: StoreUsage) {
+        self.stores.lock().unwrap().insert(store, usage);
+    }
+
+    /// the last reported sizes in the Prometheus text format, nothing before the first report
+    pub fn render(&self) -> String {
+        let stores = self.stores.lock().unwrap();
+        if stores.is_empty() {
+            return String::new();
+        }
+        let mut out = String::new();
+        out.push_str("# HELP zkp_store_bytes Approximate memory held by an in-memory store.\n");
+        out.push_str("# TYPE zkp_store_bytes gauge\n");
+        for (store, usage) in stores.iter() {
+            let _ = writeln!(out, "zkp_store_bytes{{store=\"{}\"}} {}", store, usage.bytes);
+        }
+        out.push_str("# HELP zkp_store_entries Entries in an in-memory store.\n");
+        out.push_str("# TYPE zkp_store_entries gauge\n");
+        for (store, usage) in stores.iter() {
+            let _ = writeln!(out, "zkp_store_entries{{store=\"{}\"}} {}", store, usage.entries);
+        }
+        out
+    }
+}
+
+/// the store sizes the server reports into
+pub fn stores() -> &'static StoreMetrics {
+    static STORES: OnceLock<StoreMetrics> = OnceLock::new();
+    STORES.get_or_init(StoreMetrics::default)
+}
+
+/// Records the retention task removed, by kind, and when it last ran
+#[derive(Debug, Default)]
+pub struct RetentionMetrics {
+    purged: Mutex<BTreeMap<&'static str, u64>>,
+    last_run: AtomicU64, // unix seconds, 0 before the first run
+}
+
+impl RetentionMetrics {
+    /// one run of the task: what it purged of each kind, at `now`
+    pub fn record(&self, now: SystemTime, purged: &[(&'static str, usize)]) {
+        let mut totals = self.purged.lock().unwrap();
+        for (kind, count) in purged {
+            *totals.entry(kind).or_default() += *count as u64;
+        }
+        self.last_run.store(crate::clock::unix_seconds(now), Ordering::Relaxed);
+    }
+
+    pub fn purged(&self, kind: &str) -> u64 {
+        self.purged.lock().unwrap().get(kind).copied().unwrap_or(0)
+    }
+
+    /// the totals in the Prometheus text format, nothing before the first run
+    pub fn render(&self) -> String {
+        let last_run = self.last_run.load(Ordering::Relaxed);
+        if last_run == 0 {
+            return String::new();
+        }
+        let mut out = String::new();
+        out.push_str("# HELP zkp_retention_purged_total Records removed for being past their retention.\n");
+        out.push_str("# TYPE zkp_retention_purged_total counter\n");
+        for (kind, count) in self.purged.lock().unwrap().iter() {
+            let _ = writeln!(out, "zkp_retention_purged_total{{record=\"{}\"}} {}", kind, count);
+        }
+        out.push_str("# HELP zkp_retention_last_run_timestamp_seconds When the retention task last ran.\n");
+        out.push_str("# TYPE zkp_retention_last_run_timestamp_seconds gauge\n");
+        let _ = writeln!(out, "zkp_retention_last_run_timestamp_seconds {}", last_run);
+        out
+    }
+}
+
+/// what the server's retention task reports into
+pub fn retention() -> &'static RetentionMetrics {
+    static RETENTION: OnceLock<RetentionMetrics> = OnceLock::new();
+    RETENTION.get_or_init(RetentionMetrics::default)
+}
+
+/// Runs of each background job (see scheduler.rs)
+#[derive(Debug, Default)]
+pub struct JobMetrics {
+    jobs: Mutex<BTreeMap<&'static str, JobRuns>>,
+}
+
+/// How often a job ran, and its last run
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct JobRuns {
+    pub runs: u64,
+    pub last_started: u64, // unix seconds
+    pub last_took: Duration,
+}
+
+impl JobMetrics {
+    pub fn record(&self, job: &'static str, started: SystemTime, took: Duration) {
+        let mut jobs = self.jobs.lock().unwrap();
+        let runs = jobs.entry(job).or_default();
+        *runs = JobRuns { runs: runs.runs + 1, last_started: crate::clock::unix_seconds(started), last_took: took };
+    }
+
+    pub fn get(&self, job: &str) -> Option<JobRuns> {
+        self.jobs.lock().unwrap().get(job).copied()
+    }
+
+    /// the runs so far in the Prometheus text format, nothing before the first one
+    pub fn render(&self) -> String {
+        let jobs = self.jobs.lock().unwrap();
+        if jobs.is_empty() {
+            return String::new();
+        }
+        let mut out = String::new();
+        out.push_str("# HELP zkp_job_runs_total Runs of a background job.\n");
+        out.push_str("# TYPE zkp_job_runs_total counter\n");
+        for (job, runs) in jobs.iter() {
+            let _ = writeln!(out, "zkp_job_runs_total{{job=\"{}\"}} {}", job, runs.runs);
+        }
+        out.push_str("# HELP zkp_job_last_run_timestamp_seconds When a background job last started.\n");
+        out.push_str("# TYPE zkp_job_last_run_timestamp_seconds gauge\n");
+        for (job, runs) in jobs.iter() {
+            let _ = writeln!(out, "zkp_job_last_run_timestamp_seconds{{job=\"{}\"}} {}", job, runs.last_started);
+        }
+        out.push_str("# HELP zkp_job_last_run_seconds How long the last run of a background job took.\n");
+        out.push_str("# TYPE zkp_job_last_run_seconds gauge\n");
+        for (job, runs) in jobs.iter() {
+            let _ = writeln!(out, "zkp_job_last_run_seconds{{job=\"{}\"}} {}", job, runs.last_took.as_secs_f64());
+        }
+        out
+    }
+}
+
+/// what the server's scheduler reports into
+pub fn jobs() -> &'static JobMetrics {
+    static JOBS: OnceLock<JobMetrics> = OnceLock::new();
+    JOBS.get_or_init(JobMetrics::default)
+}
+
+/// Users by the parameters they registered with, as last scanned
+#[derive(Debug, Default)]
+pub struct UserMetrics {
+    counts: Mutex<BTreeMap<UserParameters, usize>>,
+}
+
+/// What a user's proofs depend on; everything but the group is a label value
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct UserParameters {
+    pub group: &'static str,
+    pub hash: &'static str,
+    pub generator: &'static str, // "global" or "per_user"
+    pub kdf: &'static str,       // "argon2id" or "legacy"
+}
+
+impl UserMetrics {
+    /// replace the counts with those of a new scan
+    pub fn set(&self, counts: BTreeMap<UserParameters, usize>) {
+        *self.counts.lock().unwrap() = counts;
+    }
+
+    /// the last scan in the Prometheus text format, nothing before the first one
+    pub fn render(&self) -> String {
+        let counts = self.counts.lock().unwrap();
+        if counts.is_empty() {
+            return String::new();
+        }
+        let mut out = String::new();
+        out.push_str("# HELP zkp_users Registered users by group, hash, generator and password derivation.\n");
+        out.push_str("# TYPE zkp_users gauge\n");
+        for (parameters, count) in counts.iter() {
+            let _ = writeln!(
+                out,
+                "zkp_users{{group=\"{}\",hash=\"{}\",generator=\"{}\",kdf=\"{}\"}} {}",
+                parameters.group, parameters.hash, parameters.generator, parameters.kdf, count
+            );
+        }
+        out
+    }
+}
+
+/// what the server's parameter scan reports into
+pub fn users() -> &'static UserMetrics {
+    static USERS: OnceLock<UserMetrics> = OnceLock::new();
+    USERS.get_or_init(UserMetrics::default)
+}
+
+/// Whether each user store shard can be written, as last checked
+#[derive(Debug, Default)]
+pub struct ShardMetrics {
+    shards: Mutex<Vec<(String, bool)>>, // (path, up), in shard order
+}
+
+impl ShardMetrics {
+    pub fn set(&self, shards: Vec<(String, bool)>) {
+        *self.shards.lock().unwrap() = shards;
+    }
+
+    /// the last check in the Prometheus text format, nothing before the first one
+    pub fn render(&self) -> String {
+        let shards = self.shards.lock().unwrap();
+        if shards.is_empty() {
+            return String::new();
+        }
+        let mut out = String::new();
+        out.push_str("# HELP zkp_user_store_shard_up Whether a user store shard can be written.\n");
+        out.push_str("# TYPE zkp_user_store_shard_up gauge\n");
+        for (shard, (path, up)) in shards.iter().enumerate() {
+            let _ = writeln!(out, "zkp_user_store_shard_up{{shard=\"{}\",path=\"{}\"}} {}", shard, path.replace('\\', "\\\\").replace('"', "\\\""), u8::from(*up));
+        }
+        out
+    }
+}
+
+/// what the server's user store job reports into
+pub fn shards() -> &'static ShardMetrics {
+    static SHARDS: OnceLock<ShardMetrics> = OnceLock::new();
+    SHARDS.get_or_init(ShardMetrics::default)
+}
+
+/// How long the user store took to sync its writes, and how many each sync covered
+#[derive(Debug, Default)]
+pub struct FlushMetrics {
+    latency: LatencyHistogram,
+    writes: AtomicU64,
+}
+
+impl FlushMetrics {
+    pub fn record(&self, writes: usize, elapsed: Duration) {
+        self.latency.record(elapsed);
+        self.writes.fetch_add(writes as u64, Ordering::Relaxed);
+    }
+
+    pub fn latency(&self) -> &LatencyHistogram {
+        &self.latency
+    }
+
+    pub fn writes(&self) -> u64 {
+        self.writes.load(Ordering::Relaxed)
+    }
+
+    /// the syncs so far in the Prometheus text format, nothing before the first one
+    pub fn render(&self) -> String {
+        if self.latency.count() == 0 {
+            return String::new();
+        }
+        let mut out = String::new();
+        out.push_str("# HELP zkp_user_store_flush_seconds How long syncing user store writes to disk took.\n");
+        out.push_str("# TYPE zkp_user_store_flush_seconds summary\n");
+        for quantile in QUANTILES {
+            let _ = writeln!(out, "zkp_user_store_flush_seconds{{quantile=\"{}\"}} {}", quantile, self.latency.quantile(quantile).as_secs_f64());
+        }
+        let _ = writeln!(out, "zkp_user_store_flush_seconds_sum {}", self.latency.sum().as_secs_f64());
+        let _ = writeln!(out, "zkp_user_store_flush_seconds_count {}", self.latency.count());
+        out.push_str("# HELP zkp_user_store_flushed_writes_total User store writes synced to disk.\n");
+        out.push_str("# TYPE zkp_user_store_flushed_writes_total counter\n");
+        let _ = writeln!(out, "zkp_user_store_flushed_writes_total {}", self.writes());
+        out
+    }
+}
+
+/// what the user store reports its syncs into
+pub fn flushes() -> &'static FlushMetrics {
+    static FLUSHES: OnceLock<FlushMetrics> = OnceLock::new();
+    FLUSHES.get_or_init(FlushMetrics::default)
+}
+
+/// Latency of user store operations by kind, and how many were slow
+#[derive(Debug, Default)]
+pub struct StoreOpMetrics {
+    histograms: Mutex<BTreeMap<&'static str, Arc<LatencyHistogram>>>,
+    slow: Mutex<BTreeMap<&'static str, u64>>,
+}
+
+impl StoreOpMetrics {
+    pub fn record(&self, op: &'static str, elapsed: Duration, slow: bool) {
+        self.histogram(op).record(elapsed);
+        if slow {
+            *self.slow.lock().unwrap().entry(op).or_default() += 1;
+        }
+    }
+
+    pub fn histogram(&self, op: &'static str) -> Arc<LatencyHistogram> {
+        self.histograms.lock().unwrap().entry(op).or_default().clone()
+    }
+
+    /// how many `op`s took the slow threshold or longer
+    pub fn slow(&self, op: &str) -> u64 {
+        self.slow.lock().unwrap().get(op).copied().unwrap_or_default()
+    }
+
+    /// the operations so far in the Prometheus text format, nothing before the first one
+    pub fn render(&self) -> String {
+        let histograms = self.histograms.lock().unwrap();
+        if histograms.is_empty() {
+            return String::new();
+        }
+        let mut out = String::new();
+        out.push_str("# HELP zkp_user_store_op_seconds Latency of user store operations.\n");
+        out.push_str("# TYPE zkp_user_store_op_seconds summary\n");
+        for (op, histogram) in histograms.iter() {
+            for quantile in QUANTILES {
+                let _ = writeln!(out, "zkp_user_store_op_seconds{{op=\"{}\",quantile=\"{}\"}} {}", op, quantile, histogram.quantile(quantile).as_secs_f64());
+            }
+            let _ = writeln!(out, "zkp_user_store_op_seconds_sum{{op=\"{}\"}} {}", op, histogram.sum().as_secs_f64());
+            let _ = writeln!(out, "zkp_user_store_op_seconds_count{{op=\"{}\"}} {}", op, histogram.count());
+        }
+        out.push_str("# HELP zkp_user_store_slow_ops_total User store operations that took --slow-store-ms or longer.\n");
+        out.push_str("# TYPE zkp_user_store_slow_ops_total counter\n");
+        for op in histograms.keys() {
+            let _ = writeln!(out, "zkp_user_store_slow_ops_total{{op=\"{}\"}} {}", op, self.slow(op));
+        }
+        out
+    }
+}
+
+/// what the user store times its operations into
+pub fn store_ops() -> &'static StoreOpMetrics {
+    static STORE_OPS: OnceLock<StoreOpMetrics> = OnceLock::new();
+    STORE_OPS.get_or_init(StoreOpMetrics::default)
+}
+
+/// run `f`, recording how long it took for a group modulo `p`
+pub(crate) fn timed<T>(operation: Operation, p: &BigUint, f: impl FnOnce() -> T) -> T {
+    let start = Instant::now();
+    let result = f();
+    global().record(operation, p.bits(), start.elapsed());
+    result
+}
+
+/// serve `GET /metrics` (and `GET /openapi.json`, see openapi.rs) on `addr` until the process exits
+pub async fn serve(addr: SocketAddr) -> Result<(), hyper::Error> {
+    let make_service = make_service_fn(|_| async { Ok::<_, Infallible>(service_fn(handle)) });
+    hyper::Server::try_bind(&addr)?.serve(make_service).await
+}
+
+async fn handle(request: Request<Body>) -> Result<Response<Body>, Infallible> {
+    let text = "text/plain; version=0.0.4";
+    let (status, content_type, body) = match (request.method(), request.uri().path()) {
+        (&Method::GET, "/metrics") => (StatusCode::OK, text, global().render() + &stores().render() + &retention().render() + &jobs().render() + &users().render() + &shards().render() + &flushes().render() + &store_ops().render()),
+        (&Method::GET, crate::openapi::PATH) => (StatusCode::OK, "application/json", crate::openapi::document().to_string()),
+        (&Method::GET, _) => (StatusCode::NOT_FOUND, text, "not found\n".to_string()),
+        _ => (StatusCode::METHOD_NOT_ALLOWED, text, "use GET\n".to_string()),
+    };
+    Ok(Response::builder()
+        .status(status)
+        .header("content-type", content_type)
+        .body(Body::from(body))
+        .expect("static headers are valid"))
+}
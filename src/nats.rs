@@ -0,0 +1,165 @@
+//! A NATS publisher for audit events ([`crate::publish`]).
+//!
+//! Just enough of the core NATS protocol to publish: CONNECT, with a user and
+//! password or a token from the URL, then `PUB <subject>.<action>` for each
+//! event and a PING whose PONG confirms the server has taken the batch. The
+//! server's own PINGs are answered along the way. Plain TCP only: a server
+//! that requires TLS is refused at connect time, so put a local leaf node or
+//! a TLS sidecar in between.
+
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+use serde_json::Value;
+
+use crate::publish::{Message, Transport};
+
+/// The port when the URL has none
+pub const DEFAULT_PORT: u16 = 4222;
+/// How long to wait for the server
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Where and as whom to connect, from `nats://[user:password@|token@]host[:port]`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NatsUrl {
+    pub address: String, // host:port
+    pub user: Option<(String, String)>,
+    pub token: Option<String>,
+}
+
+impl std::str::FromStr for NatsUrl {
+    type Err = String;
+
+    fn from_str(url: &str) -> Result<Self, Self::Err> {
+        let rest = url.strip_prefix("nats://").unwrap_or(url);
+        if rest.contains("://") {
+            return Err(format!("{:?} is not a nats:// URL (tls:// isn't supported)", url));
+        }
+        let (credentials, host) = match rest.rsplit_once('@') {
+            Some((credentials, host)) => (Some(credentials), host),
+            None => (None, rest),
+        };
+        let host = host.trim_end_matches('/');
+        if host.is_empty() {
+            return Err(format!("{:?} has no host", url));
+        }
+        let address = match host.rsplit_once(':') {
+            Some((_, port)) if port.parse::<u16>().is_ok() => host.to_string(),
+            _ => format!("{}:{}", host, DEFAULT_PORT),
+        };
+        let (user, token) = match credentials.map(|credentials| credentials.split_once(':')) {
+            None => (None, None),
+            Some(Some((user, password))) => (Some((user.to_string(), password.to_string())), None),
+            Some(None) => (None, credentials.map(str::to_string)),
+        };
+        Ok(NatsUrl { address, user, token })
+    }
+}
+
+/// Publishes to `<subject>.<action>` on a NATS server
+#[derive(Debug)]
+pub struct Nats {
+    url: NatsUrl,
+    subject: String,
+    connection: Option<BufReader<TcpStream>>,
+}
+
+impl Nats {
+    pub fn new(url: NatsUrl, subject: &str) -> Self {
+        Nats { url, subject: subject.to_string(), connection: None }
+    }
+
+    fn connect(&self) -> io::Result<BufReader<TcpStream>> {
+        let address = self.url.address.to_socket_addrs()?.next().ok_or_else(|| io::Error::other(format!("{} has no address", self.url.address)))?;
+        let stream = TcpStream::connect_timeout(&address, TIMEOUT)?;
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        stream.set_write_timeout(Some(TIMEOUT))?;
+        let mut connection = BufReader::new(stream);
+
+        let line = read_line(&mut connection)?;
+        let info: Value = line
+            .strip_prefix("INFO ")
+            .and_then(|info| serde_json::from_str(info).ok())
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, format!("expected INFO, got {:?}", line)))?;
+        if info["tls_required"] == true {
+            return Err(io::Error::other("the server requires TLS, which this client doesn't speak"));
+        }
+        let mut connect = serde_json::json!({
+            "verbose": false,
+            "pedantic": false,
+            "name": "zkp-auth",
+            "lang": "rust",
+            "version": env!("CARGO_PKG_VERSION"),
+        });
+        if let Some((user, password)) = &self.url.user {
+            connect["user"] = Value::from(user.as_str());
+            connect["pass"] = Value::from(password.as_str());
+        }
+        if let Some(token) = &self.url.token {
+            connect["auth_token"] = Value::from(token.as_str());
+        }
+        // the PONG also says the server took the credentials
+        connection.get_mut().write_all(format!("CONNECT {}\r\nPING\r\n", connect).as_bytes())?;
+        await_pong(&mut connection)?;
+        Ok(connection)
+    }
+
+    /// send `commands` and wait for the PONG of a PING after them
+    fn round_trip(&mut self, commands: &[u8]) -> io::Result<()> {
+        if self.connection.is_none() {
+            self.connection = Some(self.connect()?);
+        }
+        let connection = self.connection.as_mut().expect("just connected");
+        let result = connection.get_mut().write_all(commands).and_then(|_| connection.get_mut().write_all(b"PING\r\n")).and_then(|_| await_pong(connection));
+        if result.is_err() {
+            self.connection = None;
+        }
+        result
+    }
+}
+
+impl Transport for Nats {
+    fn publish(&mut self, batch: &[Message]) -> io::Result<()> {
+        let mut commands = Vec::new();
+        for message in batch {
+            commands.extend_from_slice(format!("PUB {}.{} {}\r\n", self.subject, message.action, message.payload.len()).as_bytes());
+            commands.extend_from_slice(message.payload.as_bytes());
+            commands.extend_from_slice(b"\r\n");
+        }
+        // a connection the server dropped while idle shows only now: one more go on a new one
+        let reused = self.connection.is_some();
+        match self.round_trip(&commands) {
+            Err(_) if reused => self.round_trip(&commands),
+            result => result,
+        }
+    }
+
+    fn idle(&mut self) -> io::Result<()> {
+        match self.connection {
+            Some(_) => self.round_trip(&[]),
+            None => Ok(()),
+        }
+    }
+}
+
+/// read until PONG, answering the server's PINGs; `-ERR` fails
+fn await_pong(connection: &mut BufReader<TcpStream>) -> io::Result<()> {
+    loop {
+        let line = read_line(connection)?;
+        match line.as_str() {
+            "PONG" => return Ok(()),
+            "PING" => connection.get_mut().write_all(b"PONG\r\n")?,
+            error if error.starts_with("-ERR") => return Err(io::Error::other(format!("the server said {}", error))),
+            _ => {} // +OK, a new INFO
+        }
+    }
+}
+
+fn read_line(connection: &mut BufReader<TcpStream>) -> io::Result<String> {
+    let mut line = String::new();
+    if connection.read_line(&mut line)? == 0 {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "the server closed the connection"));
+    }
+    Ok(line.trim_end().to_string())
+}
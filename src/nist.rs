@@ -0,0 +1,236 @@
+//! Chaum-Pedersen over NIST P-256 (the `p256` feature).
+//!
+//! For deployments whose approval process only admits NIST curves. Unlike
+//! [`crate::ristretto`] and [`crate::bls`] this backend also speaks the
+//! interactive login: a user registered with group `p256` (see
+//! [`crate::group`]) sends compressed SEC1 points for (y1, y2) and (r1, r2)
+//! and a 32-byte big-endian s, and the server's challenge c (a number below
+//! the Z_p group's q) is reduced mod the curve order. Non-interactive proofs
+//! come back as the usual [`NonInteractiveProof`], 98 bytes of numbers.
+//!
+//! alpha is the standard P-256 base point; beta is hashed to the curve with
+//! RFC 9380 hash-to-curve (P256_XMD:SHA-256_SSWU_RO_), so nobody knows its
+//! discrete log.
+
+use num_bigint::BigUint;
+use p256::elliptic_curve::hash2curve::{ExpandMsgXmd, GroupDigest};
+use p256::elliptic_curve::sec1::{FromEncodedPoint, ToEncodedPoint};
+use p256::elliptic_curve::{Field, PrimeField};
+use p256::{AffinePoint, EncodedPoint, NistP256, ProjectivePoint, Scalar};
+use sha2::{Digest, Sha256, Sha512};
+
+use crate::clock::Clock;
+use crate::nizk::{absorb, new_proof_id, NonInteractiveProof, ValidityWindow};
+use crate::rng;
+
+/// Domain separation for the Fiat-Shamir challenge
+const DOMAIN: &[u8] = b"zkp-auth/non-interactive/p256/v1";
+/// Hash-to-curve domain separation tag for beta
+const BETA_DST: &[u8] = b"ZKP-AUTH-V01-CS01-with-P256_XMD:SHA-256_SSWU_RO_";
+
+/// Bytes of a compressed point
+pub const POINT_LEN: usize = 33;
+/// Bytes of (r1, r2, s)
+pub const PROOF_LEN: usize = 2 * POINT_LEN + 32;
+/// Bytes of (y1, y2)
+pub const PUBLIC_KEY_LEN: usize = 2 * POINT_LEN;
+
+/// The group: two generators of P-256
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct P256 {
+    pub alpha: ProjectivePoint,
+    pub beta: ProjectivePoint,
+}
+
+/// (y1, y2) = (x * alpha, x * beta)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct P256PublicKey {
+    pub y1: ProjectivePoint,
+    pub y2: ProjectivePoint,
+}
+
+/// (r1, r2) = (k * alpha, k * beta)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct P256Commitment {
+    pub r1: ProjectivePoint,
+    pub r2: ProjectivePoint,
+}
+
+impl Default for P256 {
+    fn default() -> Self {
+        P256 {
+            alpha: ProjectivePoint::GENERATOR,
+            beta: NistP256::hash_from_bytes::<ExpandMsgXmd<Sha256>>(&[b"beta"], &[BETA_DST])
+                .expect("the DST is short enough"),
+        }
+    }
+}
+
+impl P256 {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// a uniformly random scalar, from the same generator as [`crate::ZKP`]'s numbers
+    pub fn random_scalar() -> Scalar {
+        rng::with_rng(|rng| Scalar::random(rng))
+    }
+
+    pub fn public_key(&self, x: &Scalar) -> P256PublicKey {
+        P256PublicKey { y1: self.alpha * x, y2: self.beta * x }
+    }
+
+    /// the commitment for a fresh random nonce `k`
+    pub fn commitment(&self, k: &Scalar) -> P256Commitment {
+        P256Commitment { r1: self.alpha * k, r2: self.beta * k }
+    }
+
+    /// s = k - c * x
+    pub fn respond(&self, k: &Scalar, c: &Scalar, x: &Scalar) -> Scalar {
+        k - &(c * x)
+    }
+
+    /// check r1 = s * alpha + c * y1 and r2 = s * beta + c * y2
+    pub fn verify(&self, key: &P256PublicKey, commitment: &P256Commitment, c: &Scalar, s: &Scalar) -> bool {
+        commitment.r1 == self.alpha * s + key.y1 * c && commitment.r2 == self.beta * s + key.y2 * c
+    }
+
+    /// [`P256::verify`] on the encodings sent over gRPC; false for anything that doesn't decode
+    pub fn verify_wire(&self, y1: &[u8], y2: &[u8], r1: &[u8], r2: &[u8], c: &[u8], s: &[u8]) -> bool {
+        let (Some(y1), Some(y2), Some(r1), Some(r2), Some(s)) = (decode(y1), decode(y2), decode(r1), decode(r2), scalar_from_wire(s)) else {
+            return false;
+        };
+        self.verify(&P256PublicKey { y1, y2 }, &P256Commitment { r1, r2 }, &challenge_from_wire(c), &s)
+    }
+
+    /// prove knowledge of x for `window`, bound to `context` (e.g. who the proof is for)
+    pub fn prove_non_interactive(&self, x: &Scalar, window: ValidityWindow, context: &[u8]) -> NonInteractiveProof {
+        let key = self.public_key(x);
+        let k = Self::random_scalar();
+        let commitment = self.commitment(&k);
+        let id = new_proof_id();
+        let c = self.fiat_shamir(&key, &commitment, &id, &window, context);
+        let s = self.respond(&k, &c, x);
+        NonInteractiveProof {
+            id,
+            r1: encode(&commitment.r1),
+            r2: encode(&commitment.r2),
+            s: s.to_bytes().to_vec(),
+            window,
+        }
+    }
+
+    /// check `proof` for `key` and `context`, and that `clock` is inside its window
+    pub fn verify_non_interactive(&self, key: &P256PublicKey, proof: &NonInteractiveProof, context: &[u8], clock: impl Into<Clock>) -> bool {
+        if !proof.window.contains(clock) || to_compact(proof).is_none() {
+            return false;
+        }
+        let (Some(r1), Some(r2), Some(s)) = (decode(&proof.r1), decode(&proof.r2), scalar_from_wire(&proof.s)) else {
+            return false;
+        };
+        let commitment = P256Commitment { r1, r2 };
+        let c = self.fiat_shamir(key, &commitment, &proof.id, &proof.window, context);
+        self.verify(key, &commitment, &c, &s)
+    }
+
+    /// H(domain | generators | y1 | y2 | r1 | r2 | id | window | context) mod n,
+    /// laid out like [`crate::nizk`]'s hash
+    fn fiat_shamir(&self, key: &P256PublicKey, commitment: &P256Commitment, id: &str, window: &ValidityWindow, context: &[u8]) -> Scalar {
+        let points = [self.alpha, self.beta, key.y1, key.y2, commitment.r1, commitment.r2].map(|point| encode(&point));
+        let mut hasher = Sha512::new();
+        absorb(&mut hasher, DOMAIN, &points, id, window, context);
+        scalar_from_biguint(&BigUint::from_bytes_be(&hasher.finalize()))
+    }
+}
+
+impl P256PublicKey {
+    /// y1 | y2, compressed
+    pub fn to_bytes(&self) -> Vec<u8> {
+        [encode(&self.y1), encode(&self.y2)].concat()
+    }
+
+    /// None unless both halves are valid compressed points
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() != PUBLIC_KEY_LEN {
+            return None;
+        }
+        Some(P256PublicKey { y1: decode(&bytes[..POINT_LEN])?, y2: decode(&bytes[POINT_LEN..])? })
+    }
+
+    /// (y1, y2) as sent in `RegisterRequest`
+    pub fn to_wire(&self) -> (Vec<u8>, Vec<u8>) {
+        (encode(&self.y1), encode(&self.y2))
+    }
+}
+
+impl P256Commitment {
+    /// (r1, r2) as sent in `AuthenticationChallengeRequest`
+    pub fn to_wire(&self) -> (Vec<u8>, Vec<u8>) {
+        (encode(&self.r1), encode(&self.r2))
+    }
+}
+
+/// r1 | r2 | s of a P-256 proof, None if a field has the wrong length
+pub fn to_compact(proof: &NonInteractiveProof) -> Option<[u8; PROOF_LEN]> {
+    if proof.r1.len() != POINT_LEN || proof.r2.len() != POINT_LEN || proof.s.len() != 32 {
+        return None;
+    }
+    let mut bytes = [0u8; PROOF_LEN];
+    bytes[..POINT_LEN].copy_from_slice(&proof.r1);
+    bytes[POINT_LEN..2 * POINT_LEN].copy_from_slice(&proof.r2);
+    bytes[2 * POINT_LEN..].copy_from_slice(&proof.s);
+    Some(bytes)
+}
+
+/// rebuild a proof from its 98 bytes and the id and window sent alongside
+pub fn from_compact(bytes: &[u8; PROOF_LEN], id: String, window: ValidityWindow) -> NonInteractiveProof {
+    NonInteractiveProof {
+        id,
+        r1: bytes[..POINT_LEN].to_vec(),
+        r2: bytes[POINT_LEN..2 * POINT_LEN].to_vec(),
+        s: bytes[2 * POINT_LEN..].to_vec(),
+        window,
+    }
+}
+
+/// a server's challenge (big-endian, below the Z_p group's q) as a scalar
+pub fn challenge_from_wire(c: &[u8]) -> Scalar {
+    scalar_from_biguint(&BigUint::from_bytes_be(c))
+}
+
+/// s as sent in `AuthenticationAnswerRequest`: big-endian, left-padded to 32 bytes
+pub fn scalar_to_wire(s: &Scalar) -> Vec<u8> {
+    s.to_bytes().to_vec()
+}
+
+/// x reduced into the scalar field, e.g. a secret derived with [`crate::kdf`]
+pub fn scalar_from_biguint(x: &BigUint) -> Scalar {
+    let order = BigUint::from_bytes_be(&(-Scalar::ONE).to_bytes()) + 1u32;
+    let reduced = (x % order).to_bytes_be();
+    let mut bytes = [0u8; 32];
+    bytes[32 - reduced.len()..].copy_from_slice(&reduced);
+    Scalar::from_repr(bytes.into()).expect("reduced below the order")
+}
+
+/// a canonical scalar, with leading zero bytes put back if they were dropped
+fn scalar_from_wire(bytes: &[u8]) -> Option<Scalar> {
+    if bytes.len() > 32 {
+        return None;
+    }
+    let mut padded = [0u8; 32];
+    padded[32 - bytes.len()..].copy_from_slice(bytes);
+    Option::from(Scalar::from_repr(padded.into()))
+}
+
+fn encode(point: &ProjectivePoint) -> Vec<u8> {
+    point.to_affine().to_encoded_point(true).as_bytes().to_vec()
+}
+
+/// a compressed point other than the identity
+fn decode(bytes: &[u8]) -> Option<ProjectivePoint> {
+    if bytes.len() != POINT_LEN {
+        return None;
+    }
+    let point = EncodedPoint::from_bytes(bytes).ok()?;
+    Option::<AffinePoint>::from(AffinePoint::from_encoded_point(&point)).map(ProjectivePoint::from)
+}
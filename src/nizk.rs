@@ -0,0 +1,159 @@
+//! Non-interactive Chaum-Pedersen proofs (Fiat-Shamir) with a validity window.
+//!
+//! Instead of a server picking c, the prover takes c = H(statement, r1, r2,
+//! window, context) (see [`crate::sigma::prove_with`]). The not-before/not-after
+//! window is part of that hash, so it can't be changed without redoing the
+//! proof, and verifiers check it against their [`Clock`] (give or take its
+//! skew): an exported proof simply stops being accepted.
+//! Each proof also gets a random id in the transcript so it can be revoked
+//! early (see [`crate::revocation`]).
+
+use std::time::{Duration, SystemTime};
+
+use num_bigint::BigUint;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::clock::{unix_seconds, Clock};
+use crate::params::hex_bytes;
+use crate::revocation::PROOF_ID_LEN;
+use crate::sigma::{self, ChaumPedersen};
+use crate::types::{Challenge, Commitment, PublicKey, Response};
+use crate::ZKP;
+
+/// Domain separation for the Fiat-Shamir challenge
+const DOMAIN: &[u8] = b"zkp-auth/non-interactive/v1";
+
+/// Unix times (seconds) between which a proof is valid, both inclusive
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ValidityWindow {
+    pub not_before: u64,
+    pub not_after: u64,
+}
+
+impl ValidityWindow {
+    /// valid from `now` for `lifetime`
+    pub fn starting_at(now: SystemTime, lifetime: Duration) -> Self {
+        let not_before = unix_seconds(now);
+        ValidityWindow {
+            not_before,
+            not_after: not_before.saturating_add(lifetime.as_secs()),
+        }
+    }
+
+    /// valid from the current time for `lifetime`
+    pub fn starting_now(lifetime: Duration) -> Self {
+        Self::starting_at(SystemTime::now(), lifetime)
+    }
+
+    /// is the clock's time inside the window, give or take its skew?
+    pub fn contains(&self, clock: impl Into<Clock>) -> bool {
+        clock.into().within(self.not_before, self.not_after)
+    }
+}
+
+/// A proof of knowledge of x for (y1, y2) that anyone can check until it expires
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NonInteractiveProof {
+    pub id: String, // random, for revocation
+    #[serde(with = "hex_bytes")]
+    pub r1: Vec<u8>,
+    #[serde(with = "hex_bytes")]
+    pub r2: Vec<u8>,
+    #[serde(with = "hex_bytes")]
+    pub s: Vec<u8>,
+    pub window: ValidityWindow,
+}
+
+impl ZKP {
+    /// prove knowledge of x for `window`, bound to `context` (e.g. who the proof is for)
+    pub fn prove_non_interactive(&self, x: &BigUint, window: ValidityWindow, context: &[u8]) -> NonInteractiveProof {
+        let key = self.public_key(x);
+        let mut id = String::new();
+        let (commitment, s) = sigma::prove_with(&ChaumPedersen(self), &key, x, |numbers| {
+            id = new_proof_id();
+            Challenge(fiat_shamir(DOMAIN, self, numbers, &id, &window, context))
+        });
+        let (r1, r2) = commitment.to_bytes_be();
+        NonInteractiveProof {
+            id,
+            r1,
+            r2,
+            s: s.to_bytes_be(),
+            window,
+        }
+    }
+
+    /// check `proof` for (y1, y2) and `context`, and that `clock` is inside its window
+    /// (a plain `SystemTime` allows no skew)
+    pub fn verify_non_interactive(
+        &self,
+        y1: &BigUint,
+        y2: &BigUint,
+        proof: &NonInteractiveProof,
+        context: &[u8],
+        clock: impl Into<Clock>,
+    ) -> bool {
+        if !proof.window.contains(clock) {
+            return false;
+        }
+        let s = Response::from_bytes_be(&proof.s);
+        if s.0 >= self.q {
+            return false;
+        }
+        let key = PublicKey { y1: y1.clone(), y2: y2.clone() };
+        let commitment = Commitment::from_bytes_be(&proof.r1, &proof.r2);
+        // with y = r = 0 the equations hold for any s, so check the numbers first, as verify_batch does
+        if ![&key.y1, &key.y2, &commitment.r1, &commitment.r2].into_iter().all(|n| self.is_element(n)) {
+            return false;
+        }
+        sigma::verify_with(&ChaumPedersen(self), &key, &commitment, &s, |numbers| {
+            Challenge(fiat_shamir(DOMAIN, self, numbers, &proof.id, &proof.window, context))
+        })
+    }
+}
+
+/// a fresh random proof id
+pub(crate) fn new_proof_id() -> String {
+    ZKP::generate_random_string(PROOF_ID_LEN)
+}
+
+/// H(domain | group | numbers | id | window | context) mod q, every field length-prefixed
+pub(crate) fn fiat_shamir(
+    domain: &[u8],
+    zkp: &ZKP,
+    numbers: &[BigUint],
+    id: &str,
+    window: &ValidityWindow,
+    context: &[u8],
+) -> BigUint {
+    let elements: Vec<Vec<u8>> = [&zkp.p, &zkp.q, &zkp.alpha, &zkp.beta].into_iter().chain(numbers).map(|n| n.to_bytes_be()).collect();
+    let mut hasher = Sha256::new();
+    absorb(&mut hasher, domain, &elements, id, window, context);
+    BigUint::from_bytes_be(&hasher.finalize()) % &zkp.q
+}
+
+/// feed a transcript to `hasher`: domain, then the group's elements (generators
+/// first), id, window and context, every variable-length field length-prefixed.
+/// The one layout for every group's Fiat-Shamir hash
+pub(crate) fn absorb(
+    hasher: &mut impl Digest,
+    domain: &[u8],
+    elements: &[impl AsRef<[u8]>],
+    id: &str,
+    window: &ValidityWindow,
+    context: &[u8],
+) {
+    hasher.update(domain);
+    for element in elements {
+        let bytes = element.as_ref();
+        hasher.update((bytes.len() as u32).to_be_bytes());
+        hasher.update(bytes);
+    }
+    hasher.update((id.len() as u32).to_be_bytes());
+    hasher.update(id.as_bytes());
+    hasher.update(window.not_before.to_be_bytes());
+    hasher.update(window.not_after.to_be_bytes());
+    hasher.update((context.len() as u32).to_be_bytes());
+    hasher.update(context);
+}
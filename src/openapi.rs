@@ -0,0 +1,40 @@
+//! OpenAPI 3.1 description of the REST gateway, for generating HTTP clients.
+//!
+//! build.rs writes it from the proto descriptors, so it can't drift from the
+//! gRPC API. The gateway mapping is one route per unary RPC,
+//! `POST /v1/<service>/<method>` in kebab case ([`route`]), e.g.
+//! `/v1/auth/register` or `/v1/admin/set-user-enabled`. Bodies are the proto3
+//! JSON form of the messages: camelCase fields, bytes as base64 and 64-bit
+//! numbers as strings. Field rules become `required` and `maxLength`. Errors
+//! are problem+json as answered by [`crate::http_status`]. Streaming RPCs
+//! (BulkRegister) have no REST form and are left out, and Admin routes need
+//! the admin token as a bearer token.
+//!
+//! The server serves the document on `GET /openapi.json` next to `/metrics`
+//! (`--metrics-listen`).
+
+/// where the document is served
+pub const PATH: &str = "/openapi.json";
+
+/// the document, as JSON
+pub fn document() -> &'static str {
+    include_str!(concat!(env!("OUT_DIR"), "/zkp_auth.v1.openapi.json"))
+}
+
+/// the gateway route of an RPC, e.g. `("Auth", "CreateAuthenticationChallenge")`
+/// -> `/v1/auth/create-authentication-challenge`
+pub fn route(service: &str, method: &str) -> String {
+    format!("/v1/{}/{}", kebab_case(service), kebab_case(method))
+}
+
+/// CreateAuthenticationChallenge -> create-authentication-challenge
+fn kebab_case(name: &str) -> String {
+    let mut kebab = String::with_capacity(name.len() + 4);
+    for (i, c) in name.chars().enumerate() {
+        if c.is_ascii_uppercase() && i > 0 {
+            kebab.push('-');
+        }
+        kebab.push(c.to_ascii_lowercase());
+    }
+    kebab
+}
@@ -0,0 +1,246 @@
+//! Outbox of audit events on their way to a webhook.
+//!
+//! Every audit event is appended to the outbox journal and synced to disk
+//! before the request that caused it is answered, so a change a client has
+//! seen always has its event on disk, whatever happens to the server or the
+//! receiver afterwards. [`Outbox::deliver`] (the server's `events` job) then
+//! sends what is due, oldest first, as `{"id": ..., "event": {...}}`. An event
+//! the receiver doesn't take is tried again after 1s, 2s, 4s and so on, up to
+//! an hour apart. After `max_attempts` it moves to the dead letters
+//! (`<outbox>.dead`, JSON lines with the attempts and the last error) for an
+//! operator to look at, and is logged. Delivery is at least once: the id
+//! stays the same across attempts, so a receiver can skip what it has seen.
+//!
+//! Erasing a user tombstones their events here too, like in the JSON lines
+//! audit log (see [`crate::audit`]), both the pending ones and the dead letters.
+
+use std::fs::{self, File, OpenOptions};
+use std::future::Future;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::audit::{AuditEvent, AuditSink};
+use crate::clock::unix_millis;
+use crate::ulid::UlidGenerator;
+
+/// Attempts before an event goes to the dead letters, by default
+pub const MAX_ATTEMPTS: u32 = 10;
+/// Wait after the first failed attempt; it doubles with every other one
+const FIRST_RETRY: Duration = Duration::from_secs(1);
+/// Longest wait between two attempts
+const LAST_RETRY: Duration = Duration::from_secs(60 * 60);
+
+/// One event waiting in the outbox, or given up on in the dead letters
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OutboxEntry {
+    pub id: String,   // a ULID, the same on every attempt
+    pub event: Value, // as in the JSON lines audit log
+    #[serde(default)]
+    pub attempts: u32,
+    #[serde(default)]
+    pub next_attempt: u64, // unix millis
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub last_error: String,
+}
+
+impl OutboxEntry {
+    /// what is sent to the receiver
+    pub fn body(&self) -> String {
+        serde_json::json!({ "id": self.id, "event": self.event }).to_string()
+    }
+}
+
+/// What one delivery run did
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Delivery {
+    pub delivered: usize,
+    pub retrying: usize,
+    pub dead: usize, // moved to the dead letters
+}
+
+/// The outbox journal and the events in it
+#[derive(Debug)]
+pub struct Outbox {
+    path: PathBuf,
+    dead_letters: PathBuf,
+    max_attempts: u32,
+    ids: UlidGenerator,
+    state: Mutex<(File, Vec<OutboxEntry>)>, // the journal to append to, and what is in it
+}
+
+impl Outbox {
+    /// open the outbox at `path`, creating it if need be, with the events still in it
+    pub fn open(path: &Path, max_attempts: u32) -> io::Result<Self> {
+        let entries = match fs::read_to_string(path) {
+            Ok(text) => read_entries(&text, path)?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e),
+        };
+        let dead_letters = PathBuf::from(format!("{}.dead", path.display()));
+        // rewritten rather than appended to, so a line cut off by a crash goes
+        let file = rewrite(path, &entries)?;
+        Ok(Outbox { path: path.to_path_buf(), dead_letters, max_attempts, ids: UlidGenerator::default(), state: Mutex::new((file, entries)) })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn dead_letters(&self) -> &Path {
+        &self.dead_letters
+    }
+
+    /// the events waiting, oldest first
+    pub fn pending(&self) -> Vec<OutboxEntry> {
+        self.state.lock().unwrap().1.clone()
+    }
+
+    /// add an event, on disk before this returns; its id
+    pub fn enqueue(&self, event: Value, now: SystemTime) -> io::Result<String> {
+        let entry = OutboxEntry { id: self.ids.next(now).to_string(), event, attempts: 0, next_attempt: 0, last_error: String::new() };
+        let line = format!("{}\n", serde_json::to_string(&entry).expect("entries always serialize"));
+        let mut state = self.state.lock().unwrap();
+        state.0.write_all(line.as_bytes())?;
+        state.0.sync_data()?;
+        state.1.push(entry.clone());
+        Ok(entry.id)
+    }
+
+    /// hand every event that is due to `send`, one at a time, oldest first:
+    /// those it takes leave the outbox, the others are tried again later or,
+    /// out of attempts, move to the dead letters
+    pub async fn deliver<F, Fut>(&self, now: SystemTime, mut send: F) -> io::Result<Delivery>
+    where
+        F: FnMut(String) -> Fut,
+        Fut: Future<Output = Result<(), String>>,
+    {
+        let due: Vec<OutboxEntry> = self.state.lock().unwrap().1.iter().filter(|entry| entry.next_attempt <= unix_millis(now)).cloned().collect();
+        if due.is_empty() {
+            return Ok(Delivery::default());
+        }
+        // sent without the lock, so requests go on enqueueing meanwhile
+        let mut outcomes = Vec::new();
+        for entry in due {
+            let outcome = send(entry.body()).await;
+            outcomes.push((entry.id, outcome));
+        }
+
+        let mut delivery = Delivery::default();
+        let mut dead = Vec::new();
+        let mut state = self.state.lock().unwrap();
+        for (id, outcome) in outcomes {
+            let Some(position) = state.1.iter().position(|entry| entry.id == id) else {
+                continue; // erased meanwhile
+            };
+            let Err(error) = outcome else {
+                state.1.remove(position);
+                delivery.delivered += 1;
+                continue;
+            };
+            let entry = &mut state.1[position];
+            entry.attempts += 1;
+            entry.last_error = error;
+            if entry.attempts >= self.max_attempts {
+                dead.push(state.1.remove(position));
+                delivery.dead += 1;
+            } else {
+                entry.next_attempt = unix_millis(now + backoff(entry.attempts));
+                delivery.retrying += 1;
+            }
+        }
+        // dead letters first: an event is in the outbox, the dead letters or both, never neither
+        if !dead.is_empty() {
+            let lines: String = dead.iter().map(|entry| format!("{}\n", serde_json::to_string(entry).expect("entries always serialize"))).collect();
+            let mut file = OpenOptions::new().create(true).append(true).open(&self.dead_letters)?;
+            file.write_all(lines.as_bytes())?;
+            file.sync_data()?;
+        }
+        state.0 = rewrite(&self.path, &state.1)?;
+        Ok(delivery)
+    }
+
+    /// tombstone the events about `user`, waiting or dead; how many
+    pub fn erase(&self, user: &str, tombstone: &str) -> io::Result<usize> {
+        let mut state = self.state.lock().unwrap();
+        let mut erased = 0;
+        for entry in state.1.iter_mut() {
+            erased += usize::from(erase_event(&mut entry.event, user, tombstone));
+        }
+        if erased > 0 {
+            state.0 = rewrite(&self.path, &state.1)?;
+        }
+        let mut dead = match fs::read_to_string(&self.dead_letters) {
+            Ok(text) => read_entries(&text, &self.dead_letters)?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(erased),
+            Err(e) => return Err(e),
+        };
+        let before = erased;
+        for entry in dead.iter_mut() {
+            erased += usize::from(erase_event(&mut entry.event, user, tombstone));
+        }
+        if erased > before {
+            rewrite(&self.dead_letters, &dead)?;
+        }
+        Ok(erased)
+    }
+}
+
+/// how long to wait after the `attempts`th failed attempt
+pub fn backoff(attempts: u32) -> Duration {
+    FIRST_RETRY.saturating_mul(2u32.saturating_pow(attempts.saturating_sub(1))).min(LAST_RETRY)
+}
+
+impl AuditSink for Arc<Outbox> {
+    fn record(&self, event: &AuditEvent) -> io::Result<()> {
+        let event = serde_json::from_str(&event.to_json()).expect("audit events are JSON");
+        self.enqueue(event, SystemTime::now()).map(|_| ())
+    }
+
+    fn erase(&self, user: &str, tombstone: &str) -> io::Result<usize> {
+        Outbox::erase(self, user, tombstone)
+    }
+}
+
+/// the entries in a journal; a last line without its newline was cut off by a crash and is dropped
+fn read_entries(text: &str, path: &Path) -> io::Result<Vec<OutboxEntry>> {
+    let complete = text.rfind('\n').map_or("", |end| &text[..end]);
+    if complete.len() + 1 < text.len() {
+        println!("⚠️  {}: dropping an event that was cut off by a crash", path.display());
+    }
+    complete
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            serde_json::from_str(line)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path.display(), e)))
+        })
+        .collect()
+}
+
+/// replace the journal at `path` with `entries`, returning it open for appending
+fn rewrite(path: &Path, entries: &[OutboxEntry]) -> io::Result<File> {
+    let lines: String = entries.iter().map(|entry| format!("{}\n", serde_json::to_string(entry).expect("entries always serialize"))).collect();
+    let rewrite = PathBuf::from(format!("{}.rewrite", path.display()));
+    let mut file = File::create(&rewrite)?;
+    file.write_all(lines.as_bytes())?;
+    file.sync_all()?;
+    fs::rename(&rewrite, path)?;
+    OpenOptions::new().append(true).open(path)
+}
+
+/// blank who, from where and the detail of an event about `user`, like the audit log does
+fn erase_event(event: &mut Value, user: &str, tombstone: &str) -> bool {
+    if event["user"] != user {
+        return false;
+    }
+    for field in ["user", "source", "detail"] {
+        event[field] = Value::from("");
+    }
+    event["erased"] = Value::from(tombstone);
+    true
+}
@@ -0,0 +1,159 @@
+//! pam-zkp: check a Linux system login against the ZKP server.
+//!
+//! Meant for pam_exec, so no PAM module has to be built or loaded:
+//!
+//! ```text
+//! # /etc/pam.d/<service>
+//! auth required pam_exec.so expose_authtok quiet /usr/local/bin/pam-zkp --endpoint https://auth.example:50051
+//! ```
+//!
+//! pam_exec passes the user name in `PAM_USER` and writes the password to
+//! stdin (NUL-terminated). We run the same login as `zkp-auth login` and exit
+//! 0 only when the server accepts the proof. The password is never sent.
+
+use std::io::Read;
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::time::Duration;
+
+use clap::Parser;
+use tonic::transport::{Certificate, Channel, ClientTlsConfig, Endpoint};
+
+use rust_zkp_chaum_pedersen::api::v1::{
+    auth_client::AuthClient, AuthenticationAnswerRequest, AuthenticationChallengeRequest, ClientMetadata,
+    UserKdfParamsRequest,
+};
+use rust_zkp_chaum_pedersen::group::{GroupId, HashId};
+use rust_zkp_chaum_pedersen::kdf::{self, KdfParams};
+use rust_zkp_chaum_pedersen::pow;
+use rust_zkp_chaum_pedersen::types::Challenge;
+use rust_zkp_chaum_pedersen::ZKP;
+
+/// Authenticate PAM_USER with the password on stdin (for pam_exec expose_authtok)
+#[derive(Parser)]
+#[command(name = "pam-zkp", version, about)]
+struct Args {
+    /// Server endpoint
+    #[arg(long, default_value = "http://127.0.0.1:50051")]
+    endpoint: String,
+    /// PEM CA certificate to trust for an https endpoint
+    #[arg(long)]
+    ca_cert: Option<PathBuf>,
+    /// Give up (and deny the login) after this many seconds
+    #[arg(long, default_value_t = 10)]
+    timeout: u64,
+}
+
+// pam_exec only looks at zero or not, the rest is for whoever reads the logs
+const DENIED: u8 = 1;
+const UNAVAILABLE: u8 = 2;
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let args = Args::parse();
+
+    let Ok(user) = std::env::var("PAM_USER") else {
+        eprintln!("pam-zkp: PAM_USER is not set, run me from pam_exec");
+        return ExitCode::from(UNAVAILABLE);
+    };
+    let mut password = String::new();
+    if std::io::stdin().read_to_string(&mut password).is_err() {
+        eprintln!("pam-zkp: could not read the password from stdin (is expose_authtok set?)");
+        return ExitCode::from(UNAVAILABLE);
+    }
+    let password = password.trim_end_matches(['\0', '\n']);
+
+    match tokio::time::timeout(Duration::from_secs(args.timeout), login(&args, &user, password)).await {
+        Ok(Ok(())) => ExitCode::SUCCESS,
+        Ok(Err(status)) if status.code() == tonic::Code::PermissionDenied || status.code() == tonic::Code::NotFound => {
+            eprintln!("pam-zkp: login denied for {}: {}", user, status.message());
+            ExitCode::from(DENIED)
+        }
+        Ok(Err(status)) => {
+            eprintln!("pam-zkp: could not log {} in: {}", user, status);
+            ExitCode::from(UNAVAILABLE)
+        }
+        Err(_) => {
+            eprintln!("pam-zkp: no answer from {} within {}s", args.endpoint, args.timeout);
+            ExitCode::from(UNAVAILABLE)
+        }
+    }
+}
+
+/// KDF parameters, challenge and answer, like `zkp-auth login` without the prompts
+async fn login(args: &Args, user: &str, password: &str) -> Result<(), tonic::Status> {
+    let mut client = AuthClient::new(open_channel(args).await?);
+    let mut zkp = ZKP::builder().rfc5114().build().expect("RFC 5114 parameters are valid");
+
+    let user_params = client
+        .get_user_kdf_params(UserKdfParamsRequest { user: user.to_string() })
+        .await?
+        .into_inner();
+    if GroupId::from_name(&user_params.group) != Some(GroupId::ModP) {
+        return Err(tonic::Status::unavailable(format!("{} is registered in the {:?} group, only modp is spoken here", user, user_params.group)));
+    }
+    let Some(hash) = HashId::from_name(&user_params.hash) else {
+        return Err(tonic::Status::unavailable(format!("unknown hash {:?}", user_params.hash)));
+    };
+    if !user_params.beta.is_empty() {
+        zkp = zkp.for_user_with(hash, user);
+        if zkp.beta.to_bytes_be() != user_params.beta {
+            return Err(tonic::Status::unavailable("the server's generator is not H2G(user)"));
+        }
+    }
+    let x = match user_params.kdf.map(KdfParams::from) {
+        Some(kdf_params) => kdf_params
+            .derive(password, &zkp.q)
+            .map_err(|e| tonic::Status::unavailable(format!("refusing the server's {}", e)))?,
+        None => kdf::legacy_derive(password),
+    };
+
+    let k = ZKP::generate_random_number_below(&zkp.q);
+    let (r1, r2) = zkp.commitment(&k).to_bytes_be();
+    let client_metadata = ClientMetadata {
+        device_id: String::new(),
+        app_version: env!("CARGO_PKG_VERSION").to_string(),
+        platform: format!("{}/pam", std::env::consts::OS),
+    };
+    let challenge = client
+        .create_authentication_challenge(AuthenticationChallengeRequest {
+            user: user.to_string(),
+            r1,
+            r2,
+            auth_id: String::new(),
+            client: Some(client_metadata),
+            device_id: String::new(),
+        })
+        .await?
+        .into_inner();
+
+    let s = zkp.respond(&k, &Challenge::from_bytes_be(&challenge.c), &x);
+    let pow_nonce = pow::solve(&challenge.auth_id, &challenge.c, challenge.pow_difficulty).ok_or_else(|| {
+        tonic::Status::unavailable(format!("refusing the server's {}-bit proof of work", challenge.pow_difficulty))
+    })?;
+    client
+        .verify_authentication(AuthenticationAnswerRequest {
+            auth_id: challenge.auth_id,
+            s: s.to_bytes_be(),
+            id_token: None,
+            ssh_public_key: String::new(),
+            pow_nonce,
+            challenge_token: challenge.challenge_token,
+        })
+        .await?;
+    Ok(())
+}
+
+async fn open_channel(args: &Args) -> Result<Channel, tonic::Status> {
+    let unavailable = |e: &dyn std::fmt::Display| tonic::Status::unavailable(e.to_string());
+    let mut endpoint = Endpoint::from_shared(args.endpoint.clone()).map_err(|e| unavailable(&e))?;
+    if args.endpoint.starts_with("https://") {
+        let mut tls = ClientTlsConfig::new();
+        if let Some(ca_cert) = &args.ca_cert {
+            let pem = std::fs::read(ca_cert).map_err(|e| unavailable(&e))?;
+            tls = tls.ca_certificate(Certificate::from_pem(pem));
+        }
+        endpoint = endpoint.tls_config(tls).map_err(|e| unavailable(&e))?;
+    }
+    endpoint.connect().await.map_err(|e| unavailable(&e))
+}
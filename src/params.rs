@@ -0,0 +1,183 @@
+//! Signed group parameter bundles.
+//!
+//! A client has to trust (p, q, alpha, beta) before it derives anything from
+//! the password: a man in the middle on first contact could substitute a weak
+//! group and learn the secret from the registration values. The server signs
+//! its parameters with a long-term Ed25519 key and publishes them through the
+//! `GetParameters` RPC (and as a JSON file); clients that know the server's
+//! public key verify the signature before using the parameters.
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use num_bigint::BigUint;
+use serde::{Deserialize, Serialize};
+
+use crate::ZKP;
+
+/// Domain separation so these signatures can't be confused with anything else
+const DOMAIN: &[u8] = b"zkp-auth/parameters/v1";
+
+/// Group parameters together with the server's signature over them
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SignedParameters {
+    #[serde(with = "hex_bytes")]
+    pub p: Vec<u8>,
+    #[serde(with = "hex_bytes")]
+    pub q: Vec<u8>,
+    #[serde(with = "hex_bytes")]
+    pub alpha: Vec<u8>,
+    #[serde(with = "hex_bytes")]
+    pub beta: Vec<u8>,
+    #[serde(with = "hex_bytes")]
+    pub public_key: Vec<u8>, // Ed25519 key that made the signature
+    #[serde(with = "hex_bytes")]
+    pub signature: Vec<u8>,
+}
+
+/// Why a parameter bundle was rejected
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParametersError {
+    /// signed by some other key than the one we trust
+    UntrustedKey,
+    /// the signature doesn't match the parameters
+    BadSignature,
+    /// the numbers don't form a usable group
+    InvalidGroup(&'static str),
+    /// not a valid key, signature or file
+    Malformed(String),
+}
+
+impl fmt::Display for ParametersError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParametersError::UntrustedKey => write!(f, "parameters are signed by an untrusted key"),
+            ParametersError::BadSignature => write!(f, "parameter signature is invalid"),
+            ParametersError::InvalidGroup(reason) => write!(f, "invalid group parameters: {}", reason),
+            ParametersError::Malformed(reason) => write!(f, "malformed parameters: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for ParametersError {}
+
+impl SignedParameters {
+    /// sign the parameters of `zkp` with the server's long-term key
+    pub fn sign(zkp: &ZKP, key: &SigningKey) -> Self {
+        let p = zkp.p.to_bytes_be();
+        let q = zkp.q.to_bytes_be();
+        let alpha = zkp.alpha.to_bytes_be();
+        let beta = zkp.beta.to_bytes_be();
+        let signature = key.sign(&signed_message(&p, &q, &alpha, &beta));
+
+        SignedParameters {
+            p,
+            q,
+            alpha,
+            beta,
+            public_key: key.verifying_key().to_bytes().to_vec(),
+            signature: signature.to_bytes().to_vec(),
+        }
+    }
+
+    /// check the bundle was signed by `trusted_key` and describes a sane group,
+    /// only then hand out a ZKP instance built from it
+    pub fn verify(&self, trusted_key: &VerifyingKey) -> Result<ZKP, ParametersError> {
+        if self.public_key != trusted_key.as_bytes() {
+            return Err(ParametersError::UntrustedKey);
+        }
+        let signature = Signature::from_slice(&self.signature)
+            .map_err(|_| ParametersError::Malformed("signature must be 64 bytes".to_string()))?;
+        trusted_key
+            .verify(&signed_message(&self.p, &self.q, &self.alpha, &self.beta), &signature)
+            .map_err(|_| ParametersError::BadSignature)?;
+
+        ZKP::builder()
+            .p(BigUint::from_bytes_be(&self.p))
+            .q(BigUint::from_bytes_be(&self.q))
+            .alpha(BigUint::from_bytes_be(&self.alpha))
+            .beta(BigUint::from_bytes_be(&self.beta))
+            .build()
+    }
+
+    /// the bundle as a pretty JSON document (the file format)
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("parameters always serialize")
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, ParametersError> {
+        serde_json::from_str(json).map_err(|e| ParametersError::Malformed(e.to_string()))
+    }
+}
+
+/// what actually gets signed: a domain tag and the length-prefixed numbers
+fn signed_message(p: &[u8], q: &[u8], alpha: &[u8], beta: &[u8]) -> Vec<u8> {
+    let mut message = DOMAIN.to_vec();
+    for value in [p, q, alpha, beta] {
+        message.extend_from_slice(&(value.len() as u32).to_be_bytes());
+        message.extend_from_slice(value);
+    }
+    message
+}
+
+/// parse a hex-encoded Ed25519 public key (as printed by the server)
+pub fn parse_public_key(hex_key: &str) -> Result<VerifyingKey, ParametersError> {
+    let bytes: [u8; 32] = hex::decode(hex_key.trim())
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| ParametersError::Malformed("public key must be 32 hex-encoded bytes".to_string()))?;
+    VerifyingKey::from_bytes(&bytes).map_err(|e| ParametersError::Malformed(e.to_string()))
+}
+
+/// load the server's long-term signing key (hex seed), generating it on first start
+pub fn load_or_create_signing_key(path: &Path) -> io::Result<SigningKey> {
+    match fs::read_to_string(path) {
+        Ok(contents) => {
+            let seed: [u8; 32] = hex::decode(contents.trim())
+                .ok()
+                .and_then(|bytes| bytes.try_into().ok())
+                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "signing key must be 32 hex-encoded bytes"))?;
+            Ok(SigningKey::from_bytes(&seed))
+        }
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            let key = SigningKey::generate(&mut rand::rngs::OsRng);
+            write_private(path, hex::encode(key.to_bytes()).as_bytes())?;
+            Ok(key)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+#[cfg(unix)]
+pub(crate) fn write_private(path: &Path, contents: &[u8]) -> io::Result<()> {
+    use std::io::Write;
+    use std::os::unix::fs::OpenOptionsExt;
+    fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(path)?
+        .write_all(contents)
+}
+
+#[cfg(not(unix))]
+pub(crate) fn write_private(path: &Path, contents: &[u8]) -> io::Result<()> {
+    fs::write(path, contents)
+}
+
+/// serde helper: byte strings as lowercase hex in JSON
+pub(crate) mod hex_bytes {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&hex::encode(bytes))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let string = String::deserialize(deserializer)?;
+        hex::decode(string).map_err(serde::de::Error::custom)
+    }
+}
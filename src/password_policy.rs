@@ -0,0 +1,109 @@
+//! Password policies, checked on the client before anything is derived.
+//!
+//! The server only ever sees (y1, y2), so it can't judge the password itself.
+//! Instead the client evaluates a [`PasswordPolicy`] and tells the server which
+//! policy version it checked against; a server can refuse registrations that
+//! weren't checked against a recent enough version. That is an attestation by
+//! an honest client, not a proof: a modified client can always lie about it.
+
+use std::fmt;
+
+/// Version of [`DefaultPolicy`], bumped whenever its rules get stricter
+pub const DEFAULT_POLICY_VERSION: u32 = 1;
+
+/// A set of rules a password has to satisfy
+pub trait PasswordPolicy {
+    /// version sent along with the registration, higher means stricter
+    fn version(&self) -> u32;
+
+    /// everything wrong with `password` for `user`, empty when it's acceptable
+    fn check(&self, user: &str, password: &str) -> Vec<Weakness>;
+}
+
+/// One reason a password was found too weak
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Weakness {
+    TooShort { min_length: usize },
+    TooFewCharacterClasses { min_classes: usize },
+    Common,
+    ContainsUsername,
+    /// a strength estimate (see the `zxcvbn` feature) below the minimum, out of 4
+    Guessable { score: u8, min_score: u8 },
+    /// found in known data breaches (see the `hibp` feature), this many times
+    Breached { count: u64 },
+}
+
+impl fmt::Display for Weakness {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Weakness::TooShort { min_length } => write!(f, "use at least {} characters", min_length),
+            Weakness::TooFewCharacterClasses { min_classes } => write!(
+                f,
+                "mix at least {} of lowercase, uppercase, digits and symbols",
+                min_classes
+            ),
+            Weakness::Common => write!(f, "this is one of the most common passwords"),
+            Weakness::ContainsUsername => write!(f, "don't put the username in the password"),
+            Weakness::Guessable { score, min_score } => {
+                write!(f, "it would be guessed too easily (strength {} of 4, at least {} wanted)", score, min_score)
+            }
+            Weakness::Breached { count } => write!(f, "it has turned up {} times in known data breaches", count),
+        }
+    }
+}
+
+/// Length, variety and a short list of the passwords everyone tries first
+#[derive(Debug, Clone)]
+pub struct DefaultPolicy {
+    pub min_length: usize,
+    pub min_classes: usize,
+}
+
+impl Default for DefaultPolicy {
+    fn default() -> Self {
+        DefaultPolicy {
+            min_length: 10,
+            min_classes: 2,
+        }
+    }
+}
+
+/// the top of every leaked password list
+const COMMON_PASSWORDS: &[&str] = &[
+    "password", "123456", "12345678", "123456789", "1234567890", "qwerty", "qwertyuiop",
+    "abc123", "111111", "letmein", "welcome", "iloveyou", "admin", "monkey", "dragon",
+    "football", "baseball", "sunshine", "princess", "trustno1", "passw0rd", "password1",
+    "password123", "qwerty123", "1q2w3e4r", "zaq12wsx",
+];
+
+impl PasswordPolicy for DefaultPolicy {
+    fn version(&self) -> u32 {
+        DEFAULT_POLICY_VERSION
+    }
+
+    fn check(&self, user: &str, password: &str) -> Vec<Weakness> {
+        let mut weaknesses = Vec::new();
+        if password.chars().count() < self.min_length {
+            weaknesses.push(Weakness::TooShort { min_length: self.min_length });
+        }
+
+        let classes = [
+            password.chars().any(|c| c.is_lowercase()),
+            password.chars().any(|c| c.is_uppercase()),
+            password.chars().any(|c| c.is_ascii_digit()),
+            password.chars().any(|c| !c.is_alphanumeric()),
+        ];
+        if classes.iter().filter(|present| **present).count() < self.min_classes {
+            weaknesses.push(Weakness::TooFewCharacterClasses { min_classes: self.min_classes });
+        }
+
+        let lowercase = password.to_lowercase();
+        if COMMON_PASSWORDS.contains(&lowercase.as_str()) {
+            weaknesses.push(Weakness::Common);
+        }
+        if !user.is_empty() && lowercase.contains(&user.to_lowercase()) {
+            weaknesses.push(Weakness::ContainsUsername);
+        }
+        weaknesses
+    }
+}
@@ -0,0 +1,146 @@
+//! Challenges handed out and not answered yet.
+//!
+//! Each challenge has a time-ordered auth_id (a ULID) that maps to the user it
+//! was issued to. Challenges in the committed mode also hold (c, nonce) until
+//! the client sends (r1, r2). A challenge answered correctly is used up, so
+//! every one left counts as waiting for --pow-pending-threshold. Handlers
+//! insert, look up, consume and expire entries concurrently, and the bytes
+//! held count against --max-pending-challenge-bytes, so the two maps and the
+//! count must stay in step. The locks come from `crate::sync`, so tests/loom_tests.rs can check
+//! that under loom.
+
+use std::collections::BTreeMap;
+
+use num_bigint::BigUint;
+
+use crate::memory::{ByteBudget, HeapSize, StoreUsage, MAP_ENTRY_OVERHEAD};
+use crate::sync::Mutex;
+
+/// Pending challenges: auth_id -> user, plus the openings of committed ones.
+/// Whoever needs both locks takes `owners` first.
+#[derive(Debug, Default)]
+pub struct PendingChallenges {
+    // auth_ids are ULIDs, so these sort by issue time and expire with one split_off
+    owners: Mutex<BTreeMap<String, String>>,
+    // committed challenges still waiting for (r1, r2): auth_id -> (c, nonce)
+    committed: Mutex<BTreeMap<String, (BigUint, Vec<u8>)>>,
+    bytes: ByteBudget,
+}
+
+impl PendingChallenges {
+    /// `limit` caps the bytes held, None for no cap
+    pub fn new(limit: Option<usize>) -> Self {
+        PendingChallenges { bytes: ByteBudget::new(limit), ..Default::default() }
+    }
+
+    pub fn limit(&self) -> Option<usize> {
+        self.bytes.limit()
+    }
+
+    /// remember a challenge, dropping the oldest ones to stay under the cap;
+    /// returns how many were dropped
+    pub fn insert(&self, auth_id: String, user_name: String, committed: Option<(BigUint, Vec<u8>)>) -> usize {
+        let bytes = owner_entry_bytes(&auth_id, &user_name)
+            + committed.as_ref().map_or(0, |(c, nonce)| committed_entry_bytes(&auth_id, c, nonce));
+
+        let mut owners = self.owners.lock().unwrap();
+        let mut committed_map = self.committed.lock().unwrap();
+        let mut dropped = 0;
+        while !self.bytes.has_room_for(bytes) {
+            // auth_ids are ULIDs, so the first is the oldest
+            let Some((oldest, owner)) = owners.pop_first() else {
+                break;
+            };
+            self.bytes.sub(owner_entry_bytes(&oldest, &owner));
+            if let Some((c, nonce)) = committed_map.remove(&oldest) {
+                self.bytes.sub(committed_entry_bytes(&oldest, &c, &nonce));
+            }
+            dropped += 1;
+        }
+
+        self.bytes.add(bytes);
+        if let Some(entry) = committed {
+            committed_map.insert(auth_id.clone(), entry);
+        }
+        owners.insert(auth_id, user_name);
+        dropped
+    }
+
+    /// the user a challenge was issued to
+    pub fn owner(&self, auth_id: &str) -> Option<String> {
+        self.owners.lock().unwrap().get(auth_id).cloned()
+    }
+
+    /// whether a committed challenge is still waiting for (r1, r2)
+    pub fn is_committed(&self, auth_id: &str) -> bool {
+        self.committed.lock().unwrap().contains_key(auth_id)
+    }
+
+    /// forget a challenge that was used up; false if it was already gone
+    pub fn remove(&self, auth_id: &str) -> bool {
+        let mut owners = self.owners.lock().unwrap();
+        let Some((auth_id, user_name)) = owners.remove_entry(auth_id) else {
+            return false;
+        };
+        self.bytes.sub(owner_entry_bytes(&auth_id, &user_name));
+        true
+    }
+
+    /// forget every challenge issued to `user_name`; returns how many
+    pub fn remove_user(&self, user_name: &str) -> usize {
+        let mut owners = self.owners.lock().unwrap();
+        let mut committed = self.committed.lock().unwrap();
+        let auth_ids: Vec<String> = owners.iter().filter(|(_, owner)| *owner == user_name).map(|(auth_id, _)| auth_id.clone()).collect();
+        for auth_id in &auth_ids {
+            owners.remove(auth_id);
+            self.bytes.sub(owner_entry_bytes(auth_id, user_name));
+            if let Some((c, nonce)) = committed.remove(auth_id) {
+                self.bytes.sub(committed_entry_bytes(auth_id, &c, &nonce));
+            }
+        }
+        auth_ids.len()
+    }
+
+    /// the (c, nonce) of a committed challenge, which is opened only once
+    pub fn take_committed(&self, auth_id: &str) -> Option<(BigUint, Vec<u8>)> {
+        let (auth_id, (c, nonce)) = self.committed.lock().unwrap().remove_entry(auth_id)?;
+        self.bytes.sub(committed_entry_bytes(&auth_id, &c, &nonce));
+        Some((c, nonce))
+    }
+
+    /// drop every challenge whose auth_id sorts before `oldest`; returns how many
+    pub fn expire_before(&self, oldest: &str) -> usize {
+        let mut owners = self.owners.lock().unwrap();
+        let live = owners.split_off(oldest);
+        let expired = std::mem::replace(&mut *owners, live);
+        for (auth_id, user_name) in &expired {
+            self.bytes.sub(owner_entry_bytes(auth_id, user_name));
+        }
+        let mut committed = self.committed.lock().unwrap();
+        let live = committed.split_off(oldest);
+        for (auth_id, (c, nonce)) in std::mem::replace(&mut *committed, live) {
+            self.bytes.sub(committed_entry_bytes(&auth_id, &c, &nonce));
+        }
+        expired.len()
+    }
+
+    /// how many challenges are still waiting for a correct answer; the
+    /// answered ones are gone already
+    pub fn unanswered(&self) -> usize {
+        self.owners.lock().unwrap().len()
+    }
+
+    pub fn usage(&self) -> StoreUsage {
+        StoreUsage { entries: self.owners.lock().unwrap().len(), bytes: self.bytes.used() }
+    }
+}
+
+/// approximate bytes of an owners entry
+fn owner_entry_bytes(auth_id: &str, user_name: &str) -> usize {
+    2 * std::mem::size_of::<String>() + auth_id.len() + user_name.len() + MAP_ENTRY_OVERHEAD
+}
+
+/// approximate bytes of a committed entry
+fn committed_entry_bytes(auth_id: &str, c: &BigUint, nonce: &[u8]) -> usize {
+    std::mem::size_of::<String>() + auth_id.len() + c.total_size() + std::mem::size_of::<Vec<u8>>() + nonce.len() + MAP_ENTRY_OVERHEAD
+}
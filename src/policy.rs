@@ -0,0 +1,259 @@
+//! Policy hooks for authentication decisions.
+//!
+//! A correct proof shows the client knows the password, not that the login
+//! is welcome. A [`PolicyHook`] sees every login after its proof verified and
+//! before a session is issued, and may allow it, deny it, or ask for a
+//! step-up. [`AllowAll`] is the default; [`RuleSet`] is a small rule language
+//! for operators who don't want to write Rust:
+//!
+//! ```text
+//! # first matching rule wins, no match allows
+//! deny    if risk >= 80
+//! step_up if new_device && platform == "android"
+//! deny    if user == "root" || hour < 6
+//! ```
+//!
+//! Fields: `user`, `device_id`, `app_version`, `platform`, `source` (strings),
+//! `risk`, `hour` (UTC, 0-23, numbers) and `new_device` (bool). Conditions
+//! compare a field with a literal (`==`, `!=`, `<`, `<=`, `>`, `>=`) and
+//! combine with `&&` and `||`.
+
+use std::fmt;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+/// What the server knows about a login when the hook runs
+#[derive(Debug, Clone, PartialEq)]
+pub struct LoginContext {
+    pub user: String,
+    // self-reported by the client, empty when it didn't say
+    pub device_id: String,
+    pub app_version: String,
+    pub platform: String,
+    pub source: String, // peer address
+    pub new_device: bool, // device_id not in the user's sessions or last login (false on a first login)
+    pub risk_score: u32, // 0 (nothing unusual) to 100, see the server's risk estimate
+    pub time: SystemTime,
+}
+
+/// The outcome of a policy check
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Decision {
+    Allow,
+    /// refuse the login, the reason is sent to the client
+    Deny(String),
+    /// the proof alone isn't enough, the client has to verify again another way
+    StepUp,
+}
+
+/// Decides whether a verified login gets a session
+pub trait PolicyHook: fmt::Debug + Send + Sync {
+    fn decide(&self, login: &LoginContext) -> Decision;
+}
+
+/// Lets every verified login in (the default)
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AllowAll;
+
+impl PolicyHook for AllowAll {
+    fn decide(&self, _login: &LoginContext) -> Decision {
+        Decision::Allow
+    }
+}
+
+/// A shareable hook, [`AllowAll`] by default
+#[derive(Debug, Clone)]
+pub struct Policy(Arc<dyn PolicyHook>);
+
+impl Policy {
+    pub fn new(hook: impl PolicyHook + 'static) -> Self {
+        Policy(Arc::new(hook))
+    }
+
+    pub fn decide(&self, login: &LoginContext) -> Decision {
+        self.0.decide(login)
+    }
+}
+
+impl Default for Policy {
+    fn default() -> Self {
+        Policy::new(AllowAll)
+    }
+}
+
+/// Rules like `deny if risk >= 80`, checked in order
+#[derive(Debug, Clone, PartialEq)]
+pub struct RuleSet {
+    rules: Vec<Rule>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Rule {
+    line: usize,
+    action: Action,
+    condition: Condition,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Action {
+    Allow,
+    Deny,
+    StepUp,
+}
+
+/// `a || b || ...` of `x && y && ...`
+#[derive(Debug, Clone, PartialEq)]
+struct Condition(Vec<Vec<Comparison>>);
+
+#[derive(Debug, Clone, PartialEq)]
+struct Comparison {
+    field: String,
+    op: Op,
+    value: Value,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    Text(String),
+    Number(u64),
+    Bool(bool),
+}
+
+/// A rule that couldn't be parsed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RuleError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for RuleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "policy rule on line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for RuleError {}
+
+const TEXT_FIELDS: [&str; 5] = ["user", "device_id", "app_version", "platform", "source"];
+const NUMBER_FIELDS: [&str; 2] = ["risk", "hour"];
+const BOOL_FIELDS: [&str; 1] = ["new_device"];
+
+impl RuleSet {
+    /// parse one rule per line; blank lines and `#` comments are skipped
+    pub fn parse(text: &str) -> Result<Self, RuleError> {
+        let mut rules = Vec::new();
+        for (index, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (action, condition) = parse_rule(line).map_err(|message| RuleError { line: index + 1, message })?;
+            rules.push(Rule { line: index + 1, action, condition });
+        }
+        Ok(RuleSet { rules })
+    }
+}
+
+impl PolicyHook for RuleSet {
+    fn decide(&self, login: &LoginContext) -> Decision {
+        let rule = self.rules.iter().find(|rule| rule.condition.matches(login));
+        match rule {
+            None | Some(Rule { action: Action::Allow, .. }) => Decision::Allow,
+            Some(Rule { action: Action::StepUp, .. }) => Decision::StepUp,
+            Some(rule) => Decision::Deny(format!("denied by policy rule on line {}", rule.line)),
+        }
+    }
+}
+
+impl Condition {
+    fn matches(&self, login: &LoginContext) -> bool {
+        self.0.iter().any(|all| all.iter().all(|comparison| comparison.matches(login)))
+    }
+}
+
+impl Comparison {
+    fn matches(&self, login: &LoginContext) -> bool {
+        let hour = login
+            .time
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|d| d.as_secs() / 3600 % 24)
+            .unwrap_or(0);
+        let actual = match self.field.as_str() {
+            "user" => Value::Text(login.user.clone()),
+            "device_id" => Value::Text(login.device_id.clone()),
+            "app_version" => Value::Text(login.app_version.clone()),
+            "platform" => Value::Text(login.platform.clone()),
+            "source" => Value::Text(login.source.clone()),
+            "risk" => Value::Number(login.risk_score as u64),
+            "hour" => Value::Number(hour),
+            _ => Value::Bool(login.new_device),
+        };
+        let ordering = match (&actual, &self.value) {
+            (Value::Text(a), Value::Text(b)) => a.cmp(b),
+            (Value::Number(a), Value::Number(b)) => a.cmp(b),
+            (Value::Bool(a), Value::Bool(b)) => a.cmp(b),
+            _ => return false, // ruled out by the parser
+        };
+        match self.op {
+            Op::Eq => ordering.is_eq(),
+            Op::Ne => ordering.is_ne(),
+            Op::Lt => ordering.is_lt(),
+            Op::Le => ordering.is_le(),
+            Op::Gt => ordering.is_gt(),
+            Op::Ge => ordering.is_ge(),
+        }
+    }
+}
+
+fn parse_rule(line: &str) -> Result<(Action, Condition), String> {
+    let (action, condition) = line
+        .split_once(" if ")
+        .ok_or_else(|| "expected `<allow|deny|step_up> if <condition>`".to_string())?;
+    let action = match action.trim() {
+        "allow" => Action::Allow,
+        "deny" => Action::Deny,
+        "step_up" => Action::StepUp,
+        other => return Err(format!("unknown action {}", other)),
+    };
+    let condition = condition
+        .split("||")
+        .map(|all| all.split("&&").map(parse_comparison).collect())
+        .collect::<Result<_, _>>()?;
+    Ok((action, Condition(condition)))
+}
+
+fn parse_comparison(text: &str) -> Result<Comparison, String> {
+    let text = text.trim();
+    // a bare boolean field means `field == true`
+    if BOOL_FIELDS.contains(&text) {
+        return Ok(Comparison { field: text.to_string(), op: Op::Eq, value: Value::Bool(true) });
+    }
+
+    // two-character operators first, so `<=` isn't read as `<`
+    let (field, op, value) = [("==", Op::Eq), ("!=", Op::Ne), ("<=", Op::Le), (">=", Op::Ge), ("<", Op::Lt), (">", Op::Gt)]
+        .iter()
+        .find_map(|(symbol, op)| text.split_once(symbol).map(|(field, value)| (field.trim(), *op, value.trim())))
+        .ok_or_else(|| format!("expected a comparison in {:?}", text))?;
+
+    let value = if TEXT_FIELDS.contains(&field) {
+        let quoted = value.strip_prefix('"').and_then(|v| v.strip_suffix('"'));
+        Value::Text(quoted.ok_or_else(|| format!("{} compares with a \"quoted\" string", field))?.to_string())
+    } else if NUMBER_FIELDS.contains(&field) {
+        Value::Number(value.parse().map_err(|_| format!("{} compares with a number", field))?)
+    } else if BOOL_FIELDS.contains(&field) {
+        Value::Bool(value.parse().map_err(|_| format!("{} compares with true or false", field))?)
+    } else {
+        return Err(format!("unknown field {}", field));
+    };
+    Ok(Comparison { field: field.to_string(), op, value })
+}
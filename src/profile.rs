@@ -0,0 +1,136 @@
+//! Named server profiles for the `zkp-auth` CLI.
+//!
+//! A profile remembers the endpoint of a ZKP server (and optionally the
+//! username we log in with there), so people talking to several servers
+//! (staging/prod, multiple tenants) can switch with `zkp-auth profile use`
+//! instead of retyping everything.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::compression::Compression;
+
+/// Environment variable overriding where the profiles file lives
+pub const PROFILES_PATH_ENV: &str = "ZKP_AUTH_PROFILES";
+
+/// A single named server profile
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Profile {
+    pub endpoint: String, // e.g. http://127.0.0.1:50051
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>, // default username for this server
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ca_cert: Option<PathBuf>, // PEM CA to trust for https endpoints (system roots otherwise)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub server_key: Option<String>, // hex Ed25519 key the group parameters must be signed with
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub tofu: bool, // remember the server's certificate/key on first use and refuse changes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compression: Option<Compression>, // compress requests to this server
+}
+
+/// All known profiles plus the one currently in use
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProfileStore {
+    #[serde(default)]
+    pub active: Option<String>,
+    #[serde(default)]
+    pub profiles: BTreeMap<String, Profile>,
+}
+
+impl ProfileStore {
+    /// where the profiles are stored by default
+    /// $ZKP_AUTH_PROFILES, otherwise ~/.zkp-auth/profiles.json
+    pub fn default_path() -> PathBuf {
+        if let Some(path) = std::env::var_os(PROFILES_PATH_ENV) {
+            return PathBuf::from(path);
+        }
+        let home = std::env::var_os("HOME").map(PathBuf::from).unwrap_or_default();
+        home.join(".zkp-auth").join("profiles.json")
+    }
+
+    /// load the profiles from disk, a missing file is just an empty store
+    pub fn load(path: &Path) -> io::Result<Self> {
+        match fs::read(path) {
+            Ok(bytes) => serde_json::from_slice(&bytes)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// write the profiles back to disk, creating the directory if needed
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            if !dir.as_os_str().is_empty() {
+                fs::create_dir_all(dir)?;
+            }
+        }
+        let json = serde_json::to_vec_pretty(self)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        fs::write(path, json)
+    }
+
+    /// add (or replace) a profile
+    /// the very first profile added becomes the active one
+    pub fn add(&mut self, name: &str, profile: Profile) {
+        self.profiles.insert(name.to_string(), profile);
+        if self.active.is_none() {
+            self.active = Some(name.to_string());
+        }
+    }
+
+    /// remove a profile, forgetting it as active if it was
+    pub fn remove(&mut self, name: &str) -> Option<Profile> {
+        if self.active.as_deref() == Some(name) {
+            self.active = None;
+        }
+        self.profiles.remove(name)
+    }
+
+    /// switch the active profile
+    pub fn set_active(&mut self, name: &str) -> io::Result<()> {
+        if !self.profiles.contains_key(name) {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("profile {} does not exist", name),
+            ));
+        }
+        self.active = Some(name.to_string());
+        Ok(())
+    }
+
+    /// look up a profile by name, or the active one when no name is given
+    pub fn get(&self, name: Option<&str>) -> Option<(&str, &Profile)> {
+        let name = name.or(self.active.as_deref())?;
+        self.profiles
+            .get_key_value(name)
+            .map(|(name, profile)| (name.as_str(), profile))
+    }
+}
+
+/// device_id next to the profiles file
+pub fn default_device_id_path() -> PathBuf {
+    ProfileStore::default_path().with_file_name("device_id")
+}
+
+/// a random id for this installation, reported with logins so the server can
+/// tell devices apart; created on first use
+pub fn load_or_create_device_id(path: &Path) -> io::Result<String> {
+    match fs::read_to_string(path) {
+        Ok(id) => Ok(id.trim().to_string()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            let id = crate::ZKP::generate_random_string(16);
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            fs::write(path, &id)?;
+            Ok(id)
+        }
+        Err(e) => Err(e),
+    }
+}
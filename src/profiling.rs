@@ -0,0 +1,133 @@
+//! CPU profiles of a live server (`profiling` feature).
+//!
+//! `--pprof-listen 127.0.0.1:6060` starts a small HTTP server next to the
+//! gRPC one:
+//!
+//! - `GET /debug/pprof/profile?seconds=30`: samples the process for that long
+//!   and returns a pprof protobuf (`go tool pprof`, speedscope, ...)
+//! - `GET /debug/pprof/flamegraph?seconds=30`: the same as an SVG flamegraph
+//!
+//! The hot paths (modpow, verify, hash-to-group, Argon2) are never inlined
+//! with this feature and carry `tracing` spans, so they show up as their own
+//! frames and can be timed with `RUST_LOG=rust_zkp_chaum_pedersen=trace`.
+//! Only listen on localhost or an internal network: a profile can take a
+//! while and shows what the server is doing.
+
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, StatusCode};
+use pprof::protos::Message;
+
+/// Sampling rate in Hz, slightly off 100 so we don't sample in lockstep with timers
+const FREQUENCY: i32 = 99;
+
+/// What an idle server's flamegraph looks like
+const EMPTY_FLAMEGRAPH: &str = concat!(
+    r#"<svg xmlns="http://www.w3.org/2000/svg" width="400" height="40">"#,
+    r#"<text x="10" y="25">no samples: the server was idle</text></svg>"#,
+    "\n"
+);
+
+/// Longest profile one request may ask for
+pub const MAX_SECONDS: u64 = 300;
+
+/// install a `tracing` subscriber that prints spans per `RUST_LOG` (nothing by default)
+pub fn init_tracing() {
+    let _ = tracing_subscriber::fmt()
+        .with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
+        .with_span_events(tracing_subscriber::fmt::format::FmtSpan::CLOSE)
+        .try_init();
+}
+
+/// serve the pprof endpoints on `addr` until the process exits
+pub async fn serve(addr: SocketAddr) -> Result<(), hyper::Error> {
+    let make_service = make_service_fn(|_| async { Ok::<_, Infallible>(service_fn(handle)) });
+    hyper::Server::try_bind(&addr)?.serve(make_service).await
+}
+
+async fn handle(request: Request<Body>) -> Result<Response<Body>, Infallible> {
+    if request.method() != Method::GET {
+        return Ok(reply(StatusCode::METHOD_NOT_ALLOWED, "text/plain", "use GET\n"));
+    }
+    let seconds = match profile_seconds(request.uri().query()) {
+        Ok(seconds) => seconds,
+        Err(message) => return Ok(reply(StatusCode::BAD_REQUEST, "text/plain", message)),
+    };
+    let format = match request.uri().path() {
+        "/debug/pprof/profile" => Format::Pprof,
+        "/debug/pprof/flamegraph" => Format::Flamegraph,
+        _ => return Ok(reply(StatusCode::NOT_FOUND, "text/plain", "not found\n")),
+    };
+
+    match profile(Duration::from_secs(seconds), format).await {
+        Ok(body) => Ok(reply(StatusCode::OK, format.content_type(), body)),
+        Err(e) => Ok(reply(StatusCode::INTERNAL_SERVER_ERROR, "text/plain", format!("{}\n", e))),
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Format {
+    Pprof,
+    Flamegraph,
+}
+
+impl Format {
+    fn content_type(self) -> &'static str {
+        match self {
+            Format::Pprof => "application/octet-stream",
+            Format::Flamegraph => "image/svg+xml",
+        }
+    }
+}
+
+/// sample every thread for `duration`
+async fn profile(duration: Duration, format: Format) -> Result<Vec<u8>, pprof::Error> {
+    let guard = pprof::ProfilerGuardBuilder::default()
+        .frequency(FREQUENCY)
+        .blocklist(&["libc", "libgcc", "pthread", "vdso"])
+        .build()?;
+    tokio::time::sleep(duration).await;
+    let report = guard.report().build()?;
+
+    let mut body = Vec::new();
+    match format {
+        Format::Pprof => {
+            report
+                .pprof()?
+                .encode(&mut body)
+                .expect("a Vec never runs out of space");
+        }
+        // inferno writes nothing at all without samples
+        Format::Flamegraph if report.data.is_empty() => {
+            body.extend_from_slice(EMPTY_FLAMEGRAPH.as_bytes());
+        }
+        Format::Flamegraph => report.flamegraph(&mut body)?,
+    }
+    Ok(body)
+}
+
+/// `seconds=N` from the query string, 30 when missing
+fn profile_seconds(query: Option<&str>) -> Result<u64, &'static str> {
+    let value = query
+        .unwrap_or_default()
+        .split('&')
+        .find_map(|pair| pair.strip_prefix("seconds="));
+    match value {
+        None => Ok(30),
+        Some(value) => match value.parse::<u64>() {
+            Ok(seconds) if (1..=MAX_SECONDS).contains(&seconds) => Ok(seconds),
+            _ => Err("seconds must be between 1 and 300\n"),
+        },
+    }
+}
+
+fn reply(status: StatusCode, content_type: &str, body: impl Into<Body>) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header("content-type", content_type)
+        .body(body.into())
+        .expect("static headers are valid")
+}
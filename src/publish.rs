@@ -0,0 +1,126 @@
+//! Audit events published to a streaming system as CloudEvents.
+//!
+//! With the `kafka` or `nats` feature, every audit event can also go out as a
+//! CloudEvents 1.0 JSON event ([`AuditEvent::to_cloud_event`]): to a Kafka
+//! topic, keyed by the user ([`crate::kafka`]), or to a NATS subject per
+//! action ([`crate::nats`]). Recording an event only queues it, so a slow or
+//! unreachable broker never holds up a login. A thread of the sink's own
+//! publishes what is queued in batches, reconnecting and retrying with backoff
+//! while the broker is away. Up to [`QUEUE`] events wait meanwhile; past that
+//! new ones are dropped and logged like with any failing sink.
+//! Queued events don't survive a restart, for that see the webhook outbox
+//! ([`crate::outbox`]). Published events can't be taken back, so erasing a
+//! user doesn't reach them.
+
+use std::fmt;
+use std::io;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, SyncSender, TrySendError};
+use std::time::Duration;
+
+use crate::audit::{hostname, AuditEvent, AuditSink};
+use crate::outbox;
+use crate::ulid::UlidGenerator;
+
+/// Events that may wait for the broker
+pub const QUEUE: usize = 10_000;
+/// Most events published at once
+const BATCH: usize = 500;
+/// Quiet time after which the connection is checked
+const IDLE: Duration = Duration::from_secs(30);
+/// Longest wait between two attempts; until then it doubles as in the outbox
+const LAST_RETRY: Duration = Duration::from_secs(30);
+
+/// One event on its way out
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Message {
+    pub key: String, // the user, empty when there is none
+    pub action: &'static str,
+    pub payload: String, // the CloudEvent
+}
+
+/// A connection to a broker, made and remade as needed by the publishing thread
+pub trait Transport: Send + 'static {
+    /// publish all of `batch`, oldest first, or fail; after a failure the next
+    /// call starts over on a new connection
+    fn publish(&mut self, batch: &[Message]) -> io::Result<()>;
+
+    /// nothing was published for a while: keep the connection alive
+    fn idle(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// `urn:zkp-auth:<hostname>`, the CloudEvents source by default
+pub fn default_source() -> String {
+    format!("urn:zkp-auth:{}", hostname())
+}
+
+/// Queues audit events for a [`Transport`] on a thread of its own
+pub struct PublishSink {
+    name: String, // where events go, for logs
+    source: String,
+    ids: UlidGenerator,
+    queue: SyncSender<Message>,
+}
+
+impl fmt::Debug for PublishSink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PublishSink").field("name", &self.name).field("source", &self.source).finish()
+    }
+}
+
+impl PublishSink {
+    /// start publishing to `transport`; `name` says where to in logs
+    pub fn start(name: &str, source: &str, transport: impl Transport) -> io::Result<Self> {
+        let (queue, receiver) = mpsc::sync_channel(QUEUE);
+        let thread_name = name.to_string();
+        std::thread::Builder::new()
+            .name("audit-publish".to_string())
+            .spawn(move || run(&thread_name, transport, receiver))?;
+        Ok(PublishSink { name: name.to_string(), source: source.to_string(), ids: UlidGenerator::default(), queue })
+    }
+}
+
+impl AuditSink for PublishSink {
+    fn record(&self, event: &AuditEvent) -> io::Result<()> {
+        let id = self.ids.next(event.time).to_string();
+        let message = Message { key: event.user.clone(), action: event.action, payload: event.to_cloud_event(&id, &self.source) };
+        self.queue.try_send(message).map_err(|e| match e {
+            TrySendError::Full(_) => io::Error::other(format!("{} events are waiting for {}, dropped this one", QUEUE, self.name)),
+            TrySendError::Disconnected(_) => io::Error::other(format!("publishing to {} stopped", self.name)),
+        })
+    }
+}
+
+/// how long to wait after the `failures`th failed attempt in a row
+pub fn backoff(failures: u32) -> Duration {
+    outbox::backoff(failures).min(LAST_RETRY)
+}
+
+fn run(name: &str, mut transport: impl Transport, receiver: Receiver<Message>) {
+    loop {
+        let first = match receiver.recv_timeout(IDLE) {
+            Ok(message) => message,
+            Err(RecvTimeoutError::Timeout) => {
+                if let Err(e) = transport.idle() {
+                    println!("⚠️  Lost the connection to {}: {}", name, e);
+                }
+                continue;
+            }
+            Err(RecvTimeoutError::Disconnected) => return,
+        };
+        let mut batch = vec![first];
+        batch.extend(receiver.try_iter().take(BATCH - 1));
+        let mut failures = 0;
+        while let Err(e) = transport.publish(&batch) {
+            failures += 1;
+            if failures == 1 {
+                println!("⚠️  Could not publish {} audit events to {}, retrying: {}", batch.len(), name, e);
+            }
+            std::thread::sleep(backoff(failures));
+        }
+        if failures > 0 {
+            println!("✅ Publishing audit events to {} again", name);
+        }
+    }
+}
@@ -0,0 +1,137 @@
+//! How long audit events and sessions are kept.
+//!
+//! An [`AuditRetention`] rule such as `login:failure=90d` says failed logins
+//! are kept for 90 days. A rule names an action, an outcome or both (`*` for
+//! any), and the most specific rule matching an event decides; events no rule
+//! matches are kept. Sessions have no expiry of their own, so the session
+//! retention counts from when one was created. The server applies the
+//! [`RetentionPolicy`] on a schedule (`--retention-interval`), dropping expired
+//! events from the `--audit-log` files and ending expired sessions.
+
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime};
+
+/// How long events matching an action and outcome are kept
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuditRetention {
+    pub action: Option<String>,  // None for any
+    pub outcome: Option<String>, // success, failure, denied or alert; None for any
+    pub keep: Duration,
+}
+
+impl AuditRetention {
+    fn matches(&self, action: &str, outcome: &str) -> bool {
+        self.action.as_deref().is_none_or(|a| a == action) && self.outcome.as_deref().is_none_or(|o| o == outcome)
+    }
+
+    /// an action and an outcome beat an action, which beats an outcome, which beats `*`
+    fn specificity(&self) -> u8 {
+        2 * self.action.is_some() as u8 + self.outcome.is_some() as u8
+    }
+}
+
+/// `[ACTION][:OUTCOME]=AGE`, e.g. `login:failure=90d`, `*:denied=30d` or `*=1y`
+impl FromStr for AuditRetention {
+    type Err = RetentionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let malformed = || RetentionError(format!("{:?} is not [ACTION][:OUTCOME]=AGE", s));
+        let (selector, age) = s.split_once('=').ok_or_else(malformed)?;
+        let (action, outcome) = selector.split_once(':').unwrap_or((selector, ""));
+        let any = |name: &str| (!name.is_empty() && name != "*").then(|| name.to_string());
+        let outcome = any(outcome);
+        if let Some(outcome) = &outcome {
+            if !["success", "failure", "denied", "alert"].contains(&outcome.as_str()) {
+                return Err(RetentionError(format!("{:?} is not an outcome (success, failure, denied or alert)", outcome)));
+            }
+        }
+        Ok(AuditRetention { action: any(action), outcome, keep: parse_age(age)? })
+    }
+}
+
+impl fmt::Display for AuditRetention {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let action = self.action.as_deref().unwrap_or("*");
+        match &self.outcome {
+            Some(outcome) => write!(f, "{}:{}={}", action, outcome, format_age(self.keep)),
+            None => write!(f, "{}={}", action, format_age(self.keep)),
+        }
+    }
+}
+
+/// Everything that is kept for a limited time; nothing is by default
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RetentionPolicy {
+    pub audit: Vec<AuditRetention>,
+    pub sessions: Option<Duration>, // counted from the login
+}
+
+impl RetentionPolicy {
+    pub fn is_empty(&self) -> bool {
+        self.audit.is_empty() && self.sessions.is_none()
+    }
+
+    /// how long an event is kept, None for ever
+    pub fn audit_keep(&self, action: &str, outcome: &str) -> Option<Duration> {
+        self.audit
+            .iter()
+            .filter(|rule| rule.matches(action, outcome))
+            .max_by_key(|rule| rule.specificity())
+            .map(|rule| rule.keep)
+    }
+
+    /// is an event from `time` past its retention at `now`?
+    pub fn audit_expired(&self, action: &str, outcome: &str, time: SystemTime, now: SystemTime) -> bool {
+        self.audit_keep(action, outcome).is_some_and(|keep| older_than(time, keep, now))
+    }
+
+    /// is a session created at `created_at` past its retention at `now`?
+    pub fn session_expired(&self, created_at: SystemTime, now: SystemTime) -> bool {
+        self.sessions.is_some_and(|keep| older_than(created_at, keep, now))
+    }
+}
+
+fn older_than(time: SystemTime, keep: Duration, now: SystemTime) -> bool {
+    now.duration_since(time).is_ok_and(|age| age > keep)
+}
+
+/// a number with a unit: `90d`, `12h`, `30m`, `45s`, `2w` or `1y` (365 days)
+pub fn parse_age(text: &str) -> Result<Duration, RetentionError> {
+    let malformed = || RetentionError(format!("{:?} is not an age like 90d, 12h or 30m", text));
+    let split = text.find(|c: char| !c.is_ascii_digit()).ok_or_else(malformed)?;
+    let number: u64 = text[..split].parse().map_err(|_| malformed())?;
+    let unit = match &text[split..] {
+        "s" => 1,
+        "m" => 60,
+        "h" => 3600,
+        "d" => 86400,
+        "w" => 7 * 86400,
+        "y" => 365 * 86400,
+        _ => return Err(malformed()),
+    };
+    number.checked_mul(unit).map(Duration::from_secs).ok_or_else(malformed)
+}
+
+/// the largest unit parse_age reads back to the same age
+pub fn format_age(age: Duration) -> String {
+    let secs = age.as_secs();
+    for (unit, length) in [("y", 365 * 86400), ("w", 7 * 86400), ("d", 86400), ("h", 3600), ("m", 60)] {
+        if secs > 0 && secs.is_multiple_of(length) {
+            return format!("{}{}", secs / length, unit);
+        }
+    }
+    format!("{}s", secs)
+}
+
+/// A rule or age that couldn't be parsed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetentionError(pub String);
+
+impl fmt::Display for RetentionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "retention: {}", self.0)
+    }
+}
+
+impl std::error::Error for RetentionError {}
@@ -0,0 +1,128 @@
+//! Revocation of exported non-interactive proofs.
+//!
+//! Every [`crate::nizk`] and [`crate::designated`] proof carries a random id
+//! inside its transcript. A user who leaked a proof can revoke it by id on the
+//! server (with a session from a fresh login), and verifiers check archived
+//! proofs against the server (`CheckRevocation`) or against an exported list,
+//! the way TLS clients use a CRL.
+//!
+//! Entries are per user: a user can only revoke proofs made under their own
+//! name, so revoking someone else's proof id does nothing to that proof.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::api::v1;
+
+/// Length of the random proof ids
+pub const PROOF_ID_LEN: usize = 16;
+
+/// One revoked proof
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RevokedProof {
+    pub user: String,
+    pub proof_id: String,
+    pub revoked_at: u64, // unix seconds
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub reason: String,
+}
+
+/// Every revoked proof, the CRL-style export format
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RevocationList {
+    pub updated_at: u64, // unix seconds of the last revocation
+    #[serde(default)]
+    pub revoked: Vec<RevokedProof>,
+}
+
+impl RevocationList {
+    /// load a list from disk, a missing file is just an empty list
+    pub fn load(path: &Path) -> io::Result<Self> {
+        match fs::read(path) {
+            Ok(bytes) => serde_json::from_slice(&bytes)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        fs::write(path, self.to_json())
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("revocation lists always serialize")
+    }
+
+    /// revoke `proof_id` of `user`; false if it already was
+    pub fn revoke(&mut self, user: &str, proof_id: &str, reason: &str, now: SystemTime) -> bool {
+        if self.lookup(user, proof_id).is_some() {
+            return false;
+        }
+        let now = now.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+        self.revoked.push(RevokedProof {
+            user: user.to_string(),
+            proof_id: proof_id.to_string(),
+            revoked_at: now,
+            reason: reason.to_string(),
+        });
+        self.updated_at = now;
+        true
+    }
+
+    /// the revocation of `proof_id` of `user`, if any
+    pub fn lookup(&self, user: &str, proof_id: &str) -> Option<&RevokedProof> {
+        self.revoked
+            .iter()
+            .find(|entry| entry.user == user && entry.proof_id == proof_id)
+    }
+
+    pub fn is_revoked(&self, user: &str, proof_id: &str) -> bool {
+        self.lookup(user, proof_id).is_some()
+    }
+
+    /// every revocation of `user`'s proofs
+    pub fn of_user<'a>(&'a self, user: &'a str) -> impl Iterator<Item = &'a RevokedProof> {
+        self.revoked.iter().filter(move |entry| entry.user == user)
+    }
+
+    /// drop `user`'s revocations (their proofs died with the registration); how many
+    pub fn forget_user(&mut self, user: &str) -> usize {
+        let before = self.revoked.len();
+        self.revoked.retain(|entry| entry.user != user);
+        before - self.revoked.len()
+    }
+}
+
+impl From<&RevokedProof> for v1::RevokedProof {
+    fn from(entry: &RevokedProof) -> Self {
+        v1::RevokedProof {
+            user: entry.user.clone(),
+            proof_id: entry.proof_id.clone(),
+            revoked_at: entry.revoked_at,
+            reason: entry.reason.clone(),
+        }
+    }
+}
+
+impl From<v1::RevocationListResponse> for RevocationList {
+    fn from(list: v1::RevocationListResponse) -> Self {
+        RevocationList {
+            updated_at: list.updated_at,
+            revoked: list
+                .revoked
+                .into_iter()
+                .map(|entry| RevokedProof {
+                    user: entry.user,
+                    proof_id: entry.proof_id,
+                    revoked_at: entry.revoked_at,
+                    reason: entry.reason,
+                })
+                .collect(),
+        }
+    }
+}
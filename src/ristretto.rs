@@ -0,0 +1,185 @@
+//! Chaum-Pedersen over ristretto255 (the `ristretto` feature).
+//!
+//! The same non-interactive proofs as [`crate::nizk`], in the prime-order
+//! group built on Curve25519 instead of Z_p*. Points and scalars are 32 bytes
+//! each, so (r1, r2, s) is 96 bytes where the 2048-bit group needs over 500,
+//! and proving and verifying are much faster. Proofs come back as the same
+//! [`NonInteractiveProof`] struct (id, r1, r2, s, window), so they serialize,
+//! travel and get revoked like the Z_p ones; [`to_compact`] and
+//! [`from_compact`] convert the numbers to and from a fixed 96-byte array.
+//!
+//! alpha is the ristretto255 base point; beta is hashed from a fixed label,
+//! so nobody knows its discrete log. The two groups don't mix: a key
+//! registered in one can't prove in the other, and [`crate::sigma`], whose
+//! equations are written over Z_p, only covers the Z_p group.
+
+use curve25519_dalek::constants::RISTRETTO_BASEPOINT_POINT;
+use curve25519_dalek::ristretto::{CompressedRistretto, RistrettoPoint};
+use curve25519_dalek::scalar::Scalar;
+use num_bigint::BigUint;
+use sha2::{Digest, Sha512};
+
+use crate::clock::Clock;
+use crate::nizk::{absorb, new_proof_id, NonInteractiveProof, ValidityWindow};
+use crate::rng;
+
+/// Domain separation for the Fiat-Shamir challenge
+const DOMAIN: &[u8] = b"zkp-auth/non-interactive/ristretto255/v1";
+/// Label beta is hashed from
+const BETA_LABEL: &[u8] = b"zkp-auth/ristretto255/beta/v1";
+
+/// Bytes of (r1, r2, s)
+pub const PROOF_LEN: usize = 96;
+/// Bytes of (y1, y2)
+pub const PUBLIC_KEY_LEN: usize = 64;
+
+/// The group: two generators of ristretto255
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Ristretto255 {
+    pub alpha: RistrettoPoint,
+    pub beta: RistrettoPoint,
+}
+
+/// (y1, y2) = (x * alpha, x * beta)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RistrettoPublicKey {
+    pub y1: RistrettoPoint,
+    pub y2: RistrettoPoint,
+}
+
+impl Default for Ristretto255 {
+    fn default() -> Self {
+        Ristretto255 {
+            alpha: RISTRETTO_BASEPOINT_POINT,
+            beta: RistrettoPoint::hash_from_bytes::<Sha512>(BETA_LABEL),
+        }
+    }
+}
+
+impl Ristretto255 {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// a uniformly random scalar, from the same generator as [`crate::ZKP`]'s numbers
+    pub fn random_scalar() -> Scalar {
+        let mut wide = [0u8; 64];
+        rng::with_rng(|rng| rng.fill_bytes(&mut wide));
+        Scalar::from_bytes_mod_order_wide(&wide)
+    }
+
+    pub fn public_key(&self, x: &Scalar) -> RistrettoPublicKey {
+        RistrettoPublicKey { y1: x * self.alpha, y2: x * self.beta }
+    }
+
+    /// prove knowledge of x for `window`, bound to `context` (e.g. who the proof is for)
+    pub fn prove_non_interactive(&self, x: &Scalar, window: ValidityWindow, context: &[u8]) -> NonInteractiveProof {
+        let key = self.public_key(x);
+        let k = Self::random_scalar();
+        let (r1, r2) = ((k * self.alpha).compress(), (k * self.beta).compress());
+        let id = new_proof_id();
+        let c = self.fiat_shamir(&key, &r1, &r2, &id, &window, context);
+        let s = k - c * x;
+        NonInteractiveProof {
+            id,
+            r1: r1.to_bytes().to_vec(),
+            r2: r2.to_bytes().to_vec(),
+            s: s.to_bytes().to_vec(),
+            window,
+        }
+    }
+
+    /// check `proof` for `key` and `context`, and that `clock` is inside its window
+    pub fn verify_non_interactive(
+        &self,
+        key: &RistrettoPublicKey,
+        proof: &NonInteractiveProof,
+        context: &[u8],
+        clock: impl Into<Clock>,
+    ) -> bool {
+        if !proof.window.contains(clock) {
+            return false;
+        }
+        let Some(compact) = to_compact(proof) else {
+            return false;
+        };
+        let r1 = CompressedRistretto(compact[..32].try_into().unwrap());
+        let r2 = CompressedRistretto(compact[32..64].try_into().unwrap());
+        let s = Scalar::from_canonical_bytes(compact[64..].try_into().unwrap());
+        let (Some(p1), Some(p2), Some(s)) = (r1.decompress(), r2.decompress(), Option::<Scalar>::from(s)) else {
+            return false;
+        };
+        let c = self.fiat_shamir(key, &r1, &r2, &proof.id, &proof.window, context);
+        p1 == s * self.alpha + c * key.y1 && p2 == s * self.beta + c * key.y2
+    }
+
+    /// H(domain | generators | y1 | y2 | r1 | r2 | id | window | context) as a scalar,
+    /// laid out like [`crate::nizk`]'s hash
+    fn fiat_shamir(
+        &self,
+        key: &RistrettoPublicKey,
+        r1: &CompressedRistretto,
+        r2: &CompressedRistretto,
+        id: &str,
+        window: &ValidityWindow,
+        context: &[u8],
+    ) -> Scalar {
+        let points = [self.alpha.compress(), self.beta.compress(), key.y1.compress(), key.y2.compress(), *r1, *r2];
+        let mut hasher = Sha512::new();
+        absorb(&mut hasher, DOMAIN, &points.map(|point| point.to_bytes()), id, window, context);
+        Scalar::from_hash(hasher)
+    }
+}
+
+impl RistrettoPublicKey {
+    /// y1 | y2, compressed
+    pub fn to_bytes(&self) -> [u8; PUBLIC_KEY_LEN] {
+        let mut bytes = [0u8; PUBLIC_KEY_LEN];
+        bytes[..32].copy_from_slice(self.y1.compress().as_bytes());
+        bytes[32..].copy_from_slice(self.y2.compress().as_bytes());
+        bytes
+    }
+
+    /// None unless both halves are valid encodings
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() != PUBLIC_KEY_LEN {
+            return None;
+        }
+        let y1 = CompressedRistretto::from_slice(&bytes[..32]).ok()?.decompress()?;
+        let y2 = CompressedRistretto::from_slice(&bytes[32..]).ok()?.decompress()?;
+        Some(RistrettoPublicKey { y1, y2 })
+    }
+}
+
+/// r1 | r2 | s of a ristretto255 proof, None if a field isn't 32 bytes
+pub fn to_compact(proof: &NonInteractiveProof) -> Option<[u8; PROOF_LEN]> {
+    let mut bytes = [0u8; PROOF_LEN];
+    for (field, chunk) in [&proof.r1, &proof.r2, &proof.s].into_iter().zip(bytes.chunks_mut(32)) {
+        if field.len() != 32 {
+            return None;
+        }
+        chunk.copy_from_slice(field);
+    }
+    Some(bytes)
+}
+
+/// rebuild a proof from its 96 bytes and the id and window sent alongside
+pub fn from_compact(bytes: &[u8; PROOF_LEN], id: String, window: ValidityWindow) -> NonInteractiveProof {
+    NonInteractiveProof {
+        id,
+        r1: bytes[..32].to_vec(),
+        r2: bytes[32..64].to_vec(),
+        s: bytes[64..].to_vec(),
+        window,
+    }
+}
+
+/// x reduced into the scalar field, e.g. a secret derived with [`crate::kdf`]
+pub fn scalar_from_biguint(x: &BigUint) -> Scalar {
+    let mut wide = [0u8; 64];
+    let order = BigUint::from_bytes_le((-Scalar::ONE).as_bytes()) + 1u32;
+    let reduced = (x % order).to_bytes_le();
+    wide[..reduced.len()].copy_from_slice(&reduced);
+    Scalar::from_bytes_mod_order_wide(&wide)
+}
+
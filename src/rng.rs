@@ -0,0 +1,32 @@
+//! Source of randomness for nonces, challenges and IDs.
+//!
+//! By default everything comes from the OS-seeded CSPRNG (`thread_rng`).
+//! For teaching material and debugging the whole process can be switched to
+//! a generator seeded with a fixed number, which makes client and server runs
+//! reproducible. That mode is INSECURE: anyone who knows the seed can predict
+//! every nonce and therefore recover the secret from a single proof.
+
+use std::sync::Mutex;
+
+use rand::rngs::StdRng;
+use rand::{RngCore, SeedableRng};
+
+static SEEDED: Mutex<Option<StdRng>> = Mutex::new(None);
+
+/// switch the process to a seeded generator (None goes back to the OS CSPRNG)
+pub(crate) fn set_seed(seed: Option<u64>) {
+    *SEEDED.lock().unwrap() = seed.map(StdRng::seed_from_u64);
+}
+
+pub(crate) fn is_seeded() -> bool {
+    SEEDED.lock().unwrap().is_some()
+}
+
+/// run `f` with whichever generator is currently configured
+pub(crate) fn with_rng<T>(f: impl FnOnce(&mut dyn RngCore) -> T) -> T {
+    let mut seeded = SEEDED.lock().unwrap();
+    match seeded.as_mut() {
+        Some(rng) => f(rng),
+        None => f(&mut rand::thread_rng()),
+    }
+}
@@ -0,0 +1,278 @@
+//! Background jobs on cron-like schedules.
+//!
+//! The server's periodic work (expiring challenges, retention, memory and
+//! crypto pool reports, parameter scans, retrying or following the user
+//! store, reloading certificates, refreshing Vault secrets, fetching
+//! federation peers) runs as named jobs in one [`Scheduler`]. A [`Schedule`]
+//! is either `@every <age>` (run at startup, then every so often) or a
+//! five-field cron expression in UTC:
+//!
+//! ```text
+//! minute hour day-of-month month day-of-week
+//! 0 3 * * *        03:00 every day
+//! */15 * * * 1-5   every 15 minutes on weekdays
+//! @daily           the same as 0 0 * * *
+//! ```
+//!
+//! Fields take `*`, numbers, ranges (`1-5`), steps (`*/15`, `0-30/10`) and
+//! lists (`0,30`). When both the day of the month and the day of the week are
+//! restricted, either one matching is enough, like in cron. A job's runs never
+//! overlap: a run that takes longer than the gap delays the next one. A run
+//! that panics is logged and the job keeps its schedule. Every run is counted
+//! in [`crate::metrics::jobs`].
+
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::str::FromStr;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::catch_panic;
+use crate::clock::civil_from_days;
+use crate::metrics;
+use crate::retention;
+
+/// When a job runs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Schedule {
+    /// at startup, then every so often
+    Every(Duration),
+    Cron(Cron),
+}
+
+impl FromStr for Schedule {
+    type Err = ScheduleError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if let Some(age) = s.strip_prefix("@every ") {
+            let period = retention::parse_age(age.trim()).map_err(|e| ScheduleError(e.0))?;
+            if period.is_zero() {
+                return Err(ScheduleError(format!("{:?} would run all the time", s)));
+            }
+            return Ok(Schedule::Every(period));
+        }
+        let expression = match s {
+            "@hourly" => "0 * * * *",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@weekly" => "0 0 * * 0",
+            "@monthly" => "0 0 1 * *",
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            _ => s,
+        };
+        Ok(Schedule::Cron(expression.parse()?))
+    }
+}
+
+impl fmt::Display for Schedule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Schedule::Every(period) => write!(f, "@every {}", retention::format_age(*period)),
+            Schedule::Cron(cron) => write!(f, "{}", cron.expression),
+        }
+    }
+}
+
+/// A five-field cron expression, in UTC
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cron {
+    expression: String,
+    minutes: u64, // bit n set when minute n matches
+    hours: u64,
+    days: u64, // 1-31
+    months: u64, // 1-12
+    weekdays: u64, // 0-6, Sunday is 0
+    any_day: bool, // day-of-month was `*`
+    any_weekday: bool,
+}
+
+impl FromStr for Cron {
+    type Err = ScheduleError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let fields: Vec<&str> = s.split_whitespace().collect();
+        let [minute, hour, day, month, weekday] = fields[..] else {
+            return Err(ScheduleError(format!("{:?} is not `minute hour day month weekday` or @every <age>", s)));
+        };
+        let mut weekdays = field(weekday, 0, 7)?;
+        if weekdays & (1 << 7) != 0 {
+            weekdays = (weekdays | 1) & !(1 << 7); // 7 is Sunday too
+        }
+        let cron = Cron {
+            expression: fields.join(" "),
+            minutes: field(minute, 0, 59)?,
+            hours: field(hour, 0, 23)?,
+            days: field(day, 1, 31)?,
+            months: field(month, 1, 12)?,
+            weekdays,
+            any_day: day == "*",
+            any_weekday: weekday == "*",
+        };
+        if cron.next_after(SystemTime::now()).is_none() {
+            return Err(ScheduleError(format!("{:?} never comes", s)));
+        }
+        Ok(cron)
+    }
+}
+
+/// one field as a bit set of the values it matches
+fn field(text: &str, min: u64, max: u64) -> Result<u64, ScheduleError> {
+    let malformed = || ScheduleError(format!("{:?} is not a cron field for {}-{}", text, min, max));
+    let number = |text: &str| text.parse::<u64>().ok().filter(|n| (min..=max).contains(n)).ok_or_else(malformed);
+    let mut bits = 0;
+    for item in text.split(',') {
+        let (range, step) = match item.split_once('/') {
+            Some((range, step)) => (range, step.parse::<u64>().ok().filter(|step| *step > 0).ok_or_else(malformed)?),
+            None => (item, 1),
+        };
+        let (first, last) = match range.split_once('-') {
+            _ if range == "*" => (min, max),
+            Some((first, last)) => (number(first)?, number(last)?),
+            // `5/10` runs from 5 to the end, like `5-59/10`
+            None if step > 1 => (number(range)?, max),
+            None => (number(range)?, number(range)?),
+        };
+        if first > last {
+            return Err(malformed());
+        }
+        for value in (first..=last).step_by(step as usize) {
+            bits |= 1 << value;
+        }
+    }
+    Ok(bits)
+}
+
+impl Cron {
+    /// the first matching minute strictly after `after`; None if there is
+    /// none in the next five years (30 February)
+    pub fn next_after(&self, after: SystemTime) -> Option<SystemTime> {
+        let minute = after.duration_since(UNIX_EPOCH).ok()?.as_secs() / 60 + 1;
+        let (first_day, mut from) = (minute / 1440, minute % 1440);
+        for day in first_day..first_day + 5 * 366 {
+            let (_, month, day_of_month) = civil_from_days(day as i64);
+            let weekday = (day + 4) % 7; // 1970-01-01 was a Thursday
+            if self.matches_day(month as u64, day_of_month as u64, weekday) {
+                let at = (from..1440).find(|m| self.hours & (1 << (m / 60)) != 0 && self.minutes & (1 << (m % 60)) != 0);
+                if let Some(at) = at {
+                    return Some(UNIX_EPOCH + Duration::from_secs((day * 1440 + at) * 60));
+                }
+            }
+            from = 0;
+        }
+        None
+    }
+
+    fn matches_day(&self, month: u64, day: u64, weekday: u64) -> bool {
+        let by_day = self.days & (1 << day) != 0;
+        let by_weekday = self.weekdays & (1 << weekday) != 0;
+        self.months & (1 << month) != 0
+            && match (self.any_day, self.any_weekday) {
+                (false, false) => by_day || by_weekday,
+                _ => by_day && by_weekday,
+            }
+    }
+}
+
+/// A schedule that couldn't be parsed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScheduleError(pub String);
+
+impl fmt::Display for ScheduleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "schedule: {}", self.0)
+    }
+}
+
+impl std::error::Error for ScheduleError {}
+
+/// Work the scheduler runs; closures returning a future are jobs, and so is
+/// anything that keeps state between runs
+pub trait Job: Send + 'static {
+    fn run(&mut self) -> impl Future<Output = ()> + Send;
+}
+
+impl<F, Fut> Job for F
+where
+    F: FnMut() -> Fut + Send + 'static,
+    Fut: Future<Output = ()> + Send,
+{
+    fn run(&mut self) -> impl Future<Output = ()> + Send {
+        self()
+    }
+}
+
+type Task = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+/// Named jobs and their schedules, started together
+#[derive(Default)]
+pub struct Scheduler {
+    jobs: Vec<(&'static str, Schedule, Task)>,
+}
+
+impl fmt::Debug for Scheduler {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.jobs.iter().map(|(name, schedule, _)| (name, schedule))).finish()
+    }
+}
+
+impl Scheduler {
+    /// run `job` on `schedule` once the scheduler is started
+    pub fn add(&mut self, name: &'static str, schedule: Schedule, mut job: impl Job) {
+        let mut timer = Timer::from(schedule.clone());
+        let task = async move {
+            while timer.tick().await {
+                let (started, clock) = (SystemTime::now(), Instant::now());
+                if let Err(panic) = catch_panic::contained(job.run()).await {
+                    println!("💥 job {} {}\n{}", name, panic, panic.backtrace);
+                }
+                metrics::jobs().record(name, started, clock.elapsed());
+            }
+        };
+        self.jobs.push((name, schedule, Box::pin(task)));
+    }
+
+    /// the jobs added so far, in order
+    pub fn jobs(&self) -> impl Iterator<Item = (&'static str, &Schedule)> {
+        self.jobs.iter().map(|(name, schedule, _)| (*name, schedule))
+    }
+
+    /// spawn every job on the current tokio runtime
+    pub fn start(self) -> Vec<tokio::task::JoinHandle<()>> {
+        self.jobs.into_iter().map(|(_, _, task)| tokio::spawn(task)).collect()
+    }
+}
+
+/// waits for a job's next run
+enum Timer {
+    Every(Duration, Option<tokio::time::Interval>), // the interval is made on the first tick, inside the runtime
+    Cron(Cron),
+}
+
+impl From<Schedule> for Timer {
+    fn from(schedule: Schedule) -> Self {
+        match schedule {
+            Schedule::Every(period) => Timer::Every(period, None),
+            Schedule::Cron(cron) => Timer::Cron(cron),
+        }
+    }
+}
+
+impl Timer {
+    /// false when there is no next run
+    async fn tick(&mut self) -> bool {
+        match self {
+            Timer::Every(period, interval) => {
+                interval.get_or_insert_with(|| tokio::time::interval(*period)).tick().await;
+                true
+            }
+            Timer::Cron(cron) => {
+                let now = SystemTime::now();
+                let Some(next) = cron.next_after(now) else {
+                    return false;
+                };
+                tokio::time::sleep(next.duration_since(now).unwrap_or_default()).await;
+                true
+            }
+        }
+    }
+}
@@ -0,0 +1,178 @@
+//! A quick check of the cryptography before the server takes traffic.
+//!
+//! [`run`] recomputes known answers for the RFC 5114 group (public keys,
+//! commitments, a response and both user generator hashes, computed
+//! independently of num-bigint), validates the group the server uses, does a
+//! full prove/verify round with fresh random numbers and makes sure a wrong
+//! answer is rejected. When the parameters are signed, the bundle has to verify
+//! under the server's key and describe the same group. With the `p256`
+//! feature, P-256 gets the same round. The server refuses to start if any
+//! check fails, so a miscompiled math backend or a corrupted parameter file
+//! shows up at boot rather than as failed logins.
+
+use std::fmt;
+
+use ed25519_dalek::VerifyingKey;
+use num_bigint::BigUint;
+use sha2::{Digest, Sha256};
+
+use crate::group::HashId;
+use crate::params::SignedParameters;
+use crate::ZKP;
+
+// secret, nonce and challenge for the known answers
+const X: &str = "84df3b3e9c9ecf6291948ced88f3067331e66344";
+const K: &str = "46ded15bdb43964ba344f355d0ff0cf2e73ee409";
+const C: &str = "1fc52f6b6a99630e20e33555c3237acb409166d6";
+const S: &str = "47a578cf07875a692000b169c8674dbbce32e363";
+// SHA-256 of the big-endian bytes of each group element
+const Y1: &str = "d1b051b80124c1700e0989f4831e27e82d0223e6670733040d34b277e31230a5";
+const Y2: &str = "65b99aa6ba1d3a9a85ec3c94caab4dae641358e40cf2477cf21be5a3704c7cec";
+const R1: &str = "c11e714cbf3aa7e7400c227a1dede5bafa8e4279c6595e2eb2f19c46012658d4";
+const R2: &str = "baeae2fb3ce5630de83f816f29df82c98c0467d68245f4ce5aa3abcee76ddf28";
+const H2G_SHA256: &str = "827ee8d6a10303e7bf6921cae35384a767b68dbabd0a2e8dabdee0b6126db61d";
+const H2G_SHA512: &str = "22aed6c143dda4e06daa4dc79a13b179da438b0a08f90ac61c15b6f06a83af2f";
+
+/// A check that failed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SelfTestError {
+    pub check: &'static str,
+    pub reason: String,
+}
+
+impl fmt::Display for SelfTestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "self-test {}: {}", self.check, self.reason)
+    }
+}
+
+impl std::error::Error for SelfTestError {}
+
+/// run every check against the server's group `zkp` and, if it publishes
+/// them, its signed parameters with the key that signed them; the names of
+/// the checks that passed
+pub fn run(zkp: &ZKP, signed: Option<(&SignedParameters, &VerifyingKey)>) -> Result<Vec<&'static str>, SelfTestError> {
+    known_answers()?;
+    group(zkp)?;
+    round_trip(zkp)?;
+    user_generators()?;
+    let mut passed = vec!["known answers", "group", "round trip", "user generators"];
+    if let Some((parameters, key)) = signed {
+        signed_parameters(zkp, parameters, key)?;
+        passed.push("signed parameters");
+    }
+    #[cfg(feature = "p256")]
+    {
+        p256_round_trip()?;
+        passed.push("p256");
+    }
+    Ok(passed)
+}
+
+fn fail(check: &'static str, reason: impl Into<String>) -> SelfTestError {
+    SelfTestError { check, reason: reason.into() }
+}
+
+fn number(hex: &str) -> BigUint {
+    BigUint::parse_bytes(hex.as_bytes(), 16).expect("the vectors are hex")
+}
+
+fn digest(n: &BigUint) -> String {
+    hex::encode(Sha256::digest(n.to_bytes_be()))
+}
+
+/// the RFC 5114 group's arithmetic gives the answers computed elsewhere
+fn known_answers() -> Result<(), SelfTestError> {
+    let check = |name: &str, value: &BigUint, expected: &str| match digest(value) == expected {
+        true => Ok(()),
+        false => Err(fail("known answers", format!("{} is {:x}", name, value))),
+    };
+    let zkp = ZKP::builder().rfc5114().build().map_err(|e| fail("known answers", e.to_string()))?;
+    let (x, k, c) = (number(X), number(K), number(C));
+    let (y1, y2) = zkp.compute_pair(&x);
+    check("y1", &y1, Y1)?;
+    check("y2", &y2, Y2)?;
+    let (r1, r2) = zkp.compute_pair(&k);
+    check("r1", &r1, R1)?;
+    check("r2", &r2, R2)?;
+    let s = zkp.solve(&k, &c, &x);
+    if s != number(S) {
+        return Err(fail("known answers", format!("s is {:x}", s)));
+    }
+    if !zkp.verify(&r1, &r2, &y1, &y2, &c, &s) {
+        return Err(fail("known answers", "the known proof doesn't verify"));
+    }
+    if zkp.verify(&r1, &r2, &y1, &y2, &c, &(s + 1u32)) {
+        return Err(fail("known answers", "a wrong response verifies"));
+    }
+    Ok(())
+}
+
+/// the server's numbers form a usable group
+fn group(zkp: &ZKP) -> Result<(), SelfTestError> {
+    ZKP::builder()
+        .p(zkp.p.clone())
+        .q(zkp.q.clone())
+        .alpha(zkp.alpha.clone())
+        .beta(zkp.beta.clone())
+        .build()
+        .map(|_| ())
+        .map_err(|e| fail("group", e.to_string()))
+}
+
+/// a proof with fresh random numbers verifies, and only with the right challenge
+fn round_trip(zkp: &ZKP) -> Result<(), SelfTestError> {
+    let x = ZKP::generate_random_number_below(&zkp.q);
+    let k = ZKP::generate_random_number_below(&zkp.q);
+    let c = ZKP::generate_random_number_below(&zkp.q);
+    let (y1, y2) = zkp.compute_pair(&x);
+    let (r1, r2) = zkp.compute_pair(&k);
+    let s = zkp.solve(&k, &c, &x);
+    if !zkp.verify(&r1, &r2, &y1, &y2, &c, &s) {
+        return Err(fail("round trip", "a valid proof doesn't verify"));
+    }
+    if zkp.verify(&r1, &r2, &y1, &y2, &((c + 1u32) % &zkp.q), &s) {
+        return Err(fail("round trip", "a proof verifies for another challenge"));
+    }
+    Ok(())
+}
+
+/// H2G(user) is still what existing accounts registered with, for every hash
+fn user_generators() -> Result<(), SelfTestError> {
+    let zkp = ZKP::builder().rfc5114().build().map_err(|e| fail("user generators", e.to_string()))?;
+    for (hash, expected) in [(HashId::Sha256, H2G_SHA256), (HashId::Sha512, H2G_SHA512)] {
+        let beta = zkp.for_user_with(hash, "self-test").beta;
+        if digest(&beta) != expected {
+            return Err(fail("user generators", format!("H2G with {} is {:x}", hash.name(), beta)));
+        }
+    }
+    Ok(())
+}
+
+/// the published bundle verifies under the server's key and is the group in use
+fn signed_parameters(zkp: &ZKP, parameters: &SignedParameters, key: &VerifyingKey) -> Result<(), SelfTestError> {
+    let reread = SignedParameters::from_json(&parameters.to_json()).map_err(|e| fail("signed parameters", e.to_string()))?;
+    match reread.verify(key) {
+        Ok(signed) if signed == *zkp => Ok(()),
+        Ok(_) => Err(fail("signed parameters", "they describe another group than the server uses")),
+        Err(e) => Err(fail("signed parameters", e.to_string())),
+    }
+}
+
+#[cfg(feature = "p256")]
+fn p256_round_trip() -> Result<(), SelfTestError> {
+    use crate::nist::P256;
+
+    let group = P256::new();
+    let (x, k, c) = (P256::random_scalar(), P256::random_scalar(), P256::random_scalar());
+    let key = group.public_key(&x);
+    let commitment = group.commitment(&k);
+    let s = group.respond(&k, &c, &x);
+    if !group.verify(&key, &commitment, &c, &s) {
+        return Err(fail("p256", "a valid proof doesn't verify"));
+    }
+    if group.verify(&key, &commitment, &P256::random_scalar(), &s) {
+        return Err(fail("p256", "a proof verifies for another challenge"));
+    }
+    Ok(())
+}
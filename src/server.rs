@@ -1,39 +1,492 @@
-use std::{collections::HashMap, sync::Mutex};
+use std::{collections::{BTreeMap, HashMap, HashSet}, path::PathBuf, sync::{Arc, Mutex, RwLock}, time::{Duration, Instant, SystemTime}};
 
+use clap::Parser;
+use ed25519_dalek::{SigningKey, VerifyingKey};
 use num_bigint::BigUint;
-use tonic::{transport::Server, Code, Request, Response, Status};
+use prost::Message;
+use tonic::{service::{interceptor::InterceptedService, Interceptor}, transport::{Channel, ClientTlsConfig, Endpoint, Server}, Code, Request, Response, Status};
 
-use rust_zkp_chaum_pedersen::ZKP;
-
-pub mod zkp_auth {
-    include!("./zkp_auth.rs");
-}
+use rust_zkp_chaum_pedersen::{
+    admin::AdminToken,
+    api,
+    audit::{Audit, AuditEvent, JsonlSink, Outcome, StdoutSink, SyslogSink},
+    blocking::{self, CryptoPool},
+    catch_panic::{CatchPanicLayer, LockExt},
+    challenge,
+    challenge_token::{self, ChallengeToken, ChallengeTokenKey, TokenError},
+    clock::{self, Time},
+    compression::{self, Compression},
+    config_audit,
+    delegation::{self, Delegation},
+    device_key::{self, Device, MAX_DEVICES_PER_USER},
+    device_login::{self, DeviceLogins, Poll},
+    duplicates::{self, DuplicateFilter, Seen},
+    federation::{self, Federation, PeerSpec},
+    fixed::ZKP1024,
+    group::{GroupId, HashId},
+    idempotency::{IdempotencyCache, Replay},
+    id_token::{self, IdTokenIssuer},
+    shamir::{ShamirError, Share, Unsealer},
+    kdf::KdfParams,
+    lease::{self, Lease},
+    memory::{self, HeapSize, StoreUsage, MAP_ENTRY_OVERHEAD},
+    pending::PendingChallenges,
+    metrics::{self, UserParameters},
+    nizk::NonInteractiveProof,
+    ssh_cert::{self, SshCa, SshPublicKey},
+    shard::ShardedStore,
+    store::{FlushPolicy, Storage, StoreKey, StoredDevice, StoredSession, StoredUser, UserStore},
+    policy::{Decision, LoginContext, Policy, RuleSet},
+    pow::{self, PowPolicy},
+    gate::{self, ChallengeContext, Gate, GateError},
+    geoip::GeoIp,
+    ip_filter::{Blocked, IpFilter, IpFilterLayer, OnBlocked, Rule},
+    telemetry::{self, PrivacyLevel, TelemetryPolicy},
+    trace,
+    params::SignedParameters,
+    retention::{self, AuditRetention, RetentionPolicy},
+    scheduler::{Job, Schedule, ScheduleError, Scheduler},
+    selftest,
+    revocation::RevocationList,
+    tls::CertReloader,
+    types,
+    ulid::{Ulid, UlidGenerator},
+    user_id::{self, CodeSender, UserIds, VerificationCode},
+    validate::{Validate, Validated},
+    ZKP,
+};
+#[cfg(feature = "vault")]
+use rust_zkp_chaum_pedersen::vault::{SecretRef, VaultClient, VaultError};
+#[cfg(feature = "kms")]
+use rust_zkp_chaum_pedersen::{
+    id_token::{IdTokenClaims, UnsignedIdToken},
+    kms::{AwsCredentials, GcpToken, KmsKey, KmsSigner},
+};
+#[cfg(any(feature = "webhooks", feature = "captcha"))]
+use rust_zkp_chaum_pedersen::http;
+#[cfg(feature = "webhooks")]
+use rust_zkp_chaum_pedersen::outbox::{self, Outbox};
+#[cfg(feature = "captcha")]
+use rust_zkp_chaum_pedersen::captcha::{self, Captcha};
+#[cfg(feature = "kafka")]
+use rust_zkp_chaum_pedersen::kafka::Kafka;
+#[cfg(feature = "nats")]
+use rust_zkp_chaum_pedersen::nats::{Nats, NatsUrl};
+#[cfg(any(feature = "kafka", feature = "nats"))]
+use rust_zkp_chaum_pedersen::publish::{self, PublishSink};
 
-use zkp_auth::{
+use api::v1::{
+    admin_server::{Admin, AdminServer},
+    auth_client::AuthClient,
     auth_server::{Auth, AuthServer},
     AuthenticationAnswerRequest, AuthenticationAnswerResponse, AuthenticationChallengeRequest,
-    AuthenticationChallengeResponse, RegisterRequest, RegisterResponse,
+    AuthenticationChallengeResponse, ChallengeCommitmentRequest, ChallengeCommitmentResponse,
+    ChallengeMode, ClientMetadata, CheckRevocationRequest, CheckRevocationResponse, ElevateSessionRequest,
+    ElevateSessionResponse, EraseUserRequest, EraseUserResponse, ExportUserDataRequest, ExportUserDataResponse,
+    IdTokenRequest, IntrospectSessionRequest, IntrospectSessionResponse, ListSessionsRequest, ListSessionsResponse,
+    LoginRecord, ParametersRequest, ParametersResponse, RegisterRequest, RegisterResponse, RevocationListRequest,
+    RevocationListResponse, RevokeProofRequest, RevokeProofResponse, RevokeSessionRequest,
+    BulkRegisterFailure, BulkRegisterResponse, RevokeSessionResponse, ServerInfoRequest, SetUserEnabledRequest, SetUserEnabledResponse,
+    SecuritySettings, ServerInfoResponse, ServerTimeRequest, ServerTimeResponse, SessionInfo, SessionTier, UserKdfParamsRequest,
+    UnsealIdTokenKeyRequest, UnsealIdTokenKeyResponse, UserKdfParamsResponse, VerifyEmailRequest, VerifyEmailResponse,
+    ApproveDeviceLoginRequest, ApproveDeviceLoginResponse, DeviceLoginState, FinishDeviceLoginRequest,
+    FinishDeviceLoginResponse, PollDeviceLoginRequest, PollDeviceLoginResponse, StartDeviceLoginRequest,
+    StartDeviceLoginResponse, DeviceInfo, EnrollDeviceRequest, EnrollDeviceResponse, ListDevicesRequest,
+    ListDevicesResponse, RevokeDeviceRequest, RevokeDeviceResponse, VerifyDelegationRequest, VerifyDelegationResponse,
+    FederationInfoRequest, FederationInfoResponse, VerifyFederatedProofRequest, VerifyFederatedProofResponse,
+    VerifyFederatedTokenRequest, VerifyFederatedTokenResponse, VerifyProofRequest, VerifyProofResponse,
 };
 
 #[derive(Debug, Default)]
 pub struct AuthImpl {
     pub user_info: Mutex<HashMap<String, UserInfo>>,
-    pub auth_id_to_user: Mutex<HashMap<String, String>>,
+    // auth_ids are ULIDs, so these sort by issue time and expire with one split_off
+    // challenges waiting for an answer; past --max-pending-challenge-bytes the oldest are dropped
+    pub pending: PendingChallenges,
+    pub auth_ids: UlidGenerator,
+    pub challenge_ttl: Option<Duration>, // None keeps challenges until restart
+    pub session_retention: Option<Duration>, // reported by GetServerInfo, the retention job ends the sessions
+    // signs direct challenges into tokens instead of keeping them pending, None keeps them (--stateless-challenges)
+    pub challenge_tokens: Option<ChallengeTokenKey>,
+    pub elevation_ttl: Option<Duration>, // how long ElevateSession lasts, None turns it off
+    pub delegation_max_ttl: Option<Duration>, // the longest delegation VerifyDelegation accepts, None turns them off
+    // QR codes shown by devices that want a session approved from a phone, None turns them off
+    pub device_logins: Option<DeviceLogins>,
+    pub challenge_bits: Option<u64>, // shorter challenges than q, None for c uniform below q
+    pub pow: PowPolicy, // when answers need a proof of work (--pow-difficulty)
+    // published through GetParameters when the server has a signing key
+    pub parameters: Option<SignedParameters>,
+    pub parameters_key: Option<SigningKey>, // also signs what GetFederationInfo publishes
+    // peers whose ID tokens and proofs are checked here, None when there are none (--federation-peer)
+    pub federation: Option<Federation>,
+    // completed registrations by idempotency key, so retries don't fail
+    pub registrations: IdempotencyCache<RegisterRequest>,
+    // registrations must be checked against at least this password policy version
+    pub min_password_policy: u32,
+    // revoked non-interactive proofs, saved to revocation_path on every change
+    pub revocations: Mutex<RevocationList>,
+    pub revocation_path: Option<PathBuf>,
+    // clock skew we tell clients to allow for, see GetServerTime
+    pub max_clock_skew: Duration,
+    // what expiry, sessions and validity windows are checked against, the system clock outside tests
+    pub time: Time,
+    // threads the modpows run on, away from the tokio workers
+    pub crypto: Arc<CryptoPool>,
+    // (r1, r2, s) triples answered recently, to catch one proof used for several auth_ids
+    pub duplicates: DuplicateFilter,
+    pub log_duplicate_proofs: bool, // only log duplicates instead of refusing them
+    // runs after a proof verifies and before the session is issued
+    pub policy: Policy,
+    // runs before a challenge is issued, OpenGate unless --captcha-secret-file is set
+    pub gate: Gate,
+    // usernames only an intruder would try; any challenge or answer for one is an alert (--honeypot-user)
+    pub honeypots: HashSet<String>,
+    // mints ID tokens for logins that ask for one, None when --id-token-issuer isn't set
+    pub id_tokens: Mutex<Option<IdTokens>>,
+    // signs the SSH keys logins send, None when --ssh-ca-key isn't set
+    pub ssh_ca: Option<SshCa>,
+    // where registrations, logins and revocations are recorded (--audit-*)
+    pub audit: Audit,
+    // journals of registrations, users sharded over them (--user-store), None keeps users in memory only
+    pub user_store: Option<ShardedStore>,
+    // follows a user store the primary writes and refuses writes of its own (--read-replica)
+    pub read_replica: bool,
+    pub primary: Option<AuthClient<Channel>>, // where a read replica forwards registrations, None refuses them
+    // what user fields hold (--user-ids), and how email verification codes go out
+    pub user_ids: UserIds,
+    pub email_codes: Option<CodeSender>,
+    // what resource servers send to IntrospectSession, the Admin service's token; None refuses introspection
+    admin_token: Option<SharedAdminToken>,
 }
 
 #[derive(Debug, Default)]
 pub struct UserInfo {
     // registration
     pub user_name: String,
-    pub y1: BigUint,
-    pub y2: BigUint,
+    pub public_key: types::PublicKey,
+    pub kdf: Option<KdfParams>, // how the client derived x, None for the legacy derivation
+    pub beta: Option<BigUint>, // per-user generator H2G(user_name), None for the global beta
+    pub group: GroupId, // what (y1, y2) and the answers are encoded in
+    pub hash: HashId, // what beta was derived with
+    pub disabled: bool, // by an admin: no challenges, no sessions, the record stays
+    pub unverified: bool, // --user-ids email: no challenges until VerifyEmail
+    pub verification: Option<VerificationCode>, // the code sent last, None after a restart
     // authorization
-    pub r1: BigUint,
-    pub r2: BigUint,
+    pub commitment: types::Commitment,
     // verification
-    pub c: BigUint,
-    pub s: BigUint,
-    pub session_id: String,
+    pub c: types::Challenge,
+    pub s: types::Response,
+    pub pending_client: Option<ClientMetadata>, // what the client reported with the current challenge
+    pub pending_device: Option<String>, // the enrolled device the current challenge is for, None for the password
+    pub pending_pow: u32, // the proof of work the current challenge asked for, 0 for none
+    pub sessions: HashMap<String, Session>, // by session_id, one per logged-in device
+    pub devices: HashMap<String, Device>, // enrolled device keys by device_id
+    // login history, returned with the next successful login
+    pub last_login: Option<LoginRecord>,
+    pub last_failed_login: Option<LoginRecord>,
+    pub failed_logins: u32, // since last_login
+}
+
+#[derive(Debug, Clone)]
+pub struct Session {
+    pub created_at: SystemTime,
+    pub client: Option<ClientMetadata>, // the device holding the session
+    pub device: Option<String>, // the enrolled device key it logged in with, None for the password
+    pub elevated_until: Option<SystemTime>, // set by ElevateSession, None for a standard session
+}
+
+/// The ID token issuer, or what it will be once its key is unsealed
+#[derive(Debug)]
+pub enum IdTokens {
+    Ready(IdTokenIssuer),
+    /// waiting for shares of the key (--id-token-key-share, UnsealIdTokenKey)
+    Sealed {
+        issuer: String,
+        audiences: Vec<String>,
+        lifetime: Duration,
+        public_key: VerifyingKey, // what the shares have to combine to
+        unsealer: Unsealer,
+    },
+    /// signed by a cloud KMS key (--kms-aws-key, --kms-gcp-key)
+    #[cfg(feature = "kms")]
+    Kms {
+        signer: Arc<KmsSigner>,
+        issuer: String,
+        audiences: Vec<String>,
+        lifetime: Duration,
+    },
+}
+
+/// An ID token, or what the KMS still has to sign
+enum Minting {
+    Done(String),
+    #[cfg(feature = "kms")]
+    Remote(Arc<KmsSigner>, UnsignedIdToken),
+}
+
+impl IdTokens {
+    fn allows_audience(&self, audience: &str) -> bool {
+        match self {
+            IdTokens::Ready(issuer) => issuer.allows_audience(audience),
+            IdTokens::Sealed { audiences, .. } => audiences.iter().any(|allowed| allowed == audience),
+            #[cfg(feature = "kms")]
+            IdTokens::Kms { audiences, .. } => audiences.iter().any(|allowed| allowed == audience),
+        }
+    }
+
+    /// the issuer and the key tokens are signed with, as federation peers are told
+    fn identity(&self) -> (&str, VerifyingKey) {
+        match self {
+            IdTokens::Ready(issuer) => (issuer.issuer(), issuer.public_key()),
+            IdTokens::Sealed { issuer, public_key, .. } => (issuer, *public_key),
+            #[cfg(feature = "kms")]
+            IdTokens::Kms { signer, issuer, .. } => (issuer, *signer.public_key()),
+        }
+    }
+
+    /// take a share of the key; becomes Ready with the last one needed
+    #[allow(clippy::result_large_err)] // answered as is
+    fn unseal(&mut self, share: Share) -> Result<(), Status> {
+        let IdTokens::Sealed { issuer, audiences, lifetime, public_key, unsealer } = self else {
+            return Ok(());
+        };
+        let status = |e: ShamirError| Status::new(Code::InvalidArgument, e.to_string());
+        let Some(secret) = unsealer.add(share).map_err(status)? else {
+            return Ok(());
+        };
+        let key = <[u8; 32]>::try_from(secret.as_slice()).ok().map(|bytes| SigningKey::from_bytes(&bytes));
+        match key {
+            Some(key) if key.verifying_key() == *public_key => {
+                *self = IdTokens::Ready(IdTokenIssuer::new(key, issuer.clone(), std::mem::take(audiences), *lifetime));
+                Ok(())
+            }
+            _ => Err(Status::new(
+                Code::InvalidArgument,
+                "the shares don't combine to the ID token key (--id-token-public-key), start over",
+            )),
+        }
+    }
+}
+
+/// Sessions a user can hold at once; logging in on one more device ends the oldest
+const MAX_SESSIONS_PER_USER: usize = 32;
+
+/// Characters in a session ID (alphanumeric, about 71 bits)
+const SESSION_ID_LEN: usize = 12;
+
+impl HeapSize for Session {
+    fn heap_size(&self) -> usize {
+        self.client.heap_size() + self.device.heap_size()
+    }
+}
+
+impl HeapSize for UserInfo {
+    fn heap_size(&self) -> usize {
+        self.user_name.heap_size()
+            + self.public_key.heap_size()
+            + self.kdf.heap_size()
+            + self.beta.heap_size()
+            + self.commitment.heap_size()
+            + self.c.heap_size()
+            + self.s.heap_size()
+            + self.pending_client.heap_size()
+            + self.pending_device.heap_size()
+            + self.sessions.heap_size()
+            + self.devices.heap_size()
+            + self.last_login.heap_size()
+            + self.last_failed_login.heap_size()
+    }
+}
+
+impl From<StoredUser> for UserInfo {
+    fn from(stored: StoredUser) -> Self {
+        UserInfo {
+            public_key: stored.public_key(),
+            kdf: stored.kdf(),
+            beta: stored.beta(),
+            group: stored.group(),
+            hash: stored.hash(),
+            disabled: stored.disabled,
+            unverified: stored.unverified,
+            devices: stored
+                .devices
+                .into_iter()
+                .map(|device| {
+                    let enrolled_at = SystemTime::UNIX_EPOCH + Duration::from_millis(device.enrolled_at);
+                    (device.id.clone(), Device { public_key: device.public_key(), name: device.name, enrolled_at })
+                })
+                .collect(),
+            user_name: stored.user,
+            ..Default::default()
+        }
+    }
+}
+
+/// `user_info`'s sessions, as a sled user store keeps them
+fn stored_sessions(user_info: &UserInfo) -> Vec<StoredSession> {
+    user_info
+        .sessions
+        .iter()
+        .map(|(id, session)| StoredSession {
+            id: id.clone(),
+            created_at: clock::unix_millis(session.created_at),
+            client: session.client.as_ref().map(|client| hex::encode(client.encode_to_vec())).unwrap_or_default(),
+            device: session.device.clone(),
+            elevated_until: session.elevated_until.map(clock::unix_millis),
+        })
+        .collect()
+}
+
+/// a session a sled user store kept, with its id
+fn restored_session(stored: StoredSession) -> (String, Session) {
+    let at = |millis| SystemTime::UNIX_EPOCH + Duration::from_millis(millis);
+    let client = hex::decode(&stored.client).ok().filter(|client| !client.is_empty());
+    let session = Session {
+        created_at: at(stored.created_at),
+        client: client.and_then(|client| ClientMetadata::decode(client.as_slice()).ok()),
+        device: stored.device,
+        elevated_until: stored.elevated_until.map(at),
+    };
+    (stored.id, session)
+}
+
+impl UserInfo {
+    fn has_session(&self, session_id: &str) -> bool {
+        !session_id.is_empty() && self.sessions.contains_key(session_id)
+    }
+
+    /// remember a wrong answer for the next login to report
+    fn record_failed_answer(&mut self, source: String, now: SystemTime) {
+        self.last_failed_login = Some(LoginRecord {
+            unix_millis: clock::unix_millis(now),
+            source,
+            client: self.pending_client.clone(),
+        });
+        self.failed_logins = self.failed_logins.saturating_add(1);
+    }
+
+    /// remember a new session, making room by dropping the oldest one
+    fn add_session(&mut self, session_id: String, session: Session) {
+        if self.sessions.len() >= MAX_SESSIONS_PER_USER {
+            let oldest = self
+                .sessions
+                .iter()
+                .min_by_key(|(_, session)| session.created_at)
+                .map(|(id, _)| id.clone());
+            if let Some(oldest) = oldest {
+                self.sessions.remove(&oldest);
+            }
+        }
+        self.sessions.insert(session_id, session);
+    }
+}
+
+/// the validated RFC 5114 group, with the user's own beta if they have one
+fn group_of(user_info: &UserInfo) -> ZKP {
+    let zkp = ZKP1024::rfc5114().into_inner();
+    match &user_info.beta {
+        Some(beta) => ZKP { beta: beta.clone(), ..zkp },
+        None => zkp,
+    }
+}
+
+/// the enrolled device a challenge request names, None for the password
+#[allow(clippy::result_large_err)] // same error type as the handlers
+fn enrolled_device(user_info: &UserInfo, device_id: &str) -> Result<Option<String>, Status> {
+    match device_id {
+        "" => Ok(None),
+        id if user_info.devices.contains_key(id) => Ok(Some(id.to_string())),
+        id => Err(Status::new(
+            Code::NotFound,
+            format!("Device: {} is not enrolled for {}", id, user_info.user_name),
+        )),
+    }
+}
+
+/// the key an answer is checked with: the password's, or the enrolled
+/// device's; a device revoked since the challenge was handed out has none
+#[allow(clippy::result_large_err)] // same error type as the handlers
+fn answering_key(user_info: &UserInfo, device_id: Option<&str>) -> Result<types::PublicKey, Status> {
+    match device_id {
+        None => Ok(user_info.public_key.clone()),
+        Some(device_id) => match user_info.devices.get(device_id) {
+            Some(device) => Ok(device.public_key.clone()),
+            None => Err(Status::new(
+                Code::PermissionDenied,
+                format!("Device: {} was revoked", device_id),
+            )),
+        },
+    }
+}
+
+/// the ClientMetadata a challenge token was issued with
+fn token_client(token: &ChallengeToken) -> Option<ClientMetadata> {
+    match token.client.as_slice() {
+        [] => None,
+        client => ClientMetadata::decode(client).ok(),
+    }
+}
+
+/// refuse an answer without the proof of work its challenge asked for
+#[allow(clippy::result_large_err)] // same error type as the handlers
+fn require_proof_of_work(
+    user_name: &str,
+    auth_id: &str,
+    c: &[u8],
+    difficulty: u32,
+    nonce: u64,
+    source: &str,
+    trace: &trace::TraceContext,
+) -> Result<(), Status> {
+    if pow::verify(auth_id, c, difficulty, nonce) {
+        return Ok(());
+    }
+    println!("⚠️  Missing proof of work username: {:?} source: {} trace_id: {}", telemetry::user(user_name), telemetry::source(source), trace.trace_id_hex());
+    Err(Status::new(
+        Code::FailedPrecondition,
+        format!("AuthId: {} needs a proof of work of {} bits in pow_nonce", auth_id, difficulty),
+    ))
+}
+
+/// what the policy hook gets to see about a verified login
+fn login_context(user_name: &str, user_info: &UserInfo, client: Option<&ClientMetadata>, source: &str, now: SystemTime) -> LoginContext {
+    let client = client.cloned().unwrap_or_default();
+    let known_device = user_info
+        .sessions
+        .values()
+        .filter_map(|session| session.client.as_ref())
+        .chain(user_info.last_login.as_ref().and_then(|login| login.client.as_ref()))
+        .any(|known| known.device_id == client.device_id);
+    let new_device = user_info.last_login.is_some() && !known_device;
+    LoginContext {
+        user: user_name.to_string(),
+        device_id: client.device_id,
+        app_version: client.app_version,
+        platform: client.platform,
+        source: source.to_string(),
+        new_device,
+        risk_score: risk_score(user_info.failed_logins, new_device),
+        time: now,
+    }
+}
+
+/// a rough 0-100 estimate: 20 per wrong answer since the last login (at most
+/// 60) and 40 for a device the user hasn't logged in from
+fn risk_score(failed_logins: u32, new_device: bool) -> u32 {
+    failed_logins.saturating_mul(20).min(60) + if new_device { 40 } else { 0 }
+}
+
+/// "device on platform (version)" for the logs
+fn describe_client(client: Option<&ClientMetadata>) -> String {
+    match client {
+        Some(client) => format!("{} on {} ({})", client.device_id, client.platform, client.app_version),
+        None => "unknown device".to_string(),
+    }
+}
+
+/// the CAPTCHA token a client sent with its request, empty when none
+fn gate_token<T>(request: &Request<T>) -> String {
+    request.metadata().get(gate::TOKEN_HEADER).and_then(|token| token.to_str().ok()).unwrap_or_default().to_string()
 }
 
 #[tonic::async_trait]
@@ -42,53 +495,78 @@ impl Auth for AuthImpl {
         &self,
         request: Request<RegisterRequest>,
     ) -> Result<Response<RegisterResponse>, Status> {
+        let trace = trace::current(&request);
+        let source = request.remote_addr().map(|addr| addr.to_string()).unwrap_or_default();
         let request = request.into_inner();
+        if self.read_replica {
+            println!("↪️  Forwarding Registration username: {:?} to the primary trace_id: {}", telemetry::user(&request.user), trace.trace_id_hex());
+            let response = self.primary("registrations")?.register(trace.request(request)).await?.into_inner();
+            self.follow_user_store();
+            return Ok(trace.response(response));
+        }
 
-        let user_name = request.user;
-        println!("Processing Registration username: {:?}", user_name);
-
-        let user_info = UserInfo {
-            user_name: user_name.clone(),
-            y1: BigUint::from_bytes_be(&request.y1),
-            y2: BigUint::from_bytes_be(&request.y2),
-            ..Default::default()
-        };
-
-        let user_info_hashmap = &mut self.user_info.lock().unwrap();
-        user_info_hashmap.insert(user_name.clone(), user_info);
-
-        println!("✅ Successful Registration username: {:?}", user_name);
-        Ok(Response::new(RegisterResponse {}))
+        let subject = self.register_user(request, &source, &trace, false).await?;
+        let verify_email = self.user_info.locked().get(&subject).is_some_and(|user_info| user_info.unverified);
+        Ok(trace.response(RegisterResponse { subject, verify_email }))
     }
 
     async fn create_authentication_challenge(
         &self,
         request: Request<AuthenticationChallengeRequest>,
     ) -> Result<Response<AuthenticationChallengeResponse>, Status> {
-        let request = request.into_inner();
+        let trace = trace::current(&request);
+        let source = request.remote_addr().map(|addr| addr.to_string()).unwrap_or_default();
+        let token = gate_token(&request);
+        let mut request = request.into_inner();
+        request.user = self.canonical(&request.user)?;
+
+        let user_name = request.user.clone();
+        if telemetry::sampled(&trace) {
+            println!("Processing Challenge Request username: {:?} trace_id: {}", telemetry::user(&user_name), trace.trace_id_hex());
+        }
+        self.honeypot_alert(&user_name, &source, &trace, "challenge requested");
+        if request.auth_id.is_empty() {
+            self.check_gate(&user_name, &source, token, &trace).await?;
+        }
+
+        if let Some(key) = &self.challenge_tokens {
+            if !request.auth_id.is_empty() {
+                return Err(Status::new(
+                    Code::FailedPrecondition,
+                    "committed challenges are kept on the server, this one is --stateless-challenges",
+                ));
+            }
+            return self.sign_challenge(key, request, &source, trace);
+        }
 
-        let user_name = request.user;
-        println!("Processing Challenge Request username: {:?}", user_name);
+        let user_info_hashmap = &mut self.user_info.locked();
 
-        let user_info_hashmap = &mut self.user_info.lock().unwrap();
+        if !request.auth_id.is_empty() {
+            return self.open_committed_challenge(user_info_hashmap, request, &source, trace);
+        }
 
         if let Some(user_info) = user_info_hashmap.get_mut(&user_name) {
+            self.check_enabled(user_info, &source, &trace)?;
+            user_info.pending_device = enrolled_device(user_info, &request.device_id)?;
             let (_, _, _, q) = ZKP::get_constants();
-            let c = ZKP::generate_random_number_below(&q);
-            let auth_id = ZKP::generate_random_string(12);
+            let c = types::Challenge(challenge::random(self.challenge_bits.unwrap_or(q.bits()), &q));
+            let auth_id = self.new_auth_id();
 
             user_info.c = c.clone();
-            user_info.r1 = BigUint::from_bytes_be(&request.r1);
-            user_info.r2 = BigUint::from_bytes_be(&request.r2);
+            user_info.commitment = types::Commitment::from_bytes_be(&request.r1, &request.r2);
+            user_info.pending_client = request.client.clone();
+            user_info.pending_pow = self.pow.difficulty(self.pending.unanswered());
 
-            let auth_id_to_user = &mut self.auth_id_to_user.lock().unwrap();
-            auth_id_to_user.insert(auth_id.clone(), user_name.clone());
+            self.add_pending(auth_id.clone(), user_name.clone(), None);
 
-            println!("✅ Successful Challenge Request username: {:?}", user_name);
+            println!("✅ Successful Challenge Request username: {:?} trace_id: {}", telemetry::user(&user_name), trace.trace_id_hex());
             
-            Ok(Response::new(AuthenticationChallengeResponse {
+            Ok(trace.response(AuthenticationChallengeResponse {
                 auth_id,
                 c: c.to_bytes_be(),
+                nonce: Vec::new(),
+                pow_difficulty: user_info.pending_pow,
+                challenge_token: String::new(),
             }))
         } else {
             Err(Status::new(
@@ -102,68 +580,3805 @@ impl Auth for AuthImpl {
         &self,
         request: Request<AuthenticationAnswerRequest>,
     ) -> Result<Response<AuthenticationAnswerResponse>, Status> {
+        let trace = trace::current(&request);
+        let source = request.remote_addr().map(|addr| addr.to_string()).unwrap_or_default();
         let request = request.into_inner();
 
         let auth_id = request.auth_id;
-        println!("Processing Challenge Solution auth_id: {:?}", auth_id);
+        if telemetry::sampled(&trace) {
+            println!("Processing Challenge Solution auth_id: {:?} trace_id: {}", auth_id, trace.trace_id_hex());
+        }
 
-        let auth_id_to_user_hashmap = &mut self.auth_id_to_user.lock().unwrap();
+        // refuse a token we won't mint before spending modpows on the proof
+        if let Some(wanted) = &request.id_token {
+            match &*self.id_tokens.locked() {
+                None => {
+                    return Err(Status::new(
+                        Code::Unimplemented,
+                        "this server doesn't issue ID tokens (--id-token-issuer)",
+                    ))
+                }
+                Some(id_tokens) if !id_tokens.allows_audience(&wanted.audience) => {
+                    return Err(Status::new(
+                        Code::InvalidArgument,
+                        format!("ID tokens aren't issued for audience {:?}", wanted.audience),
+                    ))
+                }
+                Some(IdTokens::Sealed { unsealer, .. }) => {
+                    let (have, need) = unsealer.progress();
+                    return Err(Status::new(
+                        Code::Unavailable,
+                        format!("the ID token key is sealed ({} of {} shares), an admin has to unseal it", have, need),
+                    ));
+                }
+                Some(_) => {}
+            }
+        }
 
-        if let Some(user_name) = auth_id_to_user_hashmap.get(&auth_id) {
-            let user_info_hashmap = &mut self.user_info.lock().unwrap();
-            let user_info = user_info_hashmap
-                .get_mut(user_name)
-                .expect("AuthId not found on hashmap");
+        let ssh_key = match (request.ssh_public_key.as_str(), &self.ssh_ca) {
+            ("", _) => None,
+            (_, None) => {
+                return Err(Status::new(
+                    Code::Unimplemented,
+                    "this server doesn't issue SSH certificates (--ssh-ca-key)",
+                ))
+            }
+            (line, Some(_)) => {
+                Some(SshPublicKey::parse(line).map_err(|e| Status::new(Code::InvalidArgument, e.to_string()))?)
+            }
+        };
 
-            let s = BigUint::from_bytes_be(&request.s);
-            user_info.s = s;
+        let token = self.open_challenge_token(&request.challenge_token, &auth_id)?;
+        let (user_name, mut verification) = match &token {
+            Some(token) => {
+                require_proof_of_work(&token.user, &auth_id, &token.c, token.pow_difficulty, request.pow_nonce, &source, &trace)?;
+                self.check_token_answer(token, &request.s, &trace).await?
+            }
+            None => {
+                self.check_proof_of_work(&auth_id, request.pow_nonce, &source, &trace)?;
+                self.check_answer(&auth_id, &request.s, &trace).await?
+            }
+        };
+        // a proof that verifies for a honeypot means its planted credentials
+        // were taken; it is answered like any wrong one
+        let what = match verification {
+            true => "correct answer: its credentials have leaked",
+            false => "answer to a challenge",
+        };
+        if self.honeypot_alert(&user_name, &source, &trace, what) {
+            verification = false;
+        }
+        if verification {
+            // a pending challenge is gone once answered, a token is remembered
+            // as used instead; either way a second answer, or one racing this
+            // one, logs in no further
+            let used_up = match &token {
+                Some(token) => self.duplicates.use_up(&token.auth_id),
+                None => self.pending.remove(&auth_id),
+            };
+            if !used_up {
+                return Err(Status::new(Code::NotFound, format!("AuthId: {} has already been answered", auth_id)));
+            }
+        }
 
-            let (alpha, beta, p, q) = ZKP::get_constants();
-            let zkp = ZKP { alpha, beta, p, q };
+        if verification {
+            let session_id = ZKP::generate_random_string(SESSION_ID_LEN);
+            let mut device = String::new();
+            let mut response = AuthenticationAnswerResponse {
+                session_id: session_id.clone(),
+                subject: user_name.clone(),
+                ..Default::default()
+            };
+            // minted before the user's lock is taken, since a KMS key is a network call away;
+            // a login the policy then denies just drops it
+            let now = self.time.now();
+            if let Some(wanted) = &request.id_token {
+                response.id_token = self.mint_id_token(&user_name, wanted, &session_id, now).await?;
+            }
+            if let Some(user_info) = self.user_info.locked().get_mut(&user_name) {
+                // disabled after the challenge was handed out
+                self.check_enabled(user_info, &source, &trace)?;
 
-            let verification = zkp.verify(
-                &user_info.r1,
-                &user_info.r2,
-                &user_info.y1,
-                &user_info.y2,
-                &user_info.c,
-                &user_info.s,
-            );
+                // the proof is fine, the policy decides whether that's enough
+                let pending_client = match &token {
+                    Some(token) => token_client(token),
+                    None => user_info.pending_client.clone(),
+                };
+                let login = login_context(&user_name, user_info, pending_client.as_ref(), &source, now);
+                self.check_policy("login", &login, &trace)?;
 
-            if verification {
-                let session_id = ZKP::generate_random_string(12);
+                // a stateless challenge carries what a pending one keeps in user_info
+                let (client, device_key) = match &token {
+                    Some(token) => (pending_client, (!token.device_id.is_empty()).then(|| token.device_id.clone())),
+                    None => (user_info.pending_client.take(), user_info.pending_device.take()),
+                };
+                device = describe_client(client.as_ref());
+                self.audit("login", Outcome::Success, &user_name, &source, &trace, format!("new session on {}", device));
+                let this_login = LoginRecord { unix_millis: clock::unix_millis(now), source, client: client.clone() };
+                if let (Some(key), Some(ca)) = (&ssh_key, &self.ssh_ca) {
+                    let key_id = format!("zkp-auth:{}:{}", user_name, session_id);
+                    response.ssh_certificate = ca.sign(key, &user_name, &key_id, now, self.max_clock_skew);
+                    println!("🔑 Signed SSH {} key username: {:?} trace_id: {}", key.key_type(), telemetry::user(&user_name), trace.trace_id_hex());
+                }
+                user_info.add_session(session_id, Session { created_at: now, client, device: device_key, elevated_until: None });
+                self.keep_sessions(user_info);
 
-                println!("✅ Correct Challenge Solution username: {:?}", user_name);
+                response.last_login = user_info.last_login.replace(this_login);
+                response.last_failed_login = user_info.last_failed_login.clone();
+                response.failed_logins = std::mem::take(&mut user_info.failed_logins);
+            }
 
-                Ok(Response::new(AuthenticationAnswerResponse { session_id }))
-            } else {
-                println!("❌ Wrong Challenge Solution username: {:?}", user_name);
+            println!("✅ Correct Challenge Solution username: {:?} device: {} trace_id: {}", telemetry::user(&user_name), telemetry::device(&device), trace.trace_id_hex());
 
-                Err(Status::new(
-                    Code::PermissionDenied,
-                    format!("AuthId: {} bad solution to the challenge", auth_id),
-                ))
-            }
+            Ok(trace.response(response))
         } else {
+            println!("❌ Wrong Challenge Solution username: {:?} source: {} trace_id: {}", telemetry::user(&user_name), telemetry::source(&source), trace.trace_id_hex());
+            self.audit("login", Outcome::Failure, &user_name, &source, &trace, "wrong answer to the challenge");
+            if let Some(user_info) = self.user_info.locked().get_mut(&user_name) {
+                user_info.record_failed_answer(source, self.time.now());
+            }
+
             Err(Status::new(
-                Code::NotFound,
-                format!("AuthId: {} not found in database", auth_id),
+                Code::PermissionDenied,
+                format!("AuthId: {} bad solution to the challenge", auth_id),
             ))
         }
     }
-}
 
-#[tokio::main]
-async fn main() {
-    let addr = "0.0.0.0:50051".to_string();
+    async fn get_user_kdf_params(
+        &self,
+        request: Request<UserKdfParamsRequest>,
+    ) -> Result<Response<UserKdfParamsResponse>, Status> {
+        let trace = trace::current(&request);
+        let user_name = self.canonical(&request.into_inner().user)?;
 
-    println!("✅ Running the server in {}", addr);
+        let user_info_hashmap = self.user_info.locked();
+        match user_info_hashmap.get(&user_name) {
+            Some(user_info) => Ok(trace.response(UserKdfParamsResponse {
+                kdf: user_info.kdf.as_ref().map(Into::into),
+                beta: user_info.beta.as_ref().map(BigUint::to_bytes_be).unwrap_or_default(),
+                group: match user_info.group {
+                    GroupId::ModP => String::new(),
+                    group => group.name().to_string(),
+                },
+                hash: match user_info.hash {
+                    HashId::Sha256 => String::new(),
+                    hash => hash.name().to_string(),
+                },
+            })),
+            None => Err(Status::new(
+                Code::NotFound,
+                format!("User: {} not found in database", user_name),
+            )),
+        }
+    }
+
+    async fn get_server_info(
+        &self,
+        request: Request<ServerInfoRequest>,
+    ) -> Result<Response<ServerInfoResponse>, Status> {
+        let trace = trace::current(&request);
+        let (_, _, _, q) = ZKP::get_constants();
+        Ok(trace.response(ServerInfoResponse {
+            challenge_modes: match self.challenge_tokens {
+                Some(_) => vec![ChallengeMode::Direct as i32],
+                None => vec![ChallengeMode::Direct as i32, ChallengeMode::Committed as i32],
+            },
+            groups: GroupId::supported().iter().map(|group| group.name().to_string()).collect(),
+            hashes: HashId::ALL.iter().map(|hash| hash.name().to_string()).collect(),
+            user_ids: self.user_ids.name().to_string(),
+            security: Some(SecuritySettings {
+                challenge_bits: self.challenge_bits.unwrap_or(q.bits()),
+                session_id_bits: config_audit::alphanumeric_bits(SESSION_ID_LEN),
+                challenge_ttl_secs: match (self.challenge_ttl, &self.challenge_tokens) {
+                    (Some(ttl), _) => ttl.as_secs(),
+                    (None, Some(_)) => challenge_token::DEFAULT_LIFETIME.as_secs(),
+                    (None, None) => 0,
+                },
+                session_retention_secs: self.session_retention.map_or(0, |age| age.as_secs()),
+            }),
+        }))
+    }
+
+    async fn commit_challenge(
+        &self,
+        request: Request<ChallengeCommitmentRequest>,
+    ) -> Result<Response<ChallengeCommitmentResponse>, Status> {
+        let trace = trace::current(&request);
+        let source = request.remote_addr().map(|addr| addr.to_string()).unwrap_or_default();
+        let token = gate_token(&request);
+        let user_name = self.canonical(&request.into_inner().user)?;
+        if self.challenge_tokens.is_some() {
+            return Err(Status::new(
+                Code::FailedPrecondition,
+                "committed challenges are kept on the server, this one is --stateless-challenges",
+            ));
+        }
+        if telemetry::sampled(&trace) {
+            println!("Processing Challenge Commitment username: {:?} trace_id: {}", telemetry::user(&user_name), trace.trace_id_hex());
+        }
+        self.honeypot_alert(&user_name, &source, &trace, "challenge commitment requested");
+        self.check_gate(&user_name, &source, token, &trace).await?;
+
+        match self.user_info.locked().get(&user_name) {
+            Some(user_info) => self.check_enabled(user_info, &source, &trace)?,
+            None => {
+                return Err(Status::new(
+                    Code::NotFound,
+                    format!("User: {} not found in database", user_name),
+                ))
+            }
+        }
+
+        // c is fixed now, before the client has picked its commitments
+        let (_, _, _, q) = ZKP::get_constants();
+        let c = challenge::random(self.challenge_bits.unwrap_or(q.bits()), &q);
+        let nonce = challenge::new_nonce();
+        let commitment = challenge::commit(&c, &nonce);
+        let auth_id = self.new_auth_id();
+
+        self.add_pending(auth_id.clone(), user_name.clone(), Some((c, nonce)));
+
+        println!("✅ Committed to Challenge username: {:?} trace_id: {}", telemetry::user(&user_name), trace.trace_id_hex());
+        Ok(trace.response(ChallengeCommitmentResponse { auth_id, commitment }))
+    }
+
+    async fn revoke_proof(
+        &self,
+        request: Request<RevokeProofRequest>,
+    ) -> Result<Response<RevokeProofResponse>, Status> {
+        let trace = trace::current(&request);
+        let source = request.remote_addr().map(|addr| addr.to_string()).unwrap_or_default();
+        let mut request = request.into_inner();
+        request.user = self.canonical(&request.user)?;
+        if telemetry::sampled(&trace) {
+            println!("Processing Proof Revocation username: {:?} trace_id: {}", telemetry::user(&request.user), trace.trace_id_hex());
+        }
+
+        let logged_in = self
+            .user_info
+            .locked()
+            .get(&request.user)
+            .is_some_and(|user_info| user_info.has_session(&request.session_id));
+        if !logged_in {
+            return Err(Status::new(
+                Code::Unauthenticated,
+                format!("no valid session for {}, log in first", request.user),
+            ));
+        }
+
+        let revocations = &mut self.revocations.locked();
+        if revocations.revoke(&request.user, &request.proof_id, &request.reason, self.time.now()) {
+            if let Some(path) = &self.revocation_path {
+                revocations
+                    .save(path)
+                    .map_err(|e| Status::new(Code::Internal, format!("could not save the revocation list: {}", e)))?;
+            }
+            self.audit("revoke_proof", Outcome::Success, &request.user, &source, &trace, format!("proof {}: {}", request.proof_id, request.reason));
+            println!("🚫 Revoked Proof {} username: {:?} trace_id: {}", request.proof_id, telemetry::user(&request.user), trace.trace_id_hex());
+        }
+        Ok(trace.response(RevokeProofResponse {}))
+    }
+
+    async fn check_revocation(
+        &self,
+        request: Request<CheckRevocationRequest>,
+    ) -> Result<Response<CheckRevocationResponse>, Status> {
+        let trace = trace::current(&request);
+        let mut request = request.into_inner();
+        request.user = self.canonical(&request.user)?;
+        let revocations = self.revocations.locked();
+        let response = match revocations.lookup(&request.user, &request.proof_id) {
+            Some(entry) => CheckRevocationResponse {
+                revoked: true,
+                revoked_at: entry.revoked_at,
+                reason: entry.reason.clone(),
+            },
+            None => CheckRevocationResponse::default(),
+        };
+        Ok(trace.response(response))
+    }
+
+    async fn get_revocation_list(
+        &self,
+        request: Request<RevocationListRequest>,
+    ) -> Result<Response<RevocationListResponse>, Status> {
+        let trace = trace::current(&request);
+        let revocations = self.revocations.locked();
+        Ok(trace.response(RevocationListResponse {
+            updated_at: revocations.updated_at,
+            revoked: revocations.revoked.iter().map(Into::into).collect(),
+        }))
+    }
+
+    async fn get_server_time(
+        &self,
+        request: Request<ServerTimeRequest>,
+    ) -> Result<Response<ServerTimeResponse>, Status> {
+        let trace = trace::current(&request);
+        Ok(trace.response(ServerTimeResponse {
+            unix_millis: clock::unix_millis(self.time.now()),
+            max_skew_secs: self.max_clock_skew.as_secs(),
+        }))
+    }
+
+    async fn list_sessions(
+        &self,
+        request: Request<ListSessionsRequest>,
+    ) -> Result<Response<ListSessionsResponse>, Status> {
+        let trace = trace::current(&request);
+        let mut request = request.into_inner();
+        request.user = self.canonical(&request.user)?;
+        if telemetry::sampled(&trace) {
+            println!("Processing Session Listing username: {:?} trace_id: {}", telemetry::user(&request.user), trace.trace_id_hex());
+        }
+
+        let user_info_hashmap = self.user_info.locked();
+        let Some(user_info) = user_info_hashmap.get(&request.user).filter(|u| u.has_session(&request.session_id)) else {
+            return Err(Status::new(
+                Code::Unauthenticated,
+                format!("no valid session for {}, log in first", request.user),
+            ));
+        };
+
+        let now = self.time.now();
+        let mut sessions: Vec<SessionInfo> = user_info
+            .sessions
+            .iter()
+            .map(|(session_id, session)| SessionInfo {
+                session_id: session_id.clone(),
+                created_unix_millis: clock::unix_millis(session.created_at),
+                client: session.client.clone(),
+                current: *session_id == request.session_id,
+                device_id: session.device.clone().unwrap_or_default(),
+                elevated_until_unix_millis: session
+                    .elevated_until
+                    .filter(|until| *until > now)
+                    .map(clock::unix_millis)
+                    .unwrap_or_default(),
+            })
+            .collect();
+        sessions.sort_by_key(|session| session.created_unix_millis);
+        Ok(trace.response(ListSessionsResponse { sessions }))
+    }
+
+    async fn revoke_session(
+        &self,
+        request: Request<RevokeSessionRequest>,
+    ) -> Result<Response<RevokeSessionResponse>, Status> {
+        let trace = trace::current(&request);
+        let source = request.remote_addr().map(|addr| addr.to_string()).unwrap_or_default();
+        let mut request = request.into_inner();
+        request.user = self.canonical(&request.user)?;
+        if telemetry::sampled(&trace) {
+            println!("Processing Session Revocation username: {:?} trace_id: {}", telemetry::user(&request.user), trace.trace_id_hex());
+        }
+
+        let user_info_hashmap = &mut self.user_info.locked();
+        let Some(user_info) = user_info_hashmap.get_mut(&request.user).filter(|u| u.has_session(&request.session_id)) else {
+            return Err(Status::new(
+                Code::Unauthenticated,
+                format!("no valid session for {}, log in first", request.user),
+            ));
+        };
+
+        // only the user's own sessions can be found, so nobody ends someone else's
+        match user_info.sessions.remove(&request.revoke_session_id) {
+            Some(session) => {
+                // on disk before it is acknowledged, so a restart doesn't bring it back
+                if let Err(e) = self.save_sessions(user_info) {
+                    user_info.sessions.insert(request.revoke_session_id.clone(), session);
+                    println!("⚠️  User store failed, keeping the session: {}", e);
+                    self.audit("revoke_session", Outcome::Failure, &request.user, &source, &trace, "user store unavailable");
+                    return Err(Status::new(Code::Unavailable, format!("the user store is unavailable: {}", e)));
+                }
+                self.audit("revoke_session", Outcome::Success, &request.user, &source, &trace, format!("session of {}", describe_client(session.client.as_ref())));
+                println!(
+                    "🚪 Revoked Session of {} username: {:?} trace_id: {}",
+                    describe_client(session.client.as_ref()),
+                    telemetry::user(&request.user),
+                    trace.trace_id_hex()
+                );
+                Ok(trace.response(RevokeSessionResponse {}))
+            }
+            None => Err(Status::new(
+                Code::NotFound,
+                format!("{} has no session {}", request.user, request.revoke_session_id),
+            )),
+        }
+    }
+
+    async fn elevate_session(
+        &self,
+        request: Request<ElevateSessionRequest>,
+    ) -> Result<Response<ElevateSessionResponse>, Status> {
+        let trace = trace::current(&request);
+        let source = request.remote_addr().map(|addr| addr.to_string()).unwrap_or_default();
+        let mut request = request.into_inner();
+        request.user = self.canonical(&request.user)?;
+        if telemetry::sampled(&trace) {
+            println!("Processing Session Elevation username: {:?} trace_id: {}", telemetry::user(&request.user), trace.trace_id_hex());
+        }
+
+        let Some(ttl) = self.elevation_ttl else {
+            return Err(Status::new(
+                Code::Unimplemented,
+                "this server doesn't issue elevated sessions (--elevation-ttl 0)",
+            ));
+        };
+
+        // check the session and who the challenge was for before any modpow
+        let has_session = self
+            .user_info
+            .locked()
+            .get(&request.user)
+            .is_some_and(|user_info| user_info.has_session(&request.session_id));
+        if !has_session {
+            return Err(Status::new(
+                Code::Unauthenticated,
+                format!("no valid session for {}, log in first", request.user),
+            ));
+        }
+        let token = self.open_challenge_token(&request.challenge_token, &request.auth_id)?;
+        let owner = match &token {
+            Some(token) => Some(token.user.clone()),
+            None => self.pending.owner(&request.auth_id),
+        };
+        if owner.is_some_and(|owner| owner != request.user) {
+            return Err(Status::new(
+                Code::PermissionDenied,
+                format!("AuthId: {} is not a challenge for {}", request.auth_id, request.user),
+            ));
+        }
+
+        let (user_name, verification) = match &token {
+            Some(token) => self.check_token_answer(token, &request.s, &trace).await?,
+            None => self.check_answer(&request.auth_id, &request.s, &trace).await?,
+        };
+        // a fresh proof elevates once: the challenge is used up as a login
+        // uses it up, and one a login already used is gone
+        if verification {
+            let used_up = match &token {
+                Some(token) => self.duplicates.use_up(&token.auth_id),
+                None => self.pending.remove(&request.auth_id),
+            };
+            if !used_up {
+                return Err(Status::new(Code::NotFound, format!("AuthId: {} has already been answered", request.auth_id)));
+            }
+        }
+
+        let user_info_hashmap = &mut self.user_info.locked();
+        let Some(user_info) = user_info_hashmap.get_mut(&user_name) else {
+            return Err(Status::new(Code::NotFound, format!("User: {} not found", user_name)));
+        };
+        if !verification {
+            println!("❌ Wrong Elevation Proof username: {:?} source: {} trace_id: {}", telemetry::user(&user_name), telemetry::source(&source), trace.trace_id_hex());
+            self.audit("elevate_session", Outcome::Failure, &user_name, &source, &trace, "wrong answer to the challenge");
+            user_info.record_failed_answer(source, self.time.now());
+            return Err(Status::new(
+                Code::PermissionDenied,
+                format!("AuthId: {} bad solution to the challenge", request.auth_id),
+            ));
+        }
+
+        // the challenge was for this session, not a login on a new device
+        user_info.pending_client = None;
+        user_info.pending_device = None;
+        let Some(session) = user_info.sessions.get_mut(&request.session_id) else {
+            return Err(Status::new(
+                Code::Unauthenticated,
+                format!("no valid session for {}, log in first", request.user),
+            ));
+        };
+        let until = self.time.now() + ttl;
+        session.elevated_until = Some(until);
+        self.keep_sessions(user_info);
 
-    let auth_impl = AuthImpl::default();
+        self.audit("elevate_session", Outcome::Success, &user_name, &source, &trace, format!("elevated for {}s", ttl.as_secs()));
+        println!("🔐 Elevated Session username: {:?} for {}s trace_id: {}", telemetry::user(&user_name), ttl.as_secs(), trace.trace_id_hex());
+        Ok(trace.response(ElevateSessionResponse { elevated_until_unix_millis: clock::unix_millis(until) }))
+    }
+
+    async fn introspect_session(
+        &self,
+        request: Request<IntrospectSessionRequest>,
+    ) -> Result<Response<IntrospectSessionResponse>, Status> {
+        let trace = trace::current(&request);
+        // it says who is logged in, so only resource servers holding the admin token may ask
+        let Some(admin_token) = &self.admin_token else {
+            return Err(Status::new(Code::FailedPrecondition, "IntrospectSession needs the server started with --admin-token-file"));
+        };
+        let token = admin_token.0.read().unwrap().check(request)?.into_inner().token;
+
+        // sessions are kept per user, so look through every user's; an unknown
+        // token is an inactive one, not an error
+        let now = self.time.now();
+        let user_info_hashmap = self.user_info.locked();
+        let found = user_info_hashmap
+            .values()
+            .find_map(|user_info| user_info.sessions.get(&token).map(|session| (&user_info.user_name, session)));
+        let Some((user_name, session)) = found else {
+            println!("Introspected Inactive Session trace_id: {}", trace.trace_id_hex());
+            return Ok(trace.response(IntrospectSessionResponse::default()));
+        };
+
+        let elevated_until = session.elevated_until.filter(|until| *until > now);
+        let tier = match elevated_until {
+            Some(_) => SessionTier::Elevated,
+            None => SessionTier::Standard,
+        };
+        println!("Introspected Session username: {:?} tier: {:?} trace_id: {}", telemetry::user(user_name), tier, trace.trace_id_hex());
+        Ok(trace.response(IntrospectSessionResponse {
+            active: true,
+            username: user_name.clone(),
+            issued_at_unix_millis: clock::unix_millis(session.created_at),
+            // --session-retention ends sessions that old, otherwise they last until revoked or evicted
+            expires_at_unix_millis: self.session_retention.map_or(0, |keep| clock::unix_millis(session.created_at + keep)),
+            tier: tier as i32,
+            elevated_until_unix_millis: elevated_until.map(clock::unix_millis).unwrap_or_default(),
+        }))
+    }
+
+    async fn get_parameters(
+        &self,
+        request: Request<ParametersRequest>,
+    ) -> Result<Response<ParametersResponse>, Status> {
+        let trace = trace::current(&request);
+        match &self.parameters {
+            Some(parameters) => Ok(trace.response(ParametersResponse {
+                p: parameters.p.clone(),
+                q: parameters.q.clone(),
+                alpha: parameters.alpha.clone(),
+                beta: parameters.beta.clone(),
+                public_key: parameters.public_key.clone(),
+                signature: parameters.signature.clone(),
+            })),
+            None => Err(Status::new(
+                Code::Unimplemented,
+                "this server has no parameter signing key (--params-key)",
+            )),
+        }
+    }
+
+    async fn verify_email(
+        &self,
+        request: Request<VerifyEmailRequest>,
+    ) -> Result<Response<VerifyEmailResponse>, Status> {
+        let trace = trace::current(&request);
+        let source = request.remote_addr().map(|addr| addr.to_string()).unwrap_or_default();
+        let mut request = request.into_inner();
+        if self.user_ids != UserIds::Email {
+            return Err(Status::new(
+                Code::FailedPrecondition,
+                "this server doesn't take email addresses as user ids (--user-ids)",
+            ));
+        }
+        request.user = self.canonical(&request.user)?;
+        if telemetry::sampled(&trace) {
+            println!("Processing Email Verification username: {:?} trace_id: {}", telemetry::user(&request.user), trace.trace_id_hex());
+        }
+        if self.read_replica {
+            let response = self.primary("email verifications")?.verify_email(trace.request(request)).await?.into_inner();
+            self.follow_user_store();
+            return Ok(trace.response(response));
+        }
+
+        let user_info_hashmap = &mut self.user_info.locked();
+        let Some(user_info) = user_info_hashmap.get_mut(&request.user) else {
+            return Err(Status::new(
+                Code::NotFound,
+                format!("User: {} not found in database", request.user),
+            ));
+        };
+        if !user_info.unverified {
+            return Ok(trace.response(VerifyEmailResponse { verified: true }));
+        }
+
+        let now = Instant::now();
+        if request.code.is_empty() {
+            if user_info.verification.as_ref().is_some_and(|code| !code.can_resend(now)) {
+                return Err(Status::new(Code::ResourceExhausted, "a code was sent less than a minute ago"));
+            }
+            self.send_verification_code(user_info, now);
+            println!("📧 Sent a New Verification Code username: {:?} trace_id: {}", telemetry::user(&request.user), trace.trace_id_hex());
+            return Ok(trace.response(VerifyEmailResponse { verified: false }));
+        }
+        if !user_info.verification.as_mut().is_some_and(|code| code.matches(&request.code, now)) {
+            self.audit("verify_email", Outcome::Failure, &request.user, &source, &trace, "wrong or expired code");
+            return Err(Status::new(
+                Code::PermissionDenied,
+                "wrong or expired verification code, send an empty one to get a new code",
+            ));
+        }
+
+        // on disk first, like SetUserEnabled, so a restart doesn't ask again
+        if let Some(store) = self.store_of(&request.user) {
+            if let Err(e) = store.set_verified(&request.user) {
+                println!("⚠️  User store {} failed, pausing registrations: {}", store.path().display(), e);
+                self.audit("verify_email", Outcome::Failure, &request.user, &source, &trace, "user store unavailable");
+                return Err(Status::new(
+                    Code::Unavailable,
+                    format!("the user store is unavailable: {}", e),
+                ));
+            }
+        }
+        user_info.unverified = false;
+        user_info.verification = None;
+
+        self.audit("verify_email", Outcome::Success, &request.user, &source, &trace, "");
+        println!("📧 Verified Email username: {:?} trace_id: {}", telemetry::user(&request.user), trace.trace_id_hex());
+        Ok(trace.response(VerifyEmailResponse { verified: true }))
+    }
+    async fn start_device_login(
+        &self,
+        request: Request<StartDeviceLoginRequest>,
+    ) -> Result<Response<StartDeviceLoginResponse>, Status> {
+        let trace = trace::current(&request);
+        let source = request.remote_addr().map(|addr| addr.to_string()).unwrap_or_default();
+        let client = request.into_inner().client;
+        let device_logins = self.device_logins()?;
+        let device = describe_client(client.as_ref());
+        if telemetry::sampled(&trace) {
+            println!("Processing Device Login device: {} source: {} trace_id: {}", telemetry::device(&device), telemetry::source(&source), trace.trace_id_hex());
+        }
+
+        // c is fixed now, before any phone has picked its commitments
+        let (_, _, _, q) = ZKP::get_constants();
+        let c = challenge::random(self.challenge_bits.unwrap_or(q.bits()), &q);
+        let nonce = challenge::new_nonce();
+        let commitment = challenge::commit(&c, &nonce);
+        let now = self.time.now();
+        device_logins.expire(now);
+        let login_id = self.auth_ids.next(now).to_string();
+        let poll_token = ZKP::generate_random_string(32);
+        device_logins.start(login_id.clone(), poll_token.clone(), client, source, (c, nonce), now);
+
+        println!("📱 Showing Device Login login_id: {:?} device: {} trace_id: {}", login_id, telemetry::device(&device), trace.trace_id_hex());
+        Ok(trace.response(StartDeviceLoginResponse {
+            login_id,
+            commitment,
+            poll_token,
+            expires_unix_millis: clock::unix_millis(now + device_logins.ttl()),
+        }))
+    }
+
+    async fn approve_device_login(
+        &self,
+        request: Request<ApproveDeviceLoginRequest>,
+    ) -> Result<Response<ApproveDeviceLoginResponse>, Status> {
+        let trace = trace::current(&request);
+        let source = request.remote_addr().map(|addr| addr.to_string()).unwrap_or_default();
+        let mut request = request.into_inner();
+        request.user = self.canonical(&request.user)?;
+        let device_logins = self.device_logins()?;
+        if telemetry::sampled(&trace) {
+            println!("Processing Device Login Scan username: {:?} login_id: {:?} trace_id: {}", telemetry::user(&request.user), request.login_id, trace.trace_id_hex());
+        }
+
+        self.check_phone(&request.user, &request.session_id, &source, &trace)?;
+        let commitment = types::Commitment::from_bytes_be(&request.r1, &request.r2);
+        let scan = device_logins.scan(&request.login_id, &request.user, &request.session_id, commitment, self.time.now())?;
+
+        println!("📱 Scanned Device Login username: {:?} device: {} trace_id: {}", telemetry::user(&request.user), telemetry::device(&describe_client(scan.client.as_ref())), trace.trace_id_hex());
+        Ok(trace.response(ApproveDeviceLoginResponse {
+            c: scan.c.to_bytes_be(),
+            nonce: scan.nonce,
+            client: scan.client,
+            source: scan.source,
+        }))
+    }
+
+    async fn finish_device_login(
+        &self,
+        request: Request<FinishDeviceLoginRequest>,
+    ) -> Result<Response<FinishDeviceLoginResponse>, Status> {
+        let trace = trace::current(&request);
+        let source = request.remote_addr().map(|addr| addr.to_string()).unwrap_or_default();
+        let mut request = request.into_inner();
+        request.user = self.canonical(&request.user)?;
+        let device_logins = self.device_logins()?;
+        let (user_name, login_id) = (request.user, request.login_id);
+        if telemetry::sampled(&trace) {
+            println!("Processing Device Login Answer username: {:?} login_id: {:?} trace_id: {}", telemetry::user(&user_name), login_id, trace.trace_id_hex());
+        }
+
+        self.check_phone(&user_name, &request.session_id, &source, &trace)?;
+        let now = self.time.now();
+        if request.s.is_empty() {
+            device_logins.decline(&login_id, &user_name, &request.session_id, now)?;
+            println!("🚫 Declined Device Login username: {:?} login_id: {:?} trace_id: {}", telemetry::user(&user_name), login_id, trace.trace_id_hex());
+            self.audit("device_login", Outcome::Denied, &user_name, &source, &trace, "declined on the phone");
+            return Ok(trace.response(FinishDeviceLoginResponse { approved: false }));
+        }
+        let (commitment, scan) = device_logins.answer(&login_id, &user_name, &request.session_id, now)?;
+
+        // copy what we need and let go of the lock before the modpows
+        let Some((group, zkp, key)) = self.user_info.locked().get(&user_name).map(|user_info| {
+            (user_info.group, group_of(user_info), user_info.public_key.clone())
+        }) else {
+            device_logins.finish(&login_id, None);
+            return Err(Status::new(Code::NotFound, format!("User: {} not found", user_name)));
+        };
+        let (c, s) = (types::Challenge(scan.c), types::Response::from_bytes_be(&request.s));
+        let verification = self.crypto.run(move || group.verify_answer(&zkp, &key, &commitment, &c, &s)).await;
+        let verification = verification.inspect_err(|_| device_logins.finish(&login_id, None))?;
+
+        let user_info_hashmap = &mut self.user_info.locked();
+        let Some(user_info) = user_info_hashmap.get_mut(&user_name) else {
+            device_logins.finish(&login_id, None);
+            return Err(Status::new(Code::NotFound, format!("User: {} not found", user_name)));
+        };
+        if !verification {
+            device_logins.finish(&login_id, None);
+            println!("❌ Wrong Device Login Answer username: {:?} source: {} trace_id: {}", telemetry::user(&user_name), telemetry::source(&source), trace.trace_id_hex());
+            self.audit("device_login", Outcome::Failure, &user_name, &source, &trace, "wrong answer to the challenge");
+            user_info.record_failed_answer(source, now);
+            return Err(Status::new(
+                Code::PermissionDenied,
+                format!("LoginId: {} bad solution to the challenge", login_id),
+            ));
+        }
+
+        // the session is the device's: its metadata, its address, its login
+        let login = login_context(&user_name, user_info, scan.client.as_ref(), &scan.source, now);
+        let allowed = match self.check_enabled(user_info, &scan.source, &trace) {
+            Ok(()) => self.check_policy("device_login", &login, &trace),
+            disabled => disabled,
+        };
+        if let Err(status) = allowed {
+            device_logins.finish(&login_id, None);
+            return Err(status);
+        }
+        let session_id = ZKP::generate_random_string(SESSION_ID_LEN);
+        let device = describe_client(scan.client.as_ref());
+        self.audit("device_login", Outcome::Success, &user_name, &scan.source, &trace, format!("new session on {}, approved from {}", device, source));
+        user_info.add_session(session_id.clone(), Session { created_at: now, client: scan.client.clone(), device: None, elevated_until: None });
+        self.keep_sessions(user_info);
+        user_info.last_login = Some(LoginRecord { unix_millis: clock::unix_millis(now), source: scan.source, client: scan.client });
+        user_info.failed_logins = 0;
+        device_logins.finish(&login_id, Some(session_id));
+
+        println!("✅ Approved Device Login username: {:?} device: {} trace_id: {}", telemetry::user(&user_name), telemetry::device(&device), trace.trace_id_hex());
+        Ok(trace.response(FinishDeviceLoginResponse { approved: true }))
+    }
 
-    Server::builder()
-        .add_service(AuthServer::new(auth_impl))
-        .serve(addr.parse().expect("could not convert address"))
-        .await
-        .unwrap();
-}
\ No newline at end of file
+    async fn poll_device_login(
+        &self,
+        request: Request<PollDeviceLoginRequest>,
+    ) -> Result<Response<PollDeviceLoginResponse>, Status> {
+        let trace = trace::current(&request);
+        let request = request.into_inner();
+        let poll = self.device_logins()?.poll(&request.login_id, &request.poll_token, self.time.now())?;
+        let response = match poll {
+            Poll::Waiting => PollDeviceLoginResponse { state: DeviceLoginState::Waiting as i32, ..Default::default() },
+            Poll::Scanned => PollDeviceLoginResponse { state: DeviceLoginState::Scanned as i32, ..Default::default() },
+            Poll::Denied => PollDeviceLoginResponse { state: DeviceLoginState::Denied as i32, ..Default::default() },
+            Poll::Approved { user, session_id } => {
+                println!("📱 Handed Over Device Session username: {:?} login_id: {:?} trace_id: {}", telemetry::user(&user), request.login_id, trace.trace_id_hex());
+                PollDeviceLoginResponse { state: DeviceLoginState::Approved as i32, session_id, subject: user }
+            }
+        };
+        Ok(trace.response(response))
+    }
+    async fn enroll_device(
+        &self,
+        request: Request<EnrollDeviceRequest>,
+    ) -> Result<Response<EnrollDeviceResponse>, Status> {
+        let trace = trace::current(&request);
+        let source = request.remote_addr().map(|addr| addr.to_string()).unwrap_or_default();
+        let mut request = request.into_inner();
+        request.user = self.canonical(&request.user)?;
+        if telemetry::sampled(&trace) {
+            println!("Processing Device Enrollment username: {:?} name: {:?} trace_id: {}", telemetry::user(&request.user), telemetry::device(&request.name), trace.trace_id_hex());
+        }
+        self.writable("devices are enrolled")?;
+
+        if self.elevation_ttl.is_none() {
+            return Err(Status::new(
+                Code::Unimplemented,
+                "enrolling a device needs an elevated session, and this server doesn't issue them (--elevation-ttl 0)",
+            ));
+        }
+
+        let user_info_hashmap = &mut self.user_info.locked();
+        let Some(user_info) = user_info_hashmap.get_mut(&request.user).filter(|u| u.has_session(&request.session_id)) else {
+            return Err(Status::new(
+                Code::Unauthenticated,
+                format!("no valid session for {}, log in first", request.user),
+            ));
+        };
+        self.check_enabled(user_info, &source, &trace)?;
+
+        // a new key is as good as the password, so a session alone isn't enough
+        let now = self.time.now();
+        let elevated = user_info.sessions[&request.session_id].elevated_until.is_some_and(|until| until > now);
+        if !elevated {
+            return Err(Status::new(
+                Code::PermissionDenied,
+                "enrolling a device needs an elevated session, prove the password again with ElevateSession",
+            ));
+        }
+
+        let group = user_info.group;
+        if !group.accepts_public_key(&request.y1, &request.y2) {
+            return Err(Status::new(Code::InvalidArgument, format!("(y1, y2) are not valid {} points", group)));
+        }
+        let key = types::PublicKey::from_bytes_be(&request.y1, &request.y2);
+        let device_id = device_key::device_id(group, &key);
+        if user_info.devices.contains_key(&device_id) || key == user_info.public_key {
+            return Err(Status::new(Code::AlreadyExists, "this key is enrolled already"));
+        }
+        if user_info.devices.len() >= MAX_DEVICES_PER_USER {
+            return Err(Status::new(
+                Code::ResourceExhausted,
+                format!("{} devices are enrolled already, revoke one first", MAX_DEVICES_PER_USER),
+            ));
+        }
+
+        // on disk first, like SetUserEnabled, so a restart doesn't forget the device
+        if let Some(store) = self.store_of(&request.user) {
+            let stored = StoredDevice {
+                id: device_id.clone(),
+                name: request.name.clone(),
+                y1: hex::encode(&request.y1),
+                y2: hex::encode(&request.y2),
+                enrolled_at: clock::unix_millis(now),
+            };
+            if let Err(e) = store.add_device(&request.user, &stored) {
+                println!("⚠️  User store {} failed, pausing registrations: {}", store.path().display(), e);
+                self.audit("enroll_device", Outcome::Failure, &request.user, &source, &trace, "user store unavailable");
+                return Err(Status::new(
+                    Code::Unavailable,
+                    format!("the user store is unavailable: {}", e),
+                ));
+            }
+        }
+        let fingerprint = key.fingerprint_in(group);
+        user_info.devices.insert(device_id.clone(), Device { name: request.name.clone(), public_key: key, enrolled_at: now });
+
+        self.audit("enroll_device", Outcome::Success, &request.user, &source, &trace, format!("{:?}, key {}", request.name, fingerprint));
+        println!("📲 Enrolled Device username: {:?} name: {:?} key: {} trace_id: {}", telemetry::user(&request.user), telemetry::device(&request.name), fingerprint, trace.trace_id_hex());
+        Ok(trace.response(EnrollDeviceResponse { device_id }))
+    }
+
+    async fn revoke_device(
+        &self,
+        request: Request<RevokeDeviceRequest>,
+    ) -> Result<Response<RevokeDeviceResponse>, Status> {
+        let trace = trace::current(&request);
+        let source = request.remote_addr().map(|addr| addr.to_string()).unwrap_or_default();
+        let mut request = request.into_inner();
+        request.user = self.canonical(&request.user)?;
+        if telemetry::sampled(&trace) {
+            println!("Processing Device Revocation username: {:?} device_id: {:?} trace_id: {}", telemetry::user(&request.user), telemetry::device(&request.device_id), trace.trace_id_hex());
+        }
+        self.writable("devices are revoked")?;
+
+        let user_info_hashmap = &mut self.user_info.locked();
+        let Some(user_info) = user_info_hashmap.get_mut(&request.user).filter(|u| u.has_session(&request.session_id)) else {
+            return Err(Status::new(
+                Code::Unauthenticated,
+                format!("no valid session for {}, log in first", request.user),
+            ));
+        };
+        if !user_info.devices.contains_key(&request.device_id) {
+            return Err(Status::new(
+                Code::NotFound,
+                format!("Device: {} is not enrolled for {}", request.device_id, request.user),
+            ));
+        }
+
+        if let Some(store) = self.store_of(&request.user) {
+            if let Err(e) = store.revoke_device(&request.user, &request.device_id) {
+                println!("⚠️  User store {} failed, pausing registrations: {}", store.path().display(), e);
+                self.audit("revoke_device", Outcome::Failure, &request.user, &source, &trace, "user store unavailable");
+                return Err(Status::new(
+                    Code::Unavailable,
+                    format!("the user store is unavailable: {}", e),
+                ));
+            }
+        }
+        let device = user_info.devices.remove(&request.device_id).expect("checked above");
+        // whatever the device logged in ends with it, the password's sessions stay
+        let before = user_info.sessions.len();
+        user_info.sessions.retain(|_, session| session.device.as_ref() != Some(&request.device_id));
+        let sessions_ended = (before - user_info.sessions.len()) as u32;
+        self.keep_sessions(user_info);
+
+        self.audit("revoke_device", Outcome::Success, &request.user, &source, &trace, format!("{:?}, {} sessions ended", device.name, sessions_ended));
+        println!("📵 Revoked Device username: {:?} name: {:?} ({} sessions ended) trace_id: {}", telemetry::user(&request.user), telemetry::device(&device.name), sessions_ended, trace.trace_id_hex());
+        Ok(trace.response(RevokeDeviceResponse { sessions_ended }))
+    }
+
+    async fn list_devices(
+        &self,
+        request: Request<ListDevicesRequest>,
+    ) -> Result<Response<ListDevicesResponse>, Status> {
+        let trace = trace::current(&request);
+        let mut request = request.into_inner();
+        request.user = self.canonical(&request.user)?;
+        if telemetry::sampled(&trace) {
+            println!("Processing Device Listing username: {:?} trace_id: {}", telemetry::user(&request.user), trace.trace_id_hex());
+        }
+
+        let user_info_hashmap = self.user_info.locked();
+        let Some(user_info) = user_info_hashmap.get(&request.user).filter(|u| u.has_session(&request.session_id)) else {
+            return Err(Status::new(
+                Code::Unauthenticated,
+                format!("no valid session for {}, log in first", request.user),
+            ));
+        };
+
+        let current = user_info.sessions[&request.session_id].device.as_ref();
+        let mut devices: Vec<DeviceInfo> = user_info
+            .devices
+            .iter()
+            .map(|(device_id, device)| DeviceInfo {
+                device_id: device_id.clone(),
+                name: device.name.clone(),
+                enrolled_unix_millis: clock::unix_millis(device.enrolled_at),
+                current: current == Some(device_id),
+            })
+            .collect();
+        devices.sort_by(|a, b| (a.enrolled_unix_millis, &a.device_id).cmp(&(b.enrolled_unix_millis, &b.device_id)));
+        Ok(trace.response(ListDevicesResponse { devices }))
+    }
+
+    async fn verify_delegation(
+        &self,
+        request: Request<VerifyDelegationRequest>,
+    ) -> Result<Response<VerifyDelegationResponse>, Status> {
+        let trace = trace::current(&request);
+        let source = request.remote_addr().map(|addr| addr.to_string()).unwrap_or_default();
+        let request = request.into_inner();
+        let Some(max_ttl) = self.delegation_max_ttl else {
+            return Err(Status::new(
+                Code::Unimplemented,
+                "this server doesn't accept delegations (--delegation-max-ttl 0)",
+            ));
+        };
+        let delegation = Delegation::from_blob(&request.delegation).map_err(|e| Status::invalid_argument(e.to_string()))?;
+        let user_name = self.canonical(&delegation.user)?;
+        if telemetry::sampled(&trace) {
+            println!("Processing Delegation Check username: {:?} delegate: {:?} scope: {:?} trace_id: {}", telemetry::user(&user_name), telemetry::user(&request.delegate), request.scope, trace.trace_id_hex());
+        }
+
+        // a delegation that isn't valid is an answer, not an error
+        let invalid = |outcome: Outcome, reason: &str| {
+            println!("🚫 Invalid Delegation username: {:?} delegate: {:?} reason: {} trace_id: {}", telemetry::user(&user_name), telemetry::user(&request.delegate), reason, trace.trace_id_hex());
+            self.audit("verify_delegation", outcome, &user_name, &source, &trace, format!("{} for {:?}: {}", delegation.id(), telemetry::user(&request.delegate), reason));
+            trace.response(VerifyDelegationResponse { reason: reason.to_string(), ..Default::default() })
+        };
+        let window = delegation.proof.window;
+        if delegation.delegate != request.delegate {
+            return Ok(invalid(Outcome::Denied, "the delegation is for another service"));
+        }
+        if window.not_after.saturating_sub(window.not_before) > max_ttl.as_secs() {
+            return Ok(invalid(Outcome::Denied, "the delegation lasts longer than this server allows"));
+        }
+        if !window.contains(self.time.clock(self.max_clock_skew)) {
+            return Ok(invalid(Outcome::Denied, "the delegation has expired or isn't valid yet"));
+        }
+        if !request.scope.is_empty() && !delegation.allows(&request.scope) {
+            return Ok(invalid(Outcome::Denied, "the delegation doesn't grant this scope"));
+        }
+        if self.revocations.locked().is_revoked(&user_name, delegation.id()) {
+            return Ok(invalid(Outcome::Denied, "the delegation was revoked"));
+        }
+
+        // copy the key and let go of the lock before the modpows
+        let found = match self.user_info.locked().get(&user_name) {
+            None => Err("no such user"),
+            Some(user_info) if user_info.disabled => Err("the account is disabled"),
+            Some(user_info) if user_info.group != GroupId::ModP => Err("delegations are only minted in the modp group"),
+            Some(user_info) => {
+                Ok((group_of(user_info), user_info.public_key.clone()))
+            }
+        };
+        let (zkp, key) = match found {
+            Ok(found) => found,
+            Err(reason) => return Ok(invalid(Outcome::Denied, reason)),
+        };
+        let clock = self.time.clock(self.max_clock_skew);
+        let checked = delegation.clone();
+        if !self.crypto.run(move || checked.verify(&zkp, &key, clock)).await? {
+            return Ok(invalid(Outcome::Failure, "the delegation's proof is wrong"));
+        }
+
+        self.audit("verify_delegation", Outcome::Success, &user_name, &source, &trace, format!("{} for {:?}: {}", delegation.id(), telemetry::user(&request.delegate), request.scope));
+        println!("🤝 Valid Delegation username: {:?} delegate: {:?} scope: {:?} trace_id: {}", telemetry::user(&user_name), telemetry::user(&request.delegate), request.scope, trace.trace_id_hex());
+        Ok(trace.response(VerifyDelegationResponse {
+            valid: true,
+            user: user_name,
+            expires_unix: window.not_after,
+            delegation_id: delegation.id().to_string(),
+            scopes: delegation.scopes,
+            reason: String::new(),
+        }))
+    }
+
+    async fn get_federation_info(
+        &self,
+        request: Request<FederationInfoRequest>,
+    ) -> Result<Response<FederationInfoResponse>, Status> {
+        let trace = trace::current(&request);
+        let (Some(parameters), Some(key)) = (&self.parameters, &self.parameters_key) else {
+            return Err(Status::new(
+                Code::Unimplemented,
+                "this server has no parameter signing key (--params-key)",
+            ));
+        };
+        // signed on every call, so a key unsealed or rotated since startup is what peers get
+        let info = match self.id_tokens.locked().as_ref() {
+            Some(id_tokens) => {
+                let (issuer, id_token_key) = id_tokens.identity();
+                federation::sign_info(key, issuer, parameters, Some(&id_token_key))
+            }
+            None => {
+                return Err(Status::new(
+                    Code::FailedPrecondition,
+                    "this server has no issuer to be known by (--id-token-issuer)",
+                ))
+            }
+        };
+        Ok(trace.response(info))
+    }
+
+    async fn verify_federated_token(
+        &self,
+        request: Request<VerifyFederatedTokenRequest>,
+    ) -> Result<Response<VerifyFederatedTokenResponse>, Status> {
+        let trace = trace::current(&request);
+        let source = request.remote_addr().map(|addr| addr.to_string()).unwrap_or_default();
+        let request = request.into_inner();
+        let Some(federation) = &self.federation else {
+            return Err(Status::new(
+                Code::Unimplemented,
+                "this server has no federation peers (--federation-peer)",
+            ));
+        };
+        let issuer = id_token::issuer_of(&request.token).ok_or_else(|| Status::invalid_argument("not an ID token"))?;
+        if telemetry::sampled(&trace) {
+            println!("Processing Federated Token Check issuer: {:?} audience: {:?} trace_id: {}", issuer, request.audience, trace.trace_id_hex());
+        }
+
+        // a token that isn't valid is an answer, not an error
+        let invalid = |reason: &str| {
+            println!("🚫 Invalid Federated Token issuer: {:?} audience: {:?} reason: {} trace_id: {}", issuer, request.audience, reason, trace.trace_id_hex());
+            self.audit("verify_federated_token", Outcome::Denied, "", &source, &trace, format!("from {:?} for {:?}: {}", issuer, request.audience, reason));
+            trace.response(VerifyFederatedTokenResponse { reason: reason.to_string(), ..Default::default() })
+        };
+        let Some(peer) = federation.peer(&issuer) else {
+            return Ok(invalid("the issuer is not a federation peer, or hasn't been fetched yet"));
+        };
+        let Some(key) = peer.id_token_key else {
+            return Ok(invalid("the peer publishes no ID token key"));
+        };
+        let claims = match id_token::verify(&request.token, &key, &issuer, &request.audience, self.time.now()) {
+            Ok(claims) => claims,
+            Err(e) => return Ok(invalid(&e.to_string())),
+        };
+
+        self.audit("verify_federated_token", Outcome::Success, &claims.sub, &source, &trace, format!("from {:?} for {:?}", issuer, request.audience));
+        println!("🌐 Valid Federated Token issuer: {:?} username: {:?} audience: {:?} trace_id: {}", issuer, telemetry::user(&claims.sub), request.audience, trace.trace_id_hex());
+        Ok(trace.response(VerifyFederatedTokenResponse {
+            valid: true,
+            issuer,
+            subject: claims.sub,
+            session_id: claims.sid,
+            expires_unix: claims.exp,
+            reason: String::new(),
+        }))
+    }
+
+    async fn verify_federated_proof(
+        &self,
+        request: Request<VerifyFederatedProofRequest>,
+    ) -> Result<Response<VerifyFederatedProofResponse>, Status> {
+        let trace = trace::current(&request);
+        let source = request.remote_addr().map(|addr| addr.to_string()).unwrap_or_default();
+        let request = request.into_inner();
+        let Some(federation) = &self.federation else {
+            return Err(Status::new(
+                Code::Unimplemented,
+                "this server has no federation peers (--federation-peer)",
+            ));
+        };
+        let proof: NonInteractiveProof =
+            serde_json::from_str(&request.proof).map_err(|e| Status::invalid_argument(format!("not a proof: {}", e)))?;
+        if telemetry::sampled(&trace) {
+            println!("Processing Federated Proof Check issuer: {:?} username: {:?} proof_id: {} trace_id: {}", request.issuer, telemetry::user(&request.user), proof.id, trace.trace_id_hex());
+        }
+
+        let invalid = |outcome: Outcome, reason: &str| {
+            println!("🚫 Invalid Federated Proof issuer: {:?} username: {:?} reason: {} trace_id: {}", request.issuer, telemetry::user(&request.user), reason, trace.trace_id_hex());
+            self.audit("verify_federated_proof", outcome, &request.user, &source, &trace, format!("{} from {:?}: {}", proof.id, request.issuer, reason));
+            trace.response(VerifyFederatedProofResponse { reason: reason.to_string(), ..Default::default() })
+        };
+        let Some(peer) = federation.peer(&request.issuer) else {
+            return Ok(invalid(Outcome::Denied, "the issuer is not a federation peer, or hasn't been fetched yet"));
+        };
+        if peer.revocations.is_revoked(&request.user, &proof.id) {
+            return Ok(invalid(Outcome::Denied, "the peer revoked the proof"));
+        }
+        let (y1, y2) = (BigUint::from_bytes_be(&request.y1), BigUint::from_bytes_be(&request.y2));
+        let context = federation::proof_context(&request.issuer, &request.user, &request.context);
+        let clock = self.time.clock(self.max_clock_skew);
+        let checked = proof.clone();
+        if !self.crypto.run(move || peer.zkp.verify_non_interactive(&y1, &y2, &checked, &context, clock)).await? {
+            return Ok(invalid(Outcome::Failure, "the proof is wrong, or outside its window"));
+        }
+
+        self.audit("verify_federated_proof", Outcome::Success, &request.user, &source, &trace, format!("{} from {:?}", proof.id, request.issuer));
+        println!("🌐 Valid Federated Proof issuer: {:?} username: {:?} proof_id: {} trace_id: {}", request.issuer, telemetry::user(&request.user), proof.id, trace.trace_id_hex());
+        Ok(trace.response(VerifyFederatedProofResponse { valid: true, proof_id: proof.id, reason: String::new() }))
+    }
+
+    async fn verify_proof(
+        &self,
+        request: Request<VerifyProofRequest>,
+    ) -> Result<Response<VerifyProofResponse>, Status> {
+        let trace = trace::current(&request);
+        let source = request.remote_addr().map(|addr| addr.to_string()).unwrap_or_default();
+        let request = request.into_inner();
+        let proof: NonInteractiveProof =
+            serde_json::from_str(&request.proof).map_err(|e| Status::invalid_argument(format!("not a proof: {}", e)))?;
+        let Some(hash) = HashId::from_name(&request.hash) else {
+            return Err(Status::new(Code::InvalidArgument, format!("hash {:?} is not supported by this server", request.hash)));
+        };
+        if telemetry::sampled(&trace) {
+            println!("Processing Proof Check proof_id: {} generator_label: {:?} trace_id: {}", proof.id, request.generator_label, trace.trace_id_hex());
+        }
+
+        // nobody's account is involved, so the audit trail has no user; and
+        // without one there is no revocation list to look in either
+        let invalid = |outcome: Outcome, reason: &str| {
+            println!("🚫 Invalid Proof proof_id: {} reason: {} trace_id: {}", proof.id, reason, trace.trace_id_hex());
+            self.audit("verify_proof", outcome, "", &source, &trace, format!("{}: {}", proof.id, reason));
+            trace.response(VerifyProofResponse { reason: reason.to_string(), ..Default::default() })
+        };
+        let clock = self.time.clock(self.max_clock_skew);
+        if !proof.window.contains(clock) {
+            return Ok(invalid(Outcome::Denied, "the proof has expired or isn't valid yet"));
+        }
+        let (y1, y2) = (BigUint::from_bytes_be(&request.y1), BigUint::from_bytes_be(&request.y2));
+        let (checked, context, label) = (proof.clone(), request.context, request.generator_label);
+        let valid = self
+            .crypto
+            .run(move || {
+                let zkp = ZKP1024::rfc5114().into_inner();
+                let zkp = match label.is_empty() {
+                    true => zkp,
+                    false => zkp.for_user_with(hash, &label),
+                };
+                zkp.verify_non_interactive(&y1, &y2, &checked, &context, clock)
+            })
+            .await?;
+        if !valid {
+            return Ok(invalid(Outcome::Failure, "the proof is wrong"));
+        }
+
+        self.audit("verify_proof", Outcome::Success, "", &source, &trace, proof.id.clone());
+        println!("🔎 Valid Proof proof_id: {} trace_id: {}", proof.id, trace.trace_id_hex());
+        Ok(trace.response(VerifyProofResponse {
+            valid: true,
+            expires_unix: proof.window.not_after,
+            proof_id: proof.id,
+            reason: String::new(),
+        }))
+    }
+}
+
+#[tonic::async_trait]
+impl Admin for AuthImpl {
+    async fn set_user_enabled(
+        &self,
+        request: Request<SetUserEnabledRequest>,
+    ) -> Result<Response<SetUserEnabledResponse>, Status> {
+        let trace = trace::current(&request);
+        let source = request.remote_addr().map(|addr| addr.to_string()).unwrap_or_default();
+        let mut request = request.into_inner();
+        request.user = self.canonical(&request.user)?;
+        let action = if request.enabled { "enable_user" } else { "disable_user" };
+        if telemetry::sampled(&trace) {
+            println!("Processing Account Change {} username: {:?} trace_id: {}", action, telemetry::user(&request.user), trace.trace_id_hex());
+        }
+        self.writable("accounts are disabled and enabled")?;
+
+        let user_info_hashmap = &mut self.user_info.locked();
+        let Some(user_info) = user_info_hashmap.get_mut(&request.user) else {
+            return Err(Status::new(
+                Code::NotFound,
+                format!("User: {} not found in database", request.user),
+            ));
+        };
+        if user_info.disabled != request.enabled {
+            // already that way
+            return Ok(trace.response(SetUserEnabledResponse::default()));
+        }
+
+        // on disk first, like registrations, so a restart doesn't undo it
+        if let Some(store) = self.store_of(&request.user) {
+            if let Err(e) = store.set_enabled(&request.user, request.enabled) {
+                println!("⚠️  User store {} failed, pausing registrations: {}", store.path().display(), e);
+                self.audit(action, Outcome::Failure, &request.user, &source, &trace, "user store unavailable");
+                return Err(Status::new(
+                    Code::Unavailable,
+                    format!("the user store is unavailable: {}", e),
+                ));
+            }
+        }
+
+        user_info.disabled = !request.enabled;
+        let revoked_sessions = match request.enabled {
+            true => 0,
+            false => {
+                user_info.pending_client = None;
+                let ended = user_info.sessions.drain().count() as u32;
+                self.keep_sessions(user_info);
+                ended
+            }
+        };
+
+        self.audit(action, Outcome::Success, &request.user, &source, &trace, request.reason);
+        println!("🛡️  {} username: {:?} ({} sessions ended) trace_id: {}", action, telemetry::user(&request.user), revoked_sessions, trace.trace_id_hex());
+        Ok(trace.response(SetUserEnabledResponse { revoked_sessions }))
+    }
+
+    async fn bulk_register(
+        &self,
+        request: Request<tonic::Streaming<RegisterRequest>>,
+    ) -> Result<Response<BulkRegisterResponse>, Status> {
+        let trace = trace::current(&request);
+        let source = request.remote_addr().map(|addr| addr.to_string()).unwrap_or_default();
+        let mut registrations = request.into_inner();
+        if telemetry::sampled(&trace) {
+            println!("Processing Bulk Registration trace_id: {}", trace.trace_id_hex());
+        }
+        self.writable("users are imported")?;
+
+        let mut response = BulkRegisterResponse::default();
+        while let Some(registration) = registrations.message().await? {
+            let user_name = self.user_ids.canonical(&registration.user).unwrap_or_else(|_| registration.user.clone());
+            let key = types::PublicKey::from_bytes_be(&registration.y1, &registration.y2);
+            let registered = match registration.validate() {
+                Ok(()) => self.register_user(registration, &source, &trace, true).await,
+                Err(violation) => Err(violation.into()),
+            };
+            let Err(status) = registered else {
+                response.registered += 1;
+                continue;
+            };
+            // an import that is run again after it was interrupted
+            let unchanged = status.code() == Code::AlreadyExists
+                && self.user_info.locked().get(&user_name).is_some_and(|user_info| user_info.public_key == key);
+            if unchanged {
+                response.unchanged += 1;
+                continue;
+            }
+            response.failed += 1;
+            if response.failures.len() < MAX_BULK_FAILURES {
+                response.failures.push(BulkRegisterFailure {
+                    user: user_name,
+                    code: status.code() as i32,
+                    message: status.message().to_string(),
+                });
+            }
+        }
+
+        println!(
+            "✅ Bulk Registration: {} registered, {} unchanged, {} failed trace_id: {}",
+            response.registered, response.unchanged, response.failed, trace.trace_id_hex()
+        );
+        Ok(trace.response(response))
+    }
+
+    async fn unseal_id_token_key(
+        &self,
+        request: Request<UnsealIdTokenKeyRequest>,
+    ) -> Result<Response<UnsealIdTokenKeyResponse>, Status> {
+        let trace = trace::current(&request);
+        let source = request.remote_addr().map(|addr| addr.to_string()).unwrap_or_default();
+        let share: Share = request.into_inner().share.parse().map_err(|e: ShamirError| Status::new(Code::InvalidArgument, e.to_string()))?;
+        if telemetry::sampled(&trace) {
+            println!("Processing Unseal share: {:?} trace_id: {}", share, trace.trace_id_hex());
+        }
+
+        let mut id_tokens = self.id_tokens.locked();
+        let Some(id_tokens) = id_tokens.as_mut() else {
+            return Err(Status::new(
+                Code::FailedPrecondition,
+                "this server doesn't issue ID tokens (--id-token-issuer)",
+            ));
+        };
+        if let Err(status) = id_tokens.unseal(share) {
+            self.audit("unseal_id_token_key", Outcome::Failure, "", &source, &trace, status.message());
+            return Err(status);
+        }
+        let response = match id_tokens {
+            IdTokens::Sealed { unsealer, .. } => {
+                let (shares, threshold) = unsealer.progress();
+                UnsealIdTokenKeyResponse { unsealed: false, shares: shares as u32, threshold: threshold as u32 }
+            }
+            _ => UnsealIdTokenKeyResponse { unsealed: true, ..Default::default() },
+        };
+        let detail = match response.unsealed {
+            true => "unsealed".to_string(),
+            false => format!("{} of {} shares", response.shares, response.threshold),
+        };
+        self.audit("unseal_id_token_key", Outcome::Success, "", &source, &trace, detail.clone());
+        println!("🔓 ID token key: {} trace_id: {}", detail, trace.trace_id_hex());
+        Ok(trace.response(response))
+    }
+
+    async fn export_user_data(
+        &self,
+        request: Request<ExportUserDataRequest>,
+    ) -> Result<Response<ExportUserDataResponse>, Status> {
+        let trace = trace::current(&request);
+        let source = request.remote_addr().map(|addr| addr.to_string()).unwrap_or_default();
+        let user = self.canonical(&request.into_inner().user)?;
+        if telemetry::sampled(&trace) {
+            println!("Processing Data Export username: {:?} trace_id: {}", telemetry::user(&user), trace.trace_id_hex());
+        }
+
+        let Some(mut export) = self.user_info.locked().get(&user).map(export_user_info) else {
+            return Err(Status::new(Code::NotFound, format!("User: {} not found in database", user)));
+        };
+        let unavailable = |what: &str, e: std::io::Error| Status::new(Code::Unavailable, format!("could not read {}: {}", what, e));
+        if let Some(store) = self.store_of(&user) {
+            let stored = store.find(&user).map_err(|e| unavailable("the user store", e))?;
+            export["stored_registration"] = serde_json::json!(stored);
+        }
+        export["revoked_proofs"] = serde_json::json!(self.revocations.locked().of_user(&user).collect::<Vec<_>>());
+        let events = self.audit.export(&user).map_err(|e| unavailable("the audit log", e))?;
+        let detail = format!("{} audit events", events.len());
+        export["audit_events"] = events.into();
+
+        self.audit("export_user_data", Outcome::Success, &user, &source, &trace, detail);
+        println!("📦 Exported the data of username: {:?} trace_id: {}", telemetry::user(&user), trace.trace_id_hex());
+        let json = serde_json::to_string_pretty(&export).expect("JSON values always serialize");
+        Ok(trace.response(ExportUserDataResponse { json }))
+    }
+
+    async fn erase_user(&self, request: Request<EraseUserRequest>) -> Result<Response<EraseUserResponse>, Status> {
+        let trace = trace::current(&request);
+        let source = request.remote_addr().map(|addr| addr.to_string()).unwrap_or_default();
+        let mut request = request.into_inner();
+        request.user = self.canonical(&request.user)?;
+        // the name isn't logged: it is what's being erased
+        if telemetry::sampled(&trace) {
+            println!("Processing Erasure trace_id: {}", trace.trace_id_hex());
+        }
+        self.writable("users are erased")?;
+        let tombstone = self.auth_ids.next(self.time.now()).to_string();
+
+        // on disk first, like SetUserEnabled, so a restart doesn't bring the user back
+        if let Some(store) = self.store_of(&request.user) {
+            if let Err(e) = store.erase(&request.user) {
+                println!("⚠️  Could not erase from the user store {}: {}", store.path().display(), e);
+                self.audit("erase_user", Outcome::Failure, "", &source, &trace, format!("tombstone {}: user store unavailable", tombstone));
+                return Err(Status::new(Code::Unavailable, format!("the user store is unavailable: {}", e)));
+            }
+        }
+        let registered = self.user_info.locked().remove(&request.user);
+        let sessions_ended = registered.as_ref().map_or(0, |user_info| user_info.sessions.len() as u32);
+        self.pending.remove_user(&request.user);
+        self.registrations.forget(|registration| registration.user == request.user);
+        {
+            let mut revocations = self.revocations.locked();
+            if revocations.forget_user(&request.user) > 0 {
+                if let Some(path) = &self.revocation_path {
+                    revocations
+                        .save(path)
+                        .map_err(|e| Status::new(Code::Internal, format!("could not save the revocation list: {}", e)))?;
+                }
+            }
+        }
+        // what's left is the audit trail; a retry finds no registration but still erases it
+        let audit_events_erased = self.audit.erase(&request.user, &tombstone).map_err(|e| {
+            Status::new(Code::Unavailable, format!("the user is gone, but the audit log couldn't be rewritten (retry): {}", e))
+        })? as u32;
+        if registered.is_none() && audit_events_erased == 0 {
+            return Err(Status::new(Code::NotFound, "nothing is held about that user"));
+        }
+
+        self.audit("erase_user", Outcome::Success, "", &source, &trace, format!("tombstone {}: {}", tombstone, request.reason));
+        println!("🧽 Erased a user, tombstone {} ({} sessions, {} audit events) trace_id: {}", tombstone, sessions_ended, audit_events_erased, trace.trace_id_hex());
+        Ok(trace.response(EraseUserResponse { tombstone, sessions_ended, audit_events_erased }))
+    }
+}
+
+/// what UserInfo holds about a user, as ExportUserData shows it
+fn export_user_info(user_info: &UserInfo) -> serde_json::Value {
+    let (y1, y2) = user_info.public_key.to_bytes_be();
+    let client = |client: Option<&ClientMetadata>| {
+        client.map(|client| {
+            serde_json::json!({ "device_id": client.device_id, "app_version": client.app_version, "platform": client.platform })
+        })
+    };
+    let login = |login: Option<&LoginRecord>| {
+        login.map(|login| serde_json::json!({ "unix_millis": login.unix_millis, "source": login.source, "client": client(login.client.as_ref()) }))
+    };
+    let sessions: Vec<_> = user_info
+        .sessions
+        .values()
+        .map(|session| {
+            serde_json::json!({
+                "created_unix_millis": clock::unix_millis(session.created_at),
+                "client": client(session.client.as_ref()),
+                "device_id": session.device,
+                "elevated_until_unix_millis": session.elevated_until.map_or(0, clock::unix_millis),
+            })
+        })
+        .collect();
+    let devices: Vec<_> = user_info
+        .devices
+        .iter()
+        .map(|(device_id, device)| {
+            let (y1, y2) = device.public_key.to_bytes_be();
+            serde_json::json!({
+                "device_id": device_id,
+                "name": device.name,
+                "y1": hex::encode(y1),
+                "y2": hex::encode(y2),
+                "enrolled_unix_millis": clock::unix_millis(device.enrolled_at),
+            })
+        })
+        .collect();
+    serde_json::json!({
+        "user": user_info.user_name,
+        "registration": {
+            "fingerprint": user_info.public_key.fingerprint_in(user_info.group).to_hex(),
+            "y1": hex::encode(y1),
+            "y2": hex::encode(y2),
+            "kdf": user_info.kdf.as_ref().map(|kdf| serde_json::json!({
+                "salt": hex::encode(&kdf.salt),
+                "memory_kib": kdf.memory_kib,
+                "iterations": kdf.iterations,
+                "parallelism": kdf.parallelism,
+            })),
+            "beta": user_info.beta.as_ref().map(|beta| beta.to_str_radix(16)),
+            "group": user_info.group.to_string(),
+            "hash": user_info.hash.to_string(),
+            "disabled": user_info.disabled,
+            "email_unverified": user_info.unverified,
+        },
+        "sessions": sessions,
+        "devices": devices,
+        "login_history": {
+            "last_login": login(user_info.last_login.as_ref()),
+            "last_failed_login": login(user_info.last_failed_login.as_ref()),
+            "failed_logins": user_info.failed_logins,
+        },
+    })
+}
+
+/// Failures a BulkRegister answer lists; the rest are only counted
+const MAX_BULK_FAILURES: usize = 1000;
+
+impl AuthImpl {
+    /// add an event to the audit trail
+    fn audit(
+        &self,
+        action: &'static str,
+        outcome: Outcome,
+        user: &str,
+        source: &str,
+        trace: &trace::TraceContext,
+        detail: impl Into<String>,
+    ) {
+        if !self.audit.is_enabled() {
+            return;
+        }
+        self.audit.record(&AuditEvent {
+            time: self.time.now(),
+            action,
+            outcome,
+            user: user.to_string(),
+            source: source.to_string(),
+            trace_id: trace.trace_id_hex(),
+            detail: detail.into(),
+        });
+    }
+
+    /// register one user, for Register and BulkRegister; `by_admin` skips the
+    /// password policy check, which only the user's own client can do, and
+    /// takes the email addresses it imports as verified. Returns the subject
+    /// the user is kept under
+    async fn register_user(
+        &self,
+        mut request: RegisterRequest,
+        source: &str,
+        trace: &trace::TraceContext,
+        by_admin: bool,
+    ) -> Result<String, Status> {
+        let group = match GroupId::from_name(&request.group) {
+            Some(group) if group.is_supported() => group,
+            _ => {
+                return Err(Status::new(
+                    Code::InvalidArgument,
+                    format!("group {:?} is not supported by this server", request.group),
+                ))
+            }
+        };
+
+        // ids the server issues or derives; an admin importing users brings their subjects along
+        let issued = matches!(self.user_ids, UserIds::Subject | UserIds::Fingerprint) && request.user.is_empty();
+        let user_name = match self.user_ids {
+            UserIds::Fingerprint => {
+                let fingerprint = user_id::fingerprint(group, &request.y1, &request.y2);
+                if !issued && self.canonical(&request.user)? != fingerprint {
+                    return Err(Status::new(
+                        Code::InvalidArgument,
+                        "user must be empty or the fingerprint of (y1, y2) (--user-ids fingerprint)",
+                    ));
+                }
+                fingerprint
+            }
+            UserIds::Subject if issued => user_id::new_subject(),
+            UserIds::Subject if !by_admin => {
+                return Err(Status::new(
+                    Code::InvalidArgument,
+                    "user must be empty, this server issues the subject (--user-ids subject)",
+                ))
+            }
+            _ => self.canonical(&request.user)?,
+        };
+        if telemetry::sampled(trace) {
+            println!("Processing Registration username: {:?} trace_id: {}", telemetry::user(&user_name), trace.trace_id_hex());
+        }
+
+        let Some(hash) = HashId::from_name(&request.hash) else {
+            return Err(Status::new(Code::InvalidArgument, format!("hash {:?} is not supported by this server", request.hash)));
+        };
+        // H2G(user) needs the id before (y1, y2), which a fingerprint comes from
+        if (issued || self.user_ids == UserIds::Fingerprint) && !request.beta.is_empty() {
+            return Err(Status::new(
+                Code::InvalidArgument,
+                format!("per-user generators need the user id up front, which --user-ids {} only has after registration", self.user_ids),
+            ));
+        }
+        if group != GroupId::ModP && !request.beta.is_empty() {
+            return Err(Status::new(Code::InvalidArgument, format!("per-user generators are only for the modp group, not {}", group)));
+        }
+        if !group.accepts_public_key(&request.y1, &request.y2) {
+            return Err(Status::new(Code::InvalidArgument, format!("(y1, y2) are not valid {} points", group)));
+        }
+
+        // only H2G(user) is accepted: a generator picked by the client could
+        // have a known relation to alpha. Hashing to the group is a modpow, so
+        // it runs before we take the lock
+        let beta = match request.beta.is_empty() {
+            true => None,
+            false => {
+                let label = user_name.clone();
+                let expected = self.crypto.run(move || {
+                    ZKP1024::rfc5114().for_user_with(hash, &label).beta
+                })
+                .await?;
+                if expected.to_bytes_be() != request.beta {
+                    return Err(Status::new(
+                        Code::InvalidArgument,
+                        format!("per-user generator must be H2G(user) with {}", hash),
+                    ));
+                }
+                Some(expected)
+            }
+        };
+
+        let user_info_hashmap = &mut self.user_info.locked();
+
+        if !request.idempotency_key.is_empty() {
+            let mut replayed = String::new();
+            let replay = self.registrations.check_with(&request.idempotency_key, |done| {
+                replayed = done.user.clone();
+                // the subject the server issued isn't in the request the client repeats
+                let user = if issued { &done.user } else { &user_name };
+                *done == RegisterRequest { user: user.clone(), ..request.clone() }
+            });
+            match replay {
+                Replay::Completed => {
+                    println!("↩️  Repeated Registration username: {:?} trace_id: {}", telemetry::user(&replayed), trace.trace_id_hex());
+                    return Ok(replayed);
+                }
+                Replay::Mismatch => {
+                    return Err(Status::new(
+                        Code::InvalidArgument,
+                        "idempotency key was already used for a different registration",
+                    ));
+                }
+                Replay::New => {}
+            }
+        }
+
+        // users provisioned by an admin chose their passwords elsewhere
+        if !by_admin && request.password_policy_version < self.min_password_policy {
+            self.audit("register", Outcome::Denied, &user_name, source, trace, "password policy too old");
+            return Err(Status::new(
+                Code::FailedPrecondition,
+                format!(
+                    "the password must be checked against password policy version {} or later",
+                    self.min_password_policy
+                ),
+            ));
+        }
+
+        if user_info_hashmap.contains_key(&user_name) {
+            self.audit("register", Outcome::Failure, &user_name, source, trace, "already registered");
+            return Err(Status::new(
+                Code::AlreadyExists,
+                format!("User: {} is already registered", user_name),
+            ));
+        }
+
+        let kdf = request.kdf.clone().map(KdfParams::from);
+        if let Some(Err(e)) = kdf.as_ref().map(KdfParams::validate) {
+            return Err(Status::new(Code::InvalidArgument, e.to_string()));
+        }
+
+        // kept, journaled and remembered under the canonical id
+        request.user = user_name.clone();
+        let unverified = self.user_ids == UserIds::Email && !by_admin;
+
+        // on disk before it exists in memory, so an acknowledged user survives a restart
+        if let Some(store) = self.store_of(&user_name) {
+            if let Some(reason) = store.degraded() {
+                self.audit("register", Outcome::Failure, &user_name, source, trace, "user store unavailable");
+                return Err(Status::new(
+                    Code::Unavailable,
+                    format!("registrations are paused, the user store is unavailable: {}", reason),
+                ));
+            }
+            let stored = StoredUser {
+                registered_at: clock::unix_seconds(self.time.now()),
+                unverified,
+                ..StoredUser::from(&request)
+            };
+            if let Err(e) = store.append(&stored) {
+                println!("⚠️  User store {} failed, pausing registrations: {}", store.path().display(), e);
+                self.audit("register", Outcome::Failure, &user_name, source, trace, "user store unavailable");
+                return Err(Status::new(
+                    Code::Unavailable,
+                    format!("registrations are paused, the user store is unavailable: {}", e),
+                ));
+            }
+        }
+
+        let user_info = UserInfo {
+            user_name: user_name.clone(),
+            public_key: types::PublicKey::from_bytes_be(&request.y1, &request.y2),
+            kdf,
+            beta,
+            group,
+            hash,
+            unverified,
+            ..Default::default()
+        };
+        let fingerprint = user_info.public_key.fingerprint_in(group);
+        let user_info = user_info_hashmap.entry(user_name.clone()).or_insert(user_info);
+        if unverified {
+            self.send_verification_code(user_info, Instant::now());
+        }
+
+        if !request.idempotency_key.is_empty() {
+            let key = request.idempotency_key.clone();
+            self.registrations.record(&key, request);
+        }
+
+        self.audit("register", Outcome::Success, &user_name, source, trace, format!("key {}", fingerprint));
+        println!("✅ Successful Registration username: {:?} key: {} trace_id: {}", telemetry::user(&user_name), fingerprint, trace.trace_id_hex());
+        Ok(user_name)
+    }
+
+    /// `user` from a request, as accounts are kept under --user-ids
+    #[allow(clippy::result_large_err)] // same error type as the handlers
+    fn canonical(&self, user: &str) -> Result<String, Status> {
+        Ok(self.user_ids.canonical(user)?)
+    }
+
+    /// send the user a new code to verify their address with, replacing the last one
+    fn send_verification_code(&self, user_info: &mut UserInfo, now: Instant) {
+        let code = VerificationCode::new(now);
+        if let Some(sender) = &self.email_codes {
+            sender.send(&user_info.user_name, code.code());
+        }
+        user_info.verification = Some(code);
+    }
+
+    /// refuse a disabled account or an address that isn't verified yet,
+    /// recording the attempt
+    #[allow(clippy::result_large_err)] // same error type as the handlers
+    fn check_enabled(&self, user_info: &UserInfo, source: &str, trace: &trace::TraceContext) -> Result<(), Status> {
+        if user_info.unverified && !user_info.disabled {
+            println!("📧 Unverified Email username: {:?} source: {} trace_id: {}", telemetry::user(&user_info.user_name), telemetry::source(source), trace.trace_id_hex());
+            self.audit("login", Outcome::Denied, &user_info.user_name, source, trace, "email not verified");
+            return Err(Status::new(
+                Code::FailedPrecondition,
+                format!("User: {} has to verify the email address first (VerifyEmail)", user_info.user_name),
+            ));
+        }
+        if !user_info.disabled {
+            return Ok(());
+        }
+        println!("🚷 Disabled Account username: {:?} source: {} trace_id: {}", telemetry::user(&user_info.user_name), telemetry::source(source), trace.trace_id_hex());
+        self.audit("login", Outcome::Denied, &user_info.user_name, source, trace, "account disabled");
+        Err(Status::new(
+            Code::PermissionDenied,
+            format!("User: {} is disabled", user_info.user_name),
+        ))
+    }
+
+    #[allow(clippy::result_large_err)] // same error type as the handlers
+    fn device_logins(&self) -> Result<&DeviceLogins, Status> {
+        self.device_logins.as_ref().ok_or_else(|| {
+            Status::new(Code::Unimplemented, "this server doesn't do QR-code logins (--device-login-ttl 0)")
+        })
+    }
+
+    /// a phone approving a device login has to be logged in as the user
+    #[allow(clippy::result_large_err)] // same error type as the handlers
+    fn check_phone(&self, user_name: &str, session_id: &str, source: &str, trace: &trace::TraceContext) -> Result<(), Status> {
+        let user_info_hashmap = self.user_info.locked();
+        let Some(user_info) = user_info_hashmap.get(user_name) else {
+            return Err(Status::new(
+                Code::NotFound,
+                format!("User: {} not found in database", user_name),
+            ));
+        };
+        self.check_enabled(user_info, source, trace)?;
+        if !user_info.has_session(session_id) {
+            return Err(Status::new(
+                Code::Unauthenticated,
+                format!("no valid session for {}, log in first", user_name),
+            ));
+        }
+        Ok(())
+    }
+
+    /// the policy's say on a login whose proof checked out; logins it
+    /// refuses are audited as `action`
+    #[allow(clippy::result_large_err)] // same error type as the handlers
+    fn check_policy(&self, action: &'static str, login: &LoginContext, trace: &trace::TraceContext) -> Result<(), Status> {
+        match self.policy.decide(login) {
+            Decision::Allow => Ok(()),
+            Decision::Deny(reason) => {
+                println!("🚷 Login Denied by Policy username: {:?} risk: {} trace_id: {}", telemetry::user(&login.user), login.risk_score, trace.trace_id_hex());
+                self.audit(action, Outcome::Denied, &login.user, &login.source, trace, format!("policy: {}", reason));
+                Err(Status::new(Code::PermissionDenied, reason))
+            }
+            Decision::StepUp => {
+                println!("🪜 Login Needs Step-Up username: {:?} risk: {} trace_id: {}", telemetry::user(&login.user), login.risk_score, trace.trace_id_hex());
+                self.audit(action, Outcome::Denied, &login.user, &login.source, trace, "policy: step-up required");
+                Err(Status::new(
+                    Code::FailedPrecondition,
+                    "step-up required: this login needs verification beyond the password proof",
+                ))
+            }
+        }
+    }
+
+    /// the ID token a login asked for ("" while the key is sealed); a KMS
+    /// is called without holding the lock
+    async fn mint_id_token(&self, user: &str, wanted: &IdTokenRequest, session_id: &str, now: SystemTime) -> Result<String, Status> {
+        let minting = match &*self.id_tokens.locked() {
+            Some(IdTokens::Ready(issuer)) => Minting::Done(issuer.mint(user, &wanted.audience, &wanted.nonce, session_id, now)),
+            #[cfg(feature = "kms")]
+            Some(IdTokens::Kms { signer, issuer, lifetime, .. }) => {
+                let claims = IdTokenClaims::new(issuer, user, &wanted.audience, &wanted.nonce, session_id, now, *lifetime);
+                Minting::Remote(signer.clone(), UnsignedIdToken::new(&claims, signer.public_key()))
+            }
+            _ => Minting::Done(String::new()),
+        };
+        match minting {
+            Minting::Done(token) => Ok(token),
+            #[cfg(feature = "kms")]
+            Minting::Remote(signer, unsigned) => {
+                let signature = signer.sign(unsigned.signing_input()).await.map_err(|e| {
+                    println!("❌ ID token not signed username: {:?}: {}", telemetry::user(&user), e);
+                    Status::new(Code::Unavailable, format!("could not sign the ID token: {}", e))
+                })?;
+                unsigned.sign_with(&signature, signer.public_key()).map_err(|e| Status::new(Code::Internal, e.to_string()))
+            }
+        }
+    }
+
+    /// check s against the challenge behind auth_id, returning whose it was
+    /// and whether it verified
+    async fn check_answer(
+        &self,
+        auth_id: &str,
+        s_bytes: &[u8],
+        trace: &trace::TraceContext,
+    ) -> Result<(String, bool), Status> {
+        if self.is_expired(auth_id) {
+            return Err(Status::new(
+                Code::NotFound,
+                format!("AuthId: {} has expired", auth_id),
+            ));
+        }
+
+        if self.pending.is_committed(auth_id) {
+            return Err(Status::new(
+                Code::FailedPrecondition,
+                format!("AuthId: {} has not received (r1, r2) yet", auth_id),
+            ));
+        }
+
+        let Some(user_name) = self.pending.owner(auth_id) else {
+            return Err(Status::new(
+                Code::NotFound,
+                format!("AuthId: {} not found in database", auth_id),
+            ));
+        };
+
+        // copy what we need and let go of the lock before the modpows
+        let s = types::Response::from_bytes_be(s_bytes);
+        let (group, zkp, key, commitment, c) = {
+            let user_info_hashmap = &mut self.user_info.locked();
+            // a read replica may have dropped the user since the challenge was issued
+            let Some(user_info) = user_info_hashmap.get_mut(&user_name) else {
+                return Err(Status::new(Code::NotFound, format!("AuthId: {} not found in database", auth_id)));
+            };
+
+            self.check_duplicate(&user_name, auth_id, &user_info.commitment, s_bytes, trace)?;
+            user_info.s = s.clone();
+            let key = answering_key(user_info, user_info.pending_device.as_deref())?;
+
+            (
+                user_info.group,
+                group_of(user_info),
+                key,
+                user_info.commitment.clone(),
+                user_info.c.clone(),
+            )
+        };
+
+        let verification = self.crypto.run(move || group.verify_answer(&zkp, &key, &commitment, &c, &s)).await?;
+        Ok((user_name, verification))
+    }
+
+    /// check s against the challenge in a token, as check_answer does for a
+    /// pending one; nothing is kept, so nothing is used up
+    async fn check_token_answer(
+        &self,
+        token: &ChallengeToken,
+        s_bytes: &[u8],
+        trace: &trace::TraceContext,
+    ) -> Result<(String, bool), Status> {
+        let s = types::Response::from_bytes_be(s_bytes);
+        let commitment = types::Commitment::from_bytes_be(&token.r1, &token.r2);
+        let (group, zkp, key) = {
+            let user_info_hashmap = self.user_info.locked();
+            let Some(user_info) = user_info_hashmap.get(&token.user) else {
+                return Err(Status::new(
+                    Code::NotFound,
+                    format!("User: {} not found in database", token.user),
+                ));
+            };
+            self.check_duplicate(&token.user, &token.auth_id, &commitment, s_bytes, trace)?;
+            let device_id = (!token.device_id.is_empty()).then_some(token.device_id.as_str());
+            let key = answering_key(user_info, device_id)?;
+
+            (user_info.group, group_of(user_info), key)
+        };
+
+        let c = types::Challenge::from_bytes_be(&token.c);
+        let verification = self.crypto.run(move || group.verify_answer(&zkp, &key, &commitment, &c, &s)).await?;
+        Ok((token.user.clone(), verification))
+    }
+
+    /// cheap check before any modpow: a fresh k never repeats a triple, so
+    /// one seen for another auth_id is a replay
+    #[allow(clippy::result_large_err)] // same result type as the handlers it serves
+    fn check_duplicate(
+        &self,
+        user_name: &str,
+        auth_id: &str,
+        commitment: &types::Commitment,
+        s_bytes: &[u8],
+        trace: &trace::TraceContext,
+    ) -> Result<(), Status> {
+        let (r1, r2) = commitment.to_bytes_be();
+        if self.duplicates.check(auth_id, &r1, &r2, s_bytes) != Seen::Duplicate {
+            return Ok(());
+        }
+        println!("⚠️  Duplicate proof from another auth_id username: {:?} auth_id: {:?} trace_id: {}", telemetry::user(user_name), auth_id, trace.trace_id_hex());
+        match self.log_duplicate_proofs {
+            true => Ok(()),
+            false => Err(Status::new(
+                Code::PermissionDenied,
+                format!("AuthId: {} this proof was already submitted for another challenge", auth_id),
+            )),
+        }
+    }
+
+    /// the challenge token an answer came with, None when there is none; a
+    /// --stateless-challenges server needs one, for this auth_id
+    #[allow(clippy::result_large_err)] // same result type as the handlers it serves
+    fn open_challenge_token(&self, signed: &str, auth_id: &str) -> Result<Option<ChallengeToken>, Status> {
+        let key = match (&self.challenge_tokens, signed) {
+            (None, "") => return Ok(None),
+            (None, _) => {
+                return Err(Status::new(
+                    Code::FailedPrecondition,
+                    "this server keeps its challenges, answer without a challenge_token",
+                ))
+            }
+            (Some(_), "") => {
+                return Err(Status::new(
+                    Code::InvalidArgument,
+                    "this server is --stateless-challenges, send the challenge_token back with the answer",
+                ))
+            }
+            (Some(key), _) => key,
+        };
+        let token = match key.open(signed, self.time.now()) {
+            Ok(token) => token,
+            Err(TokenError::Expired) => {
+                return Err(Status::new(Code::NotFound, format!("AuthId: {} has expired", auth_id)));
+            }
+            Err(e) => return Err(Status::new(Code::InvalidArgument, e.to_string())),
+        };
+        if token.auth_id != auth_id {
+            return Err(Status::new(
+                Code::InvalidArgument,
+                format!("the challenge token is for AuthId: {}, not {}", token.auth_id, auth_id),
+            ));
+        }
+        Ok(Some(token))
+    }
+
+    /// a fresh, time-ordered auth_id; challenges older than the TTL are dropped first
+    fn new_auth_id(&self) -> String {
+        let now = self.time.now();
+        self.expire_challenges(now);
+        self.auth_ids.next(now).to_string()
+    }
+
+    /// drop the challenges issued longer than the TTL before `now`; how many
+    fn expire_challenges(&self, now: SystemTime) -> usize {
+        match self.challenge_ttl.and_then(|ttl| now.checked_sub(ttl)) {
+            Some(cutoff) => self.pending.expire_before(&Ulid::lower_bound(cutoff).to_string()),
+            None => 0,
+        }
+    }
+
+    /// on a read replica, refuse what would write the user store
+    #[allow(clippy::result_large_err)] // answered as is
+    fn writable(&self, what: &str) -> Result<(), Status> {
+        match self.read_replica {
+            true => Err(Status::new(Code::FailedPrecondition, format!("this is a read replica, {} on the primary", what))),
+            false => Ok(()),
+        }
+    }
+
+    /// where a read replica forwards `what`
+    #[allow(clippy::result_large_err)] // answered as is
+    fn primary(&self, what: &str) -> Result<AuthClient<Channel>, Status> {
+        self.primary.clone().ok_or_else(|| {
+            Status::new(Code::FailedPrecondition, format!("this is a read replica without a primary (--primary), send {} to the primary", what))
+        })
+    }
+
+    /// the user store journal `user` is kept in, None without a user store
+    fn store_of(&self, user: &str) -> Option<&UserStore> {
+        self.user_store.as_ref().map(|sharded| sharded.store_of(user))
+    }
+
+    /// keep `user_info`'s sessions in its user store, so a sled store brings
+    /// them back after a restart; a journal keeps none
+    fn save_sessions(&self, user_info: &UserInfo) -> std::io::Result<()> {
+        match self.store_of(&user_info.user_name) {
+            Some(store) => store.save_sessions(&user_info.user_name, &stored_sessions(user_info)),
+            None => Ok(()),
+        }
+    }
+
+    /// save_sessions for a change that holds in memory anyway: a failure
+    /// (which degrades the store) only means a restart forgets it
+    fn keep_sessions(&self, user_info: &UserInfo) {
+        if let Err(e) = self.save_sessions(user_info) {
+            println!("⚠️  User store failed, the sessions of username: {:?} are as before after a restart: {}", telemetry::user(&user_info.user_name), e);
+        }
+    }
+
+    /// give the users their sessions a sled store kept, read from `shard`;
+    /// none for disabled users or devices no longer enrolled. Sessions of a
+    /// user moved to another shard are kept there from now on. How many
+    fn load_sessions(&self, shard: usize, sessions: Vec<(String, Vec<StoredSession>)>) -> usize {
+        let mut user_info_hashmap = self.user_info.locked();
+        let mut restored = 0;
+        for (user_name, kept) in sessions {
+            let Some(user_info) = user_info_hashmap.get_mut(&user_name).filter(|user_info| !user_info.disabled) else {
+                continue;
+            };
+            for (session_id, session) in kept.into_iter().map(restored_session) {
+                if session.device.as_ref().is_none_or(|id| user_info.devices.contains_key(id)) {
+                    user_info.add_session(session_id, session);
+                    restored += 1;
+                }
+            }
+            if self.user_store.as_ref().is_some_and(|sharded| sharded.holder_of(&user_name) != shard) {
+                self.keep_sessions(user_info);
+            }
+        }
+        restored
+    }
+
+    /// on a read replica, take in what the primary wrote to the user store since the last look
+    fn follow_user_store(&self) {
+        let Some(sharded) = self.user_store.as_ref().filter(|sharded| sharded.is_read_only()) else {
+            return;
+        };
+        for (shard, store) in sharded.shards().iter().enumerate() {
+            match store.reload() {
+                Ok(None) => {}
+                Ok(Some(users)) => {
+                    sharded.locate(shard, &users);
+                    let (added, changed, removed) = self.follow_users(users, |user| sharded.holder_of(user) == shard);
+                    if added + changed + removed > 0 {
+                        println!("📖 Followed user store {}: {} added, {} changed, {} removed", store.path().display(), added, changed, removed);
+                    }
+                }
+                Err(e) => println!("❌ Could not read user store {}, keeping the users as they were: {}", store.path().display(), e),
+            }
+        }
+    }
+
+    /// make the users `held` in the journal a read replica follows match it:
+    /// new ones are added, changed ones updated (ending the sessions a disabled
+    /// account, a new key or a revoked device no longer allows) and erased ones
+    /// dropped; (added, changed, removed)
+    fn follow_users(&self, users: Vec<StoredUser>, held: impl Fn(&str) -> bool) -> (usize, usize, usize) {
+        let mut user_info_hashmap = self.user_info.locked();
+        let mut journaled = HashSet::new();
+        let (mut added, mut changed) = (0, 0);
+        for stored in users {
+            journaled.insert(stored.user.clone());
+            let fresh = UserInfo::from(stored);
+            let Some(user_info) = user_info_hashmap.get_mut(&fresh.user_name) else {
+                user_info_hashmap.insert(fresh.user_name.clone(), fresh);
+                added += 1;
+                continue;
+            };
+            let same_devices = user_info.devices.len() == fresh.devices.len() && user_info.devices.keys().all(|id| fresh.devices.contains_key(id));
+            let same = user_info.public_key == fresh.public_key
+                && user_info.kdf == fresh.kdf
+                && user_info.beta == fresh.beta
+                && user_info.group == fresh.group
+                && user_info.hash == fresh.hash
+                && user_info.disabled == fresh.disabled
+                && user_info.unverified == fresh.unverified
+                && same_devices;
+            if same {
+                continue;
+            }
+            if fresh.disabled || user_info.public_key != fresh.public_key {
+                user_info.sessions.clear();
+            }
+            user_info.sessions.retain(|_, session| session.device.as_ref().is_none_or(|id| fresh.devices.contains_key(id)));
+            user_info.public_key = fresh.public_key;
+            user_info.kdf = fresh.kdf;
+            user_info.beta = fresh.beta;
+            user_info.group = fresh.group;
+            user_info.hash = fresh.hash;
+            user_info.disabled = fresh.disabled;
+            user_info.unverified = fresh.unverified;
+            user_info.devices = fresh.devices;
+            changed += 1;
+        }
+        // honeypot decoys are never journaled
+        let erased: Vec<String> = user_info_hashmap
+            .keys()
+            .filter(|user_name| !journaled.contains(*user_name) && held(user_name) && !self.honeypots.contains(*user_name))
+            .cloned()
+            .collect();
+        for user_name in &erased {
+            user_info_hashmap.remove(user_name);
+            // their outstanding challenges go with them, as in EraseUser
+            self.pending.remove_user(user_name);
+        }
+        (added, changed, erased.len())
+    }
+
+    /// add users read from the store, keeping any already in memory; recent
+    /// idempotency keys come back too, so a client retrying a registration
+    /// whose answer was lost in a crash gets success, not AlreadyExists
+    fn load_users(&self, users: Vec<StoredUser>) -> usize {
+        let recent = clock::unix_seconds(self.time.now()).saturating_sub(self.registrations.ttl().as_secs());
+        let mut user_info_hashmap = self.user_info.locked();
+        let before = user_info_hashmap.len();
+        for stored in users {
+            if !stored.idempotency_key.is_empty() && stored.registered_at >= recent {
+                self.registrations.record(&stored.idempotency_key, RegisterRequest::from(&stored));
+            }
+            user_info_hashmap.entry(stored.user.clone()).or_insert_with(|| stored.into());
+        }
+        user_info_hashmap.len() - before
+    }
+
+    /// remember a new challenge, dropping the oldest ones to stay under
+    /// --max-pending-challenge-bytes
+    fn add_pending(&self, auth_id: String, user_name: String, committed: Option<(BigUint, Vec<u8>)>) {
+        let dropped = self.pending.insert(auth_id, user_name, committed);
+        if dropped > 0 {
+            println!("🧹 Dropped the {} oldest pending challenges to stay under {}", dropped, memory::format_bytes(self.pending.limit().unwrap_or_default()));
+        }
+    }
+
+    /// entries and approximate bytes of every in-memory store
+    fn memory_usage(&self) -> Vec<(&'static str, StoreUsage)> {
+        let mut users = StoreUsage::default();
+        let mut sessions = StoreUsage::default();
+        for (user_name, user_info) in self.user_info.locked().iter() {
+            let session_bytes = user_info.sessions.heap_size();
+            sessions.entries += user_info.sessions.len();
+            sessions.bytes += session_bytes;
+            users.entries += 1;
+            users.bytes += user_name.total_size() + user_info.total_size() + MAP_ENTRY_OVERHEAD - session_bytes;
+        }
+        vec![
+            ("users", users),
+            ("sessions", sessions),
+            ("pending_challenges", self.pending.usage()),
+            ("idempotency_keys", self.registrations.usage()),
+            // a fixed-size bloom filter, it has no entries to count
+            ("duplicate_filter", StoreUsage { entries: 0, bytes: self.duplicates.heap_size() }),
+        ]
+    }
+
+    /// how many users are on each combination of parameters
+    fn user_parameters(&self) -> BTreeMap<UserParameters, usize> {
+        let mut counts = BTreeMap::new();
+        for user_info in self.user_info.locked().values() {
+            let parameters = UserParameters {
+                group: user_info.group.name(),
+                hash: user_info.hash.name(),
+                generator: if user_info.beta.is_some() { "per_user" } else { "global" },
+                kdf: if user_info.kdf.is_some() { "argon2id" } else { "legacy" },
+            };
+            *counts.entry(parameters).or_default() += 1;
+        }
+        counts
+    }
+
+    /// end the sessions `policy` no longer keeps at `now`; how many
+    fn purge_sessions(&self, policy: &RetentionPolicy, now: SystemTime) -> usize {
+        let mut purged = 0;
+        for user_info in self.user_info.locked().values_mut() {
+            let before = user_info.sessions.len();
+            user_info.sessions.retain(|_, session| !policy.session_expired(session.created_at, now));
+            if user_info.sessions.len() < before {
+                purged += before - user_info.sessions.len();
+                self.keep_sessions(user_info);
+            }
+        }
+        purged
+    }
+
+    /// issued longer than the TTL ago (ids that aren't ULIDs are left to the lookup)
+    fn is_expired(&self, auth_id: &str) -> bool {
+        match (self.challenge_ttl, auth_id.parse::<Ulid>()) {
+            (Some(ttl), Ok(id)) => self.time.since(id.issued_at()) > ttl,
+            _ => false,
+        }
+    }
+
+    /// give each honeypot that isn't registered a decoy account no one has the
+    /// key to, kept in memory only, so it looks like any other; how many
+    fn plant_honeypots(&self) -> usize {
+        let zkp = ZKP1024::rfc5114().into_inner();
+        let mut user_info_hashmap = self.user_info.locked();
+        let mut planted = 0;
+        for user_name in &self.honeypots {
+            user_info_hashmap.entry(user_name.clone()).or_insert_with(|| {
+                planted += 1;
+                UserInfo {
+                    user_name: user_name.clone(),
+                    public_key: zkp.public_key(&ZKP::generate_random_number_below(&zkp.q)),
+                    kdf: Some(KdfParams::generate()),
+                    ..Default::default()
+                }
+            });
+        }
+        planted
+    }
+
+    /// raise an alert if `user_name` is a honeypot; whether it is
+    fn honeypot_alert(&self, user_name: &str, source: &str, trace: &trace::TraceContext, what: &'static str) -> bool {
+        if !self.honeypots.contains(user_name) {
+            return false;
+        }
+        println!("🍯 Honeypot Account Tried username: {:?} source: {} ({}) trace_id: {}", telemetry::user(user_name), telemetry::source(source), what, trace.trace_id_hex());
+        self.audit("honeypot", Outcome::Alert, user_name, source, trace, what);
+        true
+    }
+
+    /// ask the gate (a CAPTCHA check, say) whether this request gets a challenge
+    async fn check_gate(&self, user_name: &str, source: &str, token: String, trace: &trace::TraceContext) -> Result<(), Status> {
+        let context = ChallengeContext { user: user_name.to_string(), source: source.to_string(), token };
+        match self.gate.check(&context).await {
+            Ok(()) => Ok(()),
+            Err(GateError::Refused(reason)) => {
+                println!("🚧 Challenge refused by the gate username: {:?} source: {} trace_id: {}", telemetry::user(user_name), telemetry::source(source), trace.trace_id_hex());
+                Err(Status::new(Code::PermissionDenied, reason))
+            }
+            Err(GateError::Unavailable(reason)) => {
+                println!("⚠️  The challenge gate couldn't decide username: {:?} trace_id: {}: {}", telemetry::user(user_name), trace.trace_id_hex(), reason);
+                Err(Status::new(Code::Unavailable, "the challenge gate is unavailable, try again later"))
+            }
+        }
+    }
+
+    /// the proof of work a challenge asked for, checked before any modpow; an
+    /// unknown auth_id passes, check_answer turns it away
+    #[allow(clippy::result_large_err)] // same result type as the handler it serves
+    fn check_proof_of_work(&self, auth_id: &str, nonce: u64, source: &str, trace: &trace::TraceContext) -> Result<(), Status> {
+        let Some(user_name) = self.pending.owner(auth_id) else {
+            return Ok(());
+        };
+        let (difficulty, c) = match self.user_info.locked().get(&user_name) {
+            Some(user_info) => (user_info.pending_pow, user_info.c.to_bytes_be()),
+            None => return Ok(()),
+        };
+        require_proof_of_work(&user_name, auth_id, &c, difficulty, nonce, source, trace)
+    }
+
+    /// a direct challenge for --stateless-challenges: everything the answer is
+    /// checked against goes into a signed token and nothing stays here
+    #[allow(clippy::result_large_err)] // same result type as the handler it serves
+    fn sign_challenge(
+        &self,
+        key: &ChallengeTokenKey,
+        request: AuthenticationChallengeRequest,
+        source: &str,
+        trace: trace::TraceContext,
+    ) -> Result<Response<AuthenticationChallengeResponse>, Status> {
+        let device_id = match self.user_info.locked().get(&request.user) {
+            Some(user_info) => {
+                self.check_enabled(user_info, source, &trace)?;
+                enrolled_device(user_info, &request.device_id)?.unwrap_or_default()
+            }
+            None => {
+                return Err(Status::new(
+                    Code::NotFound,
+                    format!("User: {} not found in database", request.user),
+                ))
+            }
+        };
+
+        let (_, _, _, q) = ZKP::get_constants();
+        let c = challenge::random(self.challenge_bits.unwrap_or(q.bits()), &q).to_bytes_be();
+        let now = self.time.now();
+        let token = ChallengeToken {
+            auth_id: self.auth_ids.next(now).to_string(),
+            user: request.user.clone(),
+            device_id,
+            c: c.clone(),
+            r1: request.r1,
+            r2: request.r2,
+            client: request.client.map(|client| client.encode_to_vec()).unwrap_or_default(),
+            // nothing is pending here, so only --pow-pending-threshold 0 asks for work
+            pow_difficulty: self.pow.difficulty(self.pending.unanswered()),
+            expires_unix_millis: clock::unix_millis(now + self.challenge_ttl.unwrap_or(challenge_token::DEFAULT_LIFETIME)),
+        };
+
+        println!("✅ Signed Challenge Token username: {:?} trace_id: {}", telemetry::user(&request.user), trace.trace_id_hex());
+        Ok(trace.response(AuthenticationChallengeResponse {
+            auth_id: token.auth_id.clone(),
+            c,
+            nonce: Vec::new(),
+            pow_difficulty: token.pow_difficulty,
+            challenge_token: key.sign(&token),
+        }))
+    }
+
+    /// second step of the committed flow: take (r1, r2), then reveal c and the nonce
+    #[allow(clippy::result_large_err)] // same result type as the handler it serves
+    fn open_committed_challenge(
+        &self,
+        user_info_hashmap: &mut HashMap<String, UserInfo>,
+        request: AuthenticationChallengeRequest,
+        source: &str,
+        trace: trace::TraceContext,
+    ) -> Result<Response<AuthenticationChallengeResponse>, Status> {
+        let auth_id = request.auth_id;
+        let owner = match self.is_expired(&auth_id) {
+            true => None,
+            false => self.pending.owner(&auth_id),
+        };
+        let opening = match owner {
+            Some(owner) if owner == request.user => self.pending.take_committed(&auth_id),
+            _ => None,
+        };
+        let (Some((c, nonce)), Some(user_info)) = (opening, user_info_hashmap.get_mut(&request.user)) else {
+            return Err(Status::new(
+                Code::NotFound,
+                format!("AuthId: {} is not a pending committed challenge for {}", auth_id, request.user),
+            ));
+        };
+        self.check_enabled(user_info, source, &trace)?;
+        user_info.pending_device = enrolled_device(user_info, &request.device_id)?;
+
+        user_info.c = types::Challenge(c);
+        user_info.commitment = types::Commitment::from_bytes_be(&request.r1, &request.r2);
+        user_info.pending_client = request.client.clone();
+        user_info.pending_pow = self.pow.difficulty(self.pending.unanswered());
+
+        println!("✅ Opened Committed Challenge username: {:?} trace_id: {}", telemetry::user(&request.user), trace.trace_id_hex());
+        Ok(trace.response(AuthenticationChallengeResponse {
+            auth_id,
+            c: user_info.c.to_bytes_be(),
+            nonce,
+            pow_difficulty: user_info.pending_pow,
+            challenge_token: String::new(),
+        }))
+    }
+}
+
+/// gRPC server for the Chaum-Pedersen ZKP authentication protocol
+#[derive(Parser)]
+#[command(version, about)]
+#[command(group(clap::ArgGroup::new("stores").multiple(true).args(["user_store", "storage"])))]
+struct ServerArgs {
+    /// INSECURE: seed the random number generator so challenges and IDs are reproducible (demos/debugging only)
+    #[arg(long)]
+    seed: Option<u64>,
+    /// PEM certificate chain; enables TLS together with --tls-key
+    #[arg(long, requires = "tls_key")]
+    tls_cert: Option<PathBuf>,
+    /// PEM private key for --tls-cert
+    #[arg(long, requires = "tls_cert")]
+    tls_key: Option<PathBuf>,
+    /// How often (in seconds) to check the certificate files for renewals
+    #[arg(long, default_value_t = 30)]
+    tls_reload_interval: u64,
+    /// Address to listen on (tls-alpn-01 validation needs the server reachable on port 443)
+    #[arg(long, default_value = "0.0.0.0:50051")]
+    listen: String,
+    /// Long-term Ed25519 key (hex) used to sign the published group parameters; created if missing
+    #[arg(long)]
+    params_key: Option<PathBuf>,
+    /// Mint OIDC-style ID tokens with this issuer (iss) when a login asks, signed with --params-key
+    /// or with the key behind --id-token-public-key
+    #[arg(long, requires = "id_token_audience")]
+    id_token_issuer: Option<String>,
+    /// Relying party (OIDC client id) ID tokens may be minted for (repeatable)
+    #[arg(long)]
+    id_token_audience: Vec<String>,
+    /// Seconds an ID token is valid
+    #[arg(long, default_value_t = id_token::DEFAULT_LIFETIME.as_secs())]
+    id_token_ttl: u64,
+    /// Sign ID tokens with a key held in shares (`zkp-auth admin split-key`): its Ed25519 public key (hex)
+    #[arg(long, requires = "id_token_issuer")]
+    id_token_public_key: Option<String>,
+    /// File with one share of that key (repeatable); the rest come in with `zkp-auth admin unseal`
+    #[arg(long, requires = "id_token_public_key")]
+    id_token_key_share: Vec<PathBuf>,
+    /// OpenSSH CA private key (unencrypted Ed25519) to sign the SSH keys logins send
+    #[arg(long)]
+    ssh_ca_key: Option<PathBuf>,
+    /// Seconds an SSH certificate is valid
+    #[arg(long, default_value_t = ssh_cert::DEFAULT_LIFETIME.as_secs())]
+    ssh_cert_ttl: u64,
+    /// How long (in seconds) a registration's idempotency key is remembered
+    #[arg(long, default_value_t = 600)]
+    idempotency_ttl: u64,
+    /// What user fields hold: any username, an email address (verified before the first login), a
+    /// subject the server issues at registration or no name, just a fingerprint of the public key;
+    /// can't change once users are registered
+    #[arg(long, value_enum, default_value_t = UserIds::Username)]
+    user_ids: UserIds,
+    /// Shell command that sends an email verification code, run with ZKP_EMAIL and ZKP_CODE set
+    /// (needed with --user-ids email)
+    #[arg(long)]
+    email_verification_command: Option<String>,
+    /// Refuse registrations whose client didn't check the password against at least this policy version (0 = accept all)
+    #[arg(long, default_value_t = 0)]
+    min_password_policy: u32,
+    /// Clock skew (in seconds) clients should allow for in time-bound checks, published via GetServerTime
+    #[arg(long, default_value_t = clock::DEFAULT_SKEW.as_secs())]
+    max_clock_skew: u64,
+    /// Threads for big-integer work (default: one per CPU)
+    #[arg(long)]
+    crypto_threads: Option<usize>,
+    /// Crypto jobs that may wait for a thread before requests are rejected with ResourceExhausted
+    #[arg(long, default_value_t = blocking::DEFAULT_QUEUE)]
+    crypto_queue: usize,
+    /// Give up on a request after this many seconds (a shorter grpc-timeout from the client wins)
+    #[arg(long, default_value_t = 30)]
+    request_timeout: u64,
+    /// JSON file to keep revoked proofs in across restarts (also a CRL-style export)
+    #[arg(long)]
+    revocation_list: Option<PathBuf>,
+    /// Seconds a challenge (auth_id) stays answerable; 0 keeps them until restart
+    #[arg(long, default_value_t = 300)]
+    challenge_ttl: u64,
+    /// Keep no pending challenges: hand out signed tokens any replica can check the answer against
+    /// (direct challenges only)
+    #[arg(long)]
+    stateless_challenges: bool,
+    /// File with the key challenge tokens are signed with, the same on every replica (at least 32 bytes);
+    /// without it a random key is used, which only this process knows
+    #[arg(long, requires = "stateless_challenges")]
+    challenge_token_key_file: Option<PathBuf>,
+    /// Seconds a device's QR code can be scanned and answered from a phone; 0 turns QR-code logins off
+    #[arg(long, default_value_t = device_login::LOGIN_TTL.as_secs())]
+    device_login_ttl: u64,
+    /// Seconds a session stays elevated after ElevateSession; 0 turns elevation off
+    #[arg(long, default_value_t = 300)]
+    elevation_ttl: u64,
+    /// Longest lifetime (in seconds) of a delegation VerifyDelegation accepts; 0 turns delegations off
+    #[arg(long, default_value_t = delegation::MAX_LIFETIME.as_secs())]
+    delegation_max_ttl: u64,
+    /// Server whose ID tokens and proofs are checked here, as `<endpoint>#<its parameter key>` (repeatable)
+    #[arg(long = "federation-peer")]
+    federation_peer: Vec<PeerSpec>,
+    /// Seconds between fetches of the federation peers' keys and revocation lists
+    #[arg(long, default_value_t = federation::REFRESH.as_secs())]
+    federation_refresh: u64,
+    /// Bits per challenge when shorter than q saves bandwidth (at least 80; default: the size of q)
+    #[arg(long)]
+    challenge_bits: Option<u64>,
+    /// Make logins answer with a proof of work of this many leading zero bits (at most 32; 0 = never)
+    #[arg(long, default_value_t = 0)]
+    pow_difficulty: u32,
+    /// Only ask for proofs of work while at least this many challenges are pending, not yet answered (0 = always)
+    #[arg(long, default_value_t = 0, requires = "pow_difficulty")]
+    pow_pending_threshold: usize,
+    /// Username only an intruder would try (repeatable); every challenge or answer for it raises
+    /// a `honeypot` alert in the audit trail, and a correct proof is refused like a wrong one
+    #[arg(long)]
+    honeypot_user: Vec<String>,
+    /// Compress responses (requests are accepted in any of these)
+    #[arg(long, value_enum, default_value_t = Compression::None)]
+    compression: Compression,
+    /// Rules deciding which verified logins get a session, e.g. `deny if risk >= 80` (see policy.rs)
+    #[arg(long)]
+    policy_rules: Option<PathBuf>,
+    /// How long (in seconds) submitted proofs are remembered to catch duplicates across auth_ids
+    #[arg(long, default_value_t = duplicates::DEFAULT_WINDOW.as_secs())]
+    duplicate_window: u64,
+    /// Only log proofs submitted again for another auth_id instead of refusing them
+    #[arg(long)]
+    log_duplicate_proofs: bool,
+    /// How much of users, addresses and devices the console log shows (the audit log keeps them)
+    #[arg(long, value_enum, default_value_t = PrivacyLevel::Standard)]
+    privacy: PrivacyLevel,
+    /// Share of requests (0 to 1) whose routine "Processing ..." lines are logged; outcomes always are
+    #[arg(long, default_value_t = 1.0, value_parser = parse_sample_rate)]
+    log_sample_rate: f64,
+    /// Append audit events (registrations, logins, revocations) as JSON lines to this file
+    #[arg(long)]
+    audit_log: Option<PathBuf>,
+    /// Rotate --audit-log when it would grow past this many bytes
+    #[arg(long, default_value_t = 10 * 1024 * 1024)]
+    audit_log_max_bytes: u64,
+    /// Rotated audit logs to keep (<file>.1 is the newest)
+    #[arg(long, default_value_t = 5)]
+    audit_log_keep: usize,
+    /// Send audit events to syslog (RFC 5424, facility authpriv): host:port over UDP or a socket path like /dev/log
+    #[arg(long)]
+    audit_syslog: Option<String>,
+    /// Write audit events as JSON lines to stdout
+    #[arg(long)]
+    audit_stdout: bool,
+    /// Drop --audit-log events past an age, `[ACTION][:OUTCOME]=AGE` such as `login:failure=90d` (repeatable, see retention.rs)
+    #[arg(long, requires = "audit_log")]
+    audit_retention: Vec<AuditRetention>,
+    /// End sessions this long after the login, e.g. 30d (default: they last until revoked or crowded out)
+    #[arg(long, value_parser = retention::parse_age)]
+    session_retention: Option<Duration>,
+    /// How often (in seconds) what is past its retention gets purged
+    #[arg(long, default_value_t = 3600)]
+    retention_interval: u64,
+    /// Lease file on storage shared with other servers; only the server holding it purges
+    /// and rotates the audit log there (see lease.rs)
+    #[arg(long)]
+    leader_lease: Option<PathBuf>,
+    /// Seconds a --leader-lease lasts without renewal; it is renewed every third of that
+    #[arg(long, default_value_t = lease::DEFAULT_TTL.as_secs(), requires = "leader_lease")]
+    leader_lease_ttl: u64,
+    /// Run a background job on another schedule, `JOB=SCHEDULE` such as `retention=0 3 * * *` or `memory=@every 5m` (repeatable, see scheduler.rs)
+    #[arg(long, value_parser = parse_schedule)]
+    schedule: Vec<(String, Schedule)>,
+    /// JSON lines journal of registrations, read at startup so users survive restarts;
+    /// repeat to shard users over several by consistent hashing (see shard.rs)
+    #[arg(long)]
+    user_store: Vec<PathBuf>,
+    /// Keep users in `BACKEND:PATH` instead: `journal:<file>` as --user-store, or `sled:<dir>`
+    /// for an embedded database (`sled` feature); repeatable and shardable like --user-store
+    #[arg(long)]
+    storage: Vec<Storage>,
+    /// File with a 32-byte hex key (`openssl rand -hex 32`) to seal user store records with (AES-256-GCM)
+    #[arg(long, requires = "stores")]
+    user_store_key: Option<PathBuf>,
+    /// Serve logins from a user store another server (the primary) writes, e.g. on a shared volume;
+    /// registrations go to --primary, other writes are refused
+    #[arg(long, requires = "stores")]
+    read_replica: bool,
+    /// Server a read replica forwards registrations and email verifications to
+    #[arg(long, requires = "read_replica")]
+    primary: Option<String>,
+    /// Seconds between a read replica's looks at the user store
+    #[arg(long, default_value_t = 5)]
+    replica_refresh: u64,
+    /// How often (in seconds) to retry an unavailable user store
+    #[arg(long, default_value_t = 5)]
+    user_store_retry: u64,
+    /// Sync user store writes every this many milliseconds instead of one by one. 0 (the default)
+    /// acknowledges a write once it is on disk; otherwise writes are acknowledged once the OS has
+    /// them, and a power loss can take the last interval's with it (a killed server too, with sled)
+    #[arg(long, default_value_t = 0)]
+    user_store_flush_ms: u64,
+    /// With --user-store-flush-ms, sync as soon as this many writes wait (0 = no limit)
+    #[arg(long, default_value_t = 1000)]
+    user_store_batch: usize,
+    /// Log user store operations that take this many milliseconds or longer, with the user as --privacy allows (0 = never)
+    #[arg(long, default_value_t = 500)]
+    slow_store_ms: u64,
+    /// Cap on what pending challenges may take (bytes); past it the oldest are dropped, 0 = no cap
+    #[arg(long, default_value_t = 64 * 1024 * 1024)]
+    max_pending_challenge_bytes: usize,
+    /// File with the shared secret for the Admin service (`authorization: Bearer <token>`); also what IntrospectSession wants; without it both are off
+    #[arg(long)]
+    admin_token_file: Option<PathBuf>,
+    /// Serve crypto latency percentiles for Prometheus on this address, e.g. 127.0.0.1:9100 (GET /metrics)
+    #[arg(long)]
+    metrics_listen: Option<std::net::SocketAddr>,
+    /// Only serve requests from these networks or countries, `[RPC=]SOURCE[,SOURCE...]` such as
+    /// `10.0.0.0/8` or `Admin/*=192.168.0.0/16,country:DE` (repeatable, see ip_filter.rs)
+    #[arg(long = "allow-from")]
+    allow_from: Vec<Rule>,
+    /// Refuse requests from these networks or countries, in the form of --allow-from (repeatable)
+    #[arg(long = "deny-from")]
+    deny_from: Vec<Rule>,
+    /// MaxMind database (GeoLite2-Country or GeoIP2-City .mmdb) that `country:` sources are looked up in
+    #[arg(long)]
+    geoip_db: Option<PathBuf>,
+    #[cfg(feature = "acme")]
+    #[command(flatten)]
+    acme: AcmeArgs,
+    #[cfg(feature = "vault")]
+    #[command(flatten)]
+    vault: VaultArgs,
+    #[cfg(feature = "kms")]
+    #[command(flatten)]
+    kms: KmsArgs,
+    #[cfg(feature = "webhooks")]
+    #[command(flatten)]
+    webhook: WebhookArgs,
+    #[cfg(any(feature = "kafka", feature = "nats"))]
+    #[command(flatten)]
+    publish: PublishArgs,
+    #[cfg(feature = "captcha")]
+    #[command(flatten)]
+    captcha: CaptchaArgs,
+    /// Serve CPU profiles over HTTP on this address, e.g. 127.0.0.1:6060 (keep it private)
+    #[cfg(feature = "profiling")]
+    #[arg(long)]
+    pprof_listen: Option<std::net::SocketAddr>,
+}
+
+/// Certificates from an ACME CA (Let's Encrypt) instead of --tls-cert/--tls-key
+#[cfg(feature = "acme")]
+#[derive(clap::Args)]
+struct AcmeArgs {
+    /// Public hostname to obtain a certificate for (repeatable); enables ACME
+    #[arg(long = "acme-domain", conflicts_with = "tls_cert")]
+    domains: Vec<String>,
+    /// Contact email for the ACME account (repeatable)
+    #[arg(long = "acme-contact")]
+    contacts: Vec<String>,
+    /// Directory to cache the account key and certificates across restarts
+    #[arg(long = "acme-cache")]
+    cache: Option<PathBuf>,
+    /// Use the Let's Encrypt production directory instead of staging
+    #[arg(long = "acme-production")]
+    production: bool,
+}
+
+/// Secrets from HashiCorp Vault instead of files; VAULT_ADDR, VAULT_TOKEN,
+/// VAULT_NAMESPACE and VAULT_CACERT are read as by the Vault CLI
+#[cfg(feature = "vault")]
+#[derive(clap::Args)]
+struct VaultArgs {
+    /// Vault address, e.g. https://vault.internal:8200 (default: VAULT_ADDR)
+    #[arg(long = "vault-addr")]
+    addr: Option<String>,
+    /// File with the Vault token, re-read on every refresh so an agent can replace it (default: VAULT_TOKEN)
+    #[arg(long = "vault-token-file")]
+    token_file: Option<PathBuf>,
+    /// TLS certificate chain and key from a KV secret with `cert` and `key` fields (PEM), e.g. kv:secret/zkp/tls
+    #[arg(long = "vault-tls", conflicts_with = "tls_cert")]
+    tls: Option<SecretRef>,
+    /// Sign ID tokens with an exportable Ed25519 transit key (transit:transit/zkp-id-token)
+    /// or the hex seed in a KV secret's `key` field
+    #[arg(long = "vault-id-token-key", requires = "id_token_issuer", conflicts_with = "id_token_public_key")]
+    id_token_key: Option<SecretRef>,
+    /// Admin service token from a KV secret's `token` field
+    #[arg(long = "vault-admin-token", conflicts_with = "admin_token_file")]
+    admin_token: Option<SecretRef>,
+    /// Key to seal user store records with, from a KV secret's `key` field (32 hex-encoded bytes)
+    #[arg(long = "vault-user-store-key", requires = "stores", conflicts_with = "user_store_key")]
+    store_key: Option<SecretRef>,
+    /// Seconds between renewing the Vault token and re-reading the secrets for rotations
+    #[arg(long = "vault-refresh", default_value_t = 300)]
+    refresh: u64,
+}
+
+/// Audit events POSTed to a webhook, through an outbox on disk so none is lost (see outbox.rs)
+#[cfg(feature = "webhooks")]
+#[derive(clap::Args)]
+struct WebhookArgs {
+    /// URL to POST every audit event to, as `{"id": ..., "event": {...}}`
+    #[arg(long = "event-webhook", requires = "outbox")]
+    url: Option<String>,
+    /// Journal events wait in until the webhook takes them; given up ones go to <PATH>.dead
+    #[arg(long = "event-outbox", requires = "url")]
+    outbox: Option<PathBuf>,
+    /// PEM file with the CA certificates to trust for an https webhook (default: the system's)
+    #[arg(long = "event-webhook-ca-cert", requires = "url")]
+    ca_cert: Option<PathBuf>,
+    /// Attempts before an event goes to the dead letters
+    #[arg(long = "event-max-attempts", default_value_t = outbox::MAX_ATTEMPTS)]
+    max_attempts: u32,
+    /// Seconds between delivery runs
+    #[arg(long = "event-delivery-interval", default_value_t = 5)]
+    interval: u64,
+}
+
+/// Audit events published as CloudEvents to Kafka or NATS (see publish.rs)
+#[cfg(any(feature = "kafka", feature = "nats"))]
+#[derive(clap::Args)]
+struct PublishArgs {
+    /// Kafka bootstrap broker, host:port (repeatable); events are keyed by user
+    #[cfg(feature = "kafka")]
+    #[arg(long = "audit-kafka")]
+    kafka: Vec<String>,
+    /// Kafka topic to publish audit events to
+    #[cfg(feature = "kafka")]
+    #[arg(long = "audit-kafka-topic", default_value = "zkp-auth.audit")]
+    kafka_topic: String,
+    /// NATS server, nats://[user:password@|token@]host[:port]
+    #[cfg(feature = "nats")]
+    #[arg(long = "audit-nats")]
+    nats: Option<NatsUrl>,
+    /// NATS subject prefix; each event goes to <SUBJECT>.<action>
+    #[cfg(feature = "nats")]
+    #[arg(long = "audit-nats-subject", default_value = "zkp-auth.audit")]
+    nats_subject: String,
+    /// CloudEvents `source` of published events (default: urn:zkp-auth:<hostname>)
+    #[arg(long = "audit-cloudevents-source")]
+    source: Option<String>,
+}
+
+/// A CAPTCHA to solve before every challenge, checked with the provider (see captcha.rs)
+#[cfg(feature = "captcha")]
+#[derive(clap::Args)]
+struct CaptchaArgs {
+    /// File with the CAPTCHA provider's secret key; enables the check
+    #[arg(long = "captcha-secret-file")]
+    secret_file: Option<PathBuf>,
+    /// The provider's siteverify endpoint (Turnstile by default; hCaptcha and reCAPTCHA work too)
+    #[arg(long = "captcha-verify-url", default_value = captcha::TURNSTILE)]
+    verify_url: String,
+    /// Site the CAPTCHA must have been solved on (repeatable; default: any)
+    #[arg(long = "captcha-hostname", requires = "secret_file")]
+    hostnames: Vec<String>,
+    /// PEM file with the CA certificates to trust for the endpoint (default: the system's)
+    #[arg(long = "captcha-ca-cert", requires = "secret_file")]
+    ca_cert: Option<PathBuf>,
+}
+
+/// An ID token key in a cloud KMS, so the server never holds it; AWS
+/// credentials come from AWS_ACCESS_KEY_ID, AWS_SECRET_ACCESS_KEY and AWS_SESSION_TOKEN
+#[cfg(feature = "kms")]
+#[derive(clap::Args)]
+struct KmsArgs {
+    /// Sign ID tokens with an AWS KMS key of spec ECC_NIST_EDWARDS25519 (key id, ARN or alias/..)
+    #[arg(long = "kms-aws-key", requires = "id_token_issuer", conflicts_with_all = ["id_token_public_key", "gcp_key"])]
+    #[cfg_attr(feature = "vault", arg(conflicts_with = "id_token_key"))]
+    aws_key: Option<String>,
+    /// Region of that key (default: AWS_REGION or AWS_DEFAULT_REGION)
+    #[arg(long = "kms-aws-region", requires = "aws_key")]
+    aws_region: Option<String>,
+    /// Sign ID tokens with a Google Cloud KMS key version of algorithm EC_SIGN_ED25519
+    /// (projects/../locations/../keyRings/../cryptoKeys/../cryptoKeyVersions/..)
+    #[arg(long = "kms-gcp-key", requires = "id_token_issuer", conflicts_with = "id_token_public_key")]
+    #[cfg_attr(feature = "vault", arg(conflicts_with = "id_token_key"))]
+    gcp_key: Option<String>,
+    /// File with a Google access token, re-read for every signature (default: the metadata server)
+    #[arg(long = "kms-gcp-token-file", requires = "gcp_key")]
+    gcp_token_file: Option<PathBuf>,
+    /// KMS endpoint instead of the cloud's own, e.g. a VPC endpoint (default for AWS: AWS_ENDPOINT_URL_KMS)
+    #[arg(long = "kms-endpoint")]
+    endpoint: Option<String>,
+}
+
+/// The admin token behind a lock, so one rotated in Vault takes over without a restart
+#[derive(Debug, Clone)]
+struct SharedAdminToken(Arc<RwLock<AdminToken>>);
+
+impl Interceptor for SharedAdminToken {
+    fn call(&mut self, request: Request<()>) -> Result<Request<()>, Status> {
+        self.0.read().unwrap().clone().call(request)
+    }
+}
+
+/// Secrets read from Vault; always empty without the `vault` feature
+#[derive(Default)]
+struct VaultSecrets {
+    tls: Option<(String, String)>, // certificate chain, key
+    id_token_key: Option<SigningKey>,
+    admin_token: Option<String>,
+    user_store_key: Option<String>, // hex
+}
+
+#[tokio::main]
+async fn main() {
+    let args = ServerArgs::parse();
+    telemetry::set(TelemetryPolicy::new(args.privacy, args.log_sample_rate));
+    if args.privacy != PrivacyLevel::Standard || args.log_sample_rate < 1.0 {
+        println!("🕶️  Logging with --privacy {:?}, {}% of requests in detail", args.privacy, args.log_sample_rate * 100.0);
+    }
+    if let Some(seed) = args.seed {
+        println!("⚠️  INSECURE: random numbers are seeded with {}, never use this in production", seed);
+        ZKP::with_rng(seed);
+    }
+
+    let addr = args.listen;
+
+    #[cfg(feature = "profiling")]
+    {
+        rust_zkp_chaum_pedersen::profiling::init_tracing();
+        if let Some(pprof_addr) = args.pprof_listen {
+            println!("🔥 Serving CPU profiles on http://{}/debug/pprof/profile", pprof_addr);
+            tokio::spawn(async move {
+                if let Err(e) = rust_zkp_chaum_pedersen::profiling::serve(pprof_addr).await {
+                    println!("❌ pprof endpoint failed: {}", e);
+                }
+            });
+        }
+    }
+
+    if let Some(metrics_addr) = args.metrics_listen {
+        println!("📈 Serving metrics on http://{}/metrics", metrics_addr);
+        println!("📖 Serving the REST gateway's OpenAPI document on http://{}/openapi.json", metrics_addr);
+        tokio::spawn(async move {
+            if let Err(e) = rust_zkp_chaum_pedersen::metrics::serve(metrics_addr).await {
+                println!("❌ Metrics endpoint failed: {}", e);
+            }
+        });
+    }
+
+    println!("✅ Running the server in {}", addr);
+
+    #[cfg(feature = "vault")]
+    let vault_client = connect_vault(&args.vault).await;
+    #[cfg(feature = "vault")]
+    let from_vault = match &vault_client {
+        Some(client) => read_vault(client, &args.vault).await.unwrap_or_else(|e| panic!("could not read the secrets from Vault: {}", e)),
+        None => VaultSecrets::default(),
+    };
+    #[cfg(not(feature = "vault"))]
+    let from_vault = VaultSecrets::default();
+
+    let admin_token = match (&from_vault.admin_token, &args.admin_token_file) {
+        (Some(token), _) => Some(AdminToken::new(token).unwrap_or_else(|e| panic!("the admin token in Vault: {}", e))),
+        (None, Some(path)) => Some(
+            AdminToken::load(path).unwrap_or_else(|e| panic!("could not read the admin token {}: {}", path.display(), e)),
+        ),
+        (None, None) => None,
+    }
+    .map(|token| SharedAdminToken(Arc::new(RwLock::new(token))));
+
+    let time = Time::default();
+    let mut auth_impl = AuthImpl {
+        registrations: IdempotencyCache::new(Duration::from_secs(args.idempotency_ttl)).with_time(time.clone()),
+        min_password_policy: args.min_password_policy,
+        max_clock_skew: Duration::from_secs(args.max_clock_skew),
+        crypto: Arc::new(CryptoPool::new(
+            args.crypto_threads.unwrap_or_else(blocking::default_threads),
+            args.crypto_queue,
+        )),
+        // it also remembers answered challenge tokens, for at least as long as they last
+        duplicates: DuplicateFilter::new(Duration::from_secs(match (args.stateless_challenges, args.challenge_ttl) {
+            (true, 0) => args.duplicate_window.max(challenge_token::DEFAULT_LIFETIME.as_secs()),
+            (true, ttl) => args.duplicate_window.max(ttl),
+            (false, _) => args.duplicate_window,
+        }))
+        .with_time(time.clone()),
+        time,
+        log_duplicate_proofs: args.log_duplicate_proofs,
+        challenge_bits: args.challenge_bits,
+        pow: PowPolicy { difficulty: args.pow_difficulty, pending_threshold: args.pow_pending_threshold },
+        challenge_ttl: (args.challenge_ttl > 0).then(|| Duration::from_secs(args.challenge_ttl)),
+        session_retention: args.session_retention,
+        elevation_ttl: (args.elevation_ttl > 0).then(|| Duration::from_secs(args.elevation_ttl)),
+        delegation_max_ttl: (args.delegation_max_ttl > 0).then(|| Duration::from_secs(args.delegation_max_ttl)),
+        device_logins: (args.device_login_ttl > 0).then(|| DeviceLogins::new(Duration::from_secs(args.device_login_ttl))),
+        pending: PendingChallenges::new((args.max_pending_challenge_bytes > 0).then_some(args.max_pending_challenge_bytes)),
+        user_ids: args.user_ids,
+        email_codes: args.email_verification_command.clone().map(|command| CodeSender { command }),
+        admin_token: admin_token.clone(),
+        ..Default::default()
+    };
+    if args.user_ids == UserIds::Email && auth_impl.email_codes.is_none() {
+        panic!("--user-ids email needs --email-verification-command to send the verification codes");
+    }
+    if args.user_ids != UserIds::Username {
+        println!("🆔 User ids are {}s", args.user_ids);
+    }
+    if !args.federation_peer.is_empty() {
+        let endpoints: Vec<&str> = args.federation_peer.iter().map(|peer| peer.endpoint.as_str()).collect();
+        println!("🌐 Federating with {}", endpoints.join(", "));
+        auth_impl.federation = Some(Federation::new(args.federation_peer.clone()));
+    }
+
+    if args.stateless_challenges {
+        let key = match &args.challenge_token_key_file {
+            Some(path) => ChallengeTokenKey::load(path)
+                .unwrap_or_else(|e| panic!("could not read the challenge token key {}: {}", path.display(), e)),
+            None => {
+                println!("⚠️  No --challenge-token-key-file: challenge tokens only verify on this replica");
+                ChallengeTokenKey::random()
+            }
+        };
+        println!("🎟️  Stateless challenges: answers are checked against signed tokens");
+        auth_impl.challenge_tokens = Some(key);
+    }
+
+    if let Some(path) = &args.ssh_ca_key {
+        let pem = std::fs::read_to_string(path).expect("could not read the SSH CA key");
+        let ca = SshCa::from_openssh(&pem, Duration::from_secs(args.ssh_cert_ttl))
+            .unwrap_or_else(|e| panic!("{}: {}", path.display(), e));
+        println!("🔑 Signing SSH certificates, hosts should trust: {}", ca.public_key_line());
+        auth_impl.ssh_ca = Some(ca);
+    }
+
+    let flush = FlushPolicy::every(Duration::from_millis(args.user_store_flush_ms), args.user_store_batch);
+    let stores: Vec<Storage> = args.user_store.iter().map(|path| Storage::Journal(path.clone())).chain(args.storage.iter().cloned()).collect();
+    if !stores.is_empty() {
+        let key = match (&from_vault.user_store_key, &args.user_store_key) {
+            (Some(key), _) => Some(StoreKey::from_hex(key).unwrap_or_else(|e| panic!("the user store key in Vault: {}", e))),
+            (None, Some(key_path)) => Some(
+                StoreKey::load(key_path)
+                    .unwrap_or_else(|e| panic!("could not read the user store key {}: {}", key_path.display(), e)),
+            ),
+            (None, None) => None,
+        };
+        if let Some(key) = &key {
+            println!("🔐 Sealing user store records with key {}", key.id());
+        }
+        if args.read_replica {
+            // the primary owns the journals: no truncating, resealing, moving or retrying them here
+            auth_impl.read_replica = true;
+            if let Some(storage) = stores.iter().find(|storage| !matches!(storage, Storage::Journal(_))) {
+                panic!("--read-replica follows journals, --storage {} is only ever opened by the primary", storage);
+            }
+            let shards = stores.iter().map(|storage| slow_log(UserStore::follow(storage.path(), key.clone()), args.slow_store_ms)).collect();
+            auth_impl.user_store = Some(ShardedStore::new(shards));
+            auth_impl.follow_user_store();
+            let paths: Vec<String> = stores.iter().map(|storage| storage.path().display().to_string()).collect();
+            println!("📖 Read replica of {} ({} users), writes go to the primary", paths.join(", "), auth_impl.user_info.locked().len());
+        } else {
+            if !flush.is_every_write() {
+                println!("💾 Syncing user store writes {}, not before they are acknowledged", flush);
+            }
+            let mut shards = Vec::new();
+            let mut read = Vec::new();
+            let mut sessions = Vec::new();
+            for storage in &stores {
+                let path = storage.path();
+                match UserStore::open_storage(storage, key.clone()) {
+                    Ok((store, users)) => {
+                        println!("💾 {} users in {}", users.len(), storage);
+                        if let Some(stored) = users.iter().find(|stored| args.user_ids.canonical(&stored.user).as_ref() != Ok(&stored.user)) {
+                            panic!(
+                                "{} has user {:?}, which isn't a {} id: --user-ids can't change once users are registered",
+                                path.display(), stored.user, args.user_ids
+                            );
+                        }
+                        let kept = store.sessions().unwrap_or_else(|e| {
+                            println!("⚠️  Could not read the sessions in {}, they have to log in again: {}", storage, e);
+                            Vec::new()
+                        });
+                        shards.push(slow_log(store.with_flush(flush), args.slow_store_ms));
+                        read.push(users);
+                        sessions.push(kept);
+                    }
+                    Err(e) => {
+                        println!("⚠️  User store {} is unavailable, registrations are paused until it is back: {}", path.display(), e);
+                        shards.push(slow_log(UserStore::unavailable_storage(storage, &e, key.clone()).with_flush(flush), args.slow_store_ms));
+                        read.push(Vec::new());
+                        sessions.push(Vec::new());
+                    }
+                }
+            }
+            let sharded = ShardedStore::new(shards);
+            let (users, moved) = sharded.rebalance(read);
+            if moved > 0 {
+                println!("🔀 Moved {} users to their shard", moved);
+            }
+            auth_impl.load_users(users);
+            report_shards(&sharded);
+            auth_impl.user_store = Some(sharded);
+            let restored: usize = sessions.into_iter().enumerate().map(|(shard, kept)| auth_impl.load_sessions(shard, kept)).sum();
+            if restored > 0 {
+                println!("🔑 Restored {} sessions from the user store", restored);
+            }
+        }
+    }
+    for user in &args.honeypot_user {
+        let user = args.user_ids.canonical(user).unwrap_or_else(|e| panic!("--honeypot-user {:?}: {}", user, e));
+        auth_impl.honeypots.insert(user);
+    }
+    if !auth_impl.honeypots.is_empty() {
+        let planted = auth_impl.plant_honeypots();
+        println!("🍯 {} honeypot accounts ({} of them decoys), every try on them is an alert", auth_impl.honeypots.len(), planted);
+    }
+    if let Some(primary) = &args.primary {
+        let mut endpoint = Endpoint::from_shared(primary.clone())
+            .unwrap_or_else(|e| panic!("--primary {}: {}", primary, e))
+            .timeout(Duration::from_secs(args.request_timeout));
+        if primary.starts_with("https://") {
+            endpoint = endpoint.tls_config(ClientTlsConfig::new()).unwrap_or_else(|e| panic!("--primary {}: {}", primary, e));
+        }
+        auth_impl.primary = Some(AuthClient::new(endpoint.connect_lazy()));
+        println!("↪️  Forwarding registrations to the primary {}", primary);
+    }
+
+    if let Some(path) = &args.audit_log {
+        let sink = JsonlSink::open(path, args.audit_log_max_bytes, args.audit_log_keep)
+            .unwrap_or_else(|e| panic!("could not open the audit log {}: {}", path.display(), e));
+        auth_impl.audit.add(sink);
+        println!("📒 Writing the audit trail to {}", path.display());
+    }
+    if let Some(address) = &args.audit_syslog {
+        let sink = SyslogSink::connect(address)
+            .unwrap_or_else(|e| panic!("could not reach syslog at {}: {}", address, e));
+        auth_impl.audit.add(sink);
+        println!("📒 Sending the audit trail to syslog at {}", address);
+    }
+    if args.audit_stdout {
+        auth_impl.audit.add(StdoutSink);
+    }
+    #[cfg(any(feature = "kafka", feature = "nats"))]
+    let source = args.publish.source.clone().unwrap_or_else(publish::default_source);
+    #[cfg(feature = "kafka")]
+    if !args.publish.kafka.is_empty() {
+        let name = format!("Kafka topic {}", args.publish.kafka_topic);
+        let transport = Kafka::new(args.publish.kafka.clone(), &args.publish.kafka_topic);
+        let sink = PublishSink::start(&name, &source, transport).unwrap_or_else(|e| panic!("could not start publishing to Kafka: {}", e));
+        auth_impl.audit.add(sink);
+        println!("📒 Publishing the audit trail to the {} via {}", name, args.publish.kafka.join(", "));
+    }
+    #[cfg(feature = "nats")]
+    if let Some(url) = &args.publish.nats {
+        let name = format!("NATS subjects {}.*", args.publish.nats_subject);
+        let transport = Nats::new(url.clone(), &args.publish.nats_subject);
+        let sink = PublishSink::start(&name, &source, transport).unwrap_or_else(|e| panic!("could not start publishing to NATS: {}", e));
+        auth_impl.audit.add(sink);
+        println!("📒 Publishing the audit trail to the {} at {}", name, url.address);
+    }
+    #[cfg(feature = "webhooks")]
+    let event_delivery = match (&args.webhook.url, &args.webhook.outbox) {
+        (Some(url), Some(path)) => {
+            let uri: hyper::Uri = url.parse().unwrap_or_else(|e| panic!("--event-webhook {}: {}", url, e));
+            let tls = match uri.scheme_str() {
+                Some("https") => Some(
+                    http::tls_config(args.webhook.ca_cert.as_deref())
+                        .unwrap_or_else(|e| panic!("could not load the CA certificates for --event-webhook: {}", e)),
+                ),
+                Some("http") => None,
+                _ => panic!("--event-webhook {} must be http:// or https://", url),
+            };
+            let outbox = Arc::new(
+                Outbox::open(path, args.webhook.max_attempts)
+                    .unwrap_or_else(|e| panic!("could not open the event outbox {}: {}", path.display(), e)),
+            );
+            auth_impl.audit.add(outbox.clone());
+            println!("📤 Sending audit events to {} through {} ({} waiting)", url, path.display(), outbox.pending().len());
+            Some(EventDelivery { outbox, uri, tls })
+        }
+        _ => None,
+    };
+
+    #[cfg(feature = "captcha")]
+    if let Some(path) = &args.captcha.secret_file {
+        let secret = std::fs::read_to_string(path)
+            .unwrap_or_else(|e| panic!("could not read the CAPTCHA secret {}: {}", path.display(), e));
+        let uri: hyper::Uri = args.captcha.verify_url.parse().unwrap_or_else(|e| panic!("--captcha-verify-url: {}", e));
+        let tls = match uri.scheme_str() {
+            Some("https") => Some(
+                http::tls_config(args.captcha.ca_cert.as_deref())
+                    .unwrap_or_else(|e| panic!("could not load the CA certificates for --captcha-verify-url: {}", e)),
+            ),
+            Some("http") => None,
+            _ => panic!("--captcha-verify-url {} must be http:// or https://", uri),
+        };
+        println!("🚧 Asking for a CAPTCHA before every challenge, checked with {}", uri);
+        auth_impl.gate = Gate::new(Captcha::new(uri, &secret, args.captcha.hostnames.clone(), tls));
+    }
+
+    if let Some(path) = &args.policy_rules {
+        let text = std::fs::read_to_string(path).expect("could not read the policy rules");
+        let rules = RuleSet::parse(&text).unwrap_or_else(|e| panic!("{}: {}", path.display(), e));
+        auth_impl.policy = Policy::new(rules);
+        println!("📜 Checking logins against the policy rules in {}", path.display());
+    }
+
+    if let Some(bits) = auth_impl.challenge_bits {
+        let (_, _, _, q) = ZKP::get_constants();
+        if let Err(e) = challenge::validate_bits(bits, &q) {
+            panic!("--challenge-bits {}: {}", bits, e);
+        }
+        println!("🎯 Issuing {}-bit challenges (q has {} bits)", bits, q.bits());
+    }
+    if auth_impl.pow.difficulty > pow::MAX_DIFFICULTY {
+        panic!("--pow-difficulty {}: at most {} bits", auth_impl.pow.difficulty, pow::MAX_DIFFICULTY);
+    }
+    match auth_impl.pow {
+        PowPolicy { difficulty: 0, .. } => {}
+        PowPolicy { difficulty, pending_threshold: 0 } => println!("⛏️  Asking every login for a {}-bit proof of work", difficulty),
+        PowPolicy { difficulty, pending_threshold } => {
+            println!("⛏️  Asking logins for a {}-bit proof of work while {} or more challenges are pending", difficulty, pending_threshold)
+        }
+    }
+
+    println!(
+        "🧮 {} crypto threads, up to {} queued jobs",
+        auth_impl.crypto.threads(),
+        auth_impl.crypto.queue()
+    );
+
+    if let Some(path) = args.revocation_list {
+        let revocations = RevocationList::load(&path).expect("could not read the revocation list");
+        println!("🚫 {} revoked proofs in {}", revocations.revoked.len(), path.display());
+        auth_impl.revocations = Mutex::new(revocations);
+        auth_impl.revocation_path = Some(path);
+    }
+
+    // a KMS or Vault key wins over --params-key, which then only signs the parameters
+    #[cfg(feature = "kms")]
+    let kms_key = kms_key(&args.kms);
+    #[cfg(not(feature = "kms"))]
+    let kms_key: Option<()> = None;
+    let mut id_token_key = from_vault.id_token_key.clone();
+    let mut params_public_key = None;
+    if let Some(path) = &args.params_key {
+        let key = rust_zkp_chaum_pedersen::params::load_or_create_signing_key(path)
+            .expect("could not load the parameter signing key");
+        let zkp = ZKP::builder().rfc5114().build().expect("RFC 5114 parameters are valid");
+        auth_impl.parameters = Some(SignedParameters::sign(&zkp, &key));
+        auth_impl.parameters_key = Some(key.clone());
+        println!(
+            "🔏 Signing group parameters, clients should pin --server-key {}",
+            hex::encode(key.verifying_key().as_bytes())
+        );
+        params_public_key = Some(key.verifying_key());
+
+        if args.id_token_public_key.is_none() && id_token_key.is_none() && kms_key.is_none() {
+            id_token_key = Some(key);
+        }
+    }
+
+    println!("🔥 Group parameters decoded in {:?}", ZKP::warm_up());
+
+    // refuse to serve with math that gets the known answers wrong or a parameter bundle that doesn't check out
+    let started = Instant::now();
+    let signed = auth_impl.parameters.as_ref().zip(params_public_key.as_ref());
+    match selftest::run(&ZKP1024::rfc5114(), signed) {
+        Ok(checks) => println!("🩺 Self-test passed in {:?}: {}", started.elapsed(), checks.join(", ")),
+        Err(e) => panic!("{}, refusing to serve", e),
+    }
+
+    if let (Some(issuer), Some(key)) = (&args.id_token_issuer, id_token_key) {
+        let issuer = IdTokenIssuer::new(
+            key,
+            issuer.clone(),
+            args.id_token_audience.clone(),
+            Duration::from_secs(args.id_token_ttl),
+        );
+        println!("🪪 Minting ID tokens as {} for {:?}, JWK: {}", issuer.issuer(), args.id_token_audience, issuer.jwk());
+        auth_impl.id_tokens = Mutex::new(Some(IdTokens::Ready(issuer)));
+    }
+
+    #[cfg(feature = "kms")]
+    if let (Some(issuer), Some(key)) = (&args.id_token_issuer, kms_key.clone()) {
+        let signer = KmsSigner::connect(key).await.unwrap_or_else(|e| panic!("the ID token key in KMS: {}", e));
+        println!(
+            "🪪 Minting ID tokens as {} for {:?}, signed by {}, JWK: {}",
+            issuer,
+            args.id_token_audience,
+            signer.describe(),
+            id_token::jwk(signer.public_key())
+        );
+        auth_impl.id_tokens = Mutex::new(Some(IdTokens::Kms {
+            signer: Arc::new(signer),
+            issuer: issuer.clone(),
+            audiences: args.id_token_audience.clone(),
+            lifetime: Duration::from_secs(args.id_token_ttl),
+        }));
+    }
+
+    if let (Some(issuer), Some(public_key)) = (&args.id_token_issuer, &args.id_token_public_key) {
+        let public_key = rust_zkp_chaum_pedersen::params::parse_public_key(public_key).expect("invalid --id-token-public-key");
+        let mut id_tokens = IdTokens::Sealed {
+            issuer: issuer.clone(),
+            audiences: args.id_token_audience.clone(),
+            lifetime: Duration::from_secs(args.id_token_ttl),
+            public_key,
+            unsealer: Unsealer::new(),
+        };
+        for path in &args.id_token_key_share {
+            let share = std::fs::read_to_string(path)
+                .unwrap_or_else(|e| panic!("could not read the key share {}: {}", path.display(), e));
+            let share = share.parse().unwrap_or_else(|e| panic!("{}: {}", path.display(), e));
+            if let Err(status) = id_tokens.unseal(share) {
+                panic!("{}: {}", path.display(), status.message());
+            }
+        }
+        match &id_tokens {
+            IdTokens::Ready(issuer) => {
+                println!("🪪 Minting ID tokens as {} for {:?}, JWK: {}", issuer.issuer(), args.id_token_audience, issuer.jwk())
+            }
+            IdTokens::Sealed { unsealer, .. } => println!(
+                "🔒 ID token key sealed with {} shares, unseal it with `zkp-auth admin unseal`, JWK: {}",
+                unsealer.progress().0,
+                id_token::jwk(&public_key)
+            ),
+            #[cfg(feature = "kms")]
+            IdTokens::Kms { .. } => unreachable!("shares only ever unseal to a local key"),
+        }
+        auth_impl.id_tokens = Mutex::new(Some(id_tokens));
+    } else if args.id_token_issuer.is_some() && args.params_key.is_none() && from_vault.id_token_key.is_none() && kms_key.is_none() {
+        panic!("--id-token-issuer needs a key to sign with: --params-key, --vault-id-token-key, --kms-aws-key, --kms-gcp-key, or --id-token-public-key and its shares");
+    }
+
+    // grpc.health.v1 for k8s probes: "" answers liveness (the process is up),
+    // "zkp_auth.v1.Auth" answers readiness (we accept authentication traffic);
+    // the unversioned name keeps answering for probes configured before v1
+    let (mut health_reporter, health_service) = tonic_health::server::health_reporter();
+    health_reporter
+        .set_serving::<AuthServer<AuthImpl>>()
+        .await;
+    health_reporter
+        .set_service_status(api::LEGACY_SERVICE, tonic_health::ServingStatus::Serving)
+        .await;
+
+    // requests may come in gzip or zstd whatever --compression says about responses
+    let auth_impl = Arc::new(auth_impl);
+    let overrides = args.schedule.clone();
+    if let Some((job, _)) = overrides.iter().find(|(job, _)| !JOBS.contains(&job.as_str())) {
+        panic!("--schedule {}: there is no such job (one of {})", job, JOBS.join(", "));
+    }
+    let mut scheduler = Scheduler::default();
+    if let Some(path) = &args.leader_lease {
+        let lease = Lease::new(path, &Lease::process_id(), Duration::from_secs(args.leader_lease_ttl.max(3)));
+        let renew = Schedule::Every(lease.ttl() / 3);
+        lease::set(lease);
+        // --leader-lease-ttl sets this one, not --schedule
+        scheduler.add("leader", renew, leader_job());
+    }
+    scheduler.add("expiry", schedule_of(&overrides, "expiry", 60), expiry_job(auth_impl.clone()));
+    scheduler.add("memory", schedule_of(&overrides, "memory", 60), memory_job(auth_impl.clone()));
+    scheduler.add("crypto_load", schedule_of(&overrides, "crypto_load", 60), crypto_load_job(auth_impl.crypto.clone()));
+    scheduler.add("parameter_scan", schedule_of(&overrides, "parameter_scan", 3600), parameter_scan_job(auth_impl.clone()));
+    if auth_impl.federation.is_some() {
+        scheduler.add("federation", schedule_of(&overrides, "federation", args.federation_refresh), federation_job(auth_impl.clone()));
+    }
+    if auth_impl.read_replica {
+        scheduler.add("replica", schedule_of(&overrides, "replica", args.replica_refresh), replica_job(auth_impl.clone()));
+    } else if auth_impl.user_store.is_some() {
+        scheduler.add("user_store", schedule_of(&overrides, "user_store", args.user_store_retry), user_store_job(auth_impl.clone()));
+        // --user-store-flush-ms sets this one, not --schedule
+        if !flush.is_every_write() {
+            scheduler.add("user_store_flush", Schedule::Every(flush.interval), user_store_flush_job(auth_impl.clone()));
+        }
+    }
+    let retention = RetentionPolicy { audit: args.audit_retention.clone(), sessions: args.session_retention };
+    if !retention.is_empty() {
+        let mut kept: Vec<String> = retention.audit.iter().map(|rule| format!("audit {}", rule)).collect();
+        kept.extend(retention.sessions.map(|age| format!("sessions {}", retention::format_age(age))));
+        println!("🗑️  Retention: {}", kept.join(", "));
+        let schedule = schedule_of(&overrides, "retention", args.retention_interval);
+        scheduler.add("retention", schedule, retention_job(auth_impl.clone(), retention));
+    }
+    let admin_service = admin_token.clone().map(|token| {
+        println!("🛡️  Serving the Admin service");
+        let admin = AdminServer::new(Validated(auth_impl.clone()));
+        InterceptedService::new(admin, token)
+    });
+    let tls = match (args.tls_cert, args.tls_key, &from_vault.tls) {
+        (Some(cert_path), Some(key_path), _) => {
+            let reloader = Arc::new(
+                CertReloader::load(&cert_path, &key_path).expect("could not load TLS certificate"),
+            );
+            println!("🔒 TLS enabled with {}", cert_path.display());
+            let schedule = schedule_of(&overrides, "tls_reload", args.tls_reload_interval);
+            scheduler.add("tls_reload", schedule, tls_reload_job(reloader.clone()));
+            Some(reloader)
+        }
+        (_, _, Some((cert, key))) => {
+            let reloader = Arc::new(CertReloader::from_pem(cert, key).unwrap_or_else(|e| panic!("the TLS certificate in Vault: {}", e)));
+            println!("🔒 TLS enabled with the certificate from Vault");
+            Some(reloader)
+        }
+        _ => None,
+    };
+
+    #[cfg(feature = "webhooks")]
+    if let Some(delivery) = event_delivery {
+        scheduler.add("events", schedule_of(&overrides, "events", args.webhook.interval), delivery);
+    }
+    #[cfg(feature = "vault")]
+    if let Some(client) = vault_client {
+        let issuer = args.id_token_issuer.clone().map(|issuer| (issuer, args.id_token_audience.clone(), Duration::from_secs(args.id_token_ttl)));
+        let watched = WatchedSecrets { tls: tls.clone(), admin_token: admin_token.clone(), auth: auth_impl.clone(), issuer };
+        let schedule = schedule_of(&overrides, "vault", args.vault.refresh);
+        scheduler.add("vault", schedule, VaultWatch { client, args: args.vault, current: from_vault, watched });
+    }
+    let jobs: Vec<String> = scheduler.jobs().map(|(job, schedule)| format!("{} {}", job, schedule)).collect();
+    println!("⏰ Background jobs: {}", jobs.join(", "));
+    scheduler.start();
+
+    let ip_filter = IpFilter {
+        allow: args.allow_from.clone(),
+        deny: args.deny_from.clone(),
+        geoip: args.geoip_db.as_ref().map(|path| {
+            let geoip = GeoIp::open(path).unwrap_or_else(|e| panic!("could not read the GeoIP database {}: {}", path.display(), e));
+            println!("🌍 Looking up countries in {} ({})", path.display(), geoip.database_type);
+            geoip
+        }),
+    };
+    if ip_filter.needs_geoip() && ip_filter.geoip.is_none() {
+        panic!("country: sources in --allow-from or --deny-from need --geoip-db");
+    }
+    if !ip_filter.is_empty() {
+        let rules = |rules: &[Rule], none: &str| match rules.is_empty() {
+            true => none.to_string(),
+            false => rules.iter().map(Rule::to_string).collect::<Vec<_>>().join(" "),
+        };
+        println!("🧱 Serving requests from {}, refusing {}", rules(&ip_filter.allow, "anywhere"), rules(&ip_filter.deny, "none"));
+    }
+    let auditor = auth_impl.clone();
+    let on_blocked: OnBlocked = Arc::new(move |blocked: &Blocked, headers: &tonic::codegen::http::HeaderMap| {
+        let trace = headers
+            .get("traceparent")
+            .and_then(|value| value.to_str().ok())
+            .and_then(trace::TraceContext::parse)
+            .unwrap_or_else(trace::TraceContext::new_root);
+        let source = blocked.source.map(|addr| addr.to_string()).unwrap_or_default();
+        let country = blocked.country.as_ref().map(|code| format!(" (country {})", code)).unwrap_or_default();
+        let detail = format!("{}{}: {}", blocked.rpc, country, blocked.reason);
+        auditor.audit("blocked_request", Outcome::Failure, "", &source, &trace, detail);
+    });
+
+    let mut auth_service = AuthServer::new(Validated(auth_impl));
+    for encoding in compression::ACCEPTED {
+        auth_service = auth_service.accept_compressed(encoding);
+    }
+    if let Some(encoding) = args.compression.encoding() {
+        println!("🗜️  Compressing responses with {}", args.compression);
+        auth_service = auth_service.send_compressed(encoding);
+    }
+
+    // clients from before the versioned API still call zkp_auth.Auth;
+    // the timeout drops handlers (and with them pending crypto work) of abandoned requests
+    let router = Server::builder()
+        .timeout(Duration::from_secs(args.request_timeout))
+        .layer(tower::util::MapRequestLayer::new(api::upgrade_legacy_path))
+        .layer(IpFilterLayer::new(ip_filter, on_blocked))
+        .layer(CatchPanicLayer)
+        .add_service(health_service)
+        .add_service(InterceptedService::new(auth_service, trace::server_interceptor))
+        .add_optional_service(admin_service);
+
+    #[cfg(feature = "acme")]
+    if !args.acme.domains.is_empty() {
+        let listener = tokio::net::TcpListener::bind(&addr)
+            .await
+            .expect("could not bind address");
+        router
+            .serve_with_incoming(acme_incoming(listener, args.acme))
+            .await
+            .unwrap();
+        return;
+    }
+
+    match tls {
+        Some(reloader) => {
+            let listener = tokio::net::TcpListener::bind(&addr)
+                .await
+                .expect("could not bind address");
+            router
+                .serve_with_incoming(rust_zkp_chaum_pedersen::tls::incoming(
+                    listener,
+                    reloader.server_config(),
+                ))
+                .await
+                .unwrap();
+        }
+        None => {
+            router
+                .serve(addr.parse().expect("could not convert address"))
+                .await
+                .unwrap();
+        }
+    }
+}
+
+/// Every background job, for --schedule
+const JOBS: [&str; 11] = ["expiry", "memory", "crypto_load", "parameter_scan", "user_store", "replica", "retention", "tls_reload", "vault", "federation", "events"];
+
+/// `JOB=SCHEDULE` for --schedule
+fn parse_schedule(text: &str) -> Result<(String, Schedule), String> {
+    let (job, schedule) = text.split_once('=').ok_or_else(|| format!("{:?} is not JOB=SCHEDULE", text))?;
+    Ok((job.trim().to_string(), schedule.parse().map_err(|e: ScheduleError| e.to_string())?))
+}
+
+/// a share between 0 and 1 for --log-sample-rate
+fn parse_sample_rate(text: &str) -> Result<f64, String> {
+    match text.parse::<f64>() {
+        Ok(rate) if (0.0..=1.0).contains(&rate) => Ok(rate),
+        _ => Err(format!("{:?} is not a number between 0 and 1", text)),
+    }
+}
+
+/// the --schedule given for `job` (the last one wins), or every `default_secs`
+fn schedule_of(overrides: &[(String, Schedule)], job: &str, default_secs: u64) -> Schedule {
+    overrides
+        .iter()
+        .rev()
+        .find(|(name, _)| name == job)
+        .map(|(_, schedule)| schedule.clone())
+        .unwrap_or(Schedule::Every(Duration::from_secs(default_secs.max(1))))
+}
+
+/// drop expired challenges, device logins and idempotency keys, also when no new ones come in to sweep them out
+fn expiry_job(auth_impl: Arc<AuthImpl>) -> impl Job {
+    move || {
+        let now = auth_impl.time.now();
+        auth_impl.expire_challenges(now);
+        auth_impl.registrations.expire();
+        if let Some(device_logins) = &auth_impl.device_logins {
+            device_logins.expire(now);
+        }
+        async {}
+    }
+}
+
+/// log the crypto pool's counters whenever something changed, so saturation shows up in the logs
+fn crypto_load_job(pool: Arc<CryptoPool>) -> impl Job {
+    let mut last = pool.stats();
+    move || {
+        let stats = pool.stats();
+        if stats != last {
+            let (finished, panicked) = (stats.completed - last.completed, stats.panicked - last.panicked);
+            let waited = stats.total_wait - last.total_wait;
+            println!(
+                "🧮 Crypto pool: {} queued, {} running, {} done (avg wait {:?}), {} rejected, {} cancelled, {} panicked",
+                stats.queued,
+                stats.running,
+                finished,
+                waited.checked_div((finished + panicked) as u32).unwrap_or_default(),
+                stats.rejected - last.rejected,
+                stats.cancelled - last.cancelled,
+                panicked,
+            );
+            last = stats;
+        }
+        async {}
+    }
+}
+
+/// retry degraded user store shards until they can be written again
+fn user_store_job(auth_impl: Arc<AuthImpl>) -> impl Job {
+    move || {
+        if let Some(sharded) = &auth_impl.user_store {
+            // an error means it's still down, which was logged when it failed
+            for store in sharded.shards() {
+                if let Ok(Some(users)) = store.recover() {
+                    let loaded = auth_impl.load_users(users);
+                    println!("✅ User store {} is back, registrations resume ({} users loaded)", store.path().display(), loaded);
+                }
+            }
+            report_shards(sharded);
+        }
+        async {}
+    }
+}
+
+/// `store`, logging its operations that take `slow_ms` or longer (--slow-store-ms)
+fn slow_log(store: UserStore, slow_ms: u64) -> UserStore {
+    match slow_ms {
+        0 => store,
+        ms => store.with_slow_log(Duration::from_millis(ms)),
+    }
+}
+
+/// sync the user store writes that wait for it (--user-store-flush-ms)
+fn user_store_flush_job(auth_impl: Arc<AuthImpl>) -> impl Job {
+    move || {
+        if let Some(sharded) = &auth_impl.user_store {
+            for store in sharded.shards() {
+                if store.degraded().is_some() {
+                    continue;
+                }
+                if let Err(e) = store.flush() {
+                    println!("⚠️  User store {} failed, pausing registrations: {}", store.path().display(), e);
+                }
+            }
+            report_shards(sharded);
+        }
+        async {}
+    }
+}
+
+/// publish which user store shards can be written
+fn report_shards(sharded: &ShardedStore) {
+    let health = sharded.shards().iter().map(|store| (store.path().display().to_string(), store.degraded().is_none())).collect();
+    metrics::shards().set(health);
+}
+
+/// fetch the federation peers again; one that can't be reached keeps its last good copy
+fn federation_job(auth_impl: Arc<AuthImpl>) -> impl Job {
+    move || {
+        let auth_impl = auth_impl.clone();
+        async move {
+            let Some(federation) = &auth_impl.federation else {
+                return;
+            };
+            for (endpoint, outcome) in federation.refresh(SystemTime::now()).await {
+                match outcome {
+                    Ok(issuer) => println!("🌐 Fetched federation peer {} ({})", endpoint, issuer),
+                    Err(e) => println!("❌ Could not fetch federation peer {}, keeping the last copy: {}", endpoint, e),
+                }
+            }
+        }
+    }
+}
+
+/// The outbox and where its events go
+#[cfg(feature = "webhooks")]
+struct EventDelivery {
+    outbox: Arc<Outbox>,
+    uri: hyper::Uri,
+    tls: Option<Arc<tokio_rustls::rustls::ClientConfig>>, // for https webhooks
+}
+
+/// POST the events that are due to the webhook
+#[cfg(feature = "webhooks")]
+impl Job for EventDelivery {
+    async fn run(&mut self) {
+        let EventDelivery { outbox, uri, tls } = self;
+        let send = |body: String| {
+            let request = hyper::Request::post(uri.clone())
+                .header(hyper::header::CONTENT_TYPE, "application/json")
+                .body(hyper::Body::from(body))
+                .expect("a parsed URI and static headers are valid");
+            let tls = tls.clone();
+            async move {
+                match http::exchange(request, tls.as_ref()).await? {
+                    (status, _) if status.is_success() => Ok(()),
+                    (status, _) => Err(format!("the webhook answered {}", status)),
+                }
+            }
+        };
+        match outbox.deliver(SystemTime::now(), send).await {
+            Ok(delivery) => {
+                if delivery.dead > 0 {
+                    println!("⚠️  Gave up on {} audit events, they are in {}", delivery.dead, outbox.dead_letters().display());
+                }
+                if delivery.retrying > 0 {
+                    println!("❌ The webhook didn't take {} audit events, trying again later", delivery.retrying);
+                }
+            }
+            Err(e) => println!("❌ Could not update the event outbox {}: {}", outbox.path().display(), e),
+        }
+    }
+}
+
+/// take in what the primary wrote to the user store a read replica follows
+fn replica_job(auth_impl: Arc<AuthImpl>) -> impl Job {
+    move || {
+        auth_impl.follow_user_store();
+        async {}
+    }
+}
+
+/// take or renew the --leader-lease, saying so when leadership changes
+fn leader_job() -> impl Job {
+    let mut leading = None;
+    move || {
+        let lease = lease::get().expect("the leader job runs with a lease");
+        let now = SystemTime::now();
+        let won = lease.renew(now).unwrap_or_else(|e| {
+            println!("⚠️  Could not renew the leader lease: {}", e);
+            false
+        });
+        if leading != Some(won) {
+            match (won, lease.holder()) {
+                (true, _) => println!("👑 Leader: {} holds the lease", lease.id()),
+                (false, Ok(Some(current))) => println!("👑 Leader: {} holds the lease, {} follows", current.holder, lease.id()),
+                (false, _) => println!("👑 Leader: nobody holds the lease, {} follows", lease.id()),
+            }
+            leading = Some(won);
+        }
+        async {}
+    }
+}
+
+/// purge what the retention policy no longer keeps
+fn retention_job(auth_impl: Arc<AuthImpl>, policy: RetentionPolicy) -> impl Job {
+    move || {
+        let now = auth_impl.time.now();
+        let sessions = auth_impl.purge_sessions(&policy, now);
+        // sessions are this server's own, a shared audit log is the leader's to purge
+        let audit_events = match lease::is_leader() {
+            true => auth_impl.audit.purge(&policy, now).unwrap_or_else(|e| {
+                // the next run tries again
+                println!("⚠️  Could not purge the audit log: {}", e);
+                0
+            }),
+            false => 0,
+        };
+        metrics::retention().record(now, &[("audit_events", audit_events), ("sessions", sessions)]);
+        if sessions + audit_events > 0 {
+            println!("🗑️  Retention: purged {} sessions and {} audit events", sessions, audit_events);
+        }
+        async {}
+    }
+}
+
+/// log what the in-memory stores take when it changes, and keep /metrics current
+fn memory_job(auth_impl: Arc<AuthImpl>) -> impl Job {
+    let mut last = Vec::new();
+    move || {
+        let usage = auth_impl.memory_usage();
+        for (store, store_usage) in &usage {
+            metrics::stores().set(store, *store_usage);
+        }
+        if usage != last {
+            let summary: Vec<String> = usage
+                .iter()
+                .map(|(store, store_usage)| format!("{} {} ({})", store, store_usage.entries, memory::format_bytes(store_usage.bytes)))
+                .collect();
+            println!("🧠 Memory: {}", summary.join(", "));
+            last = usage;
+        }
+        async {}
+    }
+}
+
+/// count users by the parameters they registered with, to follow migrations
+/// off the legacy password derivation and the global generator
+fn parameter_scan_job(auth_impl: Arc<AuthImpl>) -> impl Job {
+    let mut last = BTreeMap::new();
+    move || {
+        let counts = auth_impl.user_parameters();
+        if counts != last {
+            let users = |matches: fn(&UserParameters) -> bool| -> usize {
+                counts.iter().filter(|(parameters, _)| matches(parameters)).map(|(_, count)| count).sum()
+            };
+            println!(
+                "🧭 Parameters: {} users, {} on the legacy password derivation, {} on the global generator",
+                users(|_| true),
+                users(|parameters| parameters.kdf == "legacy"),
+                users(|parameters| parameters.generator == "global"),
+            );
+            last = counts.clone();
+        }
+        metrics::users().set(counts);
+        async {}
+    }
+}
+
+/// poll the certificate files so renewals (cert-manager, ACME) are picked up without a restart
+fn tls_reload_job(reloader: Arc<CertReloader>) -> impl Job {
+    move || {
+        match reloader.reload_if_changed() {
+            Ok(true) => println!("🔒 Reloaded TLS certificate"),
+            Ok(false) => {}
+            // half-written files during a renewal: keep the old certificate, retry next run
+            Err(e) => println!("❌ Could not reload TLS certificate, keeping the old one: {}", e),
+        }
+        async {}
+    }
+}
+
+/// a client for --vault-addr (or VAULT_ADDR) when any secret is to come from Vault;
+/// checks the token right away so a bad one stops the server at startup
+/// the KMS key behind --kms-aws-key or --kms-gcp-key, filled in from the environment
+#[cfg(feature = "kms")]
+fn kms_key(args: &KmsArgs) -> Option<KmsKey> {
+    let env = |name: &str| std::env::var(name).ok().filter(|value| !value.is_empty());
+    if let Some(key_id) = &args.aws_key {
+        let region = args.aws_region.clone().or_else(|| env("AWS_REGION")).or_else(|| env("AWS_DEFAULT_REGION"));
+        return Some(KmsKey::Aws {
+            key_id: key_id.clone(),
+            region: region.unwrap_or_else(|| panic!("--kms-aws-key needs a region: --kms-aws-region or AWS_REGION")),
+            endpoint: args.endpoint.clone().or_else(|| env("AWS_ENDPOINT_URL_KMS")).or_else(|| env("AWS_ENDPOINT_URL")),
+            credentials: AwsCredentials::from_env().unwrap_or_else(|e| panic!("--kms-aws-key: {}", e)),
+        });
+    }
+    let key_version = args.gcp_key.clone()?;
+    let token = args.gcp_token_file.clone().map_or(GcpToken::Metadata, GcpToken::File);
+    Some(KmsKey::Gcp { key_version, endpoint: args.endpoint.clone(), token })
+}
+
+#[cfg(feature = "vault")]
+async fn connect_vault(args: &VaultArgs) -> Option<Arc<VaultClient>> {
+    if args.tls.is_none() && args.id_token_key.is_none() && args.admin_token.is_none() && args.store_key.is_none() {
+        return None;
+    }
+    let token = args.token_file.as_ref().map(|path| {
+        std::fs::read_to_string(path).unwrap_or_else(|e| panic!("could not read the Vault token {}: {}", path.display(), e))
+    });
+    let client = VaultClient::from_env(args.addr.as_deref(), token.as_deref()).unwrap_or_else(|e| panic!("{}", e));
+    let lease = client.lookup_self().await.unwrap_or_else(|e| panic!("{}", e));
+    match lease.ttl.is_zero() {
+        true => println!("🗝️  Reading secrets from Vault at {}", client.addr()),
+        false => println!("🗝️  Reading secrets from Vault at {} (token valid for {:?})", client.addr(), lease.ttl),
+    }
+    Some(Arc::new(client))
+}
+
+/// read every secret the server was told to take from Vault
+#[cfg(feature = "vault")]
+async fn read_vault(client: &VaultClient, args: &VaultArgs) -> Result<VaultSecrets, VaultError> {
+    let kv = |secret: &SecretRef| match secret {
+        SecretRef::Kv { mount, path } => Ok((mount.clone(), path.clone())),
+        SecretRef::Transit { .. } => Err(VaultError::Malformed(format!("{} has to be a kv: secret", secret))),
+    };
+    let mut secrets = VaultSecrets::default();
+    if let Some(secret) = &args.tls {
+        let (mount, path) = kv(secret)?;
+        let mut fields = client.kv(&mount, &path).await?;
+        let (Some(cert), Some(key)) = (fields.remove("cert"), fields.remove("key")) else {
+            return Err(VaultError::Missing(format!("{} needs `cert` and `key` fields", secret)));
+        };
+        secrets.tls = Some((cert, key));
+    }
+    if let Some(secret) = &args.id_token_key {
+        secrets.id_token_key = Some(client.signing_key(secret).await?);
+    }
+    if let Some(secret) = &args.admin_token {
+        let (mount, path) = kv(secret)?;
+        secrets.admin_token = Some(client.kv_field(&mount, &path, "token").await?);
+    }
+    if let Some(secret) = &args.store_key {
+        let (mount, path) = kv(secret)?;
+        secrets.user_store_key = Some(client.kv_field(&mount, &path, "key").await?);
+    }
+    Ok(secrets)
+}
+
+/// What rotated secrets are installed into
+#[cfg(feature = "vault")]
+struct WatchedSecrets {
+    tls: Option<Arc<CertReloader>>,
+    admin_token: Option<SharedAdminToken>,
+    auth: Arc<AuthImpl>,
+    issuer: Option<(String, Vec<String>, Duration)>, // to mint with a rotated key: iss, audiences, lifetime
+}
+
+/// renews the Vault token and picks up rotated secrets every --vault-refresh seconds;
+/// when Vault is unreachable the secrets in use stay until it is back
+#[cfg(feature = "vault")]
+struct VaultWatch {
+    client: Arc<VaultClient>,
+    args: VaultArgs,
+    current: VaultSecrets,
+    watched: WatchedSecrets,
+}
+
+#[cfg(feature = "vault")]
+impl Job for VaultWatch {
+    async fn run(&mut self) {
+        let VaultWatch { client, args, current, watched } = self;
+        let every = Duration::from_secs(args.refresh.max(1));
+        if let Some(path) = &args.token_file {
+            match std::fs::read_to_string(path) {
+                Ok(token) => client.set_token(&token),
+                Err(e) => println!("❌ Could not re-read the Vault token {}: {}", path.display(), e),
+            }
+        }
+        match client.lookup_self().await {
+            Ok(lease) if lease.renewable && !lease.ttl.is_zero() => match client.renew_self().await {
+                Ok(lease) if lease.ttl < every * 2 => {
+                    println!("⚠️  The Vault token only lives {:?} more, shorter than two refreshes", lease.ttl)
+                }
+                Ok(_) => {}
+                Err(e) => println!("❌ Could not renew the Vault token: {}", e),
+            },
+            Ok(_) => {}
+            Err(e) => println!("❌ Could not check the Vault token: {}", e),
+        }
+
+        let fresh = match read_vault(client, args).await {
+            Ok(fresh) => fresh,
+            Err(e) => {
+                println!("❌ Could not re-read the secrets from Vault, keeping the old ones: {}", e);
+                return;
+            }
+        };
+        if let (Some(reloader), Some((cert, key))) = (&watched.tls, &fresh.tls) {
+            match reloader.install_pem(cert, key) {
+                Ok(true) => println!("🔒 Installed the rotated TLS certificate from Vault"),
+                Ok(false) => {}
+                Err(e) => println!("❌ The TLS certificate in Vault is unusable, keeping the old one: {}", e),
+            }
+        }
+        if let (Some(lock), Some(token)) = (&watched.admin_token, &fresh.admin_token) {
+            if current.admin_token.as_ref() != Some(token) {
+                match AdminToken::new(token) {
+                    Ok(token) => {
+                        *lock.0.write().unwrap() = token;
+                        println!("🛡️  Switched to the rotated admin token from Vault");
+                    }
+                    Err(e) => println!("❌ The admin token in Vault is unusable, keeping the old one: {}", e),
+                }
+            }
+        }
+        if let (Some((issuer, audiences, lifetime)), Some(key)) = (&watched.issuer, &fresh.id_token_key) {
+            if current.id_token_key.as_ref().map(SigningKey::to_bytes) != Some(key.to_bytes()) {
+                let issuer = IdTokenIssuer::new(key.clone(), issuer.clone(), audiences.clone(), *lifetime);
+                println!("🪪 Minting ID tokens with the rotated key from Vault, JWK: {}", issuer.jwk());
+                *watched.auth.id_tokens.locked() = Some(IdTokens::Ready(issuer));
+            }
+        }
+        if current.user_store_key != fresh.user_store_key {
+            println!("⚠️  The user store key changed in Vault; it is only read at startup, keeping the old one");
+        }
+        *current = VaultSecrets { user_store_key: current.user_store_key.take(), ..fresh };
+    }
+}
+
+/// TLS with certificates obtained and renewed through ACME tls-alpn-01
+#[cfg(feature = "acme")]
+fn acme_incoming(
+    listener: tokio::net::TcpListener,
+    acme: AcmeArgs,
+) -> impl tokio_stream::Stream<
+    Item = std::io::Result<tokio_rustls::server::TlsStream<tokio::net::TcpStream>>,
+> {
+    use rust_zkp_chaum_pedersen::tls::{grpc_server_config, incoming_with, Handshake};
+    use rustls_acme::{caches::DirCache, is_tls_alpn_challenge, AcmeConfig};
+    use tokio_stream::StreamExt;
+
+    println!(
+        "🔒 Requesting certificates for {:?} from Let's Encrypt ({})",
+        acme.domains,
+        if acme.production { "production" } else { "staging" }
+    );
+
+    let mut state = AcmeConfig::new(acme.domains)
+        .contact(acme.contacts.iter().map(|e| format!("mailto:{}", e)))
+        .cache_option(acme.cache.map(DirCache::new))
+        .directory_lets_encrypt(acme.production)
+        .state();
+    let challenge_config = state.challenge_rustls_config();
+    let grpc_config = grpc_server_config(state.resolver());
+
+    // the state machine orders, renews and caches certificates as it is polled
+    tokio::spawn(async move {
+        while let Some(event) = state.next().await {
+            match event {
+                Ok(event) => println!("🔒 ACME: {:?}", event),
+                Err(e) => println!("❌ ACME error: {:?}", e),
+            }
+        }
+    });
+
+    incoming_with(listener, move |hello| {
+        if is_tls_alpn_challenge(hello) {
+            Handshake::Answer(challenge_config.clone())
+        } else {
+            Handshake::Serve(grpc_config.clone())
+        }
+    })
+}
@@ -0,0 +1,240 @@
+//! Shamir secret sharing of the ID token signing key.
+//!
+//! [`split`] cuts a secret into n shares so that any t of them give it back
+//! and fewer say nothing about it; the server can then start with only some
+//! shares (`--id-token-key-share`) and wait for key holders to send the rest
+//! with the Admin service's `UnsealIdTokenKey`, as with Vault's unseal keys,
+//! so no operator and no file holds the whole key. Shares are split byte by
+//! byte over GF(2^8). A share is written as hex of
+//! `split id (4) | threshold | index | value`; the split id keeps shares of
+//! different splits from being mixed.
+
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::{params, rng};
+
+/// One share of a secret
+#[derive(Clone, PartialEq, Eq)]
+pub struct Share {
+    pub split_id: [u8; 4], // random, the same for every share of one split
+    pub threshold: u8,     // how many shares give the secret back
+    pub index: u8,         // x, never 0
+    pub value: Vec<u8>,    // f(x), one byte per secret byte
+}
+
+// a share is a piece of a key, keep it out of logs
+impl fmt::Debug for Share {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Share({}, {} of {}, ..)", hex::encode(self.split_id), self.index, self.threshold)
+    }
+}
+
+/// Why shares were refused
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ShamirError {
+    /// t must be at least 2 and at most n
+    BadThreshold { threshold: u8, shares: u8 },
+    TooFewShares { have: usize, need: usize },
+    /// shares from different splits, or of different lengths
+    Mismatched,
+    DuplicateIndex(u8),
+    Malformed(String),
+}
+
+impl fmt::Display for ShamirError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ShamirError::BadThreshold { threshold, shares } => {
+                write!(f, "a threshold of {} doesn't work with {} shares (need 2 <= threshold <= shares)", threshold, shares)
+            }
+            ShamirError::TooFewShares { have, need } => write!(f, "{} of {} shares", have, need),
+            ShamirError::Mismatched => write!(f, "the shares are from different splits"),
+            ShamirError::DuplicateIndex(index) => write!(f, "share {} was already given", index),
+            ShamirError::Malformed(reason) => write!(f, "malformed share: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for ShamirError {}
+
+/// cut `secret` into `shares` shares, any `threshold` of which give it back
+pub fn split(secret: &[u8], threshold: u8, shares: u8) -> Result<Vec<Share>, ShamirError> {
+    if threshold < 2 || threshold > shares {
+        return Err(ShamirError::BadThreshold { threshold, shares });
+    }
+    let mut split_id = [0u8; 4];
+    // f(x) = secret + a1 x + ... + a(t-1) x^(t-1), one polynomial per byte
+    let mut coefficients = vec![0u8; secret.len() * (threshold as usize - 1)];
+    rng::with_rng(|rng| {
+        rng.fill_bytes(&mut split_id);
+        rng.fill_bytes(&mut coefficients);
+    });
+    let shares = (1..=shares)
+        .map(|x| {
+            let value = secret
+                .iter()
+                .zip(coefficients.chunks(threshold as usize - 1))
+                .map(|(&constant, higher)| {
+                    let higher = higher.iter().rev().fold(0, |acc, &a| mul(acc, x) ^ a);
+                    mul(higher, x) ^ constant
+                })
+                .collect();
+            Share { split_id, threshold, index: x, value }
+        })
+        .collect();
+    coefficients.fill(0);
+    Ok(shares)
+}
+
+/// the secret behind `shares`; needs at least the threshold, and uses that many
+pub fn combine(shares: &[Share]) -> Result<Vec<u8>, ShamirError> {
+    let Some(first) = shares.first() else {
+        return Err(ShamirError::TooFewShares { have: 0, need: 2 });
+    };
+    let need = first.threshold as usize;
+    for (i, share) in shares.iter().enumerate() {
+        check(share, &shares[..i])?;
+    }
+    if shares.len() < need {
+        return Err(ShamirError::TooFewShares { have: shares.len(), need });
+    }
+    let shares = &shares[..need];
+
+    // Lagrange interpolation at 0: sum of y_i * prod_{j != i} x_j / (x_j - x_i)
+    let mut secret = vec![0u8; first.value.len()];
+    for (i, share) in shares.iter().enumerate() {
+        let basis = shares
+            .iter()
+            .enumerate()
+            .filter(|(j, _)| *j != i)
+            .fold(1, |acc, (_, other)| mul(acc, mul(other.index, inverse(other.index ^ share.index))));
+        for (byte, &y) in secret.iter_mut().zip(&share.value) {
+            *byte ^= mul(basis, y);
+        }
+    }
+    Ok(secret)
+}
+
+/// `share` fits with `others`: same split, same length, an index of its own
+fn check(share: &Share, others: &[Share]) -> Result<(), ShamirError> {
+    if share.index == 0 || share.threshold < 2 {
+        return Err(ShamirError::Malformed("index 0 or a threshold below 2".to_string()));
+    }
+    for other in others {
+        if other.split_id != share.split_id || other.threshold != share.threshold || other.value.len() != share.value.len() {
+            return Err(ShamirError::Mismatched);
+        }
+        if other.index == share.index {
+            return Err(ShamirError::DuplicateIndex(other.index));
+        }
+    }
+    Ok(())
+}
+
+impl fmt::Display for Share {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{:02x}{:02x}{}", hex::encode(self.split_id), self.threshold, self.index, hex::encode(&self.value))
+    }
+}
+
+impl FromStr for Share {
+    type Err = ShamirError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bytes = hex::decode(s.trim()).map_err(|e| ShamirError::Malformed(e.to_string()))?;
+        if bytes.len() < 7 {
+            return Err(ShamirError::Malformed("too short".to_string()));
+        }
+        let share = Share {
+            split_id: bytes[..4].try_into().unwrap(),
+            threshold: bytes[4],
+            index: bytes[5],
+            value: bytes[6..].to_vec(),
+        };
+        check(&share, &[])?;
+        Ok(share)
+    }
+}
+
+/// write each share to `dir/share-<index>.txt`, readable only by the owner,
+/// to be handed to a different key holder each
+pub fn write_shares(dir: &Path, shares: &[Share]) -> io::Result<Vec<PathBuf>> {
+    std::fs::create_dir_all(dir)?;
+    shares
+        .iter()
+        .map(|share| {
+            let path = dir.join(format!("share-{}.txt", share.index));
+            params::write_private(&path, format!("{}\n", share).as_bytes())?;
+            Ok(path)
+        })
+        .collect()
+}
+
+/// Shares collected one at a time until there are enough
+#[derive(Debug, Default)]
+pub struct Unsealer {
+    shares: Vec<Share>,
+}
+
+impl Unsealer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// take one more share; the secret once this was the last one needed.
+    /// The shares are dropped then, so a wrong secret means starting over
+    pub fn add(&mut self, share: Share) -> Result<Option<Vec<u8>>, ShamirError> {
+        check(&share, &self.shares)?;
+        self.shares.push(share);
+        if self.shares.len() < self.shares[0].threshold as usize {
+            return Ok(None);
+        }
+        let secret = combine(&self.shares);
+        self.reset();
+        secret.map(Some)
+    }
+
+    /// (shares given, shares needed); 0 needed before the first share says how many
+    pub fn progress(&self) -> (usize, usize) {
+        (self.shares.len(), self.shares.first().map_or(0, |share| share.threshold as usize))
+    }
+
+    /// forget the shares given so far
+    pub fn reset(&mut self) {
+        for share in &mut self.shares {
+            share.value.fill(0);
+        }
+        self.shares.clear();
+    }
+}
+
+/// multiplication in GF(2^8) mod x^8 + x^4 + x^3 + x + 1, without
+/// branches or tables that depend on the secret
+fn mul(mut a: u8, mut b: u8) -> u8 {
+    let mut product = 0;
+    for _ in 0..8 {
+        product ^= a & (b & 1).wrapping_neg();
+        let carry = (a >> 7).wrapping_neg();
+        a = (a << 1) ^ (0x1b & carry);
+        b >>= 1;
+    }
+    product
+}
+
+/// a^-1 = a^254
+fn inverse(a: u8) -> u8 {
+    let a2 = mul(a, a);
+    let a3 = mul(a2, a);
+    let a6 = mul(a3, a3);
+    let a12 = mul(a6, a6);
+    let a15 = mul(a12, a3);
+    let a30 = mul(a15, a15);
+    let a60 = mul(a30, a30);
+    let a63 = mul(a60, a3);
+    let a126 = mul(a63, a63);
+    let a127 = mul(a126, a);
+    mul(a127, a127)
+}
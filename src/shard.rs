@@ -0,0 +1,180 @@
+//! Users spread over several user store journals by consistent hashing.
+//!
+//! For user bases too big for one journal, `--user-store` can be given more
+//! than once. Every shard is placed on a hash ring at [`VNODES`] points
+//! derived from its path, and a user lives on the shard owning the first
+//! point at or after SHA-256 of their canonical id. Adding a shard moves only
+//! the users whose points it takes over, about 1/n of them, instead of
+//! reshuffling everyone.
+//!
+//! Each shard keeps its own health: a journal that can't be written turns
+//! degraded on its own (see [`crate::store`]), which pauses registrations and
+//! other writes for its users only, and is retried in the background. Users
+//! found on a shard that isn't theirs, after shards were added or removed,
+//! are moved home when the server starts ([`ShardedStore::rebalance`]); until
+//! a move succeeds their writes go where they are.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+
+use sha2::{Digest, Sha256};
+
+use crate::catch_panic::LockExt;
+use crate::store::{StoredUser, UserStore};
+
+/// Points per shard on the ring; more points even out the shards' shares
+pub const VNODES: usize = 128;
+
+/// Shards placed on a hash ring
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HashRing {
+    points: Vec<(u64, usize)>, // (point, shard), sorted
+}
+
+impl HashRing {
+    /// a ring of the shards named `names`, in that order
+    pub fn new(names: &[String]) -> Self {
+        let mut points: Vec<(u64, usize)> = names
+            .iter()
+            .enumerate()
+            .flat_map(|(shard, name)| (0..VNODES).map(move |vnode| (point(&format!("{}#{}", name, vnode)), shard)))
+            .collect();
+        points.sort_unstable();
+        HashRing { points }
+    }
+
+    /// the shard `key` lives on
+    pub fn shard_of(&self, key: &str) -> usize {
+        let at = point(key);
+        let next = self.points.partition_point(|&(point, _)| point < at);
+        self.points.get(next).or(self.points.first()).map(|&(_, shard)| shard).unwrap_or(0)
+    }
+}
+
+fn point(key: &str) -> u64 {
+    let digest = Sha256::digest(key.as_bytes());
+    u64::from_be_bytes(digest[..8].try_into().expect("sha-256 is 32 bytes"))
+}
+
+/// The user store journals and which user lives on which
+#[derive(Debug)]
+pub struct ShardedStore {
+    shards: Vec<UserStore>,
+    ring: HashRing,
+    // users found away from their shard, and where they are until they are moved
+    away: Mutex<HashMap<String, usize>>,
+}
+
+impl ShardedStore {
+    /// shards placed on the ring by their paths
+    pub fn new(shards: Vec<UserStore>) -> Self {
+        let names: Vec<String> = shards.iter().map(|store| store.path().display().to_string()).collect();
+        ShardedStore { ring: HashRing::new(&names), shards, away: Mutex::new(HashMap::new()) }
+    }
+
+    pub fn shards(&self) -> &[UserStore] {
+        &self.shards
+    }
+
+    /// the shard `user` belongs on
+    pub fn shard_of(&self, user: &str) -> usize {
+        self.ring.shard_of(user)
+    }
+
+    /// the shard `user` is on: theirs unless they are still waiting to be moved
+    pub fn holder_of(&self, user: &str) -> usize {
+        self.away.locked().get(user).copied().unwrap_or_else(|| self.shard_of(user))
+    }
+
+    /// the journal `user`'s records go to
+    pub fn store_of(&self, user: &str) -> &UserStore {
+        &self.shards[self.holder_of(user)]
+    }
+
+    /// note which of the users just read from `shard` are away from theirs,
+    /// for a read replica following journals the primary moves users between
+    pub fn locate(&self, shard: usize, users: &[StoredUser]) {
+        let mut away = self.away.locked();
+        away.retain(|_, at| *at != shard);
+        for user in users.iter().filter(|user| self.shard_of(&user.user) != shard) {
+            away.insert(user.user.clone(), shard);
+        }
+    }
+
+    /// whether this is a read replica's store
+    pub fn is_read_only(&self) -> bool {
+        self.shards.iter().any(UserStore::is_read_only)
+    }
+
+    /// move the users read from each shard (`read[shard]`) that belong on
+    /// another one: first appended there, then removed where they were, so a
+    /// crash in between leaves a copy that the next start finishes moving.
+    /// Users stay where they are when either shard can't be written.
+    /// Returns every user, each once, and how many were moved
+    pub fn rebalance(&self, read: Vec<Vec<StoredUser>>) -> (Vec<StoredUser>, usize) {
+        let mut home: HashMap<String, StoredUser> = HashMap::new();
+        let mut strays = Vec::new(); // (where it is, the user)
+        for (shard, users) in read.into_iter().enumerate() {
+            for user in users {
+                match self.shard_of(&user.user) == shard {
+                    true => drop(home.insert(user.user.clone(), user)),
+                    false => strays.push((shard, user)),
+                }
+            }
+        }
+
+        // a home copy that differs from the stray one is a move cut short: the stray one is the truth
+        let mut redo: HashMap<usize, HashSet<String>> = HashMap::new();
+        let mut moving: HashMap<usize, Vec<StoredUser>> = HashMap::new();
+        for (_, user) in &strays {
+            let shard = self.shard_of(&user.user);
+            match home.get(&user.user) {
+                Some(copy) if copy == user => {}
+                Some(_) => {
+                    redo.entry(shard).or_default().insert(user.user.clone());
+                    moving.entry(shard).or_default().push(user.clone());
+                }
+                None => moving.entry(shard).or_default().push(user.clone()),
+            }
+        }
+        let mut moved_to = HashSet::new();
+        for (shard, users) in moving {
+            let store = &self.shards[shard];
+            let written = match redo.get(&shard) {
+                Some(redo) => store.remove(redo).and_then(|_| store.insert(&users)),
+                None => store.insert(&users),
+            };
+            match written {
+                Ok(()) => moved_to.extend(users.into_iter().map(|user| user.user)),
+                Err(e) => println!("⚠️  {}: could not move {} users here: {}", store.path().display(), users.len(), e),
+            }
+        }
+
+        let mut leaving: HashMap<usize, HashSet<String>> = HashMap::new();
+        for (shard, user) in &strays {
+            if moved_to.contains(&user.user) || home.get(&user.user) == Some(user) {
+                leaving.entry(*shard).or_default().insert(user.user.clone());
+            }
+        }
+        let mut away = self.away.locked();
+        let mut moved = 0;
+        for (shard, user) in strays {
+            let left = leaving.get(&shard).is_some_and(|users| users.contains(&user.user));
+            if !left {
+                away.insert(user.user.clone(), shard);
+            }
+            home.insert(user.user.clone(), user);
+        }
+        for (shard, users) in leaving {
+            let store = &self.shards[shard];
+            match store.remove(&users) {
+                Ok(_) => moved += users.len(),
+                Err(e) => {
+                    // still in both: the home copy is the one written to from now on
+                    println!("⚠️  {}: moved {} users away but could not remove them here: {}", store.path().display(), users.len(), e)
+                }
+            }
+        }
+        (home.into_values().collect(), moved)
+    }
+}
@@ -0,0 +1,439 @@
+//! Sigma protocols: commit, challenge, respond.
+//!
+//! Chaum-Pedersen is one of a family of three-move proofs that only differ in
+//! the statement and the equations the verifier checks. [`SigmaProtocol`]
+//! captures that shape, so compositions ([`And`], [`Or`]), Fiat-Shamir
+//! ([`prove_with`], [`verify_with`]) and [`verify_batch`] are written once and
+//! work for [`ChaumPedersen`], [`Schnorr`] and anything built from them.
+//! [`crate::nizk`] and [`crate::designated`] are Fiat-Shamir over
+//! `ChaumPedersen` and `Or<ChaumPedersen, Schnorr>`.
+//!
+//! Every protocol here works in one group (a [`ZKP`]) and takes challenges
+//! below q.
+
+use std::collections::{HashMap, HashSet};
+
+use num_bigint::BigUint;
+use rand::Rng;
+
+use crate::types::{Challenge, Commitment, PublicKey, Response};
+use crate::ZKP;
+
+/// One check the verifier makes: `lhs == product of base^exponent mod p`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Equation {
+    pub lhs: BigUint,
+    pub terms: Vec<(BigUint, BigUint)>, // (base, exponent)
+}
+
+impl Equation {
+    pub fn holds(&self, zkp: &ZKP) -> bool {
+        let rhs = self
+            .terms
+            .iter()
+            .fold(BigUint::from(1u32), |acc, (base, exp)| acc * base.modpow(exp, &zkp.p) % &zkp.p);
+        self.lhs == rhs
+    }
+}
+
+/// A three-move proof of knowledge of a witness for a statement
+pub trait SigmaProtocol {
+    /// what is proved, public
+    type Statement;
+    /// what the prover knows
+    type Witness;
+    /// the prover's first message
+    type Commitment;
+    /// the prover's secret state between commit and respond (e.g. k)
+    type Nonce;
+    /// the prover's answer to the challenge
+    type Response;
+
+    /// the group every number lives in
+    fn group(&self) -> &ZKP;
+
+    /// the first message, and the nonce to answer with later
+    fn commit(&self, statement: &Self::Statement, witness: &Self::Witness) -> (Self::Commitment, Self::Nonce);
+
+    /// answer challenge `c`; each nonce answers one challenge only
+    fn respond(&self, statement: &Self::Statement, witness: &Self::Witness, nonce: Self::Nonce, c: &Challenge) -> Self::Response;
+
+    /// the checks a valid transcript passes, None if the response is malformed
+    fn equations(
+        &self,
+        statement: &Self::Statement,
+        commitment: &Self::Commitment,
+        c: &Challenge,
+        response: &Self::Response,
+    ) -> Option<Vec<Equation>>;
+
+    /// does the transcript pass every check?
+    fn verify(&self, statement: &Self::Statement, commitment: &Self::Commitment, c: &Challenge, response: &Self::Response) -> bool {
+        self.equations(statement, commitment, c, response)
+            .is_some_and(|equations| equations.iter().all(|equation| equation.holds(self.group())))
+    }
+
+    /// a valid-looking transcript for `c` without the witness: pick the response first
+    fn simulate(&self, statement: &Self::Statement, c: &Challenge) -> (Self::Commitment, Self::Response);
+
+    /// the numbers of a statement, in the order Fiat-Shamir hashes them
+    fn statement_numbers(&self, statement: &Self::Statement) -> Vec<BigUint>;
+
+    /// the numbers of a commitment, in the order Fiat-Shamir hashes them
+    fn commitment_numbers(&self, commitment: &Self::Commitment) -> Vec<BigUint>;
+}
+
+/// Knowledge of x with y1 = alpha^x and y2 = beta^x (the login proof)
+#[derive(Debug, Clone, Copy)]
+pub struct ChaumPedersen<'a>(pub &'a ZKP);
+
+impl SigmaProtocol for ChaumPedersen<'_> {
+    type Statement = PublicKey;
+    type Witness = BigUint;
+    type Commitment = Commitment;
+    type Nonce = BigUint;
+    type Response = Response;
+
+    fn group(&self) -> &ZKP {
+        self.0
+    }
+
+    fn commit(&self, _statement: &PublicKey, _x: &BigUint) -> (Commitment, BigUint) {
+        let k = ZKP::generate_random_number_below(&self.0.q);
+        (self.0.compute_pair(&k).into(), k)
+    }
+
+    fn respond(&self, _statement: &PublicKey, x: &BigUint, k: BigUint, c: &Challenge) -> Response {
+        Response(self.0.solve(&k, &c.0, x) % &self.0.q)
+    }
+
+    fn equations(&self, key: &PublicKey, commitment: &Commitment, c: &Challenge, s: &Response) -> Option<Vec<Equation>> {
+        let zkp = self.0;
+        Some(vec![
+            Equation { lhs: commitment.r1.clone(), terms: vec![(zkp.alpha.clone(), s.0.clone()), (key.y1.clone(), c.0.clone())] },
+            Equation { lhs: commitment.r2.clone(), terms: vec![(zkp.beta.clone(), s.0.clone()), (key.y2.clone(), c.0.clone())] },
+        ])
+    }
+
+    // the same checks, through ZKP::verify so they are timed in the metrics
+    fn verify(&self, key: &PublicKey, commitment: &Commitment, c: &Challenge, s: &Response) -> bool {
+        self.0.verify(&commitment.r1, &commitment.r2, &key.y1, &key.y2, &c.0, &s.0)
+    }
+
+    fn simulate(&self, key: &PublicKey, c: &Challenge) -> (Commitment, Response) {
+        let zkp = self.0;
+        let s = ZKP::generate_random_number_below(&zkp.q);
+        let r1 = (zkp.alpha.modpow(&s, &zkp.p) * key.y1.modpow(&c.0, &zkp.p)) % &zkp.p;
+        let r2 = (zkp.beta.modpow(&s, &zkp.p) * key.y2.modpow(&c.0, &zkp.p)) % &zkp.p;
+        (Commitment { r1, r2 }, Response(s))
+    }
+
+    fn statement_numbers(&self, key: &PublicKey) -> Vec<BigUint> {
+        vec![key.y1.clone(), key.y2.clone()]
+    }
+
+    fn commitment_numbers(&self, commitment: &Commitment) -> Vec<BigUint> {
+        vec![commitment.r1.clone(), commitment.r2.clone()]
+    }
+}
+
+/// Knowledge of w with y = alpha^w (a plain discrete log, e.g. a verifier's key)
+#[derive(Debug, Clone, Copy)]
+pub struct Schnorr<'a>(pub &'a ZKP);
+
+impl SigmaProtocol for Schnorr<'_> {
+    type Statement = BigUint;
+    type Witness = BigUint;
+    type Commitment = BigUint;
+    type Nonce = BigUint;
+    type Response = Response;
+
+    fn group(&self) -> &ZKP {
+        self.0
+    }
+
+    fn commit(&self, _y: &BigUint, _w: &BigUint) -> (BigUint, BigUint) {
+        let k = ZKP::generate_random_number_below(&self.0.q);
+        (self.0.alpha.modpow(&k, &self.0.p), k)
+    }
+
+    fn respond(&self, _y: &BigUint, w: &BigUint, k: BigUint, c: &Challenge) -> Response {
+        Response(self.0.solve(&k, &c.0, w) % &self.0.q)
+    }
+
+    fn equations(&self, y: &BigUint, r: &BigUint, c: &Challenge, s: &Response) -> Option<Vec<Equation>> {
+        Some(vec![Equation { lhs: r.clone(), terms: vec![(self.0.alpha.clone(), s.0.clone()), (y.clone(), c.0.clone())] }])
+    }
+
+    fn simulate(&self, y: &BigUint, c: &Challenge) -> (BigUint, Response) {
+        let zkp = self.0;
+        let s = ZKP::generate_random_number_below(&zkp.q);
+        ((zkp.alpha.modpow(&s, &zkp.p) * y.modpow(&c.0, &zkp.p)) % &zkp.p, Response(s))
+    }
+
+    fn statement_numbers(&self, y: &BigUint) -> Vec<BigUint> {
+        vec![y.clone()]
+    }
+
+    fn commitment_numbers(&self, r: &BigUint) -> Vec<BigUint> {
+        vec![r.clone()]
+    }
+}
+
+/// Both statements, both witnesses known; one challenge answers both
+#[derive(Debug, Clone, Copy)]
+pub struct And<A, B>(pub A, pub B);
+
+impl<A: SigmaProtocol, B: SigmaProtocol> SigmaProtocol for And<A, B> {
+    type Statement = (A::Statement, B::Statement);
+    type Witness = (A::Witness, B::Witness);
+    type Commitment = (A::Commitment, B::Commitment);
+    type Nonce = (A::Nonce, B::Nonce);
+    type Response = (A::Response, B::Response);
+
+    fn group(&self) -> &ZKP {
+        self.0.group()
+    }
+
+    fn commit(&self, (a, b): &Self::Statement, (wa, wb): &Self::Witness) -> (Self::Commitment, Self::Nonce) {
+        let (ca, na) = self.0.commit(a, wa);
+        let (cb, nb) = self.1.commit(b, wb);
+        ((ca, cb), (na, nb))
+    }
+
+    fn respond(&self, (a, b): &Self::Statement, (wa, wb): &Self::Witness, (na, nb): Self::Nonce, c: &Challenge) -> Self::Response {
+        (self.0.respond(a, wa, na, c), self.1.respond(b, wb, nb, c))
+    }
+
+    fn equations(
+        &self,
+        (a, b): &Self::Statement,
+        (ca, cb): &Self::Commitment,
+        c: &Challenge,
+        (ra, rb): &Self::Response,
+    ) -> Option<Vec<Equation>> {
+        let mut equations = self.0.equations(a, ca, c, ra)?;
+        equations.extend(self.1.equations(b, cb, c, rb)?);
+        Some(equations)
+    }
+
+    fn verify(&self, (a, b): &Self::Statement, (ca, cb): &Self::Commitment, c: &Challenge, (ra, rb): &Self::Response) -> bool {
+        self.0.verify(a, ca, c, ra) && self.1.verify(b, cb, c, rb)
+    }
+
+    fn simulate(&self, (a, b): &Self::Statement, c: &Challenge) -> (Self::Commitment, Self::Response) {
+        let (ca, ra) = self.0.simulate(a, c);
+        let (cb, rb) = self.1.simulate(b, c);
+        ((ca, cb), (ra, rb))
+    }
+
+    fn statement_numbers(&self, (a, b): &Self::Statement) -> Vec<BigUint> {
+        [self.0.statement_numbers(a), self.1.statement_numbers(b)].concat()
+    }
+
+    fn commitment_numbers(&self, (ca, cb): &Self::Commitment) -> Vec<BigUint> {
+        [self.0.commitment_numbers(ca), self.1.commitment_numbers(cb)].concat()
+    }
+}
+
+/// Both statements, a witness for either one; the proof doesn't show which
+#[derive(Debug, Clone, Copy)]
+pub struct Or<A, B>(pub A, pub B);
+
+/// A witness for one side of an [`Or`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OrWitness<L, R> {
+    Left(L),
+    Right(R),
+}
+
+/// The real nonce of the known side, and the simulated challenge and answer of the other
+#[derive(Debug, Clone)]
+pub enum OrNonce<A: SigmaProtocol, B: SigmaProtocol> {
+    Left(A::Nonce, Challenge, B::Response),
+    Right(Challenge, A::Response, B::Nonce),
+}
+
+/// The answer to an [`Or`]: the challenge is split between the sides, one split chosen freely
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OrResponse<L, R> {
+    pub c_left: Challenge,
+    pub left: L,
+    pub c_right: Challenge,
+    pub right: R,
+}
+
+impl<A: SigmaProtocol, B: SigmaProtocol> SigmaProtocol for Or<A, B> {
+    type Statement = (A::Statement, B::Statement);
+    type Witness = OrWitness<A::Witness, B::Witness>;
+    type Commitment = (A::Commitment, B::Commitment);
+    type Nonce = OrNonce<A, B>;
+    type Response = OrResponse<A::Response, B::Response>;
+
+    fn group(&self) -> &ZKP {
+        self.0.group()
+    }
+
+    // simulate the side without a witness first, then commit on the other for real
+    fn commit(&self, (a, b): &Self::Statement, witness: &Self::Witness) -> (Self::Commitment, Self::Nonce) {
+        let q = &self.group().q;
+        match witness {
+            OrWitness::Left(wa) => {
+                let c_right = Challenge(ZKP::generate_random_number_below(q));
+                let (cb, rb) = self.1.simulate(b, &c_right);
+                let (ca, na) = self.0.commit(a, wa);
+                ((ca, cb), OrNonce::Left(na, c_right, rb))
+            }
+            OrWitness::Right(wb) => {
+                let c_left = Challenge(ZKP::generate_random_number_below(q));
+                let (ca, ra) = self.0.simulate(a, &c_left);
+                let (cb, nb) = self.1.commit(b, wb);
+                ((ca, cb), OrNonce::Right(c_left, ra, nb))
+            }
+        }
+    }
+
+    fn respond(&self, (a, b): &Self::Statement, witness: &Self::Witness, nonce: Self::Nonce, c: &Challenge) -> Self::Response {
+        let q = &self.group().q;
+        match (witness, nonce) {
+            (OrWitness::Left(wa), OrNonce::Left(na, c_right, right)) => {
+                let c_left = Challenge((&c.0 + q - &c_right.0) % q);
+                let left = self.0.respond(a, wa, na, &c_left);
+                OrResponse { c_left, left, c_right, right }
+            }
+            (OrWitness::Right(wb), OrNonce::Right(c_left, left, nb)) => {
+                let c_right = Challenge((&c.0 + q - &c_left.0) % q);
+                let right = self.1.respond(b, wb, nb, &c_right);
+                OrResponse { c_left, left, c_right, right }
+            }
+            _ => panic!("the nonce was committed for the other side of the Or"),
+        }
+    }
+
+    fn equations(
+        &self,
+        (a, b): &Self::Statement,
+        (ca, cb): &Self::Commitment,
+        c: &Challenge,
+        response: &Self::Response,
+    ) -> Option<Vec<Equation>> {
+        if !self.splits(c, response) {
+            return None;
+        }
+        let mut equations = self.0.equations(a, ca, &response.c_left, &response.left)?;
+        equations.extend(self.1.equations(b, cb, &response.c_right, &response.right)?);
+        Some(equations)
+    }
+
+    fn verify(&self, (a, b): &Self::Statement, (ca, cb): &Self::Commitment, c: &Challenge, response: &Self::Response) -> bool {
+        self.splits(c, response)
+            && self.0.verify(a, ca, &response.c_left, &response.left)
+            && self.1.verify(b, cb, &response.c_right, &response.right)
+    }
+
+    fn simulate(&self, (a, b): &Self::Statement, c: &Challenge) -> (Self::Commitment, Self::Response) {
+        let q = &self.group().q;
+        let c_left = Challenge(ZKP::generate_random_number_below(q));
+        let c_right = Challenge((&c.0 + q - &c_left.0) % q);
+        let (ca, left) = self.0.simulate(a, &c_left);
+        let (cb, right) = self.1.simulate(b, &c_right);
+        ((ca, cb), OrResponse { c_left, left, c_right, right })
+    }
+
+    fn statement_numbers(&self, (a, b): &Self::Statement) -> Vec<BigUint> {
+        [self.0.statement_numbers(a), self.1.statement_numbers(b)].concat()
+    }
+
+    fn commitment_numbers(&self, (ca, cb): &Self::Commitment) -> Vec<BigUint> {
+        [self.0.commitment_numbers(ca), self.1.commitment_numbers(cb)].concat()
+    }
+}
+
+impl<A: SigmaProtocol, B: SigmaProtocol> Or<A, B> {
+    /// do the two challenges add up to `c`? Each has to be below q, or
+    /// adding q to one would make another encoding of the same proof
+    fn splits(&self, c: &Challenge, response: &OrResponse<A::Response, B::Response>) -> bool {
+        let q = &self.group().q;
+        response.c_left.0 < *q && response.c_right.0 < *q && (&response.c_left.0 + &response.c_right.0) % q == c.0
+    }
+}
+
+/// prove non-interactively: `challenge` gets the statement's numbers followed by
+/// the commitment's and derives c from them (a hash, see [`crate::nizk`])
+pub fn prove_with<P: SigmaProtocol>(
+    protocol: &P,
+    statement: &P::Statement,
+    witness: &P::Witness,
+    challenge: impl FnOnce(&[BigUint]) -> Challenge,
+) -> (P::Commitment, P::Response) {
+    let (commitment, nonce) = protocol.commit(statement, witness);
+    let c = challenge(&transcript(protocol, statement, &commitment));
+    let response = protocol.respond(statement, witness, nonce, &c);
+    (commitment, response)
+}
+
+/// check a proof from [`prove_with`], deriving c the same way
+pub fn verify_with<P: SigmaProtocol>(
+    protocol: &P,
+    statement: &P::Statement,
+    commitment: &P::Commitment,
+    response: &P::Response,
+    challenge: impl FnOnce(&[BigUint]) -> Challenge,
+) -> bool {
+    let c = challenge(&transcript(protocol, statement, commitment));
+    protocol.verify(statement, commitment, &c, response)
+}
+
+fn transcript<P: SigmaProtocol>(protocol: &P, statement: &P::Statement, commitment: &P::Commitment) -> Vec<BigUint> {
+    [protocol.statement_numbers(statement), protocol.commitment_numbers(commitment)].concat()
+}
+
+/// One transcript to check in [`verify_batch`]
+pub struct BatchItem<'a, P: SigmaProtocol> {
+    pub statement: &'a P::Statement,
+    pub commitment: &'a P::Commitment,
+    pub c: &'a Challenge,
+    pub response: &'a P::Response,
+}
+
+/// check many transcripts at once: every equation is raised to a random 128-bit
+/// weight and the results multiplied together, so a bad transcript slips
+/// through with probability about 2^-128. Powers of the same base (alpha,
+/// beta, a key used twice) are merged into one exponentiation. The weights
+/// are only sound in a group of prime order, so each distinct number is first
+/// checked to lie in the subgroup (one exponentiation by q). With plain
+/// `modpow` that check eats the savings, and a batch is no faster than
+/// verifying one by one; it is the one place a multi-exponentiation would go.
+/// Says nothing about which transcript was bad; verify them one by one for that
+pub fn verify_batch<P: SigmaProtocol>(protocol: &P, items: &[BatchItem<'_, P>]) -> bool {
+    let zkp = protocol.group();
+    let one = BigUint::from(1u32);
+    let mut lhs = one.clone();
+    // base -> summed exponent (mod q, since every base is in the subgroup)
+    let mut exponents: HashMap<BigUint, BigUint> = HashMap::new();
+    let mut checked: HashSet<BigUint> = HashSet::from([zkp.alpha.clone(), zkp.beta.clone()]);
+
+    for item in items {
+        let Some(equations) = protocol.equations(item.statement, item.commitment, item.c, item.response) else {
+            return false;
+        };
+        for equation in equations {
+            for n in std::iter::once(&equation.lhs).chain(equation.terms.iter().map(|(base, _)| base)) {
+                if !checked.contains(n) {
+                    if n >= &zkp.p || n.modpow(&zkp.q, &zkp.p) != one {
+                        return false;
+                    }
+                    checked.insert(n.clone());
+                }
+            }
+            // the verifier's own randomness, so never the seeded generator
+            let weight = BigUint::from(rand::thread_rng().gen::<u128>());
+            lhs = lhs * equation.lhs.modpow(&weight, &zkp.p) % &zkp.p;
+            for (base, exp) in equation.terms {
+                let sum = exponents.entry(base).or_default();
+                *sum = (&*sum + exp * &weight) % &zkp.q;
+            }
+        }
+    }
+    Equation { lhs, terms: exponents.into_iter().collect() }.holds(zkp)
+}
@@ -0,0 +1,100 @@
+//! User store records in a sled database (`--storage sled:<dir>`).
+//!
+//! sled is an embedded key-value store written in Rust, so persistence needs
+//! no C library and no external database. The records are the journal's
+//! (see [`crate::store`]), grouped by user: the key is SHA-256 of the user id,
+//! the value that user's lines, sealed as in the journal when there is a
+//! `--user-store-key`. Neither keys nor sealed values show a user name.
+//! Writes stay in sled's cache until [`SledStore::flush`]; the user store
+//! calls it as its [`FlushPolicy`](crate::store::FlushPolicy) says, like it
+//! syncs a journal.
+//!
+//! Disabling an account or revoking a device adds a line to the user's value
+//! as it would to the journal. Values that grew past what they hold are
+//! rewritten compactly when the store is opened.
+//!
+//! Sessions are kept too, which a journal doesn't do: each user's sessions
+//! are one value in the `sessions` tree under the same key, replaced whenever
+//! they change, so a restart doesn't log everyone out.
+
+use std::io;
+use std::path::Path;
+
+use sha2::{Digest, Sha256};
+
+/// The tree sessions are kept in, next to the default one with the records
+const SESSIONS: &str = "sessions";
+
+/// A sled database holding user store records and sessions
+#[derive(Debug)]
+pub struct SledStore {
+    db: sled::Db,
+    sessions: sled::Tree,
+}
+
+impl SledStore {
+    /// open the database in `path` (a directory, created if missing)
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let db = sled::open(path)?;
+        let sessions = db.open_tree(SESSIONS)?;
+        Ok(SledStore { db, sessions })
+    }
+
+    /// add `lines` (journal lines, each with its newline) to `user`'s value
+    pub fn append(&self, user: &str, lines: &str) -> io::Result<()> {
+        self.db.update_and_fetch(key(user), |value| {
+            let mut value = value.map(<[u8]>::to_vec).unwrap_or_default();
+            value.extend_from_slice(lines.as_bytes());
+            Some(value)
+        })?;
+        Ok(())
+    }
+
+    /// replace `user`'s value with `lines`
+    pub fn replace(&self, user: &str, lines: &str) -> io::Result<()> {
+        self.db.insert(key(user), lines.as_bytes())?;
+        Ok(())
+    }
+
+    /// `user`'s lines, None if they aren't here
+    pub fn get(&self, user: &str) -> io::Result<Option<Vec<u8>>> {
+        Ok(self.db.get(key(user))?.map(|value| value.to_vec()))
+    }
+
+    /// drop `user`'s lines; false if they weren't here
+    pub fn remove(&self, user: &str) -> io::Result<bool> {
+        Ok(self.db.remove(key(user))?.is_some())
+    }
+
+    /// every user's lines, one value per user
+    pub fn values(&self) -> io::Result<Vec<Vec<u8>>> {
+        self.db.iter().values().map(|value| Ok(value?.to_vec())).collect()
+    }
+
+    /// replace `user`'s sessions with `value`
+    pub fn put_sessions(&self, user: &str, value: &[u8]) -> io::Result<()> {
+        self.sessions.insert(key(user), value)?;
+        Ok(())
+    }
+
+    /// drop `user`'s sessions; false if there were none
+    pub fn remove_sessions(&self, user: &str) -> io::Result<bool> {
+        Ok(self.sessions.remove(key(user))?.is_some())
+    }
+
+    /// every user's sessions, one value per user
+    pub fn sessions(&self) -> io::Result<Vec<Vec<u8>>> {
+        self.sessions.iter().values().map(|value| Ok(value?.to_vec())).collect()
+    }
+
+    /// put every write so far on disk, sessions included
+    pub fn flush(&self) -> io::Result<()> {
+        self.db.flush()?;
+        Ok(())
+    }
+}
+
+/// the key of `user`'s value
+fn key(user: &str) -> [u8; 32] {
+    Sha256::digest(user.as_bytes()).into()
+}
@@ -0,0 +1,221 @@
+//! soak: register/login cycles against a running server for hours, watching
+//! how much memory the server holds.
+//!
+//! ```text
+//! ./target/release/server > /dev/null &
+//! ./target/release/soak --server-pid $! --duration 14400
+//! ```
+//!
+//! Each simulated user registers once, then logs in over and over and logs out
+//! again. Some logins are abandoned after the challenge, which leaves a
+//! pending challenge for the server to expire. The server's resident memory
+//! (VmRSS from /proc, so Linux only) is sampled throughout, and the run fails
+//! when it grows more than --max-rss-growth-mib past what it was after the
+//! warm-up. It also fails on any call that should have worked and didn't.
+
+use std::process::ExitCode;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use clap::Parser;
+use rand::Rng;
+use tonic::transport::Channel;
+
+use rust_zkp_chaum_pedersen::api::v1::{
+    auth_client::AuthClient, AuthenticationAnswerRequest, AuthenticationChallengeRequest, RegisterRequest,
+    RevokeSessionRequest,
+};
+use rust_zkp_chaum_pedersen::memory::format_bytes;
+use rust_zkp_chaum_pedersen::pow;
+use rust_zkp_chaum_pedersen::types::Challenge;
+use rust_zkp_chaum_pedersen::ZKP;
+
+/// Run register/login cycles against a server and fail if its memory keeps growing
+#[derive(Parser)]
+#[command(name = "soak", version, about)]
+struct Args {
+    /// Server endpoint
+    #[arg(long, default_value = "http://127.0.0.1:50051")]
+    endpoint: String,
+    /// Process id of the server, whose memory is watched
+    #[arg(long)]
+    server_pid: u32,
+    /// How long to run (in seconds)
+    #[arg(long, default_value_t = 4 * 3600)]
+    duration: u64,
+    /// Users logging in at the same time, each in its own loop
+    #[arg(long, default_value_t = 16)]
+    users: usize,
+    /// Share of logins (in percent) abandoned after the challenge, never answered
+    #[arg(long, default_value_t = 20)]
+    abandon_percent: u32,
+    /// Seconds before the baseline memory is taken, so caches and pools can fill first
+    #[arg(long, default_value_t = 300)]
+    warmup: u64,
+    /// How often (in seconds) to sample the server's memory and print progress
+    #[arg(long, default_value_t = 60)]
+    sample_interval: u64,
+    /// Fail when the server's memory grows more than this past the baseline (MiB)
+    #[arg(long, default_value_t = 64)]
+    max_rss_growth_mib: u64,
+}
+
+/// What the user loops have done so far
+#[derive(Default)]
+struct Counters {
+    logins: AtomicU64,
+    abandoned: AtomicU64,
+    errors: AtomicU64,
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let args = Args::parse();
+    let Some(start_rss) = rss_bytes(args.server_pid) else {
+        eprintln!("❌ Can't read the memory of process {} (is it running, and is this Linux?)", args.server_pid);
+        return ExitCode::FAILURE;
+    };
+    let client = match AuthClient::connect(args.endpoint.clone()).await {
+        Ok(client) => client,
+        Err(e) => {
+            eprintln!("❌ Could not connect to {}: {}", args.endpoint, e);
+            return ExitCode::FAILURE;
+        }
+    };
+    println!("🔁 Soaking {} for {}s with {} users, server at {}", args.endpoint, args.duration, args.users, format_bytes(start_rss));
+
+    let zkp = Arc::new(ZKP::builder().rfc5114().build().expect("❌ RFC 5114 parameters are valid"));
+    let counters = Arc::new(Counters::default());
+    let started = Instant::now();
+    let deadline = started + Duration::from_secs(args.duration);
+    // a fresh prefix per run, so runs against the same server don't collide
+    let run = ZKP::generate_random_string(8);
+    for index in 0..args.users {
+        let user = format!("soak-{}-{}", run, index);
+        tokio::spawn(user_loop(client.clone(), zkp.clone(), user, args.abandon_percent, deadline, counters.clone()));
+    }
+
+    let limit = args.max_rss_growth_mib as usize * 1024 * 1024;
+    let warmup_end = started + Duration::from_secs(args.warmup);
+    let mut baseline = (args.warmup == 0).then_some(start_rss);
+    let mut peak = start_rss;
+    loop {
+        let now = Instant::now();
+        if now >= deadline {
+            break;
+        }
+        tokio::time::sleep(Duration::from_secs(args.sample_interval).min(deadline - now)).await;
+
+        let Some(rss) = rss_bytes(args.server_pid) else {
+            eprintln!("❌ The server (process {}) went away", args.server_pid);
+            return ExitCode::FAILURE;
+        };
+        peak = peak.max(rss);
+        if baseline.is_none() && Instant::now() >= warmup_end {
+            println!("📏 Baseline after the warm-up: {}", format_bytes(rss));
+            baseline = Some(rss);
+        }
+        let growth = baseline.map(|baseline| rss.saturating_sub(baseline));
+        println!(
+            "⏱️  {:>6}s logins {} abandoned {} errors {} server {}{}",
+            started.elapsed().as_secs(),
+            counters.logins.load(Ordering::Relaxed),
+            counters.abandoned.load(Ordering::Relaxed),
+            counters.errors.load(Ordering::Relaxed),
+            format_bytes(rss),
+            growth.map(|growth| format!(" (+{})", format_bytes(growth))).unwrap_or_default(),
+        );
+        if growth.is_some_and(|growth| growth > limit) {
+            eprintln!(
+                "❌ The server grew by {} since the warm-up, more than the {} allowed; pending challenges, sessions and idempotency keys are the usual suspects (see zkp_store_bytes on /metrics)",
+                format_bytes(growth.unwrap_or_default()),
+                format_bytes(limit)
+            );
+            return ExitCode::FAILURE;
+        }
+    }
+
+    let errors = counters.errors.load(Ordering::Relaxed);
+    println!("📊 {} logins, {} abandoned, {} errors, server peaked at {}", counters.logins.load(Ordering::Relaxed), counters.abandoned.load(Ordering::Relaxed), errors, format_bytes(peak));
+    if counters.logins.load(Ordering::Relaxed) == 0 || errors > 0 {
+        eprintln!("❌ Soak failed: {} calls went wrong", errors);
+        return ExitCode::FAILURE;
+    }
+    println!("✅ Memory stayed within {} of the baseline", format_bytes(limit));
+    ExitCode::SUCCESS
+}
+
+/// register `user`, then log in and out until the deadline
+async fn user_loop(
+    mut client: AuthClient<Channel>,
+    zkp: Arc<ZKP>,
+    user: String,
+    abandon_percent: u32,
+    deadline: Instant,
+    counters: Arc<Counters>,
+) {
+    let x = ZKP::generate_random_number_below(&zkp.q);
+    let (y1, y2) = zkp.public_key(&x).to_bytes_be();
+    if let Err(status) = client.register(RegisterRequest { user: user.clone(), y1, y2, ..Default::default() }).await {
+        eprintln!("⚠️  Could not register {}: {}", user, status.message());
+        counters.errors.fetch_add(1, Ordering::Relaxed);
+        return;
+    }
+
+    while Instant::now() < deadline {
+        let abandon = rand::thread_rng().gen_range(0..100) < abandon_percent;
+        match cycle(&mut client, &zkp, &user, &x, abandon).await {
+            Ok(()) if abandon => counters.abandoned.fetch_add(1, Ordering::Relaxed),
+            Ok(()) => counters.logins.fetch_add(1, Ordering::Relaxed),
+            Err(status) => {
+                eprintln!("⚠️  Login cycle of {} failed: {}", user, status.message());
+                counters.errors.fetch_add(1, Ordering::Relaxed)
+            }
+        };
+    }
+}
+
+/// one login, or just its challenge when `abandon`; the session is revoked right away
+async fn cycle(client: &mut AuthClient<Channel>, zkp: &ZKP, user: &str, x: &num_bigint::BigUint, abandon: bool) -> Result<(), tonic::Status> {
+    let k = ZKP::generate_random_number_below(&zkp.q);
+    let (r1, r2) = zkp.commitment(&k).to_bytes_be();
+    let challenge = client
+        .create_authentication_challenge(AuthenticationChallengeRequest { user: user.to_string(), r1, r2, ..Default::default() })
+        .await?
+        .into_inner();
+    if abandon {
+        return Ok(());
+    }
+
+    let s = zkp.respond(&k, &Challenge::from_bytes_be(&challenge.c), x);
+    let pow_nonce = pow::solve(&challenge.auth_id, &challenge.c, challenge.pow_difficulty)
+        .ok_or_else(|| tonic::Status::unavailable(format!("a {}-bit proof of work", challenge.pow_difficulty)))?;
+    let session_id = client
+        .verify_authentication(AuthenticationAnswerRequest {
+            auth_id: challenge.auth_id,
+            s: s.to_bytes_be(),
+            pow_nonce,
+            challenge_token: challenge.challenge_token,
+            ..Default::default()
+        })
+        .await?
+        .into_inner()
+        .session_id;
+    client
+        .revoke_session(RevokeSessionRequest {
+            user: user.to_string(),
+            session_id: session_id.clone(),
+            revoke_session_id: session_id,
+        })
+        .await?;
+    Ok(())
+}
+
+/// resident memory of a process, from /proc/<pid>/status
+fn rss_bytes(pid: u32) -> Option<usize> {
+    let status = std::fs::read_to_string(format!("/proc/{}/status", pid)).ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmRSS:"))?;
+    let kib: usize = line.trim_start_matches("VmRSS:").trim().trim_end_matches("kB").trim().parse().ok()?;
+    Some(kib * 1024)
+}
@@ -0,0 +1,1065 @@
+//! Registrations kept on disk across restarts.
+//!
+//! With `--user-store` every registration is appended to a JSON lines journal
+//! and synced to disk before it is acknowledged, and the journal is read back
+//! at startup. A record counts once its newline is on disk: a crash halfway
+//! through a write leaves a last line without one, which was never
+//! acknowledged and is cut off when the journal is opened again. The
+//! server keeps serving logins from memory whatever the disk does: when the
+//! journal can't be written (disk full, volume gone) the store turns
+//! *degraded*, registrations are refused with `Unavailable`, and the server
+//! retries the journal in the background until it can write again.
+//!
+//! Disabling or re-enabling an account appends `{"user":..,"enabled":false}`;
+//! the latest such line for a user wins when the journal is read. Verifying
+//! an email address (`--user-ids email`) appends `{"user":..,"verified":true}`.
+//! Enrolling a device appends `{"user":..,"device":{"id":..,"name":..,"y1":..,
+//! "y2":..,"enrolled_at":..}}` and revoking it `{"user":..,"revoked_device":<id>}`.
+//!
+//! With a [`StoreKey`] (`--user-store-key`) every record is sealed with
+//! AES-256-GCM under a fresh random nonce and written as
+//! `{"sealed":<base64 nonce|ciphertext>,"key":<key id>}`, so the journal shows
+//! neither user names nor public keys. Records are opened as the journal is
+//! read; a journal that still has plain records is rewritten sealed when it
+//! is opened with a key.
+//!
+//! Erasing a user (a GDPR erasure request) rewrites the journal without
+//! them, so their registration doesn't linger in older lines.
+//!
+//! A read replica (`--read-replica`) [follows](UserStore::follow) the journal
+//! a primary writes, on a shared or replicated volume: it never writes,
+//! truncates or reseals it, and reads it again whenever its size or
+//! modification time changes. A last line the primary is still writing is
+//! left for the next read.
+//!
+//! `--storage sled:<dir>` keeps the same records in a sled database instead
+//! of a journal (the `sled` feature, see [`crate::sled_store`]);
+//! `--storage journal:<file>` is the same as `--user-store <file>`. A sled
+//! database is only ever opened by one process, so it can't be followed by a
+//! read replica. It also keeps each user's sessions
+//! ([`UserStore::save_sessions`]), sealed like the records; a journal keeps
+//! none, so with one sessions end with the server.
+//!
+//! By default every write is synced before it is acknowledged. A
+//! [`FlushPolicy`] with an interval (`--user-store-flush-ms`) trades that for
+//! throughput when importing users in bulk or under heavy churn: writes are
+//! acknowledged once they reach the operating system, and [`UserStore::flush`]
+//! syncs them in one go every interval, or as soon as `--user-store-batch` of
+//! them wait. A journal write that reached the OS survives the server
+//! crashing, but not the machine losing power in the meantime; a sled
+//! database keeps unflushed writes in the process, so they are also lost when
+//! the server is killed.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use num_bigint::BigUint;
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN};
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::api::v1::RegisterRequest;
+use crate::group::{GroupId, HashId};
+use crate::kdf::KdfParams;
+use crate::metrics;
+use crate::telemetry;
+#[cfg(feature = "sled")]
+use crate::sled_store::SledStore;
+use crate::types::PublicKey;
+
+/// One registration, as written to the journal
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StoredUser {
+    pub user: String,
+    pub y1: String, // hex
+    pub y2: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kdf: Option<StoredKdf>,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub beta: String, // hex H2G(user), empty for the global beta
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub group: String, // see group.rs, empty for "modp"
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub hash: String, // behind beta, empty for "sha256"
+    // so a retry after a restart is still recognized as the same registration
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub idempotency_key: String,
+    #[serde(default)]
+    pub password_policy_version: u32,
+    #[serde(default)]
+    pub registered_at: u64, // unix seconds, 0 when unknown
+    // --user-ids email: the address hasn't been verified, until a verified line
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub unverified: bool,
+    // from the enabled lines after the registration, never written here
+    #[serde(skip)]
+    pub disabled: bool,
+    // from the device lines after the registration, revoked ones left out
+    #[serde(skip)]
+    pub devices: Vec<StoredDevice>,
+}
+
+/// One enrolled device, as written to the journal
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StoredDevice {
+    pub id: String, // the fingerprint of (y1, y2)
+    pub name: String,
+    pub y1: String, // hex
+    pub y2: String,
+    pub enrolled_at: u64, // unix milliseconds, so devices enrolled within a second keep their order
+}
+
+impl StoredDevice {
+    pub fn public_key(&self) -> PublicKey {
+        PublicKey::from_bytes_be(&decode(&self.y1), &decode(&self.y2))
+    }
+
+    fn is_valid(&self) -> bool {
+        hex::decode(&self.y1).is_ok() && hex::decode(&self.y2).is_ok()
+    }
+}
+
+/// One session, as a sled store keeps it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StoredSession {
+    pub id: String,
+    pub created_at: u64, // unix milliseconds
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub client: String, // hex of the ClientMetadata, empty when the client sent none
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub device: Option<String>, // the enrolled device it logged in with
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub elevated_until: Option<u64>, // unix milliseconds
+}
+
+/// A user's sessions, as one value of a sled store
+#[cfg(feature = "sled")]
+#[derive(Debug, Serialize, Deserialize)]
+struct SessionsRecord {
+    user: String,
+    sessions: Vec<StoredSession>,
+}
+
+/// A sessions value, sealed or not
+#[cfg(feature = "sled")]
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum SessionsValue {
+    Sealed { sealed: String, key: String },
+    Plain(SessionsRecord),
+}
+
+/// A line of the journal
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum Record {
+    Sealed { sealed: String, key: String },
+    User(StoredUser),
+    Enabled { user: String, enabled: bool },
+    Verified { user: String, verified: bool },
+    Device { user: String, device: StoredDevice },
+    RevokedDevice { user: String, revoked_device: String },
+}
+
+/// The KDF parameters of a registration
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StoredKdf {
+    pub salt: String, // hex
+    pub memory_kib: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+}
+
+impl From<&RegisterRequest> for StoredUser {
+    fn from(request: &RegisterRequest) -> Self {
+        StoredUser {
+            user: request.user.clone(),
+            y1: hex::encode(&request.y1),
+            y2: hex::encode(&request.y2),
+            kdf: request.kdf.as_ref().map(|kdf| StoredKdf {
+                salt: hex::encode(&kdf.salt),
+                memory_kib: kdf.memory_kib,
+                iterations: kdf.iterations,
+                parallelism: kdf.parallelism,
+            }),
+            beta: hex::encode(&request.beta),
+            group: request.group.clone(),
+            hash: request.hash.clone(),
+            idempotency_key: request.idempotency_key.clone(),
+            password_policy_version: request.password_policy_version,
+            registered_at: 0,
+            unverified: false,
+            disabled: false,
+            devices: Vec::new(),
+        }
+    }
+}
+
+impl From<&StoredUser> for RegisterRequest {
+    fn from(user: &StoredUser) -> Self {
+        RegisterRequest {
+            user: user.user.clone(),
+            y1: decode(&user.y1),
+            y2: decode(&user.y2),
+            idempotency_key: user.idempotency_key.clone(),
+            password_policy_version: user.password_policy_version,
+            kdf: user.kdf.as_ref().map(|kdf| crate::api::v1::KdfParams {
+                salt: decode(&kdf.salt),
+                memory_kib: kdf.memory_kib,
+                iterations: kdf.iterations,
+                parallelism: kdf.parallelism,
+            }),
+            beta: decode(&user.beta),
+            group: user.group.clone(),
+            hash: user.hash.clone(),
+        }
+    }
+}
+
+impl StoredUser {
+    pub fn public_key(&self) -> PublicKey {
+        PublicKey::from_bytes_be(&decode(&self.y1), &decode(&self.y2))
+    }
+
+    pub fn kdf(&self) -> Option<KdfParams> {
+        self.kdf.as_ref().map(|kdf| KdfParams {
+            salt: decode(&kdf.salt),
+            memory_kib: kdf.memory_kib,
+            iterations: kdf.iterations,
+            parallelism: kdf.parallelism,
+        })
+    }
+
+    pub fn beta(&self) -> Option<BigUint> {
+        (!self.beta.is_empty()).then(|| BigUint::from_bytes_be(&decode(&self.beta)))
+    }
+
+    /// the group the user registered in; names this build doesn't know fail is_valid
+    pub fn group(&self) -> GroupId {
+        GroupId::from_name(&self.group).unwrap_or_default()
+    }
+
+    /// the hash beta was derived with
+    pub fn hash(&self) -> HashId {
+        HashId::from_name(&self.hash).unwrap_or_default()
+    }
+
+    /// whether every hex field decodes and the group and hash are known
+    pub fn is_valid(&self) -> bool {
+        let fields = [&self.y1, &self.y2, &self.beta];
+        let salt = self.kdf.iter().map(|kdf| &kdf.salt);
+        let known = GroupId::from_name(&self.group).is_some() && HashId::from_name(&self.hash).is_some();
+        known && fields.into_iter().chain(salt).all(|field| hex::decode(field).is_ok())
+    }
+}
+
+/// fields are checked by is_valid when the journal is read
+fn decode(field: &str) -> Vec<u8> {
+    hex::decode(field).unwrap_or_default()
+}
+
+/// Associated data of every sealed record, so they can't pass for anything else
+const SEALED_AAD: &[u8] = b"zkp-auth user store v1";
+
+/// The AES-256-GCM key records are sealed with
+#[derive(Clone)]
+pub struct StoreKey {
+    key: Arc<LessSafeKey>,
+    id: String, // names the key in sealed records, not secret
+}
+
+// a key, keep it out of logs
+impl std::fmt::Debug for StoreKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "StoreKey({})", self.id)
+    }
+}
+
+impl StoreKey {
+    pub fn new(key: &[u8; 32]) -> Self {
+        let id = hex::encode(&Sha256::new().chain_update(SEALED_AAD).chain_update(key).finalize()[..4]);
+        let key = UnboundKey::new(&AES_256_GCM, key).expect("32 bytes is an AES-256 key");
+        StoreKey { key: Arc::new(LessSafeKey::new(key)), id }
+    }
+
+    /// 32 hex-encoded bytes (`openssl rand -hex 32`)
+    pub fn from_hex(hex: &str) -> io::Result<Self> {
+        let bytes: [u8; 32] = hex::decode(hex.trim())
+            .ok()
+            .and_then(|bytes| bytes.try_into().ok())
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "the user store key must be 32 hex-encoded bytes"))?;
+        Ok(Self::new(&bytes))
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        Self::from_hex(&std::fs::read_to_string(path)?)
+    }
+
+    /// the key's id, as written next to every record it sealed
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// base64 of nonce | ciphertext | tag
+    pub fn seal(&self, plaintext: &[u8]) -> String {
+        // from the OS every time: --seed makes the crate's own RNG predictable
+        let mut nonce = [0u8; NONCE_LEN];
+        SystemRandom::new().fill(&mut nonce).expect("the OS has randomness");
+        let mut sealed = plaintext.to_vec();
+        self.key
+            .seal_in_place_append_tag(Nonce::assume_unique_for_key(nonce), Aad::from(SEALED_AAD), &mut sealed)
+            .expect("records are far below the AES-GCM limit");
+        STANDARD.encode([nonce.as_slice(), &sealed].concat())
+    }
+
+    /// what `seal` sealed; None when it was another key or the record was altered
+    pub fn open(&self, sealed: &str) -> Option<Vec<u8>> {
+        let bytes = STANDARD.decode(sealed).ok()?;
+        let (nonce, ciphertext) = bytes.split_at_checked(NONCE_LEN)?;
+        let nonce = Nonce::try_assume_unique_for_key(nonce).ok()?;
+        let mut plaintext = ciphertext.to_vec();
+        let length = self.key.open_in_place(nonce, Aad::from(SEALED_AAD), &mut plaintext).ok()?.len();
+        plaintext.truncate(length);
+        Some(plaintext)
+    }
+}
+
+/// What a user store is kept in (`--storage BACKEND:PATH`)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Storage {
+    /// a JSON lines journal, as with --user-store
+    Journal(PathBuf),
+    /// a sled database in this directory (`sled` feature)
+    Sled(PathBuf),
+}
+
+impl Storage {
+    pub fn path(&self) -> &Path {
+        match self {
+            Storage::Journal(path) | Storage::Sled(path) => path,
+        }
+    }
+}
+
+impl FromStr for Storage {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, String> {
+        let (backend, path) = text.split_once(':').ok_or_else(|| format!("{:?} is not BACKEND:PATH, e.g. sled:/var/lib/zkp-auth/users", text))?;
+        if path.is_empty() {
+            return Err(format!("{:?} has no path", text));
+        }
+        match backend {
+            "journal" => Ok(Storage::Journal(PathBuf::from(path))),
+            "sled" if cfg!(feature = "sled") => Ok(Storage::Sled(PathBuf::from(path))),
+            "sled" => Err("this build has no sled storage, rebuild with --features sled".to_string()),
+            _ => Err(format!("unknown storage backend {:?}, expected journal or sled", backend)),
+        }
+    }
+}
+
+impl fmt::Display for Storage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Storage::Journal(path) => write!(f, "journal:{}", path.display()),
+            Storage::Sled(path) => write!(f, "sled:{}", path.display()),
+        }
+    }
+}
+
+/// When the user store's writes are synced to disk
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct FlushPolicy {
+    /// how long an acknowledged write may wait for its sync; zero syncs
+    /// every write before it is acknowledged
+    pub interval: Duration,
+    /// sync as soon as this many writes wait, whatever the interval; 0 = no limit
+    pub max_batch: usize,
+}
+
+impl FlushPolicy {
+    /// every `interval`, or once `max_batch` writes wait
+    pub fn every(interval: Duration, max_batch: usize) -> Self {
+        FlushPolicy { interval, max_batch }
+    }
+
+    /// whether each write is on disk when it is acknowledged
+    pub fn is_every_write(&self) -> bool {
+        self.interval.is_zero()
+    }
+}
+
+impl fmt::Display for FlushPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.is_every_write(), self.max_batch) {
+            (true, _) => f.write_str("every write"),
+            (false, 0) => write!(f, "every {}ms", self.interval.as_millis()),
+            (false, batch) => write!(f, "every {}ms or {} writes", self.interval.as_millis(), batch),
+        }
+    }
+}
+
+/// The journal of registrations and whether it can be written
+#[derive(Debug)]
+pub struct UserStore {
+    path: PathBuf,
+    file: Mutex<Option<File>>, // None while degraded
+    degraded: Mutex<Option<String>>, // why, while degraded
+    key: Option<StoreKey>, // seals every record when set
+    flush: FlushPolicy,
+    unsynced: AtomicUsize, // writes acknowledged but not yet synced
+    slow: Option<Duration>, // operations that take this long are logged
+    // a read replica's view of the primary's journal: (size, modified) when last read
+    followed: Option<Mutex<Option<(u64, SystemTime)>>>,
+    // the database of a sled store, which takes the journal's place; None inside while degraded
+    #[cfg(feature = "sled")]
+    sled: Option<Mutex<Option<SledStore>>>,
+}
+
+impl UserStore {
+    /// open a store of either kind and read the users in it, like `open_with`
+    pub fn open_storage(storage: &Storage, key: Option<StoreKey>) -> io::Result<(Self, Vec<StoredUser>)> {
+        match storage {
+            Storage::Journal(path) => Self::open_with(path, key),
+            #[cfg(feature = "sled")]
+            Storage::Sled(path) => {
+                let (db, users) = open_sled(path, key.as_ref())?;
+                let store = UserStore {
+                    path: path.to_path_buf(),
+                    file: Mutex::new(None),
+                    degraded: Mutex::new(None),
+                    key,
+                    flush: FlushPolicy::default(),
+                    unsynced: AtomicUsize::new(0),
+                    slow: None,
+                    followed: None,
+                    sled: Some(Mutex::new(Some(db))),
+                };
+                Ok((store, users))
+            }
+            #[cfg(not(feature = "sled"))]
+            Storage::Sled(_) => Err(io::Error::new(io::ErrorKind::Unsupported, "this build has no sled storage")),
+        }
+    }
+
+    /// like `unavailable_with`, for a store of either kind
+    pub fn unavailable_storage(storage: &Storage, error: &io::Error, key: Option<StoreKey>) -> Self {
+        match storage {
+            #[cfg(feature = "sled")]
+            Storage::Sled(path) => UserStore { sled: Some(Mutex::new(None)), ..Self::unavailable_with(path, error, key) },
+            _ => Self::unavailable_with(storage.path(), error, key),
+        }
+    }
+
+    /// sync writes as `flush` says instead of one by one
+    pub fn with_flush(mut self, flush: FlushPolicy) -> Self {
+        self.flush = flush;
+        self
+    }
+
+    pub fn flush_policy(&self) -> FlushPolicy {
+        self.flush
+    }
+
+    /// log operations that take `threshold` or longer (`--slow-store-ms`)
+    pub fn with_slow_log(mut self, threshold: Duration) -> Self {
+        self.slow = Some(threshold);
+        self
+    }
+
+    /// open the journal (creating it) and read the users in it
+    pub fn open(path: &Path) -> io::Result<(Self, Vec<StoredUser>)> {
+        Self::open_with(path, None)
+    }
+
+    /// like `open`, sealing records with `key`; plain records already in the
+    /// journal are sealed now
+    pub fn open_with(path: &Path, key: Option<StoreKey>) -> io::Result<(Self, Vec<StoredUser>)> {
+        let (file, users) = open_journal(path, key.as_ref())?;
+        let store = UserStore {
+            path: path.to_path_buf(),
+            file: Mutex::new(Some(file)),
+            degraded: Mutex::new(None),
+            key,
+            flush: FlushPolicy::default(),
+            unsynced: AtomicUsize::new(0),
+            slow: None,
+            followed: None,
+            #[cfg(feature = "sled")]
+            sled: None,
+        };
+        Ok((store, users))
+    }
+
+    /// follow a journal another server writes, without ever writing it; the
+    /// users come with the first `reload`
+    pub fn follow(path: &Path, key: Option<StoreKey>) -> Self {
+        UserStore {
+            path: path.to_path_buf(),
+            file: Mutex::new(None),
+            degraded: Mutex::new(None),
+            key,
+            flush: FlushPolicy::default(),
+            unsynced: AtomicUsize::new(0),
+            slow: None,
+            followed: Some(Mutex::new(None)),
+            #[cfg(feature = "sled")]
+            sled: None,
+        }
+    }
+
+    /// whether this is a read replica's store
+    pub fn is_read_only(&self) -> bool {
+        self.followed.is_some()
+    }
+
+    /// for a read replica: every user in the journal if it changed since the
+    /// last call, None if it didn't
+    pub fn reload(&self) -> io::Result<Option<Vec<StoredUser>>> {
+        let Some(followed) = &self.followed else {
+            return Ok(None);
+        };
+        self.timed("reload", String::new, || self.reload_followed(followed))
+    }
+
+    fn reload_followed(&self, followed: &Mutex<Option<(u64, SystemTime)>>) -> io::Result<Option<Vec<StoredUser>>> {
+        let metadata = std::fs::metadata(&self.path)?;
+        let seen = (metadata.len(), metadata.modified()?);
+        let mut last = followed.lock().unwrap();
+        if *last == Some(seen) {
+            return Ok(None);
+        }
+        let (users, _, _) = read_journal(&mut File::open(&self.path)?, &self.path, self.key.as_ref())?;
+        *last = Some(seen);
+        Ok(Some(users))
+    }
+
+    /// a store that couldn't be opened at startup, to be recovered later
+    pub fn unavailable(path: &Path, error: &io::Error) -> Self {
+        Self::unavailable_with(path, error, None)
+    }
+
+    /// like `unavailable`, for a store opened with `open_with`
+    pub fn unavailable_with(path: &Path, error: &io::Error, key: Option<StoreKey>) -> Self {
+        UserStore {
+            path: path.to_path_buf(),
+            file: Mutex::new(None),
+            degraded: Mutex::new(Some(error.to_string())),
+            key,
+            flush: FlushPolicy::default(),
+            unsynced: AtomicUsize::new(0),
+            slow: None,
+            followed: None,
+            #[cfg(feature = "sled")]
+            sled: None,
+        }
+    }
+
+    /// whether records are sealed
+    pub fn is_sealed(&self) -> bool {
+        self.key.is_some()
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// why the store can't be written, None when it can
+    pub fn degraded(&self) -> Option<String> {
+        self.degraded.lock().unwrap().clone()
+    }
+
+    /// append a registration and wait until it is on disk (or only written,
+    /// see [`FlushPolicy`]); a failure turns the store degraded
+    pub fn append(&self, user: &StoredUser) -> io::Result<()> {
+        self.timed("append", || for_user(&user.user), || self.write(&user.user, user))
+    }
+
+    /// record that a user was disabled or enabled again, like append
+    pub fn set_enabled(&self, user: &str, enabled: bool) -> io::Result<()> {
+        self.timed("set_enabled", || for_user(user), || self.write(user, &serde_json::json!({ "user": user, "enabled": enabled })))
+    }
+
+    /// record that a user verified their email address, like append
+    pub fn set_verified(&self, user: &str) -> io::Result<()> {
+        self.timed("set_verified", || for_user(user), || self.write(user, &serde_json::json!({ "user": user, "verified": true })))
+    }
+
+    /// record a device a user enrolled, like append
+    pub fn add_device(&self, user: &str, device: &StoredDevice) -> io::Result<()> {
+        self.timed("add_device", || for_user(user), || self.write(user, &serde_json::json!({ "user": user, "device": device })))
+    }
+
+    /// record that a user revoked one of their devices, like append
+    pub fn revoke_device(&self, user: &str, device_id: &str) -> io::Result<()> {
+        self.timed("revoke_device", || for_user(user), || self.write(user, &serde_json::json!({ "user": user, "revoked_device": device_id })))
+    }
+
+    /// append users moved here from another journal (see shard.rs), with
+    /// whether they are disabled and their devices, like append
+    pub fn insert(&self, users: &[StoredUser]) -> io::Result<()> {
+        self.timed("insert", || format!(" of {} users", users.len()), || self.insert_records(users))
+    }
+
+    fn insert_records(&self, users: &[StoredUser]) -> io::Result<()> {
+        #[cfg(feature = "sled")]
+        if self.sled.is_some() {
+            return users.iter().try_for_each(|user| self.write_sled(|store| store.append(&user.user, &user_lines(user, self.key.as_ref()))));
+        }
+        let lines: String = users.iter().map(|user| user_lines(user, self.key.as_ref())).collect();
+        self.write_lines(&lines)
+    }
+
+    /// write one of `user`'s records
+    fn write(&self, user: &str, record: &impl Serialize) -> io::Result<()> {
+        let line = format!("{}\n", to_line(record, self.key.as_ref()));
+        #[cfg(feature = "sled")]
+        if self.sled.is_some() {
+            return self.write_sled(|store| store.append(user, &line));
+        }
+        #[cfg(not(feature = "sled"))]
+        let _ = user;
+        self.write_lines(&line)
+    }
+
+    /// keep `user`'s sessions in place of the ones kept before, like append;
+    /// only a sled store keeps them, for a journal this does nothing
+    pub fn save_sessions(&self, user: &str, sessions: &[StoredSession]) -> io::Result<()> {
+        #[cfg(feature = "sled")]
+        if self.sled.is_some() {
+            return self.timed("save_sessions", || for_user(user), || {
+                let record = SessionsRecord { user: user.to_string(), sessions: sessions.to_vec() };
+                let value = to_line(&record, self.key.as_ref());
+                self.write_sled(|store| match sessions.is_empty() {
+                    true => store.remove_sessions(user).map(|_| ()),
+                    false => store.put_sessions(user, value.as_bytes()),
+                })
+            });
+        }
+        let _ = (user, sessions);
+        Ok(())
+    }
+
+    /// every user's sessions kept here, (user, their sessions); none for a journal
+    pub fn sessions(&self) -> io::Result<Vec<(String, Vec<StoredSession>)>> {
+        #[cfg(feature = "sled")]
+        if let Some(db) = &self.sled {
+            let db = db.lock().unwrap();
+            let store = db.as_ref().ok_or_else(|| io::Error::other(self.degraded().unwrap_or_default()))?;
+            return store
+                .sessions()?
+                .iter()
+                .map(|value| read_sessions(value, &self.path, self.key.as_ref()).map(|record| (record.user, record.sessions)))
+                .collect();
+        }
+        Ok(Vec::new())
+    }
+
+    /// make one write to the sled database and flush it as the policy says;
+    /// a failure turns the store degraded
+    #[cfg(feature = "sled")]
+    fn write_sled(&self, write: impl FnOnce(&SledStore) -> io::Result<()>) -> io::Result<()> {
+        let mut db = self.sled.as_ref().expect("a sled store").lock().unwrap();
+        let Some(store) = db.as_ref() else {
+            return Err(io::Error::other(self.degraded().unwrap_or_default()));
+        };
+        if let Err(e) = write(store).and_then(|()| self.synced(|| store.flush())) {
+            *db = None;
+            *self.degraded.lock().unwrap() = Some(e.to_string());
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    fn write_lines(&self, lines: &str) -> io::Result<()> {
+        if self.is_read_only() {
+            return Err(io::Error::new(io::ErrorKind::PermissionDenied, "a read replica doesn't write the user store"));
+        }
+        let mut file = self.file.lock().unwrap();
+        let Some(journal) = file.as_mut() else {
+            return Err(io::Error::other(self.degraded().unwrap_or_default()));
+        };
+        // one write per call, so only the last line can ever be torn
+        if let Err(e) = journal.write_all(lines.as_bytes()).and_then(|()| self.synced(|| journal.sync_data())) {
+            *file = None;
+            *self.degraded.lock().unwrap() = Some(e.to_string());
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    /// count a write that was just made, and `sync` now if the flush policy
+    /// doesn't let it wait
+    fn synced(&self, sync: impl FnOnce() -> io::Result<()>) -> io::Result<()> {
+        let waiting = self.unsynced.fetch_add(1, Ordering::Relaxed) + 1;
+        if self.flush.is_every_write() || (self.flush.max_batch > 0 && waiting >= self.flush.max_batch) {
+            return self.sync(sync);
+        }
+        Ok(())
+    }
+
+    /// `sync` the writes that wait, timing it for /metrics
+    fn sync(&self, sync: impl FnOnce() -> io::Result<()>) -> io::Result<()> {
+        let start = Instant::now();
+        sync()?;
+        let writes = self.unsynced.swap(0, Ordering::Relaxed);
+        metrics::flushes().record(writes, start.elapsed());
+        Ok(())
+    }
+
+    /// how many acknowledged writes wait for their sync
+    pub fn unsynced(&self) -> usize {
+        self.unsynced.load(Ordering::Relaxed)
+    }
+
+    /// sync the writes that wait (see [`FlushPolicy`]); a failure turns the
+    /// store degraded, like a failed write
+    pub fn flush(&self) -> io::Result<()> {
+        let waiting = self.unsynced();
+        if waiting == 0 {
+            return Ok(());
+        }
+        self.timed("flush", || format!(" of {} writes", waiting), || self.flush_writes())
+    }
+
+    fn flush_writes(&self) -> io::Result<()> {
+        #[cfg(feature = "sled")]
+        if let Some(db) = &self.sled {
+            let mut db = db.lock().unwrap();
+            let Some(store) = db.as_ref() else {
+                return Err(io::Error::other(self.degraded().unwrap_or_default()));
+            };
+            if let Err(e) = self.sync(|| store.flush()) {
+                *db = None;
+                *self.degraded.lock().unwrap() = Some(e.to_string());
+                return Err(e);
+            }
+            return Ok(());
+        }
+        let mut file = self.file.lock().unwrap();
+        let Some(journal) = file.as_mut() else {
+            return Err(io::Error::other(self.degraded().unwrap_or_default()));
+        };
+        if let Err(e) = self.sync(|| journal.sync_data()) {
+            *file = None;
+            *self.degraded.lock().unwrap() = Some(e.to_string());
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    /// run one operation on the store: timed into /metrics, in a `tracing`
+    /// span with the `profiling` feature, and logged when it takes the slow
+    /// threshold or longer. `detail` says what it was run on, for the log
+    fn timed<T>(&self, op: &'static str, detail: impl FnOnce() -> String, run: impl FnOnce() -> io::Result<T>) -> io::Result<T> {
+        #[cfg(feature = "profiling")]
+        let _span = tracing::trace_span!("user_store", op, path = %self.path.display()).entered();
+        let start = Instant::now();
+        let result = run();
+        let took = start.elapsed();
+        let slow = self.slow.is_some_and(|threshold| took >= threshold);
+        metrics::store_ops().record(op, took, slow);
+        if slow {
+            let failed = if result.is_err() { ", and failed" } else { "" };
+            println!("🐢 Slow user store {}{} on {} took {}ms{}", op, detail(), self.path.display(), took.as_millis(), failed);
+        }
+        result
+    }
+
+    /// `user`'s registration as the journal has it
+    pub fn find(&self, user: &str) -> io::Result<Option<StoredUser>> {
+        self.timed("find", || for_user(user), || self.find_record(user))
+    }
+
+    fn find_record(&self, user: &str) -> io::Result<Option<StoredUser>> {
+        #[cfg(feature = "sled")]
+        if let Some(db) = &self.sled {
+            let db = db.lock().unwrap();
+            let store = db.as_ref().ok_or_else(|| io::Error::other(self.degraded().unwrap_or_default()))?;
+            let Some(value) = store.get(user)? else {
+                return Ok(None);
+            };
+            let (users, _, _) = read_records(value.as_slice(), &self.path, self.key.as_ref())?;
+            return Ok(users.into_iter().find(|stored| stored.user == user));
+        }
+        let _file = self.file.lock().unwrap();
+        let (users, _, _) = read_journal(&mut File::open(&self.path)?, &self.path, self.key.as_ref())?;
+        Ok(users.into_iter().find(|stored| stored.user == user))
+    }
+
+    /// rewrite the journal without `user`; false if they weren't in it.
+    /// The old journal stays in place until the new one is on disk
+    pub fn erase(&self, user: &str) -> io::Result<bool> {
+        Ok(self.remove(&[user.to_string()].into())? > 0)
+    }
+
+    /// rewrite the journal without `users` in one go, like erase; how many were in it
+    pub fn remove(&self, users: &HashSet<String>) -> io::Result<usize> {
+        self.timed("remove", || format!(" of {} users", users.len()), || self.remove_records(users))
+    }
+
+    fn remove_records(&self, users: &HashSet<String>) -> io::Result<usize> {
+        if self.is_read_only() {
+            return Err(io::Error::new(io::ErrorKind::PermissionDenied, "a read replica doesn't write the user store"));
+        }
+        #[cfg(feature = "sled")]
+        if let Some(db) = &self.sled {
+            let db = db.lock().unwrap();
+            let store = db.as_ref().ok_or_else(|| io::Error::other(self.degraded().unwrap_or_default()))?;
+            let mut removed = 0;
+            for user in users {
+                removed += store.remove(user)? as usize;
+                store.remove_sessions(user)?;
+            }
+            // erasures are on disk before they are acknowledged, whatever the flush policy
+            self.sync(|| store.flush())?;
+            return Ok(removed);
+        }
+        let mut file = self.file.lock().unwrap();
+        if file.is_none() {
+            return Err(io::Error::other(self.degraded().unwrap_or_default()));
+        }
+        let (mut kept, _, _) = read_journal(&mut File::open(&self.path)?, &self.path, self.key.as_ref())?;
+        let before = kept.len();
+        kept.retain(|stored| !users.contains(&stored.user));
+        if kept.len() == before {
+            return Ok(0);
+        }
+        *file = Some(rewrite_journal(&self.path, &kept, self.key.as_ref())?);
+        self.unsynced.store(0, Ordering::Relaxed);
+        Ok(before - kept.len())
+    }
+
+    /// when degraded, try the journal again; on success returns every user in it
+    /// (registrations made before the failure included)
+    pub fn recover(&self) -> io::Result<Option<Vec<StoredUser>>> {
+        if self.degraded().is_none() {
+            return Ok(None);
+        }
+        self.timed("recover", String::new, || self.reopen()).map(Some)
+    }
+
+    fn reopen(&self) -> io::Result<Vec<StoredUser>> {
+        #[cfg(feature = "sled")]
+        if let Some(db) = &self.sled {
+            let (store, users) = open_sled(&self.path, self.key.as_ref())?;
+            *db.lock().unwrap() = Some(store);
+            self.unsynced.store(0, Ordering::Relaxed); // whatever waited went with the failed database
+            *self.degraded.lock().unwrap() = None;
+            return Ok(users);
+        }
+        let (journal, users) = open_journal(&self.path, self.key.as_ref())?;
+        *self.file.lock().unwrap() = Some(journal);
+        self.unsynced.store(0, Ordering::Relaxed);
+        *self.degraded.lock().unwrap() = None;
+        Ok(users)
+    }
+}
+
+/// the user an operation was for, as `--privacy` lets the log show them
+fn for_user(user: &str) -> String {
+    format!(" for {:?}", telemetry::user(user))
+}
+
+/// a journal line (without its newline), sealed with `key` if there is one
+fn to_line(record: &impl Serialize, key: Option<&StoreKey>) -> String {
+    let json = serde_json::to_string(record).expect("records always serialize");
+    match key {
+        Some(key) => serde_json::json!({ "sealed": key.seal(json.as_bytes()), "key": key.id() }).to_string(),
+        None => json,
+    }
+}
+
+/// the journal opened for appending, and what it holds
+fn open_journal(path: &Path, key: Option<&StoreKey>) -> io::Result<(File, Vec<StoredUser>)> {
+    let created = !path.exists();
+    let mut file = OpenOptions::new().create(true).read(true).append(true).open(path)?;
+    if created {
+        sync_parent(path)?;
+    }
+
+    let (users, committed, plain) = read_journal(&mut file, path, key)?;
+    if file.metadata()?.len() > committed {
+        file.set_len(committed)?;
+        file.sync_data()?;
+    }
+    if let (Some(key), true) = (key, plain > 0) {
+        println!("🔐 {}: sealing {} plain records", path.display(), plain);
+        drop(file);
+        file = rewrite_journal(path, &users, Some(key))?;
+    }
+    Ok((file, users))
+}
+
+/// the users in the journal, the bytes up to its last complete line, and
+/// how many records aren't sealed
+fn read_journal(file: &mut File, path: &Path, key: Option<&StoreKey>) -> io::Result<(Vec<StoredUser>, u64, usize)> {
+    read_records(BufReader::new(file), path, key)
+}
+
+/// read_journal for journal lines from anywhere, such as a sled value
+fn read_records(mut reader: impl BufRead, path: &Path, key: Option<&StoreKey>) -> io::Result<(Vec<StoredUser>, u64, usize)> {
+    let mut users: Vec<StoredUser> = Vec::new();
+    let mut index = HashMap::new(); // user -> position in users
+    let mut committed = 0; // bytes up to the last complete line
+    let mut plain = 0; // records that still have to be sealed
+    let mut line = String::new();
+    for number in 0.. {
+        line.clear();
+        let read = reader.read_line(&mut line)?;
+        if read == 0 {
+            break;
+        }
+        if !line.ends_with('\n') {
+            println!("⚠️  {}: dropping a registration that was cut off by a crash", path.display());
+            break;
+        }
+        committed += read as u64;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let invalid = |reason: String| {
+            io::Error::new(io::ErrorKind::InvalidData, format!("{} line {}: {}", path.display(), number + 1, reason))
+        };
+        let mut record = serde_json::from_str(&line).map_err(|e| invalid(e.to_string()))?;
+        if let Record::Sealed { sealed, key: id } = record {
+            let Some(key) = key else {
+                return Err(invalid(format!("the record is sealed with key {}, and no --user-store-key was given", id)));
+            };
+            let opened = key.open(&sealed).ok_or_else(|| match id == key.id() {
+                true => invalid("the sealed record was altered".to_string()),
+                false => invalid(format!("the record is sealed with key {}, not {}", id, key.id())),
+            })?;
+            record = serde_json::from_slice(&opened).map_err(|e| invalid(e.to_string()))?;
+            if let Record::Sealed { .. } = record {
+                return Err(invalid("a sealed record inside a sealed record".to_string()));
+            }
+        } else {
+            plain += 1;
+        }
+        match record {
+            Record::Sealed { .. } => unreachable!("opened above"),
+            Record::User(user) if !user.is_valid() => return Err(invalid("not hex, or an unknown group or hash".to_string())),
+            Record::User(user) => {
+                index.insert(user.user.clone(), users.len());
+                users.push(user);
+            }
+            Record::Enabled { user, enabled } => match index.get(&user) {
+                Some(&position) => users[position].disabled = !enabled,
+                None => return Err(invalid(format!("{} is not registered", user))),
+            },
+            Record::Verified { user, verified } => match index.get(&user) {
+                Some(&position) => users[position].unverified = !verified,
+                None => return Err(invalid(format!("{} is not registered", user))),
+            },
+            Record::Device { device, .. } if !device.is_valid() => return Err(invalid("the device key is not hex".to_string())),
+            Record::Device { user, device } => match index.get(&user) {
+                Some(&position) => users[position].devices.push(device),
+                None => return Err(invalid(format!("{} is not registered", user))),
+            },
+            Record::RevokedDevice { user, revoked_device } => match index.get(&user) {
+                Some(&position) => users[position].devices.retain(|device| device.id != revoked_device),
+                None => return Err(invalid(format!("{} is not registered", user))),
+            },
+        }
+    }
+    Ok((users, committed, plain))
+}
+
+/// the sessions in a value of a sled store's `sessions` tree
+#[cfg(feature = "sled")]
+fn read_sessions(value: &[u8], path: &Path, key: Option<&StoreKey>) -> io::Result<SessionsRecord> {
+    let invalid = |reason: String| io::Error::new(io::ErrorKind::InvalidData, format!("{} sessions: {}", path.display(), reason));
+    match serde_json::from_slice(value).map_err(|e| invalid(e.to_string()))? {
+        SessionsValue::Plain(record) => Ok(record),
+        SessionsValue::Sealed { sealed, key: id } => {
+            let Some(key) = key else {
+                return Err(invalid(format!("they are sealed with key {}, and no --user-store-key was given", id)));
+            };
+            let opened = key.open(&sealed).ok_or_else(|| match id == key.id() {
+                true => invalid("the sealed sessions were altered".to_string()),
+                false => invalid(format!("they are sealed with key {}, not {}", id, key.id())),
+            })?;
+            serde_json::from_slice(&opened).map_err(|e| invalid(e.to_string()))
+        }
+    }
+}
+
+/// the sled database in `path` and the users in it; values with records
+/// that are no longer needed, or not yet sealed, are rewritten
+#[cfg(feature = "sled")]
+fn open_sled(path: &Path, key: Option<&StoreKey>) -> io::Result<(SledStore, Vec<StoredUser>)> {
+    let store = SledStore::open(path)?;
+    let mut users = Vec::new();
+    let mut compacted = 0;
+    for value in store.values()? {
+        let (read, _, plain) = read_records(value.as_slice(), path, key)?;
+        for user in read {
+            let lines = user_lines(&user, key);
+            let superseded = lines.lines().count() < value.split(|&b| b == b'\n').filter(|line| !line.is_empty()).count();
+            if superseded || (plain > 0 && key.is_some()) {
+                store.replace(&user.user, &lines)?;
+                compacted += 1;
+            }
+            users.push(user);
+        }
+    }
+    if compacted > 0 {
+        store.flush()?;
+        println!("🧹 {}: rewrote the records of {} users", path.display(), compacted);
+    }
+    Ok((store, users))
+}
+
+/// rewrite the journal to hold just `users` (sealed under `key` if there is
+/// one), replacing it only once the new copy is on disk
+fn rewrite_journal(path: &Path, users: &[StoredUser], key: Option<&StoreKey>) -> io::Result<File> {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".rewrite");
+    let rewrite = path.with_file_name(name);
+    let lines: String = users.iter().map(|user| user_lines(user, key)).collect();
+    let mut file = File::create(&rewrite)?;
+    file.write_all(lines.as_bytes())?;
+    file.sync_all()?;
+    std::fs::rename(&rewrite, path)?;
+    sync_parent(path)?;
+    OpenOptions::new().read(true).append(true).open(path)
+}
+
+/// the lines that bring back `user` as they are: the registration, then
+/// whether they are disabled and their devices
+fn user_lines(user: &StoredUser, key: Option<&StoreKey>) -> String {
+    let mut lines = format!("{}\n", to_line(user, key));
+    if user.disabled {
+        lines += &format!("{}\n", to_line(&serde_json::json!({ "user": user.user, "enabled": false }), key));
+    }
+    for device in &user.devices {
+        lines += &format!("{}\n", to_line(&serde_json::json!({ "user": user.user, "device": device }), key));
+    }
+    lines
+}
+
+/// make a new file's directory entry durable
+fn sync_parent(path: &Path) -> io::Result<()> {
+    #[cfg(unix)]
+    {
+        let parent = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        File::open(parent)?.sync_all()?;
+    }
+    #[cfg(not(unix))]
+    let _ = path;
+    Ok(())
+}
@@ -0,0 +1,64 @@
+//! Password strength estimates with zxcvbn, made on the client.
+//!
+//! [`DefaultPolicy`] catches short, uniform and top-of-the-list passwords.
+//! zxcvbn also spots dictionary words, names, dates, keyboard walks, l33t
+//! substitutions and repeats, and estimates how many guesses a password
+//! takes. Its score goes from 0 (guessed at once) to 4 (more than 10^10
+//! guesses). Like every password check here it runs where the password is:
+//! the server only ever sees (y1, y2), so a threshold can't be enforced
+//! anywhere else.
+
+use crate::password_policy::{DefaultPolicy, PasswordPolicy, Weakness};
+
+/// The best score there is
+pub const MAX_SCORE: u8 = 4;
+
+/// What zxcvbn makes of a password
+#[derive(Debug, Clone, PartialEq)]
+pub struct Strength {
+    pub score: u8, // 0 to MAX_SCORE
+    pub guesses_log10: f64,
+    pub crack_time: String, // offline at 10^4 guesses a second, as against a slow hash like Argon2id, e.g. "3 years"
+    pub warning: Option<String>, // what's wrong, for scores up to 2
+    pub suggestions: Vec<String>, // how to do better, for scores up to 2
+}
+
+/// estimate how hard `password` is to guess; parts of the username count
+/// against it
+pub fn estimate(user: &str, password: &str) -> Strength {
+    let mut inputs = vec![user];
+    inputs.extend(user.split(['@', '.', '_', '-', '+']).filter(|part| part.len() > 2));
+    let entropy = zxcvbn::zxcvbn(password, &inputs);
+    let feedback = entropy.feedback();
+    Strength {
+        score: u8::from(entropy.score()),
+        guesses_log10: entropy.guesses_log10(),
+        crack_time: entropy.crack_times().offline_slow_hashing_1e4_per_second().to_string(),
+        warning: feedback.and_then(|feedback| feedback.warning()).map(|warning| warning.to_string()),
+        suggestions: feedback.map(|feedback| feedback.suggestions().iter().map(ToString::to_string).collect()).unwrap_or_default(),
+    }
+}
+
+/// [`DefaultPolicy`], and a strength estimate of at least `min_score`
+#[derive(Debug, Clone, Default)]
+pub struct StrengthPolicy {
+    pub rules: DefaultPolicy,
+    pub min_score: u8, // 0 only reports the estimate
+}
+
+impl PasswordPolicy for StrengthPolicy {
+    /// the minimum score is the client's own choice, the server is told the
+    /// version of the rules it can count on
+    fn version(&self) -> u32 {
+        self.rules.version()
+    }
+
+    fn check(&self, user: &str, password: &str) -> Vec<Weakness> {
+        let mut weaknesses = self.rules.check(user, password);
+        let score = estimate(user, password).score;
+        if score < self.min_score {
+            weaknesses.push(Weakness::Guessable { score, min_score: self.min_score });
+        }
+        weaknesses
+    }
+}
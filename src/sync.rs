@@ -0,0 +1,15 @@
+//! Locks and atomics for state the server's handlers share.
+//!
+//! Built with `--cfg zkp_loom` these are loom's model-checked versions, so
+//! tests/loom_tests.rs can run the stores through every interleaving.
+
+#[cfg(zkp_loom)]
+pub(crate) use loom::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Mutex,
+};
+#[cfg(not(zkp_loom))]
+pub(crate) use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Mutex,
+};
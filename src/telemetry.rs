@@ -0,0 +1,178 @@
+//! What the server's console output may say about people.
+//!
+//! Log lines name users, peer addresses and devices, which turns whatever
+//! collects them (a log shipper, a tracing backend) into a store of personal
+//! data. The [`TelemetryPolicy`] set at startup (`--privacy`) decides how much
+//! of that gets out:
+//!
+//! - `debug` prints them as they are, as the server always did
+//! - `standard` replaces users and devices with a salted hash, the same for
+//!   the same value until a restart, and cuts addresses down to their network
+//!   (/24 for IPv4, /48 for IPv6)
+//! - `strict` leaves them out altogether
+//!
+//! The routine "Processing ..." lines can also be sampled per trace
+//! (`--log-sample-rate`), so every line of a sampled request shows up and
+//! none of the others do. Outcomes (successes, failures, alerts) are always
+//! logged. The audit trail (audit.rs) is not telemetry: it is the record of
+//! who did what, and keeps the names.
+
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::OnceLock;
+
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+
+use crate::trace::TraceContext;
+
+/// How much of a person the logs may show (--privacy)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum PrivacyLevel {
+    /// no users, addresses or devices at all
+    Strict,
+    /// users and devices hashed, addresses cut to their network
+    #[default]
+    Standard,
+    /// everything as is, for development
+    Debug,
+}
+
+/// How log lines treat personal data, and which routine lines are kept
+#[derive(Debug, Clone)]
+pub struct TelemetryPolicy {
+    pub level: PrivacyLevel,
+    pub sample_rate: f64, // share of traces whose routine lines are logged, 1.0 for all
+    salt: [u8; 16],
+}
+
+impl Default for TelemetryPolicy {
+    /// what a process that never called [`set`] gets: everything, as before
+    fn default() -> Self {
+        TelemetryPolicy::new(PrivacyLevel::Debug, 1.0)
+    }
+}
+
+impl TelemetryPolicy {
+    /// a policy with a fresh salt, so hashes can't be matched across restarts
+    pub fn new(level: PrivacyLevel, sample_rate: f64) -> Self {
+        let mut salt = [0u8; 16];
+        rand::thread_rng().fill_bytes(&mut salt);
+        TelemetryPolicy { level, sample_rate, salt }
+    }
+
+    /// a username or other account id
+    pub fn user(&self, user: &str) -> Scrubbed {
+        self.scrub("u", user)
+    }
+
+    /// a device id, or a description of a device
+    pub fn device(&self, device: &str) -> Scrubbed {
+        self.scrub("d", device)
+    }
+
+    /// a peer address, `ip:port` or a bare ip
+    pub fn source(&self, source: &str) -> Scrubbed {
+        let text = match self.level {
+            PrivacyLevel::Debug => return Scrubbed::raw(source),
+            PrivacyLevel::Strict => REDACTED.to_string(),
+            PrivacyLevel::Standard => match source.parse::<SocketAddr>().map(|addr| addr.ip()).or_else(|_| source.parse::<IpAddr>()) {
+                Ok(IpAddr::V4(ip)) => {
+                    let [a, b, c, _] = ip.octets();
+                    format!("{}/24", Ipv4Addr::new(a, b, c, 0))
+                }
+                Ok(IpAddr::V6(ip)) => {
+                    let [a, b, c, ..] = ip.segments();
+                    format!("{}/48", Ipv6Addr::new(a, b, c, 0, 0, 0, 0, 0))
+                }
+                Err(_) if source.is_empty() => String::new(),
+                Err(_) => self.hash("s", source),
+            },
+        };
+        Scrubbed { text, raw: false }
+    }
+
+    /// whether the routine lines of this trace are logged
+    pub fn sampled(&self, trace: &TraceContext) -> bool {
+        if self.sample_rate >= 1.0 {
+            return true;
+        }
+        // the random half of the trace id, so every replica decides the same
+        let id = u64::from_be_bytes(trace.trace_id[8..].try_into().unwrap());
+        (id as f64) < self.sample_rate * u64::MAX as f64
+    }
+
+    fn scrub(&self, kind: &str, value: &str) -> Scrubbed {
+        match self.level {
+            PrivacyLevel::Debug => Scrubbed::raw(value),
+            PrivacyLevel::Standard => Scrubbed { text: self.hash(kind, value), raw: false },
+            PrivacyLevel::Strict => Scrubbed { text: REDACTED.to_string(), raw: false },
+        }
+    }
+
+    /// `<kind>-` and 8 hex digits of SHA-256(salt | kind | value)
+    fn hash(&self, kind: &str, value: &str) -> String {
+        let digest = Sha256::new().chain_update(self.salt).chain_update(kind).chain_update([0]).chain_update(value).finalize();
+        format!("{}-{}", kind, hex::encode(&digest[..4]))
+    }
+}
+
+/// What stands in for something strict mode leaves out
+pub const REDACTED: &str = "[redacted]";
+
+/// A value as the policy lets it be logged; `{:?}` quotes it only when it is
+/// the original, so log lines look the same as before in debug mode
+pub struct Scrubbed {
+    text: String,
+    raw: bool,
+}
+
+impl Scrubbed {
+    fn raw(value: &str) -> Self {
+        Scrubbed { text: value.to_string(), raw: true }
+    }
+}
+
+impl fmt::Display for Scrubbed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+impl fmt::Debug for Scrubbed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.raw {
+            true => fmt::Debug::fmt(&self.text, f),
+            false => f.write_str(&self.text),
+        }
+    }
+}
+
+static POLICY: OnceLock<TelemetryPolicy> = OnceLock::new();
+
+/// set the policy for this process, once at startup; false if one was set already
+pub fn set(policy: TelemetryPolicy) -> bool {
+    POLICY.set(policy).is_ok()
+}
+
+/// the policy in force
+pub fn policy() -> &'static TelemetryPolicy {
+    POLICY.get_or_init(TelemetryPolicy::default)
+}
+
+/// shorthands for the policy in force
+pub fn user(user: &str) -> Scrubbed {
+    policy().user(user)
+}
+
+pub fn device(device: &str) -> Scrubbed {
+    policy().device(device)
+}
+
+pub fn source(source: &str) -> Scrubbed {
+    policy().source(source)
+}
+
+pub fn sampled(trace: &TraceContext) -> bool {
+    policy().sampled(trace)
+}
@@ -0,0 +1,229 @@
+//! TLS for the gRPC server with certificates that can be replaced at runtime.
+//!
+//! cert-manager and ACME clients renew short-lived certificates by rewriting
+//! the PEM files on disk. `CertReloader` holds the current certificate/key
+//! pair and answers every TLS handshake with it; `reload_if_changed` re-reads
+//! the files when their modification time moves, so the server picks up a
+//! renewed certificate without a restart. Connections that are already open
+//! keep the certificate they were established with.
+
+use std::fmt;
+use std::fs;
+use std::io::{self, BufReader};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::SystemTime;
+
+use tokio::io::AsyncWriteExt;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tokio_rustls::rustls::pki_types::CertificateDer;
+use tokio_rustls::rustls::server::{Acceptor, ClientHello, ResolvesServerCert};
+use tokio_rustls::rustls::sign::CertifiedKey;
+use tokio_rustls::rustls::{self, ServerConfig};
+use tokio_rustls::server::TlsStream;
+use tokio_rustls::LazyConfigAcceptor;
+use tokio_stream::wrappers::ReceiverStream;
+
+/// Serves the certificate currently on disk, swapping it when the files change
+pub struct CertReloader {
+    cert_path: PathBuf,
+    key_path: PathBuf,
+    state: RwLock<LoadedCert>,
+}
+
+struct LoadedCert {
+    key: Arc<CertifiedKey>,
+    cert_modified: Option<SystemTime>,
+    key_modified: Option<SystemTime>,
+}
+
+impl CertReloader {
+    /// load the certificate chain and private key (both PEM)
+    pub fn load(cert_path: impl Into<PathBuf>, key_path: impl Into<PathBuf>) -> io::Result<Self> {
+        let cert_path = cert_path.into();
+        let key_path = key_path.into();
+        let state = load_pair(&cert_path, &key_path)?;
+        Ok(Self {
+            cert_path,
+            key_path,
+            state: RwLock::new(state),
+        })
+    }
+
+    /// serve a certificate chain and private key held in memory (e.g. read from
+    /// Vault) rather than files; replace it with `install_pem`
+    pub fn from_pem(cert_pem: &str, key_pem: &str) -> io::Result<Self> {
+        let key = parse_pair(cert_pem.as_bytes(), key_pem.as_bytes())?;
+        Ok(Self {
+            cert_path: PathBuf::new(),
+            key_path: PathBuf::new(),
+            state: RwLock::new(LoadedCert { key: Arc::new(key), cert_modified: None, key_modified: None }),
+        })
+    }
+
+    /// swap in a new chain and key from memory; returns false (and changes
+    /// nothing) when they are what is served already. On error the old one stays
+    pub fn install_pem(&self, cert_pem: &str, key_pem: &str) -> io::Result<bool> {
+        let key = parse_pair(cert_pem.as_bytes(), key_pem.as_bytes())?;
+        let mut state = self.state.write().unwrap();
+        if state.key.cert == key.cert {
+            return Ok(false);
+        }
+        state.key = Arc::new(key);
+        Ok(true)
+    }
+
+    /// re-read the files if either one was modified since the last load
+    /// returns true when a new certificate was installed; on error the old one stays
+    pub fn reload_if_changed(&self) -> io::Result<bool> {
+        if self.cert_path.as_os_str().is_empty() {
+            return Ok(false); // from_pem, nothing on disk
+        }
+        let cert_modified = modified(&self.cert_path);
+        let key_modified = modified(&self.key_path);
+        {
+            let state = self.state.read().unwrap();
+            if state.cert_modified == cert_modified && state.key_modified == key_modified {
+                return Ok(false);
+            }
+        }
+
+        let loaded = load_pair(&self.cert_path, &self.key_path)?;
+        *self.state.write().unwrap() = loaded;
+        Ok(true)
+    }
+
+    /// the end-entity certificate handed out to new connections
+    pub fn current_certificate(&self) -> CertificateDer<'static> {
+        self.state.read().unwrap().key.cert[0].clone()
+    }
+
+    /// rustls configuration that asks this reloader for a certificate on every handshake
+    pub fn server_config(self: &Arc<Self>) -> Arc<ServerConfig> {
+        grpc_server_config(self.clone())
+    }
+}
+
+impl ResolvesServerCert for CertReloader {
+    fn resolve(&self, _client_hello: ClientHello) -> Option<Arc<CertifiedKey>> {
+        Some(self.state.read().unwrap().key.clone())
+    }
+}
+
+impl fmt::Debug for CertReloader {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CertReloader")
+            .field("cert_path", &self.cert_path)
+            .field("key_path", &self.key_path)
+            .finish()
+    }
+}
+
+/// server config for gRPC (HTTP/2 over ALPN) taking certificates from `resolver`
+pub fn grpc_server_config(resolver: Arc<dyn ResolvesServerCert>) -> Arc<ServerConfig> {
+    let mut config = ServerConfig::builder()
+        .with_no_client_auth()
+        .with_cert_resolver(resolver);
+    config.alpn_protocols = vec![b"h2".to_vec()];
+    Arc::new(config)
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+fn load_pair(cert_path: &Path, key_path: &Path) -> io::Result<LoadedCert> {
+    // take the timestamps first so a write racing with us triggers another reload
+    let cert_modified = modified(cert_path);
+    let key_modified = modified(key_path);
+
+    let key = parse_pair(&fs::read(cert_path)?, &fs::read(key_path)?)
+        .map_err(|e| invalid_data(format!("{} / {}: {}", cert_path.display(), key_path.display(), e)))?;
+    Ok(LoadedCert {
+        key: Arc::new(key),
+        cert_modified,
+        key_modified,
+    })
+}
+
+fn parse_pair(cert_pem: &[u8], key_pem: &[u8]) -> io::Result<CertifiedKey> {
+    let certs = rustls_pemfile::certs(&mut BufReader::new(cert_pem)).collect::<io::Result<Vec<_>>>()?;
+    if certs.is_empty() {
+        return Err(invalid_data("no certificate found".to_string()));
+    }
+    let key = rustls_pemfile::private_key(&mut BufReader::new(key_pem))?
+        .ok_or_else(|| invalid_data("no private key found".to_string()))?;
+    let signing_key = rustls::crypto::ring::sign::any_supported_type(&key)
+        .map_err(|e| invalid_data(format!("unsupported private key: {}", e)))?;
+    Ok(CertifiedKey::new(certs, signing_key))
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// What to do with a connection once its ClientHello has been seen
+pub enum Handshake {
+    /// finish the handshake with this config and hand the stream to the server
+    Serve(Arc<ServerConfig>),
+    /// finish the handshake with this config and close it (e.g. ACME tls-alpn-01 validation)
+    Answer(Arc<ServerConfig>),
+}
+
+/// accept TCP connections and terminate TLS on them, for `Server::serve_with_incoming`
+/// handshakes run on their own tasks so a slow client can't hold up the accept loop,
+/// and failed handshakes are simply dropped (the stream never yields an error)
+pub fn incoming(
+    listener: TcpListener,
+    config: Arc<ServerConfig>,
+) -> ReceiverStream<io::Result<TlsStream<TcpStream>>> {
+    incoming_with(listener, move |_| Handshake::Serve(config.clone()))
+}
+
+/// like `incoming`, but picks the rustls config per connection from the ClientHello
+pub fn incoming_with<F>(listener: TcpListener, choose: F) -> ReceiverStream<io::Result<TlsStream<TcpStream>>>
+where
+    F: Fn(&ClientHello) -> Handshake + Send + Sync + 'static,
+{
+    let (tx, rx) = mpsc::channel(128);
+    let choose = Arc::new(choose);
+
+    tokio::spawn(async move {
+        loop {
+            let stream = match listener.accept().await {
+                Ok((stream, _)) => stream,
+                // e.g. out of file descriptors: back off instead of spinning or
+                // handing the error to tonic, which would stop serving
+                Err(_) => {
+                    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+                    continue;
+                }
+            };
+            if tx.is_closed() {
+                return;
+            }
+            let choose = choose.clone();
+            let tx = tx.clone();
+            tokio::spawn(async move {
+                let Ok(start) = LazyConfigAcceptor::new(Acceptor::default(), stream).await else {
+                    return;
+                };
+                match choose(&start.client_hello()) {
+                    Handshake::Serve(config) => {
+                        if let Ok(tls) = start.into_stream(config).await {
+                            let _ = tx.send(Ok(tls)).await;
+                        }
+                    }
+                    Handshake::Answer(config) => {
+                        if let Ok(mut tls) = start.into_stream(config).await {
+                            let _ = tls.shutdown().await;
+                        }
+                    }
+                }
+            });
+        }
+    });
+
+    ReceiverStream::new(rx)
+}
@@ -0,0 +1,254 @@
+//! Trust on first use, for people who can't deploy a real PKI.
+//!
+//! Works like SSH's known_hosts: the first time we talk to an endpoint we
+//! remember its TLS certificate fingerprint and its parameter signing key,
+//! and every later connection must present the same ones. A change means the
+//! server was re-keyed or someone is in the middle, and the client stops
+//! until the user explicitly forgets the old entry.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::net::TcpStream;
+use tokio_rustls::rustls::client::danger::{
+    HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier,
+};
+use tokio_rustls::rustls::crypto::{ring, verify_tls12_signature, verify_tls13_signature};
+use tokio_rustls::rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use tokio_rustls::rustls::{self, ClientConfig, DigitallySignedStruct, SignatureScheme};
+use tokio_rustls::TlsConnector;
+use tonic::transport::{Channel, Endpoint, Uri};
+
+use crate::profile::ProfileStore;
+
+/// What we remember about one endpoint
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KnownServer {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tls_fingerprint: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub params_key: Option<String>,
+}
+
+/// The known servers file, keyed by endpoint
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KnownServers {
+    #[serde(default)]
+    pub servers: BTreeMap<String, KnownServer>,
+}
+
+/// Outcome of comparing what a server presented with what we remember
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Trust {
+    /// never seen before, now remembered
+    FirstUse,
+    /// same as last time
+    Known,
+    /// different from last time: possible MITM
+    Changed { known: String },
+}
+
+impl KnownServers {
+    /// known_servers.json next to the profiles file
+    pub fn default_path() -> PathBuf {
+        ProfileStore::default_path().with_file_name("known_servers.json")
+    }
+
+    /// load the known servers, a missing file means we haven't met anyone yet
+    pub fn load(path: &Path) -> io::Result<Self> {
+        match fs::read(path) {
+            Ok(bytes) => serde_json::from_slice(&bytes)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// write the known servers back to disk, creating the directory if needed
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            if !dir.as_os_str().is_empty() {
+                fs::create_dir_all(dir)?;
+            }
+        }
+        let json = serde_json::to_vec_pretty(self)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        fs::write(path, json)
+    }
+
+    pub fn get(&self, endpoint: &str) -> Option<&KnownServer> {
+        self.servers.get(endpoint)
+    }
+
+    /// compare a TLS fingerprint with the remembered one, remembering it on first use
+    pub fn check_tls(&mut self, endpoint: &str, fingerprint: &str) -> Trust {
+        let entry = self.servers.entry(endpoint.to_string()).or_default();
+        check(&mut entry.tls_fingerprint, fingerprint)
+    }
+
+    /// compare a parameter signing key with the remembered one, remembering it on first use
+    pub fn check_params_key(&mut self, endpoint: &str, key: &str) -> Trust {
+        let entry = self.servers.entry(endpoint.to_string()).or_default();
+        check(&mut entry.params_key, key)
+    }
+
+    /// drop everything we know about an endpoint (after a legitimate re-key)
+    pub fn forget(&mut self, endpoint: &str) -> bool {
+        self.servers.remove(endpoint).is_some()
+    }
+}
+
+fn check(slot: &mut Option<String>, presented: &str) -> Trust {
+    match slot {
+        None => {
+            *slot = Some(presented.to_string());
+            Trust::FirstUse
+        }
+        Some(known) if known == presented => Trust::Known,
+        Some(known) => Trust::Changed { known: known.clone() },
+    }
+}
+
+/// SHA-256 over the DER certificate, hex encoded
+pub fn fingerprint(certificate: &[u8]) -> String {
+    hex::encode(Sha256::digest(certificate))
+}
+
+/// Accepts any certificate whose fingerprint matches the pin (or any at all
+/// when nothing is pinned yet) and records what the server presented.
+/// Handshake signatures are still checked, so the server must hold the key.
+#[derive(Debug)]
+pub struct PinningVerifier {
+    pinned: Option<String>,
+    presented: Mutex<Option<String>>,
+}
+
+impl PinningVerifier {
+    pub fn new(pinned: Option<String>) -> Self {
+        Self {
+            pinned,
+            presented: Mutex::new(None),
+        }
+    }
+
+    /// fingerprint of the certificate the server presented in the last handshake
+    pub fn presented(&self) -> Option<String> {
+        self.presented.lock().unwrap().clone()
+    }
+}
+
+impl ServerCertVerifier for PinningVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let presented = fingerprint(end_entity);
+        *self.presented.lock().unwrap() = Some(presented.clone());
+        match &self.pinned {
+            Some(pinned) if *pinned != presented => Err(rustls::Error::General(
+                "server certificate does not match the pinned fingerprint".to_string(),
+            )),
+            _ => Ok(ServerCertVerified::assertion()),
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls12_signature(message, cert, dss, &ring::default_provider().signature_verification_algorithms)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls13_signature(message, cert, dss, &ring::default_provider().signature_verification_algorithms)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        ring::default_provider()
+            .signature_verification_algorithms
+            .supported_schemes()
+    }
+}
+
+/// Why a pinned connection failed
+#[derive(Debug)]
+pub enum TofuError {
+    /// the server presented a different certificate than last time
+    Changed { known: String, presented: String },
+    Connect(tonic::transport::Error),
+}
+
+impl fmt::Display for TofuError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TofuError::Changed { known, presented } => write!(
+                f,
+                "server certificate changed (known {}, presented {})",
+                known, presented
+            ),
+            TofuError::Connect(e) => write!(f, "could not connect: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for TofuError {}
+
+/// connect to an https endpoint trusting only the pinned certificate fingerprint
+/// (or whatever the server presents when `pinned` is None); returns the channel
+/// and the fingerprint that was seen, so the caller can remember it
+pub async fn connect_pinned(endpoint: &str, pinned: Option<String>) -> Result<(Channel, String), TofuError> {
+    let verifier = Arc::new(PinningVerifier::new(pinned.clone()));
+    let mut config = ClientConfig::builder()
+        .dangerous()
+        .with_custom_certificate_verifier(verifier.clone())
+        .with_no_client_auth();
+    config.alpn_protocols = vec![b"h2".to_vec()];
+    let connector = TlsConnector::from(Arc::new(config));
+
+    // tonic would wrap an https uri in its own TLS on top of ours, so dial the
+    // plain uri and keep the real one as the origin of the requests
+    let plain = Endpoint::from_shared(endpoint.replacen("https://", "http://", 1))
+        .map_err(TofuError::Connect)?;
+    let origin = Uri::try_from(endpoint).expect("a valid uri stays valid with another scheme");
+    let channel = plain
+        .origin(origin)
+        .connect_with_connector(tower::service_fn(move |uri: Uri| {
+            let connector = connector.clone();
+            async move {
+                let host = uri.host().unwrap_or_default().to_string();
+                let port = uri.port_u16().unwrap_or(443);
+                let tcp = TcpStream::connect((host.as_str(), port)).await?;
+                let name = ServerName::try_from(host)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+                connector.connect(name, tcp).await
+            }
+        }))
+        .await;
+
+    let presented = verifier.presented();
+    match (channel, pinned, presented) {
+        (Ok(channel), _, Some(presented)) => Ok((channel, presented)),
+        (Err(_), Some(known), Some(presented)) if known != presented => {
+            Err(TofuError::Changed { known, presented })
+        }
+        (Err(e), _, _) => Err(TofuError::Connect(e)),
+        (Ok(_), _, None) => unreachable!("a TLS handshake always presents a certificate"),
+    }
+}
@@ -0,0 +1,152 @@
+//! W3C trace context (`traceparent`) for following one login across processes.
+//!
+//! The client starts a trace per command and sends a `traceparent` header
+//! with every RPC. The server continues the caller's trace (or starts one when
+//! the header is missing or malformed), tags its log lines with the trace id
+//! and sends its own span back in the response metadata, so a failed login
+//! can be found in the server logs from the id the client printed.
+//!
+//! Format: https://www.w3.org/TR/trace-context/#traceparent-header
+
+use std::fmt;
+
+use rand::RngCore;
+use tonic::metadata::{MetadataMap, MetadataValue};
+use tonic::{Request, Response, Status};
+
+/// Metadata key carrying the trace context
+pub const TRACEPARENT: &str = "traceparent";
+
+/// A position in a trace: which trace, and which span within it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TraceContext {
+    pub trace_id: [u8; 16],
+    pub span_id: [u8; 8],
+    pub sampled: bool,
+}
+
+impl TraceContext {
+    /// start a new trace
+    pub fn new_root() -> Self {
+        let mut trace_id = [0u8; 16];
+        while trace_id == [0u8; 16] {
+            rand::thread_rng().fill_bytes(&mut trace_id);
+        }
+        TraceContext {
+            trace_id,
+            span_id: new_span_id(),
+            sampled: true,
+        }
+    }
+
+    /// a new span in the same trace, e.g. for one outgoing call
+    pub fn child(&self) -> Self {
+        TraceContext {
+            span_id: new_span_id(),
+            ..*self
+        }
+    }
+
+    /// parse a `traceparent` value; None for anything we don't understand
+    pub fn parse(header: &str) -> Option<Self> {
+        let mut fields = header.trim().split('-');
+        let version = decode::<1>(fields.next()?)?;
+        let trace_id = decode::<16>(fields.next()?)?;
+        let span_id = decode::<8>(fields.next()?)?;
+        let flags = decode::<1>(fields.next()?)?;
+        // version 00 has exactly four fields, later versions may append more
+        let valid = match version[0] {
+            0x00 => fields.next().is_none(),
+            0xff => false,
+            _ => true,
+        };
+        if !valid || trace_id == [0u8; 16] || span_id == [0u8; 8] {
+            return None;
+        }
+        Some(TraceContext {
+            trace_id,
+            span_id,
+            sampled: flags[0] & 0x01 == 0x01,
+        })
+    }
+
+    pub fn trace_id_hex(&self) -> String {
+        hex::encode(self.trace_id)
+    }
+
+    /// the caller's context from request metadata
+    pub fn from_metadata(metadata: &MetadataMap) -> Option<Self> {
+        metadata
+            .get(TRACEPARENT)
+            .and_then(|value| value.to_str().ok())
+            .and_then(Self::parse)
+    }
+
+    pub fn inject(&self, metadata: &mut MetadataMap) {
+        let value = MetadataValue::try_from(self.to_string()).expect("traceparent is always ascii");
+        metadata.insert(TRACEPARENT, value);
+    }
+
+    /// wrap an outgoing message in a request carrying a new child span
+    pub fn request<T>(&self, message: T) -> Request<T> {
+        let mut request = Request::new(message);
+        self.child().inject(request.metadata_mut());
+        request
+    }
+
+    /// wrap a reply so the caller learns our span
+    pub fn response<T>(&self, message: T) -> Response<T> {
+        let mut response = Response::new(message);
+        self.inject(response.metadata_mut());
+        response
+    }
+}
+
+impl fmt::Display for TraceContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "00-{}-{}-{:02x}",
+            hex::encode(self.trace_id),
+            hex::encode(self.span_id),
+            self.sampled as u8
+        )
+    }
+}
+
+fn new_span_id() -> [u8; 8] {
+    let mut span_id = [0u8; 8];
+    while span_id == [0u8; 8] {
+        rand::thread_rng().fill_bytes(&mut span_id);
+    }
+    span_id
+}
+
+/// lowercase hex of exactly N bytes, as the spec requires
+fn decode<const N: usize>(field: &str) -> Option<[u8; N]> {
+    if field.len() != N * 2 || field.bytes().any(|b| b.is_ascii_uppercase()) {
+        return None;
+    }
+    hex::decode(field).ok()?.try_into().ok()
+}
+
+/// Server interceptor: continue the caller's trace in a new server span (or
+/// start a trace) and make it available to handlers through [`current`]
+#[allow(clippy::result_large_err)] // the signature tonic interceptors have
+pub fn server_interceptor(mut request: Request<()>) -> Result<Request<()>, Status> {
+    let context = match TraceContext::from_metadata(request.metadata()) {
+        Some(parent) => parent.child(),
+        None => TraceContext::new_root(),
+    };
+    request.extensions_mut().insert(context);
+    Ok(request)
+}
+
+/// the trace context the interceptor attached to this request
+pub fn current<T>(request: &Request<T>) -> TraceContext {
+    request
+        .extensions()
+        .get::<TraceContext>()
+        .copied()
+        .unwrap_or_else(TraceContext::new_root)
+}
@@ -0,0 +1,141 @@
+//! Typed values of the protocol.
+//!
+//! Every number in Chaum-Pedersen is a `BigUint`, which makes it easy to hand
+//! `y1` to a parameter that wants `r1`. These newtypes give each role its own
+//! type so such mix-ups fail to compile; the raw numbers stay reachable
+//! through the public fields and the byte conversions used on the wire.
+
+use num_bigint::BigUint;
+
+use crate::sigma::{ChaumPedersen, SigmaProtocol};
+use crate::ZKP;
+
+/// The registration values (y1, y2) = (alpha^x, beta^x) mod p
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PublicKey {
+    pub y1: BigUint,
+    pub y2: BigUint,
+}
+
+/// The prover's per-login values (r1, r2) = (alpha^k, beta^k) mod p
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Commitment {
+    pub r1: BigUint,
+    pub r2: BigUint,
+}
+
+/// The verifier's challenge c
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Challenge(pub BigUint);
+
+/// The prover's answer s = k - c * x mod q
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Response(pub BigUint);
+
+impl PublicKey {
+    /// parse big-endian bytes as sent in `RegisterRequest`
+    pub fn from_bytes_be(y1: &[u8], y2: &[u8]) -> Self {
+        PublicKey {
+            y1: BigUint::from_bytes_be(y1),
+            y2: BigUint::from_bytes_be(y2),
+        }
+    }
+
+    /// (y1, y2) as big-endian bytes
+    pub fn to_bytes_be(&self) -> (Vec<u8>, Vec<u8>) {
+        (self.y1.to_bytes_be(), self.y2.to_bytes_be())
+    }
+}
+
+impl Commitment {
+    /// parse big-endian bytes as sent in `AuthenticationChallengeRequest`
+    pub fn from_bytes_be(r1: &[u8], r2: &[u8]) -> Self {
+        Commitment {
+            r1: BigUint::from_bytes_be(r1),
+            r2: BigUint::from_bytes_be(r2),
+        }
+    }
+
+    /// (r1, r2) as big-endian bytes
+    pub fn to_bytes_be(&self) -> (Vec<u8>, Vec<u8>) {
+        (self.r1.to_bytes_be(), self.r2.to_bytes_be())
+    }
+}
+
+impl From<(BigUint, BigUint)> for PublicKey {
+    fn from((y1, y2): (BigUint, BigUint)) -> Self {
+        PublicKey { y1, y2 }
+    }
+}
+
+impl From<(BigUint, BigUint)> for Commitment {
+    fn from((r1, r2): (BigUint, BigUint)) -> Self {
+        Commitment { r1, r2 }
+    }
+}
+
+macro_rules! scalar {
+    ($name:ident) => {
+        impl $name {
+            pub fn from_bytes_be(bytes: &[u8]) -> Self {
+                $name(BigUint::from_bytes_be(bytes))
+            }
+
+            pub fn to_bytes_be(&self) -> Vec<u8> {
+                self.0.to_bytes_be()
+            }
+
+            pub fn into_inner(self) -> BigUint {
+                self.0
+            }
+        }
+
+        impl From<BigUint> for $name {
+            fn from(value: BigUint) -> Self {
+                $name(value)
+            }
+        }
+
+        impl AsRef<BigUint> for $name {
+            fn as_ref(&self) -> &BigUint {
+                &self.0
+            }
+        }
+    };
+}
+
+scalar!(Challenge);
+scalar!(Response);
+
+impl ZKP {
+    /// the public key for secret `x`, registered once per account
+    pub fn public_key(&self, x: &BigUint) -> PublicKey {
+        self.compute_pair(x).into()
+    }
+
+    /// the commitment for a fresh random nonce `k`
+    pub fn commitment(&self, k: &BigUint) -> Commitment {
+        self.compute_pair(k).into()
+    }
+
+    /// a uniformly random challenge below q
+    pub fn random_challenge(&self) -> Challenge {
+        Challenge(ZKP::generate_random_number_below(&self.q))
+    }
+
+    /// answer challenge `c` for nonce `k` and secret `x`
+    pub fn respond(&self, k: &BigUint, c: &Challenge, x: &BigUint) -> Response {
+        ChaumPedersen(self).respond(&PublicKey::default(), x, k.clone(), c)
+    }
+
+    /// check `s` answers `c` for this commitment under `key`
+    pub fn verify_response(
+        &self,
+        key: &PublicKey,
+        commitment: &Commitment,
+        c: &Challenge,
+        s: &Response,
+    ) -> bool {
+        ChaumPedersen(self).verify(key, commitment, c, s)
+    }
+}
@@ -0,0 +1,106 @@
+//! Time-ordered auth_ids (ULIDs).
+//!
+//! A ULID is a 48-bit millisecond timestamp followed by 80 random bits,
+//! written as 26 Crockford base32 characters. Ids sort by the time they were
+//! issued, so the server can drop every expired challenge with one range
+//! split, and an id in a log says when its challenge was handed out.
+
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::clock::unix_millis;
+use crate::rng;
+
+const ALPHABET: &[u8; 32] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+const LEN: usize = 26;
+const RANDOM_BITS: u32 = 80;
+
+/// A 128-bit ULID
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Ulid(u128);
+
+impl Ulid {
+    pub fn new(millis: u64, random: u128) -> Self {
+        Ulid(((millis as u128) << RANDOM_BITS) | (random & ((1 << RANDOM_BITS) - 1)))
+    }
+
+    /// the smallest id issued at `time`: every later id sorts after it
+    pub fn lower_bound(time: SystemTime) -> Self {
+        Ulid::new(unix_millis(time), 0)
+    }
+
+    pub fn timestamp_millis(&self) -> u64 {
+        (self.0 >> RANDOM_BITS) as u64
+    }
+
+    /// when the id was issued
+    pub fn issued_at(&self) -> SystemTime {
+        UNIX_EPOCH + Duration::from_millis(self.timestamp_millis())
+    }
+}
+
+impl fmt::Display for Ulid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let text: String = (0..LEN)
+            .map(|i| ALPHABET[((self.0 >> (5 * (LEN - 1 - i))) & 31) as usize] as char)
+            .collect();
+        f.write_str(&text)
+    }
+}
+
+/// Not a 26-character Crockford base32 ULID
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidUlid;
+
+impl fmt::Display for InvalidUlid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "not a ULID")
+    }
+}
+
+impl std::error::Error for InvalidUlid {}
+
+impl FromStr for Ulid {
+    type Err = InvalidUlid;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // the first character only carries 3 bits
+        if s.len() != LEN || s.as_bytes()[0] > b'7' {
+            return Err(InvalidUlid);
+        }
+        s.bytes().try_fold(0u128, |value, byte| {
+            let digit = ALPHABET.iter().position(|&c| c == byte.to_ascii_uppercase()).ok_or(InvalidUlid)?;
+            Ok((value << 5) | digit as u128)
+        })
+        .map(Ulid)
+    }
+}
+
+/// Hands out strictly increasing ULIDs, even within one millisecond or when
+/// the wall clock steps back
+#[derive(Debug, Default)]
+pub struct UlidGenerator {
+    last: Mutex<Option<Ulid>>,
+}
+
+impl UlidGenerator {
+    pub fn next(&self, now: SystemTime) -> Ulid {
+        let mut last = self.last.lock().unwrap();
+        let id = match *last {
+            Some(previous) if previous.timestamp_millis() >= unix_millis(now) => Ulid(previous.0 + 1),
+            _ => Ulid::new(unix_millis(now), random_bits()),
+        };
+        *last = Some(id);
+        id
+    }
+}
+
+fn random_bits() -> u128 {
+    rng::with_rng(|rng| {
+        let mut bytes = [0u8; 16];
+        rng.fill_bytes(&mut bytes);
+        u128::from_be_bytes(bytes)
+    })
+}
@@ -0,0 +1,214 @@
+//! What the `user` field of a request identifies.
+//!
+//! By default it is a free-form username, taken as is. `--user-ids email`
+//! makes it an email address: it is lowercased before accounts are looked up,
+//! and a new account can't log in until it has entered the code sent to the
+//! address (VerifyEmail). `--user-ids subject` has the server issue a random
+//! UUID at registration, which the client then logs in with.
+//! `--user-ids fingerprint` takes no name at all: an account is known by a
+//! fingerprint of its public key (y1, y2), for pseudonymous accounts that
+//! link to nothing but the key. Register and VerifyAuthentication answer with
+//! the canonical subject either way, so integrators key their own records on
+//! what the server keeps.
+
+use std::fmt;
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+use ring::rand::{SecureRandom, SystemRandom};
+use tonic::Status;
+
+use crate::group::GroupId;
+use crate::types::PublicKey;
+
+/// How long a verification code can be entered
+pub const CODE_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+/// Wrong codes before the code is spent and a new one has to be sent
+pub const MAX_ATTEMPTS: u32 = 5;
+/// How often a new code can be sent to one address
+pub const RESEND_INTERVAL: Duration = Duration::from_secs(60);
+
+/// What the `user` field holds (--user-ids)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum UserIds {
+    /// any string the client picks, as is
+    #[default]
+    Username,
+    /// an email address, verified before the first login
+    Email,
+    /// a UUID the server issues at registration
+    Subject,
+    /// none: the fingerprint of the public key, see [`fingerprint`]
+    Fingerprint,
+}
+
+impl UserIds {
+    /// as ServerInfoResponse.user_ids has it
+    pub fn name(self) -> &'static str {
+        match self {
+            UserIds::Username => "username",
+            UserIds::Email => "email",
+            UserIds::Subject => "subject",
+            UserIds::Fingerprint => "fingerprint",
+        }
+    }
+
+    /// `user` as a client sent it, in the form accounts are kept under
+    pub fn canonical(self, user: &str) -> Result<String, UserIdError> {
+        if user.is_empty() {
+            return Err(UserIdError::Empty);
+        }
+        match self {
+            UserIds::Username => Ok(user.to_string()),
+            UserIds::Email => email(user).ok_or(UserIdError::NotEmail),
+            UserIds::Subject => subject(user).ok_or(UserIdError::NotSubject),
+            UserIds::Fingerprint => hex_fingerprint(user).ok_or(UserIdError::NotFingerprint),
+        }
+    }
+}
+
+impl fmt::Display for UserIds {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// A `user` that isn't an id of the server's kind
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UserIdError {
+    Empty,
+    NotEmail,
+    NotSubject,
+    NotFingerprint,
+}
+
+impl fmt::Display for UserIdError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            UserIdError::Empty => "user must not be empty",
+            UserIdError::NotEmail => "user must be an email address",
+            UserIdError::NotSubject => "user must be a subject (a UUID) issued at registration",
+            UserIdError::NotFingerprint => "user must be a public key fingerprint (64 hex digits)",
+        })
+    }
+}
+
+impl std::error::Error for UserIdError {}
+
+impl From<UserIdError> for Status {
+    fn from(e: UserIdError) -> Self {
+        Status::invalid_argument(e.to_string())
+    }
+}
+
+/// the address lowercased, None unless it looks like local@domain.tld; the
+/// local part is lowercased too, which every mail provider that matters
+/// treats the same way
+fn email(user: &str) -> Option<String> {
+    let (local, domain) = user.split_once('@')?;
+    let valid = user.len() <= 254
+        && (1..=64).contains(&local.len())
+        && !domain.contains('@')
+        && domain.contains('.')
+        && domain.split('.').all(|label| !label.is_empty())
+        && !user.chars().any(|c| c.is_whitespace() || c.is_control());
+    valid.then(|| user.to_lowercase())
+}
+
+/// the UUID in lowercase, None unless it is 8-4-4-4-12 hex digits
+fn subject(user: &str) -> Option<String> {
+    let valid = user.len() == 36
+        && user.char_indices().all(|(i, c)| match i {
+            8 | 13 | 18 | 23 => c == '-',
+            _ => c.is_ascii_hexdigit(),
+        });
+    valid.then(|| user.to_ascii_lowercase())
+}
+
+/// the fingerprint in lowercase, None unless it is 64 hex digits
+fn hex_fingerprint(user: &str) -> Option<String> {
+    (user.len() == 64 && user.bytes().all(|b| b.is_ascii_hexdigit())).then(|| user.to_ascii_lowercase())
+}
+
+/// The id of an account registered with (y1, y2) in `group`: the full hex
+/// [`Fingerprint`](crate::fingerprint::Fingerprint) of the key
+pub fn fingerprint(group: GroupId, y1: &[u8], y2: &[u8]) -> String {
+    PublicKey::from_bytes_be(y1, y2).fingerprint_in(group).to_hex()
+}
+
+/// a fresh random (version 4) UUID for a new account
+pub fn new_subject() -> String {
+    let mut bytes = [0u8; 16];
+    SystemRandom::new().fill(&mut bytes).expect("the OS has randomness");
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    let hex = hex::encode(bytes);
+    format!("{}-{}-{}-{}-{}", &hex[..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..])
+}
+
+/// A code sent to an address, until it is entered or spent
+#[derive(Debug, Clone)]
+pub struct VerificationCode {
+    code: String, // 8 digits
+    sent_at: Instant,
+    attempts: u32, // wrong ones
+}
+
+impl VerificationCode {
+    pub fn new(now: Instant) -> Self {
+        let mut bytes = [0u8; 8];
+        SystemRandom::new().fill(&mut bytes).expect("the OS has randomness");
+        // the bias of a 64-bit number mod 10^8 is far below a guess's chance
+        let code = format!("{:08}", u64::from_be_bytes(bytes) % 100_000_000);
+        VerificationCode { code, sent_at: now, attempts: 0 }
+    }
+
+    pub fn code(&self) -> &str {
+        &self.code
+    }
+
+    /// whether another code may be sent instead of this one
+    pub fn can_resend(&self, now: Instant) -> bool {
+        now.duration_since(self.sent_at) >= RESEND_INTERVAL
+    }
+
+    /// whether `code` is this one; a wrong guess counts, and past
+    /// MAX_ATTEMPTS or CODE_TTL nothing matches any more
+    pub fn matches(&mut self, code: &str, now: Instant) -> bool {
+        if self.attempts >= MAX_ATTEMPTS || now.duration_since(self.sent_at) >= CODE_TTL {
+            return false;
+        }
+        let matches = code.len() == self.code.len()
+            && code.bytes().zip(self.code.bytes()).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0;
+        if !matches {
+            self.attempts += 1;
+        }
+        matches
+    }
+}
+
+/// Delivers verification codes by running a shell command
+/// (--email-verification-command) with the address in `ZKP_EMAIL` and the
+/// code in `ZKP_CODE`; they are never put into the command line itself
+#[derive(Debug, Clone)]
+pub struct CodeSender {
+    pub command: String,
+}
+
+impl CodeSender {
+    /// run the command on a thread of its own, logging a failure
+    pub fn send(&self, email: &str, code: &str) {
+        let mut command = Command::new("sh");
+        command
+            .args(["-c", &self.command])
+            .env("ZKP_EMAIL", email)
+            .env("ZKP_CODE", code)
+            .stdin(Stdio::null());
+        let email = email.to_string();
+        std::thread::spawn(move || match command.status() {
+            Ok(status) if status.success() => {}
+            Ok(status) => println!("⚠️  The verification code for {:?} wasn't sent: the command {}", email, status),
+            Err(e) => println!("⚠️  The verification code for {:?} wasn't sent: {}", email, e),
+        });
+    }
+}
@@ -0,0 +1,92 @@
+//! Checking requests against the constraints declared in the proto files.
+//!
+//! Fields carry `(zkp_auth.validate.rules)` options (non-empty, byte-length
+//! bounds, see proto/zkp_auth/validate.proto), and build.rs turns them into a
+//! [`Validate`] impl for every message. The server wraps its handlers in
+//! [`Validated`], which rejects a request that breaks a rule with
+//! `InvalidArgument` before the handler runs. New RPCs and fields are covered
+//! as soon as their rules are in the proto. Streamed requests (BulkRegister)
+//! are checked one at a time by the handler, so one bad item doesn't end the
+//! stream.
+
+use std::fmt;
+use std::sync::Arc;
+
+use tonic::{Request, Status};
+
+/// A message that can be checked against its field rules
+pub trait Validate {
+    fn validate(&self) -> Result<(), Violation>;
+}
+
+/// The first rule a message broke
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Violation {
+    pub field: String, // dotted path, e.g. client.device_id
+    pub reason: String,
+}
+
+impl Violation {
+    /// the same violation, for a field inside `field`
+    fn within(self, field: &str) -> Self {
+        Violation { field: format!("{}.{}", field, self.field), ..self }
+    }
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.field, self.reason)
+    }
+}
+
+impl std::error::Error for Violation {}
+
+impl From<Violation> for Status {
+    fn from(violation: Violation) -> Self {
+        Status::invalid_argument(violation.to_string())
+    }
+}
+
+/// A service whose requests are checked before `T` handles them
+#[derive(Debug)]
+pub struct Validated<T>(pub Arc<T>);
+
+/// InvalidArgument for a request that breaks a rule
+#[allow(clippy::result_large_err)] // same error type as the handlers
+pub fn check<M: Validate>(request: &Request<M>) -> Result<(), Status> {
+    Ok(request.get_ref().validate()?)
+}
+
+/// a string field; lengths are in bytes
+pub fn text(field: &str, value: &str, required: bool, min_len: u32, max_len: u32) -> Result<(), Violation> {
+    bytes(field, value.as_bytes(), required, min_len, max_len)
+}
+
+/// a bytes field
+pub fn bytes(field: &str, value: &[u8], required: bool, min_len: u32, max_len: u32) -> Result<(), Violation> {
+    let violation = |reason: String| Err(Violation { field: field.to_string(), reason });
+    match value.len() {
+        0 if required => violation("must not be empty".to_string()),
+        0 => Ok(()),
+        len if len < min_len as usize => violation(format!("must be at least {} bytes", min_len)),
+        len if max_len > 0 && len > max_len as usize => violation(format!("must be at most {} bytes", max_len)),
+        _ => Ok(()),
+    }
+}
+
+/// a message field, checked with its own rules when set
+pub fn message<M: Validate>(field: &str, value: Option<&M>, required: bool) -> Result<(), Violation> {
+    match value {
+        Some(value) => value.validate().map_err(|violation| violation.within(field)),
+        None if required => Err(Violation { field: field.to_string(), reason: "must be set".to_string() }),
+        None => Ok(()),
+    }
+}
+
+/// a repeated message field, every item with its own rules
+pub fn each<M: Validate>(field: &str, values: &[M]) -> Result<(), Violation> {
+    for (i, value) in values.iter().enumerate() {
+        value.validate().map_err(|violation| violation.within(&format!("{}[{}]", field, i)))?;
+    }
+    Ok(())
+}
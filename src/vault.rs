@@ -0,0 +1,261 @@
+//! Secrets from HashiCorp Vault instead of files on disk.
+//!
+//! With the `vault` feature the server can take its TLS certificate and key,
+//! the ID token signing key and the admin token from Vault. Each is named by
+//! a [`SecretRef`]: `kv:<mount>/<path>` reads a KV version 2 secret, and
+//! `transit:<mount>/<key>` exports the latest version of an Ed25519 transit
+//! key (which has to be created `exportable`). The server reads them at
+//! startup, then every `--vault-refresh` seconds renews its token and picks
+//! up rotated secrets. The address, token, namespace and CA certificate come
+//! from the same `VAULT_*` variables the Vault CLI reads.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use ed25519_dalek::SigningKey;
+use hyper::{Body, Method, Request, Uri};
+use serde_json::Value;
+use tokio_rustls::rustls::ClientConfig;
+
+use crate::http;
+
+/// Where a secret lives in Vault
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SecretRef {
+    /// a KV version 2 secret: `kv:<mount>/<path>`
+    Kv { mount: String, path: String },
+    /// an exportable Ed25519 transit key: `transit:<mount>/<key>`
+    Transit { mount: String, key: String },
+}
+
+impl FromStr for SecretRef {
+    type Err = VaultError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let malformed = || VaultError::Malformed(format!("{:?} is not kv:<mount>/<path> or transit:<mount>/<key>", s));
+        let (engine, rest) = s.split_once(':').ok_or_else(malformed)?;
+        let (mount, path) = rest.trim_matches('/').split_once('/').ok_or_else(malformed)?;
+        if mount.is_empty() || path.is_empty() {
+            return Err(malformed());
+        }
+        let (mount, path) = (mount.to_string(), path.to_string());
+        match engine {
+            "kv" => Ok(SecretRef::Kv { mount, path }),
+            "transit" if !path.contains('/') => Ok(SecretRef::Transit { mount, key: path }),
+            _ => Err(malformed()),
+        }
+    }
+}
+
+impl fmt::Display for SecretRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SecretRef::Kv { mount, path } => write!(f, "kv:{}/{}", mount, path),
+            SecretRef::Transit { mount, key } => write!(f, "transit:{}/{}", mount, key),
+        }
+    }
+}
+
+/// Why a secret couldn't be had
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VaultError {
+    /// no answer: connection, TLS or timeout
+    Unreachable(String),
+    /// Vault answered with an error status (403 for a bad token, 404 for no such secret)
+    Refused { status: u16, errors: Vec<String> },
+    /// the secret is there but lacks a field, or has the wrong kind of key
+    Missing(String),
+    Malformed(String),
+}
+
+impl fmt::Display for VaultError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VaultError::Unreachable(reason) => write!(f, "Vault is unreachable: {}", reason),
+            VaultError::Refused { status, errors } => write!(f, "Vault answered {}: {}", status, errors.join("; ")),
+            VaultError::Missing(what) => write!(f, "not in Vault: {}", what),
+            VaultError::Malformed(reason) => write!(f, "{}", reason),
+        }
+    }
+}
+
+impl std::error::Error for VaultError {}
+
+/// What a token renewal got
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TokenLease {
+    pub ttl: Duration,   // 0 for tokens that never expire (e.g. root)
+    pub renewable: bool,
+}
+
+/// A Vault address and the token to call it with
+pub struct VaultClient {
+    addr: Uri,
+    token: RwLock<String>,
+    namespace: Option<String>,
+    tls: Option<Arc<ClientConfig>>, // for https addresses
+}
+
+// the token is a credential, keep it out of logs
+impl fmt::Debug for VaultClient {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("VaultClient").field("addr", &self.addr).field("namespace", &self.namespace).finish()
+    }
+}
+
+impl VaultClient {
+    /// a client for `addr` (`https://vault:8200`), trusting the system's roots
+    /// or only `ca_cert` (a PEM file) when given
+    pub fn new(addr: &str, token: &str, ca_cert: Option<&Path>) -> Result<Self, VaultError> {
+        let addr: Uri = addr.trim_end_matches('/').parse().map_err(|e| VaultError::Malformed(format!("Vault address {}: {}", addr, e)))?;
+        let tls = match addr.scheme_str() {
+            Some("https") => Some(http::tls_config(ca_cert).map_err(|e| VaultError::Malformed(format!("Vault CA certificate: {}", e)))?),
+            Some("http") => None,
+            _ => return Err(VaultError::Malformed(format!("Vault address {} must be http:// or https://", addr))),
+        };
+        if addr.host().is_none() {
+            return Err(VaultError::Malformed(format!("Vault address {} has no host", addr)));
+        }
+        Ok(Self { addr, token: RwLock::new(token.trim().to_string()), namespace: None, tls })
+    }
+
+    /// a client from VAULT_ADDR, VAULT_TOKEN, VAULT_NAMESPACE and VAULT_CACERT;
+    /// `addr` and `token` win over the environment
+    pub fn from_env(addr: Option<&str>, token: Option<&str>) -> Result<Self, VaultError> {
+        let env = |name: &str| std::env::var(name).ok().filter(|value| !value.is_empty());
+        let addr = addr.map(str::to_string).or_else(|| env("VAULT_ADDR")).ok_or_else(|| VaultError::Missing("an address (--vault-addr or VAULT_ADDR)".to_string()))?;
+        let token = token.map(str::to_string).or_else(|| env("VAULT_TOKEN")).ok_or_else(|| VaultError::Missing("a token (--vault-token-file or VAULT_TOKEN)".to_string()))?;
+        let ca_cert = env("VAULT_CACERT");
+        let mut client = Self::new(&addr, &token, ca_cert.as_deref().map(Path::new))?;
+        client.namespace = env("VAULT_NAMESPACE");
+        Ok(client)
+    }
+
+    pub fn addr(&self) -> &Uri {
+        &self.addr
+    }
+
+    /// the fields of a KV version 2 secret, as strings
+    pub async fn kv(&self, mount: &str, path: &str) -> Result<BTreeMap<String, String>, VaultError> {
+        let response = self.call(Method::GET, &format!("{}/data/{}", mount, path), None).await?;
+        let fields = response["data"]["data"]
+            .as_object()
+            .ok_or_else(|| VaultError::Missing(format!("kv:{}/{} has no data (deleted?)", mount, path)))?;
+        Ok(fields
+            .iter()
+            .filter_map(|(name, value)| value.as_str().map(|value| (name.clone(), value.to_string())))
+            .collect())
+    }
+
+    /// one field of a KV version 2 secret
+    pub async fn kv_field(&self, mount: &str, path: &str, field: &str) -> Result<String, VaultError> {
+        self.kv(mount, path)
+            .await?
+            .remove(field)
+            .ok_or_else(|| VaultError::Missing(format!("field {:?} of kv:{}/{}", field, mount, path)))
+    }
+
+    /// the latest version of an exportable Ed25519 transit key, and its version
+    pub async fn transit_signing_key(&self, mount: &str, key: &str) -> Result<(u64, SigningKey), VaultError> {
+        let response = self.call(Method::GET, &format!("{}/export/signing-key/{}/latest", mount, key), None).await?;
+        let data = &response["data"];
+        if data["type"] != "ed25519" {
+            return Err(VaultError::Missing(format!("transit:{}/{} is {}, not an ed25519 key", mount, key, data["type"])));
+        }
+        let (version, encoded) = data["keys"]
+            .as_object()
+            .and_then(|keys| keys.iter().next())
+            .and_then(|(version, encoded)| Some((version.parse().ok()?, encoded.as_str()?)))
+            .ok_or_else(|| VaultError::Missing(format!("no exported key in transit:{}/{}", mount, key)))?;
+        Ok((version, ed25519_from_export(encoded)?))
+    }
+
+    /// an Ed25519 key from either engine: transit, or the hex seed in a KV secret's `key`
+    /// field (what --params-key files hold)
+    pub async fn signing_key(&self, secret: &SecretRef) -> Result<SigningKey, VaultError> {
+        match secret {
+            SecretRef::Transit { mount, key } => Ok(self.transit_signing_key(mount, key).await?.1),
+            SecretRef::Kv { mount, path } => {
+                let seed = self.kv_field(mount, path, "key").await?;
+                let seed: [u8; 32] = hex::decode(seed.trim())
+                    .ok()
+                    .and_then(|bytes| bytes.try_into().ok())
+                    .ok_or_else(|| VaultError::Malformed(format!("{}: the key must be 32 hex-encoded bytes", secret)))?;
+                Ok(SigningKey::from_bytes(&seed))
+            }
+        }
+    }
+
+    /// the token's remaining TTL, checking on the way that Vault takes it
+    pub async fn lookup_self(&self) -> Result<TokenLease, VaultError> {
+        let response = self.call(Method::GET, "auth/token/lookup-self", None).await?;
+        Ok(TokenLease {
+            ttl: Duration::from_secs(response["data"]["ttl"].as_u64().unwrap_or(0)),
+            renewable: response["data"]["renewable"].as_bool().unwrap_or(false),
+        })
+    }
+
+    /// extend the token's lease by its default increment
+    pub async fn renew_self(&self) -> Result<TokenLease, VaultError> {
+        let response = self.call(Method::POST, "auth/token/renew-self", Some(serde_json::json!({}))).await?;
+        Ok(TokenLease {
+            ttl: Duration::from_secs(response["auth"]["lease_duration"].as_u64().unwrap_or(0)),
+            renewable: response["auth"]["renewable"].as_bool().unwrap_or(false),
+        })
+    }
+
+    /// call from now on with another token (e.g. re-read from a file an agent rewrites)
+    pub fn set_token(&self, token: &str) {
+        *self.token.write().unwrap() = token.trim().to_string();
+    }
+
+    /// one request to `/v1/<path>`
+    async fn call(&self, method: Method, path: &str, body: Option<Value>) -> Result<Value, VaultError> {
+        let mut request = Request::builder()
+            .method(method)
+            .uri(format!("{}/v1/{}", self.addr.to_string().trim_end_matches('/'), path))
+            .header("X-Vault-Token", self.token.read().unwrap().as_str())
+            .header("X-Vault-Request", "true");
+        if let Some(namespace) = &self.namespace {
+            request = request.header("X-Vault-Namespace", namespace);
+        }
+        let request = request
+            .body(body.map_or_else(Body::empty, |body| Body::from(body.to_string())))
+            .map_err(|e| VaultError::Malformed(e.to_string()))?;
+
+        let (status, body) = http::exchange(request, self.tls.as_ref()).await.map_err(VaultError::Unreachable)?;
+
+        let json: Value = match body.is_empty() {
+            true => Value::Null,
+            false => serde_json::from_slice(&body).map_err(|e| VaultError::Malformed(format!("Vault's answer: {}", e)))?,
+        };
+        if !status.is_success() {
+            let errors = json["errors"]
+                .as_array()
+                .map(|errors| errors.iter().filter_map(|e| e.as_str().map(str::to_string)).collect())
+                .unwrap_or_default();
+            return Err(VaultError::Refused { status: status.as_u16(), errors });
+        }
+        Ok(json)
+    }
+}
+
+/// Vault exports Ed25519 keys as base64 of the 64-byte private key (seed, then public key)
+fn ed25519_from_export(encoded: &str) -> Result<SigningKey, VaultError> {
+    let bytes = STANDARD.decode(encoded.trim()).map_err(|e| VaultError::Malformed(format!("exported key: {}", e)))?;
+    match bytes.len() {
+        32 => Ok(SigningKey::from_bytes(&bytes.try_into().unwrap())),
+        64 => {
+            let key = SigningKey::from_keypair_bytes(&bytes.try_into().unwrap())
+                .map_err(|_| VaultError::Malformed("exported key: the public half doesn't match".to_string()))?;
+            Ok(key)
+        }
+        n => Err(VaultError::Malformed(format!("exported key: {} bytes, not an Ed25519 key", n))),
+    }
+}
@@ -0,0 +1,349 @@
+// Tests for the Admin service and disabled accounts
+use std::path::Path;
+use std::process::{Child, Command, Stdio};
+
+use num_bigint::BigUint;
+use tonic::transport::Channel;
+use tonic::{Code, Request};
+
+use rust_zkp_chaum_pedersen::admin::AdminToken;
+use rust_zkp_chaum_pedersen::api::v1::{
+    admin_client::AdminClient, auth_client::AuthClient, AuthenticationAnswerRequest,
+    AuthenticationChallengeRequest, ChallengeCommitmentRequest, EraseUserRequest, ExportUserDataRequest,
+    IntrospectSessionRequest, RegisterRequest, RevokeSessionRequest, SessionTier, SetUserEnabledRequest,
+};
+use rust_zkp_chaum_pedersen::types::Challenge;
+use rust_zkp_chaum_pedersen::ZKP;
+
+mod common;
+use common::connect;
+
+const TOKEN: &str = "correct-horse-battery-staple";
+
+#[test]
+fn test_admin_token() {
+    println!("🛡️  Testing admin tokens");
+
+    assert!(AdminToken::new("short").is_err(), "too short to guard anything");
+    assert!(AdminToken::new("sixteen chars or more").is_err(), "spaces can't go in the header");
+    let token = AdminToken::new(&format!("  {}\n", TOKEN)).unwrap();
+    assert!(!format!("{:?}", token).contains(TOKEN), "the secret never shows up in logs");
+
+    // what the client adds is what the server accepts
+    assert!(token.check(token.authorize(Request::new(()))).is_ok());
+
+    let status = token.check(Request::new(())).unwrap_err();
+    assert_eq!(status.code(), Code::Unauthenticated);
+    let other = AdminToken::new("another-token-entirely").unwrap();
+    let status = token.check(other.authorize(Request::new(()))).unwrap_err();
+    assert_eq!(status.code(), Code::Unauthenticated);
+
+    println!("✅ Only the right token gets in!");
+}
+
+fn start_server(port: u16, token_file: &Path, store: &Path) -> Child {
+    start_server_with(port, token_file, &["--user-store".as_ref(), store.as_os_str()])
+}
+
+fn start_server_with(port: u16, token_file: &Path, args: &[&std::ffi::OsStr]) -> Child {
+    Command::new(env!("CARGO_BIN_EXE_server"))
+        .arg("--listen")
+        .arg(format!("127.0.0.1:{}", port))
+        .arg("--admin-token-file")
+        .arg(token_file)
+        .args(args)
+        .stdout(Stdio::null())
+        .spawn()
+        .expect("Failed to start server process")
+}
+
+async fn challenge(client: &mut AuthClient<Channel>, zkp: &ZKP, user: &str) -> Result<(String, Challenge, BigUint), tonic::Status> {
+    let k = ZKP::generate_random_number_below(&zkp.q);
+    let (r1, r2) = zkp.commitment(&k).to_bytes_be();
+    let request = AuthenticationChallengeRequest { user: user.to_string(), r1, r2, ..Default::default() };
+    let response = client.create_authentication_challenge(request).await?.into_inner();
+    Ok((response.auth_id, Challenge::from_bytes_be(&response.c), k))
+}
+
+async fn answer(client: &mut AuthClient<Channel>, zkp: &ZKP, x: &BigUint, challenge: (String, Challenge, BigUint)) -> Result<String, tonic::Status> {
+    let (auth_id, c, k) = challenge;
+    let s = zkp.respond(&k, &c, x).to_bytes_be();
+    let request = AuthenticationAnswerRequest { auth_id, s, ..Default::default() };
+    Ok(client.verify_authentication(request).await?.into_inner().session_id)
+}
+
+#[tokio::test]
+async fn test_disabled_accounts() {
+    println!("🛡️  Testing disabling an account");
+
+    let dir = std::env::temp_dir().join(format!("zkp-admin-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let token_file = dir.join("admin-token");
+    std::fs::write(&token_file, TOKEN).unwrap();
+    let store = dir.join("users.jsonl");
+    let token = AdminToken::load(&token_file).unwrap();
+    let port = 50072;
+
+    let mut server = start_server(port, &token_file, &store);
+    let channel = connect(port).await;
+    let mut client = AuthClient::new(channel.clone());
+    let mut admin = AdminClient::new(channel);
+
+    let zkp = ZKP::builder().rfc5114().build().unwrap();
+    let x = ZKP::generate_random_number_below(&zkp.q);
+    let (y1, y2) = zkp.public_key(&x).to_bytes_be();
+    let user = "leaver".to_string();
+    client.register(RegisterRequest { user: user.clone(), y1: y1.clone(), y2, ..Default::default() }).await.unwrap();
+    let first = challenge(&mut client, &zkp, &user).await.unwrap();
+    let session = answer(&mut client, &zkp, &x, first).await.unwrap();
+    // a challenge handed out before the account is disabled
+    let pending = challenge(&mut client, &zkp, &user).await.unwrap();
+
+    let disable = SetUserEnabledRequest { user: user.clone(), enabled: false, reason: "left the company".to_string() };
+    let status = admin.set_user_enabled(disable.clone()).await.unwrap_err();
+    assert_eq!(status.code(), Code::Unauthenticated, "no token, no admin");
+    let response = admin.set_user_enabled(token.authorize(Request::new(disable))).await.unwrap().into_inner();
+    assert_eq!(response.revoked_sessions, 1);
+
+    // the session is gone, nothing new can start and the pending answer is refused
+    let introspect = token.authorize(Request::new(IntrospectSessionRequest { token: session }));
+    let introspection = client.introspect_session(introspect).await.unwrap().into_inner();
+    assert!(!introspection.active);
+    let status = challenge(&mut client, &zkp, &user).await.unwrap_err();
+    assert_eq!(status.code(), Code::PermissionDenied);
+    let status = client.commit_challenge(ChallengeCommitmentRequest { user: user.clone() }).await.unwrap_err();
+    assert_eq!(status.code(), Code::PermissionDenied);
+    let status = answer(&mut client, &zkp, &x, pending).await.unwrap_err();
+    assert_eq!(status.code(), Code::PermissionDenied);
+    assert!(status.message().contains("disabled"), "the proof was right: {}", status.message());
+    // the name stays taken
+    let status = client.register(RegisterRequest { user: user.clone(), y2: y1.clone(), y1, ..Default::default() }).await.unwrap_err();
+    assert_eq!(status.code(), Code::AlreadyExists);
+
+    // still disabled after a restart
+    server.kill().unwrap();
+    server.wait().unwrap();
+    let mut server = start_server(port, &token_file, &store);
+    let channel = connect(port).await;
+    let mut client = AuthClient::new(channel.clone());
+    let mut admin = AdminClient::new(channel);
+    let status = challenge(&mut client, &zkp, &user).await.unwrap_err();
+    assert_eq!(status.code(), Code::PermissionDenied);
+
+    let enable = SetUserEnabledRequest { user: user.clone(), enabled: true, reason: String::new() };
+    admin.set_user_enabled(token.authorize(Request::new(enable))).await.unwrap();
+    let again = challenge(&mut client, &zkp, &user).await.unwrap();
+    let login = answer(&mut client, &zkp, &x, again).await;
+
+    let unknown = SetUserEnabledRequest { user: "nobody".to_string(), enabled: false, reason: String::new() };
+    let status = admin.set_user_enabled(token.authorize(Request::new(unknown))).await.unwrap_err();
+
+    server.kill().unwrap();
+    server.wait().unwrap();
+    assert!(login.is_ok(), "{:?}", login);
+    assert_eq!(status.code(), Code::NotFound);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+    println!("✅ Disabled accounts are locked out and keep their history!");
+}
+
+#[tokio::test]
+async fn test_session_introspection() {
+    println!("🛡️  Testing session introspection");
+
+    let dir = std::env::temp_dir().join(format!("zkp-introspect-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let token_file = dir.join("admin-token");
+    std::fs::write(&token_file, TOKEN).unwrap();
+    let token = AdminToken::load(&token_file).unwrap();
+    let port = 50114;
+
+    let mut server = start_server_with(port, &token_file, &["--session-retention".as_ref(), "1h".as_ref()]);
+    let mut client = AuthClient::new(connect(port).await);
+
+    let zkp = ZKP::builder().rfc5114().build().unwrap();
+    let x = ZKP::generate_random_number_below(&zkp.q);
+    let (y1, y2) = zkp.public_key(&x).to_bytes_be();
+    let user = "introspected".to_string();
+    client.register(RegisterRequest { user: user.clone(), y1, y2, ..Default::default() }).await.unwrap();
+    let first = challenge(&mut client, &zkp, &user).await.unwrap();
+    let session_id = answer(&mut client, &zkp, &x, first).await.unwrap();
+
+    // whoever holds a session id can't ask whose it is
+    let introspect = IntrospectSessionRequest { token: session_id.clone() };
+    let status = client.introspect_session(introspect.clone()).await.unwrap_err();
+    assert_eq!(status.code(), Code::Unauthenticated);
+    let other = AdminToken::new("another-token-entirely").unwrap();
+    let status = client.introspect_session(other.authorize(Request::new(introspect.clone()))).await.unwrap_err();
+    assert_eq!(status.code(), Code::Unauthenticated);
+
+    // a resource server with the token can, and learns when --session-retention ends it
+    let introspection = client.introspect_session(token.authorize(Request::new(introspect.clone()))).await.unwrap().into_inner();
+    assert!(introspection.active);
+    assert_eq!(introspection.username, user);
+    assert_eq!(introspection.tier(), SessionTier::Standard);
+    assert!(introspection.issued_at_unix_millis > 0);
+    assert_eq!(introspection.expires_at_unix_millis, introspection.issued_at_unix_millis + 3_600_000);
+
+    // unknown and revoked tokens are inactive, not errors
+    let unknown = IntrospectSessionRequest { token: "made-up".to_string() };
+    let unknown = client.introspect_session(token.authorize(Request::new(unknown))).await.unwrap().into_inner();
+    assert!(!unknown.active && unknown.username.is_empty());
+    let logout = RevokeSessionRequest { user, session_id: session_id.clone(), revoke_session_id: session_id };
+    client.revoke_session(logout).await.unwrap();
+    let revoked = client.introspect_session(token.authorize(Request::new(introspect))).await.unwrap().into_inner();
+
+    server.kill().unwrap();
+    server.wait().unwrap();
+    assert!(!revoked.active);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+    println!("✅ Only resource servers learn who a session belongs to!");
+}
+
+#[tokio::test]
+async fn test_bulk_registration() {
+    println!("🛡️  Testing bulk registration");
+
+    let dir = std::env::temp_dir().join(format!("zkp-bulk-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let token_file = dir.join("admin-token");
+    std::fs::write(&token_file, TOKEN).unwrap();
+    let token = AdminToken::load(&token_file).unwrap();
+    let port = 50073;
+
+    // imported users never went through our password policy, that's fine
+    let mut server = start_server_with(port, &token_file, &["--min-password-policy".as_ref(), "1".as_ref()]);
+    let channel = connect(port).await;
+    let mut client = AuthClient::new(channel.clone());
+    let mut admin = AdminClient::new(channel);
+
+    let zkp = ZKP::builder().rfc5114().build().unwrap();
+    let secrets: Vec<BigUint> = (0..3).map(|_| ZKP::generate_random_number_below(&zkp.q)).collect();
+    let registration = |user: &str, x: &BigUint| {
+        let (y1, y2) = zkp.public_key(x).to_bytes_be();
+        RegisterRequest { user: user.to_string(), y1, y2, ..Default::default() }
+    };
+
+    let users = vec![
+        registration("migrated_1", &secrets[0]),
+        registration("migrated_2", &secrets[1]),
+        // someone else's key for a name that is taken now
+        registration("migrated_1", &secrets[2]),
+        RegisterRequest { beta: vec![1, 2, 3], ..registration("migrated_3", &secrets[2]) },
+    ];
+    let status = admin.bulk_register(tokio_stream::iter(users.clone())).await.unwrap_err();
+    assert_eq!(status.code(), Code::Unauthenticated);
+    let response = admin.bulk_register(token.authorize(Request::new(tokio_stream::iter(users)))).await.unwrap().into_inner();
+    assert_eq!((response.registered, response.unchanged, response.failed), (2, 0, 2));
+    let codes: Vec<_> = response.failures.iter().map(|failure| (failure.user.as_str(), Code::from(failure.code))).collect();
+    assert_eq!(codes, [("migrated_1", Code::AlreadyExists), ("migrated_3", Code::InvalidArgument)]);
+
+    // the import runs again after being cut off: what is there is unchanged
+    let csv = dir.join("users.csv");
+    let rows: Vec<String> = [("migrated_2", &secrets[1]), ("migrated_4", &secrets[2])]
+        .iter()
+        .map(|(user, x)| {
+            let (y1, y2) = zkp.public_key(x).to_bytes_be();
+            format!("{},{},{}", user, hex::encode(y1), hex::encode(y2))
+        })
+        .collect();
+    std::fs::write(&csv, format!("user,y1,y2\n{}\n", rows.join("\n"))).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_zkp-auth"))
+        .args(["admin", "import-users"])
+        .arg(&csv)
+        .arg("--token-file")
+        .arg(&token_file)
+        .args(["--endpoint", &format!("http://127.0.0.1:{}", port)])
+        .output()
+        .unwrap();
+
+    // an imported user logs in like any other
+    let pending = challenge(&mut client, &zkp, "migrated_4").await;
+    let login = match pending {
+        Ok(pending) => answer(&mut client, &zkp, &secrets[2], pending).await,
+        Err(status) => Err(status),
+    };
+
+    server.kill().unwrap();
+    server.wait().unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(stdout.contains("1 registered, 1 already there"), "{}", stdout);
+    assert!(login.is_ok(), "{:?}", login);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+    println!("✅ A user base can be moved over in one stream!");
+}
+
+#[tokio::test]
+async fn test_export_and_erase() {
+    println!("🛡️  Testing data export and erasure");
+
+    let dir = std::env::temp_dir().join(format!("zkp-erase-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let token_file = dir.join("admin-token");
+    std::fs::write(&token_file, TOKEN).unwrap();
+    let store = dir.join("users.jsonl");
+    let audit_log = dir.join("audit.jsonl");
+    let token = AdminToken::load(&token_file).unwrap();
+    let port = 50085;
+
+    let args = ["--user-store".as_ref(), store.as_os_str(), "--audit-log".as_ref(), audit_log.as_os_str()];
+    let mut server = start_server_with(port, &token_file, &args);
+    let channel = connect(port).await;
+    let mut client = AuthClient::new(channel.clone());
+    let mut admin = AdminClient::new(channel);
+
+    let zkp = ZKP::builder().rfc5114().build().unwrap();
+    let x = ZKP::generate_random_number_below(&zkp.q);
+    let (y1, y2) = zkp.public_key(&x).to_bytes_be();
+    let user = "data_subject".to_string();
+    let register = RegisterRequest { user: user.clone(), y1: y1.clone(), y2: y2.clone(), ..Default::default() };
+    client.register(register.clone()).await.unwrap();
+    let pending = challenge(&mut client, &zkp, &user).await.unwrap();
+    answer(&mut client, &zkp, &x, pending).await.unwrap();
+    client.register(RegisterRequest { user: "bystander".to_string(), y1, y2, ..Default::default() }).await.unwrap();
+
+    let status = admin.export_user_data(ExportUserDataRequest { user: user.clone() }).await.unwrap_err();
+    assert_eq!(status.code(), Code::Unauthenticated, "no token, no admin");
+    let export = admin.export_user_data(token.authorize(Request::new(ExportUserDataRequest { user: user.clone() }))).await.unwrap().into_inner();
+    let export: serde_json::Value = serde_json::from_str(&export.json).unwrap();
+    assert_eq!(export["registration"]["y1"], hex::encode(&register.y1));
+    assert_eq!(export["stored_registration"]["user"], user.as_str());
+    assert_eq!(export["sessions"].as_array().unwrap().len(), 1);
+    let actions: Vec<_> = export["audit_events"].as_array().unwrap().iter().map(|event| event["action"].as_str().unwrap()).collect();
+    assert!(actions.contains(&"register") && actions.contains(&"login"), "{:?}", actions);
+
+    let erase = EraseUserRequest { user: user.clone(), reason: "ticket 1234".to_string() };
+    let erased = admin.erase_user(token.authorize(Request::new(erase.clone()))).await.unwrap().into_inner();
+    let again = admin.erase_user(token.authorize(Request::new(erase))).await.unwrap_err();
+    let gone = challenge(&mut client, &zkp, &user).await.unwrap_err();
+    // the name is free again
+    let reregister = client.register(register).await;
+
+    server.kill().unwrap();
+    server.wait().unwrap();
+    assert_eq!(erased.sessions_ended, 1);
+    assert!(erased.audit_events_erased >= 3, "{:?}", erased);
+    assert_eq!(again.code(), Code::NotFound);
+    assert_eq!(gone.code(), Code::NotFound);
+    assert!(reregister.is_ok(), "{:?}", reregister);
+    // the old records are gone from both files; the audit trail keeps tombstones in their place
+    let journal = std::fs::read_to_string(&store).unwrap();
+    assert_eq!(journal.lines().filter(|line| line.contains(&user)).count(), 1, "only the new registration: {}", journal);
+    assert!(journal.contains("bystander"));
+    let log = std::fs::read_to_string(&audit_log).unwrap();
+    let tombstones = log.lines().filter(|line| line.contains(&erased.tombstone)).count();
+    assert_eq!(tombstones as u32, erased.audit_events_erased + 1, "the erasures and the erase_user event itself");
+    let before_erasure = log.lines().take_while(|line| !line.contains("\"erase_user\"")).collect::<Vec<_>>().join("\n");
+    assert!(!before_erasure.contains(&user), "{}", before_erasure);
+    assert!(before_erasure.contains("bystander"));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+    println!("✅ A user's data can be handed over and then erased!");
+}
@@ -0,0 +1,42 @@
+// Tests for routing calls from clients of the unversioned API
+use rust_zkp_chaum_pedersen::api::{upgrade_legacy_path, PROTO_V1, PROTO_VALIDATE};
+use tonic::codegen::http::Request;
+
+fn routed(uri: &str) -> String {
+    let request = Request::builder().uri(uri).body(()).unwrap();
+    upgrade_legacy_path(request).uri().to_string()
+}
+
+#[test]
+fn test_legacy_paths_are_routed_to_v1() {
+    println!("🧭 Testing legacy service paths");
+
+    assert_eq!(
+        routed("http://127.0.0.1:50051/zkp_auth.Auth/Register"),
+        "http://127.0.0.1:50051/zkp_auth.v1.Auth/Register"
+    );
+    assert_eq!(routed("/zkp_auth.Auth/GetParameters"), "/zkp_auth.v1.Auth/GetParameters");
+
+    // current and unrelated services are untouched
+    for uri in [
+        "/zkp_auth.v1.Auth/Register",
+        "/grpc.health.v1.Health/Check",
+        "/zkp_auth.AuthAdmin/Register",
+    ] {
+        assert_eq!(routed(uri), uri);
+    }
+
+    println!("✅ Legacy path routing test passed!");
+}
+
+#[test]
+fn test_proto_files_ship_with_the_crate() {
+    println!("🧭 Testing the bundled .proto files");
+
+    assert!(PROTO_V1.contains("package zkp_auth.v1;"));
+    assert!(PROTO_V1.contains("service Auth {"));
+    assert!(PROTO_V1.contains("import \"zkp_auth/validate.proto\";"));
+    assert!(PROTO_VALIDATE.contains("package zkp_auth.validate;"));
+
+    println!("✅ Clients in other languages can be generated from the crate!");
+}
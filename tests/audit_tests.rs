@@ -0,0 +1,103 @@
+// Tests for the audit trail and its sinks
+use std::net::UdpSocket;
+use std::time::{Duration, SystemTime};
+
+use rust_zkp_chaum_pedersen::audit::{AuditEvent, AuditSink, JsonlSink, Outcome, SyslogSink};
+
+fn event(user: &str, outcome: Outcome) -> AuditEvent {
+    AuditEvent {
+        // 2023-11-14T22:13:20.250Z
+        time: SystemTime::UNIX_EPOCH + Duration::from_millis(1_700_000_000_250),
+        action: "login",
+        outcome,
+        user: user.to_string(),
+        source: "127.0.0.1:40000".to_string(),
+        trace_id: "0af7651916cd43dd8448eb211c80319c".to_string(),
+        detail: String::new(),
+    }
+}
+
+#[test]
+fn test_event_as_json() {
+    println!("📒 Testing audit events as JSON");
+
+    let json: serde_json::Value = serde_json::from_str(&event("alice", Outcome::Denied).to_json()).unwrap();
+    assert_eq!(json["time"], "2023-11-14T22:13:20.250Z");
+    assert_eq!(json["action"], "login");
+    assert_eq!(json["outcome"], "denied");
+    assert_eq!(json["user"], "alice");
+    assert_eq!(json["source"], "127.0.0.1:40000");
+    assert_eq!(json["trace_id"], "0af7651916cd43dd8448eb211c80319c");
+
+    println!("✅ One JSON object per event!");
+}
+
+#[test]
+fn test_event_as_syslog() {
+    println!("📒 Testing audit events as RFC 5424 messages");
+
+    let message = event("mallory\"] x=\"1", Outcome::Failure).to_syslog("", "zkp-auth");
+    // authpriv.warning = 10 * 8 + 4, and a nil hostname
+    assert!(message.starts_with("<84>1 2023-11-14T22:13:20.250Z - zkp-auth "), "{}", message);
+    assert!(message.contains(" login [zkp@32473 user=\"mallory\\\"\\] x=\\\"1\" outcome=\"failure\""), "{}", message);
+    assert!(message.ends_with("] login failure"), "{}", message);
+
+    let success = event("alice", Outcome::Success).to_syslog("host1", "zkp-auth");
+    assert!(success.starts_with("<86>1 2023-11-14T22:13:20.250Z host1 zkp-auth "), "{}", success);
+
+    println!("✅ Severity, escaping and structured data are right!");
+}
+
+#[test]
+fn test_jsonl_sink_rotates() {
+    println!("📒 Testing the JSON lines audit log");
+
+    let dir = std::env::temp_dir().join(format!("zkp-audit-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("audit.jsonl");
+    let line_len = event("user-0", Outcome::Success).to_json().len() as u64 + 1;
+
+    // room for two lines per file, keeping two old files
+    let sink = JsonlSink::open(&path, 2 * line_len, 2).unwrap();
+    for n in 0..7 {
+        sink.record(&event(&format!("user-{}", n), Outcome::Success)).unwrap();
+    }
+
+    let users = |path: &std::path::Path| -> Vec<String> {
+        std::fs::read_to_string(path)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap()["user"].as_str().unwrap().to_string())
+            .collect()
+    };
+    assert_eq!(users(&path), ["user-6"]);
+    assert_eq!(users(&dir.join("audit.jsonl.1")), ["user-4", "user-5"]);
+    assert_eq!(users(&dir.join("audit.jsonl.2")), ["user-2", "user-3"]);
+    assert!(!dir.join("audit.jsonl.3").exists(), "the oldest file should be dropped");
+
+    // reopening appends to what is there
+    let sink = JsonlSink::open(&path, 2 * line_len, 2).unwrap();
+    sink.record(&event("user-7", Outcome::Success)).unwrap();
+    assert_eq!(users(&path), ["user-6", "user-7"]);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+    println!("✅ Audit logs rotate by size!");
+}
+
+#[test]
+fn test_syslog_sink_over_udp() {
+    println!("📒 Testing the syslog audit sink");
+
+    let daemon = UdpSocket::bind("127.0.0.1:0").unwrap();
+    daemon.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    let sink = SyslogSink::connect(&daemon.local_addr().unwrap().to_string()).unwrap();
+    sink.record(&event("alice", Outcome::Success)).unwrap();
+
+    let mut buf = [0u8; 2048];
+    let len = daemon.recv(&mut buf).unwrap();
+    let message = String::from_utf8_lossy(&buf[..len]);
+    assert!(message.starts_with("<86>1 "), "{}", message);
+    assert!(message.contains(&format!(" zkp-auth {} login ", std::process::id())), "{}", message);
+
+    println!("✅ Audit events reach syslog!");
+}
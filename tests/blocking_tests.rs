@@ -0,0 +1,98 @@
+// Tests for the crypto thread pool
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::time::Duration;
+
+use rust_zkp_chaum_pedersen::blocking::CryptoPool;
+use rust_zkp_chaum_pedersen::ZKP;
+
+/// occupy the pool's only thread until the returned sender is used
+async fn occupy(pool: &Arc<CryptoPool>) -> (mpsc::Sender<()>, tokio::task::JoinHandle<()>) {
+    let (release, wait) = mpsc::channel::<()>();
+    let (started, running) = mpsc::channel::<()>();
+    let busy_pool = pool.clone();
+    let busy = tokio::spawn(async move {
+        busy_pool
+            .run(move || {
+                started.send(()).unwrap();
+                wait.recv().unwrap()
+            })
+            .await
+            .unwrap()
+    });
+    running.recv().unwrap();
+    (release, busy)
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_pool_returns_the_result() {
+    println!("🧵 Testing crypto work on the pool");
+
+    let pool = CryptoPool::new(2, 8);
+    let zkp = ZKP::builder().rfc5114().build().unwrap();
+    let x = ZKP::generate_random_number_below(&zkp.q);
+    let expected = zkp.compute_pair(&x);
+
+    let pair = pool.run(move || zkp.compute_pair(&x)).await.expect("job failed");
+    assert_eq!(pair, expected);
+
+    let stats = pool.stats();
+    assert_eq!(stats.completed, 1);
+    assert_eq!(stats.queued, 0);
+    assert_eq!(stats.running, 0);
+
+    println!("✅ Pool result test passed!");
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_full_queue_is_rejected() {
+    println!("🚦 Testing rejection when the pool is saturated");
+
+    let pool = Arc::new(CryptoPool::new(1, 1));
+    let (release, busy) = occupy(&pool).await;
+
+    // one job fits in the queue...
+    let queued_pool = pool.clone();
+    let queued = tokio::spawn(async move { queued_pool.run(|| 42).await });
+    while pool.stats().queued == 0 {
+        tokio::task::yield_now().await;
+    }
+    // ...the next one is turned away
+    let status = pool.run(|| 0).await.expect_err("a saturated pool accepted work");
+    assert_eq!(status.code(), tonic::Code::ResourceExhausted);
+    assert_eq!(pool.stats().rejected, 1);
+
+    release.send(()).unwrap();
+    busy.await.unwrap();
+    assert_eq!(queued.await.unwrap().unwrap(), 42);
+
+    println!("✅ Rejection test passed!");
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_abandoned_job_is_skipped() {
+    println!("🛑 Testing that abandoned jobs don't run");
+
+    let pool = Arc::new(CryptoPool::new(1, 4));
+    let (release, busy) = occupy(&pool).await;
+
+    let ran = Arc::new(AtomicBool::new(false));
+    let flag = ran.clone();
+    // the caller gives up (client disconnected, deadline passed) while the job is queued
+    let abandoned = tokio::time::timeout(
+        Duration::from_millis(50),
+        pool.run(move || flag.store(true, Ordering::SeqCst)),
+    )
+    .await;
+    assert!(abandoned.is_err());
+
+    release.send(()).unwrap();
+    busy.await.unwrap();
+    // the pool is FIFO, so once this ran the abandoned job was looked at
+    pool.run(|| ()).await.unwrap();
+    assert!(!ran.load(Ordering::SeqCst), "an abandoned job still ran");
+    assert_eq!(pool.stats().cancelled, 1);
+
+    println!("✅ Cancellation test passed!");
+}
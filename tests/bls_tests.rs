@@ -0,0 +1,104 @@
+// Tests for the BLS12-381 G1 backend (cargo test --features bls12-381)
+#![cfg(feature = "bls12-381")]
+
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime};
+
+use bls12_381::Scalar;
+use num_bigint::BigUint;
+use rust_zkp_chaum_pedersen::nizk::ValidityWindow;
+use rust_zkp_chaum_pedersen::bls::{self, Bls12381G1, BlsPublicKey, PROOF_LEN};
+use rust_zkp_chaum_pedersen::ZKP;
+
+// the vector test seeds the process-wide generator, so the tests take turns
+static RNG: Mutex<()> = Mutex::new(());
+
+fn window() -> ValidityWindow {
+    ValidityWindow { not_before: 1_700_000_000, not_after: 1_700_000_300 }
+}
+
+fn at(unix: u64) -> SystemTime {
+    SystemTime::UNIX_EPOCH + Duration::from_secs(unix)
+}
+
+#[test]
+fn test_bls_proofs() {
+    let _turn = RNG.lock().unwrap_or_else(|e| e.into_inner());
+    println!("🔧 Testing 128-byte BLS12-381 G1 proofs");
+
+    let group = Bls12381G1::new();
+    let x = Bls12381G1::random_scalar();
+    let key = group.public_key(&x);
+
+    let started = Instant::now();
+    let proof = group.prove_non_interactive(&x, window(), b"login to example.com");
+    assert!(group.verify_non_interactive(&key, &proof, b"login to example.com", at(1_700_000_100)));
+    println!("   prove + verify: {:?}", started.elapsed());
+
+    let compact = bls::to_compact(&proof).unwrap();
+    assert_eq!(compact.len(), PROOF_LEN);
+    assert_eq!(bls::from_compact(&compact, proof.id.clone(), proof.window), proof);
+
+    // bound to the window, the context, the key and every byte
+    assert!(!group.verify_non_interactive(&key, &proof, b"login to example.com", at(1_700_000_301)));
+    assert!(!group.verify_non_interactive(&key, &proof, b"login to evil.com", at(1_700_000_100)));
+    let other = group.public_key(&Bls12381G1::random_scalar());
+    assert!(!group.verify_non_interactive(&other, &proof, b"login to example.com", at(1_700_000_100)));
+    for i in 0..PROOF_LEN {
+        let mut tampered = compact;
+        tampered[i] ^= 1;
+        let tampered = bls::from_compact(&tampered, proof.id.clone(), proof.window);
+        assert!(!group.verify_non_interactive(&key, &tampered, b"login to example.com", at(1_700_000_100)), "byte {}", i);
+    }
+
+    // a Z_p-sized field isn't a G1 proof
+    let mut long = proof.clone();
+    long.s.insert(0, 0);
+    assert_eq!(bls::to_compact(&long), None);
+    assert!(!group.verify_non_interactive(&key, &long, b"login to example.com", at(1_700_000_100)));
+
+    let bytes = key.to_bytes();
+    assert_eq!(BlsPublicKey::from_bytes(&bytes), Some(key));
+    assert_eq!(BlsPublicKey::from_bytes(&bytes[..95]), None);
+    assert_eq!(BlsPublicKey::from_bytes(&[0xff; 96]), None, "not a valid encoding");
+
+    println!("✅ G1 proofs verify, and only for what they were made for!");
+}
+
+#[test]
+fn test_bls_vectors() {
+    let _turn = RNG.lock().unwrap_or_else(|e| e.into_inner());
+    println!("🔧 Testing BLS12-381 G1 test vectors");
+
+    // alpha is the standard G1 generator, and 0 * alpha the point at infinity, per the zcash encoding
+    let group = Bls12381G1::new();
+    let key = group.public_key(&Scalar::one());
+    assert_eq!(hex::encode(&key.to_bytes()[..48]), "97f1d3a73197d7942695638c4fa9ac0fc3688c4f9774b905a14e3a3f171bac586c55e83ff97a1aeffb3af00adb22c6bb");
+    let key = group.public_key(&Scalar::zero());
+    assert_eq!(hex::encode(&key.to_bytes()[..48]), format!("c0{}", "00".repeat(47)));
+
+    // a proof for x = 42, pinned so the hash layout and beta can't change unnoticed
+    let x = bls::scalar_from_biguint(&BigUint::from(42u32));
+    assert_eq!(x, Scalar::from(42u64));
+    let key = group.public_key(&x);
+    assert_eq!(hex::encode(key.to_bytes()), KEY_42);
+
+    ZKP::with_rng(7);
+    let proof = group.prove_non_interactive(&x, window(), b"test vector");
+    ZKP::with_os_rng();
+    assert_eq!(proof.id, PROOF_42_ID);
+    assert_eq!(hex::encode(bls::to_compact(&proof).unwrap()), PROOF_42);
+
+    let vector = bls::from_compact(&hex::decode(PROOF_42).unwrap().try_into().unwrap(), PROOF_42_ID.to_string(), window());
+    assert!(group.verify_non_interactive(&key, &vector, b"test vector", at(1_700_000_000)));
+
+    // reduction mod the group order: r + 42 is 42
+    let order = BigUint::from_bytes_le(&(-Scalar::one()).to_bytes()) + 1u32;
+    assert_eq!(bls::scalar_from_biguint(&(order + 42u32)), x);
+
+    println!("✅ The vectors match!");
+}
+
+const KEY_42: &str = "8ce3b57b791798433fd323753489cac9bca43b98deaafaed91f4cb010730ae1e38b186ccd37a09b8aed62ce23b699c48b4222a9abfabb63df6aa45630a527997fb952227a0734c1b60bd0799aa35880f98b12c84c95d22570d7c84d60523179c";
+const PROOF_42_ID: &str = "eIYugoZBFFUXSoy4";
+const PROOF_42: &str = "b20303131b5bccadebcd792c50056885260816bc9e9e711eb19e305391d60b4d5c954f16cdcd3b70c5e3ee7ac5bb44c1886fd294570ca5b3572c864000be34ea4044f00cc9cec39e0ccf39289233cea8c7d2f4869c636920bdcec0c7075afa9687ff96710d830b92560e00192bc31c063970c83d37c2b8984237620592f2e570";
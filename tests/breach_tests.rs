@@ -0,0 +1,135 @@
+// Tests for the breach check against a Pwned Passwords range API (cargo test --features hibp)
+#![cfg(feature = "hibp")]
+
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::sync::{Arc, Mutex};
+
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Request, Response, StatusCode};
+
+use rust_zkp_chaum_pedersen::breach::{self, BreachCheck, PREFIX_LENGTH};
+use rust_zkp_chaum_pedersen::password_policy::Weakness;
+
+/// (prefix, Add-Padding header) of every request
+type Seen = Arc<Mutex<Vec<(String, String)>>>;
+
+/// a range API that knows "password" and "P@ssw0rd-2019", and records the paths and headers it's asked for
+fn range_api() -> (String, Seen) {
+    let seen: Seen = Arc::default();
+    let kept = seen.clone();
+    let make_service = make_service_fn(move |_| {
+        let seen = kept.clone();
+        async move {
+            Ok::<_, std::convert::Infallible>(service_fn(move |request: Request<Body>| {
+                let seen = seen.clone();
+                async move {
+                    let padding = request.headers().get("Add-Padding").map_or("", |value| value.to_str().unwrap()).to_string();
+                    let prefix = request.uri().path().rsplit('/').next().unwrap().to_string();
+                    seen.lock().unwrap().push((prefix.clone(), padding));
+                    if prefix.len() != PREFIX_LENGTH {
+                        return Ok::<_, std::convert::Infallible>(
+                            Response::builder().status(StatusCode::BAD_REQUEST).body(Body::empty()).unwrap(),
+                        );
+                    }
+                    let mut lines = vec!["0018A45C4D1DEF81644B54AB7F969B88D65:0".to_string()]; // padding
+                    for (password, count) in [("password", 9_659_365), ("P@ssw0rd-2019", 12)] {
+                        let (known_prefix, suffix) = breach::hash_parts(password);
+                        if known_prefix == prefix {
+                            lines.push(format!("{}:{}", suffix, count));
+                        }
+                    }
+                    Ok(Response::new(Body::from(lines.join("\r\n"))))
+                }
+            }))
+        }
+    });
+    let server = hyper::Server::bind(&"127.0.0.1:0".parse().unwrap()).serve(make_service);
+    let url = format!("http://{}/range/", server.local_addr());
+    tokio::spawn(server);
+    (url, seen)
+}
+
+#[test]
+fn test_hash_parts() {
+    println!("🕳️ Testing the k-anonymous lookup");
+
+    // SHA-1("password") = 5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD8
+    let (prefix, suffix) = breach::hash_parts("password");
+    assert_eq!((prefix.as_str(), suffix.as_str()), ("5BAA6", "1E4C9B93F3F0682250B6CF8331B7EE68FD8"));
+
+    let range = "003D68EB55068C33ACE09247EE4C639306B:3\r\n1e4c9b93f3f0682250b6cf8331b7ee68fd8:9659365\r\n01330C689E5D64F660D6947A93AD634EF8F:0";
+    assert_eq!(breach::count_in_range(range, &suffix), 9_659_365, "case doesn't matter");
+    assert_eq!(breach::count_in_range(range, "01330C689E5D64F660D6947A93AD634EF8F"), 0, "padding isn't a breach");
+    assert_eq!(breach::count_in_range(range, "FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFF"), 0);
+    assert_eq!(breach::count_in_range("", &suffix), 0);
+
+    assert_eq!(Weakness::Breached { count: 12 }.to_string(), "it has turned up 12 times in known data breaches");
+    assert!(BreachCheck::new("ftp://example.com/range/").is_err());
+    assert!(BreachCheck::new("https://api.pwnedpasswords.com/range").is_ok());
+
+    println!("✅ Only a hash prefix would leave the client!");
+}
+
+#[tokio::test]
+async fn test_breach_check() {
+    println!("🕳️ Testing lookups against a range API");
+
+    let (url, seen) = range_api();
+    let check = BreachCheck::new(&url).unwrap();
+    assert_eq!(check.times_seen("password").await, Ok(9_659_365));
+    assert_eq!(check.times_seen("P@ssw0rd-2019").await, Ok(12));
+    assert_eq!(check.times_seen("correct horse battery staple").await, Ok(0));
+
+    // nothing but the prefix, and asking for padding
+    let seen = seen.lock().unwrap().clone();
+    let expected: Vec<(String, String)> = ["password", "P@ssw0rd-2019", "correct horse battery staple"]
+        .iter()
+        .map(|password| (breach::hash_parts(password).0, "true".to_string()))
+        .collect();
+    assert_eq!(seen, expected);
+
+    let unreachable = BreachCheck::new("http://127.0.0.1:9/range/").unwrap();
+    assert!(unreachable.times_seen("password").await.is_err());
+
+    println!("✅ Breached passwords are found without sending them!");
+}
+
+// the range API keeps answering on another worker while keygen runs
+#[tokio::test(flavor = "multi_thread")]
+async fn test_keygen_check_breaches() {
+    println!("🕳️ Testing zkp-auth keygen --check-breaches");
+
+    let (url, _) = range_api();
+    let dir = std::env::temp_dir().join(format!("zkp-breach-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let keygen = |password: &str, name: &str, extra: &[&str]| {
+        let out = dir.join(format!("{}.json", name));
+        let mut child = Command::new(env!("CARGO_BIN_EXE_zkp-auth"))
+            .args(["keygen", "--user", "alice", "--memory-kib", "64", "--iterations", "1", "--check-breaches", "--breach-api", &url])
+            .args(extra)
+            .arg("--out")
+            .arg(&out)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        writeln!(child.stdin.take().unwrap(), "{}", password).unwrap();
+        let output = child.wait_with_output().unwrap();
+        (output.status.success(), String::from_utf8_lossy(&output.stderr).into_owned(), out.exists())
+    };
+
+    // passes the default rules, but was breached
+    let (ok, stderr, written) = keygen("P@ssw0rd-2019", "breached", &[]);
+    assert!(!ok && !written, "refused: {}", stderr);
+    assert!(stderr.contains("12 times in known data breaches"), "{}", stderr);
+
+    let (ok, _, written) = keygen("P@ssw0rd-2019", "allowed", &["--allow-weak-password"]);
+    assert!(ok && written, "--allow-weak-password overrides it");
+    let (ok, stderr, written) = keygen("Quokka-Pickering-77", "fine", &[]);
+    assert!(ok && written, "{}", stderr);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+    println!("✅ zkp-auth refuses breached passwords when asked to!");
+}
@@ -0,0 +1,74 @@
+// Tests for building a validated ZKP instance
+use num_bigint::BigUint;
+use rust_zkp_chaum_pedersen::params::ParametersError;
+use rust_zkp_chaum_pedersen::ZKP;
+
+#[test]
+fn test_builder_accepts_valid_groups() {
+    println!("🏗️  Testing the ZKP builder");
+
+    let (alpha, beta, p, q) = ZKP::get_constants();
+    let zkp = ZKP::builder().p(p.clone()).q(q.clone()).alpha(alpha.clone()).beta(beta.clone()).build().unwrap();
+    assert_eq!(zkp, ZKP { alpha, beta, p, q });
+    assert_eq!(ZKP::builder().rfc5114().build().unwrap(), zkp);
+
+    // the toy group from the Readme
+    let toy = ZKP::builder()
+        .p(BigUint::from(23u32))
+        .q(BigUint::from(11u32))
+        .alpha(BigUint::from(4u32))
+        .beta(BigUint::from(9u32))
+        .build();
+    assert!(toy.is_ok());
+
+    println!("✅ Valid groups build!");
+}
+
+#[test]
+fn test_builder_rejects_bad_groups() {
+    println!("🚫 Testing the builder refuses broken parameters");
+
+    let toy = || {
+        ZKP::builder()
+            .p(BigUint::from(23u32))
+            .q(BigUint::from(11u32))
+            .alpha(BigUint::from(4u32))
+            .beta(BigUint::from(9u32))
+    };
+
+    // a value left out
+    let missing = ZKP::builder().p(BigUint::from(23u32)).q(BigUint::from(11u32)).alpha(BigUint::from(4u32)).build();
+    assert_eq!(missing, Err(ParametersError::InvalidGroup("beta is not set")));
+
+    // 7 does not divide 22
+    assert_eq!(
+        toy().q(BigUint::from(7u32)).build(),
+        Err(ParametersError::InvalidGroup("q does not divide p - 1"))
+    );
+    // 5 has order 22, not 11
+    assert_eq!(
+        toy().beta(BigUint::from(5u32)).build(),
+        Err(ParametersError::InvalidGroup("generators must have order q"))
+    );
+    // 4^22 = 1, but 22 isn't prime: 4 has order 11
+    assert_eq!(toy().q(BigUint::from(22u32)).build(), Err(ParametersError::InvalidGroup("q is not prime")));
+    // 2 divides 9 - 1, but Z_9 is no field
+    assert_eq!(
+        ZKP::builder().p(BigUint::from(9u32)).q(BigUint::from(2u32)).alpha(BigUint::from(8u32)).beta(BigUint::from(8u32)).build(),
+        Err(ParametersError::InvalidGroup("p is not prime"))
+    );
+    // a Carmichael number passes Fermat's test for every base prime to it, but not Miller-Rabin
+    let carmichael = BigUint::from(41u32 * 61 * 101);
+    let p = &carmichael * 2u32 + 1u32;
+    assert_eq!(
+        ZKP::builder().p(p).q(carmichael).alpha(BigUint::from(4u32)).beta(BigUint::from(9u32)).build(),
+        Err(ParametersError::InvalidGroup("q is not prime"))
+    );
+    // 1 generates nothing
+    assert_eq!(
+        toy().alpha(BigUint::from(1u32)).build(),
+        Err(ParametersError::InvalidGroup("generators must be between 1 and p"))
+    );
+
+    println!("✅ Broken groups are refused!");
+}
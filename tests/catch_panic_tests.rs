@@ -0,0 +1,193 @@
+// Tests for containing panics in handlers, the crypto pool and jobs
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tonic::body::BoxBody;
+use tonic::codegen::http;
+use tonic::{Code, Status};
+use tower::{Layer, ServiceExt};
+
+use rust_zkp_chaum_pedersen::api::v1::{
+    auth_client::AuthClient, AuthenticationAnswerRequest, AuthenticationChallengeRequest, ChallengeCommitmentRequest,
+    CheckRevocationRequest, ElevateSessionRequest, IntrospectSessionRequest, KdfParams, ListSessionsRequest,
+    RegisterRequest, RevokeProofRequest, RevokeSessionRequest, UserKdfParamsRequest,
+};
+use rust_zkp_chaum_pedersen::blocking::CryptoPool;
+use rust_zkp_chaum_pedersen::catch_panic::{self, CatchPanicLayer, LockExt};
+use rust_zkp_chaum_pedersen::metrics;
+use rust_zkp_chaum_pedersen::scheduler::{Schedule, Scheduler};
+use rust_zkp_chaum_pedersen::types::Challenge;
+use rust_zkp_chaum_pedersen::ZKP;
+
+mod common;
+use common::{connect, Server};
+
+#[test]
+fn test_contain() {
+    println!("💥 Testing contain");
+
+    assert_eq!(catch_panic::contain(|| 42).unwrap(), 42);
+    let panic = catch_panic::contain(|| -> u32 { panic!("bad {}", "input") }).unwrap_err();
+    assert_eq!(panic.message, "bad input");
+    assert!(panic.location.contains("catch_panic_tests.rs"), "{}", panic.location);
+    assert!(!panic.backtrace.is_empty(), "a backtrace even without RUST_BACKTRACE");
+    assert!(panic.to_string().starts_with("panicked at "), "{}", panic);
+    let panic = catch_panic::contain(|| std::panic::panic_any(7u8)).unwrap_err();
+    assert_eq!(panic.message, "a panic without a message");
+
+    // a handler that panicked with the lock held doesn't lock everyone out
+    let users = Mutex::new(vec!["alice"]);
+    let _ = catch_panic::contain(|| {
+        let mut users = users.lock().unwrap();
+        users.push("bob");
+        panic!("halfway");
+    });
+    assert!(users.is_poisoned());
+    assert_eq!(*users.locked(), ["alice", "bob"]);
+
+    println!("✅ Panics become values!");
+}
+
+#[tokio::test]
+async fn test_layer_answers_internal() {
+    println!("💥 Testing the panic layer");
+
+    let service = CatchPanicLayer.layer(tower::service_fn(|request: http::Request<()>| async move {
+        tokio::task::yield_now().await;
+        if request.uri().path().ends_with("/Panic") {
+            panic!("the handler fell over");
+        }
+        Ok::<_, std::convert::Infallible>(http::Response::new(tonic::body::empty_body()))
+    }));
+    let call = |path: &'static str| service.clone().oneshot(http::Request::get(path).body(()).unwrap());
+
+    let response: http::Response<BoxBody> = call("/zkp_auth.v1.Auth/Panic").await.unwrap();
+    let status = Status::from_header_map(response.headers()).unwrap();
+    assert_eq!(status.code(), Code::Internal);
+    assert_eq!(status.message(), "internal error", "nothing about the panic leaks");
+    let response = call("/zkp_auth.v1.Auth/Register").await.unwrap();
+    assert!(Status::from_header_map(response.headers()).is_none(), "other requests go through");
+
+    println!("✅ A panicking handler answers Internal!");
+}
+
+#[tokio::test]
+async fn test_pool_and_jobs_survive_panics() {
+    println!("💥 Testing panics in the crypto pool and in jobs");
+
+    let pool = CryptoPool::new(1, 4);
+    let status = pool.run(|| -> u32 { panic!("modpow went wrong") }).await.unwrap_err();
+    assert_eq!(status.code(), Code::Internal);
+    assert_eq!(pool.run(|| 2 + 2).await.unwrap(), 4, "the only thread is still there");
+    let stats = pool.stats();
+    assert_eq!((stats.completed, stats.panicked), (1, 1));
+
+    let runs = Arc::new(AtomicUsize::new(0));
+    let mut scheduler = Scheduler::default();
+    let counted = runs.clone();
+    scheduler.add("test_panics", Schedule::Every(Duration::from_millis(20)), move || {
+        counted.fetch_add(1, Ordering::SeqCst);
+        async { panic!("every time") }
+    });
+    let handles = scheduler.start();
+    tokio::time::sleep(Duration::from_millis(150)).await;
+    assert!(!handles[0].is_finished(), "the job keeps its schedule");
+    handles[0].abort();
+    assert!(runs.load(Ordering::SeqCst) >= 3);
+    assert!(metrics::jobs().get("test_panics").unwrap().runs >= 3);
+
+    println!("✅ A panic costs one job, not a thread!");
+}
+
+/// an answer, whatever it is, rather than a dropped connection
+fn answered<T>(what: &str, result: Result<tonic::Response<T>, Status>) {
+    if let Err(status) = result {
+        assert!(![Code::Unknown, Code::Unavailable, Code::Internal].contains(&status.code()), "{}: {:?}", what, status);
+    }
+}
+
+#[tokio::test]
+async fn test_server_survives_pathological_inputs() {
+    println!("💥 Testing the server against pathological inputs");
+
+    let port = 50089;
+    let mut server = Server(
+        Command::new(env!("CARGO_BIN_EXE_server"))
+            .args(["--listen", &format!("127.0.0.1:{}", port)])
+            .stdout(Stdio::null())
+            .spawn()
+            .expect("Failed to start server process"),
+    );
+    let mut client = AuthClient::new(connect(port).await);
+
+    let huge = vec![0xff; 4096];
+    let registrations = [
+        RegisterRequest::default(),
+        RegisterRequest { user: "empty".to_string(), ..Default::default() },
+        RegisterRequest { user: "huge".to_string(), y1: huge.clone(), y2: huge.clone(), ..Default::default() },
+        RegisterRequest { user: "zero".to_string(), y1: vec![0], y2: vec![0; 300], ..Default::default() },
+        RegisterRequest { user: "x".repeat(100_000), y1: vec![1], y2: vec![1], ..Default::default() },
+        RegisterRequest { user: "beta".to_string(), y1: vec![2], y2: vec![2], beta: vec![0], ..Default::default() },
+        RegisterRequest { user: "p256".to_string(), y1: vec![4; 65], y2: vec![4; 65], group: "p256".to_string(), ..Default::default() },
+        RegisterRequest { user: "md5".to_string(), y1: vec![3], y2: vec![3], hash: "md5".to_string(), ..Default::default() },
+        RegisterRequest {
+            user: "kdf".to_string(),
+            y1: vec![5],
+            y2: vec![5],
+            kdf: Some(KdfParams { salt: vec![], memory_kib: u32::MAX, iterations: 0, parallelism: u32::MAX }),
+            ..Default::default()
+        },
+    ];
+    for request in registrations {
+        answered("Register", client.register(request).await);
+    }
+
+    for user in ["", "zero", "huge", "nobody"] {
+        for (r1, r2) in [(vec![], vec![]), (vec![0], vec![0]), (huge.clone(), huge.clone())] {
+            let request = AuthenticationChallengeRequest { user: user.to_string(), r1, r2, ..Default::default() };
+            let response = client.create_authentication_challenge(request).await;
+            let auth_id = response.as_ref().map(|response| response.get_ref().auth_id.clone()).unwrap_or_default();
+            answered("CreateAuthenticationChallenge", response);
+            for s in [vec![], vec![0], huge.clone()] {
+                let request = AuthenticationAnswerRequest { auth_id: auth_id.clone(), s, ..Default::default() };
+                answered("VerifyAuthentication", client.verify_authentication(request).await);
+            }
+        }
+        answered("CommitChallenge", client.commit_challenge(ChallengeCommitmentRequest { user: user.to_string() }).await);
+        answered("GetUserKdfParams", client.get_user_kdf_params(UserKdfParamsRequest { user: user.to_string() }).await);
+    }
+    for auth_id in ["", "not-a-ulid", "01ARZ3NDEKTSV4RRFFQ69G5FAV", &"7".repeat(10_000)] {
+        let request = AuthenticationAnswerRequest { auth_id: auth_id.to_string(), s: vec![1], ..Default::default() };
+        answered("VerifyAuthentication", client.verify_authentication(request).await);
+        let request = AuthenticationChallengeRequest { user: "zero".to_string(), auth_id: auth_id.to_string(), ..Default::default() };
+        answered("CreateAuthenticationChallenge", client.create_authentication_challenge(request).await);
+        let request = ElevateSessionRequest { user: "zero".to_string(), auth_id: auth_id.to_string(), ..Default::default() };
+        answered("ElevateSession", client.elevate_session(request).await);
+    }
+    let token = "\u{0}".repeat(100_000);
+    answered("IntrospectSession", client.introspect_session(IntrospectSessionRequest { token: token.clone() }).await);
+    answered("ListSessions", client.list_sessions(ListSessionsRequest { user: "zero".to_string(), session_id: token.clone() }).await);
+    let request = RevokeSessionRequest { user: "zero".to_string(), session_id: token.clone(), revoke_session_id: token.clone() };
+    answered("RevokeSession", client.revoke_session(request).await);
+    let request = RevokeProofRequest { user: "zero".to_string(), session_id: token.clone(), proof_id: token.clone(), reason: token.clone() };
+    answered("RevokeProof", client.revoke_proof(request).await);
+    answered("CheckRevocation", client.check_revocation(CheckRevocationRequest { user: token.clone(), proof_id: token }).await);
+
+    // still up, and still logging people in
+    assert!(server.0.try_wait().unwrap().is_none(), "the server exited");
+    let zkp = ZKP::builder().rfc5114().build().unwrap();
+    let x = ZKP::generate_random_number_below(&zkp.q);
+    let (y1, y2) = zkp.public_key(&x).to_bytes_be();
+    client.register(RegisterRequest { user: "alice".to_string(), y1, y2, ..Default::default() }).await.unwrap();
+    let k = ZKP::generate_random_number_below(&zkp.q);
+    let (r1, r2) = zkp.commitment(&k).to_bytes_be();
+    let request = AuthenticationChallengeRequest { user: "alice".to_string(), r1, r2, ..Default::default() };
+    let response = client.create_authentication_challenge(request).await.unwrap().into_inner();
+    let s = zkp.respond(&k, &Challenge::from_bytes_be(&response.c), &x).to_bytes_be();
+    let request = AuthenticationAnswerRequest { auth_id: response.auth_id, s, ..Default::default() };
+    assert!(!client.verify_authentication(request).await.unwrap().into_inner().session_id.is_empty());
+
+    println!("✅ The server stays up whatever it's sent!");
+}
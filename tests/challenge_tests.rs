@@ -0,0 +1,53 @@
+// Tests for committed challenges H(c | nonce)
+use num_bigint::BigUint;
+use rust_zkp_chaum_pedersen::challenge::{self, commit, new_nonce, verify_opening};
+use rust_zkp_chaum_pedersen::ZKP;
+
+#[test]
+fn test_commitment_binds_the_challenge() {
+    println!("📮 Testing challenge commitments");
+
+    let c = BigUint::from(123456789u64);
+    let nonce = new_nonce();
+    let commitment = commit(&c, &nonce);
+
+    assert!(verify_opening(&commitment, &c, &nonce));
+    // the server can't open it to another challenge...
+    assert!(!verify_opening(&commitment, &BigUint::from(987654321u64), &nonce));
+    // ...or with another nonce
+    assert!(!verify_opening(&commitment, &c, &new_nonce()));
+    assert!(!verify_opening(&commitment, &c, &nonce[..16]));
+
+    // the nonce hides c: the same challenge commits differently every time
+    assert_ne!(commit(&c, &new_nonce()), commitment);
+
+    println!("✅ Challenge commitment test passed!");
+}
+
+#[test]
+fn test_short_challenges() {
+    println!("✂️  Testing configurable challenge sizes");
+
+    let (_, _, _, q) = ZKP::get_constants();
+
+    // at least 80 bits, at most the size of q
+    assert!(challenge::validate_bits(64, &q).is_err());
+    assert!(challenge::validate_bits(80, &q).is_ok());
+    assert!(challenge::validate_bits(q.bits(), &q).is_ok());
+    assert!(challenge::validate_bits(q.bits() + 1, &q).is_err());
+
+    for _ in 0..20 {
+        assert!(challenge::random(128, &q).bits() <= 128);
+        assert!(challenge::random(q.bits(), &q) < q);
+    }
+
+    // a 128-bit challenge still makes a valid proof
+    let zkp = ZKP::builder().rfc5114().build().unwrap();
+    let x = ZKP::generate_random_number_below(&zkp.q);
+    let k = ZKP::generate_random_number_below(&zkp.q);
+    let c = challenge::random(128, &zkp.q).into();
+    let s = zkp.respond(&k, &c, &x);
+    assert!(zkp.verify_response(&zkp.public_key(&x), &zkp.commitment(&k), &c, &s));
+
+    println!("✅ Short challenges work!");
+}
@@ -0,0 +1,130 @@
+// Tests for the signed challenge tokens of --stateless-challenges
+use std::process::{Command, Stdio};
+use std::time::{Duration, SystemTime};
+
+use tonic::Code;
+
+use rust_zkp_chaum_pedersen::api::v1::{
+    AuthenticationAnswerRequest, AuthenticationChallengeRequest, ChallengeCommitmentRequest,
+    ElevateSessionRequest, RegisterRequest,
+};
+use rust_zkp_chaum_pedersen::challenge_token::{ChallengeToken, ChallengeTokenKey, TokenError};
+use rust_zkp_chaum_pedersen::clock::unix_millis;
+use rust_zkp_chaum_pedersen::types::Challenge;
+use rust_zkp_chaum_pedersen::ZKP;
+
+mod common;
+use common::{auth_client, Server};
+
+const KEY: &[u8] = b"0123456789abcdef0123456789abcdef";
+
+#[test]
+fn test_tokens_are_signed() {
+    println!("🎟️  Testing challenge tokens");
+
+    let key = ChallengeTokenKey::new(KEY).unwrap();
+    let now = SystemTime::now();
+    let token = ChallengeToken {
+        auth_id: "01HF0000000000000000000000".to_string(),
+        user: "alice".to_string(),
+        device_id: String::new(),
+        c: vec![7; 32],
+        r1: vec![1; 256],
+        r2: vec![2; 256],
+        client: Vec::new(),
+        pow_difficulty: 0,
+        expires_unix_millis: unix_millis(now + Duration::from_secs(60)),
+    };
+    let signed = key.sign(&token);
+    assert_eq!(key.open(&signed, now), Ok(token.clone()));
+
+    // only we can issue them, and only until they expire
+    let other = ChallengeTokenKey::new(b"another key, at least 32 bytes long").unwrap();
+    assert_eq!(other.open(&signed, now), Err(TokenError::BadSignature));
+    let (_, mac) = signed.split_once('.').unwrap();
+    let forged = key.sign(&ChallengeToken { user: "mallory".to_string(), ..token.clone() });
+    let (payload, _) = forged.split_once('.').unwrap();
+    assert_eq!(key.open(&format!("{}.{}", payload, mac), now), Err(TokenError::BadSignature));
+    assert_eq!(key.open(&signed, now + Duration::from_secs(61)), Err(TokenError::Expired));
+    assert!(matches!(key.open("no-dot", now), Err(TokenError::Malformed(_))));
+
+    assert!(ChallengeTokenKey::new(b"too short").is_err());
+    assert_eq!(format!("{:?}", key), "ChallengeTokenKey(..)", "the key is never printed");
+
+    println!("✅ Challenge tokens can't be forged!");
+}
+
+#[tokio::test]
+async fn test_any_replica_checks_the_answer() {
+    println!("🎟️  Testing stateless challenges across replicas");
+
+    let key_file = std::env::temp_dir().join(format!("zkp-challenge-token-key-{}", std::process::id()));
+    std::fs::write(&key_file, KEY).unwrap();
+    let replica = |port: u16| {
+        Server(
+            Command::new(env!("CARGO_BIN_EXE_server"))
+                .args(["--listen", &format!("127.0.0.1:{}", port), "--stateless-challenges"])
+                .arg("--challenge-token-key-file")
+                .arg(&key_file)
+                .stdout(Stdio::null())
+                .spawn()
+                .expect("Failed to start server process"),
+        )
+    };
+    let (_first, _second) = (replica(50110), replica(50111));
+    let (mut first, mut second) = (auth_client(50110).await, auth_client(50111).await);
+
+    let zkp = ZKP::builder().rfc5114().build().unwrap();
+    let x = ZKP::generate_random_number_below(&zkp.q);
+    let (y1, y2) = zkp.public_key(&x).to_bytes_be();
+    for client in [&mut first, &mut second] {
+        let request = RegisterRequest { user: "alice".to_string(), y1: y1.clone(), y2: y2.clone(), ..Default::default() };
+        client.register(request).await.unwrap();
+    }
+
+    // challenged by one replica, answered on the other
+    let k = ZKP::generate_random_number_below(&zkp.q);
+    let (r1, r2) = zkp.commitment(&k).to_bytes_be();
+    let request = AuthenticationChallengeRequest { user: "alice".to_string(), r1, r2, ..Default::default() };
+    let challenge = first.create_authentication_challenge(request).await.unwrap().into_inner();
+    assert!(!challenge.challenge_token.is_empty());
+    let answer = AuthenticationAnswerRequest {
+        auth_id: challenge.auth_id.clone(),
+        s: zkp.respond(&k, &Challenge::from_bytes_be(&challenge.c), &x).to_bytes_be(),
+        challenge_token: challenge.challenge_token.clone(),
+        ..Default::default()
+    };
+    let session_id = second.verify_authentication(answer.clone()).await.unwrap().into_inner().session_id;
+    assert!(!session_id.is_empty());
+    // and it logs in once: the same answer again is refused, not another session
+    let status = second.verify_authentication(answer.clone()).await.unwrap_err();
+    assert_eq!(status.code(), Code::NotFound);
+    // nor does it elevate the session it opened
+    let elevate = ElevateSessionRequest {
+        user: "alice".to_string(),
+        session_id,
+        auth_id: answer.auth_id.clone(),
+        s: answer.s.clone(),
+        challenge_token: answer.challenge_token.clone(),
+    };
+    assert_eq!(second.elevate_session(elevate).await.unwrap_err().code(), Code::NotFound);
+
+    // the token is what the answer is checked against, so it has to be there and untouched
+    let status = second.verify_authentication(AuthenticationAnswerRequest { challenge_token: String::new(), ..answer.clone() }).await.unwrap_err();
+    assert_eq!(status.code(), Code::InvalidArgument);
+    let tampered = format!("{}x", challenge.challenge_token);
+    let status = second.verify_authentication(AuthenticationAnswerRequest { challenge_token: tampered, ..answer.clone() }).await.unwrap_err();
+    assert_eq!(status.code(), Code::InvalidArgument);
+    let other_id = AuthenticationAnswerRequest { auth_id: "01HF0000000000000000000000".to_string(), ..answer.clone() };
+    assert_eq!(second.verify_authentication(other_id).await.unwrap_err().code(), Code::InvalidArgument);
+
+    let wrong = AuthenticationAnswerRequest { s: zkp.respond(&k, &Challenge::from_bytes_be(&challenge.c), &(&x + 1u32)).to_bytes_be(), ..answer };
+    assert_eq!(second.verify_authentication(wrong).await.unwrap_err().code(), Code::PermissionDenied);
+
+    // a committed challenge would have to be remembered
+    let status = first.commit_challenge(ChallengeCommitmentRequest { user: "alice".to_string() }).await.unwrap_err();
+    assert_eq!(status.code(), Code::FailedPrecondition);
+
+    let _ = std::fs::remove_file(&key_file);
+    println!("✅ Replicas share a key, not challenges!");
+}
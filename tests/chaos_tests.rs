@@ -0,0 +1,293 @@
+// Chaos tests: the zkp-auth CLI against a server behind a faulty network
+//
+// The CLI talks to the server through ChaosProxy, which delays every answer
+// by a random amount and now and then cuts a connection before an answer gets
+// through, so the request reached the server but the client never heard back.
+// The server is also killed and restarted in the middle of logins. The client
+// has to retry calls and ask for a new challenge when the server lost its one;
+// the server has to refuse stale challenges and keep registrations.
+//
+// The faults come from a seeded RNG. A failing run prints its seed; rerun it
+// with CHAOS_SEED=<seed>, and run more rounds with CHAOS_ROUNDS=<n>.
+use std::io::Write;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+use rust_zkp_chaum_pedersen::api::v1::{
+    AuthenticationAnswerRequest, AuthenticationChallengeRequest, KdfParams, RegisterRequest,
+};
+use rust_zkp_chaum_pedersen::ZKP;
+
+mod common;
+use common::{auth_client, temp_dir, Server};
+
+const PASSWORD: &str = "correct horse battery staple";
+
+/// What the proxy does to the connections going through it
+struct Faults {
+    max_delay: Duration, // each chunk from the server waits up to this long
+    cut_rate: f64,       // share of connections cut before an answer reaches the client
+}
+
+/// A TCP proxy in front of the server that injects faults
+struct ChaosProxy {
+    rng: Mutex<StdRng>,
+    faults: Faults,
+    last_cut: AtomicBool,
+    cuts: AtomicUsize,
+}
+
+impl ChaosProxy {
+    /// listen on `listen` and forward to `upstream` until the test ends
+    async fn start(listen: u16, upstream: u16, seed: u64, faults: Faults) -> Arc<Self> {
+        let proxy = Arc::new(ChaosProxy {
+            rng: Mutex::new(StdRng::seed_from_u64(seed)),
+            faults,
+            last_cut: AtomicBool::new(false),
+            cuts: AtomicUsize::new(0),
+        });
+        let listener = TcpListener::bind(("127.0.0.1", listen)).await.unwrap();
+        let upstream = SocketAddr::from(([127, 0, 0, 1], upstream));
+        let accepting = proxy.clone();
+        tokio::spawn(async move {
+            while let Ok((client, _)) = listener.accept().await {
+                tokio::spawn(accepting.clone().forward(client, upstream));
+            }
+        });
+        proxy
+    }
+
+    /// after how many chunks from the server to cut this connection, None to leave it be;
+    /// never two in a row, so every call gets through within a retry
+    fn plan_cut(&self) -> Option<usize> {
+        let mut rng = self.rng.lock().unwrap();
+        let cut = !self.last_cut.load(Ordering::Relaxed) && rng.gen_bool(self.faults.cut_rate);
+        self.last_cut.store(cut, Ordering::Relaxed);
+        // the first chunk is the HTTP/2 handshake, let the connection come up
+        cut.then(|| rng.gen_range(1..=4))
+    }
+
+    fn delay(&self) -> Duration {
+        self.rng.lock().unwrap().gen_range(Duration::ZERO..=self.faults.max_delay)
+    }
+
+    fn cuts(&self) -> usize {
+        self.cuts.load(Ordering::Relaxed)
+    }
+
+    async fn forward(self: Arc<Self>, client: TcpStream, upstream: SocketAddr) {
+        // a server that's down (restarting) is a refused connection for the client too
+        let Ok(server) = TcpStream::connect(upstream).await else {
+            return;
+        };
+        let cut = self.plan_cut();
+        let (mut client_read, mut client_write) = client.into_split();
+        let (mut server_read, mut server_write) = server.into_split();
+        // requests go through untouched
+        let requests = tokio::spawn(async move { tokio::io::copy(&mut client_read, &mut server_write).await });
+
+        let mut buf = vec![0; 16 * 1024];
+        let mut chunk = 0;
+        loop {
+            let n = match server_read.read(&mut buf).await {
+                Ok(0) | Err(_) => break,
+                Ok(n) => n,
+            };
+            if Some(chunk) == cut {
+                // the server answered, the client never hears of it
+                self.cuts.fetch_add(1, Ordering::Relaxed);
+                break;
+            }
+            tokio::time::sleep(self.delay()).await;
+            if client_write.write_all(&buf[..n]).await.is_err() {
+                break;
+            }
+            chunk += 1;
+        }
+        // dropping both halves closes both connections
+        requests.abort();
+    }
+}
+
+fn seed() -> u64 {
+    let seed = std::env::var("CHAOS_SEED").ok().and_then(|seed| seed.parse().ok()).unwrap_or_else(rand::random);
+    println!("🎲 CHAOS_SEED={}", seed);
+    seed
+}
+
+fn rounds() -> usize {
+    std::env::var("CHAOS_ROUNDS").ok().and_then(|rounds| rounds.parse().ok()).unwrap_or(3)
+}
+
+fn start_server(port: u16, store: &Path) -> Server {
+    let child = Command::new(env!("CARGO_BIN_EXE_server"))
+        .arg("--listen")
+        .arg(format!("127.0.0.1:{}", port))
+        .arg("--user-store")
+        .arg(store)
+        .stdout(Stdio::null())
+        .spawn()
+        .expect("Failed to start server process");
+    Server(child)
+}
+
+/// run `zkp-auth <command> --endpoint .. --user ..` with the password on stdin;
+/// returns whether it succeeded and what it printed
+async fn zkp_auth(command: &str, port: u16, user: &str, home: &Path) -> (bool, String) {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_zkp-auth"))
+        .arg(command)
+        .arg("--endpoint")
+        .arg(format!("http://127.0.0.1:{}", port))
+        .arg("--user")
+        .arg(user)
+        .env("ZKP_AUTH_PROFILES", home.join("profiles.json"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    writeln!(child.stdin.take().unwrap(), "{}", PASSWORD).unwrap();
+    let output = tokio::task::spawn_blocking(move || child.wait_with_output().unwrap()).await.unwrap();
+    let printed = format!("{}{}", String::from_utf8_lossy(&output.stdout), String::from_utf8_lossy(&output.stderr));
+    (output.status.success(), printed)
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_cli_through_a_faulty_network() {
+    println!("🌪️ Testing register and login through delayed and cut connections");
+
+    let seed = seed();
+    let dir = temp_dir("network");
+    let (server_port, proxy_port) = (50074, 50075);
+    let server = start_server(server_port, &dir.join("users.jsonl"));
+    auth_client(server_port).await;
+    let faults = Faults { max_delay: Duration::from_millis(30), cut_rate: 1.0 };
+    let proxy = ChaosProxy::start(proxy_port, server_port, seed, faults).await;
+
+    let (registered, printed) = zkp_auth("register", proxy_port, "chaos_network", &dir).await;
+    assert!(registered, "seed {}: {}", seed, printed);
+    for round in 1..=rounds() {
+        let (logged_in, printed) = zkp_auth("login", proxy_port, "chaos_network", &dir).await;
+        assert!(logged_in, "seed {}, round {}: {}", seed, round, printed);
+        assert!(printed.contains("Logged in as chaos_network"), "{}", printed);
+    }
+    drop(server);
+
+    println!("📊 {} connections cut", proxy.cuts());
+    assert!(proxy.cuts() > 0, "the proxy never got to cut a connection");
+    std::fs::remove_dir_all(&dir).unwrap();
+    println!("✅ The client rides out a faulty network!");
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_cli_through_server_restarts() {
+    println!("🌪️ Testing logins while the server restarts under them");
+
+    let seed = seed();
+    let dir = temp_dir("restarts");
+    let store = dir.join("users.jsonl");
+    let (server_port, proxy_port) = (50076, 50077);
+    let mut server = start_server(server_port, &store);
+    auth_client(server_port).await;
+    // slow answers stretch the flow out, so the restarts land in the middle of it
+    let faults = Faults { max_delay: Duration::from_millis(100), cut_rate: 0.0 };
+    let proxy = ChaosProxy::start(proxy_port, server_port, seed, faults).await;
+    let mut rng = StdRng::seed_from_u64(seed);
+
+    let (registered, printed) = zkp_auth("register", proxy_port, "chaos_restart", &dir).await;
+    assert!(registered, "seed {}: {}", seed, printed);
+    let mut recovered = 0;
+    for round in 1..=rounds() {
+        let login = tokio::spawn({
+            let dir = dir.clone();
+            async move { zkp_auth("login", proxy_port, "chaos_restart", &dir).await }
+        });
+        tokio::time::sleep(Duration::from_millis(rng.gen_range(0..1500))).await;
+        drop(server);
+        server = start_server(server_port, &store);
+
+        let (logged_in, printed) = login.await.unwrap();
+        assert!(logged_in, "seed {}, round {}: {}", seed, round, printed);
+        // a restart between steps shows up as retries or a new challenge
+        let warnings: Vec<&str> = printed.lines().filter(|line| line.starts_with("⚠️")).collect();
+        for warning in &warnings {
+            println!("   round {}: {}", round, warning);
+        }
+        recovered += !warnings.is_empty() as usize;
+    }
+    drop(server);
+
+    println!("📊 {} logins recovered from a restart", recovered);
+    assert_eq!(proxy.cuts(), 0);
+    std::fs::remove_dir_all(&dir).unwrap();
+    println!("✅ Logins survive the server restarting!");
+}
+
+#[tokio::test]
+async fn test_challenges_do_not_survive_a_restart() {
+    println!("🌪️ Testing a challenge answered after the server restarted");
+
+    let dir = temp_dir("stale");
+    let store = dir.join("users.jsonl");
+    let port = 50078;
+    let server = start_server(port, &store);
+    let mut client = auth_client(port).await;
+
+    let zkp = ZKP::builder().rfc5114().build().unwrap();
+    let kdf = rust_zkp_chaum_pedersen::kdf::KdfParams { salt: vec![3; 16], memory_kib: 64, iterations: 1, parallelism: 1 };
+    let x = kdf.derive(PASSWORD, &zkp.q).unwrap();
+    let (y1, y2) = zkp.public_key(&x).to_bytes_be();
+    client
+        .register(RegisterRequest {
+            user: "chaos_stale".to_string(),
+            y1,
+            y2,
+            kdf: Some(KdfParams::from(&kdf)),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+    let k = ZKP::generate_random_number_below(&zkp.q);
+    let (r1, r2) = zkp.commitment(&k).to_bytes_be();
+    let challenge_request = AuthenticationChallengeRequest { user: "chaos_stale".to_string(), r1, r2, ..Default::default() };
+    let challenge = client.create_authentication_challenge(challenge_request.clone()).await.unwrap().into_inner();
+    let s = zkp.respond(&k, &rust_zkp_chaum_pedersen::types::Challenge::from_bytes_be(&challenge.c), &x);
+    let answer = AuthenticationAnswerRequest { auth_id: challenge.auth_id, s: s.to_bytes_be(), ..Default::default() };
+
+    // the server goes down between the challenge and the answer
+    drop(server);
+    let server = start_server(port, &store);
+    let mut client = auth_client(port).await;
+
+    // a correct answer to a challenge the new server never issued is refused
+    let stale = client.verify_authentication(answer).await.unwrap_err();
+    assert_eq!(stale.code(), tonic::Code::NotFound, "{}", stale);
+
+    // the account is still there and a fresh challenge works
+    let k = ZKP::generate_random_number_below(&zkp.q);
+    let (r1, r2) = zkp.commitment(&k).to_bytes_be();
+    let challenge = client
+        .create_authentication_challenge(AuthenticationChallengeRequest { r1, r2, ..challenge_request })
+        .await
+        .unwrap()
+        .into_inner();
+    let s = zkp.respond(&k, &rust_zkp_chaum_pedersen::types::Challenge::from_bytes_be(&challenge.c), &x);
+    let answer = client
+        .verify_authentication(AuthenticationAnswerRequest { auth_id: challenge.auth_id, s: s.to_bytes_be(), ..Default::default() })
+        .await;
+    drop(server);
+
+    assert!(answer.is_ok(), "{:?}", answer);
+    std::fs::remove_dir_all(&dir).unwrap();
+    println!("✅ Stale challenges are refused and the user can start over!");
+}
@@ -0,0 +1,48 @@
+// Tests for clock skew handling
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use rust_zkp_chaum_pedersen::clock::{estimate_offset_millis, Clock};
+use rust_zkp_chaum_pedersen::nizk::ValidityWindow;
+use rust_zkp_chaum_pedersen::ZKP;
+
+#[test]
+fn test_skew_is_tolerated_at_both_ends() {
+    println!("🕒 Testing clock skew tolerance");
+
+    let start = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+    let window = ValidityWindow::starting_at(start, Duration::from_secs(60));
+    let skew = Duration::from_secs(30);
+
+    // a verifier whose clock is behind the prover's
+    let early = start - Duration::from_secs(20);
+    assert!(!window.contains(early));
+    assert!(window.contains(Clock { now: early, skew }));
+    // and one whose clock is ahead
+    let late = start + Duration::from_secs(80);
+    assert!(!window.contains(late));
+    assert!(window.contains(Clock { now: late, skew }));
+    // skew is not unlimited
+    assert!(!window.contains(Clock { now: start + Duration::from_secs(91), skew }));
+
+    let zkp = ZKP::builder().rfc5114().build().unwrap();
+    let x = ZKP::generate_random_number_below(&zkp.q);
+    let (y1, y2) = zkp.compute_pair(&x);
+    let proof = zkp.prove_non_interactive(&x, window, b"skew");
+    assert!(zkp.verify_non_interactive(&y1, &y2, &proof, b"skew", Clock { now: late, skew }));
+
+    println!("✅ Skew test passed!");
+}
+
+#[test]
+fn test_offset_estimate_uses_the_round_trip_midpoint() {
+    println!("📡 Testing server clock offset estimation");
+
+    let sent = UNIX_EPOCH + Duration::from_secs(1_000);
+    let received = sent + Duration::from_millis(200);
+    // server read its clock 100ms after we sent, and is 5s ahead
+    assert_eq!(estimate_offset_millis(sent, received, 1_000_000 + 100 + 5_000), 5_000);
+    assert_eq!(estimate_offset_millis(sent, received, 1_000_000 + 100 - 2_000), -2_000);
+    assert!(estimate_offset_millis(SystemTime::now(), SystemTime::now(), 0) < 0);
+
+    println!("✅ Offset test passed!");
+}
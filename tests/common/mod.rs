@@ -0,0 +1,44 @@
+//! Helpers shared by the integration tests, pulled in with `mod common;`.
+//! Each test crate uses only some of them.
+#![allow(dead_code)]
+
+use std::path::PathBuf;
+use std::process::Child;
+use std::time::Duration;
+
+use rust_zkp_chaum_pedersen::api::v1::auth_client::AuthClient;
+use tonic::transport::Channel;
+
+/// A server process, killed when dropped so a failed assertion doesn't leave it running
+pub struct Server(pub Child);
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+        let _ = self.0.wait();
+    }
+}
+
+/// a channel to the test server on `port`, retried while it starts up
+pub async fn connect(port: u16) -> Channel {
+    for _ in 0..50 {
+        if let Ok(channel) = Channel::from_shared(format!("http://127.0.0.1:{}", port)).unwrap().connect().await {
+            return channel;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    panic!("the test server didn't come up on port {}", port);
+}
+
+/// an `Auth` client for the test server on `port`
+pub async fn auth_client(port: u16) -> AuthClient<Channel> {
+    AuthClient::new(connect(port).await)
+}
+
+/// an empty directory for one test, cleared of what an earlier run left
+pub fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("zkp-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
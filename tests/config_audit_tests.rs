@@ -0,0 +1,108 @@
+// Tests for zkp-auth audit-config and the baseline behind it
+use std::path::Path;
+use std::process::{Command, Output, Stdio};
+use std::time::Duration;
+
+use tonic::transport::Channel;
+
+use rust_zkp_chaum_pedersen::config_audit::{alphanumeric_bits, audit, is_loopback, Baseline, ConfigSnapshot, ServerSettings, Severity};
+
+mod common;
+use common::Server;
+
+fn checks(config: &ConfigSnapshot) -> Vec<(Severity, &'static str)> {
+    audit(config, &Baseline::default()).into_iter().map(|finding| (finding.severity, finding.check)).collect()
+}
+
+#[test]
+fn test_findings_against_the_baseline() {
+    println!("🔎 Testing the security baseline");
+
+    let settings = ServerSettings {
+        challenge_bits: 256,
+        session_id_bits: 256,
+        challenge_ttl: Some(Duration::from_secs(300)),
+        session_retention: Some(Duration::from_secs(7 * 86400)),
+    };
+    let good = ConfigSnapshot { group_bits: 2048, q_bits: 256, tls: true, loopback: false, pinned_key: true, server: Some(settings) };
+    assert_eq!(checks(&good), vec![]);
+
+    // the default server: a 1024-bit group and 12-character session IDs
+    assert_eq!(alphanumeric_bits(12), 71);
+    let default = ServerSettings { challenge_bits: 160, session_id_bits: 71, ..settings };
+    let findings = checks(&ConfigSnapshot { group_bits: 1024, q_bits: 160, server: Some(default), ..good });
+    assert_eq!(findings, vec![(Severity::Medium, "group"), (Severity::Medium, "session_id"), (Severity::Low, "session_vs_challenge")]);
+
+    // a session ID as strong as the baseline is fine even with short challenges
+    let short = ServerSettings { challenge_bits: 80, ..settings };
+    assert_eq!(checks(&ConfigSnapshot { server: Some(short), ..good }), vec![(Severity::Low, "challenge")]);
+
+    let forever = ServerSettings { challenge_ttl: None, session_retention: None, ..settings };
+    assert_eq!(checks(&ConfigSnapshot { server: Some(forever), ..good }), vec![(Severity::Medium, "challenge_ttl"), (Severity::Low, "session_retention")]);
+
+    // plaintext only matters off this machine
+    assert_eq!(checks(&ConfigSnapshot { tls: false, ..good }), vec![(Severity::High, "tls")]);
+    assert_eq!(checks(&ConfigSnapshot { tls: false, loopback: true, ..good }), vec![(Severity::Info, "tls")]);
+    assert_eq!(checks(&ConfigSnapshot { pinned_key: false, ..good }), vec![(Severity::Low, "server_key")]);
+    assert_eq!(checks(&ConfigSnapshot { group_bits: 512, ..good }), vec![(Severity::High, "group")]);
+    assert_eq!(checks(&ConfigSnapshot { server: None, ..good }), vec![(Severity::Info, "server")]);
+
+    println!("✅ Weak settings are found!");
+}
+
+#[test]
+fn test_loopback_endpoints() {
+    println!("🔎 Testing which endpoints are this machine");
+
+    assert!(is_loopback("http://127.0.0.1:50051"));
+    assert!(is_loopback("http://localhost:50051"));
+    assert!(is_loopback("http://[::1]:50051/"));
+    assert!(!is_loopback("https://auth.example.com"));
+    assert!(!is_loopback("http://192.0.2.7:50051"));
+
+    println!("✅ Loopback endpoints are recognized!");
+}
+
+fn zkp_auth(dir: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_zkp-auth"))
+        .args(args)
+        .env("ZKP_AUTH_PROFILES", dir.join("profiles.json"))
+        .output()
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_auditing_a_running_server() {
+    println!("🔎 Testing zkp-auth audit-config");
+
+    let port = 50112;
+    let _server = Server(
+        Command::new(env!("CARGO_BIN_EXE_server"))
+            .args(["--listen", &format!("127.0.0.1:{}", port), "--challenge-ttl", "0"])
+            .stdout(Stdio::null())
+            .spawn()
+            .expect("Failed to start server process"),
+    );
+    let endpoint = format!("http://127.0.0.1:{}", port);
+    for _ in 0..50 {
+        if Channel::from_shared(endpoint.clone()).unwrap().connect().await.is_ok() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+
+    let dir = std::env::temp_dir().join(format!("zkp-config-audit-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let output = zkp_auth(&dir, &["audit-config", "--endpoint", &endpoint]);
+    let printed = String::from_utf8_lossy(&output.stdout).to_string();
+    assert!(output.status.success(), "nothing is high on this machine: {}", printed);
+    for check in ["group", "session_id", "challenge_ttl", "session_retention", "tls"] {
+        assert!(printed.contains(check), "{} missing from {}", check, printed);
+    }
+
+    let output = zkp_auth(&dir, &["audit-config", "--endpoint", &endpoint, "--fail-on", "medium"]);
+    assert_eq!(output.status.code(), Some(1), "{}", String::from_utf8_lossy(&output.stdout));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+    println!("✅ zkp-auth audits the server it talks to!");
+}
@@ -152,4 +152,45 @@ fn test_edge_cases_with_random() {
     assert!(result);
 
     println!("✅ Edge case test passed!");
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_per_user_generator() {
+    println!("👤 Testing per-user generators beta_i = H2G(username)");
+
+    let zkp = ZKP::builder().rfc5114().build().unwrap();
+
+    let alice = zkp.for_user("alice");
+    let bob = zkp.for_user("bob");
+    assert_eq!(alice, zkp.for_user("alice"), "the generator must be reproducible");
+    assert_ne!(alice.beta, bob.beta);
+    assert_ne!(alice.beta, zkp.beta);
+    assert_eq!(alice.alpha, zkp.alpha, "only beta is personalized");
+
+    // it lies in the subgroup of order q
+    let one = BigUint::from(1u32);
+    assert_ne!(alice.beta, one);
+    assert_eq!(alice.beta.modpow(&zkp.q, &zkp.p), one);
+
+    // and the protocol works unchanged with it
+    let x = ZKP::generate_random_number_below(&zkp.q);
+    let k = ZKP::generate_random_number_below(&zkp.q);
+    let c = ZKP::generate_random_number_below(&zkp.q);
+    let (y1, y2) = alice.compute_pair(&x);
+    let (r1, r2) = alice.compute_pair(&k);
+    let s = alice.solve(&k, &c, &x);
+    assert!(alice.verify(&r1, &r2, &y1, &y2, &c, &s));
+    assert!(!bob.verify(&r1, &r2, &y1, &y2, &c, &s), "proofs don't transfer between generators");
+
+    // small groups work too
+    let toy = ZKP {
+        p: BigUint::from(23u32),
+        q: BigUint::from(11u32),
+        alpha: BigUint::from(4u32),
+        beta: BigUint::from(9u32),
+    };
+    let toy_beta = toy.for_user("alice").beta;
+    assert_eq!(toy_beta.modpow(&toy.q, &toy.p), one);
+
+    println!("✅ Per-user generator test passed!");
+}
@@ -0,0 +1,55 @@
+// Tests for the per-call deadlines of zkp-auth
+use std::time::{Duration, Instant};
+
+use tonic::transport::Endpoint;
+use tonic::{Code, Request, Status};
+
+use rust_zkp_chaum_pedersen::api::v1::{auth_client::AuthClient, AuthenticationChallengeRequest};
+use rust_zkp_chaum_pedersen::deadline::{within, with_deadline, CallError, CallTimeouts};
+
+#[test]
+fn test_slow_servers_are_told_apart() {
+    println!("⏱️  Testing deadline errors");
+
+    let timeout = Duration::from_secs(5);
+    let slow = CallError::new("The challenge", timeout, Status::deadline_exceeded("too slow"));
+    assert!(matches!(&slow, CallError::DeadlineExceeded { call, timeout: t } if call == "The challenge" && *t == timeout));
+    assert_eq!(slow.to_string(), "The challenge got no answer within 5s, the server is slow or unreachable");
+    let failed = CallError::new("The challenge", timeout, Status::not_found("User: bob not found in database"));
+    assert!(matches!(&failed, CallError::Failed(status) if status.code() == Code::NotFound));
+
+    // each step has its own; checking an answer takes the server longer than a challenge
+    let timeouts = CallTimeouts::default();
+    assert!(timeouts.challenge < timeouts.verify);
+
+    let request = with_deadline(Request::new(()), Duration::from_millis(1500));
+    assert!(request.metadata().get("grpc-timeout").is_some(), "the server is told too");
+
+    println!("✅ A slow server isn't a failed login!");
+}
+
+#[tokio::test]
+async fn test_calls_give_up_at_their_deadline() {
+    println!("⏱️  Testing a server that never answers");
+
+    // takes the connection and says nothing
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        let mut held = Vec::new();
+        while let Ok((stream, _)) = listener.accept().await {
+            held.push(stream);
+        }
+    });
+
+    let channel = Endpoint::from_shared(format!("http://{}", addr)).unwrap().connect_lazy();
+    let mut client = AuthClient::new(channel);
+    let timeout = Duration::from_millis(300);
+    let started = Instant::now();
+    let request = with_deadline(Request::new(AuthenticationChallengeRequest::default()), timeout);
+    let status = within(timeout, client.create_authentication_challenge(request)).await.unwrap_err();
+    assert_eq!(status.code(), Code::DeadlineExceeded);
+    assert!(started.elapsed() < Duration::from_secs(5), "waited {:?}", started.elapsed());
+
+    println!("✅ Calls stop waiting!");
+}
@@ -0,0 +1,148 @@
+// Tests for delegations: capabilities a user mints for a service, checked and revoked by the server
+use std::process::{Command, Stdio};
+use std::time::{Duration, SystemTime};
+
+use tonic::transport::Channel;
+use tonic::Code;
+
+use rust_zkp_chaum_pedersen::api::v1::{
+    auth_client::AuthClient, AuthenticationAnswerRequest, AuthenticationChallengeRequest, RegisterRequest,
+    RevokeProofRequest, VerifyDelegationRequest, VerifyDelegationResponse,
+};
+use rust_zkp_chaum_pedersen::clock::Clock;
+use rust_zkp_chaum_pedersen::delegation::{Delegation, DelegationError};
+use rust_zkp_chaum_pedersen::nizk::ValidityWindow;
+use rust_zkp_chaum_pedersen::types::Challenge;
+use rust_zkp_chaum_pedersen::ZKP;
+
+mod common;
+use common::{auth_client, Server};
+
+fn scopes(scopes: &[&str]) -> Vec<String> {
+    scopes.iter().map(|scope| scope.to_string()).collect()
+}
+
+#[test]
+fn test_mint_and_verify() {
+    println!("🤝 Testing delegations offline");
+
+    let zkp = ZKP::builder().rfc5114().build().unwrap();
+    let x = ZKP::generate_random_number_below(&zkp.q);
+    let key = zkp.public_key(&x);
+    let now = SystemTime::now();
+    let window = ValidityWindow::starting_at(now, Duration::from_secs(600));
+    let delegation = Delegation::mint(&zkp, &x, "alice", "backup", &scopes(&["backups:read", "backups:write"]), window).unwrap();
+    assert!(delegation.verify(&zkp, &key, now));
+    assert!(delegation.allows("backups:read"));
+    assert!(!delegation.allows("backups"));
+
+    // the blob is all a service gets
+    let read = Delegation::from_blob(&delegation.to_blob()).unwrap();
+    assert_eq!(read, delegation);
+    assert!(read.verify(&zkp, &key, now));
+    assert_eq!(Delegation::from_blob("not a delegation").unwrap_err(), DelegationError::Malformed);
+
+    // nothing in it can change without the proof failing
+    let mut changed = delegation.clone();
+    changed.scopes.push("admin".to_string());
+    assert!(!changed.verify(&zkp, &key, now), "more scopes");
+    let mut changed = delegation.clone();
+    changed.delegate = "mallory".to_string();
+    assert!(!changed.verify(&zkp, &key, now), "another service");
+    let mut changed = delegation.clone();
+    changed.user = "bob".to_string();
+    assert!(!changed.verify(&zkp, &key, now), "another user");
+    let mut changed = delegation.clone();
+    changed.proof.window.not_after += 3600;
+    assert!(!changed.verify(&zkp, &key, now), "a longer window");
+    assert!(!delegation.verify(&zkp, &zkp.public_key(&(x.clone() + 1u32)), now), "another key");
+
+    // and it stops working when the window ends, give or take the skew
+    let later = now + Duration::from_secs(700);
+    assert!(!delegation.verify(&zkp, &key, later));
+    assert!(delegation.verify(&zkp, &key, Clock { now: later, skew: Duration::from_secs(120) }));
+
+    for (delegate, granted, error) in [
+        ("", scopes(&["read"]), DelegationError::NoDelegate),
+        ("backup", scopes(&[]), DelegationError::NoScopes),
+        ("backup", scopes(&["read write"]), DelegationError::BadScope),
+        ("backup", vec!["x".repeat(65)], DelegationError::BadScope),
+    ] {
+        assert_eq!(Delegation::mint(&zkp, &x, "alice", delegate, &granted, window).unwrap_err(), error);
+    }
+
+    println!("✅ A delegation grants what it says and nothing else!");
+}
+
+fn start(port: u16) -> Server {
+    Server(
+        Command::new(env!("CARGO_BIN_EXE_server"))
+            .args(["--listen", &format!("127.0.0.1:{}", port)])
+            .args(["--delegation-max-ttl", "3600"])
+            .stdout(Stdio::null())
+            .spawn()
+            .expect("Failed to start server process"),
+    )
+}
+
+async fn verify(client: &mut AuthClient<Channel>, delegation: &Delegation, delegate: &str, scope: &str) -> VerifyDelegationResponse {
+    let request = VerifyDelegationRequest { delegation: delegation.to_blob(), delegate: delegate.to_string(), scope: scope.to_string() };
+    client.verify_delegation(request).await.unwrap().into_inner()
+}
+
+#[tokio::test]
+async fn test_verify_and_revoke() {
+    println!("🤝 Testing delegations checked by the server");
+
+    let _server = start(50097);
+    let mut client = auth_client(50097).await;
+    let zkp = ZKP::builder().rfc5114().build().unwrap();
+    let x = ZKP::generate_random_number_below(&zkp.q);
+    let (y1, y2) = zkp.public_key(&x).to_bytes_be();
+    client.register(RegisterRequest { user: "alice".to_string(), y1, y2, ..Default::default() }).await.unwrap();
+
+    let window = ValidityWindow::starting_now(Duration::from_secs(600));
+    let delegation = Delegation::mint(&zkp, &x, "alice", "backup", &scopes(&["backups:read"]), window).unwrap();
+    let response = verify(&mut client, &delegation, "backup", "backups:read").await;
+    assert!(response.valid, "{}", response.reason);
+    assert_eq!((response.user.as_str(), response.scopes.clone()), ("alice", scopes(&["backups:read"])));
+    assert_eq!(response.expires_unix, window.not_after);
+    assert_eq!(response.delegation_id, delegation.id());
+    assert!(verify(&mut client, &delegation, "backup", "").await.valid, "no scope checks the delegation only");
+
+    // not valid is an answer, with the reason
+    let response = verify(&mut client, &delegation, "backup", "backups:write").await;
+    assert!(!response.valid && response.user.is_empty());
+    assert!(response.reason.contains("scope"), "{}", response.reason);
+    assert!(!verify(&mut client, &delegation, "mallory", "backups:read").await.valid);
+    let long = ValidityWindow::starting_now(Duration::from_secs(7200));
+    let too_long = Delegation::mint(&zkp, &x, "alice", "backup", &scopes(&["backups:read"]), long).unwrap();
+    assert!(verify(&mut client, &too_long, "backup", "backups:read").await.reason.contains("longer"));
+    let expired = ValidityWindow { not_before: window.not_before - 7200, not_after: window.not_before - 3600 };
+    let expired = Delegation::mint(&zkp, &x, "alice", "backup", &scopes(&["backups:read"]), expired).unwrap();
+    assert!(!verify(&mut client, &expired, "backup", "backups:read").await.valid);
+    let wrong_x = x.clone() + 1u32;
+    let forged = Delegation::mint(&zkp, &wrong_x, "alice", "backup", &scopes(&["backups:read"]), window).unwrap();
+    assert!(verify(&mut client, &forged, "backup", "backups:read").await.reason.contains("proof"));
+    let request = VerifyDelegationRequest { delegation: "garbage".to_string(), delegate: "backup".to_string(), scope: String::new() };
+    assert_eq!(client.verify_delegation(request).await.unwrap_err().code(), Code::InvalidArgument);
+
+    // the user revokes it like any exported proof
+    let k = ZKP::generate_random_number_below(&zkp.q);
+    let (r1, r2) = zkp.commitment(&k).to_bytes_be();
+    let request = AuthenticationChallengeRequest { user: "alice".to_string(), r1, r2, ..Default::default() };
+    let challenge = client.create_authentication_challenge(request).await.unwrap().into_inner();
+    let s = zkp.respond(&k, &Challenge::from_bytes_be(&challenge.c), &x).to_bytes_be();
+    let request = AuthenticationAnswerRequest { auth_id: challenge.auth_id, s, ..Default::default() };
+    let session_id = client.verify_authentication(request).await.unwrap().into_inner().session_id;
+    let request = RevokeProofRequest {
+        user: "alice".to_string(),
+        session_id,
+        proof_id: delegation.id().to_string(),
+        reason: "the backup host was retired".to_string(),
+    };
+    client.revoke_proof(request).await.unwrap();
+    assert!(verify(&mut client, &delegation, "backup", "backups:read").await.reason.contains("revoked"));
+
+    println!("✅ Services learn who they act for, until the user says no!");
+}
@@ -0,0 +1,25 @@
+// Tests for zkp-auth demo --all-in-one, which runs the protocol against a server of its own
+use std::process::Command;
+
+#[test]
+fn test_all_in_one_demo() {
+    println!("🎬 Testing the all-in-one demo");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_zkp-auth"))
+        .args(["demo", "--all-in-one", "--password", "hunter2"])
+        .output()
+        .expect("could not run zkp-auth");
+    let printed = String::from_utf8_lossy(&output.stdout).to_string();
+    assert!(output.status.success(), "{}{}", printed, String::from_utf8_lossy(&output.stderr));
+
+    assert!(printed.contains("🚀 Started a server on 127.0.0.1:"), "{}", printed);
+    for step in ["1. Public parameters", "2. Registration", "3. Login: commitment", "4. Login: challenge", "5. Login: response", "6. Verification"] {
+        assert!(printed.contains(step), "{} missing from {}", step, printed);
+    }
+    assert!(printed.contains("== r1 ✓") && printed.contains("== r2 ✓"), "{}", printed);
+    assert!(printed.contains("✅ Logged in, session"), "{}", printed);
+    assert!(printed.contains("✅ Refused"), "the wrong secret is refused: {}", printed);
+    assert!(printed.contains("🛑 Stopped the server"), "{}", printed);
+
+    println!("✅ The demo runs from start to finish!");
+}
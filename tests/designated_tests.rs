@@ -0,0 +1,66 @@
+// Tests for designated-verifier proofs
+use std::time::{Duration, SystemTime};
+
+use rust_zkp_chaum_pedersen::designated::{forge, prove, verify, DesignatedProof, VerifierKey};
+use rust_zkp_chaum_pedersen::nizk::ValidityWindow;
+use rust_zkp_chaum_pedersen::ZKP;
+
+fn setup() -> ZKP {
+    ZKP::builder().rfc5114().build().unwrap()
+}
+
+#[test]
+fn test_designated_proof_convinces_its_verifier() {
+    println!("🎯 Testing designated-verifier proofs");
+
+    let zkp = setup();
+    let verifier = VerifierKey::generate(&zkp);
+    let x = ZKP::generate_random_number_below(&zkp.q);
+    let (y1, y2) = zkp.compute_pair(&x);
+
+    let now = SystemTime::now();
+    let window = ValidityWindow::starting_at(now, Duration::from_secs(300));
+    let proof = prove(&zkp, &x, &verifier.public, window, b"login to example.com");
+    assert!(verify(&zkp, &y1, &y2, &verifier.public, b"login to example.com", &proof, now));
+
+    // expires with its window
+    let later = now + Duration::from_secs(301);
+    assert!(!verify(&zkp, &y1, &y2, &verifier.public, b"login to example.com", &proof, later));
+
+    // bound to the context and to the verifier
+    assert!(!verify(&zkp, &y1, &y2, &verifier.public, b"login to evil.com", &proof, now));
+    let other = VerifierKey::generate(&zkp);
+    assert!(!verify(&zkp, &y1, &y2, &other.public, b"login to example.com", &proof, now));
+
+    // and to the statement
+    let (y1_other, y2_other) = zkp.compute_pair(&ZKP::generate_random_number_below(&zkp.q));
+    assert!(!verify(&zkp, &y1_other, &y2_other, &verifier.public, b"login to example.com", &proof, now));
+
+    let mut tampered = proof.clone();
+    tampered.c1 = tampered.c2.clone();
+    assert!(!verify(&zkp, &y1, &y2, &verifier.public, b"login to example.com", &tampered, now));
+
+    // survives being exported as JSON
+    let json = serde_json::to_string(&proof).unwrap();
+    let imported: DesignatedProof = serde_json::from_str(&json).unwrap();
+    assert!(verify(&zkp, &y1, &y2, &verifier.public, b"login to example.com", &imported, now));
+
+    println!("✅ Designated proof test passed!");
+}
+
+#[test]
+fn test_verifier_can_forge_so_proofs_are_not_evidence() {
+    println!("🕵️ Testing that the verifier could have made the proof itself");
+
+    let zkp = setup();
+    let verifier = VerifierKey::generate(&zkp);
+    // someone else's public values, the verifier doesn't know their x
+    let (y1, y2) = zkp.compute_pair(&ZKP::generate_random_number_below(&zkp.q));
+
+    let now = SystemTime::now();
+    let window = ValidityWindow::starting_at(now, Duration::from_secs(60));
+    let forged = forge(&zkp, &y1, &y2, &verifier, window, b"context");
+    assert!(verify(&zkp, &y1, &y2, &verifier.public, b"context", &forged, now));
+
+    println!("✅ Forgery test passed: a transcript proves nothing to third parties");
+}
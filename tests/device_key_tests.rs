@@ -0,0 +1,204 @@
+// Tests for devices enrolled with keys of their own, and revoking them one by one
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use num_bigint::BigUint;
+use tonic::transport::Channel;
+use tonic::Code;
+
+use rust_zkp_chaum_pedersen::api::v1::{
+    auth_client::AuthClient, AuthenticationAnswerRequest, AuthenticationChallengeRequest, ElevateSessionRequest,
+    EnrollDeviceRequest, ListDevicesRequest, ListSessionsRequest, RegisterRequest, RevokeDeviceRequest,
+};
+use rust_zkp_chaum_pedersen::device_key::{self, DeviceKey};
+use rust_zkp_chaum_pedersen::group::GroupId;
+use rust_zkp_chaum_pedersen::store::{StoredDevice, StoredUser, UserStore};
+use rust_zkp_chaum_pedersen::types::Challenge;
+use rust_zkp_chaum_pedersen::ZKP;
+
+mod common;
+use common::{auth_client, temp_dir, Server};
+
+fn device(id: &str, name: &str) -> StoredDevice {
+    StoredDevice { id: id.to_string(), name: name.to_string(), y1: "0102".to_string(), y2: "0304".to_string(), enrolled_at: 1_700_000_000_000 }
+}
+
+#[test]
+fn test_devices_survive_reopening() {
+    println!("📲 Testing enrolled devices in the user store");
+
+    let dir = temp_dir("store");
+    let path = dir.join("users.jsonl");
+    let (store, _) = UserStore::open(&path).unwrap();
+    for user in ["alice", "bob"] {
+        let request = RegisterRequest { user: user.to_string(), y1: vec![1], y2: vec![2], ..Default::default() };
+        store.append(&StoredUser::from(&request)).unwrap();
+    }
+    store.add_device("alice", &device("aa", "laptop")).unwrap();
+    store.add_device("alice", &device("bb", "phone")).unwrap();
+    store.add_device("bob", &device("cc", "tablet")).unwrap();
+    store.revoke_device("alice", "aa").unwrap();
+    drop(store);
+
+    let (store, users) = UserStore::open(&path).unwrap();
+    assert_eq!(users[0].devices, vec![device("bb", "phone")]);
+    assert_eq!(users[0].devices[0].public_key().y1, BigUint::from(0x0102u32));
+    assert_eq!(users[1].devices, vec![device("cc", "tablet")]);
+
+    // rewriting the journal keeps the devices of everyone else
+    assert!(store.erase("bob").unwrap());
+    drop(store);
+    let (_, users) = UserStore::open(&path).unwrap();
+    assert_eq!(users.len(), 1);
+    assert_eq!(users[0].devices, vec![device("bb", "phone")]);
+
+    // a device of nobody is a broken journal
+    std::fs::write(&path, "{\"user\":\"carol\",\"device\":{\"id\":\"dd\",\"name\":\"x\",\"y1\":\"01\",\"y2\":\"02\",\"enrolled_at\":0}}\n").unwrap();
+    assert_eq!(UserStore::open(&path).unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+    println!("✅ Devices are read back after a restart, revoked ones aren't!");
+}
+
+#[test]
+fn test_device_key_file() {
+    println!("📲 Testing the device key file");
+
+    let dir = temp_dir("file");
+    let path = dir.join("laptop.json");
+    let zkp = ZKP::builder().rfc5114().build().unwrap();
+    let x = ZKP::generate_random_number_below(&zkp.q);
+    let id = device_key::device_id(GroupId::ModP, &zkp.public_key(&x));
+    assert_eq!(id.len(), 64);
+
+    let key = DeviceKey::new("alice", &id, &x);
+    key.save(&path).unwrap();
+    assert_eq!(DeviceKey::load(&path).unwrap(), key);
+    assert_eq!(DeviceKey::load(&path).unwrap().secret(), x);
+    assert_eq!(key.save(&path).unwrap_err().kind(), std::io::ErrorKind::AlreadyExists, "never replaced");
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        assert_eq!(std::fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+    }
+
+    std::fs::write(&path, "{\"user\":\"alice\",\"device_id\":\"aa\",\"x\":\"not hex\"}").unwrap();
+    assert_eq!(DeviceKey::load(&path).unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+    println!("✅ A device key is kept where only its owner reads it!");
+}
+
+fn start(port: u16, user_store: &Path) -> Server {
+    Server(
+        Command::new(env!("CARGO_BIN_EXE_server"))
+            .args(["--listen", &format!("127.0.0.1:{}", port)])
+            .args(["--user-store", user_store.to_str().unwrap()])
+            .stdout(Stdio::null())
+            .spawn()
+            .expect("Failed to start server process"),
+    )
+}
+
+/// a challenge for `user` answered with `x`, for the key of `device_id`; (auth_id, s)
+async fn answer(
+    client: &mut AuthClient<Channel>,
+    user: &str,
+    device_id: &str,
+    x: &BigUint,
+) -> Result<(String, Vec<u8>), tonic::Status> {
+    let zkp = ZKP::builder().rfc5114().build().unwrap();
+    let k = ZKP::generate_random_number_below(&zkp.q);
+    let (r1, r2) = zkp.commitment(&k).to_bytes_be();
+    let request = AuthenticationChallengeRequest {
+        user: user.to_string(),
+        r1,
+        r2,
+        device_id: device_id.to_string(),
+        ..Default::default()
+    };
+    let response = client.create_authentication_challenge(request).await?.into_inner();
+    let s = zkp.respond(&k, &Challenge::from_bytes_be(&response.c), x).to_bytes_be();
+    Ok((response.auth_id, s))
+}
+
+async fn login(client: &mut AuthClient<Channel>, user: &str, device_id: &str, x: &BigUint) -> Result<String, tonic::Status> {
+    let (auth_id, s) = answer(client, user, device_id, x).await?;
+    let request = AuthenticationAnswerRequest { auth_id, s, ..Default::default() };
+    Ok(client.verify_authentication(request).await?.into_inner().session_id)
+}
+
+#[tokio::test]
+async fn test_enroll_and_revoke() {
+    println!("📲 Testing a device enrolled with its own key");
+
+    let dir = temp_dir("e2e");
+    let path = dir.join("users.jsonl");
+    let server = start(50096, &path);
+    let mut client = auth_client(50096).await;
+
+    let zkp = ZKP::builder().rfc5114().build().unwrap();
+    let x = ZKP::generate_random_number_below(&zkp.q);
+    let (y1, y2) = zkp.public_key(&x).to_bytes_be();
+    client.register(RegisterRequest { user: "alice".to_string(), y1, y2, ..Default::default() }).await.unwrap();
+    let session_id = login(&mut client, "alice", "", &x).await.unwrap();
+
+    // a session alone can't enroll a key, it has to prove the password again
+    let x_laptop = ZKP::generate_random_number_below(&zkp.q);
+    let (y1, y2) = zkp.public_key(&x_laptop).to_bytes_be();
+    let enroll = EnrollDeviceRequest {
+        user: "alice".to_string(),
+        session_id: session_id.clone(),
+        name: "laptop".to_string(),
+        y1: y1.clone(),
+        y2: y2.clone(),
+    };
+    let status = client.enroll_device(enroll.clone()).await.unwrap_err();
+    assert_eq!(status.code(), Code::PermissionDenied);
+    let (auth_id, s) = answer(&mut client, "alice", "", &x).await.unwrap();
+    let request = ElevateSessionRequest { user: "alice".to_string(), session_id: session_id.clone(), auth_id, s, ..Default::default() };
+    client.elevate_session(request).await.unwrap();
+
+    let laptop = client.enroll_device(enroll.clone()).await.unwrap().into_inner().device_id;
+    assert_eq!(laptop, device_key::device_id(GroupId::ModP, &zkp.public_key(&x_laptop)));
+    assert_eq!(client.enroll_device(enroll).await.unwrap_err().code(), Code::AlreadyExists);
+    let x_phone = ZKP::generate_random_number_below(&zkp.q);
+    let (y1, y2) = zkp.public_key(&x_phone).to_bytes_be();
+    let request = EnrollDeviceRequest { user: "alice".to_string(), session_id: session_id.clone(), name: "phone".to_string(), y1, y2 };
+    let phone = client.enroll_device(request).await.unwrap().into_inner().device_id;
+
+    // the devices are kept across a restart
+    drop(server);
+    let _server = start(50096, &path);
+    let mut client = auth_client(50096).await;
+
+    // each device logs in with its own key, and only with it
+    assert_eq!(login(&mut client, "alice", &laptop, &x).await.unwrap_err().code(), Code::PermissionDenied);
+    let laptop_session = login(&mut client, "alice", &laptop, &x_laptop).await.unwrap();
+    let phone_session = login(&mut client, "alice", &phone, &x_phone).await.unwrap();
+    let session_id = login(&mut client, "alice", "", &x).await.unwrap();
+    let status = login(&mut client, "alice", "not enrolled", &x).await.unwrap_err();
+    assert_eq!(status.code(), Code::NotFound);
+
+    let list = ListDevicesRequest { user: "alice".to_string(), session_id: laptop_session.clone() };
+    let devices = client.list_devices(list).await.unwrap().into_inner().devices;
+    let names: Vec<_> = devices.iter().map(|device| (device.name.as_str(), device.current)).collect();
+    assert_eq!(names, [("laptop", true), ("phone", false)]);
+    let list = ListSessionsRequest { user: "alice".to_string(), session_id: session_id.clone() };
+    let sessions = client.list_sessions(list.clone()).await.unwrap().into_inner().sessions;
+    let laptop_sessions = sessions.iter().filter(|session| session.device_id == laptop).count();
+    assert_eq!(laptop_sessions, 1);
+
+    // losing the laptop ends its sessions, the password and the phone keep theirs
+    let revoke = RevokeDeviceRequest { user: "alice".to_string(), session_id: phone_session.clone(), device_id: laptop.clone() };
+    assert_eq!(client.revoke_device(revoke.clone()).await.unwrap().into_inner().sessions_ended, 1);
+    assert_eq!(client.revoke_device(revoke).await.unwrap_err().code(), Code::NotFound);
+    let sessions = client.list_sessions(list).await.unwrap().into_inner().sessions;
+    assert!(sessions.iter().all(|session| session.session_id != laptop_session));
+    assert!(sessions.iter().any(|session| session.session_id == phone_session));
+    assert_eq!(login(&mut client, "alice", &laptop, &x_laptop).await.unwrap_err().code(), Code::NotFound);
+    login(&mut client, "alice", &phone, &x_phone).await.unwrap();
+
+    std::fs::remove_dir_all(&dir).unwrap();
+    println!("✅ A lost device is revoked without touching the others!");
+}
@@ -0,0 +1,221 @@
+// Tests for QR-code logins: a device shows a QR code and a logged-in phone approves it
+use std::process::{Command, Stdio};
+use std::time::{Duration, SystemTime};
+
+use num_bigint::BigUint;
+use tonic::transport::Channel;
+use tonic::Code;
+
+use rust_zkp_chaum_pedersen::api::v1::{
+    auth_client::AuthClient, ApproveDeviceLoginRequest, AuthenticationAnswerRequest, AuthenticationChallengeRequest,
+    ClientMetadata, DeviceLoginState, FinishDeviceLoginRequest, ListSessionsRequest, PollDeviceLoginRequest,
+    RegisterRequest, StartDeviceLoginRequest, StartDeviceLoginResponse,
+};
+use rust_zkp_chaum_pedersen::challenge;
+use rust_zkp_chaum_pedersen::device_login::{self, DeviceLoginError, DeviceLogins, Poll};
+use rust_zkp_chaum_pedersen::types::{Challenge, Commitment};
+use rust_zkp_chaum_pedersen::ZKP;
+
+mod common;
+use common::{auth_client, Server};
+
+#[test]
+fn test_state_machine() {
+    println!("📱 Testing the device login states");
+
+    let logins = DeviceLogins::new(Duration::from_secs(60));
+    let now = SystemTime::now();
+    let commitment = || Commitment::from_bytes_be(&[1], &[2]);
+    logins.start("login".to_string(), "token".to_string(), None, "10.0.0.7:4000".to_string(), (7u32.into(), vec![0; 32]), now);
+
+    assert_eq!(logins.poll("login", "token", now), Ok(Poll::Waiting));
+    assert_eq!(logins.poll("login", "tokeN", now).unwrap_err(), DeviceLoginError::WrongToken);
+    assert_eq!(logins.answer("login", "alice", "phone", now).unwrap_err(), DeviceLoginError::NotScanned);
+
+    // the first phone to scan gets c, nobody else
+    let scan = logins.scan("login", "alice", "phone", commitment(), now).unwrap();
+    assert_eq!((scan.c, scan.source.as_str()), (7u32.into(), "10.0.0.7:4000"));
+    assert_eq!(logins.scan("login", "mallory", "other", commitment(), now).unwrap_err(), DeviceLoginError::AlreadyScanned);
+    assert_eq!(logins.poll("login", "token", now), Ok(Poll::Scanned));
+    assert_eq!(logins.answer("login", "alice", "other", now).unwrap_err(), DeviceLoginError::OtherPhone);
+    assert_eq!(logins.decline("login", "mallory", "phone", now).unwrap_err(), DeviceLoginError::OtherPhone);
+
+    let (answered, _) = logins.answer("login", "alice", "phone", now).unwrap();
+    assert_eq!(answered, commitment());
+    assert_eq!(logins.answer("login", "alice", "phone", now).unwrap_err(), DeviceLoginError::OtherPhone, "answered once");
+    assert_eq!(logins.poll("login", "token", now), Ok(Poll::Scanned));
+    logins.finish("login", Some("session".to_string()));
+
+    // the session is told once
+    let approved = Poll::Approved { user: "alice".to_string(), session_id: "session".to_string() };
+    assert_eq!(logins.poll("login", "token", now), Ok(approved));
+    assert_eq!(logins.poll("login", "token", now).unwrap_err(), DeviceLoginError::NotFound);
+
+    // declined, wrong and expired logins end without a session
+    for (login_id, outcome) in [("declined", "decline"), ("wrong", "wrong")] {
+        logins.start(login_id.to_string(), "token".to_string(), None, String::new(), (7u32.into(), vec![0; 32]), now);
+        logins.scan(login_id, "alice", "phone", commitment(), now).unwrap();
+        match outcome {
+            "decline" => logins.decline(login_id, "alice", "phone", now).unwrap(),
+            _ => {
+                logins.answer(login_id, "alice", "phone", now).unwrap();
+                logins.finish(login_id, None);
+            }
+        }
+        assert_eq!(logins.poll(login_id, "token", now), Ok(Poll::Denied), "{}", login_id);
+    }
+    logins.start("old".to_string(), "token".to_string(), None, String::new(), (7u32.into(), vec![0; 32]), now);
+    let later = now + Duration::from_secs(60);
+    assert_eq!(logins.scan("old", "alice", "phone", commitment(), later).unwrap_err(), DeviceLoginError::Expired);
+    assert_eq!(logins.poll("old", "token", later).unwrap_err(), DeviceLoginError::NotFound);
+    logins.start("stale".to_string(), "token".to_string(), None, String::new(), (7u32.into(), vec![0; 32]), now);
+    assert_eq!(logins.expire(later), 1);
+    assert!(logins.is_empty());
+
+    println!("✅ Every login ends once, with or without a session!");
+}
+
+#[test]
+fn test_qr_payload() {
+    println!("📱 Testing the QR code payload");
+
+    let payload = device_login::qr_payload("01J9Z3", &[0xab, 0xcd]);
+    assert_eq!(payload, "zkp-auth://device-login?id=01J9Z3&commitment=abcd");
+    assert_eq!(device_login::parse_qr_payload(&payload), Some(("01J9Z3".to_string(), vec![0xab, 0xcd])));
+    assert_eq!(device_login::parse_qr_payload(&format!(" {}\n", payload)), Some(("01J9Z3".to_string(), vec![0xab, 0xcd])));
+    for bad in [
+        "https://example.com/?id=01J9Z3&commitment=abcd",
+        "zkp-auth://device-login?id=01J9Z3",
+        "zkp-auth://device-login?id=&commitment=abcd",
+        "zkp-auth://device-login?id=01J9Z3&commitment=xyz",
+        "zkp-auth://device-login?id=01J9Z3&commitment=abcd&c=12",
+    ] {
+        assert_eq!(device_login::parse_qr_payload(bad), None, "{}", bad);
+    }
+
+    println!("✅ Only our QR codes are read!");
+}
+
+fn start(port: u16) -> Server {
+    Server(
+        Command::new(env!("CARGO_BIN_EXE_server"))
+            .args(["--listen", &format!("127.0.0.1:{}", port)])
+            .stdout(Stdio::null())
+            .spawn()
+            .expect("Failed to start server process"),
+    )
+}
+
+/// register `user` and log in once, as the phone; (x, session_id)
+async fn phone(client: &mut AuthClient<Channel>, user: &str) -> (BigUint, String) {
+    let zkp = ZKP::builder().rfc5114().build().unwrap();
+    let x = ZKP::generate_random_number_below(&zkp.q);
+    let (y1, y2) = zkp.public_key(&x).to_bytes_be();
+    client.register(RegisterRequest { user: user.to_string(), y1, y2, ..Default::default() }).await.unwrap();
+
+    let k = ZKP::generate_random_number_below(&zkp.q);
+    let (r1, r2) = zkp.commitment(&k).to_bytes_be();
+    let request = AuthenticationChallengeRequest { user: user.to_string(), r1, r2, ..Default::default() };
+    let response = client.create_authentication_challenge(request).await.unwrap().into_inner();
+    let s = zkp.respond(&k, &Challenge::from_bytes_be(&response.c), &x).to_bytes_be();
+    let request = AuthenticationAnswerRequest { auth_id: response.auth_id, s, ..Default::default() };
+    (x, client.verify_authentication(request).await.unwrap().into_inner().session_id)
+}
+
+/// scan `started` from the phone and answer with `x`, checking the opening of c
+async fn approve(
+    client: &mut AuthClient<Channel>,
+    started: &StartDeviceLoginResponse,
+    user: &str,
+    session_id: &str,
+    x: &BigUint,
+) -> Result<bool, tonic::Status> {
+    let zkp = ZKP::builder().rfc5114().build().unwrap();
+    let k = ZKP::generate_random_number_below(&zkp.q);
+    let (r1, r2) = zkp.commitment(&k).to_bytes_be();
+    let request = ApproveDeviceLoginRequest {
+        user: user.to_string(),
+        session_id: session_id.to_string(),
+        login_id: started.login_id.clone(),
+        r1,
+        r2,
+    };
+    let scanned = client.approve_device_login(request).await?.into_inner();
+    let c = BigUint::from_bytes_be(&scanned.c);
+    assert!(challenge::verify_opening(&started.commitment, &c, &scanned.nonce), "c is the one the QR code committed to");
+
+    let s = zkp.respond(&k, &Challenge(c), x).to_bytes_be();
+    let request = FinishDeviceLoginRequest {
+        user: user.to_string(),
+        session_id: session_id.to_string(),
+        login_id: started.login_id.clone(),
+        s,
+    };
+    Ok(client.finish_device_login(request).await?.into_inner().approved)
+}
+
+async fn poll(client: &mut AuthClient<Channel>, started: &StartDeviceLoginResponse) -> Result<(DeviceLoginState, String), tonic::Status> {
+    let request = PollDeviceLoginRequest { login_id: started.login_id.clone(), poll_token: started.poll_token.clone() };
+    let response = client.poll_device_login(request).await?.into_inner();
+    Ok((response.state(), response.session_id))
+}
+
+#[tokio::test]
+async fn test_device_login() {
+    println!("📱 Testing a QR-code login approved from a phone");
+
+    let _server = start(50095);
+    let mut client = auth_client(50095).await;
+    let (x, phone_session) = phone(&mut client, "alice").await;
+
+    let tv = ClientMetadata { device_id: "living-room-tv".to_string(), app_version: "1.0".to_string(), platform: "tvos".to_string() };
+    let started = client
+        .start_device_login(StartDeviceLoginRequest { client: Some(tv.clone()) })
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(poll(&mut client, &started).await.unwrap().0, DeviceLoginState::Waiting);
+
+    // only a logged-in phone can scan
+    let mut wrong = started.clone();
+    wrong.poll_token = "not the token".to_string();
+    assert_eq!(poll(&mut client, &wrong).await.unwrap_err().code(), Code::PermissionDenied);
+    let status = approve(&mut client, &started, "alice", "not a session", &x).await.unwrap_err();
+    assert_eq!(status.code(), Code::Unauthenticated);
+
+    assert!(approve(&mut client, &started, "alice", &phone_session, &x).await.unwrap());
+    let (state, session_id) = poll(&mut client, &started).await.unwrap();
+    assert_eq!(state, DeviceLoginState::Approved);
+    assert_eq!(poll(&mut client, &started).await.unwrap_err().code(), Code::NotFound, "handed over once");
+
+    // the session is the TV's, next to the phone's
+    let request = ListSessionsRequest { user: "alice".to_string(), session_id: session_id.clone() };
+    let sessions = client.list_sessions(request).await.unwrap().into_inner().sessions;
+    let tv_session = sessions.iter().find(|session| session.session_id == session_id).unwrap();
+    assert_eq!(tv_session.client.as_ref(), Some(&tv));
+    assert!(sessions.iter().any(|session| session.session_id == phone_session));
+
+    // a wrong answer denies the login, and a scanned code can't be scanned again
+    let started = client.start_device_login(StartDeviceLoginRequest::default()).await.unwrap().into_inner();
+    let wrong_x = &x + 1u32;
+    assert_eq!(approve(&mut client, &started, "alice", &phone_session, &wrong_x).await.unwrap_err().code(), Code::PermissionDenied);
+    assert_eq!(poll(&mut client, &started).await.unwrap(), (DeviceLoginState::Denied, String::new()));
+    let started = client.start_device_login(StartDeviceLoginRequest::default()).await.unwrap().into_inner();
+    let (_, bob_session) = phone(&mut client, "bob").await;
+    let request = ApproveDeviceLoginRequest {
+        user: "bob".to_string(),
+        session_id: bob_session,
+        login_id: started.login_id.clone(),
+        r1: vec![1],
+        r2: vec![1],
+    };
+    client.approve_device_login(request.clone()).await.unwrap();
+    assert_eq!(approve(&mut client, &started, "alice", &phone_session, &x).await.unwrap_err().code(), Code::FailedPrecondition);
+
+    // declining: an empty answer from the phone that scanned
+    let request = FinishDeviceLoginRequest { user: request.user, session_id: request.session_id, login_id: request.login_id, s: vec![] };
+    assert!(!client.finish_device_login(request).await.unwrap().into_inner().approved);
+    assert_eq!(poll(&mut client, &started).await.unwrap().0, DeviceLoginState::Denied);
+
+    println!("✅ The device got the session the phone proved for!");
+}
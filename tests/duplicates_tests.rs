@@ -0,0 +1,64 @@
+// Tests for spotting one proof submitted under several auth_ids
+use std::time::{Duration, SystemTime};
+
+use rust_zkp_chaum_pedersen::clock::{TestClock, Time};
+use rust_zkp_chaum_pedersen::duplicates::{DuplicateFilter, Seen};
+
+#[test]
+fn test_duplicate_proofs_are_spotted() {
+    println!("🔁 Testing the duplicate proof filter");
+
+    let filter = DuplicateFilter::default();
+    assert_eq!(filter.check("auth-a", b"r1", b"r2", b"s"), Seen::New);
+    // a retry of the same answer is fine
+    assert_eq!(filter.check("auth-a", b"r1", b"r2", b"s"), Seen::SameAuth);
+    // the same triple for another challenge is not
+    assert_eq!(filter.check("auth-b", b"r1", b"r2", b"s"), Seen::Duplicate);
+    // different answers and shifted boundaries are new
+    assert_eq!(filter.check("auth-c", b"r1", b"r2", b"s2"), Seen::New);
+    assert_eq!(filter.check("auth-d", b"r1r", b"2", b"s"), Seen::New);
+
+    println!("✅ Duplicates are spotted!");
+}
+
+#[test]
+fn test_duplicate_filter_forgets_after_two_windows() {
+    println!("⏳ Testing the duplicate filter rotates");
+
+    let clock = TestClock::new(SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000));
+    let filter = DuplicateFilter::new(Duration::from_secs(60)).with_time(Time::new(clock.clone()));
+    assert_eq!(filter.check("auth-a", b"r1", b"r2", b"s"), Seen::New);
+
+    // one rotation: still in the previous filter
+    clock.advance(Duration::from_secs(60));
+    assert_eq!(filter.check("auth-b", b"r1", b"r2", b"s"), Seen::Duplicate);
+
+    // two more rotations without seeing it: gone
+    clock.advance(Duration::from_secs(60));
+    assert_eq!(filter.check("auth-x", b"x", b"x", b"x"), Seen::New);
+    // (checking it again in between would put it back into the current filter)
+    clock.advance(Duration::from_secs(60));
+    assert_eq!(filter.check("auth-d", b"r1", b"r2", b"s"), Seen::New);
+
+    println!("✅ Old submissions are forgotten!");
+}
+
+#[test]
+fn test_challenge_tokens_are_used_up() {
+    println!("🎟️  Testing an answered challenge token is remembered");
+
+    let clock = TestClock::new(SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000));
+    let filter = DuplicateFilter::new(Duration::from_secs(60)).with_time(Time::new(clock.clone()));
+    assert!(filter.use_up("auth-a"));
+    assert!(!filter.use_up("auth-a"));
+    assert!(filter.use_up("auth-b"));
+    // an answer and a use of the same auth_id don't mix
+    assert_eq!(filter.check("auth-c", b"r1", b"r2", b"s"), Seen::New);
+    assert!(filter.use_up("auth-c"));
+
+    // still used up a window later
+    clock.advance(Duration::from_secs(60));
+    assert!(!filter.use_up("auth-a"));
+
+    println!("✅ A token logs in once!");
+}
@@ -0,0 +1,207 @@
+// Tests for federation: ID tokens and proofs issued for a peer server, checked locally
+use std::process::{Command, Stdio};
+use std::time::{Duration, SystemTime};
+
+use ed25519_dalek::SigningKey;
+use tonic::transport::Channel;
+use tonic::Code;
+
+use rust_zkp_chaum_pedersen::api::v1::{
+    auth_client::AuthClient, AuthenticationAnswerRequest, AuthenticationChallengeRequest, FederationInfoRequest,
+    IdTokenRequest, RegisterRequest, RevokeProofRequest, VerifyFederatedProofRequest, VerifyFederatedProofResponse,
+    VerifyFederatedTokenRequest, VerifyFederatedTokenResponse,
+};
+use rust_zkp_chaum_pedersen::federation::{self, FederationError, PeerSpec};
+use rust_zkp_chaum_pedersen::id_token::{self, IdTokenIssuer};
+use rust_zkp_chaum_pedersen::nizk::{NonInteractiveProof, ValidityWindow};
+use rust_zkp_chaum_pedersen::params::{self, ParametersError, SignedParameters};
+use rust_zkp_chaum_pedersen::types::Challenge;
+use rust_zkp_chaum_pedersen::ZKP;
+
+mod common;
+use common::{auth_client, Server};
+
+const ISSUER: &str = "https://eu.auth.example";
+
+#[test]
+fn test_federation_info() {
+    println!("🌐 Testing the info servers publish to their peers");
+
+    let zkp = ZKP::builder().rfc5114().build().unwrap();
+    let params_key = SigningKey::from_bytes(&[7; 32]);
+    let id_token_key = SigningKey::from_bytes(&[8; 32]).verifying_key();
+    let parameters = SignedParameters::sign(&zkp, &params_key);
+    let info = federation::sign_info(&params_key, ISSUER, &parameters, Some(&id_token_key));
+
+    let (issuer, group, key) = federation::check_info(&info, &params_key.verifying_key()).unwrap();
+    assert_eq!((issuer.as_str(), group, key), (ISSUER, zkp, Some(id_token_key)));
+
+    // only the pinned key vouches for a peer, and for all of what it publishes
+    let other = SigningKey::from_bytes(&[9; 32]);
+    let error = federation::check_info(&info, &other.verifying_key()).unwrap_err();
+    assert_eq!(error, FederationError::Parameters(ParametersError::UntrustedKey));
+    let mut changed = info.clone();
+    changed.issuer = "https://us.auth.example".to_string();
+    assert_eq!(federation::check_info(&changed, &params_key.verifying_key()).unwrap_err(), FederationError::BadSignature);
+    let mut changed = info.clone();
+    changed.id_token_public_key = other.verifying_key().to_bytes().to_vec();
+    assert_eq!(federation::check_info(&changed, &params_key.verifying_key()).unwrap_err(), FederationError::BadSignature);
+
+    // a proof for one peer is no proof for another
+    assert_ne!(federation::proof_context(ISSUER, "alice", b"pay"), federation::proof_context("https://us.auth.example", "alice", b"pay"));
+    assert_ne!(federation::proof_context(ISSUER, "alice", b"pay"), federation::proof_context(ISSUER, "bob", b"pay"));
+    assert_ne!(federation::proof_context("a", "b", b"c"), federation::proof_context("ab", "", b"c"));
+
+    let key_hex = hex::encode(params_key.verifying_key().as_bytes());
+    let spec: PeerSpec = format!("https://eu.auth.example:443#{}", key_hex).parse().unwrap();
+    assert_eq!((spec.endpoint.as_str(), spec.params_key), ("https://eu.auth.example:443", params_key.verifying_key()));
+    assert!("https://eu.auth.example".parse::<PeerSpec>().is_err(), "no key");
+    assert!(format!("eu.auth.example#{}", key_hex).parse::<PeerSpec>().is_err(), "no scheme");
+    assert!("https://eu.auth.example#beef".parse::<PeerSpec>().is_err(), "not a key");
+
+    println!("✅ Peers only take what the pinned key signed!");
+}
+
+fn start(port: u16, args: &[&str]) -> Server {
+    Server(
+        Command::new(env!("CARGO_BIN_EXE_server"))
+            .args(["--listen", &format!("127.0.0.1:{}", port)])
+            .args(args)
+            .stdout(Stdio::null())
+            .spawn()
+            .expect("Failed to start server process"),
+    )
+}
+
+async fn verify_token(client: &mut AuthClient<Channel>, token: &str, audience: &str) -> VerifyFederatedTokenResponse {
+    let request = VerifyFederatedTokenRequest { token: token.to_string(), audience: audience.to_string() };
+    client.verify_federated_token(request).await.unwrap().into_inner()
+}
+
+async fn verify_proof(
+    client: &mut AuthClient<Channel>,
+    issuer: &str,
+    user: &str,
+    zkp: &ZKP,
+    x: &num_bigint::BigUint,
+    proof: &NonInteractiveProof,
+) -> VerifyFederatedProofResponse {
+    let (y1, y2) = zkp.public_key(x).to_bytes_be();
+    let request = VerifyFederatedProofRequest {
+        issuer: issuer.to_string(),
+        user: user.to_string(),
+        y1,
+        y2,
+        proof: serde_json::to_string(proof).unwrap(),
+        context: b"transfer".to_vec(),
+    };
+    client.verify_federated_proof(request).await.unwrap().into_inner()
+}
+
+#[tokio::test]
+async fn test_verify_for_peer() {
+    println!("🌐 Testing tokens and proofs of a peer checked by another region");
+
+    let dir = std::env::temp_dir().join(format!("zkp-federation-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let key_path = dir.join("params.key");
+    let params_key = params::load_or_create_signing_key(&key_path).unwrap();
+    let peer = format!("http://127.0.0.1:50098#{}", hex::encode(params_key.verifying_key().as_bytes()));
+
+    // eu mints, us only checks
+    let _eu = start(50098, &["--params-key", key_path.to_str().unwrap(), "--id-token-issuer", ISSUER, "--id-token-audience", "app"]);
+    let _us = start(50099, &["--federation-peer", &peer, "--federation-refresh", "1"]);
+    let mut eu = auth_client(50098).await;
+    let mut us = auth_client(50099).await;
+
+    let zkp = ZKP::builder().rfc5114().build().unwrap();
+    let x = ZKP::generate_random_number_below(&zkp.q);
+    let (y1, y2) = zkp.public_key(&x).to_bytes_be();
+    eu.register(RegisterRequest { user: "alice".to_string(), y1, y2, ..Default::default() }).await.unwrap();
+    let k = ZKP::generate_random_number_below(&zkp.q);
+    let (r1, r2) = zkp.commitment(&k).to_bytes_be();
+    let id_token = Some(IdTokenRequest { audience: "app".to_string(), nonce: String::new() });
+    let request = AuthenticationChallengeRequest { user: "alice".to_string(), r1, r2, ..Default::default() };
+    let challenge = eu.create_authentication_challenge(request).await.unwrap().into_inner();
+    let s = zkp.respond(&k, &Challenge::from_bytes_be(&challenge.c), &x).to_bytes_be();
+    let request = AuthenticationAnswerRequest { auth_id: challenge.auth_id, s, id_token, ..Default::default() };
+    let login = eu.verify_authentication(request).await.unwrap().into_inner();
+
+    // us fetches eu in the background; wait for the first copy
+    let mut response = verify_token(&mut us, &login.id_token, "app").await;
+    for _ in 0..50 {
+        if response.valid {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        response = verify_token(&mut us, &login.id_token, "app").await;
+    }
+    assert!(response.valid, "{}", response.reason);
+    assert_eq!((response.issuer.as_str(), response.subject.as_str()), (ISSUER, "alice"));
+    assert_eq!(response.session_id, login.session_id);
+
+    // not valid is an answer, with the reason
+    let response = verify_token(&mut us, &login.id_token, "another app").await;
+    assert!(!response.valid && response.subject.is_empty());
+    assert!(response.reason.contains("audience"), "{}", response.reason);
+    let forger = IdTokenIssuer::new(SigningKey::from_bytes(&[3; 32]), ISSUER, vec!["app".to_string()], id_token::DEFAULT_LIFETIME);
+    let forged = forger.mint("alice", "app", "", "", SystemTime::now());
+    assert!(!verify_token(&mut us, &forged, "app").await.valid, "signed by someone else");
+    let stranger = IdTokenIssuer::new(SigningKey::from_bytes(&[3; 32]), "https://evil.example", vec!["app".to_string()], id_token::DEFAULT_LIFETIME);
+    let response = verify_token(&mut us, &stranger.mint("alice", "app", "", "", SystemTime::now()), "app").await;
+    assert!(response.reason.contains("not a federation peer"), "{}", response.reason);
+    let request = VerifyFederatedTokenRequest { token: "garbage".to_string(), audience: "app".to_string() };
+    assert_eq!(us.verify_federated_token(request).await.unwrap_err().code(), Code::InvalidArgument);
+
+    // proofs made for eu check out in us, and only for eu
+    let window = ValidityWindow::starting_now(Duration::from_secs(600));
+    let proof = zkp.prove_non_interactive(&x, window, &federation::proof_context(ISSUER, "alice", b"transfer"));
+    let response = verify_proof(&mut us, ISSUER, "alice", &zkp, &x, &proof).await;
+    assert!(response.valid, "{}", response.reason);
+    assert_eq!(response.proof_id, proof.id);
+    assert!(!verify_proof(&mut us, ISSUER, "alice", &zkp, &(x.clone() + 1u32), &proof).await.valid, "another key");
+    assert!(!verify_proof(&mut us, ISSUER, "mallory", &zkp, &x, &proof).await.valid, "claimed for another user");
+    let elsewhere = zkp.prove_non_interactive(&x, window, &federation::proof_context("https://us.auth.example", "alice", b"transfer"));
+    assert!(!verify_proof(&mut us, ISSUER, "alice", &zkp, &x, &elsewhere).await.valid, "made for another server");
+    // nor a "proof" about the zero key: 0^c = 0, so it needs no witness
+    let forged = NonInteractiveProof { id: "nobodyknowsx0000".to_string(), r1: vec![0], r2: vec![0], s: vec![1], window };
+    let request = VerifyFederatedProofRequest {
+        issuer: ISSUER.to_string(),
+        user: "alice".to_string(),
+        y1: vec![0],
+        y2: vec![0],
+        proof: serde_json::to_string(&forged).unwrap(),
+        context: b"transfer".to_vec(),
+    };
+    assert!(!us.verify_federated_proof(request).await.unwrap().into_inner().valid, "a key outside the group");
+    assert!(!verify_proof(&mut us, "https://evil.example", "alice", &zkp, &x, &proof).await.valid);
+
+    // a proof eu revokes stops working in us with the next fetch
+    let request = RevokeProofRequest {
+        user: "alice".to_string(),
+        session_id: login.session_id.clone(),
+        proof_id: proof.id.clone(),
+        reason: "the laptop was stolen".to_string(),
+    };
+    eu.revoke_proof(request).await.unwrap();
+    let mut response = verify_proof(&mut us, ISSUER, "alice", &zkp, &x, &proof).await;
+    for _ in 0..50 {
+        if !response.valid {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        response = verify_proof(&mut us, ISSUER, "alice", &zkp, &x, &proof).await;
+    }
+    assert!(response.reason.contains("revoked"), "{}", response.reason);
+
+    // only a server with a parameter key and an issuer is a peer, only one with peers checks for them
+    assert_eq!(us.get_federation_info(FederationInfoRequest {}).await.unwrap_err().code(), Code::Unimplemented);
+    let info = eu.get_federation_info(FederationInfoRequest {}).await.unwrap().into_inner();
+    assert_eq!(federation::check_info(&info, &params_key.verifying_key()).unwrap().0, ISSUER);
+    let request = VerifyFederatedTokenRequest { token: login.id_token.clone(), audience: "app".to_string() };
+    assert_eq!(eu.verify_federated_token(request).await.unwrap_err().code(), Code::Unimplemented);
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    println!("✅ One region checks what another issued without asking it!");
+}
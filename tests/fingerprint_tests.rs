@@ -0,0 +1,48 @@
+// Tests for public key fingerprints and their readable forms
+use rust_zkp_chaum_pedersen::fingerprint::Fingerprint;
+use rust_zkp_chaum_pedersen::group::GroupId;
+use rust_zkp_chaum_pedersen::types::PublicKey;
+use rust_zkp_chaum_pedersen::user_id;
+use rust_zkp_chaum_pedersen::ZKP;
+
+#[test]
+fn test_encodings() {
+    println!("🔑 Testing the fingerprint encodings");
+
+    // the first seven 6-bit groups are 0, 1, ..., 6
+    let mut digest = [0u8; 32];
+    digest[..6].copy_from_slice(&[0x00, 0x10, 0x83, 0x10, 0x51, 0x80]);
+    let fingerprint = Fingerprint(digest);
+    assert_eq!(fingerprint.to_string(), "00:10:83:10:51:80:00:00");
+    assert_eq!(fingerprint.to_emoji(), "🐶 🐱 🦁 🐎 🦄 🐷 🐘");
+    assert_eq!(fingerprint.to_words(), "dog cat lion horse unicorn pig elephant");
+    assert_eq!(fingerprint.to_hex(), format!("001083105180{}", "0".repeat(52)));
+
+    let fingerprint = Fingerprint([0xff; 32]);
+    assert_eq!(fingerprint.to_words(), "pin pin pin pin pin pin pin");
+    assert_eq!(fingerprint.to_hex(), "f".repeat(64));
+
+    println!("✅ Fingerprints read the same everywhere!");
+}
+
+#[test]
+fn test_public_key_fingerprint() {
+    println!("🔑 Testing PublicKey::fingerprint");
+
+    let zkp = ZKP::builder().rfc5114().build().unwrap();
+    let key = zkp.public_key(&42u32.into());
+    let fingerprint = key.fingerprint();
+    assert_eq!(fingerprint, zkp.public_key(&42u32.into()).fingerprint());
+    assert_ne!(fingerprint, zkp.public_key(&43u32.into()).fingerprint());
+    assert_ne!(fingerprint, key.fingerprint_in(GroupId::P256), "the group counts");
+    assert_ne!(fingerprint, PublicKey { y1: key.y2.clone(), y2: key.y1.clone() }.fingerprint());
+
+    // what the wire carries, leading zeros or not, and the account ids built on it
+    let (y1, y2) = key.to_bytes_be();
+    let padded = [vec![0; 3], y1.clone()].concat();
+    assert_eq!(PublicKey::from_bytes_be(&padded, &y2).fingerprint(), fingerprint);
+    assert_eq!(user_id::fingerprint(GroupId::ModP, &y1, &y2), fingerprint.to_hex());
+    assert_eq!(fingerprint.to_string().len(), 23, "short enough for a log line");
+
+    println!("✅ One key, one fingerprint!");
+}
@@ -0,0 +1,70 @@
+// Tests for the statically sized parameter sets
+use num_bigint::BigUint;
+use rust_zkp_chaum_pedersen::fixed::{DecodeError, ZKP1024, ZKP2048};
+use rust_zkp_chaum_pedersen::ZKP;
+
+#[test]
+fn test_fixed_size_encodings() {
+    println!("📏 Testing fixed-size encodings");
+
+    let zkp = ZKP1024::rfc5114();
+    assert_eq!(*zkp, ZKP::builder().rfc5114().build().unwrap());
+
+    // a small value still takes all 128 bytes
+    let one = BigUint::from(1u32);
+    let encoded: [u8; 128] = zkp.encode_element(&one);
+    assert_eq!(encoded[127], 1);
+    assert_eq!(zkp.decode_element(&encoded), Ok(one));
+
+    // the stripped form is refused
+    assert_eq!(
+        zkp.decode_element(&[1u8]),
+        Err(DecodeError::Length { expected: 128, found: 1 })
+    );
+    // a scalar >= q is refused
+    assert_eq!(zkp.decode_scalar(&[0xff; 20]), Err(DecodeError::OutOfRange));
+
+    println!("✅ Fixed-size encodings work!");
+}
+
+#[test]
+fn test_zkp2048_round_trip() {
+    println!("🔐 Testing a full login in the 2048-bit group");
+
+    let zkp = ZKP2048::rfc5114();
+    assert_eq!(zkp.p.bits(), 2048);
+    assert_eq!(zkp.q.bits(), 256);
+
+    let x = ZKP::generate_random_number_below(&zkp.q);
+    let k = ZKP::generate_random_number_below(&zkp.q);
+    let (y1, y2) = zkp.encode_public_key(&zkp.public_key(&x));
+    let (r1, r2) = zkp.encode_commitment(&zkp.commitment(&k));
+    let c: [u8; 32] = zkp.encode_challenge(&zkp.random_challenge());
+
+    // the verifier side decodes everything from fixed-size arrays
+    let key = zkp.decode_public_key(&y1, &y2).unwrap();
+    let commitment = zkp.decode_commitment(&r1, &r2).unwrap();
+    let c = zkp.decode_challenge(&c).unwrap();
+    let s = zkp.decode_response(&zkp.encode_response(&zkp.respond(&k, &c, &x))).unwrap();
+    assert!(zkp.verify_response(&key, &commitment, &c, &s));
+
+    // the 1024-bit group doesn't fit the 2048-bit type
+    assert!(ZKP2048::new(ZKP::builder().rfc5114().build().unwrap()).is_err());
+
+    println!("✅ 2048-bit group works!");
+}
+
+#[test]
+fn test_constants_are_decoded_once() {
+    println!("🔥 Testing the warmed-up RFC 5114 constants");
+
+    ZKP::warm_up();
+    let (alpha, beta, p, q) = ZKP::get_constants();
+    assert_eq!((p.bits(), q.bits()), (1024, 160));
+    assert_eq!(beta, alpha.modpow(&BigUint::parse_bytes(b"266FEA1E5C41564B777E69", 16).unwrap(), &p));
+    assert_eq!(ZKP::get_constants(), (alpha, beta, p, q), "every call sees the same values");
+    assert_eq!(ZKP2048::rfc5114(), ZKP2048::rfc5114());
+    assert!(ZKP::warm_up() < std::time::Duration::from_secs(1), "nothing left to do the second time");
+
+    println!("✅ Constants are decoded once!");
+}
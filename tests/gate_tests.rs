@@ -0,0 +1,161 @@
+// Tests for the gates challenges have to pass, and the CAPTCHA check (cargo test --features captcha)
+use rust_zkp_chaum_pedersen::gate::{ChallengeContext, Gate, GateError, GateFuture, OpenGate, PreChallengeGate};
+
+/// turns away sources on a list, and can't decide about the rest of 10.0.0.0/8
+#[derive(Debug)]
+struct Blocklist(Vec<&'static str>);
+
+impl PreChallengeGate for Blocklist {
+    fn check<'a>(&'a self, request: &'a ChallengeContext) -> GateFuture<'a> {
+        Box::pin(async move {
+            let ip = request.source.split(':').next().unwrap_or_default();
+            if self.0.contains(&ip) {
+                return Err(GateError::Refused(format!("{} is blocked", ip)));
+            }
+            match ip.starts_with("10.") {
+                true => Err(GateError::Unavailable("the blocklist is reloading".to_string())),
+                false => Ok(()),
+            }
+        })
+    }
+}
+
+fn context(source: &str) -> ChallengeContext {
+    ChallengeContext { user: "alice".to_string(), source: source.to_string(), token: String::new() }
+}
+
+#[tokio::test]
+async fn test_gates() {
+    println!("🚧 Testing gates in front of challenges");
+
+    assert_eq!(Gate::default().check(&context("203.0.113.9:50000")).await, Ok(()), "open by default");
+    assert_eq!(Gate::new(OpenGate).check(&context("")).await, Ok(()));
+
+    let gate = Gate::new(Blocklist(vec!["203.0.113.9"]));
+    assert_eq!(gate.check(&context("198.51.100.1:40000")).await, Ok(()));
+    let refused = gate.check(&context("203.0.113.9:50000")).await.unwrap_err();
+    assert_eq!(refused, GateError::Refused("203.0.113.9 is blocked".to_string()));
+    assert_eq!(refused.to_string(), "refused: 203.0.113.9 is blocked");
+    assert!(matches!(gate.clone().check(&context("10.0.0.7:1")).await, Err(GateError::Unavailable(_))));
+
+    println!("✅ Gates decide who gets a challenge!");
+}
+
+#[cfg(feature = "captcha")]
+#[tokio::test]
+async fn test_captcha_gate() {
+    use std::collections::HashMap;
+    use std::process::{Command, Stdio};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use hyper::service::{make_service_fn, service_fn};
+    use hyper::{Body, Request, Response};
+    use tonic::{transport::Channel, Code};
+
+    use rust_zkp_chaum_pedersen::api::v1::{
+        auth_client::AuthClient, AuthenticationChallengeRequest, ChallengeCommitmentRequest, RegisterRequest,
+    };
+    use rust_zkp_chaum_pedersen::captcha::form_encode;
+    use rust_zkp_chaum_pedersen::gate::TOKEN_HEADER;
+    use rust_zkp_chaum_pedersen::ZKP;
+
+    println!("🚧 Testing a server that wants a CAPTCHA before every challenge");
+
+    assert_eq!(form_encode("0.AAAA-b_c~d"), "0.AAAA-b_c~d");
+    assert_eq!(form_encode("a b&c=d/é"), "a%20b%26c%3Dd%2F%C3%A9");
+
+    // a siteverify endpoint: "good" was solved on login.example.com, "elsewhere" on another site
+    let seen = Arc::new(Mutex::new(Vec::<HashMap<String, String>>::new()));
+    let kept = seen.clone();
+    let make_service = make_service_fn(move |_| {
+        let seen = kept.clone();
+        async move {
+            Ok::<_, std::convert::Infallible>(service_fn(move |request: Request<Body>| {
+                let seen = seen.clone();
+                async move {
+                    let body = hyper::body::to_bytes(request.into_body()).await.unwrap();
+                    let form: HashMap<String, String> = String::from_utf8(body.to_vec())
+                        .unwrap()
+                        .split('&')
+                        .filter_map(|pair| pair.split_once('='))
+                        .map(|(name, value)| (name.to_string(), value.to_string()))
+                        .collect();
+                    let answer = match (form["secret"].as_str(), form["response"].as_str()) {
+                        ("s3cret", "good") => r#"{"success":true,"hostname":"login.example.com"}"#,
+                        ("s3cret", "elsewhere") => r#"{"success":true,"hostname":"evil.example.net"}"#,
+                        _ => r#"{"success":false,"error-codes":["invalid-input-response"]}"#,
+                    };
+                    seen.lock().unwrap().push(form);
+                    Ok::<_, std::convert::Infallible>(Response::new(Body::from(answer)))
+                }
+            }))
+        }
+    });
+    let siteverify = hyper::Server::bind(&"127.0.0.1:0".parse().unwrap()).serve(make_service);
+    let url = format!("http://{}/siteverify", siteverify.local_addr());
+    tokio::spawn(siteverify);
+
+    let dir = std::env::temp_dir().join(format!("zkp-gate-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let secret = dir.join("captcha-secret");
+    std::fs::write(&secret, "s3cret\n").unwrap();
+    let mut server = Command::new(env!("CARGO_BIN_EXE_server"))
+        .args(["--listen", "127.0.0.1:50106", "--captcha-verify-url", &url, "--captcha-hostname", "login.example.com"])
+        .arg("--captcha-secret-file")
+        .arg(&secret)
+        .stdout(Stdio::null())
+        .spawn()
+        .expect("Failed to start server process");
+    let mut client = None;
+    for _ in 0..50 {
+        if let Ok(channel) = Channel::from_static("http://127.0.0.1:50106").connect().await {
+            client = Some(AuthClient::new(channel));
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    let mut client = client.expect("the test server didn't come up");
+    let zkp = ZKP::builder().rfc5114().build().unwrap();
+    let (y1, y2) = zkp.public_key(&ZKP::generate_random_number_below(&zkp.q)).to_bytes_be();
+    client.register(RegisterRequest { user: "gate_alice".to_string(), y1, y2, ..Default::default() }).await.unwrap();
+
+    let challenge = |token: &str| {
+        let mut request = tonic::Request::new(AuthenticationChallengeRequest {
+            user: "gate_alice".to_string(),
+            r1: vec![5],
+            r2: vec![7],
+            ..Default::default()
+        });
+        if !token.is_empty() {
+            request.metadata_mut().insert(TOKEN_HEADER, token.parse().unwrap());
+        }
+        request
+    };
+    let no_token = client.create_authentication_challenge(challenge("")).await.unwrap_err();
+    let bad_token = client.create_authentication_challenge(challenge("bot")).await.unwrap_err();
+    let elsewhere = client.create_authentication_challenge(challenge("elsewhere")).await.unwrap_err();
+    let good = client.create_authentication_challenge(challenge("good")).await;
+    let mut commit = tonic::Request::new(ChallengeCommitmentRequest { user: "gate_alice".to_string() });
+    commit.metadata_mut().insert(TOKEN_HEADER, "bot".parse().unwrap());
+    let committed = client.commit_challenge(commit).await;
+    let _ = server.kill();
+    let _ = server.wait();
+
+    assert_eq!(no_token.code(), Code::PermissionDenied);
+    assert!(no_token.message().contains(TOKEN_HEADER), "{}", no_token.message());
+    assert_eq!(bad_token.code(), Code::PermissionDenied);
+    assert!(bad_token.message().contains("invalid-input-response"), "{}", bad_token.message());
+    assert_eq!(elsewhere.code(), Code::PermissionDenied);
+    assert!(!good.expect("a solved CAPTCHA gets a challenge").into_inner().auth_id.is_empty());
+    assert_eq!(committed.unwrap_err().code(), Code::PermissionDenied, "committed challenges are gated too");
+
+    // the provider saw the secret, the token and the client's IP; no token, no call
+    let seen = seen.lock().unwrap();
+    assert_eq!(seen.len(), 4);
+    assert_eq!((seen[0]["secret"].as_str(), seen[0]["response"].as_str()), ("s3cret", "bot"));
+    assert_eq!(seen[0]["remoteip"], "127.0.0.1");
+
+    std::fs::remove_dir_all(&dir).unwrap();
+    println!("✅ Bots without a solved CAPTCHA get no challenge!");
+}
@@ -0,0 +1,259 @@
+// Tests for group and hash negotiation, and logins in every group the server binary
+// was built with (cargo test --features p256 for the P-256 half)
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use tonic::Code;
+
+use rust_zkp_chaum_pedersen::api::v1::{
+    auth_client::AuthClient, AuthenticationAnswerRequest, AuthenticationChallengeRequest, RegisterRequest, ServerInfoRequest,
+    UserKdfParamsRequest,
+};
+use rust_zkp_chaum_pedersen::group::{self, GroupId, HashId};
+use rust_zkp_chaum_pedersen::types::Challenge;
+use rust_zkp_chaum_pedersen::ZKP;
+
+mod common;
+use common::{auth_client, temp_dir, Server};
+
+fn start_server(port: u16, store: &Path) -> Server {
+    let child = Command::new(env!("CARGO_BIN_EXE_server"))
+        .arg("--listen")
+        .arg(format!("127.0.0.1:{}", port))
+        .arg("--user-store")
+        .arg(store)
+        .stdout(Stdio::null())
+        .spawn()
+        .expect("Failed to start server process");
+    Server(child)
+}
+
+/// one challenge/answer round with a commitment, and how to answer its challenge
+async fn login(
+    client: &mut AuthClient<tonic::transport::Channel>,
+    user: &str,
+    (r1, r2): (Vec<u8>, Vec<u8>),
+    answer: impl FnOnce(&[u8]) -> Vec<u8>,
+) -> Result<String, tonic::Status> {
+    let challenge = client
+        .create_authentication_challenge(AuthenticationChallengeRequest { user: user.to_string(), r1, r2, ..Default::default() })
+        .await?
+        .into_inner();
+    let s = answer(&challenge.c);
+    let answer = AuthenticationAnswerRequest { auth_id: challenge.auth_id, s, ..Default::default() };
+    Ok(client.verify_authentication(answer).await?.into_inner().session_id)
+}
+
+#[test]
+fn test_group_names() {
+    println!("🔧 Testing group names and negotiation");
+
+    for group in GroupId::ALL {
+        assert_eq!(GroupId::from_name(group.name()), Some(group));
+        assert_eq!(group.to_string(), group.name());
+    }
+    assert_eq!(GroupId::from_name(""), Some(GroupId::ModP), "clients from before groups");
+    assert_eq!(GroupId::from_name("P-256"), None);
+    assert_eq!(GroupId::supported()[0], GroupId::ModP);
+    assert_eq!(GroupId::supported().contains(&GroupId::P256), cfg!(feature = "p256"));
+
+    let offers = |names: &[&str]| names.iter().map(|name| name.to_string()).collect::<Vec<_>>();
+    let preferred = [GroupId::P256, GroupId::ModP];
+    assert_eq!(group::negotiate(&preferred, &offers(&["modp", "p256"])), Some(GroupId::P256));
+    assert_eq!(group::negotiate(&preferred, &offers(&["modp"])), Some(GroupId::ModP));
+    assert_eq!(group::negotiate(&preferred, &[]), Some(GroupId::ModP), "an old server only speaks modp");
+    assert_eq!(group::negotiate(&[GroupId::P256], &[]), None);
+    assert_eq!(group::negotiate(&[GroupId::P256], &offers(&["modp", "x25519"])), None);
+
+    // modp keys are in the subgroup of GetParameters, not any bytes
+    let zkp = ZKP::builder().rfc5114().build().unwrap();
+    let (y1, y2) = zkp.public_key(&ZKP::generate_random_number_below(&zkp.q)).to_bytes_be();
+    assert!(GroupId::ModP.accepts_public_key(&y1, &y2));
+    let too_big = (&zkp.p + 2u32).to_bytes_be();
+    let order_two = (&zkp.p - 1u32).to_bytes_be();
+    for bad in [&[0][..], &[1], &too_big, &order_two] {
+        assert!(!GroupId::ModP.accepts_public_key(bad, &y2), "{:?}", bad);
+        assert!(!GroupId::ModP.accepts_public_key(&y1, bad), "{:?}", bad);
+    }
+
+    println!("✅ Groups parse and negotiate!");
+}
+
+#[test]
+fn test_hash_names() {
+    println!("🔧 Testing hash names and per-user generators for each hash");
+
+    for hash in HashId::ALL {
+        assert_eq!(HashId::from_name(hash.name()), Some(hash));
+    }
+    assert_eq!(HashId::from_name(""), Some(HashId::Sha256), "clients from before hashes");
+    assert_eq!(HashId::from_name("md5"), None);
+
+    // sha256 is what for_user always used, sha512 gives another generator
+    let zkp = ZKP::builder().rfc5114().build().unwrap();
+    assert_eq!(zkp.for_user_with(HashId::Sha256, "alice"), zkp.for_user("alice"));
+    let sha512 = zkp.for_user_with(HashId::Sha512, "alice");
+    assert_ne!(sha512.beta, zkp.for_user("alice").beta);
+    assert_eq!(sha512.beta.modpow(&zkp.q, &zkp.p), 1u32.into(), "still in the subgroup");
+
+    println!("✅ Hashes parse and give their own generators!");
+}
+
+#[tokio::test]
+async fn test_logins_in_every_group() {
+    println!("🔧 Testing registrations and logins across groups");
+
+    let dir = temp_dir("logins");
+    let store = dir.join("users.jsonl");
+    let port = 50080;
+    let server = start_server(port, &store);
+    let mut client = auth_client(port).await;
+
+    let offers = client.get_server_info(ServerInfoRequest {}).await.unwrap().into_inner().groups;
+    let expected: Vec<String> = GroupId::supported().iter().map(|group| group.name().to_string()).collect();
+    assert_eq!(offers, expected);
+
+    // a modp user, as every client registered before groups
+    let zkp = ZKP::builder().rfc5114().build().unwrap();
+    let x = ZKP::generate_random_number_below(&zkp.q);
+    let (y1, y2) = zkp.public_key(&x).to_bytes_be();
+    client
+        .register(RegisterRequest { user: "group_modp".to_string(), y1, y2, ..Default::default() })
+        .await
+        .unwrap();
+    let k = ZKP::generate_random_number_below(&zkp.q);
+    let session = login(&mut client, "group_modp", zkp.commitment(&k).to_bytes_be(), |c| {
+        zkp.respond(&k, &Challenge::from_bytes_be(c), &x).to_bytes_be()
+    })
+    .await
+    .unwrap();
+    assert!(!session.is_empty());
+    let params = client.get_user_kdf_params(UserKdfParamsRequest { user: "group_modp".to_string() }).await.unwrap().into_inner();
+    assert_eq!(params.group, "");
+
+    // groups the server doesn't know, or wasn't built with, are refused up front
+    let unknown = RegisterRequest { user: "group_unknown".to_string(), y1: vec![2; 33], y2: vec![2; 33], group: "x25519".to_string(), ..Default::default() };
+    assert_eq!(client.register(unknown).await.unwrap_err().code(), Code::InvalidArgument);
+    #[cfg(not(feature = "p256"))]
+    {
+        let p256 = RegisterRequest { user: "group_p256".to_string(), y1: vec![2; 33], y2: vec![2; 33], group: "p256".to_string(), ..Default::default() };
+        assert_eq!(client.register(p256).await.unwrap_err().code(), Code::InvalidArgument);
+    }
+
+    #[cfg(feature = "p256")]
+    p256_logins(&mut client, &offers, port, &store, server).await;
+    #[cfg(not(feature = "p256"))]
+    drop(server);
+
+    let _ = std::fs::remove_dir_all(&dir);
+    println!("✅ Every group the server offers logs in!");
+}
+
+#[tokio::test]
+async fn test_logins_with_mixed_hashes() {
+    println!("🔧 Testing a user base with per-user generators from both hashes");
+
+    let dir = temp_dir("hashes");
+    let store = dir.join("users.jsonl");
+    let port = 50081;
+    let server = start_server(port, &store);
+    let mut client = auth_client(port).await;
+
+    let offers = client.get_server_info(ServerInfoRequest {}).await.unwrap().into_inner().hashes;
+    assert_eq!(offers, ["sha256", "sha512"]);
+
+    let zkp = ZKP::builder().rfc5114().build().unwrap();
+    let register = |user: &str, hash: HashId, beta_hash: HashId| {
+        let zkp = zkp.for_user_with(beta_hash, user);
+        let x = ZKP::generate_random_number_below(&zkp.q);
+        let (y1, y2) = zkp.public_key(&x).to_bytes_be();
+        let beta = zkp.beta.to_bytes_be();
+        let hash = match hash {
+            HashId::Sha256 => String::new(),
+            hash => hash.name().to_string(),
+        };
+        (RegisterRequest { user: user.to_string(), y1, y2, beta, hash, ..Default::default() }, zkp, x)
+    };
+
+    // a generator from one hash doesn't pass for the other, and unknown hashes are refused
+    let (mismatched, _, _) = register("hash_mismatch", HashId::Sha512, HashId::Sha256);
+    assert_eq!(client.register(mismatched).await.unwrap_err().code(), Code::InvalidArgument);
+    let (unknown, _, _) = register("hash_unknown", HashId::Sha256, HashId::Sha256);
+    let unknown = RegisterRequest { hash: "md5".to_string(), ..unknown };
+    assert_eq!(client.register(unknown).await.unwrap_err().code(), Code::InvalidArgument);
+
+    let mut users = Vec::new();
+    for (user, hash) in [("hash_sha256", HashId::Sha256), ("hash_sha512", HashId::Sha512)] {
+        let (request, zkp, x) = register(user, hash, hash);
+        client.register(request).await.unwrap();
+        users.push((user, hash, zkp, x));
+    }
+
+    // each account keeps its hash across a restart, and logs in with its own generator
+    drop(server);
+    let _server = start_server(port, &store);
+    let mut client = auth_client(port).await;
+    for (user, hash, zkp, x) in &users {
+        let params = client.get_user_kdf_params(UserKdfParamsRequest { user: user.to_string() }).await.unwrap().into_inner();
+        assert_eq!(HashId::from_name(&params.hash), Some(*hash));
+        assert_eq!(ZKP::builder().rfc5114().build().unwrap().for_user_with(*hash, user).beta.to_bytes_be(), params.beta);
+
+        let k = ZKP::generate_random_number_below(&zkp.q);
+        let session = login(&mut client, user, zkp.commitment(&k).to_bytes_be(), |c| {
+            zkp.respond(&k, &Challenge::from_bytes_be(c), x).to_bytes_be()
+        })
+        .await
+        .unwrap();
+        assert!(!session.is_empty());
+    }
+
+    let _ = std::fs::remove_dir_all(&dir);
+    println!("✅ Accounts from both hashes log in side by side!");
+}
+
+#[cfg(feature = "p256")]
+async fn p256_logins(client: &mut AuthClient<tonic::transport::Channel>, offers: &[String], port: u16, store: &Path, server: Server) {
+    use rust_zkp_chaum_pedersen::nist::{self, P256};
+
+    let chosen = group::negotiate(&[GroupId::P256, GroupId::ModP], offers).unwrap();
+    assert_eq!(chosen, GroupId::P256);
+
+    let curve = P256::new();
+    let x = P256::random_scalar();
+    let (y1, y2) = curve.public_key(&x).to_wire();
+    let register = RegisterRequest { user: "group_p256".to_string(), y1, y2, group: chosen.name().to_string(), ..Default::default() };
+
+    // keys that aren't points, and per-user generators, are modp only
+    let not_points = RegisterRequest { y1: vec![0xff; 33], ..register.clone() };
+    assert_eq!(client.register(not_points).await.unwrap_err().code(), Code::InvalidArgument);
+    let with_beta = RegisterRequest { beta: vec![2; 256], ..register.clone() };
+    assert_eq!(client.register(with_beta).await.unwrap_err().code(), Code::InvalidArgument);
+
+    client.register(register).await.unwrap();
+    let answer = |k| move |c: &[u8]| nist::scalar_to_wire(&curve.respond(&k, &nist::challenge_from_wire(c), &x));
+
+    let k = P256::random_scalar();
+    let session = login(client, "group_p256", curve.commitment(&k).to_wire(), answer(k)).await.unwrap();
+    assert!(!session.is_empty());
+
+    // a wrong s, or a Z_p answer for a P-256 account, is refused
+    let k = P256::random_scalar();
+    let wrong = login(client, "group_p256", curve.commitment(&k).to_wire(), |c| {
+        let mut s = answer(k)(c);
+        s[31] ^= 1;
+        s
+    })
+    .await;
+    assert_eq!(wrong.unwrap_err().code(), Code::PermissionDenied);
+
+    // the group is in the journal, so the account still works after a restart
+    drop(server);
+    let _server = start_server(port, store);
+    let mut client = auth_client(port).await;
+    let params = client.get_user_kdf_params(UserKdfParamsRequest { user: "group_p256".to_string() }).await.unwrap().into_inner();
+    assert_eq!(params.group, "p256");
+    let k = P256::random_scalar();
+    let session = login(&mut client, "group_p256", curve.commitment(&k).to_wire(), answer(k)).await.unwrap();
+    assert!(!session.is_empty());
+    println!("   p256 logins work, before and after a restart");
+}
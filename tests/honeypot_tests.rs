@@ -0,0 +1,133 @@
+// Tests for honeypot accounts: decoys, canary credentials and the alerts they raise
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::time::{Duration, UNIX_EPOCH};
+
+use tonic::{transport::Channel, Code};
+
+use rust_zkp_chaum_pedersen::api::v1::{
+    auth_client::AuthClient, AuthenticationAnswerRequest, AuthenticationChallengeRequest, RegisterRequest, UserKdfParamsRequest,
+};
+use rust_zkp_chaum_pedersen::audit::{AuditEvent, Outcome};
+use rust_zkp_chaum_pedersen::retention::AuditRetention;
+use rust_zkp_chaum_pedersen::types::Challenge;
+use rust_zkp_chaum_pedersen::ZKP;
+
+mod common;
+use common::{auth_client, Server};
+
+fn start(dir: &Path, honeypots: &[&str]) -> Server {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_server"));
+    command.args(["--listen", "127.0.0.1:50108"]).arg("--user-store").arg(dir.join("users.jsonl"));
+    command.arg("--audit-log").arg(dir.join("audit.jsonl"));
+    for user in honeypots {
+        command.args(["--honeypot-user", user]);
+    }
+    Server(command.stdout(Stdio::null()).spawn().expect("Failed to start server process"))
+}
+
+/// log in as `user` with `x`; the status of a failed login
+async fn login(client: &mut AuthClient<Channel>, zkp: &ZKP, user: &str, x: &num_bigint::BigUint) -> Result<(), tonic::Status> {
+    let k = ZKP::generate_random_number_below(&zkp.q);
+    let (r1, r2) = zkp.commitment(&k).to_bytes_be();
+    let request = AuthenticationChallengeRequest { user: user.to_string(), r1, r2, ..Default::default() };
+    let challenge = client.create_authentication_challenge(request).await?.into_inner();
+    let s = zkp.respond(&k, &Challenge::from_bytes_be(&challenge.c), x).to_bytes_be();
+    client.verify_authentication(AuthenticationAnswerRequest { auth_id: challenge.auth_id, s, ..Default::default() }).await?;
+    Ok(())
+}
+
+#[test]
+fn test_alert_outcome() {
+    println!("🍯 Testing alerts in the audit trail");
+
+    let event = AuditEvent {
+        time: UNIX_EPOCH + Duration::from_secs(1_700_000_000),
+        action: "honeypot",
+        outcome: Outcome::Alert,
+        user: "admin".to_string(),
+        source: "203.0.113.9:50000".to_string(),
+        trace_id: "trace".to_string(),
+        detail: "challenge requested".to_string(),
+    };
+    assert!(event.to_syslog("host", "zkp-auth").starts_with("<81>1 "), "authpriv.alert");
+    assert!(event.to_json().contains(r#""outcome":"alert""#));
+    assert!(event.to_cloud_event("01ID", "urn:test").contains(r#""type":"zkp-auth.honeypot.alert""#));
+    let rule: AuditRetention = "honeypot:alert=5y".parse().unwrap();
+    assert_eq!(rule.outcome.as_deref(), Some("alert"));
+
+    println!("✅ Alerts stand out from failures!");
+}
+
+#[tokio::test]
+async fn test_honeypot_accounts() {
+    println!("🍯 Testing a server with a decoy account and canary credentials");
+
+    let dir = std::env::temp_dir().join(format!("zkp-honeypot-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let zkp = ZKP::builder().rfc5114().build().unwrap();
+    let keys = || {
+        let x = ZKP::generate_random_number_below(&zkp.q);
+        let (y1, y2) = zkp.public_key(&x).to_bytes_be();
+        (x, y1, y2)
+    };
+
+    // the canary is registered like anyone, and its password planted where an intruder would find it
+    let (canary_x, y1, y2) = keys();
+    let (alice_x, alice_y1, alice_y2) = keys();
+    {
+        let _server = start(&dir, &[]);
+        let mut client = auth_client(50108).await;
+        client.register(RegisterRequest { user: "svc-backup".to_string(), y1, y2, ..Default::default() }).await.unwrap();
+        client.register(RegisterRequest { user: "alice".to_string(), y1: alice_y1, y2: alice_y2, ..Default::default() }).await.unwrap();
+    }
+
+    let _server = start(&dir, &["admin", "svc-backup"]);
+    let mut client = auth_client(50108).await;
+
+    // the decoy looks registered, with Argon2id parameters like a real account
+    let kdf = client.get_user_kdf_params(UserKdfParamsRequest { user: "admin".to_string() }).await.expect("a decoy exists");
+    assert!(kdf.into_inner().kdf.is_some());
+    let (_, y1, y2) = keys();
+    let taken = client.register(RegisterRequest { user: "admin".to_string(), y1, y2, ..Default::default() }).await;
+    assert_eq!(taken.unwrap_err().code(), Code::AlreadyExists);
+
+    // guessing at the decoy fails as it would for any account
+    let guess = ZKP::generate_random_number_below(&zkp.q);
+    let decoy = login(&mut client, &zkp, "admin", &guess).await.unwrap_err();
+    let wrong = login(&mut client, &zkp, "alice", &guess).await.unwrap_err();
+    assert_eq!((decoy.code(), wrong.code()), (Code::PermissionDenied, Code::PermissionDenied));
+    assert!(decoy.message().ends_with("bad solution to the challenge"), "{}", decoy.message());
+
+    // the right password for the canary fails too
+    let canary = login(&mut client, &zkp, "svc-backup", &canary_x).await.unwrap_err();
+    assert_eq!(canary.code(), Code::PermissionDenied);
+    assert!(canary.message().ends_with("bad solution to the challenge"), "{}", canary.message());
+    login(&mut client, &zkp, "alice", &alice_x).await.expect("other accounts are untouched");
+
+    // an alert for every step on a honeypot, none for alice
+    let audit = std::fs::read_to_string(dir.join("audit.jsonl")).unwrap();
+    let alerts: Vec<serde_json::Value> = audit
+        .lines()
+        .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+        .filter(|event| event["outcome"] == "alert")
+        .collect();
+    let seen: Vec<(&str, &str)> = alerts.iter().map(|event| (event["user"].as_str().unwrap(), event["detail"].as_str().unwrap())).collect();
+    assert_eq!(
+        seen,
+        [
+            ("admin", "challenge requested"),
+            ("admin", "answer to a challenge"),
+            ("svc-backup", "challenge requested"),
+            ("svc-backup", "correct answer: its credentials have leaked"),
+        ]
+    );
+    assert!(alerts.iter().all(|event| event["action"] == "honeypot" && event["source"].as_str().unwrap().starts_with("127.0.0.1:")));
+
+    // the decoy lives in memory only
+    let users = std::fs::read_to_string(dir.join("users.jsonl")).unwrap();
+    assert!(!users.contains("\"admin\""));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+    println!("✅ Honeypots fail like real accounts and raise alerts!");
+}
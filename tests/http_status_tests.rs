@@ -0,0 +1,107 @@
+// Tests for answering gRPC statuses over HTTP
+use std::time::Duration;
+
+use tonic::codegen::http::{header, StatusCode};
+use tonic::{Code, Status};
+
+use rust_zkp_chaum_pedersen::http_status::{default_http_status, CodeOverride, StatusMapping, PROBLEM_JSON};
+
+#[test]
+fn test_default_mapping() {
+    println!("🌐 Testing the gateway table");
+
+    let expected = [
+        (Code::Ok, 200),
+        (Code::Cancelled, 499),
+        (Code::Unknown, 500),
+        (Code::InvalidArgument, 400),
+        (Code::DeadlineExceeded, 504),
+        (Code::NotFound, 404),
+        (Code::AlreadyExists, 409),
+        (Code::PermissionDenied, 403),
+        (Code::ResourceExhausted, 429),
+        (Code::FailedPrecondition, 400),
+        (Code::Aborted, 409),
+        (Code::OutOfRange, 400),
+        (Code::Unimplemented, 501),
+        (Code::Internal, 500),
+        (Code::Unavailable, 503),
+        (Code::DataLoss, 500),
+        (Code::Unauthenticated, 401),
+    ];
+    for (code, status) in expected {
+        assert_eq!(default_http_status(code).as_u16(), status, "{:?}", code);
+        assert_eq!(StatusMapping::default().http_status(code).as_u16(), status);
+    }
+
+    println!("✅ Every code has an HTTP status!");
+}
+
+#[test]
+fn test_overrides() {
+    println!("🌐 Testing overridden statuses");
+
+    let failed_proof: CodeOverride = "PermissionDenied=401".parse().unwrap();
+    assert_eq!(failed_proof, CodeOverride { code: Code::PermissionDenied, status: StatusCode::UNAUTHORIZED });
+    assert_eq!(failed_proof.to_string(), "PermissionDenied=401");
+    assert_eq!("permission_denied = 401".parse::<CodeOverride>(), Ok(failed_proof));
+    assert_eq!("NOT_FOUND=410".parse::<CodeOverride>().unwrap().code, Code::NotFound);
+
+    for bad in ["PermissionDenied", "Nope=404", "NotFound=200", "NotFound=999", "Ok=500", "NotFound=abc"] {
+        assert!(bad.parse::<CodeOverride>().is_err(), "{}", bad);
+    }
+
+    let mapping = StatusMapping::default().with_override(failed_proof);
+    assert_eq!(mapping.http_status(Code::PermissionDenied), StatusCode::UNAUTHORIZED);
+    assert_eq!(mapping.http_status(Code::NotFound), StatusCode::NOT_FOUND, "the rest stay as they were");
+
+    println!("✅ Codes can be answered differently!");
+}
+
+#[test]
+fn test_problem_responses() {
+    println!("🌐 Testing problem+json answers");
+
+    let mapping = StatusMapping::default();
+    let response = mapping.response(&Status::not_found("User: bob not found in database"));
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    assert_eq!(response.headers()[header::CONTENT_TYPE], PROBLEM_JSON);
+    assert!(response.headers().get(header::RETRY_AFTER).is_none());
+    let body: serde_json::Value = serde_json::from_str(response.body()).unwrap();
+    assert_eq!(
+        body,
+        serde_json::json!({
+            "type": "about:blank",
+            "title": "Not Found",
+            "status": 404,
+            "detail": "User: bob not found in database",
+            "grpc_status": "NotFound",
+        })
+    );
+
+    // busy: try again later, after the default or what the server said
+    let busy = Status::resource_exhausted("server is busy, try again later");
+    let response = mapping.clone().retry_after(Duration::from_millis(2500)).response(&busy);
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(response.headers()[header::RETRY_AFTER], "3");
+    let mut told = Status::resource_exhausted("a code was sent less than a minute ago");
+    told.metadata_mut().insert("retry-after", "42".parse().unwrap());
+    assert_eq!(mapping.response(&told).headers()[header::RETRY_AFTER], "42");
+    assert_eq!(mapping.response(&Status::unavailable("down")).headers()[header::RETRY_AFTER], "1");
+
+    // 401 says how to authenticate
+    let response = mapping.response(&Status::unauthenticated("no session"));
+    assert_eq!(response.headers()[header::WWW_AUTHENTICATE], "Bearer");
+    let failed_proof = "PermissionDenied=401".parse().unwrap();
+    let response = mapping.clone().with_override(failed_proof).response(&Status::permission_denied("bad solution to the challenge"));
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    assert_eq!(response.headers()[header::WWW_AUTHENTICATE], "Bearer");
+    let problem: serde_json::Value = serde_json::from_str(response.body()).unwrap();
+    assert_eq!((problem["title"].as_str(), problem["grpc_status"].as_str()), (Some("Unauthorized"), Some("PermissionDenied")));
+
+    let typed = mapping.problem_types("https://example.com/problems/").problem(&Status::failed_precondition("verify the email first"));
+    assert_eq!(typed.type_uri, "https://example.com/problems/failed-precondition");
+    assert_eq!(typed.status, 400);
+
+    println!("✅ HTTP clients get idiomatic errors!");
+}
@@ -0,0 +1,80 @@
+// Tests for OIDC-style ID tokens minted after a login
+use std::time::{Duration, SystemTime};
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use ed25519_dalek::SigningKey;
+use rust_zkp_chaum_pedersen::id_token::{self, IdTokenError, IdTokenIssuer};
+
+fn issuer() -> IdTokenIssuer {
+    IdTokenIssuer::new(
+        SigningKey::from_bytes(&[7u8; 32]),
+        "https://zkp.example",
+        vec!["shop".to_string()],
+        Duration::from_secs(300),
+    )
+}
+
+#[test]
+fn test_id_token_round_trip() {
+    println!("🪪 Testing ID token minting");
+
+    let issuer = issuer();
+    let key = SigningKey::from_bytes(&[7u8; 32]).verifying_key();
+    let now = SystemTime::now();
+    let token = issuer.mint("alice", "shop", "n-0S6_WzA2Mj", "session-1", now);
+    assert_eq!(token.split('.').count(), 3);
+
+    let claims = id_token::verify(&token, &key, "https://zkp.example", "shop", now).unwrap();
+    assert_eq!(claims.sub, "alice");
+    assert_eq!(claims.nonce, "n-0S6_WzA2Mj");
+    assert_eq!(claims.sid, "session-1");
+    assert_eq!(claims.exp - claims.iat, 300);
+
+    // the header names the key the relying party should pick from the JWKS
+    let header: serde_json::Value =
+        serde_json::from_slice(&URL_SAFE_NO_PAD.decode(token.split('.').next().unwrap()).unwrap()).unwrap();
+    assert_eq!(header["alg"], "EdDSA");
+    assert_eq!(header["kid"], issuer.jwk()["kid"]);
+    assert_eq!(issuer.jwk()["kty"], "OKP");
+    assert_eq!(issuer.jwk()["crv"], "Ed25519");
+
+    assert!(issuer.allows_audience("shop"));
+    assert!(!issuer.allows_audience("forum"));
+
+    println!("✅ ID tokens verify!");
+}
+
+#[test]
+fn test_bad_id_tokens_are_refused() {
+    println!("🚫 Testing ID token checks");
+
+    let issuer = issuer();
+    let key = SigningKey::from_bytes(&[7u8; 32]).verifying_key();
+    let now = SystemTime::now();
+    let token = issuer.mint("alice", "shop", "", "session-1", now);
+
+    let check = |token: &str, iss: &str, aud: &str, at: SystemTime| id_token::verify(token, &key, iss, aud, at);
+    assert_eq!(check(&token, "https://evil.example", "shop", now), Err(IdTokenError::WrongIssuer));
+    assert_eq!(check(&token, "https://zkp.example", "forum", now), Err(IdTokenError::WrongAudience));
+    assert_eq!(
+        check(&token, "https://zkp.example", "shop", now + Duration::from_secs(301)),
+        Err(IdTokenError::Expired)
+    );
+
+    // swap in claims for another user, keeping the signature
+    let parts: Vec<&str> = token.split('.').collect();
+    let claims = String::from_utf8(URL_SAFE_NO_PAD.decode(parts[1]).unwrap()).unwrap();
+    let forged = URL_SAFE_NO_PAD.encode(claims.replace("alice", "admin"));
+    let tampered = format!("{}.{}.{}", parts[0], forged, parts[2]);
+    assert_eq!(check(&tampered, "https://zkp.example", "shop", now), Err(IdTokenError::BadSignature));
+
+    let other_key = SigningKey::from_bytes(&[8u8; 32]).verifying_key();
+    assert_eq!(
+        id_token::verify(&token, &other_key, "https://zkp.example", "shop", now),
+        Err(IdTokenError::BadSignature)
+    );
+    assert!(matches!(check("not-a-jwt", "https://zkp.example", "shop", now), Err(IdTokenError::Malformed(_))));
+
+    println!("✅ Bad ID tokens are refused!");
+}
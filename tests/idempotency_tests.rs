@@ -0,0 +1,45 @@
+// Tests for the idempotency key cache used by registration
+use std::time::{Duration, SystemTime};
+
+use rust_zkp_chaum_pedersen::clock::{TestClock, Time};
+use rust_zkp_chaum_pedersen::idempotency::{IdempotencyCache, Replay};
+
+#[test]
+fn test_completed_requests_are_recognised() {
+    println!("🔁 Testing idempotency keys");
+
+    let cache = IdempotencyCache::new(Duration::from_secs(60));
+    assert_eq!(cache.check("key-1", &"alice"), Replay::New);
+
+    cache.record("key-1", "alice");
+    assert_eq!(cache.check("key-1", &"alice"), Replay::Completed);
+    assert_eq!(cache.check("key-1", &"mallory"), Replay::Mismatch);
+    assert_eq!(cache.check("key-2", &"alice"), Replay::New);
+
+    println!("✅ Idempotency key test passed!");
+}
+
+#[test]
+fn test_keys_expire() {
+    let cache = IdempotencyCache::new(Duration::ZERO);
+    cache.record("key-1", 1);
+    assert_eq!(cache.check("key-1", &1), Replay::New, "an expired key is forgotten");
+
+    // expired keys are dropped when the next one is recorded
+    cache.record("key-2", 2);
+    assert_eq!(cache.len(), 1);
+}
+
+#[test]
+fn test_keys_last_their_ttl() {
+    let clock = TestClock::new(SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000));
+    let cache = IdempotencyCache::new(Duration::from_secs(600)).with_time(Time::new(clock.clone()));
+    cache.record("key-1", 1);
+
+    clock.advance(Duration::from_secs(599));
+    assert_eq!(cache.check("key-1", &1), Replay::Completed);
+    assert_eq!(cache.expire(), 0);
+    clock.advance(Duration::from_secs(1));
+    assert_eq!(cache.check("key-1", &1), Replay::New);
+    assert_eq!(cache.expire(), 1);
+}
@@ -0,0 +1,103 @@
+// Tests for reading users to provision in bulk
+use std::path::Path;
+
+use rust_zkp_chaum_pedersen::import::{self, ImportError};
+
+#[test]
+fn test_csv_import() {
+    println!("📥 Testing CSV imports");
+
+    let text = "user,y1,y2\n\
+                # migrated from the old directory\n\
+                alice,0102,0304\n\
+                \n\
+                bob, 0a0b , 0c0d ,09090909,19456,2,1\n";
+    let users = import::parse(Path::new("users.CSV"), text).unwrap();
+    assert_eq!(users.len(), 2);
+    assert_eq!(users[0].user, "alice");
+    assert_eq!(users[0].y1, vec![1, 2]);
+    assert_eq!(users[0].y2, vec![3, 4]);
+    assert_eq!(users[0].kdf, None);
+    let kdf = users[1].kdf.clone().unwrap();
+    assert_eq!(kdf.salt, vec![9; 4]);
+    assert_eq!((kdf.memory_kib, kdf.iterations, kdf.parallelism), (19456, 2, 1));
+
+    let error = import::parse_csv("alice,0102,zz\n").unwrap_err();
+    assert_eq!(error.line, 1);
+    assert!(error.message.contains("y2"), "{}", error);
+    let error = import::parse_csv("user,y1,y2\nalice,0102\n").unwrap_err();
+    assert_eq!(error, ImportError { line: 2, message: "expected 3 or 7 fields, found 2".to_string() });
+
+    println!("✅ CSV rows become registrations!");
+}
+
+#[test]
+fn test_json_import() {
+    println!("📥 Testing JSON imports");
+
+    let array = r#"[
+        {"user": "alice", "y1": "0102", "y2": "0304"},
+        {"user": "bob", "y1": "0a", "y2": "0b", "kdf": {"salt": "0909", "memory_kib": 19456, "iterations": 2, "parallelism": 1}, "beta": "07"}
+    ]"#;
+    let users = import::parse(Path::new("users.json"), array).unwrap();
+    assert_eq!(users.len(), 2);
+    assert_eq!(users[1].kdf.as_ref().unwrap().salt, vec![9, 9]);
+    assert_eq!(users[1].beta, vec![7]);
+
+    // a user store journal works as is, minus its idempotency keys
+    let lines = "{\"user\":\"carol\",\"y1\":\"01\",\"y2\":\"02\",\"idempotency_key\":\"k\",\"registered_at\":1}\n";
+    let users = import::parse(Path::new("users.jsonl"), lines).unwrap();
+    assert_eq!(users[0].user, "carol");
+    assert_eq!(users[0].idempotency_key, "");
+
+    let error = import::parse_json("{\"user\":\"a\",\"y1\":\"01\",\"y2\":\"02\"}\n{\"user\":\"b\"}\n").unwrap_err();
+    assert_eq!(error.line, 2);
+    let error = import::parse_json(r#"[{"user": "a", "y1": "01", "y2": "02"}, {"user": "", "y1": "01", "y2": "02"}]"#).unwrap_err();
+    assert!(error.to_string().starts_with("entry 2:"), "{}", error);
+
+    println!("✅ JSON registrations are read too!");
+}
+
+#[test]
+fn test_keygen_output_imports() {
+    use std::io::Write;
+    use std::process::{Command, Stdio};
+
+    use rust_zkp_chaum_pedersen::kdf::KdfParams;
+    use rust_zkp_chaum_pedersen::ZKP;
+
+    println!("📥 Testing keys generated offline");
+
+    let dir = std::env::temp_dir().join(format!("zkp-keygen-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let out = dir.join("alice.json");
+    let salt = "00112233445566778899aabbccddeeff";
+    let password = "correct horse battery staple";
+
+    let mut keygen = Command::new(env!("CARGO_BIN_EXE_zkp-auth"))
+        .args(["keygen", "--user", "alice", "--salt", salt, "--memory-kib", "64", "--iterations", "1", "--out"])
+        .arg(&out)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .spawn()
+        .unwrap();
+    writeln!(keygen.stdin.take().unwrap(), "{}", password).unwrap();
+    assert!(keygen.wait().unwrap().success());
+
+    // what import-users would send
+    let text = std::fs::read_to_string(&out).unwrap();
+    let users = import::parse(&out, &text).unwrap();
+    assert_eq!(users.len(), 1);
+    let kdf = KdfParams::from(users[0].kdf.clone().unwrap());
+    assert_eq!(hex::encode(&kdf.salt), salt);
+    assert!(users[0].password_policy_version > 0, "the password passed the policy");
+
+    // and it is the key the password derives to
+    let zkp = ZKP::builder().rfc5114().build().unwrap();
+    let x = kdf.derive(password, &zkp.q).unwrap();
+    assert_eq!(zkp.public_key(&x).to_bytes_be(), (users[0].y1.clone(), users[0].y2.clone()));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+    println!("✅ Offline keys are ready for import!");
+}
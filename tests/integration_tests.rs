@@ -6,25 +6,24 @@ use tokio::time::sleep;
 // Import our ZKP library
 use rust_zkp_chaum_pedersen::ZKP;
 
-// Import the generated protobuf code - we'll need to include it
-pub mod zkp_auth {
-    include!("../src/zkp_auth.rs");
-}
-
-use zkp_auth::{
+// Import the generated protobuf code
+use rust_zkp_chaum_pedersen::api::v1::{
     auth_client::AuthClient, AuthenticationAnswerRequest,
     AuthenticationChallengeRequest, RegisterRequest,
 };
 
 // Helper function to start server as external process
 fn start_test_server(port: u16) -> Child {
-    Command::new("cargo")
-        .args(&["run", "--bin", "server"])
+    // cargo builds the server binary for integration tests, so run it directly
+    // instead of `cargo run`, which may have to rebuild it first
+    Command::new(env!("CARGO_BIN_EXE_server"))
         .env("SERVER_PORT", port.to_string())
         .spawn()
         .expect("Failed to start server process")
 }
 
+// The server is left running on purpose so the other tests can reuse it
+#[allow(clippy::zombie_processes)]
 #[tokio::test]
 async fn test_full_authentication_flow() {
     println!("🧪 Testing complete authentication flow...");
@@ -68,6 +67,12 @@ async fn test_full_authentication_flow() {
         user: username.clone(),
         y1: y1.to_bytes_be(),
         y2: y2.to_bytes_be(),
+        idempotency_key: String::new(),
+        password_policy_version: 0,
+        kdf: None,
+        beta: Vec::new(),
+        group: String::new(),
+        hash: String::new(),
     };
 
     match client.register(register_request).await {
@@ -87,6 +92,9 @@ async fn test_full_authentication_flow() {
         user: username.clone(),
         r1: r1.to_bytes_be(),
         r2: r2.to_bytes_be(),
+        auth_id: String::new(),
+        client: None,
+        device_id: String::new(),
     };
 
     let challenge_response = match client.create_authentication_challenge(challenge_request).await {
@@ -111,6 +119,10 @@ async fn test_full_authentication_flow() {
     let answer_request = AuthenticationAnswerRequest {
         auth_id,
         s: s.to_bytes_be(),
+        id_token: None,
+        ssh_public_key: String::new(),
+        pow_nonce: 0,
+        challenge_token: String::new(),
     };
 
     match client.verify_authentication(answer_request).await {
@@ -142,8 +154,7 @@ async fn test_wrong_password_fails() {
         }
     };
 
-    let (alpha, beta, p, q) = ZKP::get_constants();
-    let zkp = ZKP { alpha, beta, p, q };
+    let zkp = ZKP::builder().rfc5114().build().unwrap();
 
     // Register with one password
     let username = "wrong_password_test_user".to_string();
@@ -156,6 +167,12 @@ async fn test_wrong_password_fails() {
         user: username.clone(),
         y1: y1.to_bytes_be(),
         y2: y2.to_bytes_be(),
+        idempotency_key: String::new(),
+        password_policy_version: 0,
+        kdf: None,
+        beta: Vec::new(),
+        group: String::new(),
+        hash: String::new(),
     };
 
     if client.register(register_request).await.is_err() {
@@ -170,6 +187,9 @@ async fn test_wrong_password_fails() {
         user: username,
         r1: r1.to_bytes_be(),
         r2: r2.to_bytes_be(),
+        auth_id: String::new(),
+        client: None,
+        device_id: String::new(),
     };
 
     if let Ok(challenge_response) = client.create_authentication_challenge(challenge_request).await {
@@ -183,6 +203,10 @@ async fn test_wrong_password_fails() {
         let answer_request = AuthenticationAnswerRequest {
             auth_id,
             s: s.to_bytes_be(),
+            id_token: None,
+            ssh_public_key: String::new(),
+            pow_nonce: 0,
+            challenge_token: String::new(),
         };
 
         // This should FAIL
@@ -212,8 +236,7 @@ async fn test_nonexistent_user_fails() {
         }
     };
 
-    let (alpha, beta, p, q) = ZKP::get_constants();
-    let zkp = ZKP { alpha, beta, p, q };
+    let zkp = ZKP::builder().rfc5114().build().unwrap();
 
     // Try to authenticate user that doesn't exist
     let k = ZKP::generate_random_number_below(&zkp.q);
@@ -223,6 +246,9 @@ async fn test_nonexistent_user_fails() {
         user: "definitely_nonexistent_user_12345".to_string(),
         r1: r1.to_bytes_be(),
         r2: r2.to_bytes_be(),
+        auth_id: String::new(),
+        client: None,
+        device_id: String::new(),
     };
 
     match client.create_authentication_challenge(challenge_request).await {
@@ -238,12 +264,813 @@ async fn test_nonexistent_user_fails() {
     println!("🎉 Nonexistent user test PASSED!");
 }
 
+#[tokio::test]
+async fn test_retried_registration_is_idempotent() {
+    println!("🧪 Testing retried registration with an idempotency key...");
+
+    let mut client = match AuthClient::connect("http://127.0.0.1:50051").await {
+        Ok(client) => client,
+        Err(_) => {
+            println!("⚠️  Server not running - skipping idempotency test");
+            return;
+        }
+    };
+
+    let zkp = ZKP::builder().rfc5114().build().unwrap();
+    let (y1, y2) = zkp.compute_pair(&BigUint::from_bytes_be(b"retry_password"));
+    let request = RegisterRequest {
+        user: "idempotent_test_user".to_string(),
+        y1: y1.to_bytes_be(),
+        y2: y2.to_bytes_be(),
+        idempotency_key: ZKP::generate_random_string(24),
+        password_policy_version: 0,
+        kdf: None,
+        beta: Vec::new(),
+        group: String::new(),
+        hash: String::new(),
+    };
+
+    // the retry of a registration that went through still succeeds
+    client.register(request.clone()).await.expect("first attempt failed");
+    client.register(request.clone()).await.expect("retry with the same key failed");
+    println!("✅ Retry with the same key succeeded");
+
+    // without the key it's a genuine duplicate
+    let duplicate = RegisterRequest { idempotency_key: String::new(), ..request.clone() };
+    let status = client.register(duplicate).await.expect_err("duplicate registration was accepted");
+    assert_eq!(status.code(), tonic::Code::AlreadyExists);
+
+    // and the key can't be reused to overwrite the user with other values
+    let (other_y1, _) = zkp.compute_pair(&BigUint::from(7u32));
+    let hijack = RegisterRequest { y1: other_y1.to_bytes_be(), ..request };
+    let status = client.register(hijack).await.expect_err("key reuse was accepted");
+    assert_eq!(status.code(), tonic::Code::InvalidArgument);
+
+    println!("🎉 Idempotency test PASSED!");
+}
+
+#[tokio::test]
+async fn test_traceparent_is_continued_by_the_server() {
+    use rust_zkp_chaum_pedersen::api::v1::ParametersRequest;
+    use rust_zkp_chaum_pedersen::trace::TraceContext;
+
+    println!("🧪 Testing trace context propagation...");
+
+    let mut client = match AuthClient::connect("http://127.0.0.1:50051").await {
+        Ok(client) => client,
+        Err(_) => {
+            println!("⚠️  Server not running - skipping trace test");
+            return;
+        }
+    };
+
+    // the server answers Unimplemented without a signing key, either way the
+    // reply should carry the server's span in our trace
+    let trace = TraceContext::new_root();
+    let metadata = match client.get_parameters(trace.request(ParametersRequest {})).await {
+        Ok(response) => response.metadata().clone(),
+        Err(_) => {
+            let zkp = ZKP::builder().rfc5114().build().unwrap();
+            let (y1, y2) = zkp.public_key(&ZKP::generate_random_number_below(&zkp.q)).to_bytes_be();
+            let response = client
+                .register(trace.request(RegisterRequest {
+                    user: "trace_test_user".to_string(),
+                    y1,
+                    y2,
+                    idempotency_key: String::new(),
+                    password_policy_version: 0,
+                    kdf: None,
+                    beta: Vec::new(),
+                    group: String::new(),
+                    hash: String::new(),
+                }))
+                .await
+                .expect("register failed");
+            response.metadata().clone()
+        }
+    };
+    let server = TraceContext::from_metadata(&metadata).expect("no traceparent in the response");
+    assert_eq!(server.trace_id, trace.trace_id);
+    assert_ne!(server.span_id, trace.span_id);
+
+    println!("🎉 Trace propagation test PASSED!");
+}
+
+#[tokio::test]
+async fn test_login_from_another_device_with_stored_kdf_params() {
+    use rust_zkp_chaum_pedersen::api::v1::UserKdfParamsRequest;
+    use rust_zkp_chaum_pedersen::kdf::KdfParams;
+
+    println!("🧪 Testing KDF parameters stored per user...");
+
+    let mut client = match AuthClient::connect("http://127.0.0.1:50051").await {
+        Ok(client) => client,
+        Err(_) => {
+            println!("⚠️  Server not running - skipping KDF test");
+            return;
+        }
+    };
+
+    let zkp = ZKP::builder().rfc5114().build().unwrap();
+    let username = "kdf_test_user".to_string();
+    let password = "a long and unguessable passphrase";
+
+    // device one registers with its own salt
+    let params = KdfParams::with_cost(256, 1, 1);
+    let x = params.derive(password, &zkp.q).unwrap();
+    let (y1, y2) = zkp.compute_pair(&x);
+    client
+        .register(RegisterRequest {
+            user: username.clone(),
+            y1: y1.to_bytes_be(),
+            y2: y2.to_bytes_be(),
+            idempotency_key: String::new(),
+            password_policy_version: 0,
+            kdf: Some((&params).into()),
+            beta: Vec::new(),
+            group: String::new(),
+            hash: String::new(),
+        })
+        .await
+        .expect("registration failed");
+
+    // device two only knows the password and asks for the parameters
+    let stored: KdfParams = client
+        .get_user_kdf_params(UserKdfParamsRequest { user: username.clone() })
+        .await
+        .expect("could not fetch the KDF parameters")
+        .into_inner()
+        .kdf
+        .expect("parameters were not stored")
+        .into();
+    assert_eq!(stored, params);
+    let x = stored.derive(password, &zkp.q).unwrap();
+
+    let k = ZKP::generate_random_number_below(&zkp.q);
+    let (r1, r2) = zkp.compute_pair(&k);
+    let challenge = client
+        .create_authentication_challenge(AuthenticationChallengeRequest {
+            user: username,
+            r1: r1.to_bytes_be(),
+            r2: r2.to_bytes_be(),
+            auth_id: String::new(),
+            client: None,
+            device_id: String::new(),
+        })
+        .await
+        .expect("challenge failed")
+        .into_inner();
+    let s = zkp.solve(&k, &BigUint::from_bytes_be(&challenge.c), &x);
+    client
+        .verify_authentication(AuthenticationAnswerRequest { auth_id: challenge.auth_id, s: s.to_bytes_be(), id_token: None, ssh_public_key: String::new(), pow_nonce: 0, challenge_token: String::new() })
+        .await
+        .expect("login with the rederived secret failed");
+
+    println!("🎉 KDF parameter test PASSED!");
+}
+
+#[tokio::test]
+async fn test_per_user_generator_login() {
+    use rust_zkp_chaum_pedersen::api::v1::UserKdfParamsRequest;
+
+    println!("🧪 Testing registration with a per-user generator...");
+
+    let mut client = match AuthClient::connect("http://127.0.0.1:50051").await {
+        Ok(client) => client,
+        Err(_) => {
+            println!("⚠️  Server not running - skipping per-user generator test");
+            return;
+        }
+    };
+
+    let global = ZKP::builder().rfc5114().build().unwrap();
+    let username = "per_user_generator_test_user".to_string();
+    let zkp = global.for_user(&username);
+    let x = ZKP::generate_random_number_below(&zkp.q);
+    let (y1, y2) = zkp.compute_pair(&x);
+    let request = RegisterRequest {
+        user: username.clone(),
+        y1: y1.to_bytes_be(),
+        y2: y2.to_bytes_be(),
+        idempotency_key: String::new(),
+        password_policy_version: 0,
+        kdf: None,
+        beta: zkp.beta.to_bytes_be(),
+        group: String::new(),
+        hash: String::new(),
+    };
+
+    // a generator of the client's choosing is refused
+    let forged = RegisterRequest { beta: global.for_user("someone_else").beta.to_bytes_be(), ..request.clone() };
+    let status = client.register(forged).await.expect_err("a foreign generator was accepted");
+    assert_eq!(status.code(), tonic::Code::InvalidArgument);
+
+    client.register(request).await.expect("registration failed");
+    let stored = client
+        .get_user_kdf_params(UserKdfParamsRequest { user: username.clone() })
+        .await
+        .expect("could not fetch the user's parameters")
+        .into_inner();
+    assert_eq!(stored.beta, zkp.beta.to_bytes_be());
+
+    let k = ZKP::generate_random_number_below(&zkp.q);
+    let (r1, r2) = zkp.compute_pair(&k);
+    let challenge = client
+        .create_authentication_challenge(AuthenticationChallengeRequest {
+            user: username,
+            r1: r1.to_bytes_be(),
+            r2: r2.to_bytes_be(),
+            auth_id: String::new(),
+            client: None,
+            device_id: String::new(),
+        })
+        .await
+        .expect("challenge failed")
+        .into_inner();
+    let s = zkp.solve(&k, &BigUint::from_bytes_be(&challenge.c), &x);
+    client
+        .verify_authentication(AuthenticationAnswerRequest { auth_id: challenge.auth_id, s: s.to_bytes_be(), id_token: None, ssh_public_key: String::new(), pow_nonce: 0, challenge_token: String::new() })
+        .await
+        .expect("login with the per-user generator failed");
+
+    println!("🎉 Per-user generator test PASSED!");
+}
+
+#[tokio::test]
+async fn test_proof_revocation() {
+    use rust_zkp_chaum_pedersen::api::v1::{CheckRevocationRequest, RevocationListRequest, RevokeProofRequest};
+    use rust_zkp_chaum_pedersen::nizk::ValidityWindow;
+
+    println!("🧪 Testing proof revocation...");
+
+    let mut client = match AuthClient::connect("http://127.0.0.1:50051").await {
+        Ok(client) => client,
+        Err(_) => {
+            println!("⚠️  Server not running - skipping revocation test");
+            return;
+        }
+    };
+
+    let zkp = ZKP::builder().rfc5114().build().unwrap();
+    let username = "revocation_test_user".to_string();
+    let x = ZKP::generate_random_number_below(&zkp.q);
+    let (y1, y2) = zkp.compute_pair(&x);
+    client
+        .register(RegisterRequest {
+            user: username.clone(),
+            y1: y1.to_bytes_be(),
+            y2: y2.to_bytes_be(),
+            idempotency_key: String::new(),
+            password_policy_version: 0,
+            kdf: None,
+            beta: Vec::new(),
+            group: String::new(),
+            hash: String::new(),
+        })
+        .await
+        .expect("registration failed");
+
+    let proof = zkp.prove_non_interactive(&x, ValidityWindow::starting_now(Duration::from_secs(3600)), b"export");
+    let revoke = RevokeProofRequest {
+        user: username.clone(),
+        session_id: "not-a-session".to_string(),
+        proof_id: proof.id.clone(),
+        reason: "leaked".to_string(),
+    };
+
+    // revoking needs a session
+    let status = client.revoke_proof(revoke.clone()).await.expect_err("revoked without logging in");
+    assert_eq!(status.code(), tonic::Code::Unauthenticated);
+
+    let k = ZKP::generate_random_number_below(&zkp.q);
+    let (r1, r2) = zkp.compute_pair(&k);
+    let challenge = client
+        .create_authentication_challenge(AuthenticationChallengeRequest {
+            user: username.clone(),
+            r1: r1.to_bytes_be(),
+            r2: r2.to_bytes_be(),
+            auth_id: String::new(),
+            client: None,
+            device_id: String::new(),
+        })
+        .await
+        .expect("challenge failed")
+        .into_inner();
+    let s = zkp.solve(&k, &BigUint::from_bytes_be(&challenge.c), &x);
+    let session_id = client
+        .verify_authentication(AuthenticationAnswerRequest { auth_id: challenge.auth_id, s: s.to_bytes_be(), id_token: None, ssh_public_key: String::new(), pow_nonce: 0, challenge_token: String::new() })
+        .await
+        .expect("login failed")
+        .into_inner()
+        .session_id;
+
+    client
+        .revoke_proof(RevokeProofRequest { session_id, ..revoke })
+        .await
+        .expect("revocation failed");
+
+    let check = client
+        .check_revocation(CheckRevocationRequest { user: username.clone(), proof_id: proof.id.clone() })
+        .await
+        .expect("check failed")
+        .into_inner();
+    assert!(check.revoked);
+    assert_eq!(check.reason, "leaked");
+    let other_user = client
+        .check_revocation(CheckRevocationRequest { user: "someone_else".to_string(), proof_id: proof.id.clone() })
+        .await
+        .expect("check failed")
+        .into_inner();
+    assert!(!other_user.revoked);
+
+    let list = client.get_revocation_list(RevocationListRequest {}).await.expect("list failed").into_inner();
+    assert!(list.revoked.iter().any(|entry| entry.user == username && entry.proof_id == proof.id));
+
+    println!("🎉 Revocation test PASSED!");
+}
+
+#[tokio::test]
+async fn test_server_time() {
+    use rust_zkp_chaum_pedersen::api::v1::ServerTimeRequest;
+    use rust_zkp_chaum_pedersen::clock::estimate_offset_millis;
+
+    println!("🧪 Testing GetServerTime...");
+
+    let mut client = match AuthClient::connect("http://127.0.0.1:50051").await {
+        Ok(client) => client,
+        Err(_) => {
+            println!("⚠️  Server not running - skipping server time test");
+            return;
+        }
+    };
+
+    let sent = std::time::SystemTime::now();
+    let time = client.get_server_time(ServerTimeRequest {}).await.expect("server time failed").into_inner();
+    let received = std::time::SystemTime::now();
+
+    // same machine, so the clocks agree up to the round trip
+    assert!(estimate_offset_millis(sent, received, time.unix_millis).abs() < 5_000);
+    assert_eq!(time.max_skew_secs, 60);
+
+    println!("🎉 Server time test PASSED!");
+}
+
+#[tokio::test]
+async fn test_compressed_login() {
+    use rust_zkp_chaum_pedersen::compression;
+    use tonic::codec::CompressionEncoding;
+
+    println!("🧪 Testing logins with compressed messages...");
+
+    for encoding in [CompressionEncoding::Gzip, CompressionEncoding::Zstd] {
+        let mut client = match AuthClient::connect("http://127.0.0.1:50051").await {
+            Ok(client) => client.send_compressed(encoding).accept_compressed(compression::ACCEPTED[0]),
+            Err(_) => {
+                println!("⚠️  Server not running - skipping compression test");
+                return;
+            }
+        };
+
+        let zkp = ZKP::builder().rfc5114().build().unwrap();
+        let user = format!("compressed_{}", ZKP::generate_random_string(8));
+        let x = ZKP::generate_random_number_below(&zkp.q);
+        let (y1, y2) = zkp.public_key(&x).to_bytes_be();
+        client
+            .register(RegisterRequest { user: user.clone(), y1, y2, ..Default::default() })
+            .await
+            .expect("compressed registration failed");
+
+        let k = ZKP::generate_random_number_below(&zkp.q);
+        let (r1, r2) = zkp.commitment(&k).to_bytes_be();
+        let challenge = client
+            .create_authentication_challenge(AuthenticationChallengeRequest { user, r1, r2, auth_id: String::new(), client: None, device_id: String::new() })
+            .await
+            .expect("compressed challenge failed")
+            .into_inner();
+
+        let c = rust_zkp_chaum_pedersen::types::Challenge::from_bytes_be(&challenge.c);
+        let s = zkp.respond(&k, &c, &x).to_bytes_be();
+        client
+            .verify_authentication(AuthenticationAnswerRequest { auth_id: challenge.auth_id, s, id_token: None, ssh_public_key: String::new(), pow_nonce: 0, challenge_token: String::new() })
+            .await
+            .expect("compressed login failed");
+        println!("✅ Logged in with {:?}", encoding);
+    }
+
+    println!("🎉 Compression test PASSED!");
+}
+
+#[tokio::test]
+async fn test_client_metadata() {
+    use rust_zkp_chaum_pedersen::api::v1::ClientMetadata;
+
+    println!("🧪 Testing client metadata on challenges...");
+
+    let mut client = match AuthClient::connect("http://127.0.0.1:50051").await {
+        Ok(client) => client,
+        Err(_) => {
+            println!("⚠️  Server not running - skipping client metadata test");
+            return;
+        }
+    };
+
+    let zkp = ZKP::builder().rfc5114().build().unwrap();
+    let user = format!("device_{}", ZKP::generate_random_string(8));
+    let x = ZKP::generate_random_number_below(&zkp.q);
+    let (y1, y2) = zkp.public_key(&x).to_bytes_be();
+    client
+        .register(RegisterRequest { user: user.clone(), y1, y2, ..Default::default() })
+        .await
+        .expect("registration failed");
+
+    let k = ZKP::generate_random_number_below(&zkp.q);
+    let (r1, r2) = zkp.commitment(&k).to_bytes_be();
+    let metadata = ClientMetadata {
+        device_id: "test-device".to_string(),
+        app_version: "1.2.3".to_string(),
+        platform: "linux".to_string(),
+    };
+
+    // oversized free text is refused
+    let huge = ClientMetadata { device_id: "x".repeat(1000), ..metadata.clone() };
+    let refused = client
+        .create_authentication_challenge(AuthenticationChallengeRequest {
+            user: user.clone(),
+            r1: r1.clone(),
+            r2: r2.clone(),
+            auth_id: String::new(),
+            client: Some(huge),
+            device_id: String::new(),
+        })
+        .await
+        .expect_err("oversized metadata must be refused");
+    assert_eq!(refused.code(), tonic::Code::InvalidArgument);
+
+    let challenge = client
+        .create_authentication_challenge(AuthenticationChallengeRequest {
+            user,
+            r1,
+            r2,
+            auth_id: String::new(),
+            client: Some(metadata),
+            device_id: String::new(),
+        })
+        .await
+        .expect("challenge with metadata failed")
+        .into_inner();
+    let c = rust_zkp_chaum_pedersen::types::Challenge::from_bytes_be(&challenge.c);
+    let s = zkp.respond(&k, &c, &x).to_bytes_be();
+    client
+        .verify_authentication(AuthenticationAnswerRequest { auth_id: challenge.auth_id, s, id_token: None, ssh_public_key: String::new(), pow_nonce: 0, challenge_token: String::new() })
+        .await
+        .expect("login with metadata failed");
+
+    println!("🎉 Client metadata test PASSED!");
+}
+
+#[tokio::test]
+async fn test_session_management() {
+    use rust_zkp_chaum_pedersen::api::v1::{ClientMetadata, ListSessionsRequest, RevokeSessionRequest};
+    use tonic::transport::Channel;
+
+    println!("🧪 Testing session listing and revocation...");
+
+    let mut client = match AuthClient::connect("http://127.0.0.1:50051").await {
+        Ok(client) => client,
+        Err(_) => {
+            println!("⚠️  Server not running - skipping session test");
+            return;
+        }
+    };
+
+    let zkp = ZKP::builder().rfc5114().build().unwrap();
+    let user = format!("sessions_{}", ZKP::generate_random_string(8));
+    let x = ZKP::generate_random_number_below(&zkp.q);
+    let (y1, y2) = zkp.public_key(&x).to_bytes_be();
+    client
+        .register(RegisterRequest { user: user.clone(), y1, y2, ..Default::default() })
+        .await
+        .expect("registration failed");
+
+    async fn login(client: &mut AuthClient<Channel>, zkp: &ZKP, user: &str, x: &BigUint, device: &str) -> String {
+        let k = ZKP::generate_random_number_below(&zkp.q);
+        let (r1, r2) = zkp.commitment(&k).to_bytes_be();
+        let client_metadata = ClientMetadata { device_id: device.to_string(), ..Default::default() };
+        let challenge = client
+            .create_authentication_challenge(AuthenticationChallengeRequest {
+                user: user.to_string(),
+                r1,
+                r2,
+                auth_id: String::new(),
+                client: Some(client_metadata),
+                device_id: String::new(),
+            })
+            .await
+            .expect("challenge failed")
+            .into_inner();
+        let c = rust_zkp_chaum_pedersen::types::Challenge::from_bytes_be(&challenge.c);
+        let s = zkp.respond(&k, &c, x).to_bytes_be();
+        client
+            .verify_authentication(AuthenticationAnswerRequest { auth_id: challenge.auth_id, s, id_token: None, ssh_public_key: String::new(), pow_nonce: 0, challenge_token: String::new() })
+            .await
+            .expect("login failed")
+            .into_inner()
+            .session_id
+    }
+
+    let laptop = login(&mut client, &zkp, &user, &x, "laptop").await;
+    let phone = login(&mut client, &zkp, &user, &x, "phone").await;
+
+    // both devices show up, oldest first, the caller's marked
+    let sessions = client
+        .list_sessions(ListSessionsRequest { user: user.clone(), session_id: laptop.clone() })
+        .await
+        .expect("listing failed")
+        .into_inner()
+        .sessions;
+    let devices: Vec<_> = sessions.iter().map(|s| s.client.clone().unwrap_or_default().device_id).collect();
+    assert_eq!(devices, ["laptop", "phone"]);
+    assert!(sessions[0].current && !sessions[1].current);
+
+    // listing needs a session of that user
+    let status = client
+        .list_sessions(ListSessionsRequest { user: user.clone(), session_id: "made-up".to_string() })
+        .await
+        .expect_err("listed without a session");
+    assert_eq!(status.code(), tonic::Code::Unauthenticated);
+
+    // the laptop kills the stolen phone's session
+    client
+        .revoke_session(RevokeSessionRequest {
+            user: user.clone(),
+            session_id: laptop.clone(),
+            revoke_session_id: phone.clone(),
+        })
+        .await
+        .expect("revoking failed");
+    let status = client
+        .list_sessions(ListSessionsRequest { user: user.clone(), session_id: phone.clone() })
+        .await
+        .expect_err("a revoked session still works");
+    assert_eq!(status.code(), tonic::Code::Unauthenticated);
+
+    let status = client
+        .revoke_session(RevokeSessionRequest { user, session_id: laptop, revoke_session_id: phone })
+        .await
+        .expect_err("revoked a session twice");
+    assert_eq!(status.code(), tonic::Code::NotFound);
+
+    println!("🎉 Session management test PASSED!");
+}
+
+#[tokio::test]
+async fn test_last_login_is_reported() {
+    use rust_zkp_chaum_pedersen::api::v1::{AuthenticationAnswerResponse, ClientMetadata};
+    use tonic::transport::Channel;
+
+    println!("🧪 Testing last login tracking...");
+
+    let mut client = match AuthClient::connect("http://127.0.0.1:50051").await {
+        Ok(client) => client,
+        Err(_) => {
+            println!("⚠️  Server not running - skipping last login test");
+            return;
+        }
+    };
+
+    let zkp = ZKP::builder().rfc5114().build().unwrap();
+    let user = format!("history_{}", ZKP::generate_random_string(8));
+    let x = ZKP::generate_random_number_below(&zkp.q);
+    let (y1, y2) = zkp.public_key(&x).to_bytes_be();
+    client
+        .register(RegisterRequest { user: user.clone(), y1, y2, ..Default::default() })
+        .await
+        .expect("registration failed");
+
+    // answer a challenge with x, from a device called `device`
+    async fn attempt(
+        client: &mut AuthClient<Channel>,
+        zkp: &ZKP,
+        user: &str,
+        x: &BigUint,
+        device: &str,
+    ) -> Result<AuthenticationAnswerResponse, tonic::Status> {
+        let k = ZKP::generate_random_number_below(&zkp.q);
+        let (r1, r2) = zkp.commitment(&k).to_bytes_be();
+        let client_metadata = ClientMetadata { device_id: device.to_string(), ..Default::default() };
+        let challenge = client
+            .create_authentication_challenge(AuthenticationChallengeRequest {
+                user: user.to_string(),
+                r1,
+                r2,
+                auth_id: String::new(),
+                client: Some(client_metadata),
+                device_id: String::new(),
+            })
+            .await?
+            .into_inner();
+        let c = rust_zkp_chaum_pedersen::types::Challenge::from_bytes_be(&challenge.c);
+        let s = zkp.respond(&k, &c, x).to_bytes_be();
+        client
+            .verify_authentication(AuthenticationAnswerRequest { auth_id: challenge.auth_id, s, id_token: None, ssh_public_key: String::new(), pow_nonce: 0, challenge_token: String::new() })
+            .await
+            .map(|response| response.into_inner())
+    }
+
+    // someone guesses wrong twice before the first real login
+    for _ in 0..2 {
+        attempt(&mut client, &zkp, &user, &(&x + 1u32), "attacker").await.expect_err("wrong x logged in");
+    }
+    let first = attempt(&mut client, &zkp, &user, &x, "laptop").await.expect("login failed");
+    assert!(first.last_login.is_none());
+    assert_eq!(first.failed_logins, 2);
+    let failed = first.last_failed_login.expect("the failures are reported");
+    assert_eq!(failed.client.unwrap().device_id, "attacker");
+    assert!(!failed.source.is_empty(), "the source address is recorded");
+
+    // the next login sees the first one, and the failures were reset
+    let second = attempt(&mut client, &zkp, &user, &x, "phone").await.expect("login failed");
+    let last = second.last_login.expect("the previous login is reported");
+    assert_eq!(last.client.unwrap().device_id, "laptop");
+    assert_eq!(second.failed_logins, 0);
+
+    println!("🎉 Last login test PASSED!");
+}
+
+#[tokio::test]
+async fn test_committed_challenge_flow() {
+    use rust_zkp_chaum_pedersen::api::v1::{ChallengeCommitmentRequest, ChallengeMode, ServerInfoRequest};
+    use rust_zkp_chaum_pedersen::challenge::verify_opening;
+
+    println!("🧪 Testing the committed challenge flow...");
+
+    let mut client = match AuthClient::connect("http://127.0.0.1:50051").await {
+        Ok(client) => client,
+        Err(_) => {
+            println!("⚠️  Server not running - skipping committed challenge test");
+            return;
+        }
+    };
+
+    let info = client.get_server_info(ServerInfoRequest {}).await.expect("server info failed").into_inner();
+    assert!(info.challenge_modes().any(|mode| mode == ChallengeMode::Committed));
+
+    let zkp = ZKP::builder().rfc5114().build().unwrap();
+    let username = "committed_challenge_test_user".to_string();
+    let x = ZKP::generate_random_number_below(&zkp.q);
+    let (y1, y2) = zkp.compute_pair(&x);
+    client
+        .register(RegisterRequest {
+            user: username.clone(),
+            y1: y1.to_bytes_be(),
+            y2: y2.to_bytes_be(),
+            idempotency_key: String::new(),
+            password_policy_version: 0,
+            kdf: None,
+            beta: Vec::new(),
+            group: String::new(),
+            hash: String::new(),
+        })
+        .await
+        .expect("registration failed");
+
+    // 1. the server commits to c
+    let commitment = client
+        .commit_challenge(ChallengeCommitmentRequest { user: username.clone() })
+        .await
+        .expect("commit failed")
+        .into_inner();
+
+    // answering before sending (r1, r2) is refused
+    let early = client
+        .verify_authentication(AuthenticationAnswerRequest { auth_id: commitment.auth_id.clone(), s: vec![1], id_token: None, ssh_public_key: String::new(), pow_nonce: 0, challenge_token: String::new() })
+        .await
+        .expect_err("answer accepted before the commitments were sent");
+    assert_eq!(early.code(), tonic::Code::FailedPrecondition);
+
+    // 2. we send (r1, r2) and the server opens the commitment
+    let k = ZKP::generate_random_number_below(&zkp.q);
+    let (r1, r2) = zkp.compute_pair(&k);
+    let challenge = client
+        .create_authentication_challenge(AuthenticationChallengeRequest {
+            user: username.clone(),
+            r1: r1.to_bytes_be(),
+            r2: r2.to_bytes_be(),
+            auth_id: commitment.auth_id.clone(),
+            client: None,
+            device_id: String::new(),
+        })
+        .await
+        .expect("opening failed")
+        .into_inner();
+    let c = BigUint::from_bytes_be(&challenge.c);
+    assert_eq!(challenge.auth_id, commitment.auth_id);
+    assert!(verify_opening(&commitment.commitment, &c, &challenge.nonce), "the opening must match");
+
+    // a commitment can only be opened once
+    let again = client
+        .create_authentication_challenge(AuthenticationChallengeRequest {
+            user: username,
+            r1: r1.to_bytes_be(),
+            r2: r2.to_bytes_be(),
+            auth_id: commitment.auth_id.clone(),
+            client: None,
+            device_id: String::new(),
+        })
+        .await;
+    assert!(again.is_err());
+
+    // 3. answer as usual
+    let s = zkp.solve(&k, &c, &x);
+    let answer = AuthenticationAnswerRequest { auth_id: challenge.auth_id, s: s.to_bytes_be(), id_token: None, ssh_public_key: String::new(), pow_nonce: 0, challenge_token: String::new() };
+    client
+        .verify_authentication(answer.clone())
+        .await
+        .expect("login with a committed challenge failed");
+
+    // and it logs in once: the same answer again is no second session
+    let replayed = client.verify_authentication(answer).await.expect_err("one answer logged in twice");
+    assert_eq!(replayed.code(), tonic::Code::NotFound);
+
+    println!("🎉 Committed challenge test PASSED!");
+}
+
+#[tokio::test]
+async fn test_health_check_reports_serving() {
+    use tonic_health::pb::{health_check_response::ServingStatus, health_client::HealthClient, HealthCheckRequest};
+
+    println!("🧪 Testing health check endpoint...");
+
+    let channel = tonic::transport::Endpoint::from_static("http://127.0.0.1:50051").connect().await;
+    let mut client = match channel {
+        Ok(channel) => HealthClient::new(channel),
+        Err(_) => {
+            println!("⚠️  Server not running - skipping health check test");
+            return;
+        }
+    };
+
+    // "" is liveness, the service name is readiness
+    for service in ["", "zkp_auth.v1.Auth", "zkp_auth.Auth"] {
+        let response = client
+            .check(HealthCheckRequest { service: service.to_string() })
+            .await
+            .expect("Health check failed")
+            .into_inner();
+        assert_eq!(response.status, ServingStatus::Serving as i32, "service {:?} not serving", service);
+    }
+
+    println!("🎉 Health check test PASSED!");
+}
+
+#[tokio::test]
+async fn test_legacy_unversioned_service_still_works() {
+    use tonic::codec::ProstCodec;
+    use tonic::codegen::http::uri::PathAndQuery;
+    use rust_zkp_chaum_pedersen::api::v1::RegisterResponse;
+
+    println!("🧪 Testing clients built before the API was versioned...");
+
+    let channel = tonic::transport::Endpoint::from_static("http://127.0.0.1:50051").connect().await;
+    let mut grpc = match channel {
+        Ok(channel) => tonic::client::Grpc::new(channel),
+        Err(_) => {
+            println!("⚠️  Server not running - skipping legacy API test");
+            return;
+        }
+    };
+
+    // exactly what an old client sends: same message, unversioned service path
+    let zkp = ZKP::builder().rfc5114().build().unwrap();
+    let (y1, y2) = zkp.compute_pair(&BigUint::from(42u32));
+    grpc.ready().await.expect("server not ready");
+    let response = grpc
+        .unary::<_, RegisterResponse, _>(
+            tonic::Request::new(RegisterRequest {
+                user: "legacy_client_user".to_string(),
+                y1: y1.to_bytes_be(),
+                y2: y2.to_bytes_be(),
+                idempotency_key: String::new(),
+                password_policy_version: 0,
+                kdf: None,
+                beta: Vec::new(),
+                group: String::new(),
+                hash: String::new(),
+            }),
+            PathAndQuery::from_static("/zkp_auth.Auth/Register"),
+            ProstCodec::default(),
+        )
+        .await;
+    assert!(response.is_ok(), "legacy register failed: {:?}", response.err());
+
+    println!("🎉 Legacy API test PASSED!");
+}
+
 #[test]
 fn test_zkp_security_properties() {
     println!("🧪 Testing ZKP security properties...");
 
-    let (alpha, beta, p, q) = ZKP::get_constants();
-    let zkp = ZKP { alpha, beta, p, q };
+    let zkp = ZKP::builder().rfc5114().build().unwrap();
 
     // Test 1: Completeness - honest prover should always succeed
     println!("🔍 Testing completeness property...");
@@ -311,8 +1138,7 @@ fn test_zkp_security_properties() {
 fn test_edge_cases() {
     println!("🧪 Testing edge cases...");
 
-    let (alpha, beta, p, q) = ZKP::get_constants();
-    let zkp = ZKP { alpha, beta, p, q };
+    let zkp = ZKP::builder().rfc5114().build().unwrap();
 
     // Test with x = 0
     println!("🔍 Testing with zero secret...");
@@ -391,4 +1217,208 @@ fn test_random_number_generation() {
     
     println!("✅ Random string generation working correctly");
     println!("🎉 Random number generation test PASSED!");
-}
\ No newline at end of file
+}
+
+#[tokio::test]
+async fn test_session_elevation() {
+    use rust_zkp_chaum_pedersen::api::v1::{ElevateSessionRequest, ListSessionsRequest};
+    use rust_zkp_chaum_pedersen::types::Challenge;
+    use tonic::transport::Channel;
+
+    println!("🧪 Testing elevated sessions...");
+
+    let mut client = match AuthClient::connect("http://127.0.0.1:50051").await {
+        Ok(client) => client,
+        Err(_) => {
+            println!("⚠️  Server not running - skipping elevation test");
+            return;
+        }
+    };
+
+    let zkp = ZKP::builder().rfc5114().build().unwrap();
+    let user = format!("elevate_{}", ZKP::generate_random_string(8));
+    let x = ZKP::generate_random_number_below(&zkp.q);
+    let (y1, y2) = zkp.public_key(&x).to_bytes_be();
+    client
+        .register(RegisterRequest { user: user.clone(), y1, y2, ..Default::default() })
+        .await
+        .expect("registration failed");
+
+    // a challenge and the answer for it; the server keeps one open challenge per user
+    async fn answer(client: &mut AuthClient<Channel>, zkp: &ZKP, user: &str, x: &BigUint) -> (String, Vec<u8>) {
+        let k = ZKP::generate_random_number_below(&zkp.q);
+        let (r1, r2) = zkp.commitment(&k).to_bytes_be();
+        let challenge = client
+            .create_authentication_challenge(AuthenticationChallengeRequest {
+                user: user.to_string(),
+                r1,
+                r2,
+                auth_id: String::new(),
+                client: None,
+                device_id: String::new(),
+            })
+            .await
+            .expect("challenge failed")
+            .into_inner();
+        let s = zkp.respond(&k, &Challenge::from_bytes_be(&challenge.c), x).to_bytes_be();
+        (challenge.auth_id, s)
+    }
+
+    let (auth_id, s) = answer(&mut client, &zkp, &user, &x).await;
+    let session_id = client
+        .verify_authentication(AuthenticationAnswerRequest { auth_id: auth_id.clone(), s: s.clone(), id_token: None, ssh_public_key: String::new(), pow_nonce: 0, challenge_token: String::new() })
+        .await
+        .expect("login failed")
+        .into_inner()
+        .session_id;
+
+    // the login's own proof is used up, it doesn't elevate
+    let status = client
+        .elevate_session(ElevateSessionRequest { user: user.clone(), session_id: session_id.clone(), auth_id, s, challenge_token: String::new() })
+        .await
+        .expect_err("elevated with the login's proof");
+    assert_eq!(status.code(), tonic::Code::NotFound);
+
+    // sessions start standard
+    let list = ListSessionsRequest { user: user.clone(), session_id: session_id.clone() };
+    let sessions = client.list_sessions(list.clone()).await.expect("listing failed").into_inner().sessions;
+    assert_eq!(sessions[0].elevated_until_unix_millis, 0);
+
+    // a wrong proof doesn't elevate
+    let (wrong_auth_id, wrong_s) = answer(&mut client, &zkp, &user, &(&x + 1u32)).await;
+    let status = client
+        .elevate_session(ElevateSessionRequest {
+            user: user.clone(),
+            session_id: session_id.clone(),
+            auth_id: wrong_auth_id,
+            s: wrong_s,
+            challenge_token: String::new(),
+        })
+        .await
+        .expect_err("elevated with a wrong proof");
+    assert_eq!(status.code(), tonic::Code::PermissionDenied);
+
+    let (elevate_auth_id, elevate_s) = answer(&mut client, &zkp, &user, &x).await;
+    let request = ElevateSessionRequest {
+        user: user.clone(),
+        session_id: session_id.clone(),
+        auth_id: elevate_auth_id,
+        s: elevate_s,
+        challenge_token: String::new(),
+    };
+    let until = client
+        .elevate_session(request.clone())
+        .await
+        .expect("elevation failed")
+        .into_inner()
+        .elevated_until_unix_millis;
+    assert!(until > rust_zkp_chaum_pedersen::clock::unix_millis(std::time::SystemTime::now()));
+    let sessions = client.list_sessions(list).await.expect("listing failed").into_inner().sessions;
+    assert_eq!(sessions[0].elevated_until_unix_millis, until);
+
+    // each proof elevates once
+    let status = client.elevate_session(request).await.expect_err("one proof elevated twice");
+    assert_eq!(status.code(), tonic::Code::NotFound);
+
+    println!("🎉 Session elevation test PASSED!");
+}
+
+#[tokio::test]
+async fn test_id_token_needs_an_issuer() {
+    use rust_zkp_chaum_pedersen::api::v1::IdTokenRequest;
+
+    println!("🧪 Testing ID tokens on a server without an issuer...");
+
+    let mut client = match AuthClient::connect("http://127.0.0.1:50051").await {
+        Ok(client) => client,
+        Err(_) => {
+            println!("⚠️  Server not running - skipping ID token test");
+            return;
+        }
+    };
+
+    // refused before the answer is even looked at
+    let id_token = IdTokenRequest { audience: "shop".to_string(), nonce: "n".to_string() };
+    let status = client
+        .verify_authentication(AuthenticationAnswerRequest {
+            auth_id: "made-up".to_string(),
+            s: vec![1],
+            id_token: Some(id_token),
+            ssh_public_key: String::new(),
+            pow_nonce: 0,
+            challenge_token: String::new(),
+        })
+        .await
+        .expect_err("minted an ID token without an issuer");
+    assert_eq!(status.code(), tonic::Code::Unimplemented);
+
+    println!("🎉 ID token test PASSED!");
+}
+
+#[tokio::test]
+async fn test_pam_helper() {
+    use std::io::Write;
+    use std::process::Stdio;
+
+    println!("🧪 Testing the pam-zkp helper...");
+
+    let mut client = match AuthClient::connect("http://127.0.0.1:50051").await {
+        Ok(client) => client,
+        Err(_) => {
+            println!("⚠️  Server not running - skipping pam-zkp test");
+            return;
+        }
+    };
+
+    let zkp = ZKP::builder().rfc5114().build().unwrap();
+    let user = format!("pam_{}", ZKP::generate_random_string(8));
+    let x = rust_zkp_chaum_pedersen::kdf::legacy_derive("correct horse");
+    let (y1, y2) = zkp.public_key(&x).to_bytes_be();
+    client
+        .register(RegisterRequest { user: user.clone(), y1, y2, ..Default::default() })
+        .await
+        .expect("registration failed");
+
+    // what pam_exec with expose_authtok does: PAM_USER and a NUL-terminated password
+    let pam_login = |password: &str| {
+        let mut helper = Command::new(env!("CARGO_BIN_EXE_pam-zkp"))
+            .env("PAM_USER", &user)
+            .stdin(Stdio::piped())
+            .spawn()
+            .expect("could not start pam-zkp");
+        helper.stdin.take().unwrap().write_all(format!("{}\0", password).as_bytes()).unwrap();
+        helper.wait().expect("pam-zkp did not finish").code()
+    };
+    assert_eq!(pam_login("correct horse"), Some(0));
+    assert_eq!(pam_login("battery staple"), Some(1));
+
+    println!("🎉 pam-zkp test PASSED!");
+}
+
+#[tokio::test]
+async fn test_ssh_certificate_needs_a_ca() {
+    println!("🧪 Testing SSH certificates on a server without a CA...");
+
+    let mut client = match AuthClient::connect("http://127.0.0.1:50051").await {
+        Ok(client) => client,
+        Err(_) => {
+            println!("⚠️  Server not running - skipping SSH certificate test");
+            return;
+        }
+    };
+
+    let status = client
+        .verify_authentication(AuthenticationAnswerRequest {
+            auth_id: "made-up".to_string(),
+            s: vec![1],
+            id_token: None,
+            ssh_public_key: "ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIOsfjNggIayIMyfM12uCVDyb+weQIfz9F/dqlk7KXGbf".to_string(),
+            pow_nonce: 0,
+            challenge_token: String::new(),
+        })
+        .await
+        .expect_err("signed an SSH key without a CA");
+    assert_eq!(status.code(), tonic::Code::Unimplemented);
+
+    println!("🎉 SSH certificate test PASSED!");
+}
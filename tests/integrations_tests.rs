@@ -0,0 +1,160 @@
+// Tests for the axum middleware (cargo test --features axum)
+#![cfg(feature = "axum")]
+
+use std::process::{Command, Stdio};
+
+use axum::body::Body;
+use axum::http::{header, HeaderMap, HeaderValue, Request, StatusCode};
+use axum::routing::get;
+use axum::{middleware, Extension, Router};
+use rust_zkp_chaum_pedersen::admin::AdminToken;
+use rust_zkp_chaum_pedersen::api::v1::{
+    auth_client::AuthClient, AuthenticationAnswerRequest, AuthenticationChallengeRequest, RegisterRequest,
+};
+use rust_zkp_chaum_pedersen::http_status::{StatusMapping, PROBLEM_JSON};
+use rust_zkp_chaum_pedersen::integrations::{bearer_token, require_session, SessionValidator, ZkpUser};
+use rust_zkp_chaum_pedersen::types::Challenge;
+use rust_zkp_chaum_pedersen::ZKP;
+use tonic::transport::Channel;
+use tower::ServiceExt;
+
+mod common;
+use common::{connect, Server};
+
+/// a route that answers 200 only for `user`
+fn app(validator: SessionValidator, user: &str) -> Router {
+    let expected = user.to_string();
+    Router::new()
+        .route(
+            "/me",
+            get(move |Extension(found): Extension<ZkpUser>| async move {
+                match found.username == expected {
+                    true => StatusCode::OK,
+                    false => StatusCode::CONFLICT,
+                }
+            }),
+        )
+        .route_layer(middleware::from_fn_with_state(validator, require_session))
+}
+
+fn get_me(token: Option<&str>) -> Request<Body> {
+    let mut request = Request::builder().uri("/me");
+    if let Some(token) = token {
+        request = request.header(header::AUTHORIZATION, format!("Bearer {}", token));
+    }
+    request.body(Body::empty()).unwrap()
+}
+
+#[test]
+fn test_bearer_token_parsing() {
+    println!("🔑 Testing bearer token parsing");
+
+    let mut headers = HeaderMap::new();
+    assert_eq!(bearer_token(&headers), None);
+    headers.insert(header::AUTHORIZATION, HeaderValue::from_static("Bearer abc123"));
+    assert_eq!(bearer_token(&headers), Some("abc123"));
+    headers.insert(header::AUTHORIZATION, HeaderValue::from_static("bearer  abc123 "));
+    assert_eq!(bearer_token(&headers), Some("abc123"));
+    headers.insert(header::AUTHORIZATION, HeaderValue::from_static("Basic YWxpY2U6cHc="));
+    assert_eq!(bearer_token(&headers), None);
+
+    println!("✅ Bearer tokens are found!");
+}
+
+#[tokio::test]
+async fn test_refusals_are_problems() {
+    println!("🧪 Testing how the middleware refuses requests...");
+
+    let channel = Channel::from_static("http://127.0.0.1:9").connect_lazy();
+    let response = app(SessionValidator::new(channel.clone()), "nobody").oneshot(get_me(None)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    assert_eq!(response.headers()[header::CONTENT_TYPE], PROBLEM_JSON);
+    assert_eq!(response.headers()[header::WWW_AUTHENTICATE], "Bearer");
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    let problem: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!((problem["status"].as_u64(), problem["grpc_status"].as_str()), (Some(401), Some("Unauthenticated")));
+
+    // nobody to ask: the client may try again, it's not their fault
+    let response = app(SessionValidator::new(channel.clone()), "nobody").oneshot(get_me(Some("abc"))).await.unwrap();
+    assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(response.headers()[header::RETRY_AFTER], "1");
+    let validator = SessionValidator::new(channel).status_mapping(StatusMapping::default().with_override("Unavailable=502".parse().unwrap()));
+    assert_eq!(validator.validate("abc").await, Err(StatusCode::BAD_GATEWAY));
+
+    println!("✅ Refusals come as problem+json!");
+}
+
+#[tokio::test]
+async fn test_routes_need_a_session() {
+    println!("🧪 Testing the session middleware...");
+
+    // no token: refused without asking the server
+    let channel = Channel::from_static("http://127.0.0.1:50051").connect_lazy();
+    let response = app(SessionValidator::new(channel), "nobody").oneshot(get_me(None)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    // the middleware introspects with the server's admin token
+    let token_file = std::env::temp_dir().join(format!("zkp-integrations-admin-token-{}", std::process::id()));
+    std::fs::write(&token_file, "integrations-admin-token").unwrap();
+    let token = AdminToken::load(&token_file).unwrap();
+    let port = 50115;
+    let _server = Server(
+        Command::new(env!("CARGO_BIN_EXE_server"))
+            .args(["--listen", &format!("127.0.0.1:{}", port)])
+            .arg("--admin-token-file")
+            .arg(&token_file)
+            .stdout(Stdio::null())
+            .spawn()
+            .expect("Failed to start server process"),
+    );
+    let channel = connect(port).await;
+    let mut client = AuthClient::new(channel.clone());
+
+    let zkp = ZKP::builder().rfc5114().build().unwrap();
+    let user = format!("web_{}", ZKP::generate_random_string(8));
+    let x = ZKP::generate_random_number_below(&zkp.q);
+    let (y1, y2) = zkp.public_key(&x).to_bytes_be();
+    client
+        .register(RegisterRequest { user: user.clone(), y1, y2, ..Default::default() })
+        .await
+        .expect("registration failed");
+    let k = ZKP::generate_random_number_below(&zkp.q);
+    let (r1, r2) = zkp.commitment(&k).to_bytes_be();
+    let challenge = client
+        .create_authentication_challenge(AuthenticationChallengeRequest {
+            user: user.clone(),
+            r1,
+            r2,
+            auth_id: String::new(),
+            client: None,
+            device_id: String::new(),
+        })
+        .await
+        .expect("challenge failed")
+        .into_inner();
+    let s = zkp.respond(&k, &Challenge::from_bytes_be(&challenge.c), &x).to_bytes_be();
+    let session_id = client
+        .verify_authentication(AuthenticationAnswerRequest { auth_id: challenge.auth_id, s, id_token: None, ssh_public_key: String::new(), pow_nonce: 0, challenge_token: String::new() })
+        .await
+        .expect("login failed")
+        .into_inner()
+        .session_id;
+
+    // without the token the server won't say, so the middleware can't either
+    let response = app(SessionValidator::new(channel.clone()), &user).oneshot(get_me(Some(&session_id))).await.unwrap();
+    assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+    let validator = SessionValidator::new(channel).admin_token(token);
+    let response = app(validator.clone(), &user).oneshot(get_me(Some(&session_id))).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = app(validator.clone(), &user).oneshot(get_me(Some("made-up"))).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    // a fresh login is a standard session
+    let response = app(validator.require_elevated(), &user).oneshot(get_me(Some(&session_id))).await.unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    let _ = std::fs::remove_file(&token_file);
+    println!("🎉 Session middleware test PASSED!");
+}
@@ -0,0 +1,269 @@
+// Tests for the network and country rules requests are filtered by, and the GeoIP lookups behind them
+use std::net::{IpAddr, SocketAddr};
+use std::process::{Command, Stdio};
+use std::time::Duration;
+
+use rust_zkp_chaum_pedersen::geoip::GeoIp;
+use rust_zkp_chaum_pedersen::ip_filter::{rpc_of, Cidr, IpFilter, Rule, Source};
+
+mod common;
+use common::Server;
+
+fn ip(s: &str) -> IpAddr {
+    s.parse().unwrap()
+}
+
+fn peer(s: &str) -> Option<SocketAddr> {
+    Some(SocketAddr::new(ip(s), 50000))
+}
+
+fn rules(rules: &[&str]) -> Vec<Rule> {
+    rules.iter().map(|rule| rule.parse().unwrap()).collect()
+}
+
+/// the data section encoding of a string, a map header, a pointer and a uint
+fn string(s: &str) -> Vec<u8> {
+    [vec![0x40 | s.len() as u8], s.as_bytes().to_vec()].concat()
+}
+
+fn map(entries: u8) -> u8 {
+    0xe0 | entries
+}
+
+fn pointer(offset: usize) -> Vec<u8> {
+    vec![0x20 | (offset >> 8) as u8, offset as u8]
+}
+
+fn uint(kind: u8, bytes: &[u8]) -> Vec<u8> {
+    [vec![kind << 5 | bytes.len() as u8], bytes.to_vec()].concat()
+}
+
+/// a GeoLite2-Country-like database: an IPv6 tree with 24-bit records
+fn mmdb(networks: &[(&str, Vec<u8>)]) -> Vec<u8> {
+    #[derive(Clone, Copy)]
+    enum Slot {
+        Empty,
+        Node(usize),
+        Data(usize),
+    }
+    let mut nodes = vec![[Slot::Empty; 2]];
+    let mut data: Vec<u8> = Vec::new();
+    for (network, record) in networks {
+        let cidr: Cidr = network.parse().unwrap();
+        let (bits, prefix) = match cidr.network {
+            IpAddr::V4(v4) => (u32::from(v4) as u128, 96 + cidr.prefix as usize),
+            IpAddr::V6(v6) => (u128::from(v6), cidr.prefix as usize),
+        };
+        let mut node = 0;
+        for i in 0..prefix {
+            let bit = ((bits >> (127 - i)) & 1) as usize;
+            if i == prefix - 1 {
+                nodes[node][bit] = Slot::Data(data.len());
+                break;
+            }
+            node = match nodes[node][bit] {
+                Slot::Node(next) => next,
+                _ => {
+                    nodes.push([Slot::Empty; 2]);
+                    nodes[node][bit] = Slot::Node(nodes.len() - 1);
+                    nodes.len() - 1
+                }
+            };
+        }
+        data.extend(record);
+    }
+    let count = nodes.len();
+    let mut file = Vec::new();
+    for slot in nodes.iter().flatten() {
+        let value = match *slot {
+            Slot::Empty => count,
+            Slot::Node(next) => next,
+            Slot::Data(offset) => count + 16 + offset,
+        };
+        file.extend(&(value as u32).to_be_bytes()[1..]);
+    }
+    file.extend([0; 16]);
+    file.extend(data);
+    file.extend(b"\xAB\xCD\xEFMaxMind.com");
+    file.push(map(4));
+    for (key, value) in [
+        ("node_count", uint(6, &(count as u32).to_be_bytes())),
+        ("record_size", uint(5, &[0, 24])),
+        ("ip_version", uint(5, &[0, 6])),
+        ("database_type", string("Test-Country")),
+    ] {
+        file.extend(string(key));
+        file.extend(value);
+    }
+    file
+}
+
+/// where countries are: DE and FR by country, JP only by registration
+fn test_database() -> Vec<u8> {
+    let de = [vec![map(1)], string("country"), vec![map(1)], string("iso_code"), string("DE")].concat();
+    // FR's iso_code key points back into DE's record
+    let key = de.len() - string("DE").len() - string("iso_code").len();
+    let fr = [vec![map(1)], string("country"), vec![map(1)], pointer(key), string("FR")].concat();
+    let jp = [vec![map(1)], string("registered_country"), vec![map(1)], string("iso_code"), string("JP")].concat();
+    mmdb(&[("192.0.2.0/24", de), ("2001:db8::/32", fr), ("198.51.100.0/25", jp)])
+}
+
+#[test]
+fn test_rules() {
+    println!("🧱 Testing network and country rules");
+
+    let cidr: Cidr = "10.0.0.0/8".parse().unwrap();
+    assert!(cidr.contains(ip("10.255.0.1")) && !cidr.contains(ip("11.0.0.1")));
+    assert!(cidr.contains(ip("::ffff:10.1.2.3")), "IPv4 peers on a dual-stack socket");
+    assert!(!cidr.contains(ip("2001:db8::1")));
+    let v6: Cidr = "2001:db8::/32".parse().unwrap();
+    assert!(v6.contains(ip("2001:db8:ffff::1")) && !v6.contains(ip("2001:db9::1")));
+    assert!("0.0.0.0/0".parse::<Cidr>().unwrap().contains(ip("203.0.113.9")));
+    assert_eq!("192.0.2.7".parse::<Cidr>().unwrap().to_string(), "192.0.2.7/32", "an address is a /32");
+    assert!("10.1.2.3/8".parse::<Cidr>().unwrap_err().contains("past the prefix"));
+    assert!("10.0.0.0/33".parse::<Cidr>().is_err());
+    assert!("corp.example.com/24".parse::<Cidr>().is_err());
+
+    assert_eq!("country:de".parse::<Source>(), Ok(Source::Country("DE".to_string())));
+    assert!("country:Germany".parse::<Source>().is_err());
+    let rule: Rule = "Admin/*=192.168.0.0/16, country:DE".parse().unwrap();
+    assert_eq!(rule.rpc.as_deref(), Some("Admin/*"));
+    assert_eq!(rule.sources.len(), 2);
+    assert!("=10.0.0.0/8".parse::<Rule>().is_err());
+
+    assert_eq!(rpc_of("/zkp_auth.v1.Auth/Register"), "Auth/Register");
+    assert_eq!(rpc_of("/grpc.health.v1.Health/Check"), "Health/Check");
+    let for_rpc = |pattern: &str, rpc: &str| format!("{}=10.0.0.0/8", pattern).parse::<Rule>().unwrap().applies_to(rpc);
+    assert!(for_rpc("Admin/*", "Admin/ListUsers") && !for_rpc("Admin/*", "Auth/Register"));
+    assert!(for_rpc("Register", "Auth/Register") && !for_rpc("Register", "Auth/RegisterDevice"));
+    assert!(for_rpc("Auth/Register", "Auth/Register") && !for_rpc("Auth/Register", "Admin/Register"));
+    assert!("10.0.0.0/8".parse::<Rule>().unwrap().applies_to("Health/Check"), "no RPC, every RPC");
+
+    // the admin service only from the office, and never from one bad host
+    let filter = IpFilter {
+        allow: rules(&["Admin/*=192.168.0.0/16,10.0.0.0/8"]),
+        deny: rules(&["10.6.6.6"]),
+        geoip: None,
+    };
+    assert_eq!(filter.check("Auth/Register", peer("203.0.113.9")), Ok(()), "no allow rule for Register");
+    assert_eq!(filter.check("Admin/ListUsers", peer("10.0.0.7")), Ok(()));
+    let outsider = filter.check("Admin/ListUsers", peer("203.0.113.9")).unwrap_err();
+    assert_eq!((outsider.rpc.as_str(), outsider.source), ("Admin/ListUsers", peer("203.0.113.9")));
+    assert_eq!(outsider.reason, "not on the allow list");
+    let denied = filter.check("Auth/Register", peer("10.6.6.6")).unwrap_err();
+    assert_eq!(denied.reason, "denied by 10.6.6.6");
+    assert!(filter.check("Admin/ListUsers", peer("10.6.6.6")).is_err(), "deny wins over allow");
+    assert!(filter.check("Admin/ListUsers", None).is_err(), "unknown peers don't get through");
+    let office = IpFilter { allow: rules(&["Admin/*=10.0.0.0/8"]), ..Default::default() };
+    assert_eq!(office.check("Auth/Login", None), Ok(()), "unless no rule is about the RPC");
+    assert!(!filter.needs_geoip() && IpFilter::default().is_empty());
+
+    println!("✅ Rules decide who may call what!");
+}
+
+#[test]
+fn test_geoip() {
+    println!("🌍 Testing country lookups in a MaxMind database");
+
+    let geoip = GeoIp::from_bytes(test_database()).unwrap();
+    assert_eq!(geoip.database_type, "Test-Country");
+    assert_eq!(geoip.country(ip("192.0.2.77")).as_deref(), Some("DE"));
+    assert_eq!(geoip.country(ip("::ffff:192.0.2.1")).as_deref(), Some("DE"));
+    assert_eq!(geoip.country(ip("2001:db8:1::1")).as_deref(), Some("FR"), "through a pointer");
+    assert_eq!(geoip.country(ip("198.51.100.1")).as_deref(), Some("JP"), "registered country");
+    assert_eq!(geoip.country(ip("198.51.100.200")), None, "outside the /25");
+    assert_eq!(geoip.country(ip("203.0.113.9")), None);
+    assert_eq!(geoip.lookup(ip("127.0.0.1")), Ok(None));
+    assert_eq!(geoip.lookup(ip("192.0.2.1")).unwrap().unwrap()["country"]["iso_code"], "DE");
+
+    assert!(GeoIp::from_bytes(b"not a database".to_vec()).unwrap_err().contains("no metadata"));
+    let mut truncated = test_database();
+    truncated.truncate(truncated.len() - 5);
+    assert!(GeoIp::from_bytes(truncated).is_err());
+
+    let filter = IpFilter { allow: rules(&["country:DE,country:JP"]), deny: rules(&["Register=country:FR"]), geoip: Some(geoip) };
+    assert!(filter.needs_geoip());
+    assert_eq!(filter.check("Auth/Login", peer("192.0.2.1")), Ok(()));
+    let elsewhere = filter.check("Auth/Login", peer("2001:db8::1")).unwrap_err();
+    assert_eq!(elsewhere.country.as_deref(), Some("FR"));
+    assert!(filter.check("Auth/Login", peer("203.0.113.9")).is_err(), "no country, no match");
+
+    println!("✅ Addresses have countries!");
+}
+
+#[tokio::test]
+async fn test_server_filters() {
+    use rust_zkp_chaum_pedersen::api::v1::{auth_client::AuthClient, RegisterRequest, ServerTimeRequest};
+    use tonic::Code;
+    use tonic_health::pb::{health_client::HealthClient, HealthCheckRequest};
+
+    println!("🧱 Testing a server that only takes registrations from some networks");
+
+    let dir = std::env::temp_dir().join(format!("zkp-ip-filter-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let database = dir.join("countries.mmdb");
+    std::fs::write(&database, test_database()).unwrap();
+    let audit_log = dir.join("audit.jsonl");
+
+    // a country rule needs a database
+    let status = Command::new(env!("CARGO_BIN_EXE_server"))
+        .args(["--listen", "127.0.0.1:50107", "--allow-from", "country:DE"])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .unwrap();
+    assert!(!status.success());
+
+    let _server = Server(
+        Command::new(env!("CARGO_BIN_EXE_server"))
+            .args(["--listen", "127.0.0.1:50107", "--allow-from", "Auth/Register=10.0.0.0/8,country:DE"])
+            .args(["--deny-from", "Health/*=127.0.0.0/8"])
+            .arg("--geoip-db")
+            .arg(&database)
+            .arg("--audit-log")
+            .arg(&audit_log)
+            .stdout(Stdio::null())
+            .spawn()
+            .expect("Failed to start server process"),
+    );
+    let mut client = None;
+    for _ in 0..50 {
+        if let Ok(connected) = AuthClient::connect("http://127.0.0.1:50107").await {
+            client = Some(connected);
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    let mut client = client.expect("the test server didn't come up");
+
+    let register = RegisterRequest { user: "filtered_alice".to_string(), y1: vec![2], y2: vec![3], ..Default::default() };
+    let refused = client.register(register).await.unwrap_err();
+    assert_eq!(refused.code(), Code::PermissionDenied, "{}", refused.message());
+    client.get_server_time(ServerTimeRequest::default()).await.expect("no rule for GetServerTime");
+    let mut health = HealthClient::new(tonic::transport::Endpoint::from_static("http://127.0.0.1:50107").connect().await.unwrap());
+    let checked = health.check(HealthCheckRequest { service: String::new() }).await.unwrap_err();
+    assert_eq!(checked.code(), Code::PermissionDenied);
+
+    // both refusals are in the audit trail, with the peer and the reason
+    let mut events: Vec<serde_json::Value> = Vec::new();
+    for _ in 0..50 {
+        events = std::fs::read_to_string(&audit_log)
+            .unwrap_or_default()
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+            .filter(|event| event["action"] == "blocked_request")
+            .collect();
+        if events.len() == 2 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    assert_eq!(events.len(), 2, "{:?}", events);
+    assert_eq!(events[0]["outcome"], "failure");
+    assert!(events[0]["source"].as_str().unwrap().starts_with("127.0.0.1:"));
+    assert_eq!(events[0]["detail"], "Auth/Register: not on the allow list");
+    assert_eq!(events[1]["detail"], "Health/Check: denied by Health/*=127.0.0.0/8");
+
+    std::fs::remove_dir_all(&dir).unwrap();
+    println!("✅ Requests from elsewhere are turned away and audited!");
+}
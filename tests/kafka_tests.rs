@@ -0,0 +1,233 @@
+// Tests for publishing audit events to Kafka, against a fake broker
+#![cfg(feature = "kafka")]
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+
+use rust_zkp_chaum_pedersen::kafka::{crc32c, Kafka};
+use rust_zkp_chaum_pedersen::publish::{Message, Transport};
+
+fn message(user: &str) -> Message {
+    Message { key: user.to_string(), action: "login", payload: format!("{{\"subject\":\"{}\"}}", user) }
+}
+
+/// A record as the fake broker took it apart
+#[derive(Debug, PartialEq)]
+struct Record {
+    partition: i32,
+    key: Option<String>,
+    value: String,
+    headers: Vec<(String, String)>,
+}
+
+struct Bytes<'a>(&'a [u8]);
+
+impl<'a> Bytes<'a> {
+    fn take(&mut self, n: usize) -> &'a [u8] {
+        let (taken, rest) = self.0.split_at(n);
+        self.0 = rest;
+        taken
+    }
+    fn i16(&mut self) -> i16 {
+        i16::from_be_bytes(self.take(2).try_into().unwrap())
+    }
+    fn i32(&mut self) -> i32 {
+        i32::from_be_bytes(self.take(4).try_into().unwrap())
+    }
+    fn i64(&mut self) -> i64 {
+        i64::from_be_bytes(self.take(8).try_into().unwrap())
+    }
+    fn string(&mut self) -> String {
+        let len = self.i16() as usize;
+        String::from_utf8(self.take(len).to_vec()).unwrap()
+    }
+    fn varint(&mut self) -> i64 {
+        let (mut value, mut shift) = (0u64, 0);
+        loop {
+            let byte = self.take(1)[0];
+            value |= ((byte & 0x7f) as u64) << shift;
+            shift += 7;
+            if byte & 0x80 == 0 {
+                return (value >> 1) as i64 ^ -((value & 1) as i64);
+            }
+        }
+    }
+    fn varbytes(&mut self) -> Option<String> {
+        let len = self.varint();
+        (len >= 0).then(|| String::from_utf8(self.take(len as usize).to_vec()).unwrap())
+    }
+}
+
+/// the records in a record batch, checking its CRC and header
+fn records(partition: i32, batch: &[u8]) -> Vec<Record> {
+    let mut bytes = Bytes(batch);
+    assert_eq!(bytes.i64(), 0, "base offset");
+    assert_eq!(bytes.i32() as usize, batch.len() - 12, "batch length");
+    bytes.i32(); // leader epoch
+    assert_eq!(bytes.take(1), [2], "magic");
+    let crc = bytes.i32() as u32;
+    assert_eq!(crc, crc32c(bytes.0), "the CRC covers the rest of the batch");
+    assert_eq!(bytes.i16(), 0, "attributes");
+    let last_offset_delta = bytes.i32();
+    let (first, max) = (bytes.i64(), bytes.i64());
+    assert!(first > 0 && first == max);
+    assert_eq!((bytes.i64(), bytes.i16(), bytes.i32()), (-1, -1, -1), "no idempotence");
+    let count = bytes.i32();
+    assert_eq!(last_offset_delta, count - 1);
+    let mut records = Vec::new();
+    for offset in 0..count as i64 {
+        let len = bytes.varint() as usize;
+        let mut record = Bytes(bytes.take(len));
+        assert_eq!(record.take(1), [0]);
+        assert_eq!((record.varint(), record.varint()), (0, offset));
+        let key = record.varbytes();
+        let value = record.varbytes().unwrap();
+        let headers = (0..record.varint()).map(|_| (record.varbytes().unwrap(), record.varbytes().unwrap())).collect();
+        assert!(record.0.is_empty());
+        records.push(Record { partition, key, value, headers });
+    }
+    assert!(bytes.0.is_empty());
+    records
+}
+
+/// a one-broker cluster on `listener` with `partitions` partitions of `topic`; answers
+/// `requests` requests, then hands back the produced records; produce requests fail with `produce_error`
+fn broker(listener: TcpListener, topic: &'static str, partitions: i32, produce_error: i16, requests: usize) -> thread::JoinHandle<Vec<Record>> {
+    let port = listener.local_addr().unwrap().port() as i32;
+    thread::spawn(move || {
+        let mut produced = Vec::new();
+        let mut served = 0;
+        while served < requests {
+            let (mut stream, _): (TcpStream, _) = listener.accept().unwrap();
+            while served < requests {
+                let mut size = [0; 4];
+                if stream.read_exact(&mut size).is_err() {
+                    break;
+                }
+                let mut request = vec![0; i32::from_be_bytes(size) as usize];
+                stream.read_exact(&mut request).unwrap();
+                let mut bytes = Bytes(&request);
+                let (api_key, api_version, correlation_id) = (bytes.i16(), bytes.i16(), bytes.i32());
+                assert_eq!(bytes.string(), "zkp-auth");
+                let mut response = correlation_id.to_be_bytes().to_vec();
+                match (api_key, api_version) {
+                    (3, 4) => {
+                        assert_eq!((bytes.i32(), bytes.string().as_str()), (1, topic));
+                        response.extend(0i32.to_be_bytes()); // throttle
+                        response.extend(1i32.to_be_bytes());
+                        response.extend(7i32.to_be_bytes()); // node id
+                        response.extend(9i16.to_be_bytes());
+                        response.extend(b"127.0.0.1");
+                        response.extend(port.to_be_bytes());
+                        response.extend((-1i16).to_be_bytes()); // rack
+                        response.extend((-1i16).to_be_bytes()); // cluster id
+                        response.extend(7i32.to_be_bytes()); // controller
+                        response.extend(1i32.to_be_bytes());
+                        response.extend(0i16.to_be_bytes());
+                        response.extend((topic.len() as i16).to_be_bytes());
+                        response.extend(topic.as_bytes());
+                        response.push(0);
+                        response.extend(partitions.to_be_bytes());
+                        for partition in (0..partitions).rev() {
+                            response.extend(0i16.to_be_bytes());
+                            response.extend(partition.to_be_bytes());
+                            response.extend(7i32.to_be_bytes()); // leader
+                            for _ in 0..2 {
+                                response.extend(1i32.to_be_bytes());
+                                response.extend(7i32.to_be_bytes());
+                            }
+                        }
+                    }
+                    (0, 3) => {
+                        assert_eq!((bytes.i16(), bytes.i16()), (-1, -1), "no transaction, acks=all");
+                        bytes.i32();
+                        assert_eq!((bytes.i32(), bytes.string().as_str()), (1, topic));
+                        let count = bytes.i32();
+                        response.extend(1i32.to_be_bytes());
+                        response.extend((topic.len() as i16).to_be_bytes());
+                        response.extend(topic.as_bytes());
+                        response.extend(count.to_be_bytes());
+                        for _ in 0..count {
+                            let partition = bytes.i32();
+                            let len = bytes.i32() as usize;
+                            produced.extend(records(partition, bytes.take(len)));
+                            response.extend(partition.to_be_bytes());
+                            response.extend(produce_error.to_be_bytes());
+                            response.extend(0i64.to_be_bytes());
+                            response.extend((-1i64).to_be_bytes());
+                        }
+                        response.extend(0i32.to_be_bytes()); // throttle
+                    }
+                    other => panic!("unexpected request {:?}", other),
+                }
+                stream.write_all(&(response.len() as i32).to_be_bytes()).unwrap();
+                stream.write_all(&response).unwrap();
+                served += 1;
+            }
+        }
+        produced
+    })
+}
+
+#[test]
+fn test_crc32c() {
+    println!("🗞️  Testing CRC-32C and partitioning");
+
+    assert_eq!(crc32c(b"123456789"), 0xE306_9283);
+    assert_eq!(crc32c(b""), 0);
+    assert_eq!(Kafka::partition_of("alice", 6), Kafka::partition_of("alice", 6), "a user always goes to the same partition");
+    assert!((0..100).map(|i| Kafka::partition_of(&format!("user{}", i), 6)).all(|partition| partition < 6));
+    assert_eq!(Kafka::partition_of("alice", 1), 0);
+
+    println!("✅ Checksums and partitions as Kafka expects!");
+}
+
+#[test]
+fn test_kafka_produce() {
+    println!("🗞️  Testing audit events produced to a Kafka topic");
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let broker = broker(listener, "zkp-auth.audit", 3, 0, 3);
+
+    let mut kafka = Kafka::new(vec![address.to_string()], "zkp-auth.audit");
+    kafka.publish(&[message("alice"), message("bob"), message("alice")]).unwrap();
+    kafka.publish(&[message("")]).unwrap();
+    let mut produced = broker.join().unwrap();
+
+    // keyed by user, a user's events in order in their partition
+    produced.sort_by_key(|record| record.partition);
+    assert_eq!(produced.len(), 4);
+    for record in &produced {
+        let partition = Kafka::partition_of(record.key.as_deref().unwrap_or(""), 3) as i32;
+        assert_eq!(record.partition, partition);
+        assert_eq!(record.headers, [("content-type".to_string(), "application/cloudevents+json".to_string())]);
+    }
+    let alice: Vec<&Record> = produced.iter().filter(|record| record.key.as_deref() == Some("alice")).collect();
+    assert_eq!(alice.len(), 2);
+    assert_eq!(alice[0].value, r#"{"subject":"alice"}"#);
+    assert!(produced.iter().any(|record| record.key.is_none()), "an event without a user has no key");
+
+    println!("✅ Audit events reach Kafka!");
+}
+
+#[test]
+fn test_kafka_errors() {
+    println!("🗞️  Testing what a Kafka broker can refuse");
+
+    // NOT_ENOUGH_REPLICAS: an error, and the metadata is asked for again next time
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let broker = broker(listener, "audit", 1, 19, 4);
+    let mut kafka = Kafka::new(vec![address.to_string()], "audit");
+    let error = kafka.publish(&[message("alice")]).unwrap_err();
+    assert!(error.to_string().contains("not enough replicas"), "{}", error);
+    assert!(kafka.publish(&[message("alice")]).is_err());
+    assert_eq!(broker.join().unwrap().len(), 2, "tried again from the metadata on");
+
+    // no broker at all
+    let mut kafka = Kafka::new(vec![address.to_string()], "audit");
+    assert!(kafka.publish(&[message("alice")]).is_err());
+
+    println!("✅ Refusals are errors, to be retried!");
+}
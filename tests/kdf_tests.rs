@@ -0,0 +1,46 @@
+// Tests for deriving the secret x from a password with Argon2id
+use rust_zkp_chaum_pedersen::kdf::{KdfParams, MAX_MEMORY_KIB};
+use rust_zkp_chaum_pedersen::ZKP;
+
+/// cheap parameters, the real cost makes debug test runs crawl
+fn cheap() -> KdfParams {
+    KdfParams::with_cost(256, 1, 1)
+}
+
+#[test]
+fn test_same_params_rederive_same_secret() {
+    println!("🧂 Testing Argon2id derivation");
+
+    let (_, _, _, q) = ZKP::get_constants();
+    let params = cheap();
+
+    // a new device with the stored parameters gets exactly the same x
+    let x = params.derive("correct horse battery staple", &q).unwrap();
+    let again = params.clone().derive("correct horse battery staple", &q).unwrap();
+    assert_eq!(x, again);
+    assert!(x < q);
+
+    assert_ne!(params.derive("correct horse battery stable", &q).unwrap(), x);
+    let other_salt = cheap();
+    assert_ne!(other_salt.salt, params.salt);
+    assert_ne!(other_salt.derive("correct horse battery staple", &q).unwrap(), x);
+
+    println!("✅ Argon2id derivation test passed!");
+}
+
+#[test]
+fn test_unreasonable_params_are_rejected() {
+    let (_, _, _, q) = ZKP::get_constants();
+
+    let short_salt = KdfParams { salt: vec![1; 4], ..cheap() };
+    assert!(short_salt.validate().is_err());
+
+    // a malicious server must not be able to make the client allocate gigabytes
+    let huge = KdfParams { memory_kib: MAX_MEMORY_KIB + 1, ..cheap() };
+    assert!(huge.derive("password", &q).is_err());
+
+    let no_passes = KdfParams { iterations: 0, ..cheap() };
+    assert!(no_passes.validate().is_err());
+
+    assert!(KdfParams::generate().validate().is_ok());
+}
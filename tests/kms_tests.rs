@@ -0,0 +1,264 @@
+// Tests for signing ID tokens in a cloud KMS (cargo test --features kms), against a fake KMS
+#![cfg(feature = "kms")]
+
+use std::convert::Infallible;
+use std::process::{Command, Stdio};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use ed25519_dalek::{Signer, SigningKey, Verifier};
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Response, StatusCode};
+use serde_json::{json, Value};
+use tonic::transport::Channel;
+
+use rust_zkp_chaum_pedersen::api::v1::{
+    auth_client::AuthClient, AuthenticationAnswerRequest, AuthenticationAnswerResponse, AuthenticationChallengeRequest, IdTokenRequest, RegisterRequest,
+};
+use rust_zkp_chaum_pedersen::id_token;
+use rust_zkp_chaum_pedersen::kms::{self, AwsCredentials, GcpToken, KmsError, KmsKey, KmsSigner};
+use rust_zkp_chaum_pedersen::types::Challenge;
+use rust_zkp_chaum_pedersen::ZKP;
+
+mod common;
+use common::{connect, temp_dir, Server};
+
+const GCP_TOKEN: &str = "ya29.test-token";
+const GCP_KEY: &str = "projects/p/locations/global/keyRings/zkp/cryptoKeys/id-token/cryptoKeyVersions/1";
+const SPKI_PREFIX: [u8; 12] = [0x30, 0x2a, 0x30, 0x05, 0x06, 0x03, 0x2b, 0x65, 0x70, 0x03, 0x21, 0x00];
+
+/// The sign-only parts of both KMS APIs: AWS's GetPublicKey and Sign, and
+/// Cloud KMS's publicKey and asymmetricSign
+struct FakeKms {
+    key: SigningKey,
+    signs_with: SigningKey, // the key behind the signatures, normally the same
+    algorithm: &'static str, // "ed25519", or anything else for a key of the wrong kind
+    signatures: usize,
+}
+
+impl FakeKms {
+    fn new(key: SigningKey) -> Self {
+        FakeKms { signs_with: key.clone(), key, algorithm: "ed25519", signatures: 0 }
+    }
+
+    fn spki(&self) -> Vec<u8> {
+        [SPKI_PREFIX.as_slice(), self.key.verifying_key().as_bytes()].concat()
+    }
+
+    fn sign(&mut self, message: &str) -> String {
+        self.signatures += 1;
+        let message = STANDARD.decode(message).unwrap();
+        STANDARD.encode(self.signs_with.sign(&message).to_bytes())
+    }
+
+    fn answer(&mut self, method: &Method, path: &str, headers: &hyper::HeaderMap, body: &[u8]) -> (StatusCode, Value) {
+        let header = |name: &str| headers.get(name).and_then(|value| value.to_str().ok()).unwrap_or_default();
+        let body: Value = serde_json::from_slice(body).unwrap_or(Value::Null);
+        if let Some(target) = headers.get("x-amz-target") {
+            if !header("authorization").starts_with("AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/") {
+                return (StatusCode::BAD_REQUEST, json!({ "__type": "IncompleteSignatureException", "message": "no SigV4" }));
+            }
+            let spec = if self.algorithm == "ed25519" { "ECC_NIST_EDWARDS25519" } else { "ECC_NIST_P256" };
+            return match target.to_str().unwrap() {
+                "TrentService.GetPublicKey" => {
+                    (StatusCode::OK, json!({ "KeyId": body["KeyId"], "KeySpec": spec, "PublicKey": STANDARD.encode(self.spki()) }))
+                }
+                "TrentService.Sign" if body["SigningAlgorithm"] == "ED25519_SHA_512" => {
+                    let signature = self.sign(body["Message"].as_str().unwrap());
+                    (StatusCode::OK, json!({ "KeyId": body["KeyId"], "Signature": signature }))
+                }
+                _ => (StatusCode::BAD_REQUEST, json!({ "__type": "ValidationException", "message": "unexpected call" })),
+            };
+        }
+
+        if header("authorization") != format!("Bearer {}", GCP_TOKEN) {
+            return (StatusCode::UNAUTHORIZED, json!({ "error": { "message": "invalid credentials" } }));
+        }
+        let algorithm = if self.algorithm == "ed25519" { "EC_SIGN_ED25519" } else { "EC_SIGN_P256_SHA256" };
+        match (method, path.strip_prefix(&format!("/v1/{}", GCP_KEY))) {
+            (&Method::GET, Some("/publicKey")) => {
+                let pem = format!("-----BEGIN PUBLIC KEY-----\n{}\n-----END PUBLIC KEY-----\n", STANDARD.encode(self.spki()));
+                (StatusCode::OK, json!({ "pem": pem, "algorithm": algorithm }))
+            }
+            (&Method::POST, Some(":asymmetricSign")) => {
+                let signature = self.sign(body["data"].as_str().unwrap());
+                (StatusCode::OK, json!({ "signature": signature, "name": GCP_KEY }))
+            }
+            _ => (StatusCode::NOT_FOUND, json!({ "error": { "message": "no such key" } })),
+        }
+    }
+}
+
+/// serve `kms` on a free port, returning its address
+fn serve(kms: Arc<Mutex<FakeKms>>) -> String {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = format!("http://{}", listener.local_addr().unwrap());
+    let make_service = make_service_fn(move |_| {
+        let kms = kms.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request: hyper::Request<Body>| {
+                let kms = kms.clone();
+                async move {
+                    let (parts, body) = request.into_parts();
+                    let body = hyper::body::to_bytes(body).await.unwrap();
+                    let (status, body) = kms.lock().unwrap().answer(&parts.method, parts.uri.path(), &parts.headers, &body);
+                    let mut response = Response::new(Body::from(body.to_string()));
+                    *response.status_mut() = status;
+                    Ok::<_, Infallible>(response)
+                }
+            }))
+        }
+    });
+    tokio::spawn(hyper::Server::from_tcp(listener).unwrap().serve(make_service));
+    addr
+}
+
+fn credentials() -> AwsCredentials {
+    AwsCredentials {
+        access_key_id: "AKIDEXAMPLE".to_string(),
+        secret_access_key: "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY".to_string(),
+        session_token: None,
+    }
+}
+
+#[test]
+fn test_sigv4() {
+    println!("🔧 Testing AWS Signature Version 4 against AWS's get-vanilla example");
+
+    let headers = vec![
+        ("Host".to_string(), "example.amazonaws.com".to_string()),
+        ("X-Amz-Date".to_string(), "20150830T123600Z".to_string()),
+    ];
+    let authorization =
+        kms::sigv4_authorization("GET", "/", "", &headers, b"", &credentials(), "us-east-1", "service", "20150830T123600Z");
+    assert_eq!(
+        authorization,
+        "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20150830/us-east-1/service/aws4_request, \
+         SignedHeaders=host;x-amz-date, \
+         Signature=5fa00fa31553b73ebf1942676e86291e8372ff2a2260956d9b8aae1d763fbf31"
+    );
+
+    assert_eq!(kms::amz_date(UNIX_EPOCH + Duration::from_secs(1440938160)), "20150830T123600Z");
+    assert!(!format!("{:?}", credentials()).contains("EXAMPLEKEY"), "the secret key never shows up in logs");
+
+    println!("✅ SigV4 matches AWS's test suite!");
+}
+
+#[tokio::test]
+async fn test_signing_in_kms() {
+    println!("🔧 Testing signatures from AWS KMS and Cloud KMS against a fake KMS");
+
+    let key = SigningKey::from_bytes(&[5; 32]);
+    let fake = Arc::new(Mutex::new(FakeKms::new(key.clone())));
+    let addr = serve(fake.clone());
+    let dir = temp_dir("signing");
+    let token_file = dir.join("token");
+    std::fs::write(&token_file, format!("{}\n", GCP_TOKEN)).unwrap();
+
+    let aws = KmsKey::Aws {
+        key_id: "alias/zkp-id-token".to_string(),
+        region: "us-east-1".to_string(),
+        endpoint: Some(addr.clone()),
+        credentials: credentials(),
+    };
+    let gcp = KmsKey::Gcp { key_version: GCP_KEY.to_string(), endpoint: Some(addr.clone()), token: GcpToken::File(token_file.clone()) };
+
+    for key_in_kms in [aws.clone(), gcp.clone()] {
+        let signer = KmsSigner::connect(key_in_kms).await.unwrap();
+        assert_eq!(signer.public_key(), &key.verifying_key());
+        let signature = signer.sign(b"header.claims").await.unwrap();
+        key.verifying_key().verify(b"header.claims", &signature).unwrap();
+    }
+    assert_eq!(fake.lock().unwrap().signatures, 2);
+
+    // a signature from another key never makes it into a token
+    fake.lock().unwrap().signs_with = SigningKey::from_bytes(&[6; 32]);
+    let signer = KmsSigner::connect(gcp.clone()).await.unwrap();
+    assert!(matches!(signer.sign(b"header.claims").await, Err(KmsError::WrongKey(_))));
+
+    // keys that aren't Ed25519 are refused up front
+    fake.lock().unwrap().algorithm = "p256";
+    assert!(matches!(KmsSigner::connect(aws.clone()).await, Err(KmsError::WrongKey(_))));
+    assert!(matches!(KmsSigner::connect(gcp.clone()).await, Err(KmsError::WrongKey(_))));
+
+    // a bad access token is the KMS's answer, not a malformed one
+    std::fs::write(&token_file, "ya29.expired").unwrap();
+    let refused = KmsSigner::connect(gcp).await.unwrap_err();
+    assert_eq!(refused, KmsError::Refused { status: 401, message: "invalid credentials".to_string() });
+
+    let port = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+    let gone = KmsKey::Aws {
+        key_id: "alias/zkp-id-token".to_string(),
+        region: "us-east-1".to_string(),
+        endpoint: Some(format!("http://127.0.0.1:{}", port)),
+        credentials: credentials(),
+    };
+    assert!(matches!(KmsSigner::connect(gone).await, Err(KmsError::Unreachable(_))));
+
+    let _ = std::fs::remove_dir_all(&dir);
+    println!("✅ Both KMS APIs sign, and wrong keys are caught!");
+}
+
+async fn login(
+    client: &mut AuthClient<Channel>,
+    zkp: &ZKP,
+    x: &num_bigint::BigUint,
+) -> Result<tonic::Response<AuthenticationAnswerResponse>, tonic::Status> {
+    let k = ZKP::generate_random_number_below(&zkp.q);
+    let (r1, r2) = zkp.commitment(&k).to_bytes_be();
+    let request = AuthenticationChallengeRequest { user: "kms_user".to_string(), r1, r2, ..Default::default() };
+    let challenge = client.create_authentication_challenge(request).await.unwrap().into_inner();
+    let s = zkp.respond(&k, &Challenge::from_bytes_be(&challenge.c), x).to_bytes_be();
+    let id_token = Some(IdTokenRequest { audience: "app".to_string(), nonce: "n".to_string() });
+    let answer = AuthenticationAnswerRequest { auth_id: challenge.auth_id, s, id_token, ..Default::default() };
+    client.verify_authentication(answer).await
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_server_signs_id_tokens_in_kms() {
+    println!("🔧 Testing a server whose ID token key only exists in Cloud KMS");
+
+    let key = SigningKey::from_bytes(&[8; 32]);
+    let fake = Arc::new(Mutex::new(FakeKms::new(key.clone())));
+    let addr = serve(fake.clone());
+    let dir = temp_dir("server");
+    let token_file = dir.join("token");
+    std::fs::write(&token_file, GCP_TOKEN).unwrap();
+
+    let port = 50084;
+    let _server = Server(
+        Command::new(env!("CARGO_BIN_EXE_server"))
+            .arg("--listen")
+            .arg(format!("127.0.0.1:{}", port))
+            .args(["--kms-gcp-key", GCP_KEY, "--kms-endpoint", &addr])
+            .arg("--kms-gcp-token-file")
+            .arg(&token_file)
+            .args(["--id-token-issuer", "https://zkp.example", "--id-token-audience", "app"])
+            .stdout(Stdio::null())
+            .spawn()
+            .expect("Failed to start server process"),
+    );
+    let mut client = AuthClient::new(connect(port).await);
+
+    let zkp = ZKP::builder().rfc5114().build().unwrap();
+    let x = ZKP::generate_random_number_below(&zkp.q);
+    let (y1, y2) = zkp.public_key(&x).to_bytes_be();
+    let register = RegisterRequest { user: "kms_user".to_string(), y1, y2, ..Default::default() };
+    client.register(register).await.unwrap();
+
+    let token = login(&mut client, &zkp, &x).await.unwrap().into_inner().id_token;
+    let claims = id_token::verify(&token, &key.verifying_key(), "https://zkp.example", "app", SystemTime::now()).unwrap();
+    assert_eq!(claims.sub, "kms_user");
+    assert_eq!(fake.lock().unwrap().signatures, 1, "signed by the KMS, not the server");
+
+    // no signature, no login: the session isn't handed out without its token
+    std::fs::write(&token_file, "ya29.expired").unwrap();
+    let refused = login(&mut client, &zkp, &x).await.unwrap_err();
+    assert_eq!(refused.code(), tonic::Code::Unavailable);
+    assert!(refused.message().contains("invalid credentials"), "{}", refused.message());
+
+    let _ = std::fs::remove_dir_all(&dir);
+    println!("✅ ID tokens are signed in KMS!");
+}
@@ -0,0 +1,80 @@
+// Tests for electing one leader through a lease file
+use std::time::{Duration, SystemTime};
+
+use rust_zkp_chaum_pedersen::lease::Lease;
+
+const TTL: Duration = Duration::from_secs(30);
+
+fn lease_dir(name: &str) -> std::path::PathBuf {
+    let dir = std::env::temp_dir().join(format!("zkp-lease-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn test_one_server_leads() {
+    println!("👑 Testing only one server takes a free lease");
+
+    let path = lease_dir("one").join("leader");
+    let (a, b) = (Lease::new(&path, "a", TTL), Lease::new(&path, "b", TTL));
+    let now = SystemTime::now();
+    assert!(a.renew(now).unwrap());
+    assert!(!b.renew(now).unwrap());
+    assert!(a.is_leader());
+    assert!(!b.is_leader());
+    assert_eq!(b.holder().unwrap().unwrap().holder, "a");
+
+    // renewing pushes the expiry out, and nobody else gets in meanwhile
+    let later = now + TTL / 3;
+    assert!(a.renew(later).unwrap());
+    assert!(!b.renew(later).unwrap());
+    assert!(a.holder().unwrap().unwrap().expires > now.duration_since(SystemTime::UNIX_EPOCH).unwrap().as_millis() as u64 + TTL.as_millis() as u64);
+
+    // released, the next one takes it at once
+    a.release().unwrap();
+    assert!(!a.is_leader());
+    assert!(b.renew(later).unwrap());
+    assert!(b.is_leader());
+    // releasing a lease someone else holds leaves it alone
+    a.release().unwrap();
+    assert_eq!(b.holder().unwrap().unwrap().holder, "b");
+
+    println!("✅ One leader at a time!");
+}
+
+#[test]
+fn test_expired_lease_is_taken_over() {
+    println!("⏳ Testing a lease that ran out is taken over");
+
+    let path = lease_dir("expired").join("leader");
+    let (a, b) = (Lease::new(&path, "a", TTL), Lease::new(&path, "b", TTL));
+    let now = SystemTime::now();
+    // a last renewed longer ago than the lease lasts
+    assert!(a.renew(now - TTL * 2).unwrap());
+    assert!(!a.is_leader());
+
+    assert!(b.renew(now).unwrap());
+    assert!(b.is_leader());
+    assert_eq!(b.holder().unwrap().unwrap().holder, "b");
+    // a comes back and finds it taken
+    assert!(!a.renew(now).unwrap());
+    assert!(!a.is_leader());
+    // no files are left behind next to the lease
+    let files: Vec<_> = std::fs::read_dir(path.parent().unwrap()).unwrap().map(|entry| entry.unwrap().file_name()).collect();
+    assert_eq!(files, vec![std::ffi::OsString::from("leader")]);
+
+    println!("✅ Expired leases change hands!");
+}
+
+#[test]
+fn test_unwritable_lease_follows() {
+    println!("🚫 Testing a lease that can't be written leaves the server a follower");
+
+    let path = lease_dir("unwritable").join("missing").join("leader");
+    let a = Lease::new(&path, "a", TTL);
+    assert!(a.renew(SystemTime::now()).is_err());
+    assert!(!a.is_leader());
+
+    println!("✅ No lease, no leading!");
+}
@@ -0,0 +1,146 @@
+// Concurrency tests for the pending challenge store under loom
+//
+// loom runs each model through every interleaving of its threads, so a race
+// between inserting, consuming and expiring challenges shows up as a failed
+// assertion (or a deadlock) instead of a flaky test. Only built with --cfg
+// zkp_loom, which swaps the store's locks for loom's (not plain `loom`, which
+// would change tokio too):
+//   RUSTFLAGS="--cfg zkp_loom" cargo test --release --test loom_tests
+#![cfg(zkp_loom)]
+
+use loom::sync::Arc;
+use loom::thread;
+use num_bigint::BigUint;
+use rust_zkp_chaum_pedersen::pending::PendingChallenges;
+
+fn opening() -> (BigUint, Vec<u8>) {
+    (BigUint::from(42u32), vec![7; 16])
+}
+
+/// bytes the store counts for one challenge, measured on a store of its own
+fn bytes_of(auth_id: &str, committed: bool) -> usize {
+    let store = PendingChallenges::new(None);
+    store.insert(auth_id.to_string(), "alice".to_string(), committed.then(opening));
+    store.usage().bytes
+}
+
+#[test]
+fn test_committed_challenge_opens_once() {
+    println!("🧵 Testing two threads opening the same committed challenge");
+
+    loom::model(|| {
+        let store = Arc::new(PendingChallenges::new(None));
+        store.insert("01A".to_string(), "alice".to_string(), Some(opening()));
+
+        let other = store.clone();
+        let racer = thread::spawn(move || other.take_committed("01A").is_some());
+        let mine = store.take_committed("01A").is_some();
+        let theirs = racer.join().unwrap();
+
+        assert!(mine ^ theirs, "opened {} times", mine as u8 + theirs as u8);
+        assert_eq!(store.usage().bytes, bytes_of("01A", false), "the opening's bytes are freed once");
+        store.remove("01A");
+        assert_eq!(store.usage().bytes, 0);
+    });
+
+    println!("✅ A committed challenge is opened exactly once!");
+}
+
+#[test]
+fn test_expiry_races_consumption() {
+    println!("🧵 Testing expiry against a client answering the same challenge");
+
+    loom::model(|| {
+        let store = Arc::new(PendingChallenges::new(None));
+        store.insert("01A".to_string(), "alice".to_string(), Some(opening()));
+
+        let expirer = store.clone();
+        let expiry = thread::spawn(move || expirer.expire_before("01B"));
+        let opened = store.take_committed("01A").is_some();
+        let owner = store.owner("01A");
+        store.remove("01A");
+        let expired = expiry.join().unwrap();
+
+        // if expiry got there first there is nothing to open and no owner
+        if !opened {
+            assert_eq!(expired, 1);
+            assert_eq!(owner, None);
+        }
+        assert!(!store.is_committed("01A"));
+        assert_eq!(store.usage().entries, 0);
+        assert_eq!(store.usage().bytes, 0, "every byte counted is given back exactly once");
+    });
+
+    println!("✅ A challenge is either expired or consumed, and the count stays right!");
+}
+
+#[test]
+fn test_insert_races_expiry() {
+    println!("🧵 Testing a new challenge issued while old ones expire");
+
+    loom::model(|| {
+        let store = Arc::new(PendingChallenges::new(None));
+        store.insert("01A".to_string(), "alice".to_string(), Some(opening()));
+
+        let issuer = store.clone();
+        let issue = thread::spawn(move || issuer.insert("01C".to_string(), "alice".to_string(), Some(opening())));
+        store.expire_before("01B");
+        assert_eq!(issue.join().unwrap(), 0);
+
+        // the new challenge is after the cutoff, whichever ran first
+        assert_eq!(store.owner("01A"), None);
+        assert_eq!(store.owner("01C").as_deref(), Some("alice"));
+        assert!(store.is_committed("01C"));
+        assert_eq!(store.usage().bytes, bytes_of("01C", true));
+    });
+
+    println!("✅ Expiry only takes what it should!");
+}
+
+#[test]
+fn test_cap_holds_under_concurrent_inserts() {
+    println!("🧵 Testing the byte cap with two challenges issued at once");
+
+    loom::model(|| {
+        // room for one challenge: whichever comes second evicts the first
+        let one = bytes_of("01A", true);
+        let store = Arc::new(PendingChallenges::new(Some(one)));
+
+        let other = store.clone();
+        let racer = thread::spawn(move || other.insert("01B".to_string(), "alice".to_string(), Some(opening())));
+        let mine = store.insert("01A".to_string(), "alice".to_string(), Some(opening()));
+        let theirs = racer.join().unwrap();
+
+        assert_eq!(mine + theirs, 1, "exactly one challenge was dropped");
+        assert_eq!(store.usage().entries, 1);
+        assert_eq!(store.usage().bytes, one);
+        let survivor = ["01A", "01B"].into_iter().find(|auth_id| store.owner(auth_id).is_some()).unwrap();
+        assert!(store.is_committed(survivor), "the opening went with its challenge");
+    });
+
+    println!("✅ The cap holds and nothing is left half-evicted!");
+}
+
+#[test]
+fn test_answer_races_expiry() {
+    println!("🧵 Testing a challenge answered while it expires");
+
+    loom::model(|| {
+        let store = Arc::new(PendingChallenges::new(None));
+        store.insert("01A".to_string(), "alice".to_string(), None);
+        store.insert("01C".to_string(), "alice".to_string(), None);
+
+        let expirer = store.clone();
+        let expiry = thread::spawn(move || expirer.expire_before("01B"));
+        let answered = store.remove("01A");
+        assert!(store.remove("01C"));
+        let expired = expiry.join().unwrap();
+
+        // either the answer or the expiry took it, never both
+        assert_eq!(usize::from(answered) + expired, 1);
+        assert_eq!(store.unanswered(), 0);
+        assert_eq!(store.usage().bytes, 0, "every byte counted is given back exactly once");
+    });
+
+    println!("✅ An answer and an expiry use a challenge up once!");
+}
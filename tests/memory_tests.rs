@@ -0,0 +1,104 @@
+// Tests for the memory accounting of the server's stores
+use std::time::Duration;
+
+use num_bigint::BigUint;
+use rust_zkp_chaum_pedersen::api::v1::{ClientMetadata, RegisterRequest};
+use rust_zkp_chaum_pedersen::idempotency::IdempotencyCache;
+use rust_zkp_chaum_pedersen::memory::{self, ByteBudget, HeapSize, StoreUsage};
+use rust_zkp_chaum_pedersen::metrics::StoreMetrics;
+use rust_zkp_chaum_pedersen::types::PublicKey;
+use rust_zkp_chaum_pedersen::ZKP;
+
+#[test]
+fn test_sizes_grow_with_the_group() {
+    println!("🧠 Testing size estimates");
+
+    assert_eq!(BigUint::from(0u32).heap_size(), 0);
+    assert_eq!(BigUint::from(1u32).heap_size(), 8);
+    assert_eq!((BigUint::from(1u32) << 64u32).heap_size(), 16);
+
+    // a public key is two numbers below p
+    let small = ZKP::builder().rfc5114().build().unwrap();
+    let large = rust_zkp_chaum_pedersen::fixed::ZKP2048::rfc5114().into_inner();
+    let x = BigUint::from(123456789u32);
+    let small_key: PublicKey = small.compute_pair(&x).into();
+    let large_key: PublicKey = large.compute_pair(&x).into();
+    assert_eq!(small_key.heap_size(), 2 * 1024 / 8);
+    assert_eq!(large_key.heap_size(), 2 * 2048 / 8);
+
+    let client = ClientMetadata {
+        device_id: "d".repeat(100),
+        app_version: "1.0".to_string(),
+        platform: "linux".to_string(),
+    };
+    assert!(client.heap_size() >= 108);
+    assert!(Some(client).heap_size() >= 108);
+    assert_eq!(None::<ClientMetadata>.heap_size(), 0);
+
+    println!("✅ Records are sized by their numbers and strings!");
+}
+
+#[test]
+fn test_byte_budget() {
+    println!("🧠 Testing byte budgets");
+
+    let unlimited = ByteBudget::default();
+    unlimited.add(usize::MAX / 2);
+    assert!(unlimited.has_room_for(1000));
+
+    let budget = ByteBudget::new(Some(1000));
+    assert!(budget.has_room_for(1000));
+    budget.add(600);
+    assert!(budget.has_room_for(400));
+    assert!(!budget.has_room_for(401));
+    budget.sub(100);
+    assert_eq!(budget.used(), 500);
+    // estimates can drift, but never wrap around
+    budget.sub(10_000);
+    assert_eq!(budget.used(), 0);
+
+    println!("✅ Budgets track bytes against the cap!");
+}
+
+#[test]
+fn test_idempotency_cache_usage() {
+    println!("🧠 Testing the idempotency cache's size");
+
+    let cache = IdempotencyCache::new(Duration::from_secs(60));
+    assert_eq!(cache.usage(), StoreUsage::default());
+
+    let request = |y_bytes: usize| RegisterRequest {
+        user: "alice".to_string(),
+        y1: vec![7; y_bytes],
+        y2: vec![7; y_bytes],
+        ..Default::default()
+    };
+    cache.record("key-1", request(128));
+    let one = cache.usage();
+    assert_eq!(one.entries, 1);
+    cache.record("key-2", request(256));
+    let two = cache.usage();
+    assert_eq!(two.entries, 2);
+    // the 2048-bit registration costs more than the 1024-bit one
+    assert!(two.bytes - one.bytes > one.bytes, "{:?} then {:?}", one, two);
+
+    println!("✅ Cached registrations are sized by their keys!");
+}
+
+#[test]
+fn test_store_metrics() {
+    println!("🧠 Testing store gauges");
+
+    let stores = StoreMetrics::default();
+    assert_eq!(stores.render(), "");
+    stores.set("sessions", StoreUsage { entries: 3, bytes: 1500 });
+    let text = stores.render();
+    assert!(text.contains("zkp_store_bytes{store=\"sessions\"} 1500\n"), "{}", text);
+    assert!(text.contains("zkp_store_entries{store=\"sessions\"} 3\n"), "{}", text);
+
+    assert_eq!(memory::format_bytes(512), "512 B");
+    assert_eq!(memory::format_bytes(1536), "1.5 KiB");
+    assert_eq!(memory::format_bytes(64 * 1024 * 1024), "64.0 MiB");
+
+    println!("✅ Store sizes are published!");
+}
@@ -0,0 +1,118 @@
+// Tests for the crypto latency histograms and the /metrics endpoint
+use std::io::{Read, Write};
+use std::time::Duration;
+
+use num_bigint::BigUint;
+use rust_zkp_chaum_pedersen::metrics::{self, CryptoMetrics, LatencyHistogram, Operation};
+use rust_zkp_chaum_pedersen::ZKP;
+
+#[test]
+fn test_histogram_quantiles() {
+    println!("📈 Testing latency quantiles");
+
+    let histogram = LatencyHistogram::default();
+    assert_eq!(histogram.quantile(0.5), Duration::ZERO, "an empty histogram has no latency");
+
+    // 90 fast samples and 10 slow ones
+    for _ in 0..90 {
+        histogram.record(Duration::from_micros(1000));
+    }
+    for _ in 0..10 {
+        histogram.record(Duration::from_millis(50));
+    }
+    assert_eq!(histogram.count(), 100);
+    assert_eq!(histogram.sum(), Duration::from_micros(90 * 1000 + 10 * 50_000));
+
+    // quantiles are bucket upper bounds: never below the sample, at most ~25% above
+    let close_above = |quantile: Duration, sample: Duration| {
+        quantile >= sample && quantile.as_secs_f64() <= sample.as_secs_f64() * 1.25
+    };
+    assert!(close_above(histogram.quantile(0.5), Duration::from_micros(1000)), "{:?}", histogram.quantile(0.5));
+    assert!(close_above(histogram.quantile(0.9), Duration::from_micros(1000)));
+    assert!(close_above(histogram.quantile(0.95), Duration::from_millis(50)), "{:?}", histogram.quantile(0.95));
+    assert!(close_above(histogram.quantile(0.99), Duration::from_millis(50)));
+
+    // tiny and huge samples land somewhere
+    histogram.record(Duration::ZERO);
+    histogram.record(Duration::MAX);
+    assert_eq!(histogram.count(), 102);
+
+    println!("✅ Quantiles are within a bucket of the samples!");
+}
+
+#[test]
+fn test_render_is_prometheus_text() {
+    println!("📈 Testing the Prometheus rendering");
+
+    let metrics = CryptoMetrics::default();
+    metrics.record(Operation::Verify, 2048, Duration::from_millis(4));
+    metrics.record(Operation::ComputePair, 1024, Duration::from_millis(1));
+
+    let text = metrics.render();
+    println!("{}", text);
+    assert!(text.contains("# TYPE zkp_crypto_op_seconds summary\n"));
+    for quantile in ["0.5", "0.95", "0.99"] {
+        assert!(text.contains(&format!("zkp_crypto_op_seconds{{op=\"verify\",group_bits=\"2048\",quantile=\"{}\"}} ", quantile)));
+    }
+    assert!(text.contains("zkp_crypto_op_seconds_count{op=\"compute_pair\",group_bits=\"1024\"} 1\n"));
+    assert!(text.contains("zkp_crypto_op_seconds_sum{op=\"verify\",group_bits=\"2048\"} 0.004\n"));
+
+    println!("✅ Metrics render as summaries!");
+}
+
+#[test]
+fn test_zkp_operations_are_timed_by_group_size() {
+    println!("📈 Testing that compute_pair and verify are timed");
+
+    let small = ZKP { p: BigUint::from(23u32), q: BigUint::from(11u32), alpha: BigUint::from(4u32), beta: BigUint::from(9u32) };
+    let pairs = metrics::global().histogram(Operation::ComputePair, 5).count();
+    let verifies = metrics::global().histogram(Operation::Verify, 5).count();
+
+    let x = BigUint::from(6u32);
+    let k = BigUint::from(7u32);
+    let c = BigUint::from(4u32);
+    let (y1, y2) = small.compute_pair(&x);
+    let (r1, r2) = small.compute_pair(&k);
+    let s = small.solve(&k, &c, &x);
+    assert!(small.verify(&r1, &r2, &y1, &y2, &c, &s));
+
+    // other tests may run ZKPs of the same size at the same time
+    assert!(metrics::global().histogram(Operation::ComputePair, 5).count() >= pairs + 2);
+    assert!(metrics::global().histogram(Operation::Verify, 5).count() > verifies);
+
+    let (_, _, p, _) = ZKP::get_constants();
+    let zkp = ZKP::builder().rfc5114().build().unwrap();
+    zkp.compute_pair(&BigUint::from(12345u32));
+    assert!(metrics::global().histogram(Operation::ComputePair, p.bits()).count() >= 1);
+
+    println!("✅ Latencies are recorded per group size!");
+}
+
+#[tokio::test]
+async fn test_metrics_endpoint() {
+    println!("📈 Testing GET /metrics");
+
+    let addr = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+    tokio::spawn(metrics::serve(addr));
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    let (_, _, _, q) = ZKP::get_constants();
+    ZKP::builder().rfc5114().build().unwrap().compute_pair(&q);
+
+    let response = tokio::task::spawn_blocking(move || {
+        let mut stream = std::net::TcpStream::connect(addr).unwrap();
+        stream
+            .write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    })
+    .await
+    .unwrap();
+
+    assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+    assert!(response.contains("zkp_crypto_op_seconds_count{op=\"compute_pair\""), "{}", response);
+
+    println!("✅ Prometheus can scrape the server!");
+}
@@ -0,0 +1,140 @@
+// Tests for the NIST P-256 backend (cargo test --features p256)
+#![cfg(feature = "p256")]
+
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime};
+
+use num_bigint::BigUint;
+use p256::elliptic_curve::PrimeField;
+use p256::Scalar;
+use rust_zkp_chaum_pedersen::nist::{self, P256PublicKey, P256, PROOF_LEN};
+use rust_zkp_chaum_pedersen::nizk::ValidityWindow;
+use rust_zkp_chaum_pedersen::ZKP;
+
+// the vector test seeds the process-wide generator, so the tests take turns
+static RNG: Mutex<()> = Mutex::new(());
+
+fn window() -> ValidityWindow {
+    ValidityWindow { not_before: 1_700_000_000, not_after: 1_700_000_300 }
+}
+
+fn at(unix: u64) -> SystemTime {
+    SystemTime::UNIX_EPOCH + Duration::from_secs(unix)
+}
+
+#[test]
+fn test_p256_proofs() {
+    let _turn = RNG.lock().unwrap_or_else(|e| e.into_inner());
+    println!("🔧 Testing 98-byte P-256 proofs");
+
+    let group = P256::new();
+    let x = P256::random_scalar();
+    let key = group.public_key(&x);
+
+    let started = Instant::now();
+    let proof = group.prove_non_interactive(&x, window(), b"login to example.com");
+    assert!(group.verify_non_interactive(&key, &proof, b"login to example.com", at(1_700_000_100)));
+    println!("   prove + verify: {:?}", started.elapsed());
+
+    let compact = nist::to_compact(&proof).unwrap();
+    assert_eq!(compact.len(), PROOF_LEN);
+    assert_eq!(nist::from_compact(&compact, proof.id.clone(), proof.window), proof);
+
+    // bound to the window, the context, the key and every byte
+    assert!(!group.verify_non_interactive(&key, &proof, b"login to example.com", at(1_700_000_301)));
+    assert!(!group.verify_non_interactive(&key, &proof, b"login to evil.com", at(1_700_000_100)));
+    let other = group.public_key(&P256::random_scalar());
+    assert!(!group.verify_non_interactive(&other, &proof, b"login to example.com", at(1_700_000_100)));
+    for i in 0..PROOF_LEN {
+        let mut tampered = compact;
+        tampered[i] ^= 1;
+        let tampered = nist::from_compact(&tampered, proof.id.clone(), proof.window);
+        assert!(!group.verify_non_interactive(&key, &tampered, b"login to example.com", at(1_700_000_100)), "byte {}", i);
+    }
+
+    // a Z_p-sized field isn't a P-256 proof
+    let mut long = proof.clone();
+    long.s.insert(0, 0);
+    assert_eq!(nist::to_compact(&long), None);
+    assert!(!group.verify_non_interactive(&key, &long, b"login to example.com", at(1_700_000_100)));
+
+    let bytes = key.to_bytes();
+    assert_eq!(P256PublicKey::from_bytes(&bytes), Some(key));
+    assert_eq!(P256PublicKey::from_bytes(&bytes[..65]), None);
+    assert_eq!(P256PublicKey::from_bytes(&[0xff; 66]), None, "not a valid encoding");
+
+    println!("✅ P-256 proofs verify, and only for what they were made for!");
+}
+
+#[test]
+fn test_p256_wire_answers() {
+    let _turn = RNG.lock().unwrap_or_else(|e| e.into_inner());
+    println!("🔧 Testing P-256 answers as sent over gRPC");
+
+    let group = P256::new();
+    let x = P256::random_scalar();
+    let (y1, y2) = group.public_key(&x).to_wire();
+    let zkp = ZKP::builder().rfc5114().build().unwrap();
+
+    for _ in 0..32 {
+        let k = P256::random_scalar();
+        let (r1, r2) = group.commitment(&k).to_wire();
+        // the server's c is a Z_p challenge, wider than the curve order
+        let c = ZKP::generate_random_number_below(&zkp.q).to_bytes_be();
+        let s = group.respond(&k, &nist::challenge_from_wire(&c), &x);
+        let s = nist::scalar_to_wire(&s);
+        assert_eq!(s.len(), 32);
+        assert!(group.verify_wire(&y1, &y2, &r1, &r2, &c, &s));
+
+        // what the server gets back after a trip through BigUint, leading zeros dropped
+        let s = BigUint::from_bytes_be(&s).to_bytes_be();
+        assert!(group.verify_wire(&y1, &y2, &r1, &r2, &c, &s));
+
+        let mut wrong = s.clone();
+        *wrong.last_mut().unwrap() ^= 1;
+        assert!(!group.verify_wire(&y1, &y2, &r1, &r2, &c, &wrong));
+        assert!(!group.verify_wire(&y1, &y2, &r2, &r1, &c, &s));
+    }
+
+    // garbage doesn't panic, it just fails
+    assert!(!group.verify_wire(&y1, &y2, &[], &[2; 33], &[1], &[1]));
+    assert!(!group.verify_wire(&y1, &y2, &y1, &y2, &[1], &[0xff; 33]));
+
+    println!("✅ Wire answers verify, and only the right ones!");
+}
+
+#[test]
+fn test_p256_vectors() {
+    let _turn = RNG.lock().unwrap_or_else(|e| e.into_inner());
+    println!("🔧 Testing P-256 test vectors");
+
+    // alpha is the standard base point G, from FIPS 186-4 D.1.2.3
+    let group = P256::new();
+    let key = group.public_key(&Scalar::ONE);
+    assert_eq!(hex::encode(&key.to_bytes()[..33]), "036b17d1f2e12c4247f8bce6e563a440f277037d812deb33a0f4a13945d898c296");
+
+    // a proof for x = 42, pinned so the hash layout and beta can't change unnoticed
+    let x = nist::scalar_from_biguint(&BigUint::from(42u32));
+    assert_eq!(x, Scalar::from(42u64));
+    let key = group.public_key(&x);
+    assert_eq!(hex::encode(key.to_bytes()), KEY_42);
+
+    ZKP::with_rng(7);
+    let proof = group.prove_non_interactive(&x, window(), b"test vector");
+    ZKP::with_os_rng();
+    assert_eq!(proof.id, PROOF_42_ID);
+    assert_eq!(hex::encode(nist::to_compact(&proof).unwrap()), PROOF_42);
+
+    let vector = nist::from_compact(&hex::decode(PROOF_42).unwrap().try_into().unwrap(), PROOF_42_ID.to_string(), window());
+    assert!(group.verify_non_interactive(&key, &vector, b"test vector", at(1_700_000_000)));
+
+    // reduction mod the group order: n + 42 is 42
+    let order = BigUint::from_bytes_be(&(-Scalar::ONE).to_repr()) + 1u32;
+    assert_eq!(nist::scalar_from_biguint(&(order + 42u32)), x);
+
+    println!("✅ The vectors match!");
+}
+
+const KEY_42: &str = "026780c5fc70275e2c7061a0e7877bb174deadeb9887027f3fa83654158ba7f50c03af5024180d2f86c779f9893e07a0112efb72812f7322f63f511b6dd0f1b0f1ff";
+const PROOF_42_ID: &str = "nRv8mLvQeIYugoZB";
+const PROOF_42: &str = "03b3c644e9c852bc25df0ad1f6ab3e52006f9fa8ca294808a316a323d52201507403b832453d808f421b164f25905a2a15ed7226a21e6d17478a56a0fce00a9c0a7ee39911402550427d87c9407ff9de8347a49fa5d7cbf8f46233dd6f7c07c00284";
@@ -0,0 +1,136 @@
+// Tests for non-interactive proofs with a validity window
+use std::process::{Command, Stdio};
+use std::time::{Duration, SystemTime};
+
+use num_bigint::BigUint;
+use tonic::Code;
+
+use rust_zkp_chaum_pedersen::api::v1::VerifyProofRequest;
+use rust_zkp_chaum_pedersen::group::HashId;
+use rust_zkp_chaum_pedersen::nizk::{NonInteractiveProof, ValidityWindow};
+use rust_zkp_chaum_pedersen::ZKP;
+
+mod common;
+use common::{auth_client, Server};
+
+fn setup() -> ZKP {
+    ZKP::builder().rfc5114().build().unwrap()
+}
+
+#[test]
+fn test_non_interactive_proof_expires() {
+    println!("⏳ Testing time-boxed non-interactive proofs");
+
+    let zkp = setup();
+    let x = ZKP::generate_random_number_below(&zkp.q);
+    let (y1, y2) = zkp.compute_pair(&x);
+
+    let now = SystemTime::now();
+    let window = ValidityWindow::starting_at(now, Duration::from_secs(60));
+    let proof = zkp.prove_non_interactive(&x, window, b"audit export");
+
+    assert!(zkp.verify_non_interactive(&y1, &y2, &proof, b"audit export", now));
+    assert!(zkp.verify_non_interactive(&y1, &y2, &proof, b"audit export", now + Duration::from_secs(60)));
+    // too late, too early
+    assert!(!zkp.verify_non_interactive(&y1, &y2, &proof, b"audit export", now + Duration::from_secs(61)));
+    assert!(!zkp.verify_non_interactive(&y1, &y2, &proof, b"audit export", now - Duration::from_secs(1)));
+
+    // stretching the window breaks the proof
+    let mut extended = proof.clone();
+    extended.window.not_after += 3600;
+    assert!(!zkp.verify_non_interactive(&y1, &y2, &extended, b"audit export", now));
+
+    assert!(!zkp.verify_non_interactive(&y1, &y2, &proof, b"another context", now));
+
+    // the id is in the transcript, so a revoked proof can't be renamed
+    let mut renamed = proof.clone();
+    renamed.id = "somethingelse123".to_string();
+    assert!(!zkp.verify_non_interactive(&y1, &y2, &renamed, b"audit export", now));
+
+    let json = serde_json::to_string(&proof).unwrap();
+    let imported: NonInteractiveProof = serde_json::from_str(&json).unwrap();
+    assert!(zkp.verify_non_interactive(&y1, &y2, &imported, b"audit export", now));
+
+    println!("✅ Time-boxed proof test passed!");
+}
+
+#[test]
+fn test_proofs_about_non_elements_fail() {
+    println!("⏳ Testing proofs for keys outside the group");
+
+    let zkp = setup();
+    let now = SystemTime::now();
+    let window = ValidityWindow::starting_at(now, Duration::from_secs(60));
+    // 0^c = 0, so r = alpha^s * y^c holds for r = y = 0 and any s
+    let zero = BigUint::from(0u32);
+    let forged = NonInteractiveProof { id: "nobodyknowsx0000".to_string(), r1: vec![0], r2: vec![0], s: vec![1], window };
+    assert!(!zkp.verify_non_interactive(&zero, &zero, &forged, b"audit export", now));
+
+    // an honest proof doesn't pass for a key outside the subgroup either
+    let x = ZKP::generate_random_number_below(&zkp.q);
+    let (y1, y2) = zkp.compute_pair(&x);
+    let proof = zkp.prove_non_interactive(&x, window, b"audit export");
+    assert!(zkp.verify_non_interactive(&y1, &y2, &proof, b"audit export", now));
+    for bad in [BigUint::from(1u32), &zkp.p - 1u32, &y1 + &zkp.p] {
+        assert!(!zkp.verify_non_interactive(&bad, &y2, &proof, b"audit export", now));
+    }
+
+    println!("✅ Proofs need keys in the group!");
+}
+
+#[tokio::test]
+async fn test_server_verifies_proofs_without_registration() {
+    println!("⏳ Testing VerifyProof");
+
+    let _server = Server(
+        Command::new(env!("CARGO_BIN_EXE_server"))
+            .args(["--listen", "127.0.0.1:50109"])
+            .stdout(Stdio::null())
+            .spawn()
+            .expect("Failed to start server process"),
+    );
+    let mut client = auth_client(50109).await;
+    let zkp = setup();
+    let x = ZKP::generate_random_number_below(&zkp.q);
+    let (y1, y2) = zkp.public_key(&x).to_bytes_be();
+    let window = ValidityWindow::starting_now(Duration::from_secs(60));
+    let proof = zkp.prove_non_interactive(&x, window, b"order 42");
+    let request = VerifyProofRequest {
+        y1: y1.clone(),
+        y2: y2.clone(),
+        proof: serde_json::to_string(&proof).unwrap(),
+        context: b"order 42".to_vec(),
+        ..Default::default()
+    };
+
+    // nobody registered (y1, y2), the proof is all it takes
+    let response = client.verify_proof(request.clone()).await.unwrap().into_inner();
+    assert!(response.valid, "{}", response.reason);
+    assert_eq!((response.proof_id, response.expires_unix), (proof.id.clone(), window.not_after));
+
+    // anything else in the statement and it's an answer, not an error
+    let response = client.verify_proof(VerifyProofRequest { context: b"order 43".to_vec(), ..request.clone() }).await.unwrap().into_inner();
+    assert!(!response.valid);
+    assert_eq!(response.reason, "the proof is wrong");
+    let response = client.verify_proof(VerifyProofRequest { y1: y2.clone(), ..request.clone() }).await.unwrap().into_inner();
+    assert!(!response.valid);
+    let expired = zkp.prove_non_interactive(&x, ValidityWindow { not_before: 1, not_after: 2 }, b"order 42");
+    let response = client.verify_proof(VerifyProofRequest { proof: serde_json::to_string(&expired).unwrap(), ..request.clone() }).await.unwrap().into_inner();
+    assert!(!response.valid);
+    assert!(response.reason.contains("expired"), "{}", response.reason);
+
+    // a prover with its own generator says which
+    let own = zkp.for_user("alice");
+    let (y1, y2) = own.public_key(&x).to_bytes_be();
+    let proof = serde_json::to_string(&own.prove_non_interactive(&x, window, b"order 42")).unwrap();
+    let request = VerifyProofRequest { y1, y2, proof, generator_label: "alice".to_string(), ..request };
+    assert!(client.verify_proof(request.clone()).await.unwrap().into_inner().valid);
+    assert!(!client.verify_proof(VerifyProofRequest { generator_label: "bob".to_string(), ..request.clone() }).await.unwrap().into_inner().valid);
+    let wrong_hash = VerifyProofRequest { hash: HashId::Sha512.name().to_string(), ..request.clone() };
+    assert!(!client.verify_proof(wrong_hash).await.unwrap().into_inner().valid);
+
+    let status = client.verify_proof(VerifyProofRequest { proof: "not json".to_string(), ..request }).await.unwrap_err();
+    assert_eq!(status.code(), Code::InvalidArgument);
+
+    println!("✅ The server is a stateless verification oracle!");
+}
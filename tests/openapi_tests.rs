@@ -0,0 +1,71 @@
+// Tests for the REST gateway's OpenAPI document
+use std::io::{Read, Write};
+use std::time::Duration;
+
+use rust_zkp_chaum_pedersen::{metrics, openapi};
+
+#[test]
+fn test_document_follows_the_proto() {
+    println!("📖 Testing the OpenAPI document");
+
+    let document: serde_json::Value = serde_json::from_str(openapi::document()).expect("the document is JSON");
+    assert_eq!(document["openapi"], "3.1.0");
+    assert_eq!(document["info"]["version"], env!("CARGO_PKG_VERSION"));
+
+    // one POST per unary RPC, at its gateway route
+    let paths = document["paths"].as_object().unwrap();
+    let register = &paths[&openapi::route("Auth", "Register")]["post"];
+    assert_eq!(openapi::route("Auth", "Register"), "/v1/auth/register");
+    assert_eq!(register["operationId"], "Auth.Register");
+    assert_eq!(register["requestBody"]["content"]["application/json"]["schema"]["$ref"], "#/components/schemas/RegisterRequest");
+    assert_eq!(register["responses"]["200"]["content"]["application/json"]["schema"]["$ref"], "#/components/schemas/RegisterResponse");
+    assert_eq!(register["responses"]["default"]["content"]["application/problem+json"]["schema"]["$ref"], "#/components/schemas/Problem");
+    assert!(paths.contains_key("/v1/auth/create-authentication-challenge"));
+    assert!(register.get("security").is_none());
+    assert_eq!(paths["/v1/admin/set-user-enabled"]["post"]["security"][0]["adminToken"], serde_json::json!([]));
+    assert!(!paths.contains_key("/v1/admin/bulk-register"), "a client stream has no REST form");
+    for (path, item) in paths {
+        let (service, method) = item["post"]["operationId"].as_str().unwrap().split_once('.').unwrap();
+        assert_eq!(*path, openapi::route(service, method));
+    }
+
+    // proto3 JSON: camelCase, base64 bytes, 64-bit numbers as strings, field rules
+    let schemas = &document["components"]["schemas"];
+    let challenge = &schemas["AuthenticationChallengeRequest"];
+    assert_eq!(challenge["properties"]["authId"], serde_json::json!({"type": "string", "maxLength": 64}));
+    assert_eq!(challenge["properties"]["r1"], serde_json::json!({"type": "string", "contentEncoding": "base64"}));
+    assert_eq!(challenge["properties"]["client"]["$ref"], "#/components/schemas/ClientMetadata");
+    assert!(challenge["required"].as_array().unwrap().contains(&serde_json::json!("r1")));
+    assert_eq!(schemas["LoginRecord"]["properties"]["unixMillis"]["type"], "string");
+    assert_eq!(schemas["ServerInfoResponse"]["properties"]["challengeModes"]["items"]["$ref"], "#/components/schemas/ChallengeMode");
+    assert_eq!(schemas["SessionTier"]["type"], "string");
+
+    println!("✅ The gateway is described from the proto!");
+}
+
+#[tokio::test]
+async fn test_document_is_served() {
+    println!("📖 Testing GET /openapi.json");
+
+    let addr = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+    tokio::spawn(metrics::serve(addr));
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    let response = tokio::task::spawn_blocking(move || {
+        let mut stream = std::net::TcpStream::connect(addr).unwrap();
+        stream
+            .write_all(b"GET /openapi.json HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    })
+    .await
+    .unwrap();
+
+    assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+    assert!(response.to_ascii_lowercase().contains("content-type: application/json"), "{}", response);
+    assert!(response.ends_with(openapi::document()), "{}", response);
+
+    println!("✅ API consumers can fetch the document!");
+}
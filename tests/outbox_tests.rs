@@ -0,0 +1,217 @@
+// Tests for the outbox audit events wait in until a webhook takes them
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use serde_json::{json, Value};
+
+use rust_zkp_chaum_pedersen::audit::{Audit, AuditEvent, Outcome};
+use rust_zkp_chaum_pedersen::outbox::{self, Delivery, Outbox, OutboxEntry};
+
+mod common;
+use common::temp_dir;
+
+fn dead_letters(outbox: &Outbox) -> Vec<OutboxEntry> {
+    let text = std::fs::read_to_string(outbox.dead_letters()).unwrap_or_default();
+    text.lines().map(|line| serde_json::from_str(line).unwrap()).collect()
+}
+
+#[test]
+fn test_outbox_survives_restarts() {
+    println!("📤 Testing events kept in the outbox across restarts");
+
+    let dir = temp_dir("restart");
+    let path = dir.join("outbox.jsonl");
+    let outbox = Outbox::open(&path, outbox::MAX_ATTEMPTS).unwrap();
+    let now = SystemTime::now();
+    let ids: Vec<String> = (0..3).map(|i| outbox.enqueue(json!({ "action": "login", "n": i }), now).unwrap()).collect();
+    assert!(ids.windows(2).all(|pair| pair[0] < pair[1]), "oldest first");
+    drop(outbox);
+
+    // on disk once enqueued; a line cut off by a crash was never enqueued
+    let mut journal = std::fs::read(&path).unwrap();
+    journal.extend_from_slice(b"{\"id\":\"01J");
+    std::fs::write(&path, journal).unwrap();
+    let outbox = Outbox::open(&path, outbox::MAX_ATTEMPTS).unwrap();
+    let pending = outbox.pending();
+    assert_eq!(pending.iter().map(|entry| entry.id.clone()).collect::<Vec<_>>(), ids);
+    assert_eq!(pending[2].event["n"], 2);
+    assert_eq!(pending[0].body(), json!({ "id": ids[0], "event": { "action": "login", "n": 0 } }).to_string());
+    assert!(std::fs::read_to_string(&path).unwrap().ends_with("}\n"), "the cut off line is gone");
+
+    // a journal that isn't one is refused, not taken for an empty outbox
+    std::fs::write(&path, "not json\n").unwrap();
+    assert_eq!(Outbox::open(&path, outbox::MAX_ATTEMPTS).unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+    println!("✅ Enqueued events wait on disk!");
+}
+
+#[tokio::test]
+async fn test_delivery_retries_and_dead_letters() {
+    println!("📤 Testing delivery with retries and dead letters");
+
+    assert_eq!(
+        [1, 2, 3, 10, 20, 40].map(outbox::backoff),
+        [1, 2, 4, 512, 3600, 3600].map(Duration::from_secs),
+        "doubling, up to an hour"
+    );
+
+    let dir = temp_dir("delivery");
+    let outbox = Outbox::open(&dir.join("outbox.jsonl"), 3).unwrap();
+    let start = SystemTime::now();
+    let good = outbox.enqueue(json!({ "action": "register" }), start).unwrap();
+    let bad = outbox.enqueue(json!({ "action": "poison" }), start).unwrap();
+
+    // the receiver takes one and not the other
+    let sent = std::sync::Mutex::new(Vec::new());
+    let receiver = |body: String| {
+        let event: Value = serde_json::from_str(&body).unwrap();
+        sent.lock().unwrap().push(event["id"].as_str().unwrap().to_string());
+        let taken = event["event"]["action"] != "poison";
+        async move { if taken { Ok(()) } else { Err("503 Service Unavailable".to_string()) } }
+    };
+    let delivery = outbox.deliver(start, receiver).await.unwrap();
+    assert_eq!(delivery, Delivery { delivered: 1, retrying: 1, dead: 0 });
+    assert_eq!(*sent.lock().unwrap(), [good.clone(), bad.clone()]);
+    let pending = outbox.pending();
+    assert_eq!((pending.len(), pending[0].attempts, pending[0].last_error.as_str()), (1, 1, "503 Service Unavailable"));
+
+    // not due again before its backoff
+    assert_eq!(outbox.deliver(start, receiver).await.unwrap(), Delivery::default());
+    let delivery = outbox.deliver(start + Duration::from_secs(1), receiver).await.unwrap();
+    assert_eq!(delivery, Delivery { delivered: 0, retrying: 1, dead: 0 });
+    assert_eq!(outbox.deliver(start + Duration::from_secs(2), receiver).await.unwrap(), Delivery::default());
+
+    // out of attempts: to the dead letters, with why
+    let delivery = outbox.deliver(start + Duration::from_secs(3), receiver).await.unwrap();
+    assert_eq!(delivery, Delivery { delivered: 0, retrying: 0, dead: 1 });
+    assert!(outbox.pending().is_empty());
+    let dead = dead_letters(&outbox);
+    assert_eq!((dead.len(), dead[0].id.as_str(), dead[0].attempts), (1, bad.as_str(), 3));
+    assert_eq!(dead[0].event["action"], "poison");
+    assert!(Outbox::open(&dir.join("outbox.jsonl"), 3).unwrap().pending().is_empty(), "delivered ones are gone from disk");
+    assert_eq!(sent.lock().unwrap().iter().filter(|id| **id == bad).count(), 3);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+    println!("✅ Every event is delivered or dead-lettered, never dropped!");
+}
+
+#[tokio::test]
+async fn test_outbox_as_audit_sink() {
+    println!("📤 Testing audit events enqueued and erased");
+
+    let dir = temp_dir("audit");
+    let outbox = Arc::new(Outbox::open(&dir.join("outbox.jsonl"), 1).unwrap());
+    let mut audit = Audit::default();
+    audit.add(outbox.clone());
+    let event = |user: &str| AuditEvent {
+        time: SystemTime::now(),
+        action: "login",
+        outcome: Outcome::Success,
+        user: user.to_string(),
+        source: "10.0.0.7:50000".to_string(),
+        trace_id: "trace".to_string(),
+        detail: "session s1".to_string(),
+    };
+    audit.record(&event("alice"));
+    let delivery = outbox.deliver(SystemTime::now(), |_| async { Err("down".to_string()) }).await.unwrap();
+    assert_eq!(delivery.dead, 1);
+    audit.record(&event("alice"));
+    audit.record(&event("bob"));
+    let pending = outbox.pending();
+    assert_eq!((pending[0].event["action"].as_str(), pending[0].event["user"].as_str()), (Some("login"), Some("alice")));
+
+    // erased from what is waiting and from the dead letters
+    assert_eq!(audit.erase("alice", "01TOMBSTONE").unwrap(), 2);
+    let pending = outbox.pending();
+    assert_eq!((pending[0].event["user"].as_str(), pending[0].event["erased"].as_str()), (Some(""), Some("01TOMBSTONE")));
+    assert_eq!(pending[0].event["detail"], "");
+    assert_eq!(pending[1].event["user"], "bob");
+    assert_eq!(dead_letters(&outbox)[0].event["source"], "");
+
+    std::fs::remove_dir_all(&dir).unwrap();
+    println!("✅ The outbox is an audit sink like any other!");
+}
+
+#[cfg(feature = "webhooks")]
+#[tokio::test]
+async fn test_webhook_delivery() {
+    use std::process::{Command, Stdio};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Mutex;
+
+    use hyper::service::{make_service_fn, service_fn};
+    use hyper::{Body, Request, Response, StatusCode};
+    use tonic::transport::Channel;
+
+    use rust_zkp_chaum_pedersen::api::v1::{auth_client::AuthClient, RegisterRequest};
+    use rust_zkp_chaum_pedersen::ZKP;
+
+    println!("📤 Testing audit events POSTed to a webhook by the server");
+
+    // a receiver that turns the first attempt away
+    let received = Arc::new(Mutex::new(Vec::<Value>::new()));
+    let attempts = Arc::new(AtomicUsize::new(0));
+    let (kept, counted) = (received.clone(), attempts.clone());
+    let make_service = make_service_fn(move |_| {
+        let (received, attempts) = (kept.clone(), counted.clone());
+        async move {
+            Ok::<_, std::convert::Infallible>(service_fn(move |request: Request<Body>| {
+                let (received, attempts) = (received.clone(), attempts.clone());
+                async move {
+                    let body = hyper::body::to_bytes(request.into_body()).await.unwrap();
+                    let status = match attempts.fetch_add(1, Ordering::SeqCst) {
+                        0 => StatusCode::SERVICE_UNAVAILABLE,
+                        _ => {
+                            received.lock().unwrap().push(serde_json::from_slice(&body).unwrap());
+                            StatusCode::OK
+                        }
+                    };
+                    Ok::<_, std::convert::Infallible>(Response::builder().status(status).body(Body::empty()).unwrap())
+                }
+            }))
+        }
+    });
+    let receiver = hyper::Server::bind(&"127.0.0.1:0".parse().unwrap()).serve(make_service);
+    let webhook = format!("http://{}/events", receiver.local_addr());
+    tokio::spawn(receiver);
+
+    let dir = temp_dir("webhook");
+    let path = dir.join("outbox.jsonl");
+    let mut server = Command::new(env!("CARGO_BIN_EXE_server"))
+        .args(["--listen", "127.0.0.1:50104", "--event-webhook", &webhook])
+        .args(["--event-outbox", path.to_str().unwrap(), "--event-delivery-interval", "1"])
+        .stdout(Stdio::null())
+        .spawn()
+        .expect("Failed to start server process");
+    let mut client = None;
+    for _ in 0..50 {
+        if let Ok(channel) = Channel::from_static("http://127.0.0.1:50104").connect().await {
+            client = Some(AuthClient::new(channel));
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    let zkp = ZKP::builder().rfc5114().build().unwrap();
+    let (y1, y2) = zkp.public_key(&ZKP::generate_random_number_below(&zkp.q)).to_bytes_be();
+    let request = RegisterRequest { user: "alice".to_string(), y1, y2, ..Default::default() };
+    let _ = client.expect("the test server didn't come up").register(request).await;
+
+    // there after a retry, and gone from the outbox
+    for _ in 0..100 {
+        if !received.lock().unwrap().is_empty() && std::fs::read_to_string(&path).is_ok_and(|journal| journal.is_empty()) {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    let _ = server.kill();
+    let _ = server.wait();
+    let received = received.lock().unwrap();
+    assert_eq!(received.len(), 1, "{:?}", received);
+    assert_eq!((received[0]["event"]["action"].as_str(), received[0]["event"]["user"].as_str()), (Some("register"), Some("alice")));
+    assert!(attempts.load(Ordering::SeqCst) >= 2);
+    assert!(std::fs::read_to_string(&path).unwrap().is_empty());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+    println!("✅ Audit events reach the webhook, late but surely!");
+}
@@ -0,0 +1,83 @@
+// Tests for signed group parameter bundles
+use ed25519_dalek::SigningKey;
+use num_bigint::BigUint;
+use rust_zkp_chaum_pedersen::params::{
+    load_or_create_signing_key, parse_public_key, ParametersError, SignedParameters,
+};
+use rust_zkp_chaum_pedersen::ZKP;
+
+fn rfc5114() -> ZKP {
+    ZKP::builder().rfc5114().build().unwrap()
+}
+
+fn key(seed: u8) -> SigningKey {
+    SigningKey::from_bytes(&[seed; 32])
+}
+
+#[test]
+fn test_signed_parameters_roundtrip() {
+    println!("🔏 Testing signed parameter bundle");
+
+    let zkp = rfc5114();
+    let server_key = key(1);
+    let bundle = SignedParameters::sign(&zkp, &server_key);
+
+    let verified = bundle.verify(&server_key.verifying_key()).expect("bundle should verify");
+    assert_eq!(verified, zkp);
+
+    // the file format roundtrips and still verifies
+    let from_file = SignedParameters::from_json(&bundle.to_json()).unwrap();
+    assert_eq!(from_file, bundle);
+    assert!(from_file.verify(&server_key.verifying_key()).is_ok());
+
+    // the key as printed by the server parses back
+    let printed = hex::encode(server_key.verifying_key().as_bytes());
+    assert_eq!(parse_public_key(&printed).unwrap(), server_key.verifying_key());
+
+    println!("✅ Signed parameter roundtrip passed!");
+}
+
+#[test]
+fn test_substituted_parameters_are_rejected() {
+    println!("🔏 Testing parameter substitution is detected");
+
+    let server_key = key(1);
+    let bundle = SignedParameters::sign(&rfc5114(), &server_key);
+
+    // a MITM swaps beta but keeps the genuine signature
+    let mut swapped = bundle.clone();
+    swapped.beta = rfc5114().alpha.to_bytes_be();
+    assert_eq!(swapped.verify(&server_key.verifying_key()), Err(ParametersError::BadSignature));
+
+    // ...or re-signs with their own key
+    let resigned = SignedParameters::sign(&rfc5114(), &key(2));
+    assert_eq!(resigned.verify(&server_key.verifying_key()), Err(ParametersError::UntrustedKey));
+
+    // a validly signed but broken group is refused too (4 does not have order 7 mod 23)
+    let broken = ZKP {
+        p: BigUint::from(23u32),
+        q: BigUint::from(7u32),
+        alpha: BigUint::from(4u32),
+        beta: BigUint::from(9u32),
+    };
+    let signed_broken = SignedParameters::sign(&broken, &server_key);
+    assert!(matches!(
+        signed_broken.verify(&server_key.verifying_key()),
+        Err(ParametersError::InvalidGroup(_))
+    ));
+
+    println!("✅ Parameter substitution test passed!");
+}
+
+#[test]
+fn test_signing_key_is_created_once() {
+    let dir = std::env::temp_dir().join(format!("zkp-params-key-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("params.key");
+
+    let created = load_or_create_signing_key(&path).unwrap();
+    let loaded = load_or_create_signing_key(&path).unwrap();
+    assert_eq!(created.to_bytes(), loaded.to_bytes(), "the key must survive restarts");
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
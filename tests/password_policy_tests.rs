@@ -0,0 +1,55 @@
+// Tests for the client-side password policy
+use rust_zkp_chaum_pedersen::password_policy::{
+    DefaultPolicy, PasswordPolicy, Weakness, DEFAULT_POLICY_VERSION,
+};
+
+#[test]
+fn test_default_policy() {
+    println!("🔑 Testing the default password policy");
+
+    let policy = DefaultPolicy::default();
+    assert_eq!(policy.version(), DEFAULT_POLICY_VERSION);
+
+    assert!(policy.check("alice", "correct horse battery staple").is_empty());
+    assert!(policy.check("alice", "Tr0ub4dor&3x").is_empty());
+
+    assert_eq!(
+        policy.check("alice", "short1"),
+        vec![Weakness::TooShort { min_length: 10 }]
+    );
+    assert_eq!(
+        policy.check("alice", "onlylowercaseletters"),
+        vec![Weakness::TooFewCharacterClasses { min_classes: 2 }]
+    );
+    assert!(policy.check("alice", "Password123").contains(&Weakness::Common));
+    assert!(policy.check("alice", "Alice-2024-spring").contains(&Weakness::ContainsUsername));
+
+    println!("✅ Password policy test passed!");
+}
+
+/// policies are pluggable, e.g. an organisation's own rules
+struct NoSpaces;
+
+impl PasswordPolicy for NoSpaces {
+    fn version(&self) -> u32 {
+        7
+    }
+
+    fn check(&self, _user: &str, password: &str) -> Vec<Weakness> {
+        match password.contains(' ') {
+            true => vec![Weakness::TooFewCharacterClasses { min_classes: 1 }],
+            false => Vec::new(),
+        }
+    }
+}
+
+#[test]
+fn test_custom_policy_as_trait_object() {
+    let policies: Vec<Box<dyn PasswordPolicy>> = vec![Box::new(DefaultPolicy::default()), Box::new(NoSpaces)];
+    let rejected: Vec<u32> = policies
+        .iter()
+        .filter(|policy| !policy.check("bob", "a long pass phrase 42").is_empty())
+        .map(|policy| policy.version())
+        .collect();
+    assert_eq!(rejected, vec![7]);
+}
@@ -0,0 +1,86 @@
+// Tests for the login policy hook and its rule language
+use std::time::{Duration, SystemTime};
+
+use rust_zkp_chaum_pedersen::policy::{AllowAll, Decision, LoginContext, Policy, PolicyHook, RuleSet};
+
+fn login(user: &str, platform: &str, risk_score: u32, new_device: bool, hour: u64) -> LoginContext {
+    LoginContext {
+        user: user.to_string(),
+        device_id: "device-1".to_string(),
+        app_version: "1.2.0".to_string(),
+        platform: platform.to_string(),
+        source: "127.0.0.1:40000".to_string(),
+        new_device,
+        risk_score,
+        time: SystemTime::UNIX_EPOCH + Duration::from_secs(hour * 3600),
+    }
+}
+
+#[test]
+fn test_allow_all_is_the_default() {
+    println!("🚪 Testing the default policy");
+
+    let context = login("alice", "linux", 100, true, 3);
+    assert_eq!(AllowAll.decide(&context), Decision::Allow);
+    assert_eq!(Policy::default().decide(&context), Decision::Allow);
+
+    println!("✅ Every verified login is allowed by default!");
+}
+
+#[test]
+fn test_rules_first_match_wins() {
+    println!("📜 Testing policy rules");
+
+    let rules = RuleSet::parse(
+        "# risky logins first\n\
+         deny    if risk >= 80\n\
+         allow   if user == \"ci-bot\"\n\
+         step_up if new_device && platform == \"android\"\n\
+         \n\
+         deny    if user == \"root\" || hour < 6   # nobody works that early\n",
+    )
+    .unwrap();
+
+    assert_eq!(rules.decide(&login("alice", "linux", 10, false, 12)), Decision::Allow);
+    assert_eq!(
+        rules.decide(&login("alice", "linux", 80, false, 12)),
+        Decision::Deny("denied by policy rule on line 2".to_string())
+    );
+    // the earlier allow shadows the hour rule
+    assert_eq!(rules.decide(&login("ci-bot", "linux", 0, false, 3)), Decision::Allow);
+    assert_eq!(rules.decide(&login("alice", "android", 40, true, 12)), Decision::StepUp);
+    assert_eq!(rules.decide(&login("alice", "android", 0, false, 12)), Decision::Allow);
+    assert_eq!(
+        rules.decide(&login("root", "linux", 0, false, 12)),
+        Decision::Deny("denied by policy rule on line 6".to_string())
+    );
+    assert!(matches!(rules.decide(&login("alice", "linux", 0, false, 5)), Decision::Deny(_)));
+
+    // rules can be shared behind a Policy
+    let policy = Policy::new(rules);
+    assert_eq!(policy.decide(&login("alice", "android", 40, true, 12)), Decision::StepUp);
+
+    println!("✅ Rules are checked in order!");
+}
+
+#[test]
+fn test_bad_rules_are_rejected() {
+    println!("🚫 Testing policy rule errors");
+
+    for (text, line) in [
+        ("deny risk >= 80", 1),
+        ("\nblock if risk >= 80", 2),
+        ("deny if colour == \"red\"", 1),
+        ("deny if user == root", 1),
+        ("deny if risk >= high", 1),
+        ("deny if new_device == maybe", 1),
+        ("allow if user == \"a\"\ndeny if risk", 2),
+    ] {
+        let error = RuleSet::parse(text).unwrap_err();
+        println!("   {:?} -> {}", text, error);
+        assert_eq!(error.line, line);
+    }
+    assert!(RuleSet::parse("# nothing but a comment\n\n").is_ok());
+
+    println!("✅ Bad rules are rejected with their line!");
+}
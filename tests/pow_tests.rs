@@ -0,0 +1,111 @@
+// Tests for the proofs of work a server under load asks logins for
+use std::process::{Command, Stdio};
+use std::time::Duration;
+
+use tonic::Code;
+
+use rust_zkp_chaum_pedersen::api::v1::{
+    auth_client::AuthClient, AuthenticationAnswerRequest, AuthenticationChallengeRequest, AuthenticationChallengeResponse,
+    RegisterRequest,
+};
+use rust_zkp_chaum_pedersen::pow::{self, PowPolicy};
+use rust_zkp_chaum_pedersen::types::Challenge;
+use rust_zkp_chaum_pedersen::ZKP;
+
+mod common;
+use common::Server;
+
+#[test]
+fn test_proof_of_work() {
+    println!("⛏️  Testing proofs of work");
+
+    let (auth_id, c) = ("01HF0000000000000000000000", [7u8; 32]);
+    let nonce = pow::solve(auth_id, &c, 12).unwrap();
+    assert!(pow::verify(auth_id, &c, 12, nonce));
+    assert!(pow::stamp(auth_id, &c, nonce)[0] == 0 && pow::stamp(auth_id, &c, nonce)[1] < 0x10, "12 leading zero bits");
+    assert_eq!(pow::solve(auth_id, &c, 0), Some(0));
+    assert!(pow::verify(auth_id, &c, 0, 12345), "no work asked, any nonce");
+
+    // bound to the challenge: another auth_id or c needs its own
+    let failing = |auth_id: &str, c: &[u8]| !pow::verify(auth_id, c, 12, nonce);
+    assert!(failing("01HF0000000000000000000001", &c) || failing("01HF0000000000000000000002", &c));
+    assert!(failing(auth_id, &[8; 32]) || failing(auth_id, &[9; 32]));
+
+    // a client doesn't work forever for a greedy server
+    assert_eq!(pow::solve(auth_id, &c, pow::MAX_DIFFICULTY + 1), None);
+
+    let policy = PowPolicy { difficulty: 16, pending_threshold: 100 };
+    assert_eq!((policy.difficulty(0), policy.difficulty(99), policy.difficulty(100)), (0, 0, 16));
+    assert_eq!(PowPolicy { difficulty: 16, pending_threshold: 0 }.difficulty(0), 16, "always");
+    assert_eq!(PowPolicy::default().difficulty(1_000_000), 0, "never");
+
+    println!("✅ Proofs of work are bound to their challenge!");
+}
+
+#[tokio::test]
+async fn test_server_asks_under_load() {
+    println!("⛏️  Testing a server that asks for proofs of work once challenges pile up");
+
+    let _server = Server(
+        Command::new(env!("CARGO_BIN_EXE_server"))
+            .args(["--listen", "127.0.0.1:50105", "--pow-difficulty", "10", "--pow-pending-threshold", "2"])
+            .stdout(Stdio::null())
+            .spawn()
+            .expect("Failed to start server process"),
+    );
+    let mut client = None;
+    for _ in 0..50 {
+        if let Ok(connected) = AuthClient::connect("http://127.0.0.1:50105").await {
+            client = Some(connected);
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    let mut client = client.expect("the test server didn't come up");
+
+    let zkp = ZKP::builder().rfc5114().build().unwrap();
+    let x = ZKP::generate_random_number_below(&zkp.q);
+    let (y1, y2) = zkp.public_key(&x).to_bytes_be();
+    client.register(RegisterRequest { user: "pow_alice".to_string(), y1, y2, ..Default::default() }).await.unwrap();
+
+    let challenger = client.clone();
+    let challenge = || {
+        let mut client = challenger.clone();
+        let k = ZKP::generate_random_number_below(&zkp.q);
+        let (r1, r2) = zkp.commitment(&k).to_bytes_be();
+        async move {
+            let request = AuthenticationChallengeRequest { user: "pow_alice".to_string(), r1, r2, ..Default::default() };
+            (k, client.create_authentication_challenge(request).await.unwrap().into_inner())
+        }
+    };
+    let answer = |k, challenge: &AuthenticationChallengeResponse, pow_nonce| AuthenticationAnswerRequest {
+        auth_id: challenge.auth_id.clone(),
+        s: zkp.respond(&k, &Challenge::from_bytes_be(&challenge.c), &x).to_bytes_be(),
+        pow_nonce,
+        ..Default::default()
+    };
+
+    // nothing pending: no work
+    let (k, quiet) = challenge().await;
+    assert_eq!(quiet.pow_difficulty, 0);
+    client.verify_authentication(answer(k, &quiet, 0)).await.expect("no proof of work needed");
+
+    // two challenges left unanswered, then the third one asks; the
+    // answered one is used up, so it doesn't count
+    challenge().await;
+    let (_, calm) = challenge().await;
+    assert_eq!(calm.pow_difficulty, 0, "only one challenge was waiting");
+    let (k, busy) = challenge().await;
+    assert_eq!(busy.pow_difficulty, 10);
+    let wrong = (0..).find(|&nonce| !pow::verify(&busy.auth_id, &busy.c, 10, nonce)).unwrap();
+    let status = client.verify_authentication(answer(k.clone(), &busy, wrong)).await.unwrap_err();
+    assert_eq!(status.code(), Code::FailedPrecondition, "{}", status.message());
+    assert!(status.message().contains("proof of work"));
+
+    // the challenge is still good for an answer with the work done
+    let nonce = pow::solve(&busy.auth_id, &busy.c, busy.pow_difficulty).unwrap();
+    let session = client.verify_authentication(answer(k, &busy, nonce)).await.expect("with the work done").into_inner();
+    assert!(!session.session_id.is_empty());
+
+    println!("✅ Floods of challenges cost the flooder!");
+}
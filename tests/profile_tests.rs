@@ -0,0 +1,60 @@
+// Tests for the named server profiles used by the zkp-auth CLI
+use std::path::PathBuf;
+
+use rust_zkp_chaum_pedersen::profile::{Profile, ProfileStore};
+
+fn temp_profiles_path(name: &str) -> PathBuf {
+    std::env::temp_dir()
+        .join(format!("zkp-auth-profiles-{}-{}", name, std::process::id()))
+        .join("profiles.json")
+}
+
+fn profile(endpoint: &str, user: Option<&str>) -> Profile {
+    Profile {
+        endpoint: endpoint.to_string(),
+        user: user.map(str::to_string),
+        ca_cert: None,
+        server_key: None,
+        tofu: false,
+        compression: None,
+    }
+}
+
+#[test]
+fn test_first_profile_becomes_active() {
+    let mut store = ProfileStore::default();
+    store.add("staging", profile("http://staging:50051", Some("alice")));
+    store.add("prod", profile("http://prod:50051", None));
+
+    let (name, active) = store.get(None).expect("there should be an active profile");
+    assert_eq!(name, "staging");
+    assert_eq!(active.user.as_deref(), Some("alice"));
+
+    store.set_active("prod").unwrap();
+    assert_eq!(store.get(None).unwrap().0, "prod");
+    assert!(store.set_active("missing").is_err());
+
+    store.remove("prod");
+    assert!(store.get(None).is_none(), "removing the active profile clears it");
+    assert_eq!(store.get(Some("staging")).unwrap().1.endpoint, "http://staging:50051");
+}
+
+#[test]
+fn test_profiles_roundtrip_through_disk() {
+    let path = temp_profiles_path("roundtrip");
+
+    // a missing file is just an empty store
+    let empty = ProfileStore::load(&path).unwrap();
+    assert_eq!(empty, ProfileStore::default());
+
+    let mut store = ProfileStore::default();
+    store.add("local", profile("http://127.0.0.1:50051", Some("bob")));
+    store.add("tenant-a", profile("http://a.example:50051", None));
+    store.set_active("tenant-a").unwrap();
+    store.save(&path).unwrap();
+
+    let loaded = ProfileStore::load(&path).unwrap();
+    assert_eq!(loaded, store);
+
+    std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+}
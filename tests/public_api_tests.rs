@@ -0,0 +1,39 @@
+// Snapshot of the stable public API
+//
+// Fails when the public surface changes. If the change is intended (and the
+// version bumped accordingly), update the snapshot with:
+//   UPDATE_EXPECT=1 cargo test --test public_api_tests
+use std::process::Command;
+
+#[test]
+fn test_public_api_is_unchanged() {
+    println!("📜 Checking the public API against tests/public-api.txt");
+
+    // rustdoc JSON is nightly only
+    let nightly = Command::new("rustup")
+        .args(["run", "nightly", "rustdoc", "--version"])
+        .output()
+        .map(|output| output.status.success())
+        .unwrap_or(false);
+    if !nightly {
+        println!("⚠️  No nightly toolchain - skipping public API check");
+        return;
+    }
+
+    let json = rustdoc_json::Builder::default()
+        .toolchain("nightly")
+        .manifest_path(concat!(env!("CARGO_MANIFEST_DIR"), "/Cargo.toml"))
+        .quiet(true)
+        .build()
+        .expect("could not build rustdoc JSON");
+    // blanket and auto trait impls change with the compiler, not with us
+    let api = public_api::Builder::from_rustdoc_json(json)
+        .omit_blanket_impls(true)
+        .omit_auto_trait_impls(true)
+        .build()
+        .expect("could not read rustdoc JSON");
+
+    expect_test::expect_file!["public-api.txt"].assert_eq(&api.to_string());
+
+    println!("✅ Public API unchanged!");
+}
@@ -0,0 +1,92 @@
+// Tests for audit events as CloudEvents, and the sink that publishes them
+use std::time::{Duration, UNIX_EPOCH};
+
+use serde_json::Value;
+
+use rust_zkp_chaum_pedersen::audit::{AuditEvent, Outcome};
+
+fn event(user: &str) -> AuditEvent {
+    AuditEvent {
+        time: UNIX_EPOCH + Duration::from_millis(1_700_000_000_123),
+        action: "login",
+        outcome: Outcome::Failure,
+        user: user.to_string(),
+        source: "10.0.0.7:50000".to_string(),
+        trace_id: "trace".to_string(),
+        detail: "bad proof".to_string(),
+    }
+}
+
+#[test]
+fn test_cloud_event() {
+    println!("📣 Testing audit events as CloudEvents");
+
+    let cloud_event: Value = serde_json::from_str(&event("alice").to_cloud_event("01ID", "urn:zkp-auth:host-1")).unwrap();
+    assert_eq!(cloud_event["specversion"], "1.0");
+    assert_eq!((cloud_event["id"].as_str(), cloud_event["source"].as_str()), (Some("01ID"), Some("urn:zkp-auth:host-1")));
+    assert_eq!(cloud_event["type"], "zkp-auth.login.failure");
+    assert_eq!(cloud_event["subject"], "alice");
+    assert_eq!(cloud_event["datacontenttype"], "application/json");
+    assert_eq!(cloud_event["time"], cloud_event["data"]["time"]);
+    let data: Value = serde_json::from_str(&event("alice").to_json()).unwrap();
+    assert_eq!(cloud_event["data"], data, "the data is the audit log line");
+
+    // no user, no subject
+    let cloud_event: Value = serde_json::from_str(&event("").to_cloud_event("01ID", "urn:zkp-auth:host-1")).unwrap();
+    assert!(cloud_event.get("subject").is_none());
+
+    println!("✅ Audit events are CloudEvents!");
+}
+
+#[cfg(any(feature = "kafka", feature = "nats"))]
+#[test]
+fn test_publish_sink() {
+    use std::io;
+    use std::sync::{mpsc, Arc, Mutex};
+
+    use rust_zkp_chaum_pedersen::audit::Audit;
+    use rust_zkp_chaum_pedersen::publish::{self, Message, PublishSink, Transport};
+
+    println!("📣 Testing audit events published in the background, with retries");
+
+    assert_eq!([1, 2, 3, 5, 6, 20].map(publish::backoff), [1, 2, 4, 16, 30, 30].map(Duration::from_secs), "doubling, up to 30s");
+    assert!(publish::default_source().starts_with("urn:zkp-auth:"));
+
+    // a broker that is down for the first attempt
+    struct Flaky {
+        attempts: Arc<Mutex<usize>>,
+        published: mpsc::Sender<Vec<Message>>,
+    }
+    impl Transport for Flaky {
+        fn publish(&mut self, batch: &[Message]) -> io::Result<()> {
+            let mut attempts = self.attempts.lock().unwrap();
+            *attempts += 1;
+            if *attempts == 1 {
+                return Err(io::Error::other("connection refused"));
+            }
+            self.published.send(batch.to_vec()).unwrap();
+            Ok(())
+        }
+    }
+    let attempts = Arc::new(Mutex::new(0));
+    let (published, receiver) = mpsc::channel();
+    let sink = PublishSink::start("the test broker", "urn:test", Flaky { attempts: attempts.clone(), published }).unwrap();
+    let mut audit = Audit::default();
+    audit.add(sink);
+    audit.record(&event("alice"));
+    audit.record(&event("bob"));
+
+    let mut messages = Vec::new();
+    while messages.len() < 2 {
+        messages.extend(receiver.recv_timeout(Duration::from_secs(10)).expect("published after a retry"));
+    }
+    assert!(*attempts.lock().unwrap() >= 2);
+    assert_eq!(messages.iter().map(|message| message.key.as_str()).collect::<Vec<_>>(), ["alice", "bob"], "in order");
+    assert_eq!(messages[0].action, "login");
+    let cloud_event: Value = serde_json::from_str(&messages[0].payload).unwrap();
+    assert_eq!((cloud_event["source"].as_str(), cloud_event["subject"].as_str()), (Some("urn:test"), Some("alice")));
+    let ids: Vec<Value> = messages.iter().map(|message| serde_json::from_str::<Value>(&message.payload).unwrap()["id"].clone()).collect();
+    assert_ne!(ids[0], ids[1]);
+
+    println!("✅ Audit events get to the broker once it is back!");
+}
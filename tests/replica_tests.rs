@@ -0,0 +1,183 @@
+// Tests for read replicas: verification-only servers following the primary's user store
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::time::Duration;
+
+use num_bigint::BigUint;
+use tonic::transport::Channel;
+use tonic::Code;
+
+use rust_zkp_chaum_pedersen::api::v1::{
+    auth_client::AuthClient, AuthenticationAnswerRequest, AuthenticationChallengeRequest, EnrollDeviceRequest,
+    RegisterRequest,
+};
+use rust_zkp_chaum_pedersen::store::{StoredUser, UserStore};
+use rust_zkp_chaum_pedersen::types::Challenge;
+use rust_zkp_chaum_pedersen::ZKP;
+
+mod common;
+use common::{auth_client, temp_dir, Server};
+
+fn registration(user: &str) -> RegisterRequest {
+    RegisterRequest { user: user.to_string(), y1: vec![1, 2, 3], y2: vec![4, 5, 6], ..Default::default() }
+}
+
+#[test]
+fn test_follow_journal() {
+    println!("📖 Testing a user store followed by a read replica");
+
+    let dir = temp_dir("follow");
+    let path = dir.join("users.jsonl");
+    let replica = UserStore::follow(&path, None);
+    assert!(replica.is_read_only());
+    assert_eq!(replica.reload().unwrap_err().kind(), std::io::ErrorKind::NotFound, "no journal yet");
+
+    let (primary, _) = UserStore::open(&path).unwrap();
+    assert!(!primary.is_read_only());
+    primary.append(&StoredUser::from(&registration("alice"))).unwrap();
+    let users = replica.reload().unwrap().unwrap();
+    assert_eq!(users.iter().map(|stored| stored.user.as_str()).collect::<Vec<_>>(), ["alice"]);
+    assert_eq!(replica.reload().unwrap(), None, "unchanged");
+
+    primary.append(&StoredUser::from(&registration("bob"))).unwrap();
+    primary.set_enabled("alice", false).unwrap();
+    let users = replica.reload().unwrap().unwrap();
+    assert_eq!(users.len(), 2);
+    assert!(users[0].disabled);
+
+    // a line the primary is still writing waits for the next look, and stays
+    let journal = std::fs::read(&path).unwrap();
+    std::fs::write(&path, [journal.as_slice(), b"{\"user\":\"car"].concat()).unwrap();
+    assert_eq!(replica.reload().unwrap().unwrap().len(), 2);
+    assert!(std::fs::read(&path).unwrap().ends_with(b"{\"user\":\"car"), "never truncated by the replica");
+    std::fs::write(&path, &journal).unwrap();
+
+    // nothing is ever written through it
+    let error = replica.append(&StoredUser::from(&registration("carol"))).unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::PermissionDenied);
+    assert_eq!(replica.erase("bob").unwrap_err().kind(), std::io::ErrorKind::PermissionDenied);
+    assert_eq!(std::fs::read(&path).unwrap(), journal);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+    println!("✅ A replica reads the journal and leaves it alone!");
+}
+
+fn start(port: u16, user_store: &Path, args: &[&str]) -> Server {
+    Server(
+        Command::new(env!("CARGO_BIN_EXE_server"))
+            .args(["--listen", &format!("127.0.0.1:{}", port)])
+            .args(["--user-store", user_store.to_str().unwrap()])
+            .args(args)
+            .stdout(Stdio::null())
+            .spawn()
+            .expect("Failed to start server process"),
+    )
+}
+
+async fn register(client: &mut AuthClient<Channel>, user: &str, x: &BigUint) -> Result<(), tonic::Status> {
+    let zkp = ZKP::builder().rfc5114().build().unwrap();
+    let (y1, y2) = zkp.public_key(x).to_bytes_be();
+    client.register(RegisterRequest { user: user.to_string(), y1, y2, ..Default::default() }).await?;
+    Ok(())
+}
+
+async fn login(client: &mut AuthClient<Channel>, user: &str, x: &BigUint) -> Result<String, tonic::Status> {
+    let zkp = ZKP::builder().rfc5114().build().unwrap();
+    let k = ZKP::generate_random_number_below(&zkp.q);
+    let (r1, r2) = zkp.commitment(&k).to_bytes_be();
+    let request = AuthenticationChallengeRequest { user: user.to_string(), r1, r2, ..Default::default() };
+    let challenge = client.create_authentication_challenge(request).await?.into_inner();
+    let s = zkp.respond(&k, &Challenge::from_bytes_be(&challenge.c), x).to_bytes_be();
+    let request = AuthenticationAnswerRequest { auth_id: challenge.auth_id, s, ..Default::default() };
+    Ok(client.verify_authentication(request).await?.into_inner().session_id)
+}
+
+#[tokio::test]
+async fn test_replica_serves_logins() {
+    println!("📖 Testing logins served by a read replica");
+
+    let dir = temp_dir("e2e");
+    let path = dir.join("users.jsonl");
+    let _primary = start(50100, &path, &[]);
+    let mut primary = auth_client(50100).await;
+    let _replica = start(50101, &path, &["--read-replica", "--primary", "http://127.0.0.1:50100", "--replica-refresh", "1"]);
+    let mut replica = auth_client(50101).await;
+    let zkp = ZKP::builder().rfc5114().build().unwrap();
+
+    // registering with the replica lands on the primary, and logs in on both right away
+    let alice = ZKP::generate_random_number_below(&zkp.q);
+    register(&mut replica, "alice", &alice).await.unwrap();
+    login(&mut replica, "alice", &alice).await.unwrap();
+    login(&mut primary, "alice", &alice).await.unwrap();
+    assert_eq!(register(&mut replica, "alice", &alice).await.unwrap_err().code(), Code::AlreadyExists, "the primary's answer");
+
+    // users registered with the primary show up with the replica's next look
+    let bob = ZKP::generate_random_number_below(&zkp.q);
+    register(&mut primary, "bob", &bob).await.unwrap();
+    let mut logged_in = login(&mut replica, "bob", &bob).await;
+    for _ in 0..50 {
+        if logged_in.is_ok() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        logged_in = login(&mut replica, "bob", &bob).await;
+    }
+    let session_id = logged_in.unwrap();
+
+    // other writes belong on the primary
+    let x = ZKP::generate_random_number_below(&zkp.q);
+    let (y1, y2) = zkp.public_key(&x).to_bytes_be();
+    let request = EnrollDeviceRequest { user: "bob".to_string(), session_id, name: "laptop".to_string(), y1, y2 };
+    assert_eq!(replica.enroll_device(request).await.unwrap_err().code(), Code::FailedPrecondition);
+
+    // without a primary, registrations are refused
+    let replica_path = dir.join("copy.jsonl");
+    std::fs::copy(&path, &replica_path).unwrap();
+    let journal = std::fs::read(&replica_path).unwrap();
+    let _standalone = start(50102, &replica_path, &["--read-replica"]);
+    let mut standalone = auth_client(50102).await;
+    login(&mut standalone, "alice", &alice).await.unwrap();
+    let carol = ZKP::generate_random_number_below(&zkp.q);
+    assert_eq!(register(&mut standalone, "carol", &carol).await.unwrap_err().code(), Code::FailedPrecondition);
+    assert_eq!(std::fs::read(&replica_path).unwrap(), journal, "the journal is only read");
+
+    std::fs::remove_dir_all(&dir).unwrap();
+    println!("✅ Replicas verify, the primary writes!");
+}
+
+#[tokio::test]
+async fn test_erased_user_takes_their_challenges() {
+    println!("📖 Testing answers for a user the primary erased");
+
+    let dir = temp_dir("erased");
+    let path = dir.join("users.jsonl");
+    let (journal, _) = UserStore::open(&path).unwrap();
+    let zkp = ZKP::builder().rfc5114().build().unwrap();
+    let x = ZKP::generate_random_number_below(&zkp.q);
+    let (y1, y2) = zkp.public_key(&x).to_bytes_be();
+    journal.append(&StoredUser::from(&RegisterRequest { user: "alice".to_string(), y1, y2, ..Default::default() })).unwrap();
+    journal.append(&StoredUser::from(&registration("bob"))).unwrap();
+    let _replica = start(50113, &path, &["--read-replica", "--replica-refresh", "1"]);
+    let mut replica = auth_client(50113).await;
+
+    // a challenge is outstanding when the primary erases alice
+    let k = ZKP::generate_random_number_below(&zkp.q);
+    let (r1, r2) = zkp.commitment(&k).to_bytes_be();
+    let request = |user: &str| AuthenticationChallengeRequest { user: user.to_string(), r1: r1.clone(), r2: r2.clone(), ..Default::default() };
+    let challenge = replica.create_authentication_challenge(request("alice")).await.unwrap().into_inner();
+    assert!(journal.erase("alice").unwrap());
+    for _ in 0..50 {
+        match replica.create_authentication_challenge(request("alice")).await {
+            Err(status) if status.code() == Code::NotFound => break,
+            _ => tokio::time::sleep(Duration::from_millis(100)).await,
+        }
+    }
+
+    let s = zkp.respond(&k, &Challenge::from_bytes_be(&challenge.c), &x).to_bytes_be();
+    let answer = AuthenticationAnswerRequest { auth_id: challenge.auth_id, s, ..Default::default() };
+    assert_eq!(replica.verify_authentication(answer).await.unwrap_err().code(), Code::NotFound);
+    assert!(replica.create_authentication_challenge(request("bob")).await.is_ok(), "the replica is still up");
+
+    std::fs::remove_dir_all(&dir).unwrap();
+    println!("✅ Erased users can't answer old challenges!");
+}
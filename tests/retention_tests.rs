@@ -0,0 +1,219 @@
+// Tests for retention policies on audit events and sessions
+use std::io::{Read, Write};
+use std::process::{Command, Stdio};
+use std::time::{Duration, SystemTime};
+
+use num_bigint::BigUint;
+use tonic::transport::Channel;
+use tonic::Request;
+
+use rust_zkp_chaum_pedersen::admin::AdminToken;
+use rust_zkp_chaum_pedersen::api::v1::{
+    auth_client::AuthClient, AuthenticationAnswerRequest, AuthenticationChallengeRequest, IntrospectSessionRequest,
+    RegisterRequest,
+};
+use rust_zkp_chaum_pedersen::audit::{parse_rfc3339, AuditEvent, AuditSink, JsonlSink, Outcome};
+use rust_zkp_chaum_pedersen::metrics::RetentionMetrics;
+use rust_zkp_chaum_pedersen::retention::{self, AuditRetention, RetentionPolicy};
+use rust_zkp_chaum_pedersen::types::Challenge;
+use rust_zkp_chaum_pedersen::ZKP;
+
+mod common;
+use common::{connect, Server};
+
+const DAY: Duration = Duration::from_secs(86400);
+
+#[test]
+fn test_retention_rules() {
+    println!("🗑️  Testing retention rules");
+
+    assert_eq!(retention::parse_age("90d").unwrap(), 90 * DAY);
+    assert_eq!(retention::parse_age("12h").unwrap(), Duration::from_secs(12 * 3600));
+    assert_eq!(retention::parse_age("1y").unwrap(), 365 * DAY);
+    for bad in ["", "90", "d", "-1d", "1.5d", "90 days", "99999999999999999999y"] {
+        assert!(retention::parse_age(bad).is_err(), "{:?}", bad);
+    }
+    assert_eq!(retention::format_age(14 * DAY), "2w");
+    assert_eq!(retention::format_age(Duration::from_secs(90)), "90s");
+
+    let rule: AuditRetention = "login:failure=90d".parse().unwrap();
+    assert_eq!((rule.action.as_deref(), rule.outcome.as_deref(), rule.keep), (Some("login"), Some("failure"), 90 * DAY));
+    assert_eq!(rule.to_string(), "login:failure=90d");
+    let any: AuditRetention = "*=1y".parse().unwrap();
+    assert_eq!((any.action, any.outcome), (None, None));
+    assert_eq!("*:denied=30d".parse::<AuditRetention>().unwrap().to_string(), "*:denied=30d");
+    assert!("login:lost=1d".parse::<AuditRetention>().is_err(), "not an outcome");
+    assert!("login".parse::<AuditRetention>().is_err(), "no age");
+
+    // the most specific rule decides, whatever the order
+    let policy = RetentionPolicy {
+        audit: ["*=1y", "login:failure=90d", "*:denied=30d", "login=2y"].iter().map(|rule| rule.parse().unwrap()).collect(),
+        sessions: None,
+    };
+    assert_eq!(policy.audit_keep("login", "failure"), Some(90 * DAY));
+    assert_eq!(policy.audit_keep("login", "denied"), Some(2 * 365 * DAY), "the action beats the outcome");
+    assert_eq!(policy.audit_keep("register", "denied"), Some(30 * DAY));
+    assert_eq!(policy.audit_keep("register", "success"), Some(365 * DAY));
+    assert_eq!(RetentionPolicy::default().audit_keep("login", "failure"), None, "kept for ever");
+
+    let now = SystemTime::now();
+    assert!(policy.audit_expired("login", "failure", now - 91 * DAY, now));
+    assert!(!policy.audit_expired("login", "failure", now - 89 * DAY, now));
+    assert!(!policy.session_expired(now - 1000 * DAY, now), "no session retention");
+
+    println!("✅ Rules parse and the most specific one wins!");
+}
+
+#[test]
+fn test_audit_log_purge() {
+    println!("🗑️  Testing purging the audit log");
+
+    let dir = std::env::temp_dir().join(format!("zkp-retention-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("audit.jsonl");
+    let now = SystemTime::now();
+    let event = |age: Duration, outcome: Outcome| AuditEvent {
+        time: now - age,
+        action: "login",
+        outcome,
+        user: "alice".to_string(),
+        source: String::new(),
+        trace_id: String::new(),
+        detail: String::new(),
+    };
+
+    // the times round-trip through the log
+    let line: serde_json::Value = serde_json::from_str(&event(DAY, Outcome::Success).to_json()).unwrap();
+    let time = parse_rfc3339(line["time"].as_str().unwrap()).unwrap();
+    assert!((now - DAY).duration_since(time).unwrap() < Duration::from_millis(1), "to the millisecond");
+    assert_eq!(parse_rfc3339("2023-11-14T22:13:20.250Z"), Some(SystemTime::UNIX_EPOCH + Duration::from_millis(1_700_000_000_250)));
+    assert_eq!(parse_rfc3339("2023-11-14 22:13:20"), None);
+
+    // small files, so the old events end up in rotated ones
+    let line_len = event(DAY, Outcome::Success).to_json().len() as u64 + 1;
+    let sink = JsonlSink::open(&path, 2 * line_len, 5).unwrap();
+    for age in [200, 100, 50, 10] {
+        sink.record(&event(age * DAY, Outcome::Failure)).unwrap();
+        sink.record(&event(age * DAY, Outcome::Success)).unwrap();
+    }
+    let policy = RetentionPolicy { audit: vec!["login:failure=90d".parse().unwrap()], sessions: None };
+    assert_eq!(sink.purge(&policy, now).unwrap(), 2);
+    assert_eq!(sink.purge(&policy, now).unwrap(), 0, "nothing left to purge");
+
+    // appends (and rotations) go on after the rewrite
+    sink.record(&event(Duration::ZERO, Outcome::Failure)).unwrap();
+    let mut events = Vec::new();
+    for file in ["audit.jsonl.4", "audit.jsonl.3", "audit.jsonl.2", "audit.jsonl.1", "audit.jsonl"] {
+        let text = std::fs::read_to_string(dir.join(file)).unwrap_or_default();
+        events.extend(text.lines().map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap()));
+    }
+    let outcomes: Vec<_> = events.iter().map(|event| event["outcome"].as_str().unwrap()).collect();
+    assert_eq!(outcomes, ["success", "success", "failure", "success", "failure", "success", "failure"]);
+    assert!(!dir.join("audit.jsonl.rewrite").exists());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+    println!("✅ Only events past their retention are dropped!");
+}
+
+#[test]
+fn test_retention_metrics() {
+    println!("🗑️  Testing the retention metrics");
+
+    let metrics = RetentionMetrics::default();
+    assert_eq!(metrics.render(), "", "nothing before the first run");
+    let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+    metrics.record(now, &[("audit_events", 3), ("sessions", 0)]);
+    metrics.record(now, &[("audit_events", 2), ("sessions", 1)]);
+    assert_eq!(metrics.purged("audit_events"), 5);
+
+    let text = metrics.render();
+    assert!(text.contains("# TYPE zkp_retention_purged_total counter\n"), "{}", text);
+    assert!(text.contains("zkp_retention_purged_total{record=\"audit_events\"} 5\n"), "{}", text);
+    assert!(text.contains("zkp_retention_purged_total{record=\"sessions\"} 1\n"), "{}", text);
+    assert!(text.contains("zkp_retention_last_run_timestamp_seconds 1700000000\n"), "{}", text);
+
+    println!("✅ Purges are counted for Prometheus!");
+}
+
+async fn login(client: &mut AuthClient<Channel>, zkp: &ZKP, x: &BigUint, user: &str) -> String {
+    let k = ZKP::generate_random_number_below(&zkp.q);
+    let (r1, r2) = zkp.commitment(&k).to_bytes_be();
+    let request = AuthenticationChallengeRequest { user: user.to_string(), r1, r2, ..Default::default() };
+    let response = client.create_authentication_challenge(request).await.unwrap().into_inner();
+    let s = zkp.respond(&k, &Challenge::from_bytes_be(&response.c), x).to_bytes_be();
+    let request = AuthenticationAnswerRequest { auth_id: response.auth_id, s, ..Default::default() };
+    client.verify_authentication(request).await.unwrap().into_inner().session_id
+}
+
+fn scrape(addr: std::net::SocketAddr) -> String {
+    let mut stream = std::net::TcpStream::connect(addr).unwrap();
+    stream.write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n").unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    response
+}
+
+#[tokio::test]
+async fn test_server_enforces_retention() {
+    println!("🗑️  Testing the server's retention task");
+
+    let dir = std::env::temp_dir().join(format!("zkp-retention-server-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    // a failed login from long ago, already in the log
+    let audit_log = dir.join("audit.jsonl");
+    let old = AuditEvent {
+        time: SystemTime::now() - 100 * DAY,
+        action: "login",
+        outcome: Outcome::Failure,
+        user: "mallory".to_string(),
+        source: "192.0.2.1:4000".to_string(),
+        trace_id: String::new(),
+        detail: "wrong answer".to_string(),
+    };
+    std::fs::write(&audit_log, format!("{}\n", old.to_json())).unwrap();
+    // introspection, which tells whether the session is still there, needs the admin token
+    let token_file = dir.join("admin-token");
+    std::fs::write(&token_file, "retention-test-admin-token").unwrap();
+    let token = AdminToken::load(&token_file).unwrap();
+    let metrics_addr = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+    let port = 50086;
+
+    let _server = Server(
+        Command::new(env!("CARGO_BIN_EXE_server"))
+            .args(["--listen", &format!("127.0.0.1:{}", port)])
+            .arg("--audit-log")
+            .arg(&audit_log)
+            .args(["--audit-retention", "login:failure=90d", "--session-retention", "2s", "--retention-interval", "1"])
+            .args(["--metrics-listen", &metrics_addr.to_string()])
+            .arg("--admin-token-file")
+            .arg(&token_file)
+            .stdout(Stdio::null())
+            .spawn()
+            .expect("Failed to start server process"),
+    );
+    let mut client = AuthClient::new(connect(port).await);
+
+    let zkp = ZKP::builder().rfc5114().build().unwrap();
+    let x = ZKP::generate_random_number_below(&zkp.q);
+    let (y1, y2) = zkp.public_key(&x).to_bytes_be();
+    client.register(RegisterRequest { user: "alice".to_string(), y1, y2, ..Default::default() }).await.unwrap();
+    let session = login(&mut client, &zkp, &x, "alice").await;
+    let introspect = IntrospectSessionRequest { token: session };
+    assert!(client.introspect_session(token.authorize(Request::new(introspect.clone()))).await.unwrap().into_inner().active);
+
+    tokio::time::sleep(Duration::from_millis(3500)).await;
+    let active = client.introspect_session(token.authorize(Request::new(introspect))).await.unwrap().into_inner().active;
+    let metrics = tokio::task::spawn_blocking(move || scrape(metrics_addr)).await.unwrap();
+
+    assert!(!active, "the session outlived its retention");
+    let log = std::fs::read_to_string(&audit_log).unwrap();
+    assert!(!log.contains("mallory"), "{}", log);
+    assert!(log.contains("\"register\""), "recent events stay: {}", log);
+    assert!(metrics.contains("zkp_retention_purged_total{record=\"audit_events\"} 1\n"), "{}", metrics);
+    assert!(metrics.contains("zkp_retention_purged_total{record=\"sessions\"} 1\n"), "{}", metrics);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+    println!("✅ Old events and sessions are purged on schedule!");
+}
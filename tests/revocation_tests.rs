@@ -0,0 +1,42 @@
+// Tests for the proof revocation list
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use rust_zkp_chaum_pedersen::revocation::RevocationList;
+
+#[test]
+fn test_revocation_list() {
+    println!("🚫 Testing the revocation list");
+
+    let mut list = RevocationList::default();
+    let now = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+
+    assert!(list.revoke("alice", "proof-1", "leaked", now));
+    assert!(!list.revoke("alice", "proof-1", "again", now), "revoking twice is a no-op");
+    assert_eq!(list.updated_at, 1_700_000_000);
+
+    let entry = list.lookup("alice", "proof-1").expect("revoked");
+    assert_eq!(entry.reason, "leaked");
+    // revocations are per user
+    assert!(!list.is_revoked("bob", "proof-1"));
+    assert!(!list.is_revoked("alice", "proof-2"));
+
+    println!("✅ Revocation list test passed!");
+}
+
+#[test]
+fn test_revocation_list_survives_a_restart() {
+    println!("💾 Testing revocation list persistence");
+
+    let path = std::env::temp_dir().join(format!("zkp-revocations-{}.json", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+
+    assert_eq!(RevocationList::load(&path).unwrap(), RevocationList::default());
+
+    let mut list = RevocationList::default();
+    list.revoke("alice", "proof-1", "", SystemTime::now());
+    list.save(&path).unwrap();
+    assert_eq!(RevocationList::load(&path).unwrap(), list);
+
+    std::fs::remove_file(&path).unwrap();
+    println!("✅ Persistence test passed!");
+}
@@ -0,0 +1,104 @@
+// Tests for the ristretto255 backend (cargo test --features ristretto)
+#![cfg(feature = "ristretto")]
+
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime};
+
+use curve25519_dalek::scalar::Scalar;
+use num_bigint::BigUint;
+use rust_zkp_chaum_pedersen::nizk::ValidityWindow;
+use rust_zkp_chaum_pedersen::ristretto::{self, Ristretto255, RistrettoPublicKey, PROOF_LEN};
+use rust_zkp_chaum_pedersen::ZKP;
+
+// the vector test seeds the process-wide generator, so the tests take turns
+static RNG: Mutex<()> = Mutex::new(());
+
+fn window() -> ValidityWindow {
+    ValidityWindow { not_before: 1_700_000_000, not_after: 1_700_000_300 }
+}
+
+fn at(unix: u64) -> SystemTime {
+    SystemTime::UNIX_EPOCH + Duration::from_secs(unix)
+}
+
+#[test]
+fn test_ristretto_proofs() {
+    let _turn = RNG.lock().unwrap_or_else(|e| e.into_inner());
+    println!("🔧 Testing 96-byte ristretto255 proofs");
+
+    let group = Ristretto255::new();
+    let x = Ristretto255::random_scalar();
+    let key = group.public_key(&x);
+
+    let started = Instant::now();
+    let proof = group.prove_non_interactive(&x, window(), b"login to example.com");
+    assert!(group.verify_non_interactive(&key, &proof, b"login to example.com", at(1_700_000_100)));
+    println!("   prove + verify: {:?}", started.elapsed());
+
+    let compact = ristretto::to_compact(&proof).unwrap();
+    assert_eq!(compact.len(), PROOF_LEN);
+    assert_eq!(ristretto::from_compact(&compact, proof.id.clone(), proof.window), proof);
+
+    // bound to the window, the context, the key and every byte
+    assert!(!group.verify_non_interactive(&key, &proof, b"login to example.com", at(1_700_000_301)));
+    assert!(!group.verify_non_interactive(&key, &proof, b"login to evil.com", at(1_700_000_100)));
+    let other = group.public_key(&Ristretto255::random_scalar());
+    assert!(!group.verify_non_interactive(&other, &proof, b"login to example.com", at(1_700_000_100)));
+    for i in 0..PROOF_LEN {
+        let mut tampered = compact;
+        tampered[i] ^= 1;
+        let tampered = ristretto::from_compact(&tampered, proof.id.clone(), proof.window);
+        assert!(!group.verify_non_interactive(&key, &tampered, b"login to example.com", at(1_700_000_100)), "byte {}", i);
+    }
+
+    // a Z_p-sized field isn't a ristretto proof
+    let mut long = proof.clone();
+    long.s.insert(0, 0);
+    assert_eq!(ristretto::to_compact(&long), None);
+    assert!(!group.verify_non_interactive(&key, &long, b"login to example.com", at(1_700_000_100)));
+
+    let bytes = key.to_bytes();
+    assert_eq!(RistrettoPublicKey::from_bytes(&bytes), Some(key));
+    assert_eq!(RistrettoPublicKey::from_bytes(&bytes[..63]), None);
+    assert_eq!(RistrettoPublicKey::from_bytes(&[0xff; 64]), None, "not a valid encoding");
+
+    println!("✅ ristretto255 proofs verify, and only for what they were made for!");
+}
+
+#[test]
+fn test_ristretto_vectors() {
+    let _turn = RNG.lock().unwrap_or_else(|e| e.into_inner());
+    println!("🔧 Testing ristretto255 test vectors");
+
+    // alpha is the standard base point: 1 and 2 times it, from the ristretto255 spec
+    let group = Ristretto255::new();
+    let key = group.public_key(&Scalar::ONE);
+    assert_eq!(hex::encode(&key.to_bytes()[..32]), "e2f2ae0a6abc4e71a884a961c500515f58e30b6aa582dd8db6a65945e08d2d76");
+    let key = group.public_key(&Scalar::from(2u64));
+    assert_eq!(hex::encode(&key.to_bytes()[..32]), "6a493210f7499cd17fecb510ae0cea23a110e8d5b901f8acadd3095c73a3b919");
+
+    // a proof for x = 42, pinned so the hash layout and beta can't change unnoticed
+    let x = ristretto::scalar_from_biguint(&BigUint::from(42u32));
+    assert_eq!(x, Scalar::from(42u64));
+    let key = group.public_key(&x);
+    assert_eq!(hex::encode(key.to_bytes()), KEY_42);
+
+    ZKP::with_rng(7);
+    let proof = group.prove_non_interactive(&x, window(), b"test vector");
+    ZKP::with_os_rng();
+    assert_eq!(proof.id, PROOF_42_ID);
+    assert_eq!(hex::encode(ristretto::to_compact(&proof).unwrap()), PROOF_42);
+
+    let vector = ristretto::from_compact(&hex::decode(PROOF_42).unwrap().try_into().unwrap(), PROOF_42_ID.to_string(), window());
+    assert!(group.verify_non_interactive(&key, &vector, b"test vector", at(1_700_000_000)));
+
+    // reduction mod the group order: l + 42 is 42
+    let order = BigUint::from_bytes_le((-Scalar::ONE).as_bytes()) + 1u32;
+    assert_eq!(ristretto::scalar_from_biguint(&(order + 42u32)), x);
+
+    println!("✅ The vectors match!");
+}
+
+const KEY_42: &str = "e00af9c74d9edb8ebcc160ceec97d531cbd6e2956f9e9162b8e9eda260e82e43e6c219914d67d7c8b0a0ee414db1ab93301ee23a0ac0e72833c3350d3b11a25d";
+const PROOF_42_ID: &str = "eIYugoZBFFUXSoy4";
+const PROOF_42: &str = "421b1d25065ccc536a3bdbf33263f3cec16fb01ae396f55841a61996f99fee2572a3b49c6ccf1d3218ea945f52caaf81276e2bf2fc823a231ed9cd58c4628916035bb4b68e44140d4cdd46e8cdf39a2d263129549be408f915921f82cbaf7e07";
@@ -0,0 +1,151 @@
+// Tests for the background job scheduler
+use std::io::{Read, Write};
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+
+use rust_zkp_chaum_pedersen::api::v1::{auth_client::AuthClient, RegisterRequest};
+use rust_zkp_chaum_pedersen::metrics;
+use rust_zkp_chaum_pedersen::scheduler::{Job, Schedule, Scheduler};
+use rust_zkp_chaum_pedersen::ZKP;
+
+mod common;
+use common::{connect, Server};
+
+/// 2023-11-14T22:13:20Z, a Tuesday
+const TUESDAY: u64 = 1_700_000_000;
+
+fn next(schedule: &str, after: u64) -> u64 {
+    let Schedule::Cron(cron) = schedule.parse::<Schedule>().unwrap() else {
+        panic!("{} is not a cron expression", schedule);
+    };
+    let next = cron.next_after(UNIX_EPOCH + Duration::from_secs(after)).unwrap();
+    next.duration_since(UNIX_EPOCH).unwrap().as_secs()
+}
+
+#[test]
+fn test_cron_schedules() {
+    println!("⏰ Testing cron expressions");
+
+    assert_eq!(next("0 3 * * *", TUESDAY), 1_700_017_200, "03:00 on Wednesday");
+    assert_eq!(next("*/15 * * * 1-5", TUESDAY), 1_700_000_100, "22:15 the same evening");
+    assert_eq!(next("13 22 * * *", TUESDAY - 20), TUESDAY - 20 + 86400, "strictly after: 22:13 tomorrow");
+    assert_eq!(next("@weekly", TUESDAY), 1_700_352_000, "Sunday 2023-11-19");
+    assert_eq!(next("0 0 * * 7", TUESDAY), 1_700_352_000, "7 is Sunday too");
+    assert_eq!(next("0 0 13 * 5", TUESDAY), 1_700_179_200, "the 13th or a Friday, whichever comes first");
+    assert_eq!(next("0 0 29 2 *", TUESDAY), 1_709_164_800, "2024-02-29");
+    assert_eq!(next("0,30 9-17/4 * * *", TUESDAY), 1_700_038_800, "09:00 on Wednesday");
+    assert_eq!(next("@hourly", TUESDAY), 1_700_002_800);
+
+    for bad in ["0 0 30 2 *", "60 * * * *", "* * *", "5-1 * * * *", "*/0 * * * *", "@every 0s", "@every soon", "@fortnightly"] {
+        assert!(bad.parse::<Schedule>().is_err(), "{:?}", bad);
+    }
+    assert_eq!("@every 5m".parse::<Schedule>().unwrap(), Schedule::Every(Duration::from_secs(300)));
+    assert_eq!("@every 300s".parse::<Schedule>().unwrap().to_string(), "@every 5m");
+    assert_eq!("  0  3 * *   * ".parse::<Schedule>().unwrap().to_string(), "0 3 * * *");
+
+    println!("✅ Cron expressions fire when cron would!");
+}
+
+/// a job that keeps state between runs
+struct Countdown(usize, Arc<AtomicUsize>);
+
+impl Job for Countdown {
+    async fn run(&mut self) {
+        if self.0 > 0 {
+            self.0 -= 1;
+            self.1.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_scheduler_runs_jobs() {
+    println!("⏰ Testing the scheduler");
+
+    let ticks = Arc::new(AtomicUsize::new(0));
+    let counted = Arc::new(AtomicUsize::new(0));
+    let mut scheduler = Scheduler::default();
+    let every = Schedule::Every(Duration::from_millis(50));
+    let job_ticks = ticks.clone();
+    scheduler.add("test_ticks", every.clone(), move || {
+        job_ticks.fetch_add(1, Ordering::SeqCst);
+        async {}
+    });
+    scheduler.add("test_countdown", every, Countdown(2, counted.clone()));
+    scheduler.add("test_cron", "0 0 1 1 *".parse().unwrap(), || async { panic!("not before new year") });
+    let names: Vec<_> = scheduler.jobs().map(|(name, _)| name).collect();
+    assert_eq!(names, ["test_ticks", "test_countdown", "test_cron"]);
+    assert_eq!(ticks.load(Ordering::SeqCst), 0, "nothing runs before start");
+
+    let handles = scheduler.start();
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    for handle in &handles {
+        handle.abort();
+    }
+
+    // at startup, then every 50ms
+    let runs = ticks.load(Ordering::SeqCst);
+    assert!((3..=8).contains(&runs), "{} runs", runs);
+    assert_eq!(counted.load(Ordering::SeqCst), 2, "the job's state carried over");
+    let recorded = metrics::jobs().get("test_ticks").unwrap();
+    assert_eq!(recorded.runs, runs as u64);
+    assert!(SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() - recorded.last_started <= 1);
+    assert!(metrics::jobs().get("test_cron").is_none());
+    assert!(metrics::jobs().render().contains("zkp_job_runs_total{job=\"test_countdown\"} "));
+
+    println!("✅ Jobs run on their schedules!");
+}
+
+fn scrape(addr: std::net::SocketAddr) -> String {
+    let mut stream = std::net::TcpStream::connect(addr).unwrap();
+    stream.write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n").unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    response
+}
+
+#[tokio::test]
+async fn test_server_schedules() {
+    println!("⏰ Testing --schedule on the server");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_server"))
+        .args(["--listen", "127.0.0.1:0", "--schedule", "sweeping=@every 1m"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("there is no such job"), "{}", String::from_utf8_lossy(&output.stderr));
+    let output = Command::new(env!("CARGO_BIN_EXE_server")).args(["--schedule", "memory=0 25 * * *"]).output().unwrap();
+    assert!(!output.status.success(), "there is no hour 25");
+
+    let metrics_addr = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+    let port = 50087;
+    let _server = Server(
+        Command::new(env!("CARGO_BIN_EXE_server"))
+            .args(["--listen", &format!("127.0.0.1:{}", port)])
+            .args(["--schedule", "parameter_scan=@every 1s", "--schedule", "memory=@every 1h"])
+            .args(["--metrics-listen", &metrics_addr.to_string()])
+            .stdout(Stdio::null())
+            .spawn()
+            .expect("Failed to start server process"),
+    );
+    let mut client = AuthClient::new(connect(port).await);
+    let zkp = ZKP::builder().rfc5114().build().unwrap();
+    let (y1, y2) = zkp.public_key(&ZKP::generate_random_number_below(&zkp.q)).to_bytes_be();
+    client.register(RegisterRequest { user: "alice".to_string(), y1, y2, ..Default::default() }).await.unwrap();
+
+    tokio::time::sleep(Duration::from_millis(2500)).await;
+    let metrics = tokio::task::spawn_blocking(move || scrape(metrics_addr)).await.unwrap();
+    let runs = |job: &str| -> u64 {
+        let prefix = format!("zkp_job_runs_total{{job=\"{}\"}} ", job);
+        let line = metrics.lines().find(|line| line.starts_with(&prefix)).unwrap_or_else(|| panic!("no {} in {}", job, metrics));
+        line[prefix.len()..].parse().unwrap()
+    };
+    assert!(runs("parameter_scan") >= 3, "{}", metrics);
+    assert_eq!(runs("memory"), 1, "only the run at startup");
+    assert!(metrics.contains("zkp_users{group=\"modp\",hash=\"sha256\",generator=\"global\",kdf=\"legacy\"} 1\n"), "{}", metrics);
+
+    println!("✅ The server's jobs follow --schedule!");
+}
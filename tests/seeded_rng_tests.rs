@@ -0,0 +1,39 @@
+// Tests for the insecure seeded RNG mode used for reproducible demos.
+// They live in their own file because the seed is process-wide and would
+// otherwise leak into the other tests running in parallel.
+use rust_zkp_chaum_pedersen::ZKP;
+
+#[test]
+fn test_seeded_rng_is_reproducible() {
+    println!("🎲 Testing seeded random number generation");
+
+    let (_, _, _, q) = ZKP::get_constants();
+
+    let draw = || {
+        (
+            ZKP::generate_random_number_below(&q),
+            ZKP::generate_random_number_below(&q),
+            ZKP::generate_random_string(12),
+        )
+    };
+
+    assert!(!ZKP::is_rng_seeded(), "the OS CSPRNG must be the default");
+
+    ZKP::with_rng(42);
+    assert!(ZKP::is_rng_seeded());
+    let first = draw();
+
+    ZKP::with_rng(42);
+    let second = draw();
+    assert_eq!(first, second, "same seed should give the same numbers");
+
+    ZKP::with_rng(43);
+    let other = draw();
+    assert_ne!(first, other, "different seeds should give different numbers");
+
+    ZKP::with_os_rng();
+    assert!(!ZKP::is_rng_seeded());
+    assert_ne!(draw(), first);
+
+    println!("✅ Seeded RNG test passed!");
+}
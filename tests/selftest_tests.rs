@@ -0,0 +1,80 @@
+// Tests for the startup self-test
+use std::io::{BufRead, BufReader};
+use std::process::{Command, Stdio};
+
+use ed25519_dalek::SigningKey;
+use num_bigint::BigUint;
+
+use rust_zkp_chaum_pedersen::params::SignedParameters;
+use rust_zkp_chaum_pedersen::selftest;
+use rust_zkp_chaum_pedersen::ZKP;
+
+mod common;
+use common::Server;
+
+#[test]
+fn test_self_test() {
+    println!("🩺 Testing the self-test");
+
+    let zkp = ZKP::builder().rfc5114().build().unwrap();
+    let checks = selftest::run(&zkp, None).unwrap();
+    assert_eq!(checks[..4], ["known answers", "group", "round trip", "user generators"]);
+    assert!(!checks.contains(&"signed parameters"), "nothing signed");
+
+    let key = SigningKey::from_bytes(&[7; 32]);
+    let signed = SignedParameters::sign(&zkp, &key);
+    let checks = selftest::run(&zkp, Some((&signed, &key.verifying_key()))).unwrap();
+    assert!(checks.contains(&"signed parameters"), "{:?}", checks);
+
+    // a generator that isn't of order q
+    let broken = ZKP { alpha: BigUint::from(2u32), ..zkp.clone() };
+    let e = selftest::run(&broken, None).unwrap_err();
+    assert_eq!(e.check, "group");
+    assert!(e.to_string().starts_with("self-test group: "), "{}", e);
+
+    // a parameter file with a flipped bit, or signed by someone else
+    let mut corrupted = signed.clone();
+    corrupted.p[10] ^= 1;
+    let e = selftest::run(&zkp, Some((&corrupted, &key.verifying_key()))).unwrap_err();
+    assert_eq!(e.check, "signed parameters");
+    let other = SigningKey::from_bytes(&[8; 32]);
+    let e = selftest::run(&zkp, Some((&signed, &other.verifying_key()))).unwrap_err();
+    assert_eq!(e.check, "signed parameters");
+
+    // a valid bundle, but for a group the server doesn't use
+    let per_user = zkp.for_user("alice");
+    let e = selftest::run(&zkp, Some((&SignedParameters::sign(&per_user, &key), &key.verifying_key()))).unwrap_err();
+    assert!(e.reason.contains("another group"), "{}", e);
+
+    println!("✅ The self-test catches broken groups and parameter files!");
+}
+
+#[test]
+fn test_server_runs_self_test() {
+    println!("🩺 Testing the self-test at startup");
+
+    let dir = std::env::temp_dir().join(format!("zkp-selftest-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let mut server = Server(
+        Command::new(env!("CARGO_BIN_EXE_server"))
+            .args(["--listen", "127.0.0.1:50088"])
+            .arg("--params-key")
+            .arg(dir.join("params.key"))
+            .stdout(Stdio::piped())
+            .spawn()
+            .expect("Failed to start server process"),
+    );
+    let stdout = BufReader::new(server.0.stdout.take().unwrap());
+    let line = stdout
+        .lines()
+        .map(Result::unwrap)
+        .find(|line| line.starts_with("🩺"))
+        .expect("the server never reported its self-test");
+    assert!(line.contains("Self-test passed"), "{}", line);
+    assert!(line.contains("known answers") && line.contains("signed parameters"), "{}", line);
+
+    drop(server);
+    std::fs::remove_dir_all(&dir).unwrap();
+    println!("✅ The server checks its math before serving!");
+}
@@ -0,0 +1,205 @@
+// Tests for Shamir shares of the ID token key, and unsealing a server with them
+use std::path::Path;
+use std::process::{Command, Output, Stdio};
+use std::time::SystemTime;
+
+use tonic::transport::Channel;
+use tonic::Code;
+
+use rust_zkp_chaum_pedersen::admin::AdminToken;
+use rust_zkp_chaum_pedersen::api::v1::{
+    admin_client::AdminClient, auth_client::AuthClient, AuthenticationAnswerRequest, AuthenticationChallengeRequest,
+    IdTokenRequest, RegisterRequest, UnsealIdTokenKeyRequest,
+};
+use rust_zkp_chaum_pedersen::id_token;
+use rust_zkp_chaum_pedersen::params::parse_public_key;
+use rust_zkp_chaum_pedersen::shamir::{self, ShamirError, Share, Unsealer};
+use rust_zkp_chaum_pedersen::types::Challenge;
+use rust_zkp_chaum_pedersen::ZKP;
+
+mod common;
+use common::{connect, temp_dir, Server};
+
+const TOKEN: &str = "correct-horse-battery-staple";
+
+#[test]
+fn test_split_and_combine() {
+    println!("🔧 Testing Shamir splits of a 32-byte key");
+
+    let secret: Vec<u8> = (0..32).map(|i| i * 7 + 1).collect();
+    let shares = shamir::split(&secret, 3, 5).unwrap();
+    assert_eq!(shares.len(), 5);
+    assert!(shares.iter().all(|share| share.value.len() == 32 && share.value != secret));
+
+    // any 3 of the 5, in any order
+    for a in 0..5 {
+        for b in 0..5 {
+            for c in 0..5 {
+                if a == b || b == c || a == c {
+                    continue;
+                }
+                let subset = [shares[a].clone(), shares[b].clone(), shares[c].clone()];
+                assert_eq!(shamir::combine(&subset).unwrap(), secret, "shares {} {} {}", a, b, c);
+            }
+        }
+    }
+    assert_eq!(shamir::combine(&shares).unwrap(), secret, "more than enough is fine");
+    assert_eq!(shamir::combine(&shares[..2]), Err(ShamirError::TooFewShares { have: 2, need: 3 }));
+
+    // two shares of a 3-of-5 split fit any secret: with each candidate third
+    // value the combination comes out different, so the two say nothing alone
+    let mut seen = std::collections::HashSet::new();
+    for guess in 0..=255u8 {
+        let third = Share { value: vec![guess; 32], ..shares[2].clone() };
+        seen.insert(shamir::combine(&[shares[0].clone(), shares[1].clone(), third]).unwrap()[0]);
+    }
+    assert_eq!(seen.len(), 256);
+
+    // shares of another split, a repeated share and tampering don't combine to the secret
+    let other = shamir::split(&secret, 3, 5).unwrap();
+    assert_eq!(shamir::combine(&[shares[0].clone(), shares[1].clone(), other[2].clone()]), Err(ShamirError::Mismatched));
+    assert_eq!(shamir::combine(&[shares[0].clone(), shares[1].clone(), shares[1].clone()]), Err(ShamirError::DuplicateIndex(2)));
+    let mut tampered = shares[2].clone();
+    tampered.value[0] ^= 1;
+    assert_ne!(shamir::combine(&[shares[0].clone(), shares[1].clone(), tampered]).unwrap(), secret);
+
+    assert_eq!(shamir::split(&secret, 1, 5), Err(ShamirError::BadThreshold { threshold: 1, shares: 5 }));
+    assert_eq!(shamir::split(&secret, 4, 3), Err(ShamirError::BadThreshold { threshold: 4, shares: 3 }));
+    let all = shamir::split(&secret, 255, 255).unwrap();
+    assert_eq!(shamir::combine(&all).unwrap(), secret);
+
+    println!("✅ Any threshold of shares gives the key back, fewer don't!");
+}
+
+#[test]
+fn test_share_encoding_and_unsealer() {
+    println!("🔧 Testing share encoding and collecting shares one by one");
+
+    let secret = [42u8; 32];
+    let shares = shamir::split(&secret, 2, 3).unwrap();
+    for share in &shares {
+        let text = share.to_string();
+        assert_eq!(text.len(), 2 * (6 + 32));
+        assert_eq!(text.parse::<Share>().unwrap(), *share);
+        assert_eq!(format!(" {}\n", text).parse::<Share>().unwrap(), *share, "as read from a file");
+        assert!(!format!("{:?}", share).contains(&hex::encode(&share.value)), "the value never shows up in logs");
+    }
+    assert!(matches!("zz".parse::<Share>(), Err(ShamirError::Malformed(_))));
+    assert!(matches!("0102030402".parse::<Share>(), Err(ShamirError::Malformed(_))));
+    let index_zero = format!("{}0200{}", hex::encode(shares[0].split_id), "00".repeat(32));
+    assert!(matches!(index_zero.parse::<Share>(), Err(ShamirError::Malformed(_))));
+
+    let mut unsealer = Unsealer::new();
+    assert_eq!(unsealer.progress(), (0, 0));
+    assert_eq!(unsealer.add(shares[2].clone()), Ok(None));
+    assert_eq!(unsealer.progress(), (1, 2));
+    assert_eq!(unsealer.add(shares[2].clone()), Err(ShamirError::DuplicateIndex(3)));
+    let other = shamir::split(&secret, 2, 3).unwrap();
+    assert_eq!(unsealer.add(other[0].clone()), Err(ShamirError::Mismatched));
+    assert_eq!(unsealer.progress(), (1, 2), "refused shares aren't kept");
+    assert_eq!(unsealer.add(shares[0].clone()), Ok(Some(secret.to_vec())));
+    assert_eq!(unsealer.progress(), (0, 0), "the shares are forgotten once used");
+
+    println!("✅ Shares round-trip and collect!");
+}
+
+fn zkp_auth(dir: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_zkp-auth"))
+        .args(args)
+        .env("ZKP_AUTH_PROFILES", dir.join("profiles.json"))
+        .output()
+        .unwrap()
+}
+
+/// log `user` in asking for an ID token for "app"
+async fn login(client: &mut AuthClient<Channel>, zkp: &ZKP, x: &num_bigint::BigUint, user: &str) -> Result<String, tonic::Status> {
+    let k = ZKP::generate_random_number_below(&zkp.q);
+    let (r1, r2) = zkp.commitment(&k).to_bytes_be();
+    let challenge = client
+        .create_authentication_challenge(AuthenticationChallengeRequest { user: user.to_string(), r1, r2, ..Default::default() })
+        .await?
+        .into_inner();
+    let s = zkp.respond(&k, &Challenge::from_bytes_be(&challenge.c), x).to_bytes_be();
+    let id_token = Some(IdTokenRequest { audience: "app".to_string(), nonce: "n-0S6".to_string() });
+    let answer = AuthenticationAnswerRequest { auth_id: challenge.auth_id, s, id_token, ..Default::default() };
+    Ok(client.verify_authentication(answer).await?.into_inner().id_token)
+}
+
+#[tokio::test]
+async fn test_unsealing_the_id_token_key() {
+    println!("🔧 Testing a server whose ID token key comes in shares");
+
+    let dir = temp_dir("unseal");
+    let token_file = dir.join("admin-token");
+    std::fs::write(&token_file, TOKEN).unwrap();
+
+    // split a fresh key 3-of-5: only the shares and the public key come out
+    let output = zkp_auth(&dir, &["admin", "split-key", "--out-dir", dir.join("shares").to_str().unwrap(), "--threshold", "3", "--shares", "5"]);
+    let printed = String::from_utf8_lossy(&output.stdout).to_string();
+    assert!(output.status.success(), "{}", printed);
+    let public_key = printed.split("--id-token-public-key ").nth(1).unwrap().trim().to_string();
+    let public_key_parsed = parse_public_key(&public_key).unwrap();
+    let share = |n: u32| dir.join("shares").join(format!("share-{}.txt", n));
+    assert!(std::fs::read_dir(dir.join("shares")).unwrap().count() == 5);
+
+    // the server gets one share at startup and waits for two more
+    let port = 50082;
+    let _server = Server(
+        Command::new(env!("CARGO_BIN_EXE_server"))
+            .arg("--listen")
+            .arg(format!("127.0.0.1:{}", port))
+            .arg("--admin-token-file")
+            .arg(&token_file)
+            .args(["--id-token-issuer", "https://zkp.example", "--id-token-audience", "app"])
+            .args(["--id-token-public-key", &public_key])
+            .arg("--id-token-key-share")
+            .arg(share(4))
+            .stdout(Stdio::null())
+            .spawn()
+            .expect("Failed to start server process"),
+    );
+    let channel = connect(port).await;
+    let mut client = AuthClient::new(channel.clone());
+    let mut admin = AdminClient::new(channel);
+    let token = AdminToken::new(TOKEN).unwrap();
+
+    let zkp = ZKP::builder().rfc5114().build().unwrap();
+    let x = ZKP::generate_random_number_below(&zkp.q);
+    let (y1, y2) = zkp.public_key(&x).to_bytes_be();
+    client.register(RegisterRequest { user: "shamir_user".to_string(), y1, y2, ..Default::default() }).await.unwrap();
+
+    // sealed: logins that want a token are turned away before the proof is checked
+    let status = login(&mut client, &zkp, &x, "shamir_user").await.unwrap_err();
+    assert_eq!(status.code(), Code::Unavailable, "{}", status.message());
+    assert!(status.message().contains("1 of 3"), "{}", status.message());
+
+    // shares go to the Admin service only, and shares of another split are refused
+    let unseal = |share: String| token.authorize(tonic::Request::new(UnsealIdTokenKeyRequest { share }));
+    let anonymous = admin.unseal_id_token_key(UnsealIdTokenKeyRequest { share: std::fs::read_to_string(share(1)).unwrap() }).await;
+    assert_eq!(anonymous.unwrap_err().code(), Code::Unauthenticated);
+    let stranger = shamir::split(&[1; 32], 3, 5).unwrap();
+    let status = admin.unseal_id_token_key(unseal(stranger[0].to_string())).await.unwrap_err();
+    assert_eq!(status.code(), Code::InvalidArgument);
+    let status = admin.unseal_id_token_key(unseal(std::fs::read_to_string(share(4)).unwrap())).await.unwrap_err();
+    assert_eq!(status.code(), Code::InvalidArgument, "share 4 was given at startup");
+
+    let response = admin.unseal_id_token_key(unseal(std::fs::read_to_string(share(2)).unwrap())).await.unwrap().into_inner();
+    assert!(!response.unsealed);
+    assert_eq!((response.shares, response.threshold), (2, 3));
+
+    // the last share through the CLI
+    let endpoint = format!("http://127.0.0.1:{}", port);
+    let output = zkp_auth(&dir, &["admin", "unseal", share(5).to_str().unwrap(), "--token-file", token_file.to_str().unwrap(), "--endpoint", &endpoint]);
+    let printed = format!("{}{}", String::from_utf8_lossy(&output.stdout), String::from_utf8_lossy(&output.stderr));
+    assert!(output.status.success() && printed.contains("unsealed"), "{}", printed);
+
+    // unsealed: tokens are minted, signed by the key the shares made
+    let id_token = login(&mut client, &zkp, &x, "shamir_user").await.unwrap();
+    let claims = id_token::verify(&id_token, &public_key_parsed, "https://zkp.example", "app", SystemTime::now()).unwrap();
+    assert_eq!(claims.sub, "shamir_user");
+    let response = admin.unseal_id_token_key(unseal(std::fs::read_to_string(share(1)).unwrap())).await.unwrap().into_inner();
+    assert!(response.unsealed, "more shares after unsealing change nothing");
+
+    let _ = std::fs::remove_dir_all(&dir);
+    println!("✅ The key is only there once enough key holders sent their shares!");
+}
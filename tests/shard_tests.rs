@@ -0,0 +1,181 @@
+// Tests for sharding users over several user store journals
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use num_bigint::BigUint;
+use tonic::transport::Channel;
+
+use rust_zkp_chaum_pedersen::api::v1::{
+    auth_client::AuthClient, AuthenticationAnswerRequest, AuthenticationChallengeRequest, RegisterRequest,
+};
+use rust_zkp_chaum_pedersen::metrics::ShardMetrics;
+use rust_zkp_chaum_pedersen::shard::{HashRing, ShardedStore};
+use rust_zkp_chaum_pedersen::store::{StoredUser, UserStore};
+use rust_zkp_chaum_pedersen::types::Challenge;
+use rust_zkp_chaum_pedersen::ZKP;
+
+mod common;
+use common::{auth_client, temp_dir, Server};
+
+fn registration(user: &str) -> StoredUser {
+    StoredUser::from(&RegisterRequest { user: user.to_string(), y1: vec![1, 2, 3], y2: vec![4, 5, 6], ..Default::default() })
+}
+
+fn names(shards: usize) -> Vec<String> {
+    (0..shards).map(|shard| format!("/data/users-{}.jsonl", shard)).collect()
+}
+
+#[test]
+fn test_hash_ring() {
+    println!("🔀 Testing users placed on a hash ring of shards");
+
+    let users: Vec<String> = (0..10_000).map(|i| format!("user{}", i)).collect();
+    let four = HashRing::new(&names(4));
+    let mut shares = [0usize; 4];
+    for user in &users {
+        shares[four.shard_of(user)] += 1;
+    }
+    for share in shares {
+        assert!((1_500..3_500).contains(&share), "uneven shares: {:?}", shares);
+    }
+    assert_eq!(HashRing::new(&names(4)), four, "the same shards make the same ring");
+    assert!(users.iter().all(|user| HashRing::new(&names(1)).shard_of(user) == 0));
+
+    // a fifth shard takes about a fifth of the users, all from the others, and moves nobody else
+    let five = HashRing::new(&names(5));
+    let moved: Vec<&String> = users.iter().filter(|user| four.shard_of(user) != five.shard_of(user)).collect();
+    assert!((1_000..3_000).contains(&moved.len()), "{} users moved", moved.len());
+    assert!(moved.iter().all(|user| five.shard_of(user) == 4));
+
+    // shards are known by name, not by where they are listed
+    let mut reordered = names(4);
+    reordered.swap(0, 3);
+    let ring = HashRing::new(&reordered);
+    assert!(users.iter().all(|user| names(4)[four.shard_of(user)] == reordered[ring.shard_of(user)]));
+
+    println!("✅ Adding a shard moves only its share of users!");
+}
+
+fn open(paths: &[&Path]) -> (ShardedStore, Vec<Vec<StoredUser>>) {
+    let (shards, read) = paths.iter().map(|path| UserStore::open(path).unwrap()).unzip();
+    (ShardedStore::new(shards), read)
+}
+
+fn journaled(path: &Path) -> Vec<StoredUser> {
+    UserStore::open(path).unwrap().1
+}
+
+#[test]
+fn test_rebalance() {
+    println!("🔀 Testing users moved to their shard when shards are added");
+
+    let dir = temp_dir("rebalance");
+    let (a, b) = (dir.join("a.jsonl"), dir.join("b.jsonl"));
+    let users: Vec<StoredUser> = (0..200).map(|i| registration(&format!("user{}", i))).collect();
+    UserStore::open(&a).unwrap().0.insert(&users).unwrap();
+
+    // b joins: its users move there, the rest stay
+    let (sharded, read) = open(&[&a, &b]);
+    let (loaded, moved) = sharded.rebalance(read);
+    assert_eq!(loaded.len(), 200);
+    assert!((50..150).contains(&moved), "{} users moved", moved);
+    let (in_a, in_b) = (journaled(&a), journaled(&b));
+    assert_eq!((in_a.len() + in_b.len(), in_b.len()), (200, moved));
+    assert!(in_a.iter().all(|user| sharded.shard_of(&user.user) == 0));
+    assert!(in_b.iter().all(|user| sharded.shard_of(&user.user) == 1));
+    assert!(std::ptr::eq(sharded.store_of(&in_b[0].user), &sharded.shards()[1]));
+
+    // nothing left to move
+    let (sharded, read) = open(&[&a, &b]);
+    assert_eq!(sharded.rebalance(read).1, 0);
+
+    // a move cut short left two copies: the one not yet moved, written since, wins
+    let user = in_b[0].user.clone();
+    sharded.shards()[0].insert(&[StoredUser { disabled: true, ..in_b[0].clone() }]).unwrap();
+    let (sharded, read) = open(&[&a, &b]);
+    let (loaded, moved) = sharded.rebalance(read);
+    assert_eq!((loaded.len(), moved), (200, 1));
+    assert!(loaded.iter().find(|stored| stored.user == user).unwrap().disabled);
+    assert!(journaled(&b).iter().find(|stored| stored.user == user).unwrap().disabled);
+    assert!(!journaled(&a).iter().any(|stored| stored.user == user));
+
+    // a user whose shard is down stays where it is, and is written there
+    std::fs::write(&a, "").unwrap();
+    let (store, _) = UserStore::open(&a).unwrap();
+    store.insert(&[in_b[1].clone()]).unwrap();
+    let unavailable = UserStore::unavailable(&b, &std::io::Error::other("disk gone"));
+    let sharded = ShardedStore::new(vec![store, unavailable]);
+    let (loaded, moved) = sharded.rebalance(vec![journaled(&a), Vec::new()]);
+    assert_eq!((loaded.len(), moved), (1, 0));
+    assert!(std::ptr::eq(sharded.store_of(&in_b[1].user), &sharded.shards()[0]));
+    assert_eq!(journaled(&a), [in_b[1].clone()]);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+    println!("✅ Users find their shard, and none gets lost on the way!");
+}
+
+#[test]
+fn test_shard_metrics() {
+    println!("🔀 Testing the shard health metrics");
+
+    let metrics = ShardMetrics::default();
+    assert_eq!(metrics.render(), "");
+    metrics.set(vec![("/data/a.jsonl".to_string(), true), ("/data/\"b\".jsonl".to_string(), false)]);
+    let rendered = metrics.render();
+    assert!(rendered.contains("zkp_user_store_shard_up{shard=\"0\",path=\"/data/a.jsonl\"} 1\n"), "{}", rendered);
+    assert!(rendered.contains("zkp_user_store_shard_up{shard=\"1\",path=\"/data/\\\"b\\\".jsonl\"} 0\n"), "{}", rendered);
+
+    println!("✅ Each shard reports whether it is up!");
+}
+
+fn start(port: u16, user_stores: &[&Path]) -> Server {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_server"));
+    command.args(["--listen", &format!("127.0.0.1:{}", port)]);
+    for path in user_stores {
+        command.args(["--user-store", path.to_str().unwrap()]);
+    }
+    Server(command.stdout(Stdio::null()).spawn().expect("Failed to start server process"))
+}
+
+async fn login(client: &mut AuthClient<Channel>, user: &str, x: &BigUint) -> Result<String, tonic::Status> {
+    let zkp = ZKP::builder().rfc5114().build().unwrap();
+    let k = ZKP::generate_random_number_below(&zkp.q);
+    let (r1, r2) = zkp.commitment(&k).to_bytes_be();
+    let request = AuthenticationChallengeRequest { user: user.to_string(), r1, r2, ..Default::default() };
+    let challenge = client.create_authentication_challenge(request).await?.into_inner();
+    let s = zkp.respond(&k, &Challenge::from_bytes_be(&challenge.c), x).to_bytes_be();
+    let request = AuthenticationAnswerRequest { auth_id: challenge.auth_id, s, ..Default::default() };
+    Ok(client.verify_authentication(request).await?.into_inner().session_id)
+}
+
+#[tokio::test]
+async fn test_sharded_server() {
+    println!("🔀 Testing a server keeping its users on two shards");
+
+    let dir = temp_dir("e2e");
+    let (a, b) = (dir.join("a.jsonl"), dir.join("b.jsonl"));
+    let zkp = ZKP::builder().rfc5114().build().unwrap();
+    let users: Vec<(String, BigUint)> = (0..20).map(|i| (format!("user{}", i), ZKP::generate_random_number_below(&zkp.q))).collect();
+
+    // registered on one journal, then spread over two on the next start
+    let server = start(50103, &[&a]);
+    let mut client = auth_client(50103).await;
+    for (user, x) in &users {
+        let (y1, y2) = zkp.public_key(x).to_bytes_be();
+        client.register(RegisterRequest { user: user.clone(), y1, y2, ..Default::default() }).await.unwrap();
+    }
+    drop(server);
+    assert_eq!(journaled(&a).len(), 20);
+
+    let _server = start(50103, &[&a, &b]);
+    let mut client = auth_client(50103).await;
+    for (user, x) in &users {
+        login(&mut client, user, x).await.unwrap();
+    }
+    let (in_a, in_b) = (journaled(&a).len(), journaled(&b).len());
+    assert_eq!(in_a + in_b, 20);
+    assert!(in_a > 0 && in_b > 0, "{} and {}", in_a, in_b);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+    println!("✅ Users stay put across shards and restarts!");
+}
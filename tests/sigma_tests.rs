@@ -0,0 +1,158 @@
+// Tests for the generic sigma-protocol machinery
+use std::time::Instant;
+
+use num_bigint::BigUint;
+use rust_zkp_chaum_pedersen::sigma::{self, And, BatchItem, ChaumPedersen, Or, OrWitness, Schnorr, SigmaProtocol};
+use rust_zkp_chaum_pedersen::types::{Challenge, Commitment, PublicKey, Response};
+use rust_zkp_chaum_pedersen::ZKP;
+
+fn setup() -> ZKP {
+    ZKP::builder().rfc5114().build().unwrap()
+}
+
+fn secret(zkp: &ZKP) -> BigUint {
+    ZKP::generate_random_number_below(&zkp.q)
+}
+
+/// commit, challenge, respond and verify, then check a wrong challenge fails
+fn round_trip<P: SigmaProtocol>(protocol: &P, statement: &P::Statement, witness: &P::Witness) -> bool {
+    let zkp = protocol.group();
+    let (commitment, nonce) = protocol.commit(statement, witness);
+    let c = zkp.random_challenge();
+    let response = protocol.respond(statement, witness, nonce, &c);
+    let wrong = Challenge((&c.0 + 1u32) % &zkp.q);
+    protocol.verify(statement, &commitment, &c, &response) && !protocol.verify(statement, &commitment, &wrong, &response)
+}
+
+/// a simulated transcript passes as well, which is what makes the proofs zero-knowledge
+fn simulates<P: SigmaProtocol>(protocol: &P, statement: &P::Statement) -> bool {
+    let c = protocol.group().random_challenge();
+    let (commitment, response) = protocol.simulate(statement, &c);
+    protocol.verify(statement, &commitment, &c, &response)
+}
+
+#[test]
+fn test_protocols_and_compositions() {
+    println!("🔧 Testing Chaum-Pedersen, Schnorr, AND and OR through one trait");
+
+    let zkp = setup();
+    let (x, w) = (secret(&zkp), secret(&zkp));
+    let key = zkp.public_key(&x);
+    let y = zkp.alpha.modpow(&w, &zkp.p);
+
+    assert!(round_trip(&ChaumPedersen(&zkp), &key, &x));
+    assert!(round_trip(&Schnorr(&zkp), &y, &w));
+    assert!(!round_trip(&Schnorr(&zkp), &y, &x), "the wrong witness doesn't verify");
+
+    let both = And(ChaumPedersen(&zkp), Schnorr(&zkp));
+    assert!(round_trip(&both, &(key.clone(), y.clone()), &(x.clone(), w.clone())));
+    assert!(!round_trip(&both, &(key.clone(), y.clone()), &(x.clone(), x.clone())), "AND needs both witnesses");
+
+    let either = Or(ChaumPedersen(&zkp), Schnorr(&zkp));
+    let statement = (key.clone(), y.clone());
+    assert!(round_trip(&either, &statement, &OrWitness::Left(x.clone())));
+    assert!(round_trip(&either, &statement, &OrWitness::Right(w.clone())));
+    assert!(!round_trip(&either, &statement, &OrWitness::Left(w.clone())), "OR needs one real witness");
+
+    // compositions nest
+    let nested = Or(both, Schnorr(&zkp));
+    assert!(round_trip(&nested, &((key.clone(), y.clone()), y.clone()), &OrWitness::Right(w.clone())));
+
+    assert!(simulates(&ChaumPedersen(&zkp), &key));
+    assert!(simulates(&Schnorr(&zkp), &y));
+    assert!(simulates(&both, &(key.clone(), y.clone())));
+    assert!(simulates(&either, &statement));
+
+    println!("✅ Every protocol and composition proves, verifies and simulates!");
+}
+
+#[test]
+fn test_or_challenges_are_reduced() {
+    println!("🔀 Testing an OR proof can't be re-encoded with a shifted challenge");
+
+    let zkp = setup();
+    let (x, w) = (secret(&zkp), secret(&zkp));
+    let statement = (zkp.public_key(&x), zkp.alpha.modpow(&w, &zkp.p));
+    let either = Or(ChaumPedersen(&zkp), Schnorr(&zkp));
+    let (commitment, nonce) = either.commit(&statement, &OrWitness::Left(x.clone()));
+    let c = zkp.random_challenge();
+    let mut response = either.respond(&statement, &OrWitness::Left(x), nonce, &c);
+    assert!(either.verify(&statement, &commitment, &c, &response));
+    // c_left + q splits c the same way and the left equations still hold
+    response.c_left = Challenge(&response.c_left.0 + &zkp.q);
+    assert!(!either.verify(&statement, &commitment, &c, &response));
+    response.c_left = Challenge(&response.c_left.0 - &zkp.q);
+    response.c_right = Challenge(&response.c_right.0 + &zkp.q);
+    assert!(!either.verify(&statement, &commitment, &c, &response));
+    assert!(either.equations(&statement, &commitment, &c, &response).is_none());
+
+    println!("✅ Only the reduced encoding verifies!");
+}
+
+#[test]
+fn test_fiat_shamir_for_any_protocol() {
+    println!("🔧 Testing Fiat-Shamir over an AND composition");
+
+    let zkp = setup();
+    let (x, w) = (secret(&zkp), secret(&zkp));
+    let protocol = And(ChaumPedersen(&zkp), Schnorr(&zkp));
+    let statement = (zkp.public_key(&x), zkp.alpha.modpow(&w, &zkp.p));
+    let hash = |numbers: &[BigUint]| Challenge(numbers.iter().fold(BigUint::from(7u32), |acc, n| (acc * 31u32 + n) % &zkp.q));
+
+    let (commitment, response) = sigma::prove_with(&protocol, &statement, &(x.clone(), w.clone()), hash);
+    assert!(sigma::verify_with(&protocol, &statement, &commitment, &response, hash));
+
+    let other = (zkp.public_key(&secret(&zkp)), statement.1.clone());
+    assert!(!sigma::verify_with(&protocol, &other, &commitment, &response, hash), "the statement is in the hash");
+
+    println!("✅ Fiat-Shamir works for a composed protocol!");
+}
+
+type Transcript = (PublicKey, Commitment, Challenge, Response);
+
+fn items<'a>(transcripts: &'a [Transcript]) -> Vec<BatchItem<'a, ChaumPedersen<'a>>> {
+    transcripts.iter().map(|(statement, commitment, c, response)| BatchItem { statement, commitment, c, response }).collect()
+}
+
+#[test]
+fn test_batch_verification() {
+    println!("🔧 Testing batch verification of many Chaum-Pedersen transcripts");
+
+    let zkp = setup();
+    let protocol = ChaumPedersen(&zkp);
+    let transcripts: Vec<Transcript> = (0..16)
+        .map(|_| {
+            let x = secret(&zkp);
+            let key = zkp.public_key(&x);
+            let (commitment, k) = protocol.commit(&key, &x);
+            let c = zkp.random_challenge();
+            let s = protocol.respond(&key, &x, k, &c);
+            (key, commitment, c, s)
+        })
+        .collect();
+
+    let started = Instant::now();
+    assert!(sigma::verify_batch(&protocol, &items(&transcripts)));
+    let batched = started.elapsed();
+    let started = Instant::now();
+    assert!(transcripts.iter().all(|(key, commitment, c, s)| protocol.verify(key, commitment, c, s)));
+    println!("   16 transcripts: {:?} batched, {:?} one by one", batched, started.elapsed());
+
+    // one bad answer spoils the batch
+    let mut bad = transcripts.clone();
+    bad[5].3 .0 = (&bad[5].3 .0 + 1u32) % &zkp.q;
+    assert!(!sigma::verify_batch(&protocol, &items(&bad)));
+
+    // a commitment off by an element of order 2 fails one by one, and must fail
+    // in the batch too even when its weight happens to be even
+    let mut small_order = transcripts.clone();
+    small_order[3].1.r1 = &small_order[3].1.r1 * (&zkp.p - 1u32) % &zkp.p;
+    assert!(!protocol.verify(&small_order[3].0, &small_order[3].1, &small_order[3].2, &small_order[3].3));
+    for _ in 0..8 {
+        assert!(!sigma::verify_batch(&protocol, &items(&small_order)));
+    }
+
+    assert!(sigma::verify_batch(&protocol, &[]), "an empty batch has nothing wrong with it");
+
+    println!("✅ Batches pass when every transcript does and fail otherwise!");
+}
@@ -0,0 +1,117 @@
+// Tests for the sled user store (cargo test --features sled)
+#![cfg(feature = "sled")]
+
+use rust_zkp_chaum_pedersen::api::v1::RegisterRequest;
+use rust_zkp_chaum_pedersen::store::{Storage, StoreKey, StoredDevice, StoredSession, StoredUser, UserStore};
+
+mod common;
+use common::temp_dir;
+
+fn registration(user: &str) -> StoredUser {
+    StoredUser::from(&RegisterRequest { user: user.to_string(), y1: vec![1, 2, 3], y2: vec![4, 5, 6], ..Default::default() })
+}
+
+fn names(users: &[StoredUser]) -> Vec<&str> {
+    let mut names: Vec<&str> = users.iter().map(|user| user.user.as_str()).collect();
+    names.sort();
+    names
+}
+
+#[test]
+fn test_users_survive_reopening() {
+    println!("🌳 Testing the sled user store round trip");
+
+    let dir = temp_dir("roundtrip");
+    let storage = Storage::Sled(dir.join("users"));
+    let (store, users) = UserStore::open_storage(&storage, None).unwrap();
+    assert!(users.is_empty());
+    store.append(&registration("alice")).unwrap();
+    store.append(&registration("bob")).unwrap();
+    store.set_enabled("alice", false).unwrap();
+    let device = StoredDevice { id: "laptop-id".to_string(), name: "laptop".to_string(), y1: "0102".to_string(), y2: "0304".to_string(), enrolled_at: 1 };
+    store.add_device("bob", &device).unwrap();
+    store.add_device("bob", &StoredDevice { id: "phone-id".to_string(), ..device.clone() }).unwrap();
+    store.revoke_device("bob", "phone-id").unwrap();
+    assert!(store.find("alice").unwrap().unwrap().disabled);
+    assert_eq!(store.find("carol").unwrap(), None);
+    drop(store);
+
+    let (store, users) = UserStore::open_storage(&storage, None).unwrap();
+    assert_eq!(names(&users), ["alice", "bob"]);
+    let alice = users.iter().find(|user| user.user == "alice").unwrap();
+    let bob = users.iter().find(|user| user.user == "bob").unwrap();
+    assert!(alice.disabled);
+    assert_eq!(bob.devices, vec![device]);
+
+    assert!(store.erase("alice").unwrap());
+    assert!(!store.erase("alice").unwrap(), "already gone");
+    drop(store);
+    let (_, users) = UserStore::open_storage(&storage, None).unwrap();
+    assert_eq!(names(&users), ["bob"]);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+    println!("✅ Registrations are read back from sled!");
+}
+
+#[test]
+fn test_sealed_sled_store() {
+    println!("🌳 Testing a sealed sled user store");
+
+    let dir = temp_dir("sealed");
+    let storage = Storage::Sled(dir.join("users"));
+    let key = StoreKey::from_hex(&"ab".repeat(32)).unwrap();
+    let (store, _) = UserStore::open_storage(&storage, None).unwrap();
+    store.append(&registration("alice")).unwrap();
+    drop(store);
+
+    // plain records get sealed on opening with a key, as in a journal
+    let (store, users) = UserStore::open_storage(&storage, Some(key.clone())).unwrap();
+    assert_eq!(names(&users), ["alice"]);
+    store.append(&registration("bob")).unwrap();
+    drop(store);
+
+    let (_, users) = UserStore::open_storage(&storage, Some(key)).unwrap();
+    assert_eq!(names(&users), ["alice", "bob"]);
+    let error = UserStore::open_storage(&storage, None).unwrap_err();
+    assert!(error.to_string().contains("no --user-store-key"), "{}", error);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+    println!("✅ Sealed records work in sled too!");
+}
+
+#[test]
+fn test_sessions_survive_reopening() {
+    println!("🌳 Testing sessions kept in a sled user store");
+
+    let dir = temp_dir("sessions");
+    let storage = Storage::Sled(dir.join("users"));
+    let key = StoreKey::from_hex(&"cd".repeat(32)).unwrap();
+    let (store, _) = UserStore::open_storage(&storage, Some(key.clone())).unwrap();
+    store.append(&registration("alice")).unwrap();
+    store.append(&registration("bob")).unwrap();
+    let session = StoredSession { id: "abcdefghijkl".to_string(), created_at: 1_700_000_000_000, client: String::new(), device: None, elevated_until: None };
+    let elevated = StoredSession { id: "mnopqrstuvwx".to_string(), elevated_until: Some(1_700_000_300_000), ..session.clone() };
+    store.save_sessions("alice", &[session.clone(), elevated.clone()]).unwrap();
+    store.save_sessions("bob", &[session.clone()]).unwrap();
+    // each save replaces the user's sessions, and none drops them
+    store.save_sessions("bob", &[]).unwrap();
+    drop(store);
+
+    let (store, _) = UserStore::open_storage(&storage, Some(key.clone())).unwrap();
+    assert_eq!(store.sessions().unwrap(), vec![("alice".to_string(), vec![session, elevated])]);
+    // an erased user's sessions go with them
+    assert!(store.erase("alice").unwrap());
+    assert!(store.sessions().unwrap().is_empty());
+    drop(store);
+
+    // a journal keeps none
+    let journal = dir.join("users.jsonl");
+    let (store, _) = UserStore::open(&journal).unwrap();
+    store.append(&registration("carol")).unwrap();
+    store.save_sessions("carol", &[StoredSession { id: "yzabcdefghij".to_string(), created_at: 1, client: String::new(), device: None, elevated_until: None }]).unwrap();
+    assert!(store.sessions().unwrap().is_empty());
+    assert_eq!(std::fs::read_to_string(&journal).unwrap().lines().count(), 1, "only the registration");
+
+    std::fs::remove_dir_all(&dir).unwrap();
+    println!("✅ Sessions outlive a restart with sled!");
+}
@@ -0,0 +1,80 @@
+// Short runs of the soak binary, so it keeps working between real soaks
+//
+// A real soak runs for hours (see "Soak Testing" in the Readme); these run it
+// for a few seconds against a server of its own, then once more after the
+// server is gone, to check both ways out.
+#![cfg(target_os = "linux")]
+
+use std::net::TcpStream;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+use std::time::Duration;
+
+mod common;
+use common::Server;
+
+const PORT: u16 = 50079;
+
+fn start_server(store: &Path) -> Server {
+    let child = Command::new(env!("CARGO_BIN_EXE_server"))
+        .arg("--listen")
+        .arg(format!("127.0.0.1:{}", PORT))
+        .arg("--user-store")
+        .arg(store)
+        .stdout(Stdio::null())
+        .spawn()
+        .expect("Failed to start server process");
+    let server = Server(child);
+    for _ in 0..50 {
+        if TcpStream::connect(("127.0.0.1", PORT)).is_ok() {
+            return server;
+        }
+        std::thread::sleep(Duration::from_millis(100));
+    }
+    panic!("the test server didn't come up on port {}", PORT);
+}
+
+fn soak(server: &Server, max_rss_growth_mib: u64) -> Output {
+    let output = Command::new(env!("CARGO_BIN_EXE_soak"))
+        .arg("--endpoint")
+        .arg(format!("http://127.0.0.1:{}", PORT))
+        .arg("--server-pid")
+        .arg(server.0.id().to_string())
+        .args(["--duration", "4", "--users", "4", "--warmup", "1", "--sample-interval", "1"])
+        .arg("--max-rss-growth-mib")
+        .arg(max_rss_growth_mib.to_string())
+        .output()
+        .expect("Failed to run soak");
+    println!("{}", String::from_utf8_lossy(&output.stdout));
+    println!("{}", String::from_utf8_lossy(&output.stderr));
+    output
+}
+
+#[test]
+fn test_soak_passes_a_healthy_server_and_fails_a_missing_one() {
+    println!("🔧 Testing a short soak against a fresh server");
+
+    let store: PathBuf = std::env::temp_dir().join(format!("zkp-soak-users-{}.json", std::process::id()));
+    let _ = std::fs::remove_file(&store);
+    let server = start_server(&store);
+
+    let output = soak(&server, 256);
+    assert!(output.status.success(), "a short soak within 256 MiB passes");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("📏 Baseline after the warm-up"), "the baseline is taken after the warm-up");
+    assert!(stdout.contains("✅ Memory stayed within"));
+
+    println!("🔧 Testing that the soak fails when the server is gone");
+    let pid = server.0.id();
+    drop(server);
+    let output = Command::new(env!("CARGO_BIN_EXE_soak"))
+        .arg("--server-pid")
+        .arg(pid.to_string())
+        .output()
+        .expect("Failed to run soak");
+    assert!(!output.status.success(), "a server that isn't running fails the soak");
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Can't read the memory"));
+
+    let _ = std::fs::remove_file(&store);
+    println!("✅ The soak binary passes a healthy server and fails a missing one!");
+}
@@ -0,0 +1,394 @@
+// Tests for the on-disk journal of registrations
+use std::path::PathBuf;
+use std::time::Duration;
+
+use num_bigint::BigUint;
+use rust_zkp_chaum_pedersen::api::v1::{KdfParams, RegisterRequest};
+use rust_zkp_chaum_pedersen::metrics;
+use rust_zkp_chaum_pedersen::store::{FlushPolicy, Storage, StoreKey, StoredUser, UserStore};
+
+mod common;
+use common::{auth_client, temp_dir};
+
+fn registration(user: &str) -> RegisterRequest {
+    RegisterRequest {
+        user: user.to_string(),
+        y1: vec![1, 2, 3],
+        y2: vec![4, 5, 6],
+        kdf: Some(KdfParams { salt: vec![9; 16], memory_kib: 19456, iterations: 2, parallelism: 1 }),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_users_survive_reopening() {
+    println!("💾 Testing the user store round trip");
+
+    let dir = temp_dir("roundtrip");
+    let path = dir.join("users.jsonl");
+
+    let (store, users) = UserStore::open(&path).unwrap();
+    assert!(users.is_empty());
+    assert_eq!(store.degraded(), None);
+    store.append(&StoredUser::from(&registration("alice"))).unwrap();
+    let mut bob = registration("bob");
+    bob.kdf = None;
+    bob.beta = vec![0x0f, 0xff];
+    store.append(&StoredUser::from(&bob)).unwrap();
+    drop(store);
+
+    let (_, users) = UserStore::open(&path).unwrap();
+    assert_eq!(users.len(), 2);
+    assert_eq!(users[0].user, "alice");
+    assert_eq!(users[0].public_key().y1, BigUint::from(0x010203u32));
+    assert_eq!(users[0].public_key().y2, BigUint::from(0x040506u32));
+    assert_eq!(users[0].kdf().unwrap().salt, vec![9; 16]);
+    assert_eq!(users[0].beta(), None);
+    assert_eq!(users[1].kdf(), None);
+    assert_eq!(users[1].beta(), Some(BigUint::from(0x0fffu32)));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+    println!("✅ Registrations are read back after a restart!");
+}
+
+#[test]
+fn test_corrupt_journal_is_refused() {
+    println!("💾 Testing a corrupt user store");
+
+    let dir = temp_dir("corrupt");
+    let path = dir.join("users.jsonl");
+    std::fs::write(&path, "{\"user\":\"alice\",\"y1\":\"zz\",\"y2\":\"00\"}\n").unwrap();
+
+    let error = UserStore::open(&path).unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+    assert!(error.to_string().contains("line 1"), "{}", error);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+    println!("✅ A journal we can't read is an error, not an empty store!");
+}
+
+#[test]
+fn test_degraded_until_the_journal_is_back() {
+    println!("💾 Testing degraded mode and recovery");
+
+    // the directory doesn't exist yet, as if the volume weren't mounted
+    let dir = temp_dir("degraded");
+    std::fs::remove_dir(&dir).unwrap();
+    let path = dir.join("users.jsonl");
+    let error = UserStore::open(&path).unwrap_err();
+    let store = UserStore::unavailable(&path, &error);
+    assert!(store.degraded().is_some());
+    assert!(store.append(&StoredUser::from(&registration("alice"))).is_err());
+    assert!(store.recover().is_err(), "still missing");
+
+    // the volume comes back with what was written before
+    std::fs::create_dir_all(&dir).unwrap();
+    let (earlier, _) = UserStore::open(&path).unwrap();
+    earlier.append(&StoredUser::from(&registration("carol"))).unwrap();
+    drop(earlier);
+
+    let users = store.recover().unwrap().expect("recovered");
+    assert_eq!(users.iter().map(|user| user.user.as_str()).collect::<Vec<_>>(), ["carol"]);
+    assert_eq!(store.degraded(), None);
+    assert_eq!(store.recover().unwrap(), None, "nothing to recover when healthy");
+    store.append(&StoredUser::from(&registration("alice"))).unwrap();
+
+    std::fs::remove_dir_all(&dir).unwrap();
+    println!("✅ The store recovers on its own!");
+}
+
+#[test]
+fn test_torn_last_line_is_dropped() {
+    println!("💾 Testing recovery from a write cut off by a crash");
+
+    let dir = temp_dir("torn");
+    let path = dir.join("users.jsonl");
+    let (store, _) = UserStore::open(&path).unwrap();
+    store.append(&StoredUser::from(&registration("alice"))).unwrap();
+    drop(store);
+
+    // the crash hit halfway through bob's line: no newline, never acknowledged
+    let complete = std::fs::read_to_string(&path).unwrap();
+    let bob = serde_json::to_string(&StoredUser::from(&registration("bob"))).unwrap();
+    std::fs::write(&path, format!("{}{}", complete, &bob[..bob.len() / 2])).unwrap();
+
+    let (store, users) = UserStore::open(&path).unwrap();
+    assert_eq!(users.iter().map(|user| user.user.as_str()).collect::<Vec<_>>(), ["alice"]);
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), complete, "the torn tail is cut off");
+
+    // a complete record without its newline wasn't acknowledged either
+    std::fs::write(&path, format!("{}{}", complete, bob)).unwrap();
+    drop(store);
+    let (store, users) = UserStore::open(&path).unwrap();
+    assert_eq!(users.len(), 1);
+
+    // and the journal keeps working after it
+    store.append(&StoredUser::from(&registration("bob"))).unwrap();
+    let (_, users) = UserStore::open(&path).unwrap();
+    assert_eq!(users.iter().map(|user| user.user.as_str()).collect::<Vec<_>>(), ["alice", "bob"]);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+    println!("✅ Only acknowledged registrations survive a crash!");
+}
+
+#[test]
+fn test_registration_round_trips_for_idempotency() {
+    println!("💾 Testing stored registrations against retries");
+
+    let mut request = registration("alice");
+    request.idempotency_key = "retry-1".to_string();
+    request.password_policy_version = 2;
+    request.beta = vec![7, 7];
+    // a retry after a restart is compared with what the journal gives back
+    assert_eq!(RegisterRequest::from(&StoredUser::from(&request)), request);
+
+    println!("✅ The journal keeps everything a retry is compared on!");
+}
+
+// Helper to run a server on its own port with a user store
+fn start_server(port: u16, store: &std::path::Path) -> std::process::Child {
+    std::process::Command::new(env!("CARGO_BIN_EXE_server"))
+        .arg("--listen")
+        .arg(format!("127.0.0.1:{}", port))
+        .arg("--user-store")
+        .arg(store)
+        .stdout(std::process::Stdio::null())
+        .spawn()
+        .expect("Failed to start server process")
+}
+
+#[tokio::test]
+async fn test_registration_survives_a_crash() {
+    println!("💾 Testing a registration acknowledged right before a crash");
+
+    let dir = temp_dir("crash");
+    let path = dir.join("users.jsonl");
+    let port = 50071;
+
+    let mut request = registration("crash_user");
+    request.idempotency_key = "crash-retry".to_string();
+
+    let mut server = start_server(port, &path);
+    auth_client(port).await.register(request.clone()).await.unwrap();
+    // SIGKILL: nothing gets to flush after the answer
+    server.kill().unwrap();
+    server.wait().unwrap();
+
+    let mut server = start_server(port, &path);
+    let mut client = auth_client(port).await;
+    // the client never saw the answer and retries: same key, same success
+    let retry = client.register(request.clone()).await;
+    // someone else can't take the name
+    let mut other = registration("crash_user");
+    other.y1 = vec![9, 9, 9];
+    let taken = client.register(other).await;
+    server.kill().unwrap();
+    server.wait().unwrap();
+
+    assert!(retry.is_ok(), "{:?}", retry);
+    assert_eq!(taken.unwrap_err().code(), tonic::Code::AlreadyExists);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+    println!("✅ Client and server agree the account exists!");
+}
+
+#[test]
+fn test_disabled_users_stay_disabled() {
+    println!("💾 Testing disabled accounts in the journal");
+
+    let dir = temp_dir("disabled");
+    let path = dir.join("users.jsonl");
+    let (store, _) = UserStore::open(&path).unwrap();
+    store.append(&StoredUser::from(&registration("alice"))).unwrap();
+    store.append(&StoredUser::from(&registration("bob"))).unwrap();
+    store.set_enabled("alice", false).unwrap();
+    store.set_enabled("bob", false).unwrap();
+    store.set_enabled("bob", true).unwrap();
+    drop(store);
+
+    // the latest line per user wins
+    let (_, users) = UserStore::open(&path).unwrap();
+    assert_eq!(users.len(), 2);
+    assert!(users[0].disabled);
+    assert!(!users[1].disabled);
+
+    // a change for a user the journal never registered is corruption
+    std::fs::write(&path, "{\"user\":\"mallory\",\"enabled\":true}\n").unwrap();
+    let error = UserStore::open(&path).unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+    println!("✅ Disabling an account survives a restart!");
+}
+
+#[test]
+fn test_sealed_records() {
+    println!("💾 Testing a user store sealed with --user-store-key");
+
+    let dir = temp_dir("sealed");
+    let path = dir.join("users.jsonl");
+    let key = StoreKey::from_hex(&"ab".repeat(32)).unwrap();
+    assert!(StoreKey::from_hex("abcd").is_err(), "AES-256 only");
+    assert!(!format!("{:?}", key).contains("abab"), "the key never shows up in logs");
+
+    // a journal from before the key: its plain records get sealed on opening
+    let (store, _) = UserStore::open(&path).unwrap();
+    store.append(&StoredUser::from(&registration("alice"))).unwrap();
+    store.set_enabled("alice", false).unwrap();
+    drop(store);
+    let (store, users) = UserStore::open_with(&path, Some(key.clone())).unwrap();
+    assert!(store.is_sealed());
+    assert!(users[0].disabled);
+    store.append(&StoredUser::from(&registration("bob"))).unwrap();
+    drop(store);
+
+    let journal = std::fs::read_to_string(&path).unwrap();
+    // random base64 could spell anything, so look at the fields rather than the text
+    for line in journal.lines() {
+        let record: serde_json::Map<String, serde_json::Value> = serde_json::from_str(line).unwrap();
+        assert_eq!(record.keys().collect::<Vec<_>>(), ["key", "sealed"], "no user names or public keys on disk");
+    }
+    assert_eq!(journal.lines().count(), 3);
+    assert!(journal.lines().all(|line| line.contains(key.id())));
+
+    let (_, users) = UserStore::open_with(&path, Some(key.clone())).unwrap();
+    assert_eq!(users.iter().map(|user| user.user.as_str()).collect::<Vec<_>>(), ["alice", "bob"]);
+    assert!(users[0].disabled);
+    assert_eq!(users[1].public_key().y1, BigUint::from(0x010203u32));
+
+    // same record, different nonces
+    assert_ne!(key.seal(b"record"), key.seal(b"record"));
+    assert_eq!(key.open(&key.seal(b"record")).unwrap(), b"record");
+
+    // without the key, or with another one, the journal is unreadable, not empty
+    let error = UserStore::open(&path).unwrap_err();
+    assert!(error.to_string().contains("no --user-store-key"), "{}", error);
+    let other = StoreKey::from_hex(&"cd".repeat(32)).unwrap();
+    let error = UserStore::open_with(&path, Some(other)).unwrap_err();
+    assert!(error.to_string().contains(&format!("sealed with key {}", key.id())), "{}", error);
+
+    // a flipped bit is caught by the tag
+    let mut line: serde_json::Value = serde_json::from_str(journal.lines().next().unwrap()).unwrap();
+    let mut sealed = line["sealed"].as_str().unwrap().to_string();
+    let at = sealed.len() - 4;
+    let flipped = if &sealed[at..at + 1] == "A" { "B" } else { "A" };
+    sealed.replace_range(at..at + 1, flipped);
+    line["sealed"] = sealed.into();
+    std::fs::write(&path, format!("{}\n", line)).unwrap();
+    let error = UserStore::open_with(&path, Some(key)).unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+    assert!(error.to_string().contains("altered"), "{}", error);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+    println!("✅ Sealed records are opened transparently!");
+}
+
+#[test]
+fn test_erased_users_leave_the_journal() {
+    println!("💾 Testing erasing a user from the journal");
+
+    let dir = temp_dir("erase");
+    let path = dir.join("users.jsonl");
+    let key = StoreKey::from_hex(&"ef".repeat(32)).unwrap();
+
+    let (store, _) = UserStore::open_with(&path, Some(key.clone())).unwrap();
+    store.append(&StoredUser::from(&registration("alice"))).unwrap();
+    store.append(&StoredUser::from(&registration("bob"))).unwrap();
+    store.set_enabled("alice", false).unwrap();
+    assert!(store.find("alice").unwrap().unwrap().disabled);
+
+    assert!(store.erase("alice").unwrap());
+    assert!(!store.erase("alice").unwrap(), "already gone");
+    assert_eq!(store.find("alice").unwrap(), None);
+    // the rewritten journal takes appends like the old one
+    store.append(&StoredUser::from(&registration("carol"))).unwrap();
+    drop(store);
+
+    let (_, users) = UserStore::open_with(&path, Some(key)).unwrap();
+    assert_eq!(users.iter().map(|user| user.user.as_str()).collect::<Vec<_>>(), ["bob", "carol"]);
+    assert_eq!(std::fs::read_to_string(&path).unwrap().lines().count(), 2, "alice's disabling went with her");
+    assert!(!dir.join("users.jsonl.rewrite").exists());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+    println!("✅ Erased users don't come back!");
+}
+
+#[test]
+fn test_storage_specs() {
+    println!("💾 Testing --storage BACKEND:PATH");
+
+    let journal: Storage = "journal:/var/lib/zkp/users.jsonl".parse().unwrap();
+    assert_eq!(journal, Storage::Journal(PathBuf::from("/var/lib/zkp/users.jsonl")));
+    assert_eq!(journal.to_string(), "journal:/var/lib/zkp/users.jsonl");
+    assert_eq!("sled:/var/lib/zkp/users".parse::<Storage>().is_ok(), cfg!(feature = "sled"), "only with the sled feature");
+    assert!("users.jsonl".parse::<Storage>().is_err(), "the backend is not optional");
+    assert!("sled:".parse::<Storage>().is_err());
+    assert!("sqlite:/tmp/users.db".parse::<Storage>().unwrap_err().contains("unknown storage backend"));
+
+    println!("✅ Storage specs are parsed!");
+}
+
+#[test]
+fn test_batched_flushes() {
+    println!("💾 Testing --user-store-flush-ms and --user-store-batch");
+
+    let dir = temp_dir("batched");
+    let path = dir.join("users.jsonl");
+    assert!(FlushPolicy::default().is_every_write());
+    let policy = FlushPolicy::every(Duration::from_secs(3600), 3);
+    assert_eq!(policy.to_string(), "every 3600000ms or 3 writes");
+
+    let (store, _) = UserStore::open(&path).unwrap();
+    let store = store.with_flush(policy);
+    let synced = metrics::flushes().latency().count();
+    store.append(&StoredUser::from(&registration("alice"))).unwrap();
+    store.append(&StoredUser::from(&registration("bob"))).unwrap();
+    assert_eq!(store.unsynced(), 2, "acknowledged before they are synced");
+    assert_eq!(UserStore::open_with(&path, None).unwrap().1.len(), 2, "but already in the journal");
+    store.append(&StoredUser::from(&registration("carol"))).unwrap();
+    assert_eq!(store.unsynced(), 0, "a full batch is synced at once");
+
+    store.set_enabled("alice", false).unwrap();
+    assert_eq!(store.unsynced(), 1);
+    store.flush().unwrap();
+    assert_eq!(store.unsynced(), 0);
+    store.flush().unwrap();
+    assert!(metrics::flushes().latency().count() >= synced + 2);
+    assert!(metrics::flushes().render().contains("zkp_user_store_flush_seconds_count"));
+    drop(store);
+
+    let (_, users) = UserStore::open(&path).unwrap();
+    assert_eq!(users.len(), 3);
+    assert!(users[0].disabled);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+    println!("✅ Writes are synced in batches!");
+}
+
+#[test]
+fn test_operations_are_timed() {
+    println!("💾 Testing user store latency and --slow-store-ms");
+
+    let dir = temp_dir("timed");
+    let path = dir.join("users.jsonl");
+    let (store, _) = UserStore::open(&path).unwrap();
+    let ops = metrics::store_ops();
+    let (appended, found, slow) = (ops.histogram("append").count(), ops.histogram("find").count(), ops.slow("find"));
+
+    store.append(&StoredUser::from(&registration("alice"))).unwrap();
+    assert!(store.find("alice").unwrap().is_some());
+    // other tests share the process-wide metrics, so at least one more each
+    assert!(ops.histogram("append").count() > appended);
+    assert!(ops.histogram("find").count() > found);
+
+    // with a threshold of zero everything is slow, and logged
+    let store = store.with_slow_log(Duration::ZERO);
+    assert!(store.find("bob").unwrap().is_none());
+    assert!(ops.slow("find") > slow);
+    let rendered = ops.render();
+    assert!(rendered.contains("zkp_user_store_op_seconds_count{op=\"find\"}"), "{}", rendered);
+    assert!(rendered.contains("zkp_user_store_slow_ops_total{op=\"find\"}"), "{}", rendered);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+    println!("✅ Store operations are timed!");
+}
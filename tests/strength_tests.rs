@@ -0,0 +1,91 @@
+// Tests for password strength estimates (cargo test --features zxcvbn)
+#![cfg(feature = "zxcvbn")]
+
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+use rust_zkp_chaum_pedersen::password_policy::{PasswordPolicy, Weakness, DEFAULT_POLICY_VERSION};
+use rust_zkp_chaum_pedersen::strength::{self, StrengthPolicy, MAX_SCORE};
+
+#[test]
+fn test_estimate() {
+    println!("🔐 Testing password strength estimates");
+
+    let weak = strength::estimate("alice", "password");
+    assert_eq!(weak.score, 0);
+    assert!(weak.warning.is_some(), "a top password says why");
+    assert!(!weak.suggestions.is_empty());
+
+    let strong = strength::estimate("alice", "correct horse battery staple");
+    assert_eq!(strong.score, MAX_SCORE);
+    assert!(strong.guesses_log10 > 10.0);
+    assert!(strong.warning.is_none() && strong.suggestions.is_empty());
+    assert!(!strong.crack_time.is_empty());
+
+    // the username, and its parts, are the first thing an attacker tries
+    let password = "Pickering-Quokka";
+    let guesses = |user: &str| strength::estimate(user, password).guesses_log10;
+    assert!(guesses("alice.pickering@example.com") < guesses("alice"));
+
+    let empty = strength::estimate("alice", "");
+    assert_eq!(empty.score, 0);
+
+    println!("✅ Strength estimates tell weak from strong!");
+}
+
+#[test]
+fn test_strength_policy() {
+    println!("🔐 Testing a minimum password strength");
+
+    // passes the default rules, but is a word and a year
+    let password = "Summer2024!";
+    let policy = StrengthPolicy { min_score: 3, ..Default::default() };
+    assert_eq!(policy.version(), DEFAULT_POLICY_VERSION, "the server is told about the rules it knows");
+    let score = strength::estimate("alice", password).score;
+    assert!(score < 3, "scored {}", score);
+    assert_eq!(policy.check("alice", password), vec![Weakness::Guessable { score, min_score: 3 }]);
+    assert_eq!(
+        Weakness::Guessable { score: 1, min_score: 3 }.to_string(),
+        "it would be guessed too easily (strength 1 of 4, at least 3 wanted)"
+    );
+    assert!(policy.check("alice", "correct horse battery staple").is_empty());
+    assert!(StrengthPolicy::default().check("alice", password).is_empty(), "0 only reports");
+    assert!(policy.check("alice", "short").contains(&Weakness::TooShort { min_length: 10 }), "and the default rules");
+
+    println!("✅ Weak passwords are refused before anything is derived!");
+}
+
+#[test]
+fn test_keygen_min_strength() {
+    println!("🔐 Testing zkp-auth keygen with a minimum strength");
+
+    let dir = std::env::temp_dir().join(format!("zkp-strength-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let keygen = |password: &str, min_strength: &str| {
+        let out = dir.join(format!("alice-{}.json", min_strength));
+        let mut child = Command::new(env!("CARGO_BIN_EXE_zkp-auth"))
+            .args(["keygen", "--user", "alice", "--memory-kib", "64", "--iterations", "1", "--min-strength", min_strength, "--out"])
+            .arg(&out)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        writeln!(child.stdin.take().unwrap(), "{}", password).unwrap();
+        let output = child.wait_with_output().unwrap();
+        (output.status.success(), String::from_utf8_lossy(&output.stderr).into_owned(), out.exists())
+    };
+
+    let (ok, stderr, written) = keygen("Summer2024!", "3");
+    assert!(!ok && !written, "refused: {}", stderr);
+    assert!(stderr.contains("Password strength"), "{}", stderr);
+    assert!(stderr.contains("guessed too easily"), "{}", stderr);
+
+    let (ok, stderr, written) = keygen("Summer2024!", "0");
+    assert!(ok && written, "only reported: {}", stderr);
+    let (ok, _, written) = keygen("correct horse battery staple", "4");
+    assert!(ok && written);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+    println!("✅ zkp-auth refuses guessable passwords when asked to!");
+}
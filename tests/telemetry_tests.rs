@@ -0,0 +1,72 @@
+// Tests for how much of a person the server's console log shows
+use rust_zkp_chaum_pedersen::telemetry::{PrivacyLevel, TelemetryPolicy, REDACTED};
+use rust_zkp_chaum_pedersen::trace::TraceContext;
+
+#[test]
+fn test_debug_logs_as_before() {
+    println!("🕶️  Testing --privacy debug");
+
+    let policy = TelemetryPolicy::new(PrivacyLevel::Debug, 1.0);
+    assert_eq!(format!("{:?}", policy.user("alice")), "\"alice\"");
+    assert_eq!(format!("{}", policy.device("laptop")), "laptop");
+    assert_eq!(format!("{}", policy.source("192.0.2.7:41234")), "192.0.2.7:41234");
+    assert_eq!(TelemetryPolicy::default().level, PrivacyLevel::Debug, "unset means unchanged");
+
+    println!("✅ Debug logs show everything!");
+}
+
+#[test]
+fn test_standard_hides_who() {
+    println!("🕶️  Testing --privacy standard");
+
+    let policy = TelemetryPolicy::new(PrivacyLevel::Standard, 1.0);
+    let alice = format!("{:?}", policy.user("alice"));
+    assert!(alice.starts_with("u-") && alice.len() == 10, "{}", alice);
+    assert!(!alice.contains("alice"));
+    assert_eq!(alice, format!("{:?}", policy.user("alice")), "the same user reads the same in every line");
+    assert_ne!(alice, format!("{:?}", policy.user("bob")));
+    assert!(format!("{}", policy.device("laptop")).starts_with("d-"));
+
+    // a restart gets another salt
+    let restarted = TelemetryPolicy::new(PrivacyLevel::Standard, 1.0);
+    assert_ne!(alice, format!("{:?}", restarted.user("alice")));
+
+    // addresses keep their network, not the host or port
+    assert_eq!(policy.source("192.0.2.7:41234").to_string(), "192.0.2.0/24");
+    assert_eq!(policy.source("192.0.2.7").to_string(), "192.0.2.0/24");
+    assert_eq!(policy.source("[2001:db8:1:2::7]:443").to_string(), "2001:db8:1::/48");
+    assert_eq!(policy.source("").to_string(), "");
+
+    println!("✅ Standard logs tell users apart without naming them!");
+}
+
+#[test]
+fn test_strict_leaves_them_out() {
+    println!("🕶️  Testing --privacy strict");
+
+    let policy = TelemetryPolicy::new(PrivacyLevel::Strict, 1.0);
+    assert_eq!(format!("{:?}", policy.user("alice")), REDACTED);
+    assert_eq!(policy.device("laptop").to_string(), REDACTED);
+    assert_eq!(policy.source("192.0.2.7:41234").to_string(), REDACTED);
+
+    println!("✅ Strict logs name no one!");
+}
+
+#[test]
+fn test_sampling_is_per_trace() {
+    println!("🕶️  Testing --log-sample-rate");
+
+    let traces: Vec<TraceContext> = (0..1000).map(|_| TraceContext::new_root()).collect();
+    let all = TelemetryPolicy::new(PrivacyLevel::Standard, 1.0);
+    let none = TelemetryPolicy::new(PrivacyLevel::Standard, 0.0);
+    assert!(traces.iter().all(|trace| all.sampled(trace)));
+    assert!(!traces.iter().any(|trace| none.sampled(trace)));
+
+    // every line of a trace is in or out together
+    let tenth = TelemetryPolicy::new(PrivacyLevel::Standard, 0.1);
+    assert!(traces.iter().all(|trace| tenth.sampled(trace) == tenth.sampled(trace)));
+    let kept = traces.iter().filter(|trace| tenth.sampled(trace)).count();
+    assert!((30..=200).contains(&kept), "kept {} of 1000", kept);
+
+    println!("✅ Sampled traces are logged whole!");
+}
@@ -0,0 +1,134 @@
+// Timing-variance tests for solve(), in the style of dudect
+//
+// Each check times solve() on inputs from two classes, interleaved at random,
+// drops the slowest measurements (interrupts, page faults) and runs Welch's
+// t-test on the rest. A |t| above THRESHOLD means the classes can be told
+// apart by how long they take. The checks on solve() need many samples and a
+// quiet machine, so they're ignored by default; run them with:
+//   cargo test --release --test timing_tests -- --ignored --nocapture
+use std::hint::black_box;
+use std::time::Instant;
+
+use num_bigint::BigUint;
+use rust_zkp_chaum_pedersen::ZKP;
+
+/// |t| past which two classes are considered distinguishable (dudect's cutoff)
+const THRESHOLD: f64 = 4.5;
+
+/// measurements per class
+const SAMPLES: usize = 20_000;
+
+/// share of the slowest measurements thrown away before the test
+const CROP: f64 = 0.1;
+
+/// Welch's t statistic for the timings of two classes
+fn welch_t(timings: &[(usize, f64)]) -> f64 {
+    let mut sorted: Vec<f64> = timings.iter().map(|(_, nanos)| *nanos).collect();
+    sorted.sort_by(f64::total_cmp);
+    let cutoff = sorted[((sorted.len() as f64) * (1.0 - CROP)) as usize - 1];
+
+    // Welford's running mean and variance per class
+    let mut n = [0f64; 2];
+    let mut mean = [0f64; 2];
+    let mut m2 = [0f64; 2];
+    for &(class, nanos) in timings.iter().filter(|(_, nanos)| *nanos <= cutoff) {
+        n[class] += 1.0;
+        let delta = nanos - mean[class];
+        mean[class] += delta / n[class];
+        m2[class] += delta * (nanos - mean[class]);
+    }
+    let variance = [m2[0] / (n[0] - 1.0), m2[1] / (n[1] - 1.0)];
+    (mean[0] - mean[1]) / (variance[0] / n[0] + variance[1] / n[1]).sqrt()
+}
+
+/// time `f` on the inputs of both classes, in random order
+fn measure<I>(inputs: [Vec<I>; 2], mut f: impl FnMut(&I)) -> f64 {
+    let mut remaining = inputs.map(|inputs| inputs.into_iter());
+    let mut timings = Vec::with_capacity(remaining[0].len() + remaining[1].len());
+    loop {
+        let mut class = rand::random::<bool>() as usize;
+        if remaining[class].len() == 0 {
+            class = 1 - class;
+        }
+        let Some(input) = remaining[class].next() else { break };
+        let start = Instant::now();
+        f(black_box(&input));
+        timings.push((class, start.elapsed().as_nanos() as f64));
+    }
+    welch_t(&timings)
+}
+
+fn rfc5114() -> ZKP {
+    ZKP::builder().rfc5114().build().unwrap()
+}
+
+#[test]
+fn test_harness_detects_a_leak() {
+    println!("⏱️ Testing that the t-test notices a function that leaks");
+
+    // a full-size exponent against a one-bit one: as leaky as it gets
+    let zkp = rfc5114();
+    let inputs = [vec![zkp.q.clone(); 500], vec![BigUint::from(1u32); 500]];
+    let t = measure(inputs, |exponent| {
+        black_box(zkp.alpha.modpow(exponent, &zkp.p));
+    });
+
+    println!("📊 t = {:.1}", t);
+    assert!(t.abs() > THRESHOLD, "the harness can't see an obvious leak (t = {:.1})", t);
+    println!("✅ The harness tells the classes apart!");
+}
+
+#[test]
+#[ignore = "slow and timing-sensitive, run in release mode"]
+fn test_solve_time_independent_of_wraparound() {
+    println!("⏱️ Testing solve() for k >= c*x against k < c*x");
+
+    // c = 1 keeps both classes the same size: only the order of k and x differs
+    let zkp = rfc5114();
+    let c = BigUint::from(1u32);
+    let class = |wraps: bool| {
+        (0..SAMPLES)
+            .map(|_| {
+                let a = ZKP::generate_random_number_below(&zkp.q);
+                let b = ZKP::generate_random_number_below(&zkp.q);
+                let (small, large) = if a < b { (a, b) } else { (b, a) };
+                // (k, x)
+                if wraps { (small, large) } else { (large, small) }
+            })
+            .collect::<Vec<_>>()
+    };
+    let t = measure([class(false), class(true)], |(k, x)| {
+        black_box(zkp.solve(k, &c, x));
+    });
+
+    println!("📊 t = {:.1}", t);
+    assert!(t.abs() < THRESHOLD, "solve() takes longer on one side of k = c*x (t = {:.1})", t);
+    println!("✅ No timing difference around the wraparound!");
+}
+
+#[test]
+#[ignore = "slow and timing-sensitive, run in release mode"]
+fn test_solve_time_independent_of_secret() {
+    println!("⏱️ Testing solve() for a fixed secret against random ones");
+
+    // dudect's fixed-vs-random: one secret over and over, against fresh ones
+    let zkp = rfc5114();
+    let fixed = ZKP::generate_random_number_below(&zkp.q);
+    let class = |secret: Option<&BigUint>| {
+        (0..SAMPLES)
+            .map(|_| {
+                let k = ZKP::generate_random_number_below(&zkp.q);
+                let c = zkp.random_challenge().0;
+                let x = secret.cloned().unwrap_or_else(|| ZKP::generate_random_number_below(&zkp.q));
+                (k, c, x)
+            })
+            .collect::<Vec<_>>()
+    };
+    let t = measure([class(Some(&fixed)), class(None)], |(k, c, x)| {
+        black_box(zkp.solve(k, c, x));
+    });
+
+    println!("📊 t = {:.1}", t);
+    assert!(t.abs() < THRESHOLD, "how long solve() takes depends on the secret (t = {:.1})", t);
+    println!("✅ No timing difference between secrets!");
+}
@@ -0,0 +1,111 @@
+// Tests for serving TLS with certificates that are swapped at runtime
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use rust_zkp_chaum_pedersen::tls::{self, CertReloader};
+use tonic::transport::{Certificate, ClientTlsConfig, Endpoint, Server};
+use tonic_health::pb::{health_client::HealthClient, HealthCheckRequest};
+
+mod common;
+use common::temp_dir;
+
+/// write a fresh self-signed localhost certificate, returning its PEM
+fn write_cert(dir: &Path, modified: SystemTime) -> String {
+    let generated = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+    let cert_pem = generated.cert.pem();
+    fs::write(dir.join("cert.pem"), &cert_pem).unwrap();
+    fs::write(dir.join("key.pem"), generated.key_pair.serialize_pem()).unwrap();
+
+    // make the change visible even on filesystems with coarse timestamps
+    for file in ["cert.pem", "key.pem"] {
+        fs::File::options()
+            .write(true)
+            .open(dir.join(file))
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+    }
+    cert_pem
+}
+
+fn pem_to_der(pem: &str) -> Vec<u8> {
+    let mut reader = pem.as_bytes();
+    let der = rustls_pemfile::certs(&mut reader).next().unwrap().unwrap();
+    der.to_vec()
+}
+
+#[test]
+fn test_reload_picks_up_renewed_certificate() {
+    println!("🔒 Testing certificate reload");
+
+    let dir = temp_dir("reload");
+    let now = SystemTime::now();
+    let first = write_cert(&dir, now);
+
+    let reloader = CertReloader::load(dir.join("cert.pem"), dir.join("key.pem")).unwrap();
+    assert_eq!(reloader.current_certificate().to_vec(), pem_to_der(&first));
+    assert!(!reloader.reload_if_changed().unwrap(), "nothing changed yet");
+
+    let second = write_cert(&dir, now + Duration::from_secs(60));
+    assert!(reloader.reload_if_changed().unwrap());
+    assert_eq!(reloader.current_certificate().to_vec(), pem_to_der(&second));
+
+    // a broken renewal keeps serving the last good certificate
+    fs::write(dir.join("cert.pem"), "not a certificate").unwrap();
+    assert!(reloader.reload_if_changed().is_err());
+    assert_eq!(reloader.current_certificate().to_vec(), pem_to_der(&second));
+
+    fs::remove_dir_all(dir).unwrap();
+    println!("✅ Certificate reload test passed!");
+}
+
+async fn health_check(port: u16, ca_pem: &str) -> Result<(), tonic::transport::Error> {
+    let tls = ClientTlsConfig::new()
+        .ca_certificate(Certificate::from_pem(ca_pem))
+        .domain_name("localhost");
+    let channel = Endpoint::from_shared(format!("https://localhost:{}", port))
+        .unwrap()
+        .tls_config(tls)?
+        .connect()
+        .await?;
+    HealthClient::new(channel)
+        .check(HealthCheckRequest { service: String::new() })
+        .await
+        .expect("health check over TLS failed");
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_tls_server_serves_rotated_certificate() {
+    println!("🔒 Testing TLS server with certificate rotation");
+
+    let dir = temp_dir("serve");
+    let now = SystemTime::now();
+    let first = write_cert(&dir, now);
+    let reloader = Arc::new(CertReloader::load(dir.join("cert.pem"), dir.join("key.pem")).unwrap());
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let (_, health_service) = tonic_health::server::health_reporter();
+    tokio::spawn(
+        Server::builder()
+            .add_service(health_service)
+            .serve_with_incoming(tls::incoming(listener, reloader.server_config())),
+    );
+
+    health_check(port, &first).await.expect("first certificate should be trusted");
+
+    // rotate: new connections must now present the second certificate
+    let second = write_cert(&dir, now + Duration::from_secs(60));
+    assert!(reloader.reload_if_changed().unwrap());
+    health_check(port, &second).await.expect("rotated certificate should be served");
+    assert!(
+        health_check(port, &first).await.is_err(),
+        "the old certificate should no longer be presented"
+    );
+
+    fs::remove_dir_all(dir).unwrap();
+    println!("✅ TLS rotation test passed!");
+}
@@ -0,0 +1,111 @@
+// Tests for trust-on-first-use pinning of server certificates and keys
+use std::sync::Arc;
+
+use rust_zkp_chaum_pedersen::tls;
+use rust_zkp_chaum_pedersen::tofu::{self, KnownServers, TofuError, Trust};
+use tokio_rustls::rustls::crypto::ring;
+use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer};
+use tokio_rustls::rustls::sign::CertifiedKey;
+use tokio_rustls::rustls::server::ResolvesServerCert;
+use tonic::transport::Server;
+use tonic_health::pb::{health_client::HealthClient, HealthCheckRequest};
+
+#[test]
+fn test_known_servers_remember_first_use() {
+    println!("🤝 Testing trust on first use decisions");
+
+    let endpoint = "https://zkp.example:50051";
+    let mut known = KnownServers::default();
+    assert_eq!(known.check_tls(endpoint, "aaaa"), Trust::FirstUse);
+    assert_eq!(known.check_tls(endpoint, "aaaa"), Trust::Known);
+    assert_eq!(
+        known.check_tls(endpoint, "bbbb"),
+        Trust::Changed { known: "aaaa".to_string() }
+    );
+    // a changed value is never remembered on its own
+    assert_eq!(known.get(endpoint).unwrap().tls_fingerprint.as_deref(), Some("aaaa"));
+
+    // the parameter key is tracked separately
+    assert_eq!(known.check_params_key(endpoint, "cafe"), Trust::FirstUse);
+    assert_eq!(known.check_params_key(endpoint, "cafe"), Trust::Known);
+
+    let path = std::env::temp_dir()
+        .join(format!("zkp-known-servers-{}", std::process::id()))
+        .join("known_servers.json");
+    known.save(&path).unwrap();
+    assert_eq!(KnownServers::load(&path).unwrap(), known);
+
+    assert!(known.forget(endpoint));
+    assert_eq!(known.check_tls(endpoint, "bbbb"), Trust::FirstUse);
+
+    std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    println!("✅ Trust on first use test passed!");
+}
+
+/// resolver serving a fresh self-signed localhost certificate
+#[derive(Debug)]
+struct SelfSigned(Arc<CertifiedKey>);
+
+impl SelfSigned {
+    fn generate() -> (Arc<Self>, Vec<u8>) {
+        let generated = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+        let der = generated.cert.der().to_vec();
+        let key = PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(generated.key_pair.serialize_der()));
+        let signing_key = ring::sign::any_supported_type(&key).unwrap();
+        let certified = CertifiedKey::new(vec![CertificateDer::from(der.clone())], signing_key);
+        (Arc::new(SelfSigned(Arc::new(certified))), der)
+    }
+}
+
+impl ResolvesServerCert for SelfSigned {
+    fn resolve(
+        &self,
+        _client_hello: tokio_rustls::rustls::server::ClientHello,
+    ) -> Option<Arc<CertifiedKey>> {
+        Some(self.0.clone())
+    }
+}
+
+async fn serve(resolver: Arc<SelfSigned>) -> String {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let (_, health_service) = tonic_health::server::health_reporter();
+    tokio::spawn(
+        Server::builder()
+            .add_service(health_service)
+            .serve_with_incoming(tls::incoming(listener, tls::grpc_server_config(resolver))),
+    );
+    format!("https://localhost:{}", port)
+}
+
+#[tokio::test]
+async fn test_pinned_connection_rejects_new_certificate() {
+    println!("🤝 Testing certificate pinning against a re-keyed server");
+
+    let (first, first_der) = SelfSigned::generate();
+    let endpoint = serve(first).await;
+
+    // first use: anything goes, and we learn the fingerprint
+    let (channel, seen) = tofu::connect_pinned(&endpoint, None).await.unwrap();
+    assert_eq!(seen, tofu::fingerprint(&first_der));
+    HealthClient::new(channel)
+        .check(HealthCheckRequest { service: String::new() })
+        .await
+        .expect("health check over pinned TLS failed");
+
+    // same server, same pin
+    tofu::connect_pinned(&endpoint, Some(seen.clone())).await.unwrap();
+
+    // a different certificate on the same endpoint is refused
+    let (second, second_der) = SelfSigned::generate();
+    let impostor = serve(second).await;
+    match tofu::connect_pinned(&impostor, Some(seen.clone())).await {
+        Err(TofuError::Changed { known, presented }) => {
+            assert_eq!(known, seen);
+            assert_eq!(presented, tofu::fingerprint(&second_der));
+        }
+        other => panic!("expected a changed certificate, got {:?}", other.map(|(_, fp)| fp)),
+    }
+
+    println!("✅ Certificate pinning test passed!");
+}
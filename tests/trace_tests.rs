@@ -0,0 +1,54 @@
+// Tests for W3C traceparent propagation
+use rust_zkp_chaum_pedersen::trace::{self, TraceContext, TRACEPARENT};
+
+const EXAMPLE: &str = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+
+#[test]
+fn test_traceparent_roundtrip() {
+    println!("🧵 Testing traceparent parsing");
+
+    let context = TraceContext::parse(EXAMPLE).expect("spec example should parse");
+    assert_eq!(context.trace_id_hex(), "4bf92f3577b34da6a3ce929d0e0e4736");
+    assert!(context.sampled);
+    assert_eq!(context.to_string(), EXAMPLE);
+
+    // a child stays in the same trace with a new span
+    let child = context.child();
+    assert_eq!(child.trace_id, context.trace_id);
+    assert_ne!(child.span_id, context.span_id);
+
+    // future versions may append fields, we still read the ones we know
+    assert!(TraceContext::parse("01-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-00-extra").is_some());
+
+    for invalid in [
+        "",
+        "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7",
+        "00-4BF92F3577B34DA6A3CE929D0E0E4736-00f067aa0ba902b7-01",
+        "00-00000000000000000000000000000000-00f067aa0ba902b7-01",
+        "00-4bf92f3577b34da6a3ce929d0e0e4736-0000000000000000-01",
+        "ff-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+        "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01-extra",
+    ] {
+        assert!(TraceContext::parse(invalid).is_none(), "{:?} should be rejected", invalid);
+    }
+
+    println!("✅ Traceparent parsing test passed!");
+}
+
+#[test]
+fn test_server_interceptor_continues_the_callers_trace() {
+    let caller = TraceContext::parse(EXAMPLE).unwrap();
+    let mut request = tonic::Request::new(());
+    caller.inject(request.metadata_mut());
+
+    let request = trace::server_interceptor(request).unwrap();
+    let server = trace::current(&request);
+    assert_eq!(server.trace_id, caller.trace_id, "the server joins the caller's trace");
+    assert_ne!(server.span_id, caller.span_id, "in a span of its own");
+
+    // no (or a garbage) header starts a fresh trace
+    let mut request = tonic::Request::new(());
+    request.metadata_mut().insert(TRACEPARENT, "garbage".parse().unwrap());
+    let request = trace::server_interceptor(request).unwrap();
+    assert_ne!(trace::current(&request).trace_id, caller.trace_id);
+}
@@ -0,0 +1,53 @@
+// Tests for the typed protocol values
+use rust_zkp_chaum_pedersen::types::{Challenge, Commitment, PublicKey, Response};
+use rust_zkp_chaum_pedersen::ZKP;
+
+fn setup() -> ZKP {
+    ZKP::builder().rfc5114().build().unwrap()
+}
+
+#[test]
+fn test_typed_round_trip() {
+    println!("🏷️  Testing the typed prove/verify API");
+
+    let zkp = setup();
+    let x = ZKP::generate_random_number_below(&zkp.q);
+    let k = ZKP::generate_random_number_below(&zkp.q);
+
+    let key = zkp.public_key(&x);
+    let commitment = zkp.commitment(&k);
+    let c = zkp.random_challenge();
+    let s = zkp.respond(&k, &c, &x);
+
+    assert!(zkp.verify_response(&key, &commitment, &c, &s));
+    // same result as the untyped API
+    assert!(zkp.verify(&commitment.r1, &commitment.r2, &key.y1, &key.y2, &c.0, &s.0));
+
+    let wrong = zkp.respond(&k, &c, &(x + 1u32));
+    assert!(!zkp.verify_response(&key, &commitment, &c, &wrong));
+
+    println!("✅ Typed API works!");
+}
+
+#[test]
+fn test_typed_wire_conversions() {
+    println!("📦 Testing byte conversions of the typed values");
+
+    let zkp = setup();
+    let key = zkp.public_key(&ZKP::generate_random_number_below(&zkp.q));
+    let (y1, y2) = key.to_bytes_be();
+    assert_eq!(PublicKey::from_bytes_be(&y1, &y2), key);
+
+    let commitment = zkp.commitment(&ZKP::generate_random_number_below(&zkp.q));
+    let (r1, r2) = commitment.to_bytes_be();
+    assert_eq!(Commitment::from_bytes_be(&r1, &r2), commitment);
+
+    let c = zkp.random_challenge();
+    assert_eq!(Challenge::from_bytes_be(&c.to_bytes_be()), c);
+    assert!(c.as_ref() < &zkp.q);
+
+    let s = Response::from(c.clone().into_inner());
+    assert_eq!(s.0, c.0);
+
+    println!("✅ Conversions round-trip!");
+}
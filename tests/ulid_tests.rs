@@ -0,0 +1,51 @@
+// Tests for time-ordered auth_ids
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use rust_zkp_chaum_pedersen::ulid::{Ulid, UlidGenerator};
+
+#[test]
+fn test_ulid_text_round_trip() {
+    println!("🔤 Testing ULID encoding");
+
+    let id = Ulid::new(1_700_000_000_123, 0x1234_5678_9abc_def0_1234);
+    let text = id.to_string();
+    assert_eq!(text.len(), 26);
+    assert_eq!(text.parse::<Ulid>(), Ok(id));
+    assert_eq!(text.to_lowercase().parse::<Ulid>(), Ok(id));
+    assert_eq!(id.timestamp_millis(), 1_700_000_000_123);
+    assert_eq!(id.issued_at(), UNIX_EPOCH + Duration::from_millis(1_700_000_000_123));
+
+    // the all-zero and all-ones ids of the spec
+    assert_eq!(Ulid::new(0, 0).to_string(), "00000000000000000000000000");
+    assert!("7ZZZZZZZZZZZZZZZZZZZZZZZZZ".parse::<Ulid>().is_ok());
+    assert!("80000000000000000000000000".parse::<Ulid>().is_err());
+    assert!("0000000000000000000000000U".parse::<Ulid>().is_err());
+    assert!("abc".parse::<Ulid>().is_err());
+
+    println!("✅ ULIDs round-trip!");
+}
+
+#[test]
+fn test_ulids_are_monotonic() {
+    println!("📈 Testing ULID ordering");
+
+    let generator = UlidGenerator::default();
+    let now = SystemTime::now();
+
+    // same millisecond, and a clock that steps back, still increase
+    let ids: Vec<String> = [now, now, now - Duration::from_secs(5), now + Duration::from_millis(1)]
+        .into_iter()
+        .map(|t| generator.next(t).to_string())
+        .collect();
+    let mut sorted = ids.clone();
+    sorted.sort();
+    sorted.dedup();
+    assert_eq!(sorted, ids);
+
+    // the lower bound for a time sorts before every id issued from then on
+    let bound = Ulid::lower_bound(now).to_string();
+    assert!(ids.iter().all(|id| *id >= bound));
+    assert!(Ulid::lower_bound(now + Duration::from_secs(1)).to_string() > ids[3]);
+
+    println!("✅ ULIDs sort by issue time!");
+}
@@ -0,0 +1,235 @@
+// Tests for the --user-ids modes: usernames, verified email addresses, issued subjects and key fingerprints
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+use tonic::transport::Channel;
+use tonic::Code;
+
+use rust_zkp_chaum_pedersen::api::v1::{
+    auth_client::AuthClient, AuthenticationAnswerRequest, AuthenticationChallengeRequest, RegisterRequest,
+    ServerInfoRequest, VerifyEmailRequest,
+};
+use rust_zkp_chaum_pedersen::group::GroupId;
+use rust_zkp_chaum_pedersen::types::Challenge;
+use rust_zkp_chaum_pedersen::user_id::{self, UserIdError, UserIds, VerificationCode, MAX_ATTEMPTS};
+use rust_zkp_chaum_pedersen::ZKP;
+
+mod common;
+use common::{auth_client, Server};
+
+#[test]
+fn test_canonical_ids() {
+    println!("🆔 Testing canonical user ids");
+
+    assert_eq!(UserIds::Username.canonical(" Alice "), Ok(" Alice ".to_string()), "usernames are taken as is");
+    assert_eq!(UserIds::Username.canonical(""), Err(UserIdError::Empty));
+
+    assert_eq!(UserIds::Email.canonical("Alice@Example.COM"), Ok("alice@example.com".to_string()));
+    for bad in ["alice", "alice@", "@example.com", "alice@example", "alice@@example.com", "a b@example.com", "alice@example..com"] {
+        assert_eq!(UserIds::Email.canonical(bad), Err(UserIdError::NotEmail), "{}", bad);
+    }
+    assert_eq!(UserIds::Email.canonical(&format!("{}@example.com", "a".repeat(65))), Err(UserIdError::NotEmail));
+
+    let subject = user_id::new_subject();
+    assert_eq!(UserIds::Subject.canonical(&subject), Ok(subject.clone()));
+    assert_eq!(&subject[14..15], "4", "a version 4 UUID");
+    assert_ne!(subject, user_id::new_subject());
+    assert_eq!(UserIds::Subject.canonical(&subject.to_uppercase()), Ok(subject.clone()));
+    assert_eq!(UserIds::Subject.canonical("alice"), Err(UserIdError::NotSubject));
+    assert_eq!(UserIds::Subject.canonical(&subject.replace('-', "_")), Err(UserIdError::NotSubject));
+
+    println!("✅ Every kind of id has one canonical form!");
+}
+
+#[test]
+fn test_verification_code() {
+    println!("🆔 Testing verification codes");
+
+    let sent = Instant::now();
+    let mut code = VerificationCode::new(sent);
+    assert_eq!(code.code().len(), 8);
+    assert!(code.code().bytes().all(|b| b.is_ascii_digit()));
+    assert!(!code.can_resend(sent));
+    assert!(code.can_resend(sent + user_id::RESEND_INTERVAL));
+    let right = code.code().to_string();
+    assert!(!code.matches(&right, sent + user_id::CODE_TTL), "expired");
+    assert!(code.matches(&right, sent));
+
+    // guessing spends the code
+    let mut code = VerificationCode::new(sent);
+    let right = code.code().to_string();
+    for _ in 0..MAX_ATTEMPTS {
+        assert!(!code.matches("not it", sent));
+    }
+    assert!(!code.matches(&right, sent));
+
+    println!("✅ Codes expire and can't be guessed at leisure!");
+}
+
+fn start(port: u16, args: &[&str]) -> Server {
+    Server(
+        Command::new(env!("CARGO_BIN_EXE_server"))
+            .args(["--listen", &format!("127.0.0.1:{}", port)])
+            .args(args)
+            .stdout(Stdio::null())
+            .spawn()
+            .expect("Failed to start server process"),
+    )
+}
+
+/// a registration for `user` and the secret behind it
+fn registration(user: &str) -> (RegisterRequest, num_bigint::BigUint) {
+    let zkp = ZKP::builder().rfc5114().build().unwrap();
+    let x = ZKP::generate_random_number_below(&zkp.q);
+    let (y1, y2) = zkp.public_key(&x).to_bytes_be();
+    (RegisterRequest { user: user.to_string(), y1, y2, ..Default::default() }, x)
+}
+
+/// log in as `user`, returning the subject the server answers with
+async fn login(client: &mut AuthClient<Channel>, user: &str, x: &num_bigint::BigUint) -> Result<String, tonic::Status> {
+    let zkp = ZKP::builder().rfc5114().build().unwrap();
+    let k = ZKP::generate_random_number_below(&zkp.q);
+    let (r1, r2) = zkp.commitment(&k).to_bytes_be();
+    let request = AuthenticationChallengeRequest { user: user.to_string(), r1, r2, ..Default::default() };
+    let response = client.create_authentication_challenge(request).await?.into_inner();
+    let s = zkp.respond(&k, &Challenge::from_bytes_be(&response.c), x).to_bytes_be();
+    let request = AuthenticationAnswerRequest { auth_id: response.auth_id, s, ..Default::default() };
+    Ok(client.verify_authentication(request).await?.into_inner().subject)
+}
+
+/// the last code the verification command got for `email`
+async fn sent_code(codes: &Path, email: &str) -> String {
+    for _ in 0..50 {
+        let sent = std::fs::read_to_string(codes).unwrap_or_default();
+        if let Some(code) = sent.lines().rev().find_map(|line| line.strip_prefix(&format!("{} ", email))) {
+            return code.to_string();
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    panic!("no code was sent to {}", email);
+}
+
+#[tokio::test]
+async fn test_email_ids() {
+    println!("🆔 Testing email addresses as user ids");
+
+    let dir = std::env::temp_dir().join(format!("zkp-user-ids-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let codes = dir.join("codes");
+    let store = dir.join("users.jsonl");
+    let command = format!("echo \"$ZKP_EMAIL $ZKP_CODE\" >> {}", codes.display());
+    let args = ["--user-ids", "email", "--email-verification-command", &command, "--user-store", store.to_str().unwrap()];
+    let server = start(50091, &args);
+    let mut client = auth_client(50091).await;
+
+    let info = client.get_server_info(ServerInfoRequest {}).await.unwrap().into_inner();
+    assert_eq!(info.user_ids, "email");
+    let status = client.register(registration("alice").0).await.unwrap_err();
+    assert_eq!((status.code(), status.message()), (Code::InvalidArgument, "user must be an email address"));
+
+    let (request, x) = registration("Alice@Example.com");
+    let response = client.register(request).await.unwrap().into_inner();
+    assert_eq!(response.subject, "alice@example.com");
+    assert!(response.verify_email);
+    let status = client.register(registration("ALICE@example.com").0).await.unwrap_err();
+    assert_eq!(status.code(), Code::AlreadyExists, "one account per address, whatever the case");
+
+    // no logins until the code is entered
+    let status = login(&mut client, "alice@example.com", &x).await.unwrap_err();
+    assert_eq!(status.code(), Code::FailedPrecondition);
+    let code = sent_code(&codes, "alice@example.com").await;
+    let verify = |code: &str| VerifyEmailRequest { user: "Alice@Example.com".to_string(), code: code.to_string() };
+    let status = client.verify_email(verify("00000000x")).await.unwrap_err();
+    assert_eq!(status.code(), Code::PermissionDenied);
+    let status = client.verify_email(verify("")).await.unwrap_err();
+    assert_eq!(status.code(), Code::ResourceExhausted, "one code a minute");
+    assert!(client.verify_email(verify(&code)).await.unwrap().into_inner().verified);
+    assert!(client.verify_email(verify("")).await.unwrap().into_inner().verified, "nothing left to verify");
+
+    assert_eq!(login(&mut client, "ALICE@EXAMPLE.COM", &x).await.unwrap(), "alice@example.com");
+
+    // the verification is in the journal
+    drop(server);
+    let _server = start(50092, &args);
+    let mut client = auth_client(50092).await;
+    assert_eq!(login(&mut client, "alice@example.com", &x).await.unwrap(), "alice@example.com");
+
+    std::fs::remove_dir_all(&dir).unwrap();
+    println!("✅ Email addresses are lowercased and verified!");
+}
+
+#[tokio::test]
+async fn test_subject_ids() {
+    println!("🆔 Testing subjects issued by the server");
+
+    let _server = start(50093, &["--user-ids", "subject"]);
+    let mut client = auth_client(50093).await;
+
+    let status = client.register(registration("alice").0).await.unwrap_err();
+    assert_eq!(status.code(), Code::InvalidArgument);
+
+    let (request, x) = registration("");
+    let request = RegisterRequest { idempotency_key: "register-once".to_string(), ..request };
+    let subject = client.register(request.clone()).await.unwrap().into_inner().subject;
+    assert_eq!(UserIds::Subject.canonical(&subject), Ok(subject.clone()));
+    let retried = client.register(request).await.unwrap().into_inner().subject;
+    assert_eq!(retried, subject, "a retry gets the subject it was issued");
+    let other = client.register(registration("").0).await.unwrap().into_inner().subject;
+    assert_ne!(other, subject);
+
+    assert_eq!(login(&mut client, &subject.to_uppercase(), &x).await.unwrap(), subject);
+    let status = login(&mut client, "alice", &x).await.unwrap_err();
+    assert_eq!(status.message(), "user must be a subject (a UUID) issued at registration");
+
+    println!("✅ Subjects are issued once and logged in with!");
+}
+
+#[test]
+fn test_key_fingerprints() {
+    println!("🆔 Testing public key fingerprints");
+
+    let (request, _) = registration("");
+    let fingerprint = user_id::fingerprint(GroupId::ModP, &request.y1, &request.y2);
+    assert_eq!(UserIds::Fingerprint.canonical(&fingerprint.to_uppercase()), Ok(fingerprint.clone()));
+    assert_eq!(UserIds::Fingerprint.canonical(&fingerprint[1..]), Err(UserIdError::NotFingerprint));
+    let padded = [vec![0, 0], request.y1.clone()].concat();
+    assert_eq!(user_id::fingerprint(GroupId::ModP, &padded, &request.y2), fingerprint, "the numbers count, not their encoding");
+    assert_ne!(user_id::fingerprint(GroupId::P256, &request.y1, &request.y2), fingerprint, "the group counts");
+    assert_ne!(user_id::fingerprint(GroupId::ModP, &request.y2, &request.y1), fingerprint);
+    // pinned: clients compute it too
+    assert_eq!(
+        user_id::fingerprint(GroupId::ModP, &[1], &[2]),
+        "092a13b1abc2d5fabc591cf8ce180c8cb5d0c4c509ce6663476771cb1d3366e4"
+    );
+
+    println!("✅ A key has exactly one fingerprint!");
+}
+
+#[tokio::test]
+async fn test_fingerprint_ids() {
+    println!("🆔 Testing pseudonymous accounts known by their key");
+
+    let _server = start(50094, &["--user-ids", "fingerprint"]);
+    let mut client = auth_client(50094).await;
+    assert_eq!(client.get_server_info(ServerInfoRequest {}).await.unwrap().into_inner().user_ids, "fingerprint");
+
+    let (request, x) = registration("");
+    let fingerprint = user_id::fingerprint(GroupId::ModP, &request.y1, &request.y2);
+    assert_eq!(client.register(request.clone()).await.unwrap().into_inner().subject, fingerprint);
+    let status = client.register(request.clone()).await.unwrap_err();
+    assert_eq!(status.code(), Code::AlreadyExists, "one account per key");
+    let status = client.register(RegisterRequest { user: "alice".to_string(), ..registration("").0 }).await.unwrap_err();
+    assert_eq!(status.code(), Code::InvalidArgument, "no names");
+    let (other, _) = registration("");
+    let named = user_id::fingerprint(GroupId::ModP, &other.y1, &other.y2);
+    let response = client.register(RegisterRequest { user: named.clone(), ..other }).await.unwrap().into_inner();
+    assert_eq!(response.subject, named, "its own fingerprint is fine");
+    let status = client.register(RegisterRequest { beta: vec![1], ..registration("").0 }).await.unwrap_err();
+    assert_eq!(status.code(), Code::InvalidArgument, "no per-user generators");
+
+    assert_eq!(login(&mut client, &fingerprint, &x).await.unwrap(), fingerprint);
+
+    println!("✅ Keys log in without a name!");
+}
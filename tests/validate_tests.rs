@@ -0,0 +1,100 @@
+// Tests for the request validation generated from the proto field rules
+use std::process::{Command, Stdio};
+
+use tonic::{Code, Request, Status};
+
+use rust_zkp_chaum_pedersen::admin::AdminToken;
+use rust_zkp_chaum_pedersen::api::v1::{
+    admin_client::AdminClient, auth_client::AuthClient, AuthenticationChallengeRequest, BulkRegisterResponse,
+    ClientMetadata, IntrospectSessionRequest, KdfParams, RegisterRequest, RevokeProofRequest,
+};
+use rust_zkp_chaum_pedersen::validate::{Validate, Violation};
+use rust_zkp_chaum_pedersen::ZKP;
+
+mod common;
+use common::{connect, Server};
+
+fn registration(user: &str) -> RegisterRequest {
+    let zkp = ZKP::builder().rfc5114().build().unwrap();
+    let (y1, y2) = zkp.public_key(&ZKP::generate_random_number_below(&zkp.q)).to_bytes_be();
+    RegisterRequest { user: user.to_string(), y1, y2, ..Default::default() }
+}
+
+fn violation(message: &impl Validate) -> String {
+    message.validate().unwrap_err().to_string()
+}
+
+#[test]
+fn test_field_rules() {
+    println!("📏 Testing the generated field rules");
+
+    assert_eq!(registration("alice").validate(), Ok(()));
+    assert_eq!(violation(&AuthenticationChallengeRequest::default()), "user must not be empty");
+    assert_eq!(violation(&RegisterRequest::default()), "y1 must not be empty", "an empty user is up to --user-ids");
+    assert_eq!(violation(&RegisterRequest { y1: vec![], ..registration("alice") }), "y1 must not be empty");
+    assert_eq!(violation(&RegisterRequest { y2: vec![1; 513], ..registration("alice") }), "y2 must be at most 512 bytes");
+    assert_eq!(RegisterRequest { y2: vec![1; 512], ..registration("alice") }.validate(), Ok(()), "the bound is inclusive");
+    assert_eq!(violation(&registration(&"é".repeat(129))), "user must be at most 256 bytes", "bytes, not characters");
+
+    // rules of nested messages, with the path to the field
+    let kdf = KdfParams { salt: vec![0; 65], memory_kib: 19456, iterations: 2, parallelism: 1 };
+    assert_eq!(violation(&RegisterRequest { kdf: Some(kdf), ..registration("alice") }), "kdf.salt must be at most 64 bytes");
+    let client = ClientMetadata { device_id: "d".repeat(129), ..Default::default() };
+    let request = AuthenticationChallengeRequest { user: "alice".to_string(), r1: vec![1], r2: vec![1], client: Some(client), ..Default::default() };
+    let Err(Violation { field, .. }) = request.validate() else { panic!("the device id is too long") };
+    assert_eq!(field, "client.device_id");
+
+    let status = Status::from(RevokeProofRequest::default().validate().unwrap_err());
+    assert_eq!((status.code(), status.message()), (Code::InvalidArgument, "user must not be empty"));
+    assert_eq!(IntrospectSessionRequest::default().validate(), Ok(()), "an empty token is just inactive");
+    assert_eq!(BulkRegisterResponse::default().validate(), Ok(()), "responses have no rules");
+
+    println!("✅ The proto options became checks!");
+}
+
+#[tokio::test]
+async fn test_server_validates_requests() {
+    println!("📏 Testing validation on the server");
+
+    let dir = std::env::temp_dir().join(format!("zkp-validate-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let token_file = dir.join("admin-token");
+    std::fs::write(&token_file, "correct-horse-battery-staple").unwrap();
+    let token = AdminToken::load(&token_file).unwrap();
+    let port = 50090;
+    let _server = Server(
+        Command::new(env!("CARGO_BIN_EXE_server"))
+            .args(["--listen", &format!("127.0.0.1:{}", port)])
+            .arg("--admin-token-file")
+            .arg(&token_file)
+            .stdout(Stdio::null())
+            .spawn()
+            .expect("Failed to start server process"),
+    );
+    let channel = connect(port).await;
+    let mut client = AuthClient::new(channel.clone());
+    let mut admin = AdminClient::new(channel);
+
+    let status = client.register(RegisterRequest { user: String::new(), ..registration("") }).await.unwrap_err();
+    assert_eq!((status.code(), status.message()), (Code::InvalidArgument, "user must not be empty"));
+    client.register(registration("alice")).await.unwrap();
+    let request = AuthenticationChallengeRequest { user: "alice".to_string(), r1: vec![1; 600], r2: vec![1], ..Default::default() };
+    let status = client.create_authentication_challenge(request).await.unwrap_err();
+    assert_eq!((status.code(), status.message()), (Code::InvalidArgument, "r1 must be at most 512 bytes"));
+    // the rules come before the handler's own checks, like the session
+    let request = RevokeProofRequest { user: "alice".to_string(), session_id: "nope".to_string(), ..Default::default() };
+    let status = client.revoke_proof(request).await.unwrap_err();
+    assert_eq!((status.code(), status.message()), (Code::InvalidArgument, "proof_id must not be empty"));
+
+    // a bad registration in a bulk import is one failure, not the end of the stream
+    let users = vec![registration("bulk_1"), RegisterRequest { y1: vec![], ..registration("bulk_2") }, registration("bulk_3")];
+    let response = admin.bulk_register(token.authorize(Request::new(tokio_stream::iter(users)))).await.unwrap().into_inner();
+    assert_eq!((response.registered, response.failed), (2, 1));
+    assert_eq!(response.failures[0].user, "bulk_2");
+    assert_eq!(Code::from(response.failures[0].code), Code::InvalidArgument);
+    assert_eq!(response.failures[0].message, "y1 must not be empty");
+
+    std::fs::remove_dir_all(&dir).unwrap();
+    println!("✅ Invalid requests never reach the handlers!");
+}
@@ -0,0 +1,295 @@
+// Tests for reading secrets from Vault (cargo test --features vault), against a fake Vault
+#![cfg(feature = "vault")]
+
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::process::{Command, Stdio};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use ed25519_dalek::SigningKey;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Response, StatusCode};
+use serde_json::{json, Value};
+use tonic::transport::{Certificate, Channel, ClientTlsConfig, Endpoint};
+use tonic::Code;
+
+use rust_zkp_chaum_pedersen::admin::AdminToken;
+use rust_zkp_chaum_pedersen::api::v1::{
+    admin_client::AdminClient, auth_client::AuthClient, AuthenticationAnswerRequest, AuthenticationChallengeRequest,
+    IdTokenRequest, RegisterRequest, SetUserEnabledRequest,
+};
+use rust_zkp_chaum_pedersen::id_token;
+use rust_zkp_chaum_pedersen::types::Challenge;
+use rust_zkp_chaum_pedersen::vault::{SecretRef, VaultClient, VaultError};
+use rust_zkp_chaum_pedersen::ZKP;
+
+mod common;
+use common::{temp_dir, Server};
+
+const VAULT_TOKEN: &str = "hvs.test-token";
+
+/// The parts of Vault's HTTP API the server uses: KV v2 reads, transit key
+/// export and token lookup/renewal
+#[derive(Default)]
+struct FakeVault {
+    kv: HashMap<String, Value>,                // "mount/path" -> fields
+    transit: HashMap<String, (u64, SigningKey)>, // "mount/key" -> latest version
+    renewals: usize,
+}
+
+impl FakeVault {
+    fn answer(&mut self, method: &Method, path: &str, token: Option<&str>) -> (StatusCode, Value) {
+        if token != Some(VAULT_TOKEN) {
+            return (StatusCode::FORBIDDEN, json!({ "errors": ["permission denied"] }));
+        }
+        let path = path.strip_prefix("/v1/").unwrap_or_default();
+        match (method, path) {
+            (&Method::GET, "auth/token/lookup-self") => (StatusCode::OK, json!({ "data": { "ttl": 3600, "renewable": true } })),
+            (&Method::POST, "auth/token/renew-self") => {
+                self.renewals += 1;
+                (StatusCode::OK, json!({ "auth": { "lease_duration": 3600, "renewable": true } }))
+            }
+            (&Method::GET, path) if path.contains("/data/") => {
+                let (mount, path) = path.split_once("/data/").unwrap();
+                match self.kv.get(&format!("{}/{}", mount, path)) {
+                    Some(fields) => (StatusCode::OK, json!({ "data": { "data": fields, "metadata": { "version": 1 } } })),
+                    None => (StatusCode::NOT_FOUND, json!({ "errors": [] })),
+                }
+            }
+            (&Method::GET, path) if path.contains("/export/signing-key/") => {
+                let (mount, rest) = path.split_once("/export/signing-key/").unwrap();
+                let name = rest.trim_end_matches("/latest");
+                match self.transit.get(&format!("{}/{}", mount, name)) {
+                    Some((version, key)) => {
+                        let exported = STANDARD.encode(key.to_keypair_bytes());
+                        let keys = json!({ version.to_string(): exported });
+                        (StatusCode::OK, json!({ "data": { "name": name, "type": "ed25519", "keys": keys } }))
+                    }
+                    None => (StatusCode::NOT_FOUND, json!({ "errors": [] })),
+                }
+            }
+            _ => (StatusCode::NOT_FOUND, json!({ "errors": ["no handler for route"] })),
+        }
+    }
+}
+
+/// serve `vault` on a free port, returning its address
+fn serve(vault: Arc<Mutex<FakeVault>>) -> String {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = format!("http://{}", listener.local_addr().unwrap());
+    let make_service = make_service_fn(move |_| {
+        let vault = vault.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request: hyper::Request<Body>| {
+                let vault = vault.clone();
+                async move {
+                    let token = request.headers().get("X-Vault-Token").and_then(|value| value.to_str().ok());
+                    let (status, body) = vault.lock().unwrap().answer(request.method(), request.uri().path(), token);
+                    let mut response = Response::new(Body::from(body.to_string()));
+                    *response.status_mut() = status;
+                    Ok::<_, Infallible>(response)
+                }
+            }))
+        }
+    });
+    tokio::spawn(hyper::Server::from_tcp(listener).unwrap().serve(make_service));
+    addr
+}
+
+#[test]
+fn test_secret_refs() {
+    println!("🔧 Testing kv: and transit: secret names");
+
+    let kv: SecretRef = "kv:secret/zkp/tls".parse().unwrap();
+    assert_eq!(kv, SecretRef::Kv { mount: "secret".to_string(), path: "zkp/tls".to_string() });
+    assert_eq!(kv.to_string(), "kv:secret/zkp/tls");
+    let transit: SecretRef = "transit:transit/zkp-id-token".parse().unwrap();
+    assert_eq!(transit, SecretRef::Transit { mount: "transit".to_string(), key: "zkp-id-token".to_string() });
+    assert_eq!(transit.to_string().parse::<SecretRef>().unwrap(), transit);
+
+    for bad in ["secret/zkp/tls", "kv:secret", "kv:/zkp", "transit:transit/a/b", "pki:pki/issue/zkp", ""] {
+        assert!(matches!(bad.parse::<SecretRef>(), Err(VaultError::Malformed(_))), "{:?}", bad);
+    }
+
+    assert!(VaultClient::new("vault:8200", VAULT_TOKEN, None).is_err(), "needs a scheme");
+    assert!(VaultClient::new("ftp://vault:8200", VAULT_TOKEN, None).is_err());
+    let client = VaultClient::new("http://vault:8200", VAULT_TOKEN, None).unwrap();
+    assert!(!format!("{:?}", client).contains(VAULT_TOKEN), "the token never shows up in logs");
+
+    println!("✅ Secret names parse!");
+}
+
+#[tokio::test]
+async fn test_reading_from_vault() {
+    println!("🔧 Testing the Vault client against a fake Vault");
+
+    let key = SigningKey::from_bytes(&[7; 32]);
+    let mut fake = FakeVault::default();
+    fake.kv.insert("secret/zkp/admin".to_string(), json!({ "token": "from-vault-0123456789", "note": 5 }));
+    fake.kv.insert("secret/zkp/jwt".to_string(), json!({ "key": hex::encode([9; 32]) }));
+    fake.transit.insert("transit/zkp-id".to_string(), (3, key.clone()));
+    let fake = Arc::new(Mutex::new(fake));
+    let addr = serve(fake.clone());
+    let client = VaultClient::new(&addr, VAULT_TOKEN, None).unwrap();
+
+    let fields = client.kv("secret", "zkp/admin").await.unwrap();
+    assert_eq!(fields.get("token").map(String::as_str), Some("from-vault-0123456789"));
+    assert!(!fields.contains_key("note"), "only string fields");
+    assert_eq!(client.kv_field("secret", "zkp/admin", "token").await.unwrap(), "from-vault-0123456789");
+    assert!(matches!(client.kv_field("secret", "zkp/admin", "password").await, Err(VaultError::Missing(_))));
+    assert_eq!(
+        client.kv("secret", "zkp/nothing").await,
+        Err(VaultError::Refused { status: 404, errors: Vec::new() })
+    );
+
+    // both engines give an Ed25519 key
+    let (version, exported) = client.transit_signing_key("transit", "zkp-id").await.unwrap();
+    assert_eq!((version, exported.to_bytes()), (3, key.to_bytes()));
+    let transit = "transit:transit/zkp-id".parse().unwrap();
+    assert_eq!(client.signing_key(&transit).await.unwrap().to_bytes(), key.to_bytes());
+    let kv = "kv:secret/zkp/jwt".parse().unwrap();
+    assert_eq!(client.signing_key(&kv).await.unwrap().to_bytes(), [9; 32]);
+    let not_a_key = "kv:secret/zkp/admin".parse().unwrap();
+    assert!(client.signing_key(&not_a_key).await.is_err());
+
+    let lease = client.lookup_self().await.unwrap();
+    assert_eq!((lease.ttl, lease.renewable), (Duration::from_secs(3600), true));
+    client.renew_self().await.unwrap();
+    assert_eq!(fake.lock().unwrap().renewals, 1);
+
+    // a wrong token is refused, with Vault's reason
+    client.set_token("hvs.revoked");
+    let refused = client.lookup_self().await.unwrap_err();
+    assert_eq!(refused, VaultError::Refused { status: 403, errors: vec!["permission denied".to_string()] });
+    assert!(refused.to_string().contains("permission denied"));
+
+    // nothing listening
+    let port = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+    let gone = VaultClient::new(&format!("http://127.0.0.1:{}", port), VAULT_TOKEN, None).unwrap();
+    assert!(matches!(gone.lookup_self().await, Err(VaultError::Unreachable(_))));
+
+    println!("✅ KV secrets, transit keys and token renewals work!");
+}
+
+/// a self-signed localhost certificate as a `cert`/`key` KV secret, and the certificate alone
+fn tls_secret() -> (Value, String) {
+    let generated = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+    let cert = generated.cert.pem();
+    (json!({ "cert": cert, "key": generated.key_pair.serialize_pem() }), cert)
+}
+
+async fn connect(port: u16, ca_pem: &str) -> Result<Channel, tonic::transport::Error> {
+    let tls = ClientTlsConfig::new().ca_certificate(Certificate::from_pem(ca_pem)).domain_name("localhost");
+    Endpoint::from_shared(format!("https://localhost:{}", port)).unwrap().tls_config(tls)?.connect().await
+}
+
+async fn connect_retrying(port: u16, ca_pem: &str) -> Channel {
+    for _ in 0..50 {
+        if let Ok(channel) = connect(port, ca_pem).await {
+            return channel;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    panic!("the test server didn't come up on port {}", port);
+}
+
+/// an admin call with `token` for a user that doesn't exist: NotFound means
+/// the token was taken
+async fn admin_call(channel: Channel, token: &str) -> Code {
+    let request = SetUserEnabledRequest { user: "nobody".to_string(), enabled: true, reason: String::new() };
+    let request = AdminToken::new(token).unwrap().authorize(tonic::Request::new(request));
+    AdminClient::new(channel).set_user_enabled(request).await.unwrap_err().code()
+}
+
+async fn id_token(channel: Channel, zkp: &ZKP, x: &num_bigint::BigUint) -> String {
+    let mut client = AuthClient::new(channel);
+    let k = ZKP::generate_random_number_below(&zkp.q);
+    let (r1, r2) = zkp.commitment(&k).to_bytes_be();
+    let request = AuthenticationChallengeRequest { user: "vault_user".to_string(), r1, r2, ..Default::default() };
+    let challenge = client.create_authentication_challenge(request).await.unwrap().into_inner();
+    let s = zkp.respond(&k, &Challenge::from_bytes_be(&challenge.c), x).to_bytes_be();
+    let id_token = Some(IdTokenRequest { audience: "app".to_string(), nonce: "n".to_string() });
+    let answer = AuthenticationAnswerRequest { auth_id: challenge.auth_id, s, id_token, ..Default::default() };
+    client.verify_authentication(answer).await.unwrap().into_inner().id_token
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_server_takes_secrets_from_vault() {
+    println!("🔧 Testing a server whose TLS certificate, ID token key and admin token are in Vault");
+
+    let dir = temp_dir("server");
+    let token_file = dir.join("vault-token");
+    std::fs::write(&token_file, format!("{}\n", VAULT_TOKEN)).unwrap();
+
+    let (tls, first_cert) = tls_secret();
+    let first_key = SigningKey::from_bytes(&[1; 32]);
+    let mut fake = FakeVault::default();
+    fake.kv.insert("secret/zkp/tls".to_string(), tls);
+    fake.kv.insert("secret/zkp/admin".to_string(), json!({ "token": "first-admin-token-0123" }));
+    fake.transit.insert("transit/zkp-id".to_string(), (1, first_key.clone()));
+    let fake = Arc::new(Mutex::new(fake));
+    let addr = serve(fake.clone());
+
+    // nothing secret on disk but the Vault token
+    let port = 50083;
+    let _server = Server(
+        Command::new(env!("CARGO_BIN_EXE_server"))
+            .arg("--listen")
+            .arg(format!("127.0.0.1:{}", port))
+            .args(["--vault-addr", &addr])
+            .arg("--vault-token-file")
+            .arg(&token_file)
+            .args(["--vault-tls", "kv:secret/zkp/tls"])
+            .args(["--vault-admin-token", "kv:secret/zkp/admin"])
+            .args(["--vault-id-token-key", "transit:transit/zkp-id"])
+            .args(["--id-token-issuer", "https://zkp.example", "--id-token-audience", "app"])
+            .args(["--vault-refresh", "1"])
+            .env_remove("VAULT_ADDR")
+            .env_remove("VAULT_TOKEN")
+            .stdout(Stdio::null())
+            .spawn()
+            .expect("Failed to start server process"),
+    );
+    let channel = connect_retrying(port, &first_cert).await;
+
+    assert_eq!(admin_call(channel.clone(), "first-admin-token-0123").await, Code::NotFound);
+    assert_eq!(admin_call(channel.clone(), "some-other-token-0123").await, Code::Unauthenticated);
+
+    let zkp = ZKP::builder().rfc5114().build().unwrap();
+    let x = ZKP::generate_random_number_below(&zkp.q);
+    let (y1, y2) = zkp.public_key(&x).to_bytes_be();
+    let register = RegisterRequest { user: "vault_user".to_string(), y1, y2, ..Default::default() };
+    AuthClient::new(channel.clone()).register(register).await.unwrap();
+    let token = id_token(channel.clone(), &zkp, &x).await;
+    id_token::verify(&token, &first_key.verifying_key(), "https://zkp.example", "app", SystemTime::now()).unwrap();
+
+    // rotate all three in Vault; the server picks them up on its next refresh
+    let (tls, second_cert) = tls_secret();
+    let second_key = SigningKey::from_bytes(&[2; 32]);
+    {
+        let mut fake = fake.lock().unwrap();
+        fake.kv.insert("secret/zkp/tls".to_string(), tls);
+        fake.kv.insert("secret/zkp/admin".to_string(), json!({ "token": "second-admin-token-0123" }));
+        fake.transit.insert("transit/zkp-id".to_string(), (2, second_key.clone()));
+    }
+    tokio::time::sleep(Duration::from_millis(2500)).await;
+
+    let channel = connect(port, &second_cert).await.expect("the rotated certificate should be served");
+    assert!(connect(port, &first_cert).await.is_err(), "the old certificate should no longer be presented");
+    assert_eq!(admin_call(channel.clone(), "second-admin-token-0123").await, Code::NotFound);
+    assert_eq!(admin_call(channel.clone(), "first-admin-token-0123").await, Code::Unauthenticated);
+    let token = id_token(channel.clone(), &zkp, &x).await;
+    id_token::verify(&token, &second_key.verifying_key(), "https://zkp.example", "app", SystemTime::now()).unwrap();
+    assert!(fake.lock().unwrap().renewals >= 1, "the token is renewed on every refresh");
+
+    // while Vault refuses the server, it keeps what it has
+    std::fs::write(&token_file, "hvs.revoked").unwrap();
+    tokio::time::sleep(Duration::from_millis(1500)).await;
+    assert_eq!(admin_call(channel.clone(), "second-admin-token-0123").await, Code::NotFound);
+
+    let _ = std::fs::remove_dir_all(&dir);
+    println!("✅ Secrets come from Vault and rotate without a restart!");
+}